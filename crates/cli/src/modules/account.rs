@@ -23,10 +23,11 @@
 
 use std::fmt::Display;
 
+use mail_parser::DateTime;
 use prettytable::{Attr, Cell, Row, Table};
 use pwhash::sha512_crypt;
 use reqwest::Method;
-use serde_json::Value;
+use serde_json::{json, Value};
 
 use super::{
     cli::{AccountCommands, Client},
@@ -238,6 +239,127 @@ impl AccountCommands {
                     .list_principals("individual", "Account", from, limit)
                     .await;
             }
+            AccountCommands::AddAppPassword {
+                name,
+                app_name,
+                expires,
+                protocols,
+            } => {
+                let app_password = client
+                    .http_request::<AppPasswordSecret, _>(
+                        Method::POST,
+                        &format!("/admin/principal/{name}/app-passwords"),
+                        Some(json!({
+                            "name": app_name,
+                            "expires": expires.map(|expires| expires.to_timestamp()),
+                            "protocols": protocols.unwrap_or_default(),
+                        })),
+                    )
+                    .await;
+                eprintln!(
+                    "Successfully created application password {:?} for account {name:?}: {}",
+                    app_password.name, app_password.password
+                );
+                eprintln!("This password will not be displayed again.");
+            }
+            AccountCommands::ListAppPasswords { name } => {
+                let app_passwords = client
+                    .http_request::<Vec<AppPassword>, String>(
+                        Method::GET,
+                        &format!("/admin/principal/{name}/app-passwords"),
+                        None,
+                    )
+                    .await;
+                if !app_passwords.is_empty() {
+                    let mut table = Table::new();
+                    table.add_row(Row::new(vec![
+                        Cell::new("Name").with_style(Attr::Bold),
+                        Cell::new("Protocols").with_style(Attr::Bold),
+                        Cell::new("Created").with_style(Attr::Bold),
+                        Cell::new("Expires").with_style(Attr::Bold),
+                        Cell::new("Last Used").with_style(Attr::Bold),
+                        Cell::new("Last IP").with_style(Attr::Bold),
+                    ]));
+
+                    for app_password in &app_passwords {
+                        table.add_row(Row::new(vec![
+                            Cell::new(&app_password.name),
+                            Cell::new(&if !app_password.protocols.is_empty() {
+                                app_password.protocols.join(", ")
+                            } else {
+                                "all".to_string()
+                            }),
+                            Cell::new(&format_timestamp(Some(app_password.created))),
+                            Cell::new(&format_timestamp(app_password.expires)),
+                            Cell::new(&format_timestamp(app_password.last_used)),
+                            Cell::new(app_password.last_ip.as_deref().unwrap_or_default()),
+                        ]));
+                    }
+
+                    eprintln!();
+                    table.printstd();
+                    eprintln!();
+                }
+
+                eprintln!(
+                    "\n\n{} application password{} found.\n",
+                    app_passwords.len(),
+                    if app_passwords.len() == 1 { "" } else { "s" }
+                );
+            }
+            AccountCommands::RemoveAppPassword { name, app_name } => {
+                client
+                    .http_request::<Value, String>(
+                        Method::DELETE,
+                        &format!("/admin/principal/{name}/app-passwords/{app_name}"),
+                        None,
+                    )
+                    .await;
+                eprintln!(
+                    "Successfully revoked application password {app_name:?} of account {name:?}."
+                );
+            }
+            AccountCommands::TotpEnroll { name } => {
+                let enrollment = client
+                    .http_request::<TotpEnrollment, String>(
+                        Method::POST,
+                        &format!("/admin/principal/{name}/totp"),
+                        None,
+                    )
+                    .await;
+                eprintln!("Add the following secret to the authenticator app of {name:?}:\n");
+                eprintln!("Secret: {}", enrollment.secret);
+                eprintln!("URI: {}\n", enrollment.uri);
+                eprintln!("Recovery codes (each one can be used only once):\n");
+                for code in &enrollment.recovery_codes {
+                    eprintln!("  {code}");
+                }
+                eprintln!(
+                    "\nRun 'account totp-confirm {name} <code>' to enable two-factor authentication."
+                );
+            }
+            AccountCommands::TotpConfirm { name, code } => {
+                client
+                    .http_request::<Value, _>(
+                        Method::POST,
+                        &format!("/admin/principal/{name}/totp/confirm"),
+                        Some(json!({
+                            "code": code,
+                        })),
+                    )
+                    .await;
+                eprintln!("Successfully enabled two-factor authentication for account {name:?}.");
+            }
+            AccountCommands::TotpDisable { name } => {
+                client
+                    .http_request::<Value, String>(
+                        Method::DELETE,
+                        &format!("/admin/principal/{name}/totp"),
+                        None,
+                    )
+                    .await;
+                eprintln!("Successfully disabled two-factor authentication for account {name:?}.");
+            }
         }
     }
 }
@@ -359,6 +481,38 @@ impl Client {
     }
 }
 
+#[derive(Debug, serde::Deserialize)]
+struct AppPassword {
+    name: String,
+    created: u64,
+    expires: Option<u64>,
+    protocols: Vec<String>,
+    #[serde(rename = "lastUsed")]
+    last_used: Option<u64>,
+    #[serde(rename = "lastIp")]
+    last_ip: Option<String>,
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct TotpEnrollment {
+    secret: String,
+    uri: String,
+    #[serde(rename = "recoveryCodes")]
+    recovery_codes: Vec<String>,
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct AppPasswordSecret {
+    name: String,
+    password: String,
+}
+
+fn format_timestamp(timestamp: Option<u64>) -> String {
+    timestamp
+        .map(|timestamp| DateTime::from_timestamp(timestamp as i64).to_rfc3339())
+        .unwrap_or_default()
+}
+
 impl Display for Type {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
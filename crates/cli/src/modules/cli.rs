@@ -195,6 +195,55 @@ pub enum AccountCommands {
         /// Maximum number of accounts to list
         limit: Option<usize>,
     },
+
+    /// Generate a new application password for a user account
+    AddAppPassword {
+        /// Account login
+        name: String,
+        /// Application password name
+        app_name: String,
+        /// Expire the password at a certain datetime
+        #[clap(short, long)]
+        #[arg(value_parser = parse_datetime)]
+        expires: Option<DateTime>,
+        /// Protocols the password is valid for (imap, smtp, jmap or managesieve)
+        #[clap(short, long)]
+        protocols: Option<Vec<String>>,
+    },
+
+    /// List the application passwords of a user account
+    ListAppPasswords {
+        /// Account login
+        name: String,
+    },
+
+    /// Revoke an application password
+    RemoveAppPassword {
+        /// Account login
+        name: String,
+        /// Application password name
+        app_name: String,
+    },
+
+    /// Generate a new TOTP secret and recovery codes for a user account
+    TotpEnroll {
+        /// Account login
+        name: String,
+    },
+
+    /// Enable two-factor authentication after verifying a TOTP code
+    TotpConfirm {
+        /// Account login
+        name: String,
+        /// Code generated by the authenticator app
+        code: String,
+    },
+
+    /// Disable two-factor authentication for a user account
+    TotpDisable {
+        /// Account login
+        name: String,
+    },
 }
 
 #[derive(Subcommand)]
@@ -260,6 +309,103 @@ pub enum ListCommands {
         /// Maximum number of mailing lists to list
         limit: Option<usize>,
     },
+
+    /// Enable or update the mailing list manager settings of a list
+    Configure {
+        /// List Name
+        name: String,
+        /// Who is allowed to post to the list
+        #[clap(short, long, value_enum, default_value = "open")]
+        policy: PostingPolicy,
+        /// Addresses allowed to moderate and post to the list
+        #[clap(short, long)]
+        moderators: Option<Vec<String>>,
+        /// When to rewrite the From header of list messages
+        #[clap(short, long, value_enum, default_value = "dmarc")]
+        rewrite_from: FromRewrite,
+        /// Prefix to add to the subject of list messages
+        #[clap(short, long)]
+        subject_prefix: Option<String>,
+        /// Store a copy of every list message in the list's mailbox
+        #[clap(short, long)]
+        archive: bool,
+    },
+
+    /// Disable the mailing list manager for a list
+    Unconfigure {
+        /// List Name
+        name: String,
+    },
+
+    /// List the external subscribers of a mailing list
+    Subscribers {
+        /// List Name
+        name: String,
+    },
+
+    /// Subscribe external addresses to a mailing list
+    Subscribe {
+        /// List Name
+        name: String,
+        /// Addresses to subscribe
+        #[clap(required = true)]
+        addresses: Vec<String>,
+    },
+
+    /// Unsubscribe external addresses from a mailing list
+    Unsubscribe {
+        /// List Name
+        name: String,
+        /// Addresses to unsubscribe
+        #[clap(required = true)]
+        addresses: Vec<String>,
+    },
+
+    /// List the messages awaiting moderation
+    Held {
+        /// List Name
+        name: String,
+    },
+
+    /// Approve a message awaiting moderation
+    Approve {
+        /// List Name
+        name: String,
+        /// Held message id
+        id: u64,
+    },
+
+    /// Reject a message awaiting moderation
+    Reject {
+        /// List Name
+        name: String,
+        /// Held message id
+        id: u64,
+    },
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, ValueEnum, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum PostingPolicy {
+    /// Anyone can post
+    Open,
+    /// Only members and subscribers can post
+    MembersOnly,
+    /// Posts from non-moderators are held for approval
+    Moderated,
+    /// Only moderators can post
+    AnnounceOnly,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, ValueEnum, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum FromRewrite {
+    /// Never rewrite the From header
+    Never,
+    /// Rewrite when the sender's domain has a quarantine or reject DMARC policy
+    Dmarc,
+    /// Always rewrite the From header
+    Always,
 }
 
 #[derive(Subcommand)]
@@ -348,6 +494,29 @@ pub enum DomainCommands {
         /// Maximum number of domains to list
         limit: Option<usize>,
     },
+
+    /// Generate a new DKIM key for a domain
+    DkimGenerate {
+        /// Domain name
+        name: String,
+        /// Signature algorithm (rsa-sha256 or ed25519-sha256)
+        #[clap(short, long)]
+        algorithm: Option<String>,
+    },
+
+    /// Display the DKIM keys and DNS records of a domain
+    DkimList {
+        /// Domain name
+        name: String,
+    },
+
+    /// Delete a DKIM key
+    DkimDelete {
+        /// Domain name
+        name: String,
+        /// Selector of the key to delete
+        selector: String,
+    },
 }
 
 #[derive(Subcommand)]
@@ -541,6 +710,43 @@ pub enum ReportCommands {
         #[clap(required = true)]
         ids: Vec<String>,
     },
+
+    /// Shows received DMARC, TLS and ARF reports
+    Incoming {
+        /// Filter by reported domain
+        #[clap(short, long)]
+        domain: Option<String>,
+        /// Filter by reporting organization or sender address
+        #[clap(short, long)]
+        reporter: Option<String>,
+        /// Filter by report type
+        #[clap(short, long)]
+        #[clap(value_enum)]
+        format: Option<ReportFormat>,
+        /// Filter reports covering a period before a certain datetime
+        #[clap(short, long)]
+        #[arg(value_parser = parse_datetime)]
+        before: Option<DateTime>,
+        /// Filter reports covering a period after a certain datetime
+        #[clap(short, long)]
+        #[arg(value_parser = parse_datetime)]
+        after: Option<DateTime>,
+        /// Minimum number of passing results
+        #[clap(long)]
+        min_pass: Option<u64>,
+        /// Minimum number of failing results
+        #[clap(long)]
+        min_fail: Option<u64>,
+        /// Number of items to show per page
+        #[clap(short, long)]
+        page_size: Option<usize>,
+    },
+
+    /// Displays the contents of a received report
+    IncomingStatus {
+        #[clap(required = true)]
+        ids: Vec<String>,
+    },
 }
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum, Deserialize)]
@@ -551,6 +757,9 @@ pub enum ReportFormat {
     /// TLS report
     #[serde(rename = "tls")]
     Tls,
+    /// Abuse or authentication failure report (incoming only)
+    #[serde(rename = "arf")]
+    Arf,
 }
 
 fn parse_datetime(arg: &str) -> Result<DateTime, &'static str> {
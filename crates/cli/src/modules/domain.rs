@@ -25,7 +25,7 @@ use std::borrow::Cow;
 
 use prettytable::{Attr, Cell, Row, Table};
 use reqwest::Method;
-use serde_json::Value;
+use serde_json::{json, Value};
 
 use super::cli::{Client, DomainCommands};
 
@@ -93,6 +93,86 @@ impl DomainCommands {
                     if domains.len() == 1 { "" } else { "s" }
                 );
             }
+            DomainCommands::DkimGenerate { name, algorithm } => {
+                let key = client
+                    .http_request::<DkimKey, _>(
+                        Method::POST,
+                        &format!("/admin/domain/{name}/dkim"),
+                        Some(json!({
+                            "algorithm": algorithm.unwrap_or_else(|| "rsa-sha256".to_string()),
+                        })),
+                    )
+                    .await;
+                eprintln!(
+                    "Successfully generated {} key for domain {name:?}, {}.",
+                    key.algorithm,
+                    if key.state == "active" {
+                        "signing with it now"
+                    } else {
+                        "signing will switch to it once the grace period is over"
+                    }
+                );
+                eprintln!("\nPublish the following DNS record:\n");
+                eprintln!("{} IN TXT \"{}\"\n", key.dns_name, key.dns_record);
+            }
+            DomainCommands::DkimList { name } => {
+                let keys = client
+                    .http_request::<Vec<DkimKey>, String>(
+                        Method::GET,
+                        &format!("/admin/domain/{name}/dkim"),
+                        None,
+                    )
+                    .await;
+                if !keys.is_empty() {
+                    let mut table = Table::new();
+                    table.add_row(Row::new(vec![
+                        Cell::new("Selector").with_style(Attr::Bold),
+                        Cell::new("Algorithm").with_style(Attr::Bold),
+                        Cell::new("State").with_style(Attr::Bold),
+                        Cell::new("DNS Record").with_style(Attr::Bold),
+                    ]));
+
+                    for key in &keys {
+                        table.add_row(Row::new(vec![
+                            Cell::new(&key.selector),
+                            Cell::new(&key.algorithm),
+                            Cell::new(&key.state),
+                            Cell::new(&format!("{} IN TXT \"{}\"", key.dns_name, key.dns_record)),
+                        ]));
+                    }
+
+                    eprintln!();
+                    table.printstd();
+                    eprintln!();
+                }
+
+                eprintln!(
+                    "\n\n{} key{} found.\n",
+                    keys.len(),
+                    if keys.len() == 1 { "" } else { "s" }
+                );
+            }
+            DomainCommands::DkimDelete { name, selector } => {
+                client
+                    .http_request::<Value, String>(
+                        Method::DELETE,
+                        &format!("/admin/domain/{name}/dkim/{selector}"),
+                        None,
+                    )
+                    .await;
+                eprintln!("Successfully deleted DKIM key {selector:?} of domain {name:?}");
+            }
         }
     }
 }
+
+#[derive(Debug, serde::Deserialize)]
+struct DkimKey {
+    selector: String,
+    algorithm: String,
+    state: String,
+    #[serde(rename = "dnsName")]
+    dns_name: String,
+    #[serde(rename = "dnsRecord")]
+    dns_record: String,
+}
@@ -23,8 +23,10 @@
 
 use std::vec;
 
+use mail_parser::DateTime;
+use prettytable::{Attr, Cell, Row, Table};
 use reqwest::Method;
-use serde_json::Value;
+use serde_json::{json, Value};
 
 use crate::modules::{Principal, Type};
 
@@ -160,6 +162,155 @@ impl ListCommands {
                     .list_principals("list", "Mailing List", from, limit)
                     .await;
             }
+            ListCommands::Configure {
+                name,
+                policy,
+                moderators,
+                rewrite_from,
+                subject_prefix,
+                archive,
+            } => {
+                client
+                    .http_request::<Value, _>(
+                        Method::POST,
+                        &format!("/admin/principal/{name}/list"),
+                        Some(json!({
+                            "policy": policy,
+                            "moderators": moderators.unwrap_or_default(),
+                            "rewriteFrom": rewrite_from,
+                            "subjectPrefix": subject_prefix,
+                            "archive": archive,
+                        })),
+                    )
+                    .await;
+                eprintln!("Successfully configured mailing list {name:?}.");
+            }
+            ListCommands::Unconfigure { name } => {
+                client
+                    .http_request::<Value, String>(
+                        Method::DELETE,
+                        &format!("/admin/principal/{name}/list"),
+                        None,
+                    )
+                    .await;
+                eprintln!("Successfully disabled the list manager for {name:?}.");
+            }
+            ListCommands::Subscribers { name } => {
+                let subscribers = client
+                    .http_request::<Vec<String>, String>(
+                        Method::GET,
+                        &format!("/admin/principal/{name}/list/subscribers"),
+                        None,
+                    )
+                    .await;
+                for subscriber in &subscribers {
+                    println!("{subscriber}");
+                }
+                eprintln!(
+                    "\n\n{} subscriber{} found.\n",
+                    subscribers.len(),
+                    if subscribers.len() == 1 { "" } else { "s" }
+                );
+            }
+            ListCommands::Subscribe { name, addresses } => {
+                for address in addresses {
+                    client
+                        .http_request::<Value, _>(
+                            Method::POST,
+                            &format!("/admin/principal/{name}/list/subscribers"),
+                            Some(json!({ "email": address })),
+                        )
+                        .await;
+                }
+                eprintln!("Successfully updated mailing list {name:?}.");
+            }
+            ListCommands::Unsubscribe { name, addresses } => {
+                for address in addresses {
+                    client
+                        .http_request::<Value, String>(
+                            Method::DELETE,
+                            &format!("/admin/principal/{name}/list/subscribers/{address}"),
+                            None,
+                        )
+                        .await;
+                }
+                eprintln!("Successfully updated mailing list {name:?}.");
+            }
+            ListCommands::Held { name } => {
+                let messages = client
+                    .http_request::<Vec<HeldMessage>, String>(
+                        Method::GET,
+                        &format!("/admin/principal/{name}/list/moderation"),
+                        None,
+                    )
+                    .await;
+                if !messages.is_empty() {
+                    let mut table = Table::new();
+                    table.add_row(Row::new(vec![
+                        Cell::new("Id").with_style(Attr::Bold),
+                        Cell::new("Sender").with_style(Attr::Bold),
+                        Cell::new("Subject").with_style(Attr::Bold),
+                        Cell::new("Size").with_style(Attr::Bold),
+                        Cell::new("Received").with_style(Attr::Bold),
+                        Cell::new("Expires").with_style(Attr::Bold),
+                    ]));
+
+                    for message in &messages {
+                        table.add_row(Row::new(vec![
+                            Cell::new(&message.id.to_string()),
+                            Cell::new(&message.sender),
+                            Cell::new(&message.subject),
+                            Cell::new(&message.size.to_string()),
+                            Cell::new(
+                                &DateTime::from_timestamp(message.received as i64).to_rfc3339(),
+                            ),
+                            Cell::new(
+                                &DateTime::from_timestamp(message.expires as i64).to_rfc3339(),
+                            ),
+                        ]));
+                    }
+
+                    eprintln!();
+                    table.printstd();
+                    eprintln!();
+                }
+
+                eprintln!(
+                    "\n\n{} held message{} found.\n",
+                    messages.len(),
+                    if messages.len() == 1 { "" } else { "s" }
+                );
+            }
+            ListCommands::Approve { name, id } => {
+                client
+                    .http_request::<Value, String>(
+                        Method::POST,
+                        &format!("/admin/principal/{name}/list/moderation/{id}"),
+                        None,
+                    )
+                    .await;
+                eprintln!("Successfully approved message {id} of mailing list {name:?}.");
+            }
+            ListCommands::Reject { name, id } => {
+                client
+                    .http_request::<Value, String>(
+                        Method::DELETE,
+                        &format!("/admin/principal/{name}/list/moderation/{id}"),
+                        None,
+                    )
+                    .await;
+                eprintln!("Successfully rejected message {id} of mailing list {name:?}.");
+            }
         }
     }
 }
+
+#[derive(Debug, serde::Deserialize)]
+struct HeldMessage {
+    id: u64,
+    sender: String,
+    subject: String,
+    size: usize,
+    received: u64,
+    expires: u64,
+}
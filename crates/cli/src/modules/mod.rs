@@ -115,6 +115,8 @@ pub enum PrincipalField {
     MemberOf,
     #[serde(rename = "members")]
     Members,
+    #[serde(rename = "appPasswords")]
+    AppPasswords,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
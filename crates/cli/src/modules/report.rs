@@ -22,7 +22,7 @@
 */
 
 use super::cli::{Client, ReportCommands, ReportFormat};
-use crate::modules::queue::deserialize_datetime;
+use crate::modules::queue::{deserialize_datetime, deserialize_maybe_datetime};
 use console::Term;
 use human_size::{Byte, SpecificSize};
 use mail_parser::DateTime;
@@ -42,6 +42,26 @@ pub struct Report {
     pub size: usize,
 }
 
+#[derive(Debug, Deserialize)]
+pub struct IncomingReport {
+    #[serde(rename = "type")]
+    pub type_: ReportFormat,
+    pub domains: Vec<String>,
+    pub reporter: String,
+    pub from: String,
+    pub subject: String,
+    #[serde(deserialize_with = "deserialize_datetime")]
+    pub received: DateTime,
+    #[serde(deserialize_with = "deserialize_maybe_datetime")]
+    pub range_from: Option<DateTime>,
+    #[serde(deserialize_with = "deserialize_maybe_datetime")]
+    pub range_to: Option<DateTime>,
+    pub pass: u64,
+    pub fail: u64,
+    #[serde(default)]
+    pub report: Option<serde_json::Value>,
+}
+
 impl ReportCommands {
     pub async fn exec(self, client: Client) {
         match self {
@@ -195,6 +215,179 @@ impl ReportCommands {
                 }
                 eprintln!();
             }
+            ReportCommands::Incoming {
+                domain,
+                reporter,
+                format,
+                before,
+                after,
+                min_pass,
+                min_fail,
+                page_size,
+            } => {
+                let stdout = Term::buffered_stdout();
+                let mut query =
+                    form_urlencoded::Serializer::new("/admin/report/incoming?".to_string());
+
+                if let Some(domain) = &domain {
+                    query.append_pair("domain", domain);
+                }
+                if let Some(reporter) = &reporter {
+                    query.append_pair("reporter", reporter);
+                }
+                if let Some(format) = &format {
+                    query.append_pair("type", format.id());
+                }
+                if let Some(before) = &before {
+                    query.append_pair("before", &before.to_rfc3339());
+                }
+                if let Some(after) = &after {
+                    query.append_pair("after", &after.to_rfc3339());
+                }
+                if let Some(min_pass) = min_pass {
+                    query.append_pair("min-pass", &min_pass.to_string());
+                }
+                if let Some(min_fail) = min_fail {
+                    query.append_pair("min-fail", &min_fail.to_string());
+                }
+
+                let ids = client
+                    .http_request::<Vec<String>, String>(Method::GET, &query.finish(), None)
+                    .await;
+                let ids_len = ids.len();
+                let page_size = page_size.map(|p| std::cmp::max(p, 1)).unwrap_or(20);
+                let pages_total = (ids_len as f64 / page_size as f64).ceil() as usize;
+                for (page_num, chunk) in ids.chunks(page_size).enumerate() {
+                    // Build table
+                    let mut table = Table::new();
+                    table.add_row(Row::new(
+                        [
+                            "ID",
+                            "Type",
+                            "Domain",
+                            "Reporter",
+                            "From Date",
+                            "To Date",
+                            "Pass",
+                            "Fail",
+                        ]
+                        .iter()
+                        .map(|p| Cell::new(p).with_style(Attr::Bold))
+                        .collect(),
+                    ));
+                    for (report, id) in client
+                        .http_request::<Vec<Option<IncomingReport>>, String>(
+                            Method::GET,
+                            &format!("/admin/report/incoming-status?ids={}", chunk.join(",")),
+                            None,
+                        )
+                        .await
+                        .into_iter()
+                        .zip(chunk)
+                    {
+                        if let Some(report) = report {
+                            table.add_row(Row::new(vec![
+                                Cell::new(id),
+                                Cell::new(report.type_.name()),
+                                Cell::new(&report.domains.join(", ")),
+                                Cell::new(&report.reporter),
+                                Cell::new(
+                                    &report
+                                        .range_from
+                                        .as_ref()
+                                        .unwrap_or(&report.received)
+                                        .to_rfc822(),
+                                ),
+                                Cell::new(
+                                    &report
+                                        .range_to
+                                        .as_ref()
+                                        .unwrap_or(&report.received)
+                                        .to_rfc822(),
+                                ),
+                                Cell::new(&report.pass.to_string()),
+                                Cell::new(&report.fail.to_string()),
+                            ]));
+                        }
+                    }
+
+                    eprintln!();
+                    table.printstd();
+                    eprintln!();
+                    if page_num + 1 != pages_total {
+                        eprintln!("\n--- Press any key to continue or 'q' to exit ---");
+                        if let Ok('q' | 'Q') = stdout.read_char() {
+                            break;
+                        }
+                    }
+                }
+                eprintln!("\n{ids_len} received report(s) found.")
+            }
+            ReportCommands::IncomingStatus { ids } => {
+                for (report, id) in client
+                    .http_request::<Vec<Option<IncomingReport>>, String>(
+                        Method::GET,
+                        &format!("/admin/report/incoming-status?ids={}", ids.join(",")),
+                        None,
+                    )
+                    .await
+                    .into_iter()
+                    .zip(&ids)
+                {
+                    let mut table = Table::new();
+                    table.add_row(Row::new(vec![
+                        Cell::new("ID").with_style(Attr::Bold),
+                        Cell::new(id),
+                    ]));
+                    let mut contents = None;
+                    if let Some(mut report) = report {
+                        contents = report.report.take();
+                        for (name, value) in [
+                            ("Type", report.type_.name().to_string()),
+                            ("Domains", report.domains.join(", ")),
+                            ("Reporter", report.reporter),
+                            ("From", report.from),
+                            ("Subject", report.subject),
+                            ("Received", report.received.to_rfc822()),
+                            (
+                                "From Date",
+                                report
+                                    .range_from
+                                    .map(|dt| dt.to_rfc822())
+                                    .unwrap_or_default(),
+                            ),
+                            (
+                                "To Date",
+                                report.range_to.map(|dt| dt.to_rfc822()).unwrap_or_default(),
+                            ),
+                            ("Pass", report.pass.to_string()),
+                            ("Fail", report.fail.to_string()),
+                        ] {
+                            table.add_row(Row::new(vec![
+                                Cell::new(name).with_style(Attr::Bold),
+                                Cell::new(&value),
+                            ]));
+                        }
+                    } else {
+                        table.add_row(Row::new(vec![Cell::new_align(
+                            "-- Not found --",
+                            Alignment::CENTER,
+                        )
+                        .with_hspan(2)]));
+                    }
+
+                    eprintln!();
+                    table.printstd();
+                    eprintln!();
+
+                    if let Some(contents) = contents {
+                        println!(
+                            "{}",
+                            serde_json::to_string_pretty(&contents).unwrap_or_default()
+                        );
+                    }
+                }
+            }
         }
     }
 }
@@ -204,6 +397,7 @@ impl ReportFormat {
         match self {
             ReportFormat::Dmarc => "dmarc",
             ReportFormat::Tls => "tls",
+            ReportFormat::Arf => "arf",
         }
     }
 
@@ -211,6 +405,7 @@ impl ReportFormat {
         match self {
             ReportFormat::Dmarc => "DMARC",
             ReportFormat::Tls => "TLS",
+            ReportFormat::Arf => "ARF",
         }
     }
 }
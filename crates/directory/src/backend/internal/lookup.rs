@@ -23,11 +23,14 @@
 
 use mail_send::Credentials;
 use store::{
-    write::{DirectoryClass, ValueClass},
-    IterateParams, Store, ValueKey,
+    write::{assert::HashedValue, BatchBuilder, DirectoryClass, ValueClass},
+    IterateParams, Serialize, Store, ValueKey,
 };
 
-use crate::{Principal, QueryBy, Type};
+use crate::{
+    core::scram::{ScramAlgorithm, ScramSecret},
+    Principal, QueryBy, Type,
+};
 
 use super::{manage::ManageDirectory, PrincipalIdType};
 
@@ -44,6 +47,12 @@ pub trait DirectoryStore: Sync + Send {
     async fn rcpt(&self, address: &str) -> crate::Result<bool>;
     async fn vrfy(&self, address: &str) -> crate::Result<Vec<String>>;
     async fn expn(&self, address: &str) -> crate::Result<Vec<String>>;
+    async fn add_scram_secrets(
+        &self,
+        account_id: u32,
+        principal: &HashedValue<Principal<u32>>,
+        secret: &str,
+    );
 }
 
 impl DirectoryStore for Store {
@@ -70,19 +79,27 @@ impl DirectoryStore for Store {
 
         if let Some(account_id) = account_id {
             match (
-                self.get_value::<Principal<u32>>(ValueKey::from(ValueClass::Directory(
-                    DirectoryClass::Principal(account_id),
-                )))
+                self.get_value::<HashedValue<Principal<u32>>>(ValueKey::from(
+                    ValueClass::Directory(DirectoryClass::Principal(account_id)),
+                ))
                 .await?,
                 secret,
             ) {
-                (Some(mut principal), Some(secret)) if principal.verify_secret(secret).await => {
+                (Some(principal), Some(secret)) if principal.inner.verify_secret(secret).await => {
+                    // Upgrade to SCRAM secrets on the first successful plain text login
+                    if matches!(by, QueryBy::Credentials(Credentials::Plain { .. }))
+                        && !principal.inner.has_scram_secrets()
+                    {
+                        self.add_scram_secrets(account_id, &principal, secret).await;
+                    }
+                    let mut principal = principal.inner;
                     if return_member_of {
                         principal.member_of = self.get_member_of(principal.id).await?;
                     }
                     Ok(Some(principal))
                 }
-                (Some(mut principal), None) => {
+                (Some(principal), None) => {
+                    let mut principal = principal.inner;
                     if return_member_of {
                         principal.member_of = self.get_member_of(principal.id).await?;
                     }
@@ -174,4 +191,37 @@ impl DirectoryStore for Store {
 
         Ok(results)
     }
+
+    async fn add_scram_secrets(
+        &self,
+        account_id: u32,
+        principal: &HashedValue<Principal<u32>>,
+        secret: &str,
+    ) {
+        let mut updated = principal.inner.clone();
+        for algorithm in [ScramAlgorithm::Sha256, ScramAlgorithm::Sha1] {
+            updated
+                .secrets
+                .push(ScramSecret::new(algorithm, secret).to_string());
+        }
+
+        // Only update the principal if it was not modified after the secret was verified
+        let class = ValueClass::Directory(DirectoryClass::Principal(account_id));
+        let mut batch = BatchBuilder::new();
+        batch
+            .assert_value(class.clone(), principal)
+            .set(class, updated.serialize());
+        match self.write(batch.build()).await {
+            Ok(_) | Err(store::Error::AssertValueFailed) => (),
+            Err(err) => {
+                tracing::debug!(
+                    context = "directory",
+                    event = "error",
+                    account_id = account_id,
+                    reason = ?err,
+                    "Failed to add SCRAM secrets"
+                );
+            }
+        }
+    }
 }
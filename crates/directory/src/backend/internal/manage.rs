@@ -32,7 +32,10 @@ use store::{
     BitmapKey, Deserialize, IterateParams, Serialize, Store, ValueKey, U32_LEN,
 };
 
-use crate::{DirectoryError, ManagementError, Principal, QueryBy, Type};
+use crate::{
+    core::{app_password::AppPasswordStore, mailing_list::MailingListStore},
+    DirectoryError, ManagementError, Principal, QueryBy, Type,
+};
 
 use super::{
     lookup::DirectoryStore, PrincipalAction, PrincipalField, PrincipalIdType, PrincipalUpdate,
@@ -294,7 +297,8 @@ impl ManageDirectory for Store {
             .with_account_id(account_id)
             .clear(DirectoryClass::NameToId(principal.name.into_bytes()))
             .clear(DirectoryClass::Principal(account_id))
-            .clear(DirectoryClass::UsedQuota(account_id));
+            .clear(DirectoryClass::UsedQuota(account_id))
+            .clear(DirectoryClass::Totp(account_id));
 
         for email in principal.emails {
             batch.clear(DirectoryClass::EmailToId(email.into_bytes()));
@@ -322,6 +326,30 @@ impl ManageDirectory for Store {
             });
         }
 
+        for app_password in self.list_app_passwords(account_id).await? {
+            app_password.clear_index(&mut batch, account_id);
+            batch.clear(DirectoryClass::AppPassword {
+                principal_id: account_id,
+                name: app_password.name.into_bytes(),
+            });
+        }
+
+        if principal.typ == Type::List {
+            batch.clear(DirectoryClass::MailingList(account_id));
+            for email in self.list_subscribers(account_id).await? {
+                batch.clear(DirectoryClass::ListSubscriber {
+                    list_id: account_id,
+                    email: email.into_bytes(),
+                });
+            }
+            for message in self.list_held_messages(account_id).await? {
+                batch.clear(DirectoryClass::ListModeration {
+                    list_id: account_id,
+                    id: message.id,
+                });
+            }
+        }
+
         self.write(batch.build()).await?;
 
         Ok(())
@@ -31,7 +31,7 @@ use utils::codec::leb128::Leb128Iterator;
 
 use crate::{Principal, Type};
 
-pub(super) struct PrincipalIdType {
+pub(crate) struct PrincipalIdType {
     pub account_id: u32,
     pub typ: Type,
 }
@@ -151,6 +151,8 @@ pub enum PrincipalField {
     MemberOf,
     #[serde(rename = "members")]
     Members,
+    #[serde(rename = "appPasswords")]
+    AppPasswords,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
@@ -215,11 +217,12 @@ impl Display for PrincipalField {
             PrincipalField::Emails => write!(f, "emails"),
             PrincipalField::MemberOf => write!(f, "memberOf"),
             PrincipalField::Members => write!(f, "members"),
+            PrincipalField::AppPasswords => write!(f, "appPasswords"),
         }
     }
 }
 
-fn deserialize_string(bytes: &mut Iter<'_, u8>) -> Option<String> {
+pub(crate) fn deserialize_string(bytes: &mut Iter<'_, u8>) -> Option<String> {
     let len = bytes.next_leb128()?;
     let mut string = Vec::with_capacity(len);
     for _ in 0..len {
@@ -228,7 +231,7 @@ fn deserialize_string(bytes: &mut Iter<'_, u8>) -> Option<String> {
     String::from_utf8(string).ok()
 }
 
-fn deserialize_string_list(bytes: &mut Iter<'_, u8>) -> Option<Vec<String>> {
+pub(crate) fn deserialize_string_list(bytes: &mut Iter<'_, u8>) -> Option<Vec<String>> {
     let len = bytes.next_leb128()?;
     let mut list = Vec::with_capacity(len);
     for _ in 0..len {
@@ -0,0 +1,401 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::net::IpAddr;
+
+use hmac::{Hmac, Mac};
+use mail_send::Credentials;
+use sha2::Sha256;
+use store::{
+    rand::{distributions::Alphanumeric, thread_rng, Rng},
+    write::{
+        assert::{AssertValue, HashedValue},
+        key::KeySerializer,
+        now, BatchBuilder, DirectoryClass, ValueClass,
+    },
+    Deserialize, IterateParams, Serialize, Store, ValueKey, U32_LEN, U64_LEN,
+};
+use utils::{codec::leb128::Leb128Iterator, config::ServerProtocol};
+
+use crate::{
+    backend::internal::{deserialize_string, manage::ManageDirectory, PrincipalField},
+    Directory, DirectoryError, ManagementError, Principal, QueryBy,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct AppPassword {
+    pub name: String,
+    #[serde(skip)]
+    pub secret: String,
+    pub created: u64,
+    pub expires: Option<u64>,
+    pub protocols: Vec<ServerProtocol>,
+    #[serde(rename = "lastUsed")]
+    pub last_used: Option<u64>,
+    #[serde(rename = "lastIp")]
+    pub last_ip: Option<IpAddr>,
+}
+
+#[allow(async_fn_in_trait)]
+pub trait AppPasswordStore: Sized {
+    async fn create_app_password(
+        &self,
+        account_id: u32,
+        name: &str,
+        expires: Option<u64>,
+        protocols: Vec<ServerProtocol>,
+    ) -> crate::Result<String>;
+    async fn list_app_passwords(&self, account_id: u32) -> crate::Result<Vec<AppPassword>>;
+    async fn revoke_app_password(&self, account_id: u32, name: &str) -> crate::Result<bool>;
+}
+
+impl AppPasswordStore for Store {
+    async fn create_app_password(
+        &self,
+        account_id: u32,
+        name: &str,
+        expires: Option<u64>,
+        protocols: Vec<ServerProtocol>,
+    ) -> crate::Result<String> {
+        let class = ValueClass::Directory(DirectoryClass::AppPassword {
+            principal_id: account_id,
+            name: name.as_bytes().to_vec(),
+        });
+
+        // Generate a random password, only its hash is stored
+        let secret = thread_rng()
+            .sample_iter(Alphanumeric)
+            .take(24)
+            .map(char::from)
+            .collect::<String>();
+        let hash = secret_hash(account_id, &secret);
+        let app_password = AppPassword {
+            name: name.to_string(),
+            secret: hash.iter().map(|byte| format!("{byte:02x}")).collect(),
+            created: now(),
+            expires,
+            protocols,
+            last_used: None,
+            last_ip: None,
+        };
+
+        let mut batch = BatchBuilder::new();
+        batch
+            .assert_value(class.clone(), ())
+            .set(class, app_password.serialize())
+            .set(
+                DirectoryClass::AppPasswordHash {
+                    principal_id: account_id,
+                    hash,
+                },
+                name.as_bytes().to_vec(),
+            )
+            .clear(DirectoryClass::AppPasswordUsage {
+                principal_id: account_id,
+                name: name.as_bytes().to_vec(),
+            });
+        match self.write(batch.build()).await {
+            Ok(_) => Ok(secret),
+            Err(store::Error::AssertValueFailed) => {
+                Err(DirectoryError::Management(ManagementError::AlreadyExists {
+                    field: PrincipalField::AppPasswords,
+                    value: name.to_string(),
+                }))
+            }
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    async fn list_app_passwords(&self, account_id: u32) -> crate::Result<Vec<AppPassword>> {
+        let from_key = ValueKey::from(ValueClass::Directory(DirectoryClass::AppPassword {
+            principal_id: account_id,
+            name: vec![],
+        }));
+        let to_key = ValueKey::from(ValueClass::Directory(DirectoryClass::AppPassword {
+            principal_id: account_id,
+            name: vec![u8::MAX],
+        }));
+        let mut results = Vec::new();
+        self.iterate(IterateParams::new(from_key, to_key), |_, value| {
+            results.push(AppPassword::deserialize(value)?);
+            Ok(true)
+        })
+        .await?;
+
+        // Add the last use, which is stored separately
+        for app_password in &mut results {
+            if let Some(usage) = self
+                .get_value::<AppPasswordUsage>(ValueKey::from(ValueClass::Directory(
+                    DirectoryClass::AppPasswordUsage {
+                        principal_id: account_id,
+                        name: app_password.name.as_bytes().to_vec(),
+                    },
+                )))
+                .await?
+            {
+                app_password.last_used = usage.last_used.into();
+                app_password.last_ip = usage.last_ip;
+            }
+        }
+
+        Ok(results)
+    }
+
+    async fn revoke_app_password(&self, account_id: u32, name: &str) -> crate::Result<bool> {
+        let class = ValueClass::Directory(DirectoryClass::AppPassword {
+            principal_id: account_id,
+            name: name.as_bytes().to_vec(),
+        });
+        if let Some(app_password) = self
+            .get_value::<HashedValue<AppPassword>>(ValueKey::from(class.clone()))
+            .await?
+        {
+            let mut batch = BatchBuilder::new();
+            batch
+                .assert_value(class.clone(), &app_password)
+                .clear(class);
+            app_password.inner.clear_index(&mut batch, account_id);
+            match self.write(batch.build()).await {
+                Ok(_) => Ok(true),
+                Err(store::Error::AssertValueFailed) => Ok(false),
+                Err(err) => Err(err.into()),
+            }
+        } else {
+            Ok(false)
+        }
+    }
+}
+
+impl Directory {
+    pub(crate) async fn authenticate_app_password(
+        &self,
+        credentials: &Credentials<String>,
+        remote_ip: IpAddr,
+        protocol: ServerProtocol,
+        return_member_of: bool,
+    ) -> crate::Result<Option<Principal<u32>>> {
+        let (username, secret) = match credentials {
+            Credentials::Plain { username, secret } => (username, secret),
+            _ => return Ok(None),
+        };
+        let store = self.store();
+        let account_id = if let Some(account_id) = store.get_account_id(username).await? {
+            account_id
+        } else {
+            return Ok(None);
+        };
+
+        // Look up the app password by the hash of its secret
+        let name = if let Some(name) = store
+            .get_value::<String>(ValueKey::from(ValueClass::Directory(
+                DirectoryClass::AppPasswordHash {
+                    principal_id: account_id,
+                    hash: secret_hash(account_id, secret),
+                },
+            )))
+            .await?
+        {
+            name
+        } else {
+            return Ok(None);
+        };
+        let class = ValueClass::Directory(DirectoryClass::AppPassword {
+            principal_id: account_id,
+            name: name.as_bytes().to_vec(),
+        });
+        let app_password = if let Some(app_password) = store
+            .get_value::<AppPassword>(ValueKey::from(class.clone()))
+            .await?
+        {
+            app_password
+        } else {
+            return Ok(None);
+        };
+        let now = now();
+        if app_password.expires.is_some_and(|expires| expires <= now)
+            || !app_password.allows(protocol)
+        {
+            return Ok(None);
+        }
+
+        let principal = if let Some(principal) = self
+            .query(QueryBy::Name(username), return_member_of)
+            .await?
+        {
+            principal
+        } else {
+            return Ok(None);
+        };
+
+        // Record last use, provided that the app password was not revoked meanwhile
+        let mut batch = BatchBuilder::new();
+        batch.assert_value(class, AssertValue::Some).set(
+            DirectoryClass::AppPasswordUsage {
+                principal_id: account_id,
+                name: name.into_bytes(),
+            },
+            AppPasswordUsage {
+                last_used: now,
+                last_ip: remote_ip.into(),
+            }
+            .serialize(),
+        );
+        match store.write(batch.build()).await {
+            Ok(_) => Ok(Some(principal)),
+            Err(store::Error::AssertValueFailed) => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+}
+
+impl AppPassword {
+    /// Clears the secret hash index and the last use of the app password.
+    pub fn clear_index(&self, batch: &mut BatchBuilder, account_id: u32) {
+        let hash = (0..self.secret.len())
+            .step_by(2)
+            .filter_map(|pos| u8::from_str_radix(self.secret.get(pos..pos + 2)?, 16).ok())
+            .collect::<Vec<_>>();
+        batch
+            .clear(DirectoryClass::AppPasswordHash {
+                principal_id: account_id,
+                hash,
+            })
+            .clear(DirectoryClass::AppPasswordUsage {
+                principal_id: account_id,
+                name: self.name.as_bytes().to_vec(),
+            });
+    }
+
+    pub fn allows(&self, protocol: ServerProtocol) -> bool {
+        let protocol = match protocol {
+            ServerProtocol::Lmtp => ServerProtocol::Smtp,
+            ServerProtocol::Http => return false,
+            protocol => protocol,
+        };
+        self.protocols.is_empty() || self.protocols.contains(&protocol)
+    }
+}
+
+impl Serialize for AppPassword {
+    fn serialize(self) -> Vec<u8> {
+        KeySerializer::new(U64_LEN * 2 + U32_LEN + self.name.len() + self.secret.len())
+            .write(1u8)
+            .write_leb128(self.created)
+            .write_leb128(self.expires.unwrap_or_default())
+            .write(protocols_to_mask(&self.protocols))
+            .write_leb128(self.name.len())
+            .write(self.name.as_bytes())
+            .write_leb128(self.secret.len())
+            .write(self.secret.as_bytes())
+            .finalize()
+    }
+}
+
+impl Deserialize for AppPassword {
+    fn deserialize(bytes: &[u8]) -> store::Result<Self> {
+        deserialize(bytes)
+            .ok_or_else(|| store::Error::InternalError("Failed to deserialize app password".into()))
+    }
+}
+
+fn deserialize(bytes: &[u8]) -> Option<AppPassword> {
+    let mut bytes = bytes.iter();
+    if bytes.next()? != &1 {
+        return None;
+    }
+    let created = bytes.next_leb128()?;
+    let expires = bytes.next_leb128::<u64>()?;
+    let protocols = mask_to_protocols(*bytes.next()?);
+
+    Some(AppPassword {
+        name: deserialize_string(&mut bytes)?,
+        secret: deserialize_string(&mut bytes)?,
+        created,
+        expires: (expires != 0).then_some(expires),
+        protocols,
+        last_used: None,
+        last_ip: None,
+    })
+}
+
+struct AppPasswordUsage {
+    last_used: u64,
+    last_ip: Option<IpAddr>,
+}
+
+impl Serialize for AppPasswordUsage {
+    fn serialize(self) -> Vec<u8> {
+        let last_ip = self.last_ip.map(|ip| ip.to_string()).unwrap_or_default();
+        KeySerializer::new(U64_LEN + last_ip.len())
+            .write_leb128(self.last_used)
+            .write(last_ip.as_bytes())
+            .finalize()
+    }
+}
+
+impl Deserialize for AppPasswordUsage {
+    fn deserialize(bytes: &[u8]) -> store::Result<Self> {
+        let mut iter = bytes.iter();
+        let last_used = iter.next_leb128::<u64>().ok_or_else(|| {
+            store::Error::InternalError("Failed to deserialize app password usage".into())
+        })?;
+        Ok(AppPasswordUsage {
+            last_used,
+            last_ip: std::str::from_utf8(iter.as_slice())
+                .ok()
+                .and_then(|ip| ip.parse().ok()),
+        })
+    }
+}
+
+// App passwords are long random strings, so a fast keyed hash is enough to
+// protect them and allows looking them up without trying every stored hash.
+fn secret_hash(account_id: u32, secret: &str) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(&account_id.to_be_bytes()).unwrap();
+    mac.update(secret.as_bytes());
+    mac.finalize().into_bytes().to_vec()
+}
+
+const PROTOCOLS: [ServerProtocol; 4] = [
+    ServerProtocol::Imap,
+    ServerProtocol::Smtp,
+    ServerProtocol::Jmap,
+    ServerProtocol::ManageSieve,
+];
+
+fn protocols_to_mask(protocols: &[ServerProtocol]) -> u8 {
+    PROTOCOLS
+        .iter()
+        .enumerate()
+        .filter(|(_, protocol)| protocols.contains(protocol))
+        .fold(0, |mask, (pos, _)| mask | (1 << pos))
+}
+
+fn mask_to_protocols(mask: u8) -> Vec<ServerProtocol> {
+    PROTOCOLS
+        .iter()
+        .enumerate()
+        .filter(|(pos, _)| mask & (1 << pos) != 0)
+        .map(|(_, protocol)| *protocol)
+        .collect()
+}
@@ -25,6 +25,7 @@ use deadpool::{
     managed::{Manager, Pool},
     Runtime,
 };
+use rand::RngCore;
 use regex::Regex;
 use std::{sync::Arc, time::Duration};
 use store::{Store, Stores};
@@ -43,7 +44,7 @@ use crate::{
     AddressMapping, Directories, Directory, DirectoryInner, Lookup,
 };
 
-use super::cache::CachedDirectory;
+use super::{cache::CachedDirectory, oidc::OidcDirectory};
 
 #[allow(async_fn_in_trait)]
 pub trait ConfigDirectory {
@@ -151,7 +152,16 @@ impl ConfigDirectory for Config {
                     ("directory", id, "options.subaddressing"),
                 )?,
                 cache: CachedDirectory::try_from_config(self, ("directory", id))?,
+                oidc: OidcDirectory::try_from_config(self, ("directory", id))?,
                 blocked_ips: servers.blocked_ips.clone(),
+                scram_dummy_key: self
+                    .text_file_contents(("directory", id, "options.scram.dummy-key"))?
+                    .map(|key| key.into_bytes())
+                    .unwrap_or_else(|| {
+                        let mut key = vec![0u8; 32];
+                        rand::thread_rng().fill_bytes(&mut key);
+                        key
+                    }),
             });
 
             // Add lookups
@@ -25,10 +25,11 @@ use std::net::IpAddr;
 
 use mail_send::Credentials;
 use store::Store;
+use utils::{config::ServerProtocol, metrics::metrics};
 
 use crate::{
-    backend::internal::lookup::DirectoryStore, AuthResult, Directory, DirectoryInner, Principal,
-    QueryBy,
+    backend::internal::lookup::DirectoryStore, core::totp::TotpStore, AuthResult, Directory,
+    DirectoryInner, Principal, QueryBy,
 };
 
 impl Directory {
@@ -36,19 +37,89 @@ impl Directory {
         &self,
         credentials: &Credentials<String>,
         remote_ip: IpAddr,
+        protocol: ServerProtocol,
         return_member_of: bool,
     ) -> crate::Result<AuthResult<Principal<u32>>> {
+        self.authenticate_(credentials, remote_ip, protocol, return_member_of, false)
+            .await
+    }
+
+    // Also accepts the account password of principals with two-factor authentication
+    // enabled, callers are responsible for verifying the second factor afterwards.
+    pub async fn authenticate_first_factor(
+        &self,
+        credentials: &Credentials<String>,
+        remote_ip: IpAddr,
+        protocol: ServerProtocol,
+        return_member_of: bool,
+    ) -> crate::Result<AuthResult<Principal<u32>>> {
+        self.authenticate_(credentials, remote_ip, protocol, return_member_of, true)
+            .await
+    }
+
+    async fn authenticate_(
+        &self,
+        credentials: &Credentials<String>,
+        remote_ip: IpAddr,
+        protocol: ServerProtocol,
+        return_member_of: bool,
+        has_second_factor: bool,
+    ) -> crate::Result<AuthResult<Principal<u32>>> {
+        let login = match credentials {
+            Credentials::Plain { username, .. }
+            | Credentials::XOauth2 { username, .. }
+            | Credentials::OAuthBearer { token: username } => username,
+        };
+
+        // Bearer tokens are validated by the external identity provider
+        if let (Some(oidc), Credentials::OAuthBearer { .. } | Credentials::XOauth2 { .. }) =
+            (&self.oidc, credentials)
+        {
+            return if let Some(principal) = self
+                .authenticate_oidc(oidc, credentials, return_member_of)
+                .await?
+            {
+                Ok(AuthResult::Success(principal))
+            } else {
+                self.auth_failure(login, remote_ip).await
+            };
+        }
+
         if let Some(principal) = self
             .query(QueryBy::Credentials(credentials), return_member_of)
             .await?
         {
-            Ok(AuthResult::Success(principal))
-        } else if self.blocked_ips.has_fail2ban() {
-            let login = match credentials {
-                Credentials::Plain { username, .. }
-                | Credentials::XOauth2 { username, .. }
-                | Credentials::OAuthBearer { token: username } => username,
-            };
+            // Once two-factor authentication is enabled, the account password is
+            // only accepted by logins which then ask for the second factor.
+            if has_second_factor || !self.is_second_factor_required(&principal).await? {
+                return Ok(AuthResult::Success(principal));
+            }
+        }
+
+        if let Some(principal) = self
+            .authenticate_app_password(credentials, remote_ip, protocol, return_member_of)
+            .await?
+        {
+            return Ok(AuthResult::Success(principal));
+        }
+
+        self.auth_failure(login, remote_ip).await
+    }
+
+    pub(crate) async fn is_second_factor_required(
+        &self,
+        principal: &Principal<u32>,
+    ) -> crate::Result<bool> {
+        self.store().is_totp_enabled(principal.id).await
+    }
+
+    pub(crate) async fn auth_failure<T>(
+        &self,
+        login: &str,
+        remote_ip: IpAddr,
+    ) -> crate::Result<AuthResult<T>> {
+        metrics().auth_failures.add(1, &[]);
+        if self.blocked_ips.has_fail2ban() {
             if let Some(banned) = self
                 .blocked_ips
                 .is_fail2banned(remote_ip, login.to_string())
@@ -209,7 +280,7 @@ impl Directory {
         }
     }
 
-    fn store(&self) -> &Store {
+    pub(crate) fn store(&self) -> &Store {
         match &self.store {
             DirectoryInner::Internal(store) => store,
             DirectoryInner::Ldap(store) => &store.data_store,
@@ -0,0 +1,397 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use store::{
+    write::{
+        assert::HashedValue,
+        key::{DeserializeBigEndian, KeySerializer},
+        now, BatchBuilder, DirectoryClass, ValueClass,
+    },
+    BlobHash, Deserialize, IterateParams, Serialize, Store, ValueKey, BLOB_HASH_LEN, U32_LEN,
+    U64_LEN,
+};
+use utils::codec::leb128::Leb128Iterator;
+
+use crate::{
+    backend::internal::{deserialize_string, deserialize_string_list, PrincipalIdType},
+    Type,
+};
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct MailingList {
+    #[serde(default)]
+    pub policy: PostingPolicy,
+    #[serde(default)]
+    pub moderators: Vec<String>,
+    #[serde(default, rename = "rewriteFrom")]
+    pub rewrite_from: FromRewrite,
+    #[serde(default, rename = "subjectPrefix")]
+    pub subject_prefix: Option<String>,
+    #[serde(default)]
+    pub archive: bool,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum PostingPolicy {
+    #[default]
+    Open = 0,
+    MembersOnly = 1,
+    Moderated = 2,
+    AnnounceOnly = 3,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum FromRewrite {
+    Never = 0,
+    #[default]
+    Dmarc = 1,
+    Always = 2,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct HeldMessage {
+    pub id: u64,
+    pub sender: String,
+    pub subject: String,
+    #[serde(skip)]
+    pub blob_hash: BlobHash,
+    pub size: usize,
+    pub received: u64,
+    pub expires: u64,
+}
+
+#[allow(async_fn_in_trait)]
+pub trait MailingListStore: Sized {
+    async fn get_list_id(&self, address: &str) -> crate::Result<Option<u32>>;
+    async fn get_mailing_list(&self, list_id: u32) -> crate::Result<Option<MailingList>>;
+    async fn set_mailing_list(&self, list_id: u32, list: Option<MailingList>) -> crate::Result<()>;
+    async fn list_subscribers(&self, list_id: u32) -> crate::Result<Vec<String>>;
+    async fn add_subscriber(&self, list_id: u32, email: &str) -> crate::Result<bool>;
+    async fn remove_subscriber(&self, list_id: u32, email: &str) -> crate::Result<bool>;
+    async fn hold_message(&self, list_id: u32, message: HeldMessage) -> crate::Result<()>;
+    async fn list_held_messages(&self, list_id: u32) -> crate::Result<Vec<HeldMessage>>;
+    async fn take_held_message(&self, list_id: u32, id: u64) -> crate::Result<Option<HeldMessage>>;
+    async fn purge_held_messages(&self) -> crate::Result<usize>;
+}
+
+impl MailingListStore for Store {
+    async fn get_list_id(&self, address: &str) -> crate::Result<Option<u32>> {
+        Ok(self
+            .get_value::<PrincipalIdType>(ValueKey::from(ValueClass::Directory(
+                DirectoryClass::EmailToId(address.as_bytes().to_vec()),
+            )))
+            .await?
+            .filter(|ptype| ptype.typ == Type::List)
+            .map(|ptype| ptype.account_id))
+    }
+
+    async fn get_mailing_list(&self, list_id: u32) -> crate::Result<Option<MailingList>> {
+        self.get_value::<MailingList>(ValueKey::from(ValueClass::Directory(
+            DirectoryClass::MailingList(list_id),
+        )))
+        .await
+        .map_err(Into::into)
+    }
+
+    async fn set_mailing_list(&self, list_id: u32, list: Option<MailingList>) -> crate::Result<()> {
+        let mut batch = BatchBuilder::new();
+        let class = ValueClass::Directory(DirectoryClass::MailingList(list_id));
+        if let Some(list) = list {
+            batch.set(class, list.serialize());
+        } else {
+            batch.clear(class);
+        }
+        self.write(batch.build()).await?;
+        Ok(())
+    }
+
+    async fn list_subscribers(&self, list_id: u32) -> crate::Result<Vec<String>> {
+        let from_key = ValueKey::from(ValueClass::Directory(DirectoryClass::ListSubscriber {
+            list_id,
+            email: vec![],
+        }));
+        let to_key = ValueKey::from(ValueClass::Directory(DirectoryClass::ListSubscriber {
+            list_id,
+            email: vec![u8::MAX],
+        }));
+        let mut results = Vec::new();
+        self.iterate(
+            IterateParams::new(from_key, to_key).no_values(),
+            |key, _| {
+                results.push(
+                    std::str::from_utf8(key.get(U32_LEN + 1..).unwrap_or_default())
+                        .unwrap_or_default()
+                        .to_string(),
+                );
+                Ok(true)
+            },
+        )
+        .await?;
+        Ok(results)
+    }
+
+    async fn add_subscriber(&self, list_id: u32, email: &str) -> crate::Result<bool> {
+        let class = ValueClass::Directory(DirectoryClass::ListSubscriber {
+            list_id,
+            email: email.to_lowercase().into_bytes(),
+        });
+        if self
+            .get_value::<u64>(ValueKey::from(class.clone()))
+            .await?
+            .is_none()
+        {
+            let mut batch = BatchBuilder::new();
+            batch.set(class, now().serialize());
+            self.write(batch.build()).await?;
+            Ok(true)
+        } else {
+            Ok(false)
+        }
+    }
+
+    async fn remove_subscriber(&self, list_id: u32, email: &str) -> crate::Result<bool> {
+        let class = ValueClass::Directory(DirectoryClass::ListSubscriber {
+            list_id,
+            email: email.to_lowercase().into_bytes(),
+        });
+        if self
+            .get_value::<u64>(ValueKey::from(class.clone()))
+            .await?
+            .is_some()
+        {
+            let mut batch = BatchBuilder::new();
+            batch.clear(class);
+            self.write(batch.build()).await?;
+            Ok(true)
+        } else {
+            Ok(false)
+        }
+    }
+
+    async fn hold_message(&self, list_id: u32, message: HeldMessage) -> crate::Result<()> {
+        let mut batch = BatchBuilder::new();
+        batch.set(
+            ValueClass::Directory(DirectoryClass::ListModeration {
+                list_id,
+                id: message.id,
+            }),
+            message.serialize(),
+        );
+        self.write(batch.build()).await?;
+        Ok(())
+    }
+
+    async fn list_held_messages(&self, list_id: u32) -> crate::Result<Vec<HeldMessage>> {
+        let from_key = ValueKey::from(ValueClass::Directory(DirectoryClass::ListModeration {
+            list_id,
+            id: 0,
+        }));
+        let to_key = ValueKey::from(ValueClass::Directory(DirectoryClass::ListModeration {
+            list_id,
+            id: u64::MAX,
+        }));
+        let now = now();
+        let mut results = Vec::new();
+        self.iterate(IterateParams::new(from_key, to_key), |_, value| {
+            let message = HeldMessage::deserialize(value)?;
+            if message.expires > now {
+                results.push(message);
+            }
+            Ok(true)
+        })
+        .await?;
+        Ok(results)
+    }
+
+    async fn take_held_message(&self, list_id: u32, id: u64) -> crate::Result<Option<HeldMessage>> {
+        let class = ValueClass::Directory(DirectoryClass::ListModeration { list_id, id });
+        if let Some(message) = self
+            .get_value::<HashedValue<HeldMessage>>(ValueKey::from(class.clone()))
+            .await?
+        {
+            // Only one of several concurrent approvals obtains the message
+            let mut batch = BatchBuilder::new();
+            batch.assert_value(class.clone(), &message).clear(class);
+            match self.write(batch.build()).await {
+                Ok(_) => Ok(Some(message.inner).filter(|message| message.expires > now())),
+                Err(store::Error::AssertValueFailed) => Ok(None),
+                Err(err) => Err(err.into()),
+            }
+        } else {
+            Ok(None)
+        }
+    }
+
+    async fn purge_held_messages(&self) -> crate::Result<usize> {
+        let from_key = ValueKey::from(ValueClass::Directory(DirectoryClass::ListModeration {
+            list_id: 0,
+            id: 0,
+        }));
+        let to_key = ValueKey::from(ValueClass::Directory(DirectoryClass::ListModeration {
+            list_id: u32::MAX,
+            id: u64::MAX,
+        }));
+        let now = now();
+        let mut expired = Vec::new();
+        self.iterate(IterateParams::new(from_key, to_key), |key, value| {
+            if HeldMessage::deserialize(value)?.expires <= now {
+                expired.push((
+                    key.deserialize_be_u32(1)?,
+                    key.deserialize_be_u64(U32_LEN + 1)?,
+                ));
+            }
+            Ok(true)
+        })
+        .await?;
+
+        // The blobs are released once their reservation expires
+        let num_expired = expired.len();
+        for chunk in expired.chunks(1000) {
+            let mut batch = BatchBuilder::new();
+            for (list_id, id) in chunk {
+                batch.clear(ValueClass::Directory(DirectoryClass::ListModeration {
+                    list_id: *list_id,
+                    id: *id,
+                }));
+            }
+            self.write(batch.build()).await?;
+        }
+
+        Ok(num_expired)
+    }
+}
+
+impl Serialize for MailingList {
+    fn serialize(self) -> Vec<u8> {
+        let subject_prefix = self.subject_prefix.unwrap_or_default();
+        let mut serializer = KeySerializer::new(
+            U32_LEN * 2
+                + 3
+                + subject_prefix.len()
+                + self.moderators.iter().map(|s| s.len() + 1).sum::<usize>(),
+        )
+        .write(1u8)
+        .write(self.policy as u8)
+        .write(self.rewrite_from as u8)
+        .write(self.archive as u8)
+        .write_leb128(subject_prefix.len())
+        .write(subject_prefix.as_bytes())
+        .write_leb128(self.moderators.len());
+        for moderator in &self.moderators {
+            serializer = serializer
+                .write_leb128(moderator.len())
+                .write(moderator.as_bytes());
+        }
+        serializer.finalize()
+    }
+}
+
+impl Deserialize for MailingList {
+    fn deserialize(bytes: &[u8]) -> store::Result<Self> {
+        deserialize_list(bytes)
+            .ok_or_else(|| store::Error::InternalError("Failed to deserialize mailing list".into()))
+    }
+}
+
+fn deserialize_list(bytes: &[u8]) -> Option<MailingList> {
+    let mut bytes = bytes.iter();
+    if bytes.next()? != &1 {
+        return None;
+    }
+    let policy = match bytes.next()? {
+        0 => PostingPolicy::Open,
+        1 => PostingPolicy::MembersOnly,
+        2 => PostingPolicy::Moderated,
+        3 => PostingPolicy::AnnounceOnly,
+        _ => return None,
+    };
+    let rewrite_from = match bytes.next()? {
+        0 => FromRewrite::Never,
+        1 => FromRewrite::Dmarc,
+        2 => FromRewrite::Always,
+        _ => return None,
+    };
+    let archive = *bytes.next()? != 0;
+    let subject_prefix = deserialize_string(&mut bytes)?;
+
+    Some(MailingList {
+        policy,
+        moderators: deserialize_string_list(&mut bytes)?,
+        rewrite_from,
+        subject_prefix: (!subject_prefix.is_empty()).then_some(subject_prefix),
+        archive,
+    })
+}
+
+impl Serialize for HeldMessage {
+    fn serialize(self) -> Vec<u8> {
+        KeySerializer::new(
+            U64_LEN * 4 + BLOB_HASH_LEN + 1 + self.sender.len() + self.subject.len() + 2,
+        )
+        .write(1u8)
+        .write_leb128(self.id)
+        .write_leb128(self.size)
+        .write_leb128(self.received)
+        .write_leb128(self.expires)
+        .write(self.blob_hash.as_slice())
+        .write_leb128(self.sender.len())
+        .write(self.sender.as_bytes())
+        .write_leb128(self.subject.len())
+        .write(self.subject.as_bytes())
+        .finalize()
+    }
+}
+
+impl Deserialize for HeldMessage {
+    fn deserialize(bytes: &[u8]) -> store::Result<Self> {
+        deserialize_held(bytes)
+            .ok_or_else(|| store::Error::InternalError("Failed to deserialize held message".into()))
+    }
+}
+
+fn deserialize_held(bytes: &[u8]) -> Option<HeldMessage> {
+    let mut bytes = bytes.iter();
+    if bytes.next()? != &1 {
+        return None;
+    }
+    let id = bytes.next_leb128()?;
+    let size = bytes.next_leb128()?;
+    let received = bytes.next_leb128()?;
+    let expires = bytes.next_leb128()?;
+    let mut blob_hash = [0u8; BLOB_HASH_LEN];
+    for byte in blob_hash.iter_mut() {
+        *byte = *bytes.next()?;
+    }
+
+    Some(HeldMessage {
+        id,
+        blob_hash: BlobHash::try_from_hash_slice(&blob_hash).ok()?,
+        size,
+        received,
+        expires,
+        sender: deserialize_string(&mut bytes)?,
+        subject: deserialize_string(&mut bytes)?,
+    })
+}
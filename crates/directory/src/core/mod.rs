@@ -21,7 +21,12 @@
  * for more details.
 */
 
+pub mod app_password;
 pub mod cache;
 pub mod config;
 pub mod dispatch;
+pub mod mailing_list;
+pub mod oidc;
+pub mod scram;
 pub mod secret;
+pub mod totp;
@@ -0,0 +1,593 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::time::{Duration, Instant, SystemTime};
+
+use ahash::AHashMap;
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use mail_send::Credentials;
+use parking_lot::Mutex;
+use rsa::{pkcs1v15::VerifyingKey, signature::Verifier, BigUint, RsaPublicKey};
+use serde_json::{Map, Value};
+use sha2::{Sha256, Sha384, Sha512};
+use store::{
+    write::{BatchBuilder, DirectoryClass, ValueClass},
+    Serialize, Store, ValueKey,
+};
+use utils::config::{utils::AsKey, Config};
+
+use crate::{
+    backend::internal::{
+        lookup::DirectoryStore, manage::ManageDirectory, PrincipalField, PrincipalUpdate,
+        PrincipalValue,
+    },
+    Directory, DirectoryError, DirectoryInner, Principal, QueryBy,
+};
+
+pub struct OidcDirectory {
+    issuer: String,
+    audience: String,
+    method: OidcMethod,
+    claim_username: String,
+    claim_email: String,
+    claim_name: String,
+    provision: bool,
+    client: reqwest::Client,
+}
+
+enum OidcMethod {
+    Jwks {
+        url: String,
+        ttl: Duration,
+        min_refresh: Duration,
+        keys: Mutex<JwksCache>,
+    },
+    Introspect {
+        url: String,
+        client_id: String,
+        client_secret: Option<String>,
+    },
+}
+
+#[derive(Default)]
+struct JwksCache {
+    keys: AHashMap<String, RsaPublicKey>,
+    valid_until: Option<Instant>,
+    last_fetch: Option<Instant>,
+}
+
+// Clock skew tolerated when validating token timestamps
+const LEEWAY: u64 = 60;
+
+impl OidcDirectory {
+    pub fn try_from_config(
+        config: &Config,
+        prefix: impl AsKey,
+    ) -> utils::config::Result<Option<Self>> {
+        let prefix = prefix.as_key();
+        let issuer = if let Some(issuer) = config.value((&prefix, "oidc.issuer")) {
+            issuer.trim_end_matches('/').to_string()
+        } else {
+            return Ok(None);
+        };
+
+        let method = match config.value((&prefix, "oidc.method")).unwrap_or("jwks") {
+            "jwks" => OidcMethod::Jwks {
+                url: config
+                    .value_require((&prefix, "oidc.jwks.url"))?
+                    .to_string(),
+                ttl: config.property_or_static((&prefix, "oidc.jwks.cache"), "1h")?,
+                min_refresh: config.property_or_static((&prefix, "oidc.jwks.min-refresh"), "1m")?,
+                keys: Mutex::new(JwksCache::default()),
+            },
+            "introspect" => OidcMethod::Introspect {
+                url: config
+                    .value_require((&prefix, "oidc.introspect.url"))?
+                    .to_string(),
+                client_id: config
+                    .value_require((&prefix, "oidc.introspect.client-id"))?
+                    .to_string(),
+                client_secret: config
+                    .value((&prefix, "oidc.introspect.client-secret"))
+                    .map(|secret| secret.to_string()),
+            },
+            method => {
+                return Err(format!(
+                    "Invalid OIDC validation method {method:?} for key {:?}.",
+                    (&prefix, "oidc.method").as_key()
+                ))
+            }
+        };
+
+        Ok(Some(OidcDirectory {
+            issuer,
+            audience: config
+                .value_require((&prefix, "oidc.audience"))?
+                .to_string(),
+            method,
+            claim_username: config
+                .value((&prefix, "oidc.claims.username"))
+                .unwrap_or("preferred_username")
+                .to_string(),
+            claim_email: config
+                .value((&prefix, "oidc.claims.email"))
+                .unwrap_or("email")
+                .to_string(),
+            claim_name: config
+                .value((&prefix, "oidc.claims.name"))
+                .unwrap_or("name")
+                .to_string(),
+            provision: config.property_or_static((&prefix, "oidc.provision"), "false")?,
+            client: reqwest::Client::builder()
+                .timeout(config.property_or_static((&prefix, "oidc.timeout"), "30s")?)
+                .build()
+                .map_err(|err| format!("Failed to build OIDC HTTP client: {err}"))?,
+        }))
+    }
+
+    // Validates a bearer token and returns its claims
+    pub async fn validate(&self, token: &str) -> crate::Result<Option<Map<String, Value>>> {
+        let claims = match &self.method {
+            OidcMethod::Jwks { .. } => self.validate_jwt(token).await?,
+            OidcMethod::Introspect {
+                url,
+                client_id,
+                client_secret,
+            } => {
+                let response = self
+                    .client
+                    .post(url)
+                    .basic_auth(client_id, client_secret.as_ref())
+                    .form(&[("token", token), ("token_type_hint", "access_token")])
+                    .send()
+                    .await?
+                    .error_for_status()?
+                    .json::<Map<String, Value>>()
+                    .await?;
+                if response.get("active").and_then(|v| v.as_bool()) == Some(true) {
+                    Some(response)
+                } else {
+                    None
+                }
+            }
+        };
+
+        Ok(claims.filter(|claims| self.validate_claims(claims)))
+    }
+
+    async fn validate_jwt(&self, token: &str) -> crate::Result<Option<Map<String, Value>>> {
+        let (message, signature) = match token.rsplit_once('.') {
+            Some(parts) => parts,
+            None => return Ok(None),
+        };
+        let (header, payload) = match message.split_once('.') {
+            Some(parts) => parts,
+            None => return Ok(None),
+        };
+        let (header, payload, signature) = match (
+            decode_json(header),
+            decode_json(payload),
+            URL_SAFE_NO_PAD.decode(signature),
+        ) {
+            (Some(header), Some(payload), Ok(signature)) => (header, payload, signature),
+            _ => return Ok(None),
+        };
+        let key_id = header
+            .get("kid")
+            .and_then(|v| v.as_str())
+            .unwrap_or_default();
+        let key = match self.signing_key(key_id).await? {
+            Some(key) => key,
+            None => return Ok(None),
+        };
+        let signature = match rsa::pkcs1v15::Signature::try_from(signature.as_slice()) {
+            Ok(signature) => signature,
+            Err(_) => return Ok(None),
+        };
+
+        let is_valid = match header.get("alg").and_then(|v| v.as_str()) {
+            Some("RS256") => VerifyingKey::<Sha256>::new(key)
+                .verify(message.as_bytes(), &signature)
+                .is_ok(),
+            Some("RS384") => VerifyingKey::<Sha384>::new(key)
+                .verify(message.as_bytes(), &signature)
+                .is_ok(),
+            Some("RS512") => VerifyingKey::<Sha512>::new(key)
+                .verify(message.as_bytes(), &signature)
+                .is_ok(),
+            _ => false,
+        };
+
+        Ok(if is_valid { Some(payload) } else { None })
+    }
+
+    async fn signing_key(&self, key_id: &str) -> crate::Result<Option<RsaPublicKey>> {
+        let (url, ttl, min_refresh, keys) = match &self.method {
+            OidcMethod::Jwks {
+                url,
+                ttl,
+                min_refresh,
+                keys,
+            } => (url, ttl, min_refresh, keys),
+            OidcMethod::Introspect { .. } => return Ok(None),
+        };
+
+        // Refresh the key set when it expires or an unknown key is requested,
+        // the provider is contacted at most once per refresh interval.
+        {
+            let mut keys = keys.lock();
+            let now = Instant::now();
+            let key = keys.keys.get(key_id).cloned();
+            if (key.is_some()
+                && keys
+                    .valid_until
+                    .is_some_and(|valid_until| valid_until > now))
+                || keys
+                    .last_fetch
+                    .is_some_and(|last_fetch| last_fetch + *min_refresh > now)
+            {
+                return Ok(key);
+            }
+            keys.last_fetch = Some(now);
+        }
+
+        let jwks = self
+            .client
+            .get(url)
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<Value>()
+            .await?;
+        let mut fetched_keys = AHashMap::new();
+        for jwk in jwks
+            .get("keys")
+            .and_then(|keys| keys.as_array())
+            .map(|keys| keys.as_slice())
+            .unwrap_or_default()
+        {
+            if jwk.get("kty").and_then(|v| v.as_str()) != Some("RSA")
+                || jwk
+                    .get("use")
+                    .and_then(|v| v.as_str())
+                    .is_some_and(|v| v != "sig")
+            {
+                continue;
+            }
+            if let (Some(n), Some(e)) = (
+                jwk.get("n")
+                    .and_then(|v| v.as_str())
+                    .and_then(|v| URL_SAFE_NO_PAD.decode(v).ok()),
+                jwk.get("e")
+                    .and_then(|v| v.as_str())
+                    .and_then(|v| URL_SAFE_NO_PAD.decode(v).ok()),
+            ) {
+                if let Ok(key) =
+                    RsaPublicKey::new(BigUint::from_bytes_be(&n), BigUint::from_bytes_be(&e))
+                {
+                    fetched_keys.insert(
+                        jwk.get("kid")
+                            .and_then(|v| v.as_str())
+                            .unwrap_or_default()
+                            .to_string(),
+                        key,
+                    );
+                }
+            }
+        }
+
+        let key = fetched_keys.get(key_id).cloned();
+        let mut keys = keys.lock();
+        keys.keys = fetched_keys;
+        keys.valid_until = Some(Instant::now() + *ttl);
+
+        Ok(key)
+    }
+
+    fn validate_claims(&self, claims: &Map<String, Value>) -> bool {
+        let now = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+
+        // Validate issuer
+        if let Some(issuer) = claims.get("iss").and_then(|v| v.as_str()) {
+            if issuer.trim_end_matches('/') != self.issuer {
+                return false;
+            }
+        } else if matches!(self.method, OidcMethod::Jwks { .. }) {
+            return false;
+        }
+
+        // Validate expiration
+        match claims.get("exp").and_then(|v| v.as_u64()) {
+            Some(exp) if exp + LEEWAY < now => return false,
+            None if matches!(self.method, OidcMethod::Jwks { .. }) => return false,
+            _ => (),
+        }
+        if claims
+            .get("nbf")
+            .and_then(|v| v.as_u64())
+            .is_some_and(|nbf| nbf > now + LEEWAY)
+        {
+            return false;
+        }
+
+        // Validate audience
+        match claims.get("aud") {
+            Some(Value::String(aud)) if aud == &self.audience => (),
+            Some(Value::Array(auds)) if auds.iter().any(|aud| aud == &self.audience) => (),
+            _ => return false,
+        }
+
+        true
+    }
+
+    async fn linked_account(&self, store: &Store, subject: &str) -> crate::Result<Option<u32>> {
+        store
+            .get_value::<u32>(ValueKey::from(ValueClass::Directory(
+                self.subject_class(subject),
+            )))
+            .await
+            .map_err(Into::into)
+    }
+
+    async fn link_account(
+        &self,
+        store: &Store,
+        subject: &str,
+        account_id: u32,
+    ) -> crate::Result<()> {
+        let mut batch = BatchBuilder::new();
+        batch.set(self.subject_class(subject), account_id.serialize());
+        store
+            .write(batch.build())
+            .await
+            .map(|_| ())
+            .map_err(Into::into)
+    }
+
+    fn subject_class(&self, subject: &str) -> DirectoryClass {
+        DirectoryClass::OidcSubject(format!("{}\n{}", self.issuer, subject).into_bytes())
+    }
+
+    fn claim<'x>(&self, claims: &'x Map<String, Value>, name: &str) -> Option<&'x str> {
+        claims
+            .get(name)
+            .and_then(|v| v.as_str())
+            .filter(|v| !v.is_empty())
+    }
+}
+
+impl Directory {
+    // Links an identity of the external provider to an existing account
+    pub async fn link_oidc_subject(&self, subject: &str, account_id: u32) -> crate::Result<bool> {
+        if let Some(oidc) = &self.oidc {
+            oidc.link_account(self.store(), subject, account_id)
+                .await
+                .map(|_| true)
+        } else {
+            Ok(false)
+        }
+    }
+
+    pub async fn unlink_oidc_subject(&self, subject: &str, account_id: u32) -> crate::Result<bool> {
+        if let Some(oidc) = &self.oidc {
+            if oidc.linked_account(self.store(), subject).await? == Some(account_id) {
+                let mut batch = BatchBuilder::new();
+                batch.clear(oidc.subject_class(subject));
+                self.store().write(batch.build()).await?;
+                return Ok(true);
+            }
+        }
+
+        Ok(false)
+    }
+
+    pub(crate) async fn authenticate_oidc(
+        &self,
+        oidc: &OidcDirectory,
+        credentials: &Credentials<String>,
+        return_member_of: bool,
+    ) -> crate::Result<Option<Principal<u32>>> {
+        let (username, token) = match credentials {
+            Credentials::OAuthBearer { token } => (None, bearer_token(token)),
+            Credentials::XOauth2 { username, secret } => (Some(username), secret.as_str()),
+            Credentials::Plain { .. } => return Ok(None),
+        };
+        let claims = match oidc.validate(token).await? {
+            Some(claims) => claims,
+            None => {
+                tracing::debug!(
+                    context = "directory",
+                    event = "oidc",
+                    "Failed to validate bearer token"
+                );
+                return Ok(None);
+            }
+        };
+
+        // Identities are linked to accounts by issuer and subject
+        let subject = match oidc.claim(&claims, "sub") {
+            Some(subject) => subject,
+            None => {
+                tracing::debug!(
+                    context = "directory",
+                    event = "oidc",
+                    "Bearer token does not contain a subject claim"
+                );
+                return Ok(None);
+            }
+        };
+        if let Some(principal) = oidc.linked_account(self.store(), subject).await? {
+            return Ok(self
+                .query(QueryBy::Id(principal), return_member_of)
+                .await?
+                .filter(|principal| is_token_owner(principal, username)));
+        }
+
+        // Map claims to a principal, the email address is only trusted once verified
+        let email = oidc
+            .claim(&claims, &oidc.claim_email)
+            .filter(|_| claims.get("email_verified").and_then(|v| v.as_bool()) == Some(true))
+            .map(|email| email.to_lowercase());
+        let name = match oidc
+            .claim(&claims, &oidc.claim_username)
+            .map(|name| name.to_string())
+            .or_else(|| email.clone())
+        {
+            Some(name) => name,
+            None => {
+                tracing::debug!(
+                    context = "directory",
+                    event = "oidc",
+                    claim = oidc.claim_username,
+                    "Bearer token does not contain a username claim"
+                );
+                return Ok(None);
+            }
+        };
+
+        // XOAUTH2 requires the username to match the token owner
+        if let Some(username) = username {
+            if !username.eq_ignore_ascii_case(&name)
+                && email
+                    .as_ref()
+                    .is_none_or(|email| !username.eq_ignore_ascii_case(email))
+            {
+                return Ok(None);
+            }
+        }
+
+        // Existing accounts have to be linked explicitly by an administrator
+        if self.query(QueryBy::Name(&name), false).await?.is_some()
+            || match &email {
+                Some(email) => !self.email_to_ids(email).await?.is_empty(),
+                None => false,
+            }
+        {
+            tracing::debug!(
+                context = "directory",
+                event = "oidc",
+                account = name,
+                subject = subject,
+                "Bearer token is valid but the account is not linked to this identity"
+            );
+            return Ok(None);
+        }
+
+        // Provision account
+        match &self.store {
+            DirectoryInner::Internal(store) if oidc.provision => {
+                let account_id = store.get_or_create_account_id(&name).await?;
+                let mut changes = Vec::new();
+                if let Some(email) = email {
+                    changes.push(PrincipalUpdate::set(
+                        PrincipalField::Emails,
+                        PrincipalValue::StringList(vec![email]),
+                    ));
+                }
+                if let Some(description) = oidc.claim(&claims, &oidc.claim_name) {
+                    changes.push(PrincipalUpdate::set(
+                        PrincipalField::Description,
+                        PrincipalValue::String(description.to_string()),
+                    ));
+                }
+                if !changes.is_empty() {
+                    if let Err(err) = store.update_account(QueryBy::Id(account_id), changes).await {
+                        tracing::warn!(
+                            context = "directory",
+                            event = "oidc",
+                            account = name,
+                            reason = ?err,
+                            "Failed to update provisioned account"
+                        );
+                    }
+                }
+
+                oidc.link_account(store, subject, account_id).await?;
+
+                tracing::info!(
+                    context = "directory",
+                    event = "oidc",
+                    account = name,
+                    account_id = account_id,
+                    subject = subject,
+                    "Provisioned account"
+                );
+
+                store.query(QueryBy::Id(account_id), return_member_of).await
+            }
+            _ => {
+                tracing::debug!(
+                    context = "directory",
+                    event = "oidc",
+                    account = name,
+                    "Bearer token is valid but the account does not exist"
+                );
+                Ok(None)
+            }
+        }
+    }
+}
+
+impl From<reqwest::Error> for DirectoryError {
+    fn from(error: reqwest::Error) -> Self {
+        tracing::warn!(
+            context = "directory",
+            event = "error",
+            protocol = "oidc",
+            reason = %error,
+            "OIDC provider error"
+        );
+
+        DirectoryError::Http(error)
+    }
+}
+
+// XOAUTH2 requires the username to match the token owner
+fn is_token_owner(principal: &Principal<u32>, username: Option<&String>) -> bool {
+    username.is_none_or(|username| {
+        principal.name.eq_ignore_ascii_case(username)
+            || principal
+                .emails
+                .iter()
+                .any(|email| email.eq_ignore_ascii_case(username))
+    })
+}
+
+// SMTP passes the full OAUTHBEARER message, strip the GS2 header if present
+fn bearer_token(token: &str) -> &str {
+    if let Some((_, token)) = token.split_once("auth=Bearer ") {
+        token.split('\x01').next().unwrap_or_default()
+    } else {
+        token
+    }
+}
+
+fn decode_json(value: &str) -> Option<Map<String, Value>> {
+    URL_SAFE_NO_PAD
+        .decode(value)
+        .ok()
+        .and_then(|value| serde_json::from_slice(&value).ok())
+}
@@ -0,0 +1,472 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::{fmt::Display, net::IpAddr};
+
+use hmac::{Hmac, Mac};
+use mail_builder::encoders::base64::base64_encode;
+use mail_parser::decoders::base64::base64_decode;
+use rand::RngCore;
+use sha1::{Digest, Sha1};
+use sha2::Sha256;
+use utils::constant_time_eq;
+
+use crate::{AuthResult, Directory, Principal, QueryBy};
+
+pub const SCRAM_ITERATIONS: u32 = 4096;
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 18;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScramAlgorithm {
+    Sha1,
+    Sha256,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScramSecret {
+    pub algorithm: ScramAlgorithm,
+    pub iterations: u32,
+    pub salt: Vec<u8>,
+    pub stored_key: Vec<u8>,
+    pub server_key: Vec<u8>,
+}
+
+pub struct ScramSession {
+    algorithm: ScramAlgorithm,
+    is_plus: bool,
+    channel_binding: Option<Vec<u8>>,
+    state: ScramState,
+}
+
+enum ScramState {
+    ClientFirst,
+    ClientFinal(Box<ServerFirst>),
+    Done,
+}
+
+struct ServerFirst {
+    username: String,
+    gs2_header: String,
+    client_first_bare: String,
+    server_first: String,
+    nonce: String,
+    principal: Option<Principal<u32>>,
+    secret: ScramSecret,
+}
+
+impl ScramAlgorithm {
+    pub fn parse(value: &str) -> Option<Self> {
+        if value.eq_ignore_ascii_case("SCRAM-SHA-256") {
+            Some(ScramAlgorithm::Sha256)
+        } else if value.eq_ignore_ascii_case("SCRAM-SHA-1") {
+            Some(ScramAlgorithm::Sha1)
+        } else {
+            None
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ScramAlgorithm::Sha1 => "SCRAM-SHA-1",
+            ScramAlgorithm::Sha256 => "SCRAM-SHA-256",
+        }
+    }
+
+    fn hash_len(&self) -> usize {
+        match self {
+            ScramAlgorithm::Sha1 => 20,
+            ScramAlgorithm::Sha256 => 32,
+        }
+    }
+
+    fn hash(&self, data: &[u8]) -> Vec<u8> {
+        match self {
+            ScramAlgorithm::Sha1 => Sha1::digest(data).to_vec(),
+            ScramAlgorithm::Sha256 => Sha256::digest(data).to_vec(),
+        }
+    }
+
+    fn hmac(&self, key: &[u8], data: &[u8]) -> Vec<u8> {
+        match self {
+            ScramAlgorithm::Sha1 => {
+                let mut mac = Hmac::<Sha1>::new_from_slice(key).unwrap();
+                mac.update(data);
+                mac.finalize().into_bytes().to_vec()
+            }
+            ScramAlgorithm::Sha256 => {
+                let mut mac = Hmac::<Sha256>::new_from_slice(key).unwrap();
+                mac.update(data);
+                mac.finalize().into_bytes().to_vec()
+            }
+        }
+    }
+
+    fn salted_password(&self, password: &[u8], salt: &[u8], iterations: u32) -> Vec<u8> {
+        let mut salted_password = vec![0u8; self.hash_len()];
+        match self {
+            ScramAlgorithm::Sha1 => {
+                pbkdf2::pbkdf2_hmac::<Sha1>(password, salt, iterations, &mut salted_password)
+            }
+            ScramAlgorithm::Sha256 => {
+                pbkdf2::pbkdf2_hmac::<Sha256>(password, salt, iterations, &mut salted_password)
+            }
+        }
+        salted_password
+    }
+}
+
+impl ScramSecret {
+    pub fn new(algorithm: ScramAlgorithm, password: &str) -> Self {
+        let mut salt = vec![0u8; SALT_LEN];
+        rand::thread_rng().fill_bytes(&mut salt);
+        Self::derive(algorithm, password, salt, SCRAM_ITERATIONS)
+    }
+
+    pub fn derive(
+        algorithm: ScramAlgorithm,
+        password: &str,
+        salt: Vec<u8>,
+        iterations: u32,
+    ) -> Self {
+        let salted_password = algorithm.salted_password(password.as_bytes(), &salt, iterations);
+        ScramSecret {
+            algorithm,
+            iterations,
+            salt,
+            stored_key: algorithm.hash(&algorithm.hmac(&salted_password, b"Client Key")),
+            server_key: algorithm.hmac(&salted_password, b"Server Key"),
+        }
+    }
+
+    // Parses a secret in the format "<iterations>:<salt>$<stored_key>:<server_key>"
+    pub fn parse(algorithm: ScramAlgorithm, value: &str) -> Option<Self> {
+        let (params, keys) = value.split_once('$')?;
+        let (iterations, salt) = params.split_once(':')?;
+        let (stored_key, server_key) = keys.split_once(':')?;
+        let secret = ScramSecret {
+            algorithm,
+            iterations: iterations.parse().ok().filter(|&i| i > 0)?,
+            salt: base64_decode(salt.as_bytes())?,
+            stored_key: base64_decode(stored_key.as_bytes())?,
+            server_key: base64_decode(server_key.as_bytes())?,
+        };
+
+        if secret.stored_key.len() == algorithm.hash_len()
+            && secret.server_key.len() == algorithm.hash_len()
+        {
+            Some(secret)
+        } else {
+            None
+        }
+    }
+
+    pub fn verify(&self, password: &str) -> bool {
+        constant_time_eq(
+            &ScramSecret::derive(self.algorithm, password, self.salt.clone(), self.iterations)
+                .stored_key,
+            &self.stored_key,
+        )
+    }
+
+    // Derived from the username so that repeated challenges for an unknown
+    // account return the same salt and iteration count as an existing one would.
+    fn dummy(algorithm: ScramAlgorithm, key: &[u8], username: &str) -> Self {
+        let mut mac = Hmac::<Sha256>::new_from_slice(key).unwrap();
+        mac.update(algorithm.as_str().as_bytes());
+        mac.update(b"\n");
+        mac.update(username.to_lowercase().as_bytes());
+        let seed = mac.finalize().into_bytes();
+
+        ScramSecret {
+            algorithm,
+            iterations: SCRAM_ITERATIONS,
+            salt: seed[..SALT_LEN].to_vec(),
+            stored_key: algorithm.hash(&algorithm.hmac(&seed, b"Client Key")),
+            server_key: algorithm.hmac(&seed, b"Server Key"),
+        }
+    }
+}
+
+impl Display for ScramSecret {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{{{}}}{}:{}${}:{}",
+            self.algorithm.as_str(),
+            self.iterations,
+            encode(&self.salt),
+            encode(&self.stored_key),
+            encode(&self.server_key)
+        )
+    }
+}
+
+impl<T: serde::Serialize + serde::de::DeserializeOwned> Principal<T> {
+    pub fn scram_secret(&self, algorithm: ScramAlgorithm) -> Option<ScramSecret> {
+        self.secrets.iter().find_map(|secret| {
+            secret
+                .strip_prefix('{')
+                .and_then(|secret| secret.split_once('}'))
+                .filter(|(algo, _)| ScramAlgorithm::parse(algo) == Some(algorithm))
+                .and_then(|(_, secret)| ScramSecret::parse(algorithm, secret))
+        })
+    }
+
+    pub fn has_scram_secrets(&self) -> bool {
+        self.secrets
+            .iter()
+            .any(|secret| secret.starts_with("{SCRAM-"))
+    }
+}
+
+impl ScramSession {
+    pub fn new(algorithm: ScramAlgorithm, is_plus: bool, channel_binding: Option<Vec<u8>>) -> Self {
+        ScramSession {
+            algorithm,
+            is_plus,
+            channel_binding,
+            state: ScramState::ClientFirst,
+        }
+    }
+
+    pub fn algorithm(&self) -> ScramAlgorithm {
+        self.algorithm
+    }
+
+    pub fn is_client_first(&self) -> bool {
+        matches!(self.state, ScramState::ClientFirst)
+    }
+
+    fn parse_client_first(&self, message: &str) -> Option<(String, String, String, String)> {
+        // Parse GS2 header
+        let (cbind_flag, message) = message.split_once(',')?;
+        let (authzid, client_first_bare) = message.split_once(',')?;
+        match cbind_flag {
+            "n" if !self.is_plus => (),
+            // The client supports channel binding but believes the server does not
+            "y" if !self.is_plus && self.channel_binding.is_none() => (),
+            "p=tls-server-end-point" if self.is_plus && self.channel_binding.is_some() => (),
+            _ => return None,
+        }
+        let gs2_header = format!("{cbind_flag},{authzid},");
+
+        // Parse client-first-message-bare
+        let mut username = None;
+        let mut nonce = None;
+        for (pos, attribute) in client_first_bare.split(',').enumerate() {
+            match (pos, attribute.split_once('=')?) {
+                (0, ("n", value)) => {
+                    username = decode_sasl_name(value);
+                }
+                (1, ("r", value)) if !value.is_empty() => {
+                    nonce = Some(value.to_string());
+                }
+                (0 | 1, _) => return None,
+                _ => (),
+            }
+        }
+        let username = username.filter(|username| !username.is_empty())?;
+
+        // Authorization identities other than the authenticated user are not supported
+        if let Some(authzid) = authzid.strip_prefix("a=") {
+            if decode_sasl_name(authzid)? != username {
+                return None;
+            }
+        } else if !authzid.is_empty() {
+            return None;
+        }
+
+        Some((gs2_header, client_first_bare.to_string(), username, nonce?))
+    }
+}
+
+impl Directory {
+    pub async fn scram_challenge(
+        &self,
+        session: &mut ScramSession,
+        client_first: &[u8],
+        return_member_of: bool,
+    ) -> crate::Result<Option<Vec<u8>>> {
+        let (gs2_header, client_first_bare, username, client_nonce) =
+            match std::str::from_utf8(client_first)
+                .ok()
+                .filter(|_| session.is_client_first())
+                .and_then(|message| session.parse_client_first(message))
+            {
+                Some(result) => result,
+                None => {
+                    session.state = ScramState::Done;
+                    return Ok(None);
+                }
+            };
+
+        // Obtain the SCRAM secret, a dummy one is used for unknown accounts
+        // in order to avoid disclosing which accounts exist.
+        let (principal, secret) = match self
+            .query(QueryBy::Name(&username), return_member_of)
+            .await?
+            .and_then(|principal| {
+                principal
+                    .scram_secret(session.algorithm)
+                    .map(|secret| (principal, secret))
+            }) {
+            Some((principal, secret)) => (Some(principal), secret),
+            None => {
+                tracing::debug!(
+                    context = "directory",
+                    event = "scram",
+                    account = username,
+                    algorithm = session.algorithm.as_str(),
+                    "Account not found or no SCRAM secret available"
+                );
+                (
+                    None,
+                    ScramSecret::dummy(session.algorithm, &self.scram_dummy_key, &username),
+                )
+            }
+        };
+
+        let mut server_nonce = vec![0u8; NONCE_LEN];
+        rand::thread_rng().fill_bytes(&mut server_nonce);
+        let nonce = format!("{client_nonce}{}", encode(&server_nonce));
+        let server_first = format!(
+            "r={nonce},s={},i={}",
+            encode(&secret.salt),
+            secret.iterations
+        );
+        let response = server_first.as_bytes().to_vec();
+
+        session.state = ScramState::ClientFinal(Box::new(ServerFirst {
+            username,
+            gs2_header,
+            client_first_bare,
+            server_first,
+            nonce,
+            principal,
+            secret,
+        }));
+
+        Ok(Some(response))
+    }
+
+    pub async fn scram_authenticate(
+        &self,
+        session: &mut ScramSession,
+        client_final: &[u8],
+        remote_ip: IpAddr,
+    ) -> crate::Result<AuthResult<(Principal<u32>, Vec<u8>)>> {
+        let server_first = match std::mem::replace(&mut session.state, ScramState::Done) {
+            ScramState::ClientFinal(server_first) => server_first,
+            _ => return Ok(AuthResult::Failure),
+        };
+
+        let server_final = std::str::from_utf8(client_final)
+            .ok()
+            .and_then(|client_final| session.verify_client_final(&server_first, client_final));
+        match (server_first.principal, server_final) {
+            (Some(principal), Some(server_final))
+                if !self.is_second_factor_required(&principal).await? =>
+            {
+                Ok(AuthResult::Success((principal, server_final)))
+            }
+            _ => self.auth_failure(&server_first.username, remote_ip).await,
+        }
+    }
+}
+
+impl ScramSession {
+    fn verify_client_final(&self, server_first: &ServerFirst, message: &str) -> Option<Vec<u8>> {
+        let (client_final_without_proof, proof) = message.rsplit_once(",p=")?;
+        let proof = base64_decode(proof.as_bytes())?;
+        let algorithm = self.algorithm;
+        let secret = &server_first.secret;
+
+        // Validate channel binding and nonce
+        let mut channel_binding = None;
+        let mut nonce = None;
+        for (pos, attribute) in client_final_without_proof.split(',').enumerate() {
+            match (pos, attribute.split_once('=')?) {
+                (0, ("c", value)) => {
+                    channel_binding = base64_decode(value.as_bytes());
+                }
+                (1, ("r", value)) => {
+                    nonce = Some(value);
+                }
+                (0 | 1, _) => return None,
+                _ => (),
+            }
+        }
+        let mut expected_binding = server_first.gs2_header.as_bytes().to_vec();
+        if server_first.gs2_header.starts_with("p=") {
+            expected_binding.extend_from_slice(self.channel_binding.as_deref()?);
+        }
+        // Evaluate every check so the response time does not reveal which one failed
+        let is_valid_binding = channel_binding
+            .is_some_and(|channel_binding| constant_time_eq(&channel_binding, &expected_binding));
+        let is_valid_nonce = nonce
+            .is_some_and(|nonce| constant_time_eq(nonce.as_bytes(), server_first.nonce.as_bytes()));
+        if !(is_valid_binding & is_valid_nonce & (proof.len() == algorithm.hash_len())) {
+            return None;
+        }
+
+        // Verify client proof
+        let auth_message = format!(
+            "{},{},{}",
+            server_first.client_first_bare, server_first.server_first, client_final_without_proof
+        );
+        let client_signature = algorithm.hmac(&secret.stored_key, auth_message.as_bytes());
+        let client_key = proof
+            .iter()
+            .zip(client_signature)
+            .map(|(a, b)| a ^ b)
+            .collect::<Vec<_>>();
+        if constant_time_eq(&algorithm.hash(&client_key), &secret.stored_key) {
+            let server_signature = algorithm.hmac(&secret.server_key, auth_message.as_bytes());
+            Some(format!("v={}", encode(&server_signature)).into_bytes())
+        } else {
+            None
+        }
+    }
+}
+
+fn decode_sasl_name(value: &str) -> Option<String> {
+    let mut result = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(ch) = chars.next() {
+        if ch == '=' {
+            match (chars.next(), chars.next()) {
+                (Some('2'), Some('C')) => result.push(','),
+                (Some('3'), Some('D')) => result.push('='),
+                _ => return None,
+            }
+        } else {
+            result.push(ch);
+        }
+    }
+    Some(result)
+}
+
+fn encode(bytes: &[u8]) -> String {
+    String::from_utf8(base64_encode(bytes).unwrap_or_default()).unwrap_or_default()
+}
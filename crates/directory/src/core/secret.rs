@@ -36,6 +36,8 @@ use tokio::sync::oneshot;
 
 use crate::Principal;
 
+use super::scram::{ScramAlgorithm, ScramSecret};
+
 impl<T: serde::Serialize + serde::de::DeserializeOwned> Principal<T> {
     pub async fn verify_secret(&self, secret: &str) -> bool {
         for hashed_secret in &self.secrets {
@@ -109,7 +111,7 @@ async fn verify_hash_prefix(hashed_secret: &str, secret: &str) -> bool {
     }
 }
 
-async fn verify_secret_hash(hashed_secret: &str, secret: &str) -> bool {
+pub(crate) async fn verify_secret_hash(hashed_secret: &str, secret: &str) -> bool {
     if hashed_secret.starts_with('$') {
         verify_hash_prefix(hashed_secret, secret).await
     } else if hashed_secret.starts_with('_') {
@@ -189,6 +191,9 @@ async fn verify_secret_hash(hashed_secret: &str, secret: &str) -> bool {
                         unix_crypt::verify(secret, hashed_secret)
                     }
                 }
+                "SCRAM-SHA-1" | "SCRAM-SHA-256" => ScramAlgorithm::parse(algo)
+                    .and_then(|algorithm| ScramSecret::parse(algorithm, hashed_secret))
+                    .is_some_and(|scram_secret| scram_secret.verify(secret)),
                 "PLAIN" | "plain" | "CLEAR" | "clear" => hashed_secret == secret,
                 _ => {
                     tracing::warn!(
@@ -0,0 +1,351 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use hmac::{Hmac, Mac};
+use pwhash::sha512_crypt;
+use sha1::Sha1;
+use store::{
+    rand::{distributions::Alphanumeric, thread_rng, Rng, RngCore},
+    write::{
+        assert::{AssertValue, HashedValue},
+        key::KeySerializer,
+        now, BatchBuilder, DirectoryClass, ValueClass,
+    },
+    Deserialize, Serialize, Store, ValueKey,
+};
+use utils::{
+    codec::{base32_custom::Base32Writer, leb128::Leb128Iterator},
+    constant_time_eq,
+};
+
+use crate::backend::internal::deserialize_string;
+
+use super::secret::verify_secret_hash;
+
+const TOTP_STEP: u64 = 30;
+const TOTP_DIGITS: u32 = 6;
+const TOTP_SECRET_LEN: usize = 20;
+const RECOVERY_CODES: usize = 8;
+const RECOVERY_CODE_LEN: usize = 10;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TotpSecret {
+    pub secret: Vec<u8>,
+    pub enabled: bool,
+    pub last_step: u64,
+    pub recovery_codes: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct TotpEnrollment {
+    pub secret: String,
+    pub uri: String,
+    #[serde(rename = "recoveryCodes")]
+    pub recovery_codes: Vec<String>,
+}
+
+#[allow(async_fn_in_trait)]
+pub trait TotpStore: Sized {
+    async fn enroll_totp(
+        &self,
+        account_id: u32,
+        account_name: &str,
+    ) -> crate::Result<Option<TotpEnrollment>>;
+    async fn confirm_totp(&self, account_id: u32, code: &str) -> crate::Result<bool>;
+    async fn disable_totp(&self, account_id: u32) -> crate::Result<bool>;
+    async fn get_totp(&self, account_id: u32) -> crate::Result<Option<TotpSecret>>;
+    async fn is_totp_enabled(&self, account_id: u32) -> crate::Result<bool>;
+    async fn verify_totp(&self, account_id: u32, code: &str) -> crate::Result<bool>;
+}
+
+impl TotpStore for Store {
+    async fn enroll_totp(
+        &self,
+        account_id: u32,
+        account_name: &str,
+    ) -> crate::Result<Option<TotpEnrollment>> {
+        // An enabled secret has to be removed before enrolling a new one
+        let current = match self.get_totp_hashed(account_id).await? {
+            Some(current) if current.inner.enabled => return Ok(None),
+            Some(current) => AssertValue::Hash(current.hash),
+            None => AssertValue::None,
+        };
+
+        let mut secret = vec![0u8; TOTP_SECRET_LEN];
+        thread_rng().fill_bytes(&mut secret);
+        let recovery_codes = (0..RECOVERY_CODES)
+            .map(|_| {
+                thread_rng()
+                    .sample_iter(Alphanumeric)
+                    .take(RECOVERY_CODE_LEN)
+                    .map(char::from)
+                    .collect::<String>()
+            })
+            .collect::<Vec<_>>();
+        let encoded_secret = Base32Writer::rfc4648_from_bytes(&secret).finalize();
+        let enrollment = TotpEnrollment {
+            uri: format!(
+                "otpauth://totp/Stalwart:{}?secret={}&issuer=Stalwart&algorithm=SHA1&digits={}&period={}",
+                form_urlencoded::byte_serialize(account_name.as_bytes()).collect::<String>(),
+                encoded_secret,
+                TOTP_DIGITS,
+                TOTP_STEP
+            ),
+            secret: encoded_secret,
+            recovery_codes,
+        };
+
+        // The secret remains disabled until a valid code is provided
+        let mut batch = BatchBuilder::new();
+        batch
+            .assert_value(DirectoryClass::Totp(account_id), current)
+            .set(
+                DirectoryClass::Totp(account_id),
+                TotpSecret {
+                    secret,
+                    enabled: false,
+                    last_step: 0,
+                    recovery_codes: enrollment
+                        .recovery_codes
+                        .iter()
+                        .map(|code| sha512_crypt::hash(code).unwrap())
+                        .collect(),
+                }
+                .serialize(),
+            );
+        match self.write(batch.build()).await {
+            Ok(_) => Ok(Some(enrollment)),
+            Err(store::Error::AssertValueFailed) => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    async fn confirm_totp(&self, account_id: u32, code: &str) -> crate::Result<bool> {
+        if let Some(HashedValue {
+            hash,
+            inner: mut totp,
+        }) = self.get_totp_hashed(account_id).await?
+        {
+            if !totp.enabled && totp.verify_code(code, now()) {
+                totp.enabled = true;
+                return self.update_totp(account_id, hash, totp).await;
+            }
+        }
+
+        Ok(false)
+    }
+
+    async fn disable_totp(&self, account_id: u32) -> crate::Result<bool> {
+        if self.get_totp(account_id).await?.is_some() {
+            let mut batch = BatchBuilder::new();
+            batch.clear(DirectoryClass::Totp(account_id));
+            self.write(batch.build()).await?;
+            Ok(true)
+        } else {
+            Ok(false)
+        }
+    }
+
+    async fn get_totp(&self, account_id: u32) -> crate::Result<Option<TotpSecret>> {
+        self.get_value::<TotpSecret>(ValueKey::from(ValueClass::Directory(DirectoryClass::Totp(
+            account_id,
+        ))))
+        .await
+        .map_err(Into::into)
+    }
+
+    async fn is_totp_enabled(&self, account_id: u32) -> crate::Result<bool> {
+        self.get_totp(account_id)
+            .await
+            .map(|totp| totp.is_some_and(|totp| totp.enabled))
+    }
+
+    async fn verify_totp(&self, account_id: u32, code: &str) -> crate::Result<bool> {
+        let (hash, mut totp) = match self.get_totp_hashed(account_id).await? {
+            Some(HashedValue { hash, inner }) if inner.enabled => (hash, inner),
+            _ => return Ok(false),
+        };
+
+        let code = code.trim();
+        if !totp.verify_code(code, now()) {
+            // Try the recovery codes, each of them can only be used once
+            let mut used_code = None;
+            for (pos, recovery_code) in totp.recovery_codes.iter().enumerate() {
+                if verify_secret_hash(recovery_code, code).await {
+                    used_code = pos.into();
+                    break;
+                }
+            }
+            if let Some(pos) = used_code {
+                totp.recovery_codes.remove(pos);
+            } else {
+                return Ok(false);
+            }
+        }
+
+        // Concurrent logins with the same code or recovery code are rejected
+        self.update_totp(account_id, hash, totp).await
+    }
+}
+
+#[allow(async_fn_in_trait)]
+trait TotpStoreInner {
+    async fn get_totp_hashed(
+        &self,
+        account_id: u32,
+    ) -> crate::Result<Option<HashedValue<TotpSecret>>>;
+    async fn update_totp(
+        &self,
+        account_id: u32,
+        hash: u64,
+        totp: TotpSecret,
+    ) -> crate::Result<bool>;
+}
+
+impl TotpStoreInner for Store {
+    async fn get_totp_hashed(
+        &self,
+        account_id: u32,
+    ) -> crate::Result<Option<HashedValue<TotpSecret>>> {
+        self.get_value::<HashedValue<TotpSecret>>(ValueKey::from(ValueClass::Directory(
+            DirectoryClass::Totp(account_id),
+        )))
+        .await
+        .map_err(Into::into)
+    }
+
+    async fn update_totp(
+        &self,
+        account_id: u32,
+        hash: u64,
+        totp: TotpSecret,
+    ) -> crate::Result<bool> {
+        let mut batch = BatchBuilder::new();
+        batch
+            .assert_value(DirectoryClass::Totp(account_id), AssertValue::Hash(hash))
+            .set(DirectoryClass::Totp(account_id), totp.serialize());
+        match self.write(batch.build()).await {
+            Ok(_) => Ok(true),
+            Err(store::Error::AssertValueFailed) => Ok(false),
+            Err(err) => Err(err.into()),
+        }
+    }
+}
+
+impl TotpSecret {
+    // Accepts codes from the previous and next time steps to allow for clock drift,
+    // codes from steps that were already used are rejected to prevent replays.
+    fn verify_code(&mut self, code: &str, timestamp: u64) -> bool {
+        if code.len() != TOTP_DIGITS as usize || !code.chars().all(|ch| ch.is_ascii_digit()) {
+            return false;
+        }
+
+        let current_step = timestamp / TOTP_STEP;
+        for step in current_step.saturating_sub(1)..=current_step + 1 {
+            if step > self.last_step
+                && constant_time_eq(
+                    totp_code(&self.secret, step * TOTP_STEP).as_bytes(),
+                    code.as_bytes(),
+                )
+            {
+                self.last_step = step;
+                return true;
+            }
+        }
+
+        false
+    }
+}
+
+pub fn totp_code(secret: &[u8], timestamp: u64) -> String {
+    let mut mac = Hmac::<Sha1>::new_from_slice(secret).unwrap();
+    mac.update(&(timestamp / TOTP_STEP).to_be_bytes());
+    let hash = mac.finalize().into_bytes();
+    let offset = (hash[hash.len() - 1] & 0x0f) as usize;
+    let code = u32::from_be_bytes([
+        hash[offset] & 0x7f,
+        hash[offset + 1],
+        hash[offset + 2],
+        hash[offset + 3],
+    ]) % 10u32.pow(TOTP_DIGITS);
+
+    format!("{:0width$}", code, width = TOTP_DIGITS as usize)
+}
+
+impl Serialize for TotpSecret {
+    fn serialize(self) -> Vec<u8> {
+        let mut serializer = KeySerializer::new(
+            self.secret.len()
+                + self
+                    .recovery_codes
+                    .iter()
+                    .map(|s| s.len() + 1)
+                    .sum::<usize>()
+                + 16,
+        )
+        .write(1u8)
+        .write(self.enabled as u8)
+        .write_leb128(self.last_step)
+        .write_leb128(self.secret.len())
+        .write(self.secret.as_slice())
+        .write_leb128(self.recovery_codes.len());
+        for code in &self.recovery_codes {
+            serializer = serializer.write_leb128(code.len()).write(code.as_bytes());
+        }
+
+        serializer.finalize()
+    }
+}
+
+impl Deserialize for TotpSecret {
+    fn deserialize(bytes: &[u8]) -> store::Result<Self> {
+        deserialize(bytes)
+            .ok_or_else(|| store::Error::InternalError("Failed to deserialize TOTP secret".into()))
+    }
+}
+
+fn deserialize(bytes: &[u8]) -> Option<TotpSecret> {
+    let mut bytes = bytes.iter();
+    if bytes.next()? != &1 {
+        return None;
+    }
+    let enabled = *bytes.next()? == 1;
+    let last_step = bytes.next_leb128()?;
+    let secret_len = bytes.next_leb128::<usize>()?;
+    let mut secret = Vec::with_capacity(secret_len);
+    for _ in 0..secret_len {
+        secret.push(*bytes.next()?);
+    }
+    let num_codes = bytes.next_leb128::<usize>()?;
+    let mut recovery_codes = Vec::with_capacity(num_codes);
+    for _ in 0..num_codes {
+        recovery_codes.push(deserialize_string(&mut bytes)?);
+    }
+
+    Some(TotpSecret {
+        secret,
+        enabled,
+        last_step,
+        recovery_codes,
+    })
+}
@@ -21,7 +21,7 @@
  * for more details.
 */
 
-use core::cache::CachedDirectory;
+use core::{cache::CachedDirectory, oidc::OidcDirectory};
 use std::{borrow::Cow, fmt::Debug, sync::Arc};
 
 use ahash::AHashMap;
@@ -47,7 +47,9 @@ pub struct Directory {
     pub catch_all: AddressMapping,
     pub subaddressing: AddressMapping,
     pub cache: Option<CachedDirectory>,
+    pub oidc: Option<OidcDirectory>,
     pub blocked_ips: Arc<BlockedIps>,
+    pub scram_dummy_key: Vec<u8>,
 }
 
 #[derive(Debug, Default, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
@@ -95,6 +97,7 @@ pub enum DirectoryError {
     Store(store::Error),
     Imap(ImapError),
     Smtp(mail_send::Error),
+    Http(reqwest::Error),
     Pool(String),
     Management(ManagementError),
     TimedOut,
@@ -24,7 +24,7 @@
 use std::borrow::Cow;
 
 use jmap_proto::error::{method::MethodError, set::SetErrorType};
-use protocol::capability::Capability;
+use protocol::{capability::Capability, metadata::MetadataCode, notify::Event};
 
 pub mod parser;
 pub mod protocol;
@@ -89,6 +89,21 @@ pub enum Command {
 
     // RFC 2971
     Id,
+
+    // RFC 9208
+    GetQuota,
+    GetQuotaRoot,
+    SetQuota,
+
+    // RFC 5464
+    GetMetadata,
+    SetMetadata,
+
+    // RFC 4978
+    Compress,
+
+    // RFC 5465
+    Notify,
 }
 
 impl Command {
@@ -167,6 +182,15 @@ pub enum ResponseCode {
 
     // USEATTR
     UseAttr,
+    // METADATA
+    Metadata(MetadataCode),
+
+    // COMPRESS
+    CompressionActive,
+
+    // NOTIFY
+    BadEvent(Vec<Event>),
+    NotificationOverflow,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -57,6 +57,10 @@ impl Mechanism {
             Ok(Self::ScramSha1)
         } else if value.eq_ignore_ascii_case(b"SCRAM-SHA-256") {
             Ok(Self::ScramSha256)
+        } else if value.eq_ignore_ascii_case(b"SCRAM-SHA-1-PLUS") {
+            Ok(Self::ScramSha1Plus)
+        } else if value.eq_ignore_ascii_case(b"SCRAM-SHA-256-PLUS") {
+            Ok(Self::ScramSha256Plus)
         } else if value.eq_ignore_ascii_case(b"APOP") {
             Ok(Self::Apop)
         } else if value.eq_ignore_ascii_case(b"NTLM") {
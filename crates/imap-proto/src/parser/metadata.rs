@@ -0,0 +1,360 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::borrow::Cow;
+
+use crate::{
+    protocol::{
+        metadata::{self, Depth},
+        ProtocolVersion,
+    },
+    receiver::{Request, Token},
+    utf7::utf7_maybe_decode,
+    Command,
+};
+
+use super::parse_number;
+
+/*
+
+   getmetadata     = "GETMETADATA" [SP getmetadata-options]
+                     SP mailbox SP entries
+
+   getmetadata-options = "(" getmetadata-option
+                         *(SP getmetadata-option) ")"
+
+   getmetadata-option = "MAXSIZE" SP number / "DEPTH" SP ("0" / "1" / "infinity")
+
+   entries         = entry / "(" entry *(SP entry) ")"
+
+   setmetadata     = "SETMETADATA" SP mailbox SP entry-values
+
+   entry-values    = "(" entry-value *(SP entry-value) ")"
+
+   entry-value     = entry SP value
+
+   value           = nstring / literal8
+
+*/
+
+impl Request<Command> {
+    pub fn parse_get_metadata(
+        self,
+        version: ProtocolVersion,
+    ) -> crate::Result<metadata::GetArguments> {
+        let mut tokens = self.tokens.into_iter().peekable();
+        let mut max_size = None;
+        let mut depth = Depth::Zero;
+
+        // Parse options
+        if matches!(tokens.peek(), Some(Token::ParenthesisOpen)) {
+            tokens.next();
+            loop {
+                match tokens.next() {
+                    Some(Token::ParenthesisClose) => break,
+                    Some(Token::Argument(option)) => {
+                        let value = tokens
+                            .next()
+                            .ok_or((self.tag.as_str(), "Missing option value."))?
+                            .unwrap_bytes();
+                        if option.eq_ignore_ascii_case(b"MAXSIZE") {
+                            max_size = parse_number::<usize>(&value)
+                                .map_err(|v| (self.tag.as_str(), v))?
+                                .into();
+                        } else if option.eq_ignore_ascii_case(b"DEPTH") {
+                            depth = Depth::parse(&value).map_err(|v| (self.tag.as_str(), v))?;
+                        } else {
+                            return Err((
+                                self.tag.as_str(),
+                                Cow::from(format!(
+                                    "Invalid GETMETADATA option {:?}.",
+                                    String::from_utf8_lossy(&option)
+                                )),
+                            )
+                                .into());
+                        }
+                    }
+                    _ => {
+                        return Err((self.tag.as_str(), "Invalid GETMETADATA options.").into());
+                    }
+                }
+            }
+        }
+
+        let mailbox_name = utf7_maybe_decode(
+            tokens
+                .next()
+                .ok_or((self.tag.as_str(), "Missing mailbox name."))?
+                .unwrap_string()
+                .map_err(|v| (self.tag.as_str(), v))?,
+            version,
+        );
+
+        // Parse entries
+        let mut entries = Vec::new();
+        match tokens.next() {
+            Some(Token::ParenthesisOpen) => loop {
+                match tokens.next() {
+                    Some(Token::ParenthesisClose) => break,
+                    Some(token @ Token::Argument(_)) => {
+                        entries.push(parse_entry(token).map_err(|v| (self.tag.as_str(), v))?);
+                    }
+                    _ => {
+                        return Err((self.tag.as_str(), "Invalid entry list.").into());
+                    }
+                }
+            },
+            Some(token @ Token::Argument(_)) => {
+                entries.push(parse_entry(token).map_err(|v| (self.tag.as_str(), v))?);
+            }
+            _ => (),
+        }
+
+        if !entries.is_empty() {
+            Ok(metadata::GetArguments {
+                tag: self.tag,
+                mailbox_name,
+                entries,
+                max_size,
+                depth,
+            })
+        } else {
+            Err((self.tag.as_str(), "At least one entry is required.").into())
+        }
+    }
+
+    pub fn parse_set_metadata(
+        self,
+        version: ProtocolVersion,
+    ) -> crate::Result<metadata::SetArguments> {
+        let mut tokens = self.tokens.into_iter();
+        let mailbox_name = utf7_maybe_decode(
+            tokens
+                .next()
+                .ok_or((self.tag.as_str(), "Missing mailbox name."))?
+                .unwrap_string()
+                .map_err(|v| (self.tag.as_str(), v))?,
+            version,
+        );
+
+        if !matches!(tokens.next(), Some(Token::ParenthesisOpen)) {
+            return Err((
+                self.tag.as_str(),
+                "Expected parenthesis after mailbox name.",
+            )
+                .into());
+        }
+
+        let mut entries = Vec::new();
+        loop {
+            match tokens.next() {
+                Some(Token::ParenthesisClose) => break,
+                Some(token @ Token::Argument(_)) => {
+                    let name = parse_entry(token).map_err(|v| (self.tag.as_str(), v))?;
+                    let value = match tokens.next() {
+                        Some(Token::Argument(value)) if value.eq_ignore_ascii_case(b"NIL") => None,
+                        Some(Token::Argument(value)) => Some(value),
+                        Some(Token::Nil) => Some(Vec::new()),
+                        _ => {
+                            return Err((self.tag.as_str(), "Missing entry value.").into());
+                        }
+                    };
+                    entries.push((name, value));
+                }
+                _ => {
+                    return Err((self.tag.as_str(), "Invalid entry list.").into());
+                }
+            }
+        }
+
+        if !entries.is_empty() {
+            Ok(metadata::SetArguments {
+                tag: self.tag,
+                mailbox_name,
+                entries,
+            })
+        } else {
+            Err((self.tag.as_str(), "At least one entry is required.").into())
+        }
+    }
+}
+
+fn parse_entry(token: Token) -> super::Result<String> {
+    let entry = token.unwrap_string()?.to_ascii_lowercase();
+    let name = entry
+        .strip_prefix("/private/")
+        .or_else(|| entry.strip_prefix("/shared/"))
+        .ok_or_else(|| {
+            Cow::from(format!(
+                "Entry {entry:?} must start with \"/private/\" or \"/shared/\"."
+            ))
+        })?;
+
+    if !name.is_empty()
+        && !name.ends_with('/')
+        && !name.contains("//")
+        && name
+            .chars()
+            .all(|ch| ch.is_ascii_graphic() && !['*', '%'].contains(&ch))
+    {
+        Ok(entry)
+    } else {
+        Err(format!("Invalid entry name {entry:?}.").into())
+    }
+}
+
+impl Depth {
+    pub fn parse(value: &[u8]) -> super::Result<Self> {
+        if value == b"0" {
+            Ok(Depth::Zero)
+        } else if value == b"1" {
+            Ok(Depth::One)
+        } else if value.eq_ignore_ascii_case(b"infinity") {
+            Ok(Depth::Infinity)
+        } else {
+            Err(format!("Invalid depth {:?}.", String::from_utf8_lossy(value)).into())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        protocol::{
+            metadata::{self, Depth},
+            ProtocolVersion,
+        },
+        receiver::Receiver,
+    };
+
+    #[test]
+    fn parse_get_metadata() {
+        let mut receiver = Receiver::new();
+
+        for (command, arguments) in [
+            (
+                "a GETMETADATA \"\" /shared/comment\r\n",
+                metadata::GetArguments {
+                    tag: "a".to_string(),
+                    mailbox_name: "".to_string(),
+                    entries: vec!["/shared/comment".to_string()],
+                    max_size: None,
+                    depth: Depth::Zero,
+                },
+            ),
+            (
+                "a GETMETADATA INBOX (/Shared/Comment /private/Comment)\r\n",
+                metadata::GetArguments {
+                    tag: "a".to_string(),
+                    mailbox_name: "INBOX".to_string(),
+                    entries: vec![
+                        "/shared/comment".to_string(),
+                        "/private/comment".to_string(),
+                    ],
+                    max_size: None,
+                    depth: Depth::Zero,
+                },
+            ),
+            (
+                "a GETMETADATA (MAXSIZE 1024 DEPTH infinity) \"INBOX\" \"/private/filters/values\"\r\n",
+                metadata::GetArguments {
+                    tag: "a".to_string(),
+                    mailbox_name: "INBOX".to_string(),
+                    entries: vec!["/private/filters/values".to_string()],
+                    max_size: Some(1024),
+                    depth: Depth::Infinity,
+                },
+            ),
+        ] {
+            assert_eq!(
+                receiver
+                    .parse(&mut command.as_bytes().iter())
+                    .unwrap()
+                    .parse_get_metadata(ProtocolVersion::Rev2)
+                    .unwrap(),
+                arguments,
+                "{:?}",
+                command
+            );
+        }
+
+        for command in [
+            "a GETMETADATA INBOX /comment\r\n",
+            "a GETMETADATA INBOX /private/\r\n",
+            "a GETMETADATA INBOX /shared/a//b\r\n",
+            "a GETMETADATA INBOX /shared/a*\r\n",
+            "a GETMETADATA (DEPTH 2) INBOX /shared/comment\r\n",
+        ] {
+            assert!(
+                receiver
+                    .parse(&mut command.as_bytes().iter())
+                    .unwrap()
+                    .parse_get_metadata(ProtocolVersion::Rev2)
+                    .is_err(),
+                "{:?}",
+                command
+            );
+        }
+    }
+
+    #[test]
+    fn parse_set_metadata() {
+        let mut receiver = Receiver::new();
+
+        for (command, arguments) in [
+            (
+                "a SETMETADATA INBOX (/private/comment \"My new comment\")\r\n",
+                metadata::SetArguments {
+                    tag: "a".to_string(),
+                    mailbox_name: "INBOX".to_string(),
+                    entries: vec![(
+                        "/private/comment".to_string(),
+                        b"My new comment".to_vec().into(),
+                    )],
+                },
+            ),
+            (
+                "a SETMETADATA INBOX (/private/comment NIL /shared/comment {3+}\r\nabc)\r\n",
+                metadata::SetArguments {
+                    tag: "a".to_string(),
+                    mailbox_name: "INBOX".to_string(),
+                    entries: vec![
+                        ("/private/comment".to_string(), None),
+                        ("/shared/comment".to_string(), b"abc".to_vec().into()),
+                    ],
+                },
+            ),
+        ] {
+            assert_eq!(
+                receiver
+                    .parse(&mut command.as_bytes().iter())
+                    .unwrap()
+                    .parse_set_metadata(ProtocolVersion::Rev2)
+                    .unwrap(),
+                arguments,
+                "{:?}",
+                command
+            );
+        }
+    }
+}
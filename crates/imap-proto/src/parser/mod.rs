@@ -24,6 +24,7 @@
 pub mod acl;
 pub mod append;
 pub mod authenticate;
+pub mod compress;
 pub mod copy_move;
 pub mod create;
 pub mod delete;
@@ -32,6 +33,9 @@ pub mod fetch;
 pub mod list;
 pub mod login;
 pub mod lsub;
+pub mod metadata;
+pub mod notify;
+pub mod quota;
 pub mod rename;
 pub mod search;
 pub mod select;
@@ -94,6 +98,13 @@ impl CommandParser for Command {
             b"MYRIGHTS" => Some(Command::MyRights),
             b"UNAUTHENTICATE" => Some(Command::Unauthenticate),
             b"ID" => Some(Command::Id),
+            b"GETQUOTA" => Some(Command::GetQuota),
+            b"GETQUOTAROOT" => Some(Command::GetQuotaRoot),
+            b"SETQUOTA" => Some(Command::SetQuota),
+            b"GETMETADATA" => Some(Command::GetMetadata),
+            b"SETMETADATA" => Some(Command::SetMetadata),
+            b"COMPRESS" => Some(Command::Compress),
+            b"NOTIFY" => Some(Command::Notify),
             _ => None,
         }
     }
@@ -0,0 +1,424 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::{iter::Peekable, vec::IntoIter};
+
+use crate::{
+    protocol::{
+        fetch::Attribute,
+        notify::{self, Event, EventGroup, Filter},
+        ProtocolVersion,
+    },
+    receiver::{Request, Token},
+    utf7::utf7_maybe_decode,
+    Command,
+};
+
+/*
+
+   notify          = "NOTIFY" SP
+                     (notify-set / notify-none)
+
+   notify-none     = "NONE"
+
+   notify-set      = "SET" [status-indicator] SP event-groups
+
+   status-indicator = SP "STATUS"
+
+   event-groups    = event-group *(SP event-group)
+
+   event-group     = "(" filter-mailboxes SP events ")"
+
+   filter-mailboxes = filter-mailboxes-selected /
+                      filter-mailboxes-other
+
+   filter-mailboxes-other = "inboxes" / "personal" / "subscribed" /
+                            ( "subtree" SP one-or-more-mailbox ) /
+                            ( "mailboxes" SP one-or-more-mailbox )
+
+   filter-mailboxes-selected = "selected" / "selected-delayed"
+
+   one-or-more-mailbox = mailbox / many-mailboxes
+
+   many-mailboxes  = "(" mailbox *(SP mailbox) ")"
+
+   events          = ( "(" event *(SP event) ")" ) / "NONE"
+
+   event           = message-event / mailbox-event / user-event
+
+   message-event   = ( "MessageNew" [SP
+                           "(" fetch-att *(SP fetch-att) ")" ] ) /
+                     "MessageExpunge" /
+                     "FlagChange" / "AnnotationChange"
+
+   mailbox-event   = "MailboxName" /
+                     "SubscriptionChange" / "MailboxMetadataChange" /
+                     "ServerMetadataChange"
+
+*/
+
+impl Request<Command> {
+    pub fn parse_notify(self, version: ProtocolVersion) -> crate::Result<notify::Arguments> {
+        let mut tokens = self.tokens.into_iter().peekable();
+        let command = tokens
+            .next()
+            .ok_or((self.tag.as_str(), "Missing arguments."))?
+            .unwrap_bytes();
+
+        if command.eq_ignore_ascii_case(b"NONE") {
+            return if tokens.next().is_none() {
+                Ok(notify::Arguments {
+                    tag: self.tag,
+                    status: false,
+                    groups: vec![],
+                })
+            } else {
+                Err((self.tag.as_str(), "Too many arguments.").into())
+            };
+        } else if !command.eq_ignore_ascii_case(b"SET") {
+            return Err((self.tag.as_str(), "Expected SET or NONE.").into());
+        }
+
+        let status = if matches!(tokens.peek(), Some(token) if token.eq_ignore_ascii_case(b"STATUS"))
+        {
+            tokens.next();
+            true
+        } else {
+            false
+        };
+
+        let mut groups = Vec::new();
+        while let Some(token) = tokens.next() {
+            if !token.is_parenthesis_open() {
+                return Err((self.tag.as_str(), "Expected event group.").into());
+            }
+            let group = EventGroup::parse(&mut tokens, version)
+                .map_err(|message| (self.tag.as_str(), message))?;
+            if !matches!(tokens.next(), Some(token) if token.is_parenthesis_close()) {
+                return Err((self.tag.as_str(), "Expected ')' after event group.").into());
+            }
+            groups.push(group);
+        }
+
+        if !groups.is_empty() {
+            Ok(notify::Arguments {
+                tag: self.tag,
+                status,
+                groups,
+            })
+        } else {
+            Err((self.tag.as_str(), "Missing event groups.").into())
+        }
+    }
+}
+
+impl EventGroup {
+    fn parse(
+        tokens: &mut Peekable<IntoIter<Token>>,
+        version: ProtocolVersion,
+    ) -> super::Result<Self> {
+        let filter = tokens
+            .next()
+            .ok_or("Missing mailbox filter.")?
+            .unwrap_bytes();
+        let filter = if filter.eq_ignore_ascii_case(b"SELECTED") {
+            Filter::Selected
+        } else if filter.eq_ignore_ascii_case(b"SELECTED-DELAYED") {
+            Filter::SelectedDelayed
+        } else if filter.eq_ignore_ascii_case(b"INBOXES") {
+            Filter::Inboxes
+        } else if filter.eq_ignore_ascii_case(b"PERSONAL") {
+            Filter::Personal
+        } else if filter.eq_ignore_ascii_case(b"SUBSCRIBED") {
+            Filter::Subscribed
+        } else if filter.eq_ignore_ascii_case(b"SUBTREE") {
+            Filter::Subtree(parse_mailboxes(tokens, version)?)
+        } else if filter.eq_ignore_ascii_case(b"MAILBOXES") {
+            Filter::Mailboxes(parse_mailboxes(tokens, version)?)
+        } else {
+            return Err(format!(
+                "Invalid mailbox filter '{}'.",
+                String::from_utf8_lossy(&filter)
+            )
+            .into());
+        };
+
+        let mut events = Vec::new();
+        match tokens.next() {
+            Some(Token::ParenthesisOpen) => loop {
+                match tokens.next() {
+                    Some(Token::ParenthesisClose) if !events.is_empty() => break,
+                    Some(Token::Argument(event)) => {
+                        let mut event = Event::parse(&event)?;
+                        if let Event::MessageNew { attributes } = &mut event {
+                            if matches!(tokens.peek(), Some(token) if token.is_parenthesis_open()) {
+                                tokens.next();
+                                *attributes = parse_fetch_attributes(tokens)?;
+                            }
+                        }
+                        events.push(event);
+                    }
+                    _ => return Err("Invalid event list.".into()),
+                }
+            },
+            Some(Token::Argument(value)) if value.eq_ignore_ascii_case(b"NONE") => (),
+            _ => return Err("Expected event list or NONE.".into()),
+        }
+
+        // Validate events
+        let has_event = |event: &Event| events.iter().any(|e| e == event);
+        let has_message_new = events.iter().any(|e| matches!(e, Event::MessageNew { .. }));
+        if has_message_new != has_event(&Event::MessageExpunge) {
+            return Err("MessageNew and MessageExpunge must be specified together.".into());
+        } else if !has_message_new
+            && (has_event(&Event::FlagChange) || has_event(&Event::AnnotationChange))
+        {
+            return Err(
+                "FlagChange and AnnotationChange require MessageNew and MessageExpunge.".into(),
+            );
+        } else if filter.is_selected() {
+            if events.iter().any(|e| !e.is_message_event()) {
+                return Err("Only message events are allowed for the selected mailbox.".into());
+            }
+        } else if events
+            .iter()
+            .any(|e| matches!(e, Event::MessageNew { attributes } if !attributes.is_empty()))
+        {
+            return Err("Fetch attributes are only allowed for the selected mailbox.".into());
+        }
+
+        Ok(EventGroup { filter, events })
+    }
+}
+
+impl Event {
+    pub fn parse(value: &[u8]) -> super::Result<Self> {
+        if value.eq_ignore_ascii_case(b"MessageNew") {
+            Ok(Event::MessageNew {
+                attributes: Vec::new(),
+            })
+        } else if value.eq_ignore_ascii_case(b"MessageExpunge") {
+            Ok(Event::MessageExpunge)
+        } else if value.eq_ignore_ascii_case(b"FlagChange") {
+            Ok(Event::FlagChange)
+        } else if value.eq_ignore_ascii_case(b"AnnotationChange") {
+            Ok(Event::AnnotationChange)
+        } else if value.eq_ignore_ascii_case(b"MailboxName") {
+            Ok(Event::MailboxName)
+        } else if value.eq_ignore_ascii_case(b"SubscriptionChange") {
+            Ok(Event::SubscriptionChange)
+        } else if value.eq_ignore_ascii_case(b"MailboxMetadataChange") {
+            Ok(Event::MailboxMetadataChange)
+        } else if value.eq_ignore_ascii_case(b"ServerMetadataChange") {
+            Ok(Event::ServerMetadataChange)
+        } else {
+            Err(format!("Invalid event '{}'.", String::from_utf8_lossy(value)).into())
+        }
+    }
+}
+
+fn parse_mailboxes(
+    tokens: &mut Peekable<IntoIter<Token>>,
+    version: ProtocolVersion,
+) -> super::Result<Vec<String>> {
+    let mut mailboxes = Vec::new();
+    match tokens.next() {
+        Some(Token::ParenthesisOpen) => loop {
+            match tokens.next() {
+                Some(Token::ParenthesisClose) if !mailboxes.is_empty() => break,
+                Some(token) if !token.is_parenthesis_open() && !token.is_parenthesis_close() => {
+                    mailboxes.push(utf7_maybe_decode(token.unwrap_string()?, version));
+                }
+                _ => return Err("Invalid mailbox list.".into()),
+            }
+        },
+        Some(token) if !token.is_parenthesis_close() => {
+            mailboxes.push(utf7_maybe_decode(token.unwrap_string()?, version));
+        }
+        _ => return Err("Missing mailbox name.".into()),
+    }
+    Ok(mailboxes)
+}
+
+// Only fetch attributes that do not require section parsing are supported.
+fn parse_fetch_attributes(tokens: &mut Peekable<IntoIter<Token>>) -> super::Result<Vec<Attribute>> {
+    let mut attributes = Vec::new();
+    loop {
+        match tokens.next() {
+            Some(Token::ParenthesisClose) if !attributes.is_empty() => break,
+            Some(Token::Argument(value)) => {
+                let attribute = if value.eq_ignore_ascii_case(b"UID") {
+                    Attribute::Uid
+                } else if value.eq_ignore_ascii_case(b"FLAGS") {
+                    Attribute::Flags
+                } else if value.eq_ignore_ascii_case(b"ENVELOPE") {
+                    Attribute::Envelope
+                } else if value.eq_ignore_ascii_case(b"INTERNALDATE") {
+                    Attribute::InternalDate
+                } else if value.eq_ignore_ascii_case(b"RFC822.SIZE") {
+                    Attribute::Rfc822Size
+                } else if value.eq_ignore_ascii_case(b"BODYSTRUCTURE") {
+                    Attribute::BodyStructure
+                } else if value.eq_ignore_ascii_case(b"MODSEQ") {
+                    Attribute::ModSeq
+                } else if value.eq_ignore_ascii_case(b"EMAILID") {
+                    Attribute::EmailId
+                } else if value.eq_ignore_ascii_case(b"THREADID") {
+                    Attribute::ThreadId
+                } else {
+                    return Err(format!(
+                        "Unsupported fetch attribute '{}'.",
+                        String::from_utf8_lossy(&value)
+                    )
+                    .into());
+                };
+                if !attributes.contains(&attribute) {
+                    attributes.push(attribute);
+                }
+            }
+            _ => return Err("Invalid fetch attribute list.".into()),
+        }
+    }
+    Ok(attributes)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        protocol::{
+            fetch::Attribute,
+            notify::{self, Event, EventGroup, Filter},
+            ProtocolVersion,
+        },
+        receiver::Receiver,
+    };
+
+    #[test]
+    fn parse_notify() {
+        let mut receiver = Receiver::new();
+
+        for (command, arguments) in [
+            (
+                "A01 NOTIFY NONE\r\n",
+                notify::Arguments {
+                    tag: "A01".to_string(),
+                    status: false,
+                    groups: vec![],
+                },
+            ),
+            (
+                concat!(
+                    "A02 NOTIFY SET STATUS (selected (MessageNew (uid flags envelope) ",
+                    "MessageExpunge FlagChange)) (subtree (INBOX \"Lists/Work\") ",
+                    "(MessageNew MessageExpunge MailboxName)) (mailboxes Drafts NONE)\r\n"
+                ),
+                notify::Arguments {
+                    tag: "A02".to_string(),
+                    status: true,
+                    groups: vec![
+                        EventGroup {
+                            filter: Filter::Selected,
+                            events: vec![
+                                Event::MessageNew {
+                                    attributes: vec![
+                                        Attribute::Uid,
+                                        Attribute::Flags,
+                                        Attribute::Envelope,
+                                    ],
+                                },
+                                Event::MessageExpunge,
+                                Event::FlagChange,
+                            ],
+                        },
+                        EventGroup {
+                            filter: Filter::Subtree(vec![
+                                "INBOX".to_string(),
+                                "Lists/Work".to_string(),
+                            ]),
+                            events: vec![
+                                Event::MessageNew { attributes: vec![] },
+                                Event::MessageExpunge,
+                                Event::MailboxName,
+                            ],
+                        },
+                        EventGroup {
+                            filter: Filter::Mailboxes(vec!["Drafts".to_string()]),
+                            events: vec![],
+                        },
+                    ],
+                },
+            ),
+            (
+                "A03 NOTIFY SET (personal (SubscriptionChange MailboxName)) (selected-delayed (MessageNew MessageExpunge))\r\n",
+                notify::Arguments {
+                    tag: "A03".to_string(),
+                    status: false,
+                    groups: vec![
+                        EventGroup {
+                            filter: Filter::Personal,
+                            events: vec![Event::SubscriptionChange, Event::MailboxName],
+                        },
+                        EventGroup {
+                            filter: Filter::SelectedDelayed,
+                            events: vec![
+                                Event::MessageNew { attributes: vec![] },
+                                Event::MessageExpunge,
+                            ],
+                        },
+                    ],
+                },
+            ),
+        ] {
+            assert_eq!(
+                receiver
+                    .parse(&mut command.as_bytes().iter())
+                    .unwrap()
+                    .parse_notify(ProtocolVersion::Rev2)
+                    .unwrap(),
+                arguments,
+                "{command}"
+            );
+        }
+
+        for command in [
+            "A04 NOTIFY\r\n",
+            "A05 NOTIFY SET\r\n",
+            "A06 NOTIFY SET (personal (MessageNew))\r\n",
+            "A07 NOTIFY SET (personal (FlagChange))\r\n",
+            "A08 NOTIFY SET (selected (MailboxName))\r\n",
+            "A09 NOTIFY SET (personal (MessageNew (UID) MessageExpunge))\r\n",
+            "A10 NOTIFY SET (everything (MailboxName))\r\n",
+            "A11 NOTIFY SET (subtree (MailboxName))\r\n",
+            "A12 NOTIFY SET (personal (MessageNew (BODY) MessageExpunge))\r\n",
+        ] {
+            assert!(
+                receiver
+                    .parse(&mut command.as_bytes().iter())
+                    .unwrap()
+                    .parse_notify(ProtocolVersion::Rev2)
+                    .is_err(),
+                "{command}"
+            );
+        }
+    }
+}
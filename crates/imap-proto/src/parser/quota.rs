@@ -0,0 +1,204 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use crate::{
+    protocol::{
+        quota::{self, QuotaResource},
+        ProtocolVersion,
+    },
+    receiver::{Request, Token},
+    utf7::utf7_maybe_decode,
+    Command,
+};
+
+use super::parse_number;
+
+/*
+
+   getquota        = "GETQUOTA" SP quota-root-name
+
+   getquotaroot    = "GETQUOTAROOT" SP mailbox
+
+   setquota        = "SETQUOTA" SP quota-root-name
+                       SP setquota-list
+
+   setquota-list   = "(" [setquota-resource
+                       *(SP setquota-resource)] ")"
+
+   setquota-resource = resource-name SP resource-limit
+
+*/
+
+impl Request<Command> {
+    pub fn parse_quota(self, version: ProtocolVersion) -> crate::Result<quota::Arguments> {
+        let mut tokens = self.tokens.into_iter();
+        let name = utf7_maybe_decode(
+            tokens
+                .next()
+                .ok_or_else(|| {
+                    (
+                        self.tag.as_str(),
+                        if self.command == Command::GetQuotaRoot {
+                            "Missing mailbox name."
+                        } else {
+                            "Missing quota root name."
+                        },
+                    )
+                })?
+                .unwrap_string()
+                .map_err(|v| (self.tag.as_str(), v))?,
+            version,
+        );
+        let mut limits = Vec::new();
+
+        if self.command == Command::SetQuota {
+            if !matches!(tokens.next(), Some(Token::ParenthesisOpen)) {
+                return Err((
+                    self.tag.as_str(),
+                    "Expected parenthesis after quota root name.",
+                )
+                    .into());
+            }
+
+            loop {
+                match tokens.next() {
+                    Some(Token::ParenthesisClose) => break,
+                    Some(Token::Argument(value)) => {
+                        let resource =
+                            QuotaResource::parse(&value).map_err(|v| (self.tag.as_str(), v))?;
+                        let limit = parse_number::<u64>(
+                            &tokens
+                                .next()
+                                .ok_or((self.tag.as_str(), "Missing resource limit."))?
+                                .unwrap_bytes(),
+                        )
+                        .map_err(|v| (self.tag.as_str(), v))?;
+                        limits.push((resource, limit));
+                    }
+                    _ => {
+                        return Err((self.tag.as_str(), "Invalid quota resource list.").into());
+                    }
+                }
+            }
+        }
+
+        Ok(quota::Arguments {
+            tag: self.tag,
+            name,
+            limits,
+        })
+    }
+}
+
+impl QuotaResource {
+    pub fn parse(value: &[u8]) -> super::Result<Self> {
+        if value.eq_ignore_ascii_case(b"storage") {
+            Ok(Self::Storage)
+        } else if value.eq_ignore_ascii_case(b"message") {
+            Ok(Self::Message)
+        } else if value.eq_ignore_ascii_case(b"mailbox") {
+            Ok(Self::Mailbox)
+        } else if value.eq_ignore_ascii_case(b"annotation-storage") {
+            Ok(Self::AnnotationStorage)
+        } else {
+            Err(format!(
+                "Invalid quota resource '{}'.",
+                String::from_utf8_lossy(value)
+            )
+            .into())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        protocol::{
+            quota::{self, QuotaResource},
+            ProtocolVersion,
+        },
+        receiver::Receiver,
+    };
+
+    #[test]
+    fn parse_quota() {
+        let mut receiver = Receiver::new();
+
+        for (command, arguments) in [
+            (
+                "A003 GETQUOTA \"\"\r\n",
+                quota::Arguments {
+                    tag: "A003".to_string(),
+                    name: "".to_string(),
+                    limits: vec![],
+                },
+            ),
+            (
+                "A003 GETQUOTAROOT INBOX\r\n",
+                quota::Arguments {
+                    tag: "A003".to_string(),
+                    name: "INBOX".to_string(),
+                    limits: vec![],
+                },
+            ),
+            (
+                "A001 SETQUOTA \"\" (STORAGE 512)\r\n",
+                quota::Arguments {
+                    tag: "A001".to_string(),
+                    name: "".to_string(),
+                    limits: vec![(QuotaResource::Storage, 512)],
+                },
+            ),
+            (
+                "A002 SETQUOTA \"Shared Folders/jdoe\" (STORAGE 1024 message 300)\r\n",
+                quota::Arguments {
+                    tag: "A002".to_string(),
+                    name: "Shared Folders/jdoe".to_string(),
+                    limits: vec![
+                        (QuotaResource::Storage, 1024),
+                        (QuotaResource::Message, 300),
+                    ],
+                },
+            ),
+            (
+                "A004 SETQUOTA \"\" ()\r\n",
+                quota::Arguments {
+                    tag: "A004".to_string(),
+                    name: "".to_string(),
+                    limits: vec![],
+                },
+            ),
+        ] {
+            assert_eq!(
+                receiver
+                    .parse(&mut command.as_bytes().iter())
+                    .unwrap()
+                    .parse_quota(ProtocolVersion::Rev2)
+                    .unwrap(),
+                arguments,
+                "{:?}",
+                command
+            );
+        }
+    }
+}
@@ -35,6 +35,8 @@ pub enum Mechanism {
     DigestMd5,
     ScramSha1,
     ScramSha256,
+    ScramSha1Plus,
+    ScramSha256Plus,
     Apop,
     Ntlm,
     Gssapi,
@@ -52,6 +54,8 @@ impl Mechanism {
             Mechanism::DigestMd5 => b"DIGEST-MD5",
             Mechanism::ScramSha1 => b"SCRAM-SHA-1",
             Mechanism::ScramSha256 => b"SCRAM-SHA-256",
+            Mechanism::ScramSha1Plus => b"SCRAM-SHA-1-PLUS",
+            Mechanism::ScramSha256Plus => b"SCRAM-SHA-256-PLUS",
             Mechanism::Apop => b"APOP",
             Mechanism::Ntlm => b"NTLM",
             Mechanism::Gssapi => b"GSSAPI",
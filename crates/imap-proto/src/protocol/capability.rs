@@ -21,7 +21,7 @@
  * for more details.
 */
 
-use super::{authenticate::Mechanism, ImapResponse};
+use super::{authenticate::Mechanism, compress::Algorithm, quota::QuotaResource, ImapResponse};
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Response {
@@ -64,6 +64,11 @@ pub enum Capability {
     ObjectId,
     Preview,
     Utf8Accept,
+    Quota,
+    Metadata,
+    Notify,
+    QuotaResource(QuotaResource), //QUOTA=RES-*
+    Compress(Algorithm),          //COMPRESS=*
     Auth(Mechanism),
 }
 
@@ -75,6 +80,16 @@ impl Capability {
                 mechanism.serialize(buf);
                 return;
             }
+            Capability::QuotaResource(resource) => {
+                buf.extend_from_slice(b"QUOTA=RES-");
+                resource.serialize(buf);
+                return;
+            }
+            Capability::Compress(algorithm) => {
+                buf.extend_from_slice(b"COMPRESS=");
+                algorithm.serialize(buf);
+                return;
+            }
             Capability::IMAP4rev2 => b"IMAP4rev2",
             Capability::IMAP4rev1 => b"IMAP4rev1",
             Capability::StartTLS => b"STARTTLS",
@@ -109,6 +124,9 @@ impl Capability {
             Capability::CreateSpecialUse => b"CREATE-SPECIAL-USE",
             Capability::Move => b"MOVE",
             Capability::Utf8Accept => b"UTF8=ACCEPT",
+            Capability::Quota => b"QUOTA",
+            Capability::Metadata => b"METADATA",
+            Capability::Notify => b"NOTIFY",
         });
     }
 
@@ -150,12 +168,26 @@ impl Capability {
                 Capability::StatusSize,
                 Capability::ObjectId,
                 Capability::Preview,
+                Capability::Quota,
+                Capability::QuotaResource(QuotaResource::Storage),
+                Capability::QuotaResource(QuotaResource::Message),
+                Capability::Metadata,
+                Capability::Compress(Algorithm::Deflate),
+                Capability::Notify,
             ]);
         } else {
             capabilties.extend([
                 Capability::Auth(Mechanism::OAuthBearer),
                 Capability::Auth(Mechanism::Plain),
+                Capability::Auth(Mechanism::ScramSha256),
+                Capability::Auth(Mechanism::ScramSha1),
             ]);
+            if is_tls {
+                capabilties.extend([
+                    Capability::Auth(Mechanism::ScramSha256Plus),
+                    Capability::Auth(Mechanism::ScramSha1Plus),
+                ]);
+            }
         }
         if !is_tls {
             capabilties.push(Capability::StartTLS);
@@ -0,0 +1,192 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use crate::utf7::utf7_encode;
+
+use super::{literal_string, quoted_or_literal_string, quoted_string};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GetArguments {
+    pub tag: String,
+    pub mailbox_name: String,
+    pub entries: Vec<String>,
+    pub max_size: Option<usize>,
+    pub depth: Depth,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SetArguments {
+    pub tag: String,
+    pub mailbox_name: String,
+    pub entries: Vec<(String, Option<Vec<u8>>)>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Depth {
+    #[default]
+    Zero,
+    One,
+    Infinity,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MetadataCode {
+    LongEntries(usize),
+    MaxSize(usize),
+    TooMany,
+    NoPrivate,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Response {
+    pub mailbox_name: String,
+    pub entries: Vec<(String, Vec<u8>)>,
+}
+
+impl Depth {
+    pub fn matches(&self, entry: &str, name: &str) -> bool {
+        if entry == name {
+            true
+        } else if let Some(child) = entry
+            .strip_prefix(name)
+            .and_then(|child| child.strip_prefix('/'))
+        {
+            match self {
+                Depth::Zero => false,
+                Depth::One => !child.contains('/'),
+                Depth::Infinity => true,
+            }
+        } else {
+            false
+        }
+    }
+}
+
+impl MetadataCode {
+    pub fn serialize(&self, buf: &mut Vec<u8>) {
+        match self {
+            MetadataCode::LongEntries(size) => {
+                buf.extend_from_slice(b"LONGENTRIES ");
+                buf.extend_from_slice(size.to_string().as_bytes());
+            }
+            MetadataCode::MaxSize(size) => {
+                buf.extend_from_slice(b"MAXSIZE ");
+                buf.extend_from_slice(size.to_string().as_bytes());
+            }
+            MetadataCode::TooMany => buf.extend_from_slice(b"TOOMANY"),
+            MetadataCode::NoPrivate => buf.extend_from_slice(b"NOPRIVATE"),
+        }
+    }
+}
+
+impl Response {
+    pub fn into_bytes(self, is_rev2: bool) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(
+            self.mailbox_name.len()
+                + 16
+                + self
+                    .entries
+                    .iter()
+                    .map(|(name, value)| name.len() + value.len() + 4)
+                    .sum::<usize>(),
+        );
+        buf.extend_from_slice(b"* METADATA ");
+        if is_rev2 {
+            quoted_string(&mut buf, &self.mailbox_name);
+        } else {
+            quoted_string(&mut buf, &utf7_encode(&self.mailbox_name));
+        }
+        buf.extend_from_slice(b" (");
+        for (pos, (name, value)) in self.entries.iter().enumerate() {
+            if pos > 0 {
+                buf.push(b' ');
+            }
+            quoted_string(&mut buf, name);
+            buf.push(b' ');
+            match std::str::from_utf8(value) {
+                Ok(value) if value.is_ascii() => quoted_or_literal_string(&mut buf, value),
+                _ => literal_string(&mut buf, value),
+            }
+        }
+        buf.extend_from_slice(b")\r\n");
+        buf
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::protocol::metadata::{Depth, Response};
+
+    #[test]
+    fn serialize_metadata() {
+        assert_eq!(
+            String::from_utf8(
+                Response {
+                    mailbox_name: "INBOX".to_string(),
+                    entries: vec![
+                        ("/private/comment".to_string(), b"My own comment".to_vec()),
+                        (
+                            "/shared/comment".to_string(),
+                            "Café\r\n".as_bytes().to_vec()
+                        ),
+                    ],
+                }
+                .into_bytes(true)
+            )
+            .unwrap(),
+            concat!(
+                "* METADATA \"INBOX\" (\"/private/comment\" \"My own comment\" ",
+                "\"/shared/comment\" {7}\r\nCafé\r\n)\r\n"
+            )
+        );
+    }
+
+    #[test]
+    fn metadata_depth() {
+        for (depth, entry, name, expected) in [
+            (Depth::Zero, "/shared/comment", "/shared/comment", true),
+            (Depth::Zero, "/shared/comment/a", "/shared/comment", false),
+            (Depth::One, "/shared/comment/a", "/shared/comment", true),
+            (Depth::One, "/shared/comment/a/b", "/shared/comment", false),
+            (Depth::One, "/shared/commentary", "/shared/comment", false),
+            (
+                Depth::Infinity,
+                "/shared/comment/a/b",
+                "/shared/comment",
+                true,
+            ),
+            (
+                Depth::Infinity,
+                "/private/comment/a",
+                "/shared/comment",
+                false,
+            ),
+        ] {
+            assert_eq!(
+                depth.matches(entry, name),
+                expected,
+                "{depth:?} {entry} {name}"
+            );
+        }
+    }
+}
@@ -33,6 +33,7 @@ pub mod acl;
 pub mod append;
 pub mod authenticate;
 pub mod capability;
+pub mod compress;
 pub mod copy_move;
 pub mod create;
 pub mod delete;
@@ -41,7 +42,10 @@ pub mod expunge;
 pub mod fetch;
 pub mod list;
 pub mod login;
+pub mod metadata;
 pub mod namespace;
+pub mod notify;
+pub mod quota;
 pub mod rename;
 pub mod search;
 pub mod select;
@@ -398,6 +402,24 @@ impl ResponseCode {
                 return;
             }
             ResponseCode::UseAttr => b"USEATTR",
+            ResponseCode::Metadata(code) => {
+                buf.extend_from_slice(b"METADATA ");
+                code.serialize(buf);
+                return;
+            }
+            ResponseCode::CompressionActive => b"COMPRESSIONACTIVE",
+            ResponseCode::BadEvent(events) => {
+                buf.extend_from_slice(b"BADEVENT (");
+                for (pos, event) in events.iter().enumerate() {
+                    if pos > 0 {
+                        buf.push(b' ');
+                    }
+                    event.serialize(buf);
+                }
+                buf.push(b')');
+                return;
+            }
+            ResponseCode::NotificationOverflow => b"NOTIFICATIONOVERFLOW",
         });
     }
 }
@@ -526,6 +548,13 @@ impl Display for Command {
             Command::MyRights => write!(f, "MYRIGHTS"),
             Command::Unauthenticate => write!(f, "UNAUTHENTICATE"),
             Command::Id => write!(f, "ID"),
+            Command::GetQuota => write!(f, "GETQUOTA"),
+            Command::GetQuotaRoot => write!(f, "GETQUOTAROOT"),
+            Command::SetQuota => write!(f, "SETQUOTA"),
+            Command::GetMetadata => write!(f, "GETMETADATA"),
+            Command::SetMetadata => write!(f, "SETMETADATA"),
+            Command::Compress => write!(f, "COMPRESS"),
+            Command::Notify => write!(f, "NOTIFY"),
         }
     }
 }
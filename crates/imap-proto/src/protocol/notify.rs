@@ -0,0 +1,181 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use super::fetch::Attribute;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Arguments {
+    pub tag: String,
+    pub status: bool,
+    pub groups: Vec<EventGroup>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EventGroup {
+    pub filter: Filter,
+    pub events: Vec<Event>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Filter {
+    Selected,
+    SelectedDelayed,
+    Inboxes,
+    Personal,
+    Subscribed,
+    Subtree(Vec<String>),
+    Mailboxes(Vec<String>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Event {
+    MessageNew { attributes: Vec<Attribute> },
+    MessageExpunge,
+    FlagChange,
+    AnnotationChange,
+    MailboxName,
+    SubscriptionChange,
+    MailboxMetadataChange,
+    ServerMetadataChange,
+}
+
+impl Filter {
+    pub fn is_selected(&self) -> bool {
+        matches!(self, Filter::Selected | Filter::SelectedDelayed)
+    }
+
+    pub fn matches(&self, mailbox_name: &str, is_subscribed: bool, shared_prefix: &str) -> bool {
+        match self {
+            Filter::Selected | Filter::SelectedDelayed => false,
+            Filter::Inboxes => mailbox_name.eq_ignore_ascii_case("INBOX"),
+            Filter::Personal => {
+                mailbox_name != shared_prefix
+                    && !mailbox_name
+                        .strip_prefix(shared_prefix)
+                        .is_some_and(|name| name.starts_with('/'))
+            }
+            Filter::Subscribed => is_subscribed,
+            Filter::Subtree(mailboxes) => mailboxes.iter().any(|parent_name| {
+                is_same_mailbox(mailbox_name, parent_name)
+                    || mailbox_name
+                        .strip_prefix(parent_name.as_str())
+                        .is_some_and(|name| name.starts_with('/'))
+            }),
+            Filter::Mailboxes(mailboxes) => mailboxes
+                .iter()
+                .any(|name| is_same_mailbox(mailbox_name, name)),
+        }
+    }
+}
+
+fn is_same_mailbox(mailbox_name: &str, other_name: &str) -> bool {
+    mailbox_name == other_name
+        || (mailbox_name.eq_ignore_ascii_case("INBOX") && other_name.eq_ignore_ascii_case("INBOX"))
+}
+
+impl Event {
+    pub fn is_message_event(&self) -> bool {
+        matches!(
+            self,
+            Event::MessageNew { .. }
+                | Event::MessageExpunge
+                | Event::FlagChange
+                | Event::AnnotationChange
+        )
+    }
+
+    pub fn serialize(&self, buf: &mut Vec<u8>) {
+        buf.extend_from_slice(match self {
+            Event::MessageNew { .. } => b"MessageNew",
+            Event::MessageExpunge => b"MessageExpunge",
+            Event::FlagChange => b"FlagChange",
+            Event::AnnotationChange => b"AnnotationChange",
+            Event::MailboxName => b"MailboxName",
+            Event::SubscriptionChange => b"SubscriptionChange",
+            Event::MailboxMetadataChange => b"MailboxMetadataChange",
+            Event::ServerMetadataChange => b"ServerMetadataChange",
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::protocol::notify::Filter;
+
+    #[test]
+    fn notify_filter() {
+        for (filter, mailbox_name, is_subscribed, expected) in [
+            (Filter::Inboxes, "INBOX", false, true),
+            (Filter::Inboxes, "Sent", false, false),
+            (Filter::Personal, "Sent/2023", false, true),
+            (Filter::Personal, "Shared Folders", false, false),
+            (Filter::Personal, "Shared Folders/jane/INBOX", false, false),
+            (Filter::Personal, "Shared Folders Archive", false, true),
+            (Filter::Subscribed, "Sent", true, true),
+            (Filter::Subscribed, "Sent", false, false),
+            (
+                Filter::Subtree(vec!["Sent".to_string()]),
+                "Sent",
+                false,
+                true,
+            ),
+            (
+                Filter::Subtree(vec!["Sent".to_string()]),
+                "Sent/2023",
+                false,
+                true,
+            ),
+            (
+                Filter::Subtree(vec!["Sent".to_string()]),
+                "Sent Items",
+                false,
+                false,
+            ),
+            (
+                Filter::Subtree(vec!["inbox".to_string()]),
+                "INBOX",
+                false,
+                true,
+            ),
+            (
+                Filter::Mailboxes(vec!["Sent".to_string()]),
+                "Sent",
+                false,
+                true,
+            ),
+            (
+                Filter::Mailboxes(vec!["Sent".to_string()]),
+                "Sent/2023",
+                false,
+                false,
+            ),
+            (Filter::Selected, "INBOX", true, false),
+        ] {
+            assert_eq!(
+                filter.matches(mailbox_name, is_subscribed, "Shared Folders"),
+                expected,
+                "{filter:?} {mailbox_name}"
+            );
+        }
+    }
+}
@@ -0,0 +1,196 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use crate::utf7::utf7_encode;
+
+use super::quoted_string;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum QuotaResource {
+    Storage,
+    Message,
+    Mailbox,
+    AnnotationStorage,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Arguments {
+    pub tag: String,
+    pub name: String,
+    pub limits: Vec<(QuotaResource, u64)>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QuotaItem {
+    pub root: String,
+    pub resources: Vec<QuotaResourceItem>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QuotaResourceItem {
+    pub resource: QuotaResource,
+    pub usage: u64,
+    pub limit: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QuotaResponse {
+    pub quota: QuotaItem,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QuotaRootResponse {
+    pub mailbox_name: String,
+    pub quotas: Vec<QuotaItem>,
+}
+
+impl QuotaResource {
+    pub fn serialize(&self, buf: &mut Vec<u8>) {
+        buf.extend_from_slice(match self {
+            QuotaResource::Storage => b"STORAGE",
+            QuotaResource::Message => b"MESSAGE",
+            QuotaResource::Mailbox => b"MAILBOX",
+            QuotaResource::AnnotationStorage => b"ANNOTATION-STORAGE",
+        });
+    }
+}
+
+impl QuotaItem {
+    pub fn serialize(&self, buf: &mut Vec<u8>, is_rev2: bool) {
+        buf.extend_from_slice(b"* QUOTA ");
+        if is_rev2 {
+            quoted_string(buf, &self.root);
+        } else {
+            quoted_string(buf, &utf7_encode(&self.root));
+        }
+        buf.extend_from_slice(b" (");
+        for (pos, item) in self.resources.iter().enumerate() {
+            if pos > 0 {
+                buf.push(b' ');
+            }
+            item.resource.serialize(buf);
+            buf.push(b' ');
+            buf.extend_from_slice(item.usage.to_string().as_bytes());
+            buf.push(b' ');
+            buf.extend_from_slice(item.limit.to_string().as_bytes());
+        }
+        buf.extend_from_slice(b")\r\n");
+    }
+}
+
+impl QuotaResponse {
+    pub fn into_bytes(self, is_rev2: bool) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(64);
+        self.quota.serialize(&mut buf, is_rev2);
+        buf
+    }
+}
+
+impl QuotaRootResponse {
+    pub fn into_bytes(self, is_rev2: bool) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(self.mailbox_name.len() + 64 * (self.quotas.len() + 1));
+        buf.extend_from_slice(b"* QUOTAROOT ");
+        if is_rev2 {
+            quoted_string(&mut buf, &self.mailbox_name);
+        } else {
+            quoted_string(&mut buf, &utf7_encode(&self.mailbox_name));
+        }
+        for quota in &self.quotas {
+            buf.push(b' ');
+            if is_rev2 {
+                quoted_string(&mut buf, &quota.root);
+            } else {
+                quoted_string(&mut buf, &utf7_encode(&quota.root));
+            }
+        }
+        buf.extend_from_slice(b"\r\n");
+        for quota in &self.quotas {
+            quota.serialize(&mut buf, is_rev2);
+        }
+        buf
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::protocol::quota::{
+        QuotaItem, QuotaResource, QuotaResourceItem, QuotaResponse, QuotaRootResponse,
+    };
+
+    #[test]
+    fn serialize_quota() {
+        let quota = QuotaItem {
+            root: "".to_string(),
+            resources: vec![
+                QuotaResourceItem {
+                    resource: QuotaResource::Storage,
+                    usage: 10,
+                    limit: 512,
+                },
+                QuotaResourceItem {
+                    resource: QuotaResource::Message,
+                    usage: 14,
+                    limit: 1000,
+                },
+            ],
+        };
+
+        assert_eq!(
+            String::from_utf8(
+                QuotaResponse {
+                    quota: quota.clone()
+                }
+                .into_bytes(true)
+            )
+            .unwrap(),
+            "* QUOTA \"\" (STORAGE 10 512 MESSAGE 14 1000)\r\n"
+        );
+
+        assert_eq!(
+            String::from_utf8(
+                QuotaRootResponse {
+                    mailbox_name: "INBOX".to_string(),
+                    quotas: vec![quota],
+                }
+                .into_bytes(true)
+            )
+            .unwrap(),
+            concat!(
+                "* QUOTAROOT \"INBOX\" \"\"\r\n",
+                "* QUOTA \"\" (STORAGE 10 512 MESSAGE 14 1000)\r\n"
+            )
+        );
+
+        assert_eq!(
+            String::from_utf8(
+                QuotaRootResponse {
+                    mailbox_name: "comp.mail.mime".to_string(),
+                    quotas: vec![],
+                }
+                .into_bytes(true)
+            )
+            .unwrap(),
+            "* QUOTAROOT \"comp.mail.mime\"\r\n"
+        );
+    }
+}
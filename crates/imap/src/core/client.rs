@@ -33,10 +33,10 @@ use utils::listener::{
     SessionStream,
 };
 
-use super::{SelectedMailbox, Session, SessionData, State, IMAP};
+use super::{SelectedMailbox, Session, SessionData, State, StreamUpgrade, IMAP};
 
 impl<T: SessionStream> Session<T> {
-    pub async fn ingest(&mut self, bytes: &[u8]) -> crate::Result<bool> {
+    pub async fn ingest(&mut self, bytes: &[u8]) -> crate::Result<Option<StreamUpgrade>> {
         /*for line in String::from_utf8_lossy(bytes).split("\r\n") {
             let c = println!("{}", line);
         }*/
@@ -162,7 +162,7 @@ impl<T: SessionStream> Session<T> {
                                 .into_bytes(),
                         )
                         .await
-                        .map(|_| true);
+                        .map(|_| Some(StreamUpgrade::Tls));
                 }
                 Command::Noop => {
                     self.handle_noop(request).await?;
@@ -194,6 +194,29 @@ impl<T: SessionStream> Session<T> {
                 Command::Id => {
                     self.handle_id(request).await?;
                 }
+                Command::GetQuota => {
+                    self.handle_get_quota(request).await?;
+                }
+                Command::GetQuotaRoot => {
+                    self.handle_get_quota_root(request).await?;
+                }
+                Command::SetQuota => {
+                    self.handle_set_quota(request).await?;
+                }
+                Command::GetMetadata => {
+                    self.handle_get_metadata(request).await?;
+                }
+                Command::SetMetadata => {
+                    self.handle_set_metadata(request).await?;
+                }
+                Command::Notify => {
+                    self.handle_notify(request).await?;
+                }
+                Command::Compress => {
+                    if self.handle_compress(request).await? {
+                        return Ok(Some(StreamUpgrade::Compress));
+                    }
+                }
             }
         }
 
@@ -202,7 +225,7 @@ impl<T: SessionStream> Session<T> {
                 .await?;
         }
 
-        Ok(false)
+        Ok(None)
     }
 }
 
@@ -243,7 +266,7 @@ impl<T: SessionStream> Session<T> {
             Command::Capability | Command::Noop | Command::Logout | Command::Id => Ok(request),
             Command::StartTls => {
                 if !self.is_tls {
-                    if self.instance.acceptor.is_tls() {
+                    if self.instance.acceptor.load().is_tls() {
                         Ok(request)
                     } else {
                         Err(StatusResponse::no("TLS is not available.").with_tag(request.tag))
@@ -292,7 +315,14 @@ impl<T: SessionStream> Session<T> {
             | Command::GetAcl
             | Command::ListRights
             | Command::MyRights
-            | Command::Unauthenticate => {
+            | Command::Unauthenticate
+            | Command::GetQuota
+            | Command::GetQuotaRoot
+            | Command::SetQuota
+            | Command::GetMetadata
+            | Command::SetMetadata
+            | Command::Notify
+            | Command::Compress => {
                 if let State::Authenticated { .. } | State::Selected { .. } = state {
                     Ok(request)
                 } else {
@@ -40,7 +40,8 @@ impl<T: SessionStream> SessionData<T> {
             .await
             .map_err(|_| tracing::warn!(parent: &session.span, account_id = session.account_id, event = "error", "Failed to retrieve mailboxes."))?];
 
-        // Fetch shared mailboxes
+        // Fetch shared mailboxes, the accounts of the groups the user belongs to
+        // (including nested groups) are listed along with those shared through ACLs
         for &account_id in access_token.shared_accounts(Collection::Mailbox) {
             match session
                 .fetch_account_mailboxes(
@@ -51,6 +52,7 @@ impl<T: SessionStream> SessionData<T> {
                         session
                             .jmap
                             .directory
+                            .load()
                             .query(QueryBy::Id(account_id), false)
                             .await
                             .unwrap_or_default()
@@ -319,6 +321,7 @@ impl<T: SessionStream> SessionData<T> {
                     self.imap.name_shared,
                     self.jmap
                         .directory
+                        .load()
                         .query(QueryBy::Id(account_id), false)
                         .await
                         .unwrap_or_default()
@@ -388,6 +391,7 @@ impl<T: SessionStream> SessionData<T> {
                         if account.account_id == account_id {
                             account.mailbox_state.values_mut().for_each(|v| {
                                 v.total_deleted = None;
+                                v.total_deleted_storage = None;
                                 v.total_unseen = None;
                                 v.total_messages = None;
                                 v.size = None;
@@ -406,6 +410,7 @@ impl<T: SessionStream> SessionData<T> {
                             self.imap.name_shared,
                             self.jmap
                                 .directory
+                                .load()
                                 .query(QueryBy::Id(account_id), false)
                                 .await
                                 .unwrap_or_default()
@@ -456,6 +461,9 @@ impl<T: SessionStream> SessionData<T> {
                                     {
                                         changes.changed.push(mailbox_name.to_string());
                                     }
+                                    if mailbox.is_subscribed != old_mailbox.is_subscribed {
+                                        changes.subscription_changes.push(mailbox_name.to_string());
+                                    }
                                 }
                             } else {
                                 changes.added.push(mailbox_name.to_string());
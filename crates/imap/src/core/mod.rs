@@ -30,8 +30,9 @@ use std::{
 
 use ahash::AHashMap;
 use dashmap::DashMap;
+use directory::core::scram::ScramSession;
 use imap_proto::{
-    protocol::{list::Attribute, ProtocolVersion},
+    protocol::{list::Attribute, notify::EventGroup, ProtocolVersion},
     receiver::Receiver,
     Command, ResponseCode, StatusResponse,
 };
@@ -39,10 +40,11 @@ use jmap::{
     auth::{rate_limit::AuthenticatedLimiter, AccessToken},
     JMAP,
 };
+use jmap_proto::types::state::StateChange;
 use store::roaring::RoaringBitmap;
 use tokio::{
     io::{ReadHalf, WriteHalf},
-    sync::watch,
+    sync::{mpsc, watch},
 };
 use utils::{
     config::Rate,
@@ -83,6 +85,8 @@ pub struct IMAP {
     pub rate_limiter: DashMap<u32, Arc<AuthenticatedLimiter>>,
     pub rate_requests: Rate,
     pub rate_concurrent: u64,
+    pub metadata_max_size: usize,
+    pub metadata_max_entries: usize,
 }
 
 pub struct Session<T: SessionStream> {
@@ -95,13 +99,34 @@ pub struct Session<T: SessionStream> {
     pub is_tls: bool,
     pub is_condstore: bool,
     pub is_qresync: bool,
+    pub is_compressed: bool,
+    pub notify: Option<NotifySubscription>,
     pub stream_rx: ReadHalf<T>,
     pub stream_tx: Arc<tokio::sync::Mutex<WriteHalf<T>>>,
     pub in_flight: InFlight,
     pub remote_addr: IpAddr,
+    pub channel_binding: Option<Vec<u8>>,
+    pub sasl: Option<SaslExchange>,
     pub span: tracing::Span,
 }
 
+pub enum SaslExchange {
+    Scram(ScramSession),
+    Verified(AccessToken),
+}
+
+pub struct NotifySubscription {
+    pub change_rx: mpsc::Receiver<StateChange>,
+    pub groups: Vec<EventGroup>,
+    pub pending_selected: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StreamUpgrade {
+    Tls,
+    Compress,
+}
+
 pub struct SessionData<T: SessionStream> {
     pub account_id: u32,
     pub jmap: Arc<JMAP>,
@@ -121,9 +146,10 @@ pub struct Mailbox {
     pub total_messages: Option<u32>,
     pub total_unseen: Option<u32>,
     pub total_deleted: Option<u32>,
+    pub total_deleted_storage: Option<u64>,
     pub uid_validity: Option<u32>,
     pub uid_next: Option<u32>,
-    pub size: Option<u32>,
+    pub size: Option<u64>,
     pub recent_messages: RoaringBitmap,
 }
 
@@ -174,6 +200,7 @@ pub struct MailboxSync {
     pub added: Vec<String>,
     pub changed: Vec<String>,
     pub deleted: Vec<String>,
+    pub subscription_changes: Vec<String>,
 }
 
 pub enum SavedSearch {
@@ -21,14 +21,18 @@
  * for more details.
 */
 
-use std::{borrow::Cow, sync::Arc};
+use std::{borrow::Cow, sync::Arc, time::Duration};
 
 use imap_proto::{protocol::ProtocolVersion, receiver::Receiver};
+use jmap_proto::types::state::StateChange;
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
 use tokio_rustls::server::TlsStream;
-use utils::listener::{stream::NullIo, SessionManager, SessionStream};
+use utils::listener::{deflate::DeflateStream, stream::NullIo, SessionManager, SessionStream};
 
-use super::{ImapSessionManager, Session, State};
+use super::{ImapSessionManager, NotifySubscription, Session, State, StreamUpgrade};
+
+// How often queued notifications are retried while a command is in flight
+const NOTIFY_PENDING_INTERVAL: Duration = Duration::from_millis(100);
 
 impl SessionManager for ImapSessionManager {
     #[allow(clippy::manual_async_fn)]
@@ -38,10 +42,22 @@ impl SessionManager for ImapSessionManager {
     ) -> impl std::future::Future<Output = ()> + Send {
         async move {
             if let Ok(mut session) = Session::new(session, self).await {
-                if session.handle_conn().await && session.instance.acceptor.is_tls() {
-                    if let Ok(mut session) = session.into_tls().await {
-                        session.handle_conn().await;
+                match session.handle_conn().await {
+                    Some(StreamUpgrade::Tls) if session.instance.acceptor.load().is_tls() => {
+                        if let Ok(mut session) = session.into_tls().await {
+                            if let Some(StreamUpgrade::Compress) = session.handle_conn().await {
+                                if let Ok(mut session) = session.into_compressed().await {
+                                    session.handle_conn().await;
+                                }
+                            }
+                        }
                     }
+                    Some(StreamUpgrade::Compress) => {
+                        if let Ok(mut session) = session.into_compressed().await {
+                            session.handle_conn().await;
+                        }
+                    }
+                    _ => (),
                 }
             }
         }
@@ -54,7 +70,7 @@ impl SessionManager for ImapSessionManager {
 }
 
 impl<T: SessionStream> Session<T> {
-    pub async fn handle_conn(&mut self) -> bool {
+    pub async fn handle_conn(&mut self) -> Option<StreamUpgrade> {
         let mut buf = vec![0; 8192];
         let mut shutdown_rx = self.instance.shutdown_rx.clone();
 
@@ -71,9 +87,9 @@ impl<T: SessionStream> Session<T> {
                         Ok(Ok(bytes_read)) => {
                             if bytes_read > 0 {
                                 match self.ingest(&buf[..bytes_read]).await {
-                                    Ok(false) => (),
-                                    Ok(true) => {
-                                        return true;
+                                    Ok(None) => (),
+                                    Ok(Some(upgrade)) => {
+                                        return Some(upgrade);
                                     }
                                     Err(_) => {
                                         tracing::debug!(parent: &self.span, event = "disconnect", "Disconnecting client.");
@@ -96,6 +112,16 @@ impl<T: SessionStream> Session<T> {
                         }
                     }
                 },
+                state_change = next_notification(&mut self.notify), if self.notify.is_some() => {
+                    if self.handle_notify_change(state_change).await.is_err() {
+                        break;
+                    }
+                },
+                _ = tokio::time::sleep(NOTIFY_PENDING_INTERVAL), if self.notify.as_ref().is_some_and(|notify| notify.pending_selected) => {
+                    if self.write_pending_notifications().await.is_err() {
+                        break;
+                    }
+                },
                 _ = shutdown_rx.changed() => {
                     self.write_bytes(&b"* BYE Server shutting down.\r\n"[..]).await.ok();
                     tracing::debug!(parent: &self.span, event = "shutdown", "IMAP server shutting down.");
@@ -104,7 +130,7 @@ impl<T: SessionStream> Session<T> {
             };
         }
 
-        false
+        None
     }
 
     pub async fn new(
@@ -124,6 +150,9 @@ impl<T: SessionStream> Session<T> {
         let _ = session.stream.flush().await;
 
         // Split stream into read and write halves
+        let channel_binding = session
+            .stream
+            .tls_server_end_point(&session.instance.acceptor.load());
         let (stream_rx, stream_tx) = tokio::io::split(session.stream);
 
         Ok(Session {
@@ -133,12 +162,16 @@ impl<T: SessionStream> Session<T> {
             is_tls,
             is_condstore: false,
             is_qresync: false,
+            is_compressed: false,
+            notify: None,
             imap: manager.imap,
             jmap: manager.jmap,
             instance: session.instance,
             span: session.span,
             in_flight: session.in_flight,
             remote_addr: session.remote_ip,
+            channel_binding,
+            sasl: None,
             stream_rx,
             stream_tx: Arc::new(tokio::sync::Mutex::new(stream_tx)),
         })
@@ -168,8 +201,9 @@ impl<T: SessionStream> Session<T> {
         };
 
         // Upgrade to TLS
-        let (stream_rx, stream_tx) =
-            tokio::io::split(self.instance.tls_accept(stream, &self.span).await?);
+        let stream = self.instance.tls_accept(stream, &self.span).await?;
+        let channel_binding = stream.tls_server_end_point(&self.instance.acceptor.load());
+        let (stream_rx, stream_tx) = tokio::io::split(stream);
         let stream_tx = Arc::new(tokio::sync::Mutex::new(stream_tx));
 
         Ok(Session {
@@ -182,15 +216,85 @@ impl<T: SessionStream> Session<T> {
             is_tls: true,
             is_condstore: self.is_condstore,
             is_qresync: self.is_qresync,
+            is_compressed: self.is_compressed,
+            notify: self.notify,
+            span: self.span,
+            in_flight: self.in_flight,
+            remote_addr: self.remote_addr,
+            channel_binding,
+            sasl: None,
+            stream_rx,
+            stream_tx,
+        })
+    }
+
+    // The stream can only be swapped once no in-flight command holds a reference to it
+    pub fn can_replace_stream(&self) -> bool {
+        match &self.state {
+            State::NotAuthenticated { .. } => Arc::strong_count(&self.stream_tx) == 1,
+            State::Authenticated { data } | State::Selected { data, .. } => {
+                Arc::strong_count(data) == 1 && Arc::strong_count(&self.stream_tx) == 2
+            }
+        }
+    }
+
+    pub async fn into_compressed(self) -> Result<Session<DeflateStream<T>>, ()> {
+        // Drop references to write half from state
+        let state = if let Some(state) =
+            self.state
+                .try_replace_stream_tx(Arc::new(tokio::sync::Mutex::new(
+                    tokio::io::split(NullIo::default()).1,
+                ))) {
+            state
+        } else {
+            tracing::debug!("Failed to obtain write half state.");
+            return Err(());
+        };
+
+        // Take ownership of WriteHalf and unsplit it from ReadHalf
+        let stream = if let Ok(stream_tx) =
+            Arc::try_unwrap(self.stream_tx).map(|mutex| mutex.into_inner())
+        {
+            self.stream_rx.unsplit(stream_tx)
+        } else {
+            tracing::debug!("Failed to take ownership of write half.");
+            return Err(());
+        };
+
+        // Enable compression
+        let (stream_rx, stream_tx) = tokio::io::split(DeflateStream::new(stream));
+        let stream_tx = Arc::new(tokio::sync::Mutex::new(stream_tx));
+
+        Ok(Session {
+            jmap: self.jmap,
+            imap: self.imap,
+            instance: self.instance,
+            receiver: self.receiver,
+            version: self.version,
+            state: state.try_replace_stream_tx(stream_tx.clone()).unwrap(),
+            is_tls: self.is_tls,
+            is_condstore: self.is_condstore,
+            is_qresync: self.is_qresync,
+            is_compressed: true,
+            notify: self.notify,
             span: self.span,
             in_flight: self.in_flight,
             remote_addr: self.remote_addr,
+            channel_binding: self.channel_binding,
+            sasl: self.sasl,
             stream_rx,
             stream_tx,
         })
     }
 }
 
+async fn next_notification(notify: &mut Option<NotifySubscription>) -> Option<StateChange> {
+    match notify {
+        Some(notify) => notify.change_rx.recv().await,
+        None => std::future::pending().await,
+    }
+}
+
 impl<T: SessionStream> Session<T> {
     pub async fn write_bytes(&self, bytes: impl Into<Cow<'static, [u8]>>) -> crate::OpResult {
         let bytes = bytes.into();
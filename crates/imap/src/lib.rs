@@ -74,6 +74,8 @@ impl IMAP {
             rate_concurrent: config.property("imap.rate-limit.concurrent")?.unwrap_or(4),
             allow_plain_auth: config.property_or_static("imap.auth.allow-plain-text", "false")?,
             enable_uidplus: config.property_or_static("imap.protocol.uidplus", "false")?,
+            metadata_max_size: config.property_or_static("imap.metadata.max-size", "4096")?,
+            metadata_max_entries: config.property_or_static("imap.metadata.max-entries", "100")?,
         }))
     }
 }
@@ -74,6 +74,7 @@ impl<T: SessionStream> Session<T> {
                                     if let Some(account_name) = data
                                         .jmap
                                         .directory
+                                        .load()
                                         .query(QueryBy::Id(item.account_id), false)
                                         .await
                                         .unwrap_or_default()
@@ -245,6 +246,7 @@ impl<T: SessionStream> Session<T> {
                     let acl_account_id = match data
                         .jmap
                         .directory
+                        .load()
                         .query(QueryBy::Name(arguments.identifier.as_ref().unwrap()), false)
                         .await
                     {
@@ -133,7 +133,10 @@ impl<T: SessionStream> SessionData<T> {
                     keywords: message.flags.into_iter().map(Keyword::from).collect(),
                     received_at: message.received_at.map(|d| d as u64),
                     skip_duplicates: false,
-                    encrypt: self.jmap.config.encrypt && self.jmap.config.encrypt_append,
+                    encrypt: self.jmap.config.load().encrypt
+                        && self.jmap.config.load().encrypt_append,
+                    authenticated_sender: None,
+                    is_delivery: false,
                 })
                 .await
             {
@@ -23,20 +23,29 @@
 
 use std::sync::Arc;
 
-use directory::AuthResult;
+use directory::{
+    core::scram::{ScramAlgorithm, ScramSession},
+    AuthResult,
+};
 use imap_proto::{
-    protocol::{authenticate::Mechanism, capability::Capability},
+    protocol::{
+        authenticate::{Arguments, Mechanism},
+        capability::Capability,
+    },
     receiver::{self, Request},
     Command, ResponseCode, StatusResponse,
 };
+use jmap::auth::AccessToken;
+use mail_builder::encoders::base64::base64_encode;
 use mail_parser::decoders::base64::base64_decode;
 use mail_send::Credentials;
-use utils::listener::SessionStream;
+use utils::{config::ServerProtocol, listener::SessionStream};
 
-use crate::core::{Session, SessionData, State};
+use crate::core::{SaslExchange, Session, SessionData, State};
 
 impl<T: SessionStream> Session<T> {
     pub async fn handle_authenticate(&mut self, request: Request<Command>) -> crate::OpResult {
+        let exchange = self.sasl.take();
         match request.parse_authenticate() {
             Ok(mut args) => match args.mechanism {
                 Mechanism::Plain | Mechanism::OAuthBearer => {
@@ -81,6 +90,10 @@ impl<T: SessionStream> Session<T> {
                         self.write_bytes(b"+ \"\"\r\n".to_vec()).await
                     }
                 }
+                Mechanism::ScramSha1
+                | Mechanism::ScramSha256
+                | Mechanism::ScramSha1Plus
+                | Mechanism::ScramSha256Plus => self.handle_scram(args, exchange).await,
                 _ => {
                     self.write_bytes(
                         StatusResponse::no("Authentication mechanism not supported.")
@@ -95,11 +108,133 @@ impl<T: SessionStream> Session<T> {
         }
     }
 
-    pub async fn authenticate(
+    async fn handle_scram(
+        &mut self,
+        mut args: Arguments,
+        exchange: Option<SaslExchange>,
+    ) -> crate::OpResult {
+        let response = match args.params.pop() {
+            Some(response) if response == "*" => {
+                return self
+                    .write_bytes(
+                        StatusResponse::bad("Authentication cancelled.")
+                            .with_tag(args.tag)
+                            .into_bytes(),
+                    )
+                    .await;
+            }
+            Some(response) => match base64_decode(response.as_bytes()) {
+                Some(response) => Some(response),
+                None => {
+                    return self
+                        .write_bytes(
+                            StatusResponse::no("Failed to decode challenge.")
+                                .with_tag(args.tag)
+                                .with_code(ResponseCode::Parse)
+                                .into_bytes(),
+                        )
+                        .await;
+                }
+            },
+            None => None,
+        };
+
+        match (exchange, response) {
+            (None, None) => {
+                // Client did not send an initial response
+                self.continue_authenticate(args.tag, args.mechanism, b"")
+                    .await
+            }
+            (None, Some(client_first)) => {
+                self.is_auth_allowed().await?;
+
+                let (algorithm, is_plus) = match args.mechanism {
+                    Mechanism::ScramSha1 => (ScramAlgorithm::Sha1, false),
+                    Mechanism::ScramSha1Plus => (ScramAlgorithm::Sha1, true),
+                    Mechanism::ScramSha256Plus => (ScramAlgorithm::Sha256, true),
+                    _ => (ScramAlgorithm::Sha256, false),
+                };
+                let mut session =
+                    ScramSession::new(algorithm, is_plus, self.channel_binding.clone());
+                match self
+                    .jmap
+                    .directory
+                    .load()
+                    .scram_challenge(&mut session, &client_first, true)
+                    .await
+                {
+                    Ok(Some(server_first)) => {
+                        self.sasl = SaslExchange::Scram(session).into();
+                        self.continue_authenticate(args.tag, args.mechanism, &server_first)
+                            .await
+                    }
+                    Ok(None) => {
+                        self.write_bytes(
+                            StatusResponse::no("Invalid SCRAM client-first message.")
+                                .with_tag(args.tag)
+                                .with_code(ResponseCode::Parse)
+                                .into_bytes(),
+                        )
+                        .await
+                    }
+                    Err(_) => {
+                        self.write_bytes(
+                            StatusResponse::database_failure()
+                                .with_tag(args.tag)
+                                .into_bytes(),
+                        )
+                        .await
+                    }
+                }
+            }
+            (Some(SaslExchange::Scram(mut session)), Some(client_final)) => {
+                match self
+                    .jmap
+                    .authenticate_scram(&mut session, &client_final, self.remote_addr)
+                    .await
+                {
+                    AuthResult::Success((access_token, server_final)) => {
+                        self.sasl = SaslExchange::Verified(access_token).into();
+                        self.continue_authenticate(args.tag, args.mechanism, &server_final)
+                            .await
+                    }
+                    AuthResult::Failure => self.finish_authenticate(None, args.tag).await,
+                    AuthResult::Banned => Err(()),
+                }
+            }
+            (Some(SaslExchange::Verified(access_token)), _) => {
+                self.finish_authenticate(access_token.into(), args.tag)
+                    .await
+            }
+            (Some(SaslExchange::Scram(_)), None) => self.finish_authenticate(None, args.tag).await,
+        }
+    }
+
+    async fn continue_authenticate(
         &mut self,
-        credentials: Credentials<String>,
         tag: String,
-    ) -> crate::Result<()> {
+        mechanism: Mechanism,
+        challenge: &[u8],
+    ) -> crate::OpResult {
+        self.receiver.request = receiver::Request {
+            tag,
+            command: Command::Authenticate,
+            tokens: vec![receiver::Token::Argument(mechanism.into_bytes())],
+        };
+        self.receiver.state = receiver::State::Argument { last_ch: b' ' };
+
+        let mut buf = Vec::with_capacity(challenge.len() * 4 / 3 + 6);
+        buf.extend_from_slice(b"+ ");
+        if !challenge.is_empty() {
+            buf.extend_from_slice(&base64_encode(challenge).unwrap_or_default());
+        } else {
+            buf.extend_from_slice(b"\"\"");
+        }
+        buf.extend_from_slice(b"\r\n");
+        self.write_bytes(buf).await
+    }
+
+    async fn is_auth_allowed(&mut self) -> crate::Result<()> {
         // Throttle authentication requests
         if self.jmap.is_auth_allowed_soft(&self.remote_addr).is_err() {
             self.write_bytes(
@@ -111,15 +246,25 @@ impl<T: SessionStream> Session<T> {
                 event = "disconnect",
                 "Too many authentication attempts, disconnecting.",
             );
-            return Err(());
+            Err(())
+        } else {
+            Ok(())
         }
+    }
+
+    pub async fn authenticate(
+        &mut self,
+        credentials: Credentials<String>,
+        tag: String,
+    ) -> crate::Result<()> {
+        self.is_auth_allowed().await?;
 
         // Authenticate
         let access_token = match credentials {
             Credentials::Plain { username, secret } | Credentials::XOauth2 { username, secret } => {
                 match self
                     .jmap
-                    .authenticate_plain(&username, &secret, self.remote_addr)
+                    .authenticate_plain(&username, &secret, self.remote_addr, ServerProtocol::Imap)
                     .await
                 {
                     AuthResult::Success(token) => Some(token),
@@ -130,23 +275,24 @@ impl<T: SessionStream> Session<T> {
             Credentials::OAuthBearer { token } => {
                 match self
                     .jmap
-                    .validate_access_token("access_token", &token)
+                    .authenticate_bearer(&token, self.remote_addr, ServerProtocol::Imap)
                     .await
                 {
-                    Ok((account_id, _, _)) => self.jmap.get_access_token(account_id).await,
-                    Err(err) => {
-                        tracing::debug!(
-                            parent: &self.span,
-                            context = "authenticate",
-                            err = err,
-                            "Failed to validate access token."
-                        );
-                        None
-                    }
+                    AuthResult::Success(token) => Some(token),
+                    AuthResult::Failure => None,
+                    AuthResult::Banned => return Err(()),
                 }
             }
         };
 
+        self.finish_authenticate(access_token, tag).await
+    }
+
+    async fn finish_authenticate(
+        &mut self,
+        access_token: Option<AccessToken>,
+        tag: String,
+    ) -> crate::Result<()> {
         if let Some(access_token) = access_token {
             // Enforce concurrency limits
             let in_flight = self
@@ -216,6 +362,7 @@ impl<T: SessionStream> Session<T> {
 
     pub async fn handle_unauthenticate(&mut self, request: Request<Command>) -> crate::OpResult {
         self.state = State::NotAuthenticated { auth_failures: 0 };
+        self.notify = None;
 
         self.write_bytes(
             StatusResponse::completed(Command::Unauthenticate)
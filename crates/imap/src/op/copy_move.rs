@@ -28,7 +28,10 @@ use imap_proto::{
     StatusResponse,
 };
 
-use jmap::{email::set::TagManager, mailbox::UidMailbox};
+use jmap::{
+    email::{bayes::SpamTraining, set::TagManager},
+    mailbox::UidMailbox,
+};
 use jmap_proto::{
     error::{method::MethodError, set::SetErrorType},
     types::{
@@ -216,6 +219,9 @@ impl<T: SessionStream> SessionData<T> {
                     mailboxes.update(UidMailbox::from(src_mailbox.id.mailbox_id), false);
                 }
 
+                // Check for Junk/Not Junk actions
+                let spam_training = SpamTraining::from_changes(Some(&mailboxes), None);
+
                 // Write changes
                 let mut batch = BatchBuilder::new();
                 batch
@@ -232,6 +238,11 @@ impl<T: SessionStream> SessionData<T> {
                 batch.value(Property::Cid, changelog.change_id, F_VALUE);
                 match self.jmap.write_batch(batch).await {
                     Ok(_) => {
+                        if let Some(spam_training) = spam_training {
+                            self.jmap
+                                .schedule_spam_training(account_id, id, spam_training)
+                                .await;
+                        }
                         changelog.log_update(Collection::Email, Id::from_parts(thread_id, id));
                         changelog.log_child_update(Collection::Mailbox, dest_mailbox_id.mailbox_id);
                         if is_move {
@@ -209,6 +209,7 @@ impl<T: SessionStream> SessionData<T> {
                     total_messages: 0.into(),
                     total_unseen: 0.into(),
                     total_deleted: 0.into(),
+                    total_deleted_storage: 0.into(),
                     uid_validity: None,
                     uid_next: None,
                     size: 0.into(),
@@ -253,13 +254,13 @@ impl<T: SessionStream> SessionData<T> {
                 let path_item = path_item.trim();
                 if path_item.is_empty() {
                     return Err(StatusResponse::no("Invalid empty path item."));
-                } else if path_item.len() > self.jmap.config.mailbox_name_max_len {
+                } else if path_item.len() > self.jmap.config.load().mailbox_name_max_len {
                     return Err(StatusResponse::no("Mailbox name is too long."));
                 }
                 path.push(path_item);
             }
 
-            if path.len() > self.jmap.config.mailbox_max_depth {
+            if path.len() > self.jmap.config.load().mailbox_max_depth {
                 return Err(StatusResponse::no("Mailbox path is too deep."));
             }
         } else {
@@ -28,6 +28,7 @@ use imap_proto::{
     protocol::{
         fetch,
         list::{Attribute, ListItem},
+        notify::EventGroup,
         status::Status,
         Sequence,
     },
@@ -35,16 +36,24 @@ use imap_proto::{
     Command, ResponseCode, StatusResponse,
 };
 
-use jmap_proto::types::{collection::Collection, type_state::DataType};
+use jmap_proto::types::{collection::Collection, state::StateChange, type_state::DataType};
 use store::query::log::Query;
-use tokio::io::AsyncReadExt;
+use tokio::{io::AsyncReadExt, sync::mpsc};
 use utils::listener::SessionStream;
 use utils::map::bitmap::Bitmap;
 
-use crate::core::{SelectedMailbox, Session, SessionData, State};
+use crate::core::{NotifySubscription, SelectedMailbox, Session, SessionData, State};
 
 impl<T: SessionStream> Session<T> {
     pub async fn handle_idle(&mut self, request: Request<Command>) -> crate::OpResult {
+        // Send any updates to the selected mailbox that were held back by NOTIFY
+        if self
+            .notify
+            .as_ref()
+            .is_some_and(|notify| notify.pending_selected)
+        {
+            self.write_pending_notifications().await?;
+        }
         let (data, mailbox, types) = match &self.state {
             State::Authenticated { data, .. } => {
                 (data.clone(), None, Bitmap::from_iter([DataType::Mailbox]))
@@ -59,13 +68,15 @@ impl<T: SessionStream> Session<T> {
         let is_rev2 = self.version.is_rev2();
         let is_qresync = self.is_qresync;
 
-        // Register with state manager
-        let mut change_rx = if let Some(change_rx) = self
+        // Register with state manager, or reuse the NOTIFY subscription
+        let (mut change_rx, notify_groups) = if let Some(notify) = self.notify.take() {
+            (notify.change_rx, Some(notify.groups))
+        } else if let Some(change_rx) = self
             .jmap
             .subscribe_state_manager(data.account_id, data.account_id, types)
             .await
         {
-            change_rx
+            (change_rx, None)
         } else {
             return self
                 .write_bytes(
@@ -77,6 +88,39 @@ impl<T: SessionStream> Session<T> {
                 .await;
         };
 
+        let result = self
+            .idle(
+                &request.tag,
+                &data,
+                &mailbox,
+                &mut change_rx,
+                &notify_groups,
+                is_qresync,
+                is_rev2,
+            )
+            .await;
+        if let Some(groups) = notify_groups {
+            self.notify = NotifySubscription {
+                change_rx,
+                groups,
+                pending_selected: false,
+            }
+            .into();
+        }
+        result
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    async fn idle(
+        &mut self,
+        tag: &str,
+        data: &SessionData<T>,
+        mailbox: &Option<Arc<SelectedMailbox>>,
+        change_rx: &mut mpsc::Receiver<StateChange>,
+        notify_groups: &Option<Vec<EventGroup>>,
+        is_qresync: bool,
+        is_rev2: bool,
+    ) -> crate::OpResult {
         // Send continuation response
         self.write_bytes(b"+ Idling, send 'DONE' to stop.\r\n".to_vec())
             .await?;
@@ -91,7 +135,7 @@ impl<T: SessionStream> Session<T> {
                                 if (buf[..bytes_read]).windows(4).any(|w| w == b"DONE") {
                                     tracing::debug!(parent: &self.span, event = "stop", context = "idle", "Stopping IDLE.");
                                     return self.write_bytes(StatusResponse::completed(Command::Idle)
-                                                                    .with_tag(request.tag)
+                                                                    .with_tag(tag)
                                                                     .into_bytes()).await;
                                 }
                             } else {
@@ -112,6 +156,11 @@ impl<T: SessionStream> Session<T> {
                 }
                 state_change = change_rx.recv() => {
                     if let Some(state_change) = state_change {
+                        if let Some(groups) = notify_groups {
+                            data.write_notifications(groups, mailbox, state_change, true, is_qresync, is_rev2).await;
+                            continue;
+                        }
+
                         let mut has_mailbox_changes = false;
                         let mut has_email_changes = false;
 
@@ -128,7 +177,7 @@ impl<T: SessionStream> Session<T> {
                         }
 
                         if has_mailbox_changes || has_email_changes {
-                            data.write_changes(&mailbox, has_mailbox_changes, has_email_changes, is_qresync, is_rev2).await;
+                            data.write_changes(mailbox, has_mailbox_changes, has_email_changes, is_qresync, is_rev2).await;
                         }
                     } else {
                         self.write_bytes(&b"* BYE Server shutting down.\r\n"[..]).await.ok();
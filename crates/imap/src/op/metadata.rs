@@ -0,0 +1,434 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::slice::Iter;
+
+use imap_proto::{
+    protocol::metadata::{GetArguments, MetadataCode, Response, SetArguments},
+    receiver::Request,
+    Command, ResponseCode, StatusResponse,
+};
+use jmap::{auth::acl::EffectiveAcl, auth::AccessToken};
+use jmap_proto::{
+    error::method::MethodError,
+    object::Object,
+    types::{acl::Acl, collection::Collection, property::Property, value::Value},
+};
+use store::{
+    write::{assert::HashedValue, BatchBuilder, DeserializeFrom, SerializeInto, ValueClass},
+    Deserialize, Serialize,
+};
+use utils::{
+    codec::leb128::{Leb128Iterator, Leb128Vec},
+    listener::SessionStream,
+};
+
+use crate::core::{Session, SessionData};
+
+// Server annotations are stored under a reserved document id, shared
+// server annotations under a reserved account id.
+const SERVER_METADATA_ID: u32 = u32::MAX;
+
+#[derive(Debug, Default, Clone)]
+struct Metadata {
+    entries: Vec<MetadataEntry>,
+}
+
+#[derive(Debug, Clone)]
+struct MetadataEntry {
+    account_id: Option<u32>,
+    name: String,
+    value: Vec<u8>,
+}
+
+struct MetadataLocation {
+    account_id: u32,
+    document_id: u32,
+    may_read: bool,
+    may_write_private: bool,
+    may_write_shared: bool,
+}
+
+type MetadataChanges<'x> = Vec<(Option<u32>, &'x String, &'x Option<Vec<u8>>)>;
+
+impl<T: SessionStream> Session<T> {
+    pub async fn handle_get_metadata(&mut self, request: Request<Command>) -> crate::OpResult {
+        match request.parse_get_metadata(self.version) {
+            Ok(arguments) => {
+                let data = self.state.session_data();
+                let is_rev2 = self.version.is_rev2();
+
+                tokio::spawn(async move {
+                    let tag = arguments.tag.clone();
+                    let bytes = match data.get_metadata(arguments, is_rev2).await {
+                        Ok(bytes) => bytes,
+                        Err(response) => response.with_tag(tag).into_bytes(),
+                    };
+                    data.write_bytes(bytes).await;
+                });
+                Ok(())
+            }
+            Err(response) => self.write_bytes(response.into_bytes()).await,
+        }
+    }
+
+    pub async fn handle_set_metadata(&mut self, request: Request<Command>) -> crate::OpResult {
+        match request.parse_set_metadata(self.version) {
+            Ok(arguments) => {
+                let data = self.state.session_data();
+
+                tokio::spawn(async move {
+                    let tag = arguments.tag.clone();
+                    data.write_bytes(
+                        match data.set_metadata(arguments).await {
+                            Ok(response) => response,
+                            Err(response) => response,
+                        }
+                        .with_tag(tag)
+                        .into_bytes(),
+                    )
+                    .await;
+                });
+                Ok(())
+            }
+            Err(response) => self.write_bytes(response.into_bytes()).await,
+        }
+    }
+}
+
+impl<T: SessionStream> SessionData<T> {
+    async fn get_metadata(&self, arguments: GetArguments, is_rev2: bool) -> super::Result<Vec<u8>> {
+        let access_token = self.get_access_token().await?;
+        let mut locations = Vec::with_capacity(2);
+        for is_private in [false, true] {
+            if arguments
+                .entries
+                .iter()
+                .any(|entry| entry.starts_with("/private/") == is_private)
+            {
+                let location = self
+                    .metadata_location(&arguments.mailbox_name, is_private, &access_token)
+                    .await?;
+                if !location.may_read {
+                    return Err(StatusResponse::no(
+                        "You do not have enough permissions to perform this operation.",
+                    )
+                    .with_code(ResponseCode::NoPerm));
+                }
+                locations.push(location);
+            }
+        }
+
+        let mut entries = Vec::new();
+        let mut longest_entry = 0;
+        for location in locations {
+            let metadata = self
+                .jmap
+                .get_property::<Metadata>(
+                    location.account_id,
+                    Collection::Mailbox,
+                    location.document_id,
+                    Property::Metadata,
+                )
+                .await?
+                .unwrap_or_default();
+
+            for entry in metadata.entries {
+                if entry.account_id.unwrap_or(access_token.primary_id) == access_token.primary_id
+                    && !entries.iter().any(|(name, _)| name == &entry.name)
+                    && arguments
+                        .entries
+                        .iter()
+                        .any(|name| arguments.depth.matches(&entry.name, name))
+                {
+                    if entry.value.len() <= arguments.max_size.unwrap_or(usize::MAX) {
+                        entries.push((entry.name, entry.value));
+                    } else {
+                        longest_entry = std::cmp::max(longest_entry, entry.value.len());
+                    }
+                }
+            }
+        }
+
+        let mut response = StatusResponse::completed(Command::GetMetadata).with_tag(arguments.tag);
+        if longest_entry > 0 {
+            response = response.with_code(ResponseCode::Metadata(MetadataCode::LongEntries(
+                longest_entry,
+            )));
+        }
+
+        Ok(response.serialize(if !entries.is_empty() {
+            Response {
+                mailbox_name: arguments.mailbox_name,
+                entries,
+            }
+            .into_bytes(is_rev2)
+        } else {
+            Vec::new()
+        }))
+    }
+
+    async fn set_metadata(&self, arguments: SetArguments) -> super::Result<StatusResponse> {
+        // Validate sizes
+        let max_size = self.imap.metadata_max_size;
+        if let Some(size) = arguments
+            .entries
+            .iter()
+            .filter_map(|(_, value)| value.as_ref().map(|v| v.len()))
+            .find(|size| *size > max_size)
+        {
+            return Err(StatusResponse::no(format!(
+                "Entry value of {size} bytes exceeds the maximum size of {max_size} bytes."
+            ))
+            .with_code(ResponseCode::Metadata(MetadataCode::MaxSize(max_size))));
+        }
+
+        // Group changes by the document holding them, private and shared
+        // mailbox entries are stored together.
+        let access_token = self.get_access_token().await?;
+        let mut updates: Vec<(u32, u32, MetadataChanges)> = Vec::new();
+        for is_private in [false, true] {
+            let changes = arguments
+                .entries
+                .iter()
+                .filter(|(entry, _)| entry.starts_with("/private/") == is_private)
+                .collect::<Vec<_>>();
+            if changes.is_empty() {
+                continue;
+            }
+
+            let location = self
+                .metadata_location(&arguments.mailbox_name, is_private, &access_token)
+                .await?;
+            if !(if is_private {
+                location.may_write_private
+            } else {
+                location.may_write_shared
+            }) {
+                return Err(StatusResponse::no(
+                    "You do not have enough permissions to perform this operation.",
+                )
+                .with_code(ResponseCode::NoPerm));
+            }
+            let account_id = if is_private {
+                Some(access_token.primary_id)
+            } else {
+                None
+            };
+            let changes = changes
+                .into_iter()
+                .map(|(name, value)| (account_id, name, value));
+            if let Some((_, _, update)) = updates.iter_mut().find(|(account_id, document_id, _)| {
+                *account_id == location.account_id && *document_id == location.document_id
+            }) {
+                update.extend(changes);
+            } else {
+                updates.push((location.account_id, location.document_id, changes.collect()));
+            }
+        }
+
+        let mut batch = BatchBuilder::new();
+        for (location_account_id, location_document_id, changes) in updates {
+            // Apply changes
+            let current = self
+                .jmap
+                .get_property::<HashedValue<Metadata>>(
+                    location_account_id,
+                    Collection::Mailbox,
+                    location_document_id,
+                    Property::Metadata,
+                )
+                .await?;
+            let mut metadata = current
+                .as_ref()
+                .map(|current| current.inner.clone())
+                .unwrap_or_default();
+            for (account_id, name, value) in changes {
+                let pos = metadata
+                    .entries
+                    .iter()
+                    .position(|entry| entry.account_id == account_id && &entry.name == name);
+                match (pos, value) {
+                    (Some(pos), Some(value)) => {
+                        metadata.entries[pos].value = value.clone();
+                    }
+                    (Some(pos), None) => {
+                        metadata.entries.swap_remove(pos);
+                    }
+                    (None, Some(value)) => {
+                        metadata.entries.push(MetadataEntry {
+                            account_id,
+                            name: name.clone(),
+                            value: value.clone(),
+                        });
+                    }
+                    (None, None) => (),
+                }
+            }
+
+            // Shared entries and the private entries of each user are limited separately,
+            // so that no user can use up the entries available to others.
+            if [None, Some(access_token.primary_id)]
+                .into_iter()
+                .any(|account_id| {
+                    metadata
+                        .entries
+                        .iter()
+                        .filter(|entry| entry.account_id == account_id)
+                        .count()
+                        > self.imap.metadata_max_entries
+                })
+            {
+                return Err(StatusResponse::no("Too many metadata entries.")
+                    .with_code(ResponseCode::Metadata(MetadataCode::TooMany)));
+            }
+
+            // Write changes
+            let class = ValueClass::from(Property::Metadata);
+            batch
+                .with_account_id(location_account_id)
+                .with_collection(Collection::Mailbox)
+                .update_document(location_document_id);
+            if let Some(current) = &current {
+                batch.assert_value(class.clone(), current);
+            } else {
+                batch.assert_value(class.clone(), ());
+            }
+            if !metadata.entries.is_empty() {
+                batch.set(class, metadata.serialize());
+            } else {
+                batch.clear(class);
+            }
+        }
+
+        match self.jmap.write_batch(batch).await {
+            Ok(_) => Ok(StatusResponse::completed(Command::SetMetadata)),
+            Err(MethodError::ServerUnavailable) => Err(StatusResponse::no(
+                "Another process is currently updating this mailbox",
+            )),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    async fn metadata_location(
+        &self,
+        mailbox_name: &str,
+        is_private: bool,
+        access_token: &AccessToken,
+    ) -> super::Result<MetadataLocation> {
+        if mailbox_name.is_empty() {
+            // Server annotations
+            Ok(if is_private {
+                MetadataLocation {
+                    account_id: access_token.primary_id,
+                    document_id: SERVER_METADATA_ID,
+                    may_read: true,
+                    may_write_private: true,
+                    may_write_shared: false,
+                }
+            } else {
+                MetadataLocation {
+                    account_id: SERVER_METADATA_ID,
+                    document_id: SERVER_METADATA_ID,
+                    may_read: true,
+                    may_write_private: false,
+                    may_write_shared: access_token.is_super_user(),
+                }
+            })
+        } else if let Some(mailbox) = self.get_mailbox_by_name(mailbox_name) {
+            if access_token.is_member(mailbox.account_id) {
+                Ok(MetadataLocation {
+                    account_id: mailbox.account_id,
+                    document_id: mailbox.mailbox_id,
+                    may_read: true,
+                    may_write_private: true,
+                    may_write_shared: true,
+                })
+            } else {
+                let acl = self
+                    .jmap
+                    .get_property::<Object<Value>>(
+                        mailbox.account_id,
+                        Collection::Mailbox,
+                        mailbox.mailbox_id,
+                        Property::Value,
+                    )
+                    .await?
+                    .ok_or_else(|| StatusResponse::no("Mailbox no longer exists."))?
+                    .effective_acl(access_token);
+                let may_read = acl.contains(Acl::Read) && acl.contains(Acl::ReadItems);
+
+                Ok(MetadataLocation {
+                    account_id: mailbox.account_id,
+                    document_id: mailbox.mailbox_id,
+                    may_read,
+                    may_write_private: may_read,
+                    may_write_shared: may_read && acl.contains(Acl::ModifyItems),
+                })
+            }
+        } else {
+            Err(StatusResponse::no("Mailbox does not exist.").with_code(ResponseCode::NonExistent))
+        }
+    }
+}
+
+impl Serialize for Metadata {
+    fn serialize(self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(
+            self.entries
+                .iter()
+                .map(|entry| entry.name.len() + entry.value.len() + 8)
+                .sum(),
+        );
+        buf.push_leb128(self.entries.len());
+        for entry in &self.entries {
+            // Shared entries are stored with an account id of zero
+            buf.push_leb128(entry.account_id.map_or(0, |account_id| account_id + 1));
+            entry.name.serialize_into(&mut buf);
+            entry.value.serialize_into(&mut buf);
+        }
+        buf
+    }
+}
+
+impl Deserialize for Metadata {
+    fn deserialize(bytes: &[u8]) -> store::Result<Self> {
+        fn deserialize_entries(bytes: &mut Iter<'_, u8>) -> Option<Vec<MetadataEntry>> {
+            let len: usize = bytes.next_leb128()?;
+            let mut entries = Vec::with_capacity(len);
+            for _ in 0..len {
+                let account_id: u32 = bytes.next_leb128()?;
+                entries.push(MetadataEntry {
+                    account_id: account_id.checked_sub(1),
+                    name: String::deserialize_from(bytes)?,
+                    value: <Vec<u8>>::deserialize_from(bytes)?,
+                });
+            }
+            Some(entries)
+        }
+
+        deserialize_entries(&mut bytes.iter())
+            .map(|entries| Metadata { entries })
+            .ok_or_else(|| store::Error::InternalError("Failed to deserialize metadata".into()))
+    }
+}
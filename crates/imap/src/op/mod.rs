@@ -29,6 +29,7 @@ pub mod append;
 pub mod authenticate;
 pub mod capability;
 pub mod close;
+pub mod compress;
 pub mod copy_move;
 pub mod create;
 pub mod delete;
@@ -39,8 +40,11 @@ pub mod idle;
 pub mod list;
 pub mod login;
 pub mod logout;
+pub mod metadata;
 pub mod namespace;
 pub mod noop;
+pub mod notify;
+pub mod quota;
 pub mod rename;
 pub mod search;
 pub mod select;
@@ -0,0 +1,491 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::sync::Arc;
+
+use imap_proto::{
+    protocol::{
+        fetch,
+        list::{Attribute, ListItem},
+        notify::{Arguments, Event, EventGroup, Filter},
+        status::Status,
+        Sequence,
+    },
+    receiver::Request,
+    Command, ResponseCode, StatusResponse,
+};
+use jmap_proto::types::{state::StateChange, type_state::DataType};
+use utils::{listener::SessionStream, map::bitmap::Bitmap};
+
+use crate::core::{NotifySubscription, SelectedMailbox, Session, SessionData, State};
+
+const STATUS_ITEMS: &[Status] = &[
+    Status::Messages,
+    Status::UidNext,
+    Status::UidValidity,
+    Status::Unseen,
+];
+
+impl<T: SessionStream> Session<T> {
+    pub async fn handle_notify(&mut self, request: Request<Command>) -> crate::OpResult {
+        let arguments = match request.parse_notify(self.version) {
+            Ok(arguments) => arguments,
+            Err(response) => return self.write_bytes(response.into_bytes()).await,
+        };
+
+        // Annotation events are not supported
+        if arguments.groups.iter().any(|group| {
+            group.events.iter().any(|event| {
+                matches!(
+                    event,
+                    Event::AnnotationChange
+                        | Event::MailboxMetadataChange
+                        | Event::ServerMetadataChange
+                )
+            })
+        }) {
+            return self
+                .write_bytes(
+                    StatusResponse::no("Unsupported notification event.")
+                        .with_tag(arguments.tag)
+                        .with_code(ResponseCode::BadEvent(vec![
+                            Event::MessageNew { attributes: vec![] },
+                            Event::MessageExpunge,
+                            Event::FlagChange,
+                            Event::MailboxName,
+                            Event::SubscriptionChange,
+                        ]))
+                        .into_bytes(),
+                )
+                .await;
+        }
+
+        if arguments.groups.is_empty() {
+            self.notify = None;
+            return self
+                .write_bytes(
+                    StatusResponse::completed(Command::Notify)
+                        .with_tag(arguments.tag)
+                        .into_bytes(),
+                )
+                .await;
+        }
+
+        // Refresh the mailboxes shared with the user before subscribing, the
+        // state manager reports changes in every account the user has access to.
+        let (data, mailbox) = self.state.session_mailbox_state();
+        if let Err(response) = data.synchronize_mailboxes(false).await {
+            return self
+                .write_bytes(response.with_tag(arguments.tag).into_bytes())
+                .await;
+        }
+
+        // Register with state manager
+        let change_rx = if let Some(change_rx) = self
+            .jmap
+            .subscribe_state_manager(
+                data.account_id,
+                data.account_id,
+                Bitmap::from_iter([DataType::Email, DataType::Mailbox, DataType::EmailDelivery]),
+            )
+            .await
+        {
+            change_rx
+        } else {
+            return self
+                .write_bytes(
+                    StatusResponse::no("It was not possible to enable notifications.")
+                        .with_tag(arguments.tag)
+                        .with_code(ResponseCode::ContactAdmin)
+                        .into_bytes(),
+                )
+                .await;
+        };
+        let Arguments {
+            tag,
+            status,
+            groups,
+        } = arguments;
+        self.notify = NotifySubscription {
+            change_rx,
+            groups,
+            pending_selected: false,
+        }
+        .into();
+
+        // Send the status of all monitored mailboxes
+        let mut buf = Vec::new();
+        if status {
+            let groups = &self.notify.as_ref().unwrap().groups;
+            let is_rev2 = self.version.is_rev2();
+            for (mailbox_name, is_subscribed) in data.notify_mailboxes(&mailbox) {
+                if data
+                    .notify_events(groups, &mailbox_name, is_subscribed)
+                    .iter()
+                    .any(|event| event.is_message_event())
+                {
+                    if let Ok(status) = data.status(mailbox_name, STATUS_ITEMS).await {
+                        status.serialize(&mut buf, is_rev2);
+                    }
+                }
+            }
+        }
+
+        self.write_bytes(
+            StatusResponse::completed(Command::Notify)
+                .with_tag(tag)
+                .serialize(buf),
+        )
+        .await
+    }
+
+    pub async fn handle_notify_change(
+        &mut self,
+        state_change: Option<StateChange>,
+    ) -> crate::OpResult {
+        if let Some(state_change) = state_change {
+            let is_busy = self.is_command_in_flight();
+            let (data, mailbox) = self.state.session_mailbox_state();
+            if let Some(notify) = &mut self.notify {
+                let (has_email_changes, has_mailbox_changes) = has_changes(&state_change);
+
+                // Updates to the selected mailbox are not sent while a command is
+                // running, as they could change sequence numbers it is using.
+                if let (Some(mailbox), true) = (&mailbox, has_email_changes) {
+                    notify.pending_selected = is_busy;
+                    if !is_busy {
+                        data.write_selected_notifications(
+                            &notify.groups,
+                            mailbox,
+                            false,
+                            self.is_qresync,
+                            self.version.is_rev2(),
+                        )
+                        .await;
+                    }
+                }
+                data.write_mailbox_notifications(
+                    &notify.groups,
+                    &mailbox,
+                    has_email_changes,
+                    has_mailbox_changes,
+                    self.version.is_rev2(),
+                )
+                .await;
+            }
+            Ok(())
+        } else {
+            // The subscription was dropped by the state manager
+            self.notify = None;
+            self.write_bytes(
+                StatusResponse::ok("Notifications are no longer available.")
+                    .with_code(ResponseCode::NotificationOverflow)
+                    .into_bytes(),
+            )
+            .await
+        }
+    }
+
+    pub async fn write_pending_notifications(&mut self) -> crate::OpResult {
+        if self.is_command_in_flight() {
+            return Ok(());
+        }
+        if let Some(notify) = &mut self.notify {
+            notify.pending_selected = false;
+            if let (data, Some(mailbox)) = self.state.session_mailbox_state() {
+                data.write_selected_notifications(
+                    &notify.groups,
+                    &mailbox,
+                    false,
+                    self.is_qresync,
+                    self.version.is_rev2(),
+                )
+                .await;
+            }
+        }
+        Ok(())
+    }
+
+    fn is_command_in_flight(&self) -> bool {
+        // Commands running in the background hold a reference to the session data
+        match &self.state {
+            State::Authenticated { data } | State::Selected { data, .. } => {
+                Arc::strong_count(data) > 1
+            }
+            State::NotAuthenticated { .. } => false,
+        }
+    }
+}
+
+impl<T: SessionStream> SessionData<T> {
+    pub async fn write_notifications(
+        &self,
+        groups: &[EventGroup],
+        mailbox: &Option<Arc<SelectedMailbox>>,
+        state_change: StateChange,
+        is_idle: bool,
+        is_qresync: bool,
+        is_rev2: bool,
+    ) {
+        let (has_email_changes, has_mailbox_changes) = has_changes(&state_change);
+        if let (Some(mailbox), true) = (mailbox, has_email_changes) {
+            self.write_selected_notifications(groups, mailbox, is_idle, is_qresync, is_rev2)
+                .await;
+        }
+        self.write_mailbox_notifications(
+            groups,
+            mailbox,
+            has_email_changes,
+            has_mailbox_changes,
+            is_rev2,
+        )
+        .await;
+    }
+
+    // Notify changes in the selected mailbox. Without a SELECTED filter, the
+    // selected mailbox is only updated while idling, as in the base protocol.
+    // Changes in SELECTED-DELAYED mailboxes are sent on the next command.
+    async fn write_selected_notifications(
+        &self,
+        groups: &[EventGroup],
+        mailbox: &Arc<SelectedMailbox>,
+        is_idle: bool,
+        is_qresync: bool,
+        is_rev2: bool,
+    ) {
+        let selected = groups.iter().find(|group| group.filter.is_selected());
+        if selected.map_or(is_idle, |group| {
+            !group.events.is_empty() && (is_idle || group.filter == Filter::Selected)
+        }) {
+            let uid_max = mailbox.state.lock().uid_max;
+            self.write_changes(&Some(mailbox.clone()), false, true, is_qresync, is_rev2)
+                .await;
+
+            // Fetch the requested attributes of new messages
+            let new_uid_max = mailbox.state.lock().uid_max;
+            if let Some(attributes) = selected
+                .and_then(|group| {
+                    group.events.iter().find_map(|event| match event {
+                        Event::MessageNew { attributes } if !attributes.is_empty() => {
+                            Some(attributes)
+                        }
+                        _ => None,
+                    })
+                })
+                .filter(|_| new_uid_max > uid_max)
+            {
+                self.fetch(
+                    fetch::Arguments {
+                        tag: String::new(),
+                        sequence_set: Sequence::Range {
+                            start: (uid_max + 1).into(),
+                            end: new_uid_max.into(),
+                        },
+                        attributes: attributes.clone(),
+                        changed_since: None,
+                        include_vanished: false,
+                    },
+                    mailbox.clone(),
+                    true,
+                    is_qresync,
+                    is_rev2,
+                    false,
+                )
+                .await;
+            }
+        }
+    }
+
+    // Notify changes in other mailboxes
+    async fn write_mailbox_notifications(
+        &self,
+        groups: &[EventGroup],
+        mailbox: &Option<Arc<SelectedMailbox>>,
+        has_email_changes: bool,
+        has_mailbox_changes: bool,
+        is_rev2: bool,
+    ) {
+        if !has_email_changes && !has_mailbox_changes {
+            return;
+        }
+        let changes = match self.synchronize_mailboxes(true).await {
+            Ok(Some(changes)) => changes,
+            Ok(None) => return,
+            Err(_) => {
+                tracing::debug!(parent: &self.span, "Failed to refresh mailboxes.");
+                return;
+            }
+        };
+        let selected_name = mailbox
+            .as_ref()
+            .and_then(|mailbox| self.mailbox_name(mailbox));
+        let mut buf = Vec::with_capacity(64);
+
+        // List deleted mailboxes
+        for mailbox_name in changes.deleted {
+            if self
+                .notify_events(groups, &mailbox_name, false)
+                .contains(&Event::MailboxName)
+            {
+                ListItem {
+                    mailbox_name,
+                    attributes: vec![Attribute::NonExistent],
+                    tags: vec![],
+                }
+                .serialize(&mut buf, is_rev2, false);
+            }
+        }
+
+        // List added mailboxes and subscription changes, mailboxes that were
+        // just shared with the user also get their status reported.
+        let mut changed = changes.changed;
+        for (mailbox_name, event) in changes
+            .added
+            .into_iter()
+            .map(|name| (name, Event::MailboxName))
+            .chain(
+                changes
+                    .subscription_changes
+                    .into_iter()
+                    .map(|name| (name, Event::SubscriptionChange)),
+            )
+        {
+            let is_subscribed = self.is_subscribed(&mailbox_name);
+            let events = self.notify_events(groups, &mailbox_name, is_subscribed);
+            if event == Event::MailboxName
+                && events.iter().any(|event| event.is_message_event())
+                && !changed.contains(&mailbox_name)
+            {
+                changed.push(mailbox_name.clone());
+            }
+            if events.contains(&event) {
+                ListItem {
+                    mailbox_name,
+                    attributes: if is_subscribed {
+                        vec![Attribute::Subscribed]
+                    } else {
+                        vec![]
+                    },
+                    tags: vec![],
+                }
+                .serialize(&mut buf, is_rev2, false);
+            }
+        }
+
+        // Obtain status of changed mailboxes
+        for mailbox_name in changed {
+            if selected_name.as_ref() != Some(&mailbox_name)
+                && self
+                    .notify_events(groups, &mailbox_name, self.is_subscribed(&mailbox_name))
+                    .iter()
+                    .any(|event| event.is_message_event())
+            {
+                if let Ok(status) = self.status(mailbox_name, STATUS_ITEMS).await {
+                    status.serialize(&mut buf, is_rev2);
+                }
+            }
+        }
+
+        if !buf.is_empty() {
+            self.write_bytes(buf).await;
+        }
+    }
+
+    // Events of the first filter matching the mailbox, the selected
+    // mailbox is handled separately.
+    fn notify_events<'x>(
+        &self,
+        groups: &'x [EventGroup],
+        mailbox_name: &str,
+        is_subscribed: bool,
+    ) -> &'x [Event] {
+        groups
+            .iter()
+            .find(|group| {
+                group
+                    .filter
+                    .matches(mailbox_name, is_subscribed, &self.imap.name_shared)
+            })
+            .map(|group| group.events.as_slice())
+            .unwrap_or_default()
+    }
+
+    // Names and subscription status of all mailboxes except the selected one
+    fn notify_mailboxes(&self, selected: &Option<Arc<SelectedMailbox>>) -> Vec<(String, bool)> {
+        let mut mailboxes = Vec::new();
+        for account in self.mailboxes.lock().iter() {
+            for (mailbox_name, mailbox_id) in &account.mailbox_names {
+                if !selected.as_ref().is_some_and(|selected| {
+                    selected.id.account_id == account.account_id
+                        && selected.id.mailbox_id == *mailbox_id
+                }) {
+                    mailboxes.push((
+                        mailbox_name.to_string(),
+                        account
+                            .mailbox_state
+                            .get(mailbox_id)
+                            .is_some_and(|mailbox| mailbox.is_subscribed),
+                    ));
+                }
+            }
+        }
+        mailboxes
+    }
+
+    fn mailbox_name(&self, mailbox: &SelectedMailbox) -> Option<String> {
+        self.mailboxes
+            .lock()
+            .iter()
+            .filter(|account| account.account_id == mailbox.id.account_id)
+            .flat_map(|account| account.mailbox_names.iter())
+            .find(|(_, mailbox_id)| **mailbox_id == mailbox.id.mailbox_id)
+            .map(|(mailbox_name, _)| mailbox_name.to_string())
+    }
+
+    fn is_subscribed(&self, mailbox_name: &str) -> bool {
+        self.mailboxes.lock().iter().any(|account| {
+            account
+                .mailbox_names
+                .get(mailbox_name)
+                .and_then(|mailbox_id| account.mailbox_state.get(mailbox_id))
+                .is_some_and(|mailbox| mailbox.is_subscribed)
+        })
+    }
+}
+
+fn has_changes(state_change: &StateChange) -> (bool, bool) {
+    let mut has_email_changes = false;
+    let mut has_mailbox_changes = false;
+    for (type_state, _) in &state_change.types {
+        match type_state {
+            DataType::Email | DataType::EmailDelivery => {
+                has_email_changes = true;
+            }
+            DataType::Mailbox => {
+                has_mailbox_changes = true;
+            }
+            _ => {}
+        }
+    }
+    (has_email_changes, has_mailbox_changes)
+}
@@ -0,0 +1,173 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use imap_proto::{
+    protocol::quota::{
+        QuotaItem, QuotaResource, QuotaResourceItem, QuotaResponse, QuotaRootResponse,
+    },
+    receiver::Request,
+    Command, ResponseCode, StatusResponse,
+};
+use jmap_proto::types::collection::Collection;
+use utils::listener::SessionStream;
+
+use crate::core::{Session, SessionData};
+
+impl<T: SessionStream> Session<T> {
+    pub async fn handle_get_quota(&mut self, request: Request<Command>) -> crate::OpResult {
+        match request.parse_quota(self.version) {
+            Ok(arguments) => {
+                let data = self.state.session_data();
+                let is_rev2 = self.version.is_rev2();
+
+                tokio::spawn(async move {
+                    // Quota roots are named after the account prefix
+                    let account_id = data.mailboxes.lock().iter().find_map(|account| {
+                        if account.prefix.as_deref().unwrap_or_default() == arguments.name {
+                            Some(account.account_id)
+                        } else {
+                            None
+                        }
+                    });
+                    let result = match account_id {
+                        Some(account_id) => data.get_quota(account_id, arguments.name).await,
+                        None => Ok(None),
+                    };
+
+                    data.write_bytes(match result {
+                        Ok(Some(quota)) => StatusResponse::completed(Command::GetQuota)
+                            .with_tag(arguments.tag)
+                            .serialize(QuotaResponse { quota }.into_bytes(is_rev2)),
+                        Ok(None) => StatusResponse::no("Quota root does not exist.")
+                            .with_code(ResponseCode::NonExistent)
+                            .with_tag(arguments.tag)
+                            .into_bytes(),
+                        Err(response) => response.with_tag(arguments.tag).into_bytes(),
+                    })
+                    .await;
+                });
+                Ok(())
+            }
+            Err(response) => self.write_bytes(response.into_bytes()).await,
+        }
+    }
+
+    pub async fn handle_get_quota_root(&mut self, request: Request<Command>) -> crate::OpResult {
+        match request.parse_quota(self.version) {
+            Ok(arguments) => {
+                let data = self.state.session_data();
+                let is_rev2 = self.version.is_rev2();
+
+                tokio::spawn(async move {
+                    let result = if let Some(mailbox) = data.get_mailbox_by_name(&arguments.name) {
+                        let root = data
+                            .mailboxes
+                            .lock()
+                            .iter()
+                            .find(|account| account.account_id == mailbox.account_id)
+                            .and_then(|account| account.prefix.clone())
+                            .unwrap_or_default();
+                        data.get_quota(mailbox.account_id, root).await
+                    } else {
+                        Err(StatusResponse::no("Mailbox does not exist.")
+                            .with_code(ResponseCode::NonExistent))
+                    };
+
+                    data.write_bytes(match result {
+                        Ok(quota) => StatusResponse::completed(Command::GetQuotaRoot)
+                            .with_tag(arguments.tag)
+                            .serialize(
+                                QuotaRootResponse {
+                                    mailbox_name: arguments.name,
+                                    quotas: quota.into_iter().collect(),
+                                }
+                                .into_bytes(is_rev2),
+                            ),
+                        Err(response) => response.with_tag(arguments.tag).into_bytes(),
+                    })
+                    .await;
+                });
+                Ok(())
+            }
+            Err(response) => self.write_bytes(response.into_bytes()).await,
+        }
+    }
+
+    pub async fn handle_set_quota(&mut self, request: Request<Command>) -> crate::OpResult {
+        match request.parse_quota(self.version) {
+            Ok(arguments) => {
+                self.write_bytes(
+                    StatusResponse::no("Quota limits can only be changed by an administrator.")
+                        .with_code(ResponseCode::NoPerm)
+                        .with_tag(arguments.tag)
+                        .into_bytes(),
+                )
+                .await
+            }
+            Err(response) => self.write_bytes(response.into_bytes()).await,
+        }
+    }
+}
+
+impl<T: SessionStream> SessionData<T> {
+    pub async fn get_quota(
+        &self,
+        account_id: u32,
+        root: String,
+    ) -> crate::op::Result<Option<QuotaItem>> {
+        let access_token = self.get_access_token().await?;
+        let mut resources = Vec::with_capacity(2);
+
+        // Storage is reported in units of 1024 octets
+        let quota = self.jmap.get_quota(&access_token, account_id).await? as u64;
+        if quota > 0 {
+            let used = self.jmap.get_used_quota(account_id).await?.max(0) as u64;
+            resources.push(QuotaResourceItem {
+                resource: QuotaResource::Storage,
+                usage: used.div_ceil(1024),
+                limit: quota / 1024,
+            });
+        }
+
+        let max_messages = self.jmap.config.load().mail_max_messages;
+        if max_messages > 0 {
+            resources.push(QuotaResourceItem {
+                resource: QuotaResource::Message,
+                usage: self
+                    .jmap
+                    .get_document_ids(account_id, Collection::Email)
+                    .await?
+                    .map_or(0, |ids| ids.len()),
+                limit: max_messages,
+            });
+        }
+
+        // Accounts without any limit have no quota root, GETQUOTAROOT does not
+        // list it and GETQUOTA reports it as nonexistent.
+        Ok(if !resources.is_empty() {
+            Some(QuotaItem { root, resources })
+        } else {
+            None
+        })
+    }
+}
@@ -285,7 +285,7 @@ impl<T: SessionStream> SessionData<T> {
                                 fts_filters.push(FtsFilter::has_text_detect(
                                     Field::Body,
                                     text,
-                                    self.jmap.config.default_language,
+                                    self.jmap.config.load().default_language,
                                 ));
                             }
                             search::Filter::Cc(text) => {
@@ -343,7 +343,7 @@ impl<T: SessionStream> SessionData<T> {
                                 fts_filters.push(FtsFilter::has_text_detect(
                                     Field::Header(HeaderName::Subject),
                                     text,
-                                    self.jmap.config.default_language,
+                                    self.jmap.config.load().default_language,
                                 ));
                             }
                             search::Filter::Text(text) => {
@@ -371,17 +371,17 @@ impl<T: SessionStream> SessionData<T> {
                                 fts_filters.push(FtsFilter::has_text_detect(
                                     Field::Header(HeaderName::Subject),
                                     &text,
-                                    self.jmap.config.default_language,
+                                    self.jmap.config.load().default_language,
                                 ));
                                 fts_filters.push(FtsFilter::has_text_detect(
                                     Field::Body,
                                     &text,
-                                    self.jmap.config.default_language,
+                                    self.jmap.config.load().default_language,
                                 ));
                                 fts_filters.push(FtsFilter::has_text_detect(
                                     Field::Attachment,
                                     text,
-                                    self.jmap.config.default_language,
+                                    self.jmap.config.load().default_language,
                                 ));
                                 fts_filters.push(FtsFilter::End);
                             }
@@ -113,6 +113,7 @@ impl<T: SessionStream> SessionData<T> {
                                     | Status::Unseen
                                     | Status::Recent
                                     | Status::Deleted
+                                    | Status::DeletedStorage
                                     | Status::HighestModSeq => StatusItemType::Number(0),
                                     Status::UidNext | Status::UidValidity => {
                                         StatusItemType::Number(1)
@@ -177,9 +178,16 @@ impl<T: SessionStream> SessionData<T> {
                                 items_update.push_unique(*item);
                             }
                         }
+                        Status::DeletedStorage => {
+                            if let Some(value) = mailbox_state.total_deleted_storage {
+                                items_response.push((*item, StatusItemType::Number(value)));
+                            } else {
+                                items_update.push_unique(*item);
+                            }
+                        }
                         Status::Size => {
                             if let Some(value) = mailbox_state.size {
-                                items_response.push((*item, StatusItemType::Number(value as u64)));
+                                items_response.push((*item, StatusItemType::Number(value)));
                             } else {
                                 items_update.push_unique(*item);
                             }
@@ -310,10 +318,31 @@ impl<T: SessionStream> SessionData<T> {
                             0
                         }
                     }
+                    Status::DeletedStorage => {
+                        if let (Some(mailbox_message_ids), Some(mut deleted)) = (
+                            &mailbox_message_ids,
+                            self.jmap
+                                .get_tag(
+                                    mailbox.account_id,
+                                    Collection::Email,
+                                    Property::Keywords,
+                                    Keyword::Deleted,
+                                )
+                                .await?,
+                        ) {
+                            deleted &= mailbox_message_ids.as_ref();
+                            // Reported in units of 1024 octets, same as the STORAGE quota resource
+                            self.calculate_mailbox_size(mailbox.account_id, &deleted)
+                                .await?
+                                .div_ceil(1024)
+                        } else {
+                            0
+                        }
+                    }
                     Status::Size => {
                         if let Some(mailbox_message_ids) = &mailbox_message_ids {
                             self.calculate_mailbox_size(mailbox.account_id, mailbox_message_ids)
-                                .await? as u64
+                                .await?
                         } else {
                             0
                         }
@@ -328,7 +357,7 @@ impl<T: SessionStream> SessionData<T> {
                 };
 
                 items_response.push((item, StatusItemType::Number(result)));
-                values_update.push((item, result as u32));
+                values_update.push((item, result));
             }
 
             // Update cache
@@ -341,11 +370,18 @@ impl<T: SessionStream> SessionData<T> {
 
                     for (item, value) in values_update {
                         match item {
-                            Status::Messages => mailbox_state.total_messages = value.into(),
-                            Status::UidNext => mailbox_state.uid_next = value.into(),
-                            Status::UidValidity => mailbox_state.uid_validity = value.into(),
-                            Status::Unseen => mailbox_state.total_unseen = value.into(),
-                            Status::Deleted => mailbox_state.total_deleted = value.into(),
+                            Status::Messages => {
+                                mailbox_state.total_messages = (value as u32).into()
+                            }
+                            Status::UidNext => mailbox_state.uid_next = (value as u32).into(),
+                            Status::UidValidity => {
+                                mailbox_state.uid_validity = (value as u32).into()
+                            }
+                            Status::Unseen => mailbox_state.total_unseen = (value as u32).into(),
+                            Status::Deleted => mailbox_state.total_deleted = (value as u32).into(),
+                            Status::DeletedStorage => {
+                                mailbox_state.total_deleted_storage = value.into()
+                            }
                             Status::Size => mailbox_state.size = value.into(),
                             Status::Recent => {
                                 items_response
@@ -376,9 +412,9 @@ impl<T: SessionStream> SessionData<T> {
     async fn calculate_mailbox_size(
         &self,
         account_id: u32,
-        message_ids: &Arc<RoaringBitmap>,
-    ) -> super::Result<u32> {
-        let mut total_size = 0u32;
+        message_ids: &RoaringBitmap,
+    ) -> super::Result<u64> {
+        let mut total_size = 0u64;
         self.jmap
             .store
             .iterate(
@@ -407,7 +443,7 @@ impl<T: SessionStream> SessionData<T> {
                             })
                             .and_then(u32::deserialize)
                             .map(|size| {
-                                total_size += size;
+                                total_size += size as u64;
                             })?;
                     }
                     Ok(true)
@@ -33,7 +33,10 @@ use imap_proto::{
     receiver::Request,
     Command, ResponseCode, ResponseType, StatusResponse,
 };
-use jmap::{email::set::TagManager, mailbox::UidMailbox};
+use jmap::{
+    email::{bayes::SpamTraining, set::TagManager},
+    mailbox::UidMailbox,
+};
 use jmap_proto::{
     error::method::MethodError,
     types::{
@@ -260,6 +263,9 @@ impl<T: SessionStream> SessionData<T> {
                         vec![]
                     };
 
+                    // Check for Junk/Not Junk actions
+                    let spam_training = SpamTraining::from_changes(None, Some(&keywords));
+
                     // Write changes
                     let mut batch = BatchBuilder::new();
                     batch
@@ -277,6 +283,13 @@ impl<T: SessionStream> SessionData<T> {
                     batch.value(Property::Cid, changelog.change_id, F_VALUE);
                     match self.jmap.write_batch(batch).await {
                         Ok(_) => {
+                            // Train spam classifier
+                            if let Some(spam_training) = spam_training {
+                                self.jmap
+                                    .schedule_spam_training(account_id, id, spam_training)
+                                    .await;
+                            }
+
                             // Set all current mailboxes as changed if the Seen tag changed
                             if seen_changed {
                                 if let Some(mailboxes) = self
@@ -123,3 +123,59 @@ impl JsonObjectParser for ParseEmailRequest {
         Ok(request)
     }
 }
+
+#[derive(Debug, Clone)]
+pub struct ParseMdnRequest {
+    pub account_id: Id,
+    pub blob_ids: Vec<BlobId>,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct ParseMdnResponse {
+    #[serde(rename = "accountId")]
+    pub account_id: Id,
+
+    #[serde(rename = "parsed")]
+    #[serde(skip_serializing_if = "VecMap::is_empty")]
+    pub parsed: VecMap<BlobId, Object<Value>>,
+
+    #[serde(rename = "notParsable")]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub not_parsable: Vec<BlobId>,
+
+    #[serde(rename = "notFound")]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub not_found: Vec<BlobId>,
+}
+
+impl JsonObjectParser for ParseMdnRequest {
+    fn parse(parser: &mut Parser<'_>) -> crate::parser::Result<Self>
+    where
+        Self: Sized,
+    {
+        let mut request = ParseMdnRequest {
+            account_id: Id::default(),
+            blob_ids: vec![],
+        };
+
+        parser
+            .next_token::<String>()?
+            .assert_jmap(Token::DictStart)?;
+
+        while let Some(key) = parser.next_dict_key::<RequestProperty>()? {
+            match (&key.hash[0], &key.hash[1]) {
+                (0x0064_4974_6e75_6f63_6361, _) if !key.is_ref => {
+                    request.account_id = parser.next_token::<Id>()?.unwrap_string("accountId")?;
+                }
+                (0x0073_6449_626f_6c62, _) => {
+                    request.blob_ids = <Vec<BlobId>>::parse(parser)?;
+                }
+                _ => {
+                    parser.skip_token(parser.depth_array, parser.depth_dict)?;
+                }
+            }
+        }
+
+        Ok(request)
+    }
+}
@@ -0,0 +1,99 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use utils::map::vec_map::VecMap;
+
+use crate::{
+    error::set::SetError,
+    object::{mdn::Mdn, Object},
+    parser::{json::Parser, JsonObjectParser, Token},
+    request::{reference::MaybeReference, RequestProperty},
+    types::{
+        id::Id,
+        value::{SetValue, Value},
+    },
+};
+
+#[derive(Debug, Clone)]
+pub struct SendMdnRequest {
+    pub account_id: Id,
+    pub identity_id: Id,
+    pub send: VecMap<String, Mdn>,
+    pub on_success_update_email: Option<VecMap<MaybeReference<Id, String>, Object<SetValue>>>,
+}
+
+#[derive(Debug, Clone, Default, serde::Serialize)]
+pub struct SendMdnResponse {
+    #[serde(rename = "accountId")]
+    pub account_id: Id,
+
+    #[serde(rename = "sent")]
+    #[serde(skip_serializing_if = "VecMap::is_empty")]
+    pub sent: VecMap<String, Object<Value>>,
+
+    #[serde(rename = "notSent")]
+    #[serde(skip_serializing_if = "VecMap::is_empty")]
+    pub not_sent: VecMap<String, SetError>,
+}
+
+impl JsonObjectParser for SendMdnRequest {
+    fn parse(parser: &mut Parser<'_>) -> crate::parser::Result<Self>
+    where
+        Self: Sized,
+    {
+        let mut request = SendMdnRequest {
+            account_id: Id::default(),
+            identity_id: Id::default(),
+            send: VecMap::new(),
+            on_success_update_email: None,
+        };
+
+        parser
+            .next_token::<String>()?
+            .assert_jmap(Token::DictStart)?;
+
+        while let Some(key) = parser.next_dict_key::<RequestProperty>()? {
+            match (&key.hash[0], &key.hash[1]) {
+                (0x0064_4974_6e75_6f63_6361, _) if !key.is_ref => {
+                    request.account_id = parser.next_token::<Id>()?.unwrap_string("accountId")?;
+                }
+                (0x6449_7974_6974_6e65_6469, _) if !key.is_ref => {
+                    request.identity_id = parser.next_token::<Id>()?.unwrap_string("identityId")?;
+                }
+                (0x646e_6573, _) if !key.is_ref => {
+                    request.send = <VecMap<String, Mdn>>::parse(parser)?;
+                }
+                (0x4565_7461_6470_5573_7365_6363_7553_6e6f, 0x6c69_616d) if !key.is_ref => {
+                    request.on_success_update_email = <Option<
+                        VecMap<MaybeReference<Id, String>, Object<SetValue>>,
+                    >>::parse(parser)?;
+                }
+                _ => {
+                    parser.skip_token(parser.depth_array, parser.depth_dict)?;
+                }
+            }
+        }
+
+        Ok(request)
+    }
+}
@@ -29,7 +29,7 @@ use crate::{
         method::MethodError,
         set::{InvalidProperty, SetError},
     },
-    object::{email_submission, mailbox, sieve, Object},
+    object::{calendar, contact, email_submission, mailbox, sieve, Object},
     parser::{json::Parser, Error, JsonObjectParser, Token},
     request::{
         method::MethodObject,
@@ -71,6 +71,10 @@ pub enum RequestArguments {
     PushSubscription,
     SieveScript(sieve::SetArguments),
     VacationResponse,
+    AddressBook(contact::SetArguments),
+    ContactCard,
+    Calendar(calendar::SetArguments),
+    CalendarEvent(calendar::EventSetArguments),
 }
 
 #[derive(Debug, Clone, Default, serde::Serialize)]
@@ -131,6 +135,10 @@ impl JsonObjectParser for SetRequest<RequestArguments> {
                 MethodObject::PushSubscription => RequestArguments::PushSubscription,
                 MethodObject::VacationResponse => RequestArguments::VacationResponse,
                 MethodObject::SieveScript => RequestArguments::SieveScript(Default::default()),
+                MethodObject::AddressBook => RequestArguments::AddressBook(Default::default()),
+                MethodObject::ContactCard => RequestArguments::ContactCard,
+                MethodObject::Calendar => RequestArguments::Calendar(Default::default()),
+                MethodObject::CalendarEvent => RequestArguments::CalendarEvent(Default::default()),
                 _ => {
                     return Err(Error::Method(MethodError::UnknownMethod(format!(
                         "{}/set",
@@ -200,6 +208,26 @@ impl JsonObjectParser for Object<SetValue> {
         while let Some(mut key) = parser.next_dict_key::<SetProperty>()? {
             let value = if !key.is_ref {
                 match &key.property {
+                    property
+                        if parser.ctx == MethodObject::ContactCard
+                            && !matches!(property, Property::Id | Property::AddressBookIds) =>
+                    {
+                        // JSContact cards are stored as-is
+                        SetValue::Value(Value::parse::<ObjectProperty, String>(
+                            parser.next_token()?,
+                            parser,
+                        )?)
+                    }
+                    property
+                        if parser.ctx == MethodObject::CalendarEvent
+                            && !matches!(property, Property::Id | Property::CalendarIds) =>
+                    {
+                        // JSCalendar events are stored as-is
+                        SetValue::Value(Value::parse::<ObjectProperty, String>(
+                            parser.next_token()?,
+                            parser,
+                        )?)
+                    }
                     Property::Id | Property::ThreadId => parser
                         .next_token::<Id>()?
                         .unwrap_string_or_null("")?
@@ -238,6 +266,7 @@ impl JsonObjectParser for Object<SetValue> {
                     | Property::Location
                     | Property::Cid
                     | Property::Role
+                    | Property::Color
                     | Property::PartId => parser
                         .next_token::<String>()?
                         .unwrap_string_or_null("")?
@@ -260,7 +289,9 @@ impl JsonObjectParser for Object<SetValue> {
                     Property::HasAttachment
                     | Property::IsSubscribed
                     | Property::IsEnabled
-                    | Property::IsActive => parser
+                    | Property::IsActive
+                    | Property::IsDefault
+                    | Property::IsVisible => parser
                         .next_token::<String>()?
                         .unwrap_bool_or_null("")?
                         .map(|bool| SetValue::Value(Value::Bool(bool)))
@@ -275,7 +306,7 @@ impl JsonObjectParser for Object<SetValue> {
                         .unwrap_string_or_null("")?
                         .map(SetValue::from)
                         .unwrap_or(SetValue::Value(Value::Null)),
-                    Property::MailboxIds => {
+                    Property::MailboxIds | Property::AddressBookIds | Property::CalendarIds => {
                         if key.patch.is_empty() {
                             SetValue::from(
                                 <SetValueMap<MaybeReference<Id, String>>>::parse(parser)?.values,
@@ -411,6 +442,9 @@ impl RequestPropertyParser for RequestArguments {
             RequestArguments::Mailbox(args) => args.parse(parser, property),
             RequestArguments::EmailSubmission(args) => args.parse(parser, property),
             RequestArguments::SieveScript(args) => args.parse(parser, property),
+            RequestArguments::AddressBook(args) => args.parse(parser, property),
+            RequestArguments::Calendar(args) => args.parse(parser, property),
+            RequestArguments::CalendarEvent(args) => args.parse(parser, property),
             _ => Ok(false),
         }
     }
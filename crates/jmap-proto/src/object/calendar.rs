@@ -0,0 +1,75 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use crate::{
+    parser::{json::Parser, Ignore},
+    request::{RequestProperty, RequestPropertyParser},
+};
+
+#[derive(Debug, Clone, Default)]
+pub struct SetArguments {
+    pub on_destroy_remove_events: Option<bool>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct EventSetArguments {
+    pub send_scheduling_messages: Option<bool>,
+}
+
+impl RequestPropertyParser for SetArguments {
+    fn parse(
+        &mut self,
+        parser: &mut Parser,
+        property: RequestProperty,
+    ) -> crate::parser::Result<bool> {
+        if property.hash[0] == 0x4565_766f_6d65_5279_6f72_7473_6544_6e6f
+            && property.hash[1] == 0x0073_746e_6576
+        {
+            self.on_destroy_remove_events = parser
+                .next_token::<Ignore>()?
+                .unwrap_bool_or_null("onDestroyRemoveEvents")?;
+            Ok(true)
+        } else {
+            Ok(false)
+        }
+    }
+}
+
+impl RequestPropertyParser for EventSetArguments {
+    fn parse(
+        &mut self,
+        parser: &mut Parser,
+        property: RequestProperty,
+    ) -> crate::parser::Result<bool> {
+        if property.hash[0] == 0x654d_676e_696c_7564_6568_6353_646e_6573
+            && property.hash[1] == 0x7365_6761_7373
+        {
+            self.send_scheduling_messages = parser
+                .next_token::<Ignore>()?
+                .unwrap_bool_or_null("sendSchedulingMessages")?;
+            Ok(true)
+        } else {
+            Ok(false)
+        }
+    }
+}
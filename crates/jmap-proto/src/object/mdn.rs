@@ -0,0 +1,151 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use utils::map::vec_map::VecMap;
+
+use crate::{
+    parser::{json::Parser, Ignore, JsonObjectParser, Token},
+    types::{id::Id, property::Property},
+};
+
+#[derive(Debug, Clone, Default)]
+pub struct Mdn {
+    pub for_email_id: Option<Id>,
+    pub subject: Option<String>,
+    pub text_body: Option<String>,
+    pub include_original_message: bool,
+    pub reporting_ua: Option<String>,
+    pub disposition: Option<Disposition>,
+    pub final_recipient: Option<String>,
+    pub error: Option<Vec<String>>,
+    pub extension_fields: Option<VecMap<String, String>>,
+    pub invalid_properties: Vec<Property>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Disposition {
+    pub action_mode: String,
+    pub sending_mode: String,
+    pub type_: String,
+}
+
+impl JsonObjectParser for Mdn {
+    fn parse(parser: &mut Parser<'_>) -> crate::parser::Result<Self>
+    where
+        Self: Sized,
+    {
+        let mut mdn = Mdn::default();
+
+        parser
+            .next_token::<String>()?
+            .assert_jmap(Token::DictStart)?;
+
+        while let Some(key) = parser.next_dict_key::<String>()? {
+            match key.as_str() {
+                "forEmailId" => {
+                    mdn.for_email_id = parser
+                        .next_token::<Id>()?
+                        .unwrap_string_or_null("forEmailId")?;
+                }
+                "subject" => {
+                    mdn.subject = parser
+                        .next_token::<String>()?
+                        .unwrap_string_or_null("subject")?;
+                }
+                "textBody" => {
+                    mdn.text_body = parser
+                        .next_token::<String>()?
+                        .unwrap_string_or_null("textBody")?;
+                }
+                "includeOriginalMessage" => {
+                    mdn.include_original_message = parser
+                        .next_token::<Ignore>()?
+                        .unwrap_bool_or_null("includeOriginalMessage")?
+                        .unwrap_or_default();
+                }
+                "reportingUA" => {
+                    mdn.reporting_ua = parser
+                        .next_token::<String>()?
+                        .unwrap_string_or_null("reportingUA")?;
+                }
+                "disposition" => {
+                    mdn.disposition = <Option<Disposition>>::parse(parser)?;
+                }
+                "finalRecipient" => {
+                    mdn.final_recipient = parser
+                        .next_token::<String>()?
+                        .unwrap_string_or_null("finalRecipient")?;
+                }
+                "error" => {
+                    mdn.error = <Option<Vec<String>>>::parse(parser)?;
+                }
+                "extensionFields" => match parser.next_token::<Ignore>()? {
+                    Token::DictStart => {
+                        let mut fields = VecMap::new();
+                        while let Some(name) = parser.next_dict_key::<String>()? {
+                            let value = parser.next_token::<String>()?.unwrap_string(&name)?;
+                            fields.append(name, value);
+                        }
+                        mdn.extension_fields = fields.into();
+                    }
+                    Token::Null => (),
+                    token => return Err(token.error("extensionFields", "object or null")),
+                },
+                _ => {
+                    // Server-set or unknown properties
+                    mdn.invalid_properties.push(Property::parse(&key));
+                    parser.skip_token(parser.depth_array, parser.depth_dict)?;
+                }
+            }
+        }
+
+        Ok(mdn)
+    }
+}
+
+impl JsonObjectParser for Option<Disposition> {
+    fn parse(parser: &mut Parser<'_>) -> crate::parser::Result<Self>
+    where
+        Self: Sized,
+    {
+        match parser.next_token::<Ignore>()? {
+            Token::DictStart => {
+                let mut disposition = Disposition::default();
+
+                while let Some(key) = parser.next_dict_key::<String>()? {
+                    let value = parser.next_token::<String>()?.unwrap_string(&key)?;
+                    match key.as_str() {
+                        "actionMode" => disposition.action_mode = value,
+                        "sendingMode" => disposition.sending_mode = value,
+                        "type" => disposition.type_ = value,
+                        _ => (),
+                    }
+                }
+
+                Ok(Some(disposition))
+            }
+            Token::Null => Ok(None),
+            token => Err(token.error("disposition", "object or null")),
+        }
+    }
+}
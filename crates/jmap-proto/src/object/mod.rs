@@ -22,10 +22,13 @@
 */
 
 pub mod blob;
+pub mod calendar;
+pub mod contact;
 pub mod email;
 pub mod email_submission;
 pub mod index;
 pub mod mailbox;
+pub mod mdn;
 pub mod sieve;
 
 use std::slice::Iter;
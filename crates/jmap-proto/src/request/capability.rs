@@ -48,6 +48,8 @@ pub enum Capability {
     Blob = 1 << 8,
     #[serde(rename(serialize = "urn:ietf:params:jmap:quota"))]
     Quota = 1 << 9,
+    #[serde(rename(serialize = "urn:ietf:params:jmap:mdn"))]
+    Mdn = 1 << 10,
 }
 
 impl JsonObjectParser for Capability {
@@ -77,6 +79,7 @@ impl JsonObjectParser for Capability {
                 0x0065_7665_6973 => Ok(Capability::Sieve),
                 0x626f_6c62 => Ok(Capability::Blob),
                 0x0061_746f_7571 => Ok(Capability::Quota),
+                0x006e_646d => Ok(Capability::Mdn),
                 _ => Err(parser.error_capability()),
             },
             Err(Error::Method(_)) => Err(parser.error_capability()),
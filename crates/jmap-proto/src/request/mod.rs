@@ -41,10 +41,11 @@ use crate::{
         get::{self, GetRequest},
         import::ImportEmailRequest,
         lookup::BlobLookupRequest,
-        parse::ParseEmailRequest,
+        parse::{ParseEmailRequest, ParseMdnRequest},
         query::{self, QueryRequest},
         query_changes::QueryChangesRequest,
         search_snippet::GetSearchSnippetRequest,
+        send::SendMdnRequest,
         set::{self, SetRequest},
         upload::BlobUploadRequest,
         validate::ValidateSieveScriptRequest,
@@ -90,6 +91,8 @@ pub enum RequestMethod {
     ValidateScript(ValidateSieveScriptRequest),
     LookupBlob(BlobLookupRequest),
     UploadBlob(BlobUploadRequest),
+    SendMdn(SendMdnRequest),
+    ParseMdn(ParseMdnRequest),
     Echo(Echo),
     Error(MethodError),
 }
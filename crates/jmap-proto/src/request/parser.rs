@@ -34,10 +34,11 @@ use crate::{
         get::GetRequest,
         import::ImportEmailRequest,
         lookup::BlobLookupRequest,
-        parse::ParseEmailRequest,
+        parse::{ParseEmailRequest, ParseMdnRequest},
         query::QueryRequest,
         query_changes::QueryChangesRequest,
         search_snippet::GetSearchSnippetRequest,
+        send::SendMdnRequest,
         set::SetRequest,
         upload::BlobUploadRequest,
         validate::ValidateSieveScriptRequest,
@@ -143,6 +144,10 @@ impl Request {
                                 | MethodObject::SieveScript
                                 | MethodObject::Principal
                                 | MethodObject::Quota
+                                | MethodObject::AddressBook
+                                | MethodObject::ContactCard
+                                | MethodObject::Calendar
+                                | MethodObject::CalendarEvent
                                 | MethodObject::Blob,
                             ) => GetRequest::parse(parser).map(RequestMethod::Get),
                             (MethodFunction::Get, MethodObject::SearchSnippet) => {
@@ -183,6 +188,12 @@ impl Request {
                                 ValidateSieveScriptRequest::parse(parser)
                                     .map(RequestMethod::ValidateScript)
                             }
+                            (MethodFunction::Send, MethodObject::Mdn) => {
+                                SendMdnRequest::parse(parser).map(RequestMethod::SendMdn)
+                            }
+                            (MethodFunction::Parse, MethodObject::Mdn) => {
+                                ParseMdnRequest::parse(parser).map(RequestMethod::ParseMdn)
+                            }
                             (MethodFunction::Echo, MethodObject::Core) => {
                                 Echo::parse(parser).map(RequestMethod::Echo)
                             }
@@ -249,7 +260,10 @@ impl From<Error> for RequestError {
 
 #[cfg(test)]
 mod tests {
-    use crate::request::Request;
+    use crate::{
+        request::{capability::Capability, Request, RequestMethod},
+        types::property::Property,
+    };
 
     const TEST: &str = r#"
     {
@@ -364,9 +378,97 @@ mod tests {
       }
     "##;
 
+    const TEST3: &str = r##"
+    {
+        "using": [
+          "urn:ietf:params:jmap:core",
+          "urn:ietf:params:jmap:mail",
+          "urn:ietf:params:jmap:mdn"
+        ],
+        "methodCalls": [
+          [
+            "MDN/send",
+            {
+              "accountId": "c",
+              "identityId": "a",
+              "send": {
+                "k1546": {
+                  "forEmailId": "b",
+                  "subject": "Read receipt for: World domination",
+                  "textBody": "This receipt shows that the email has been displayed.",
+                  "reportingUA": "joes-pc.cs.example.com; Foomail 97.1",
+                  "includeOriginalMessage": true,
+                  "disposition": {
+                    "actionMode": "manual-action",
+                    "sendingMode": "mdn-sent-manually",
+                    "type": "displayed"
+                  },
+                  "extensionFields": {
+                    "X-EXTENSION-EXAMPLE": "example.com"
+                  },
+                  "originalMessageId": "<199509192301.23456@example.org>"
+                }
+              },
+              "onSuccessUpdateEmail": {
+                "#k1546": {
+                  "keywords/$mdnsent": true
+                }
+              }
+            },
+            "0"
+          ],
+          [
+            "MDN/parse",
+            {
+              "accountId": "c",
+              "blobIds": []
+            },
+            "1"
+          ]
+        ]
+      }
+    "##;
+
     #[test]
     fn parse_request() {
         println!("{:?}", Request::parse(TEST.as_bytes(), 10, 10240));
         println!("{:?}", Request::parse(TEST2.as_bytes(), 10, 10240));
     }
+
+    #[test]
+    fn parse_mdn_request() {
+        let request = Request::parse(TEST3.as_bytes(), 10, 10240).unwrap();
+        assert_eq!(
+            request.using,
+            Capability::Core as u32 | Capability::Mail as u32 | Capability::Mdn as u32
+        );
+
+        match &request.method_calls[0].method {
+            RequestMethod::SendMdn(send) => {
+                let (id, mdn) = send.send.iter().next().unwrap();
+                assert_eq!(id, "k1546");
+                assert_eq!(
+                    mdn.reporting_ua.as_deref(),
+                    Some("joes-pc.cs.example.com; Foomail 97.1")
+                );
+                assert!(mdn.include_original_message);
+                assert_eq!(mdn.disposition.as_ref().unwrap().type_, "displayed");
+                assert_eq!(
+                    mdn.extension_fields
+                        .as_ref()
+                        .unwrap()
+                        .get("X-EXTENSION-EXAMPLE")
+                        .map(|v| v.as_str()),
+                    Some("example.com")
+                );
+                assert_eq!(mdn.invalid_properties, vec![Property::OriginalMessageId]);
+                assert_eq!(send.on_success_update_email.as_ref().unwrap().len(), 1);
+            }
+            method => panic!("Unexpected method: {method:?}"),
+        }
+        assert!(matches!(
+            &request.method_calls[1].method,
+            RequestMethod::ParseMdn(parse) if parse.blob_ids.is_empty()
+        ));
+    }
 }
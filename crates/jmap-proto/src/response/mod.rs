@@ -34,10 +34,11 @@ use crate::{
         get::GetResponse,
         import::ImportEmailResponse,
         lookup::BlobLookupResponse,
-        parse::ParseEmailResponse,
+        parse::{ParseEmailResponse, ParseMdnResponse},
         query::QueryResponse,
         query_changes::QueryChangesResponse,
         search_snippet::GetSearchSnippetResponse,
+        send::SendMdnResponse,
         set::SetResponse,
         upload::BlobUploadResponse,
         validate::ValidateSieveScriptResponse,
@@ -64,6 +65,8 @@ pub enum ResponseMethod {
     ValidateScript(ValidateSieveScriptResponse),
     LookupBlob(BlobLookupResponse),
     UploadBlob(BlobUploadResponse),
+    SendMdn(SendMdnResponse),
+    ParseMdn(ParseMdnResponse),
     Echo(Echo),
     Error(MethodError),
 }
@@ -207,6 +210,18 @@ impl From<BlobLookupResponse> for ResponseMethod {
     }
 }
 
+impl From<SendMdnResponse> for ResponseMethod {
+    fn from(send_mdn: SendMdnResponse) -> Self {
+        ResponseMethod::SendMdn(send_mdn)
+    }
+}
+
+impl From<ParseMdnResponse> for ResponseMethod {
+    fn from(parse_mdn: ParseMdnResponse) -> Self {
+        ResponseMethod::ParseMdn(parse_mdn)
+    }
+}
+
 impl<T: Into<ResponseMethod>> From<Result<T, MethodError>> for ResponseMethod {
     fn from(result: Result<T, MethodError>) -> Self {
         match result {
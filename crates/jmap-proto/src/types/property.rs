@@ -27,7 +27,10 @@ use mail_parser::HeaderName;
 use serde::Serialize;
 use store::write::{DeserializeFrom, SerializeInto};
 
-use crate::parser::{json::Parser, Error, JsonObjectParser};
+use crate::{
+    parser::{json::Parser, Error, JsonObjectParser},
+    request::method::MethodObject,
+};
 
 use super::{acl::Acl, id::Id, keyword::Keyword, value::Value};
 
@@ -136,6 +139,30 @@ pub enum Property {
     WarnLimit,
     SoftLimit,
     Scope,
+    Metadata,
+    AddressBookIds,
+    Uid,
+    Kind,
+    IsDefault,
+    DavName,
+    CalendarIds,
+    Color,
+    IsVisible,
+    Start,
+    End,
+    Title,
+    ForEmailId,
+    IncludeOriginalMessage,
+    ReportingUa,
+    ActionMode,
+    SendingMode,
+    MdnGateway,
+    OriginalRecipient,
+    FinalRecipient,
+    OriginalMessageId,
+    Error,
+    ExtensionFields,
+    BayesClass,
     Digest(DigestProperty),
     Data(DataProperty),
     _T(String),
@@ -264,7 +291,18 @@ impl JsonObjectParser for SetProperty {
 
         if is_patch {
             match &property {
-                Property::MailboxIds | Property::Members => match Id::parse(parser) {
+                _ if (parser.ctx == MethodObject::ContactCard
+                    && property != Property::AddressBookIds)
+                    || (parser.ctx == MethodObject::CalendarEvent
+                        && property != Property::CalendarIds) =>
+                {
+                    // JSContact and JSCalendar patches are applied as JSON pointers
+                    property = parser.invalid_property()?;
+                }
+                Property::MailboxIds
+                | Property::Members
+                | Property::AddressBookIds
+                | Property::CalendarIds => match Id::parse(parser) {
                     Ok(id) => {
                         patch.push(Value::Id(id));
                     }
@@ -349,9 +387,11 @@ impl JsonObjectParser for SetProperty {
 fn parse_property(first_char: u8, hash: u128) -> Option<Property> {
     Some(match first_char {
         b'a' => match hash {
+            0x0065_646f_4d6e_6f69_7463 => Property::ActionMode,
             0x6c63 => Property::Acl,
             0x7365_7361_696c => Property::Aliases,
             0x7374_6e65_6d68_6361_7474 => Property::Attachments,
+            0x0073_6449_6b6f_6f42_7373_6572_6464 => Property::AddressBookIds,
             _ => return None,
         },
         b'b' => match hash {
@@ -366,6 +406,8 @@ fn parse_property(first_char: u8, hash: u128) -> Option<Property> {
             0x63 => Property::Cc,
             0x7465_7372_6168 => Property::Charset,
             0x6469 => Property::Cid,
+            0x7364_4972_6164_6e65_6c61 => Property::CalendarIds,
+            0x726f_6c6f => Property::Color,
             _ => return None,
         },
         b'd' => match hash {
@@ -378,14 +420,19 @@ fn parse_property(first_char: u8, hash: u128) -> Option<Property> {
             _ => return None,
         },
         b'e' => match hash {
+            0x7364_6c65_6946_6e6f_6973_6e65_7478 => Property::ExtensionFields,
+            0x726f_7272 => Property::Error,
             0x6c69_616d => Property::Email,
             0x6449_6c69_616d => Property::EmailId,
             0x0073_6449_6c69_616d => Property::EmailIds,
             0x0065_706f_6c65_766e => Property::Envelope,
             0x7365_7269_7078 => Property::Expires,
+            0x646e => Property::End,
             _ => return None,
         },
         b'f' => match hash {
+            0x0074_6e65_6970_6963_6552_6c61_6e69 => Property::FinalRecipient,
+            0x0064_496c_6961_6d45_726f => Property::ForEmailId,
             0x006d_6f72 => Property::From,
             0x0065_7461_446d_6f72 => Property::FromDate,
             _ => return None,
@@ -404,11 +451,14 @@ fn parse_property(first_char: u8, hash: u128) -> Option<Property> {
             0x0065_7669_7463_4173 => Property::IsActive,
             0x6465_6c62_616e_4573 => Property::IsEnabled,
             0x0064_6562_6972_6373_6275_5373 => Property::IsSubscribed,
+            0x746c_7561_6665_4473 => Property::IsDefault,
+            0x656c_6269_7369_5673 => Property::IsVisible,
             _ => return None,
         },
         b'k' => match hash {
             0x0073_7965 => Property::Keys,
             0x0073_6472_6f77_7965 => Property::Keywords,
+            0x0064_6e69 => Property::Kind,
             _ => return None,
         },
         b'l' => match hash {
@@ -417,6 +467,7 @@ fn parse_property(first_char: u8, hash: u128) -> Option<Property> {
             _ => return None,
         },
         b'm' => match hash {
+            0x0079_6177_6574_6147_6e64 => Property::MdnGateway,
             0x0073_6449_786f_626c_6961 => Property::MailboxIds,
             0x6574_656c_6544_7961 => Property::MayDelete,
             0x0073_6449_626f_6c42_6e64 => Property::MdnBlobIds,
@@ -429,6 +480,11 @@ fn parse_property(first_char: u8, hash: u128) -> Option<Property> {
             0x0065_6d61 => Property::Name,
             _ => return None,
         },
+        b'o' => match hash {
+            0x6449_6567_6173_7365_4d6c_616e_6967_6972 => Property::OriginalMessageId,
+            0x746e_6569_7069_6365_526c_616e_6967_6972 => Property::OriginalRecipient,
+            _ => return None,
+        },
         b'p' => match hash {
             0x0064_4974_6e65_7261 => Property::ParentId,
             0x0064_4974_7261 => Property::PartId,
@@ -441,6 +497,7 @@ fn parse_property(first_char: u8, hash: u128) -> Option<Property> {
             _ => return None,
         },
         b'r' => match hash {
+            0x4155_676e_6974_726f_7065 => Property::ReportingUa,
             0x0074_4164_6576_6965_6365 => Property::ReceivedAt,
             0x0073_6563_6e65_7265_6665 => Property::References,
             0x6f54_796c_7065 => Property::ReplyTo,
@@ -448,6 +505,7 @@ fn parse_property(first_char: u8, hash: u128) -> Option<Property> {
             _ => return None,
         },
         b's' => match hash {
+            0x6564_6f4d_676e_6964_6e65 => Property::SendingMode,
             0x0074_6572_6365 => Property::Secret,
             0x0074_4164_6e65 => Property::SendAt,
             0x0072_6564_6e65 => Property::Sender,
@@ -456,6 +514,7 @@ fn parse_property(first_char: u8, hash: u128) -> Option<Property> {
             0x7265_6472_4f74_726f => Property::SortOrder,
             0x7463_656a_6275 => Property::Subject,
             0x7374_7261_5062_7573 => Property::SubParts,
+            0x7472_6174 => Property::Start,
             _ => return None,
         },
         b't' => match hash {
@@ -468,6 +527,7 @@ fn parse_property(first_char: u8, hash: u128) -> Option<Property> {
             0x736c_6961_6d45_6c61_746f => Property::TotalEmails,
             0x0073_6461_6572_6854_6c61_746f => Property::TotalThreads,
             0x0065_7079 => Property::Type,
+            0x656c_7469 => Property::Title,
             0x7365_7079 => Property::Types,
             _ => return None,
         },
@@ -476,6 +536,7 @@ fn parse_property(first_char: u8, hash: u128) -> Option<Property> {
             0x0073_6c69_616d_4564_6165_726e => Property::UnreadEmails,
             0x7364_6165_7268_5464_6165_726e => Property::UnreadThreads,
             0x6c72 => Property::Url,
+            0x6469 => Property::Uid,
             _ => return None,
         },
         b'v' => match hash {
@@ -898,6 +959,30 @@ impl Display for Property {
             Property::Used => write!(f, "used"),
             Property::HardLimit => write!(f, "hardLimit"),
             Property::Scope => write!(f, "scope"),
+            Property::Metadata => write!(f, "metadata"),
+            Property::AddressBookIds => write!(f, "addressBookIds"),
+            Property::Uid => write!(f, "uid"),
+            Property::Kind => write!(f, "kind"),
+            Property::IsDefault => write!(f, "isDefault"),
+            Property::DavName => write!(f, "davName"),
+            Property::CalendarIds => write!(f, "calendarIds"),
+            Property::Color => write!(f, "color"),
+            Property::IsVisible => write!(f, "isVisible"),
+            Property::Start => write!(f, "start"),
+            Property::End => write!(f, "end"),
+            Property::Title => write!(f, "title"),
+            Property::ForEmailId => write!(f, "forEmailId"),
+            Property::IncludeOriginalMessage => write!(f, "includeOriginalMessage"),
+            Property::ReportingUa => write!(f, "reportingUA"),
+            Property::ActionMode => write!(f, "actionMode"),
+            Property::SendingMode => write!(f, "sendingMode"),
+            Property::MdnGateway => write!(f, "mdnGateway"),
+            Property::OriginalRecipient => write!(f, "originalRecipient"),
+            Property::FinalRecipient => write!(f, "finalRecipient"),
+            Property::OriginalMessageId => write!(f, "originalMessageId"),
+            Property::Error => write!(f, "error"),
+            Property::ExtensionFields => write!(f, "extensionFields"),
+            Property::BayesClass => write!(f, "bayesClass"),
             Property::WarnLimit => write!(f, "warnLimit"),
             Property::SoftLimit => write!(f, "softLimit"),
             Property::_T(s) => write!(f, "{s}"),
@@ -1080,6 +1165,30 @@ impl From<&Property> for u8 {
             Property::WarnLimit => 101,
             Property::SoftLimit => 102,
             Property::Scope => 103,
+            Property::Metadata => 104,
+            Property::AddressBookIds => 105,
+            Property::Uid => 106,
+            Property::Kind => 107,
+            Property::IsDefault => 108,
+            Property::DavName => 109,
+            Property::CalendarIds => 110,
+            Property::Color => 111,
+            Property::IsVisible => 112,
+            Property::Start => 113,
+            Property::End => 114,
+            Property::Title => 115,
+            Property::ForEmailId => 116,
+            Property::IncludeOriginalMessage => 117,
+            Property::ReportingUa => 118,
+            Property::ActionMode => 119,
+            Property::SendingMode => 120,
+            Property::MdnGateway => 121,
+            Property::OriginalRecipient => 122,
+            Property::FinalRecipient => 123,
+            Property::OriginalMessageId => 124,
+            Property::Error => 125,
+            Property::ExtensionFields => 126,
+            Property::BayesClass => 127,
             Property::Digest(_) | Property::Data(_) => unreachable!("invalid property"),
         }
     }
@@ -1222,6 +1331,30 @@ impl SerializeInto for Property {
             Property::WarnLimit => 101,
             Property::SoftLimit => 102,
             Property::Scope => 103,
+            Property::Metadata => 104,
+            Property::AddressBookIds => 105,
+            Property::Uid => 106,
+            Property::Kind => 107,
+            Property::IsDefault => 108,
+            Property::DavName => 109,
+            Property::CalendarIds => 110,
+            Property::Color => 111,
+            Property::IsVisible => 112,
+            Property::Start => 113,
+            Property::End => 114,
+            Property::Title => 115,
+            Property::ForEmailId => 116,
+            Property::IncludeOriginalMessage => 117,
+            Property::ReportingUa => 118,
+            Property::ActionMode => 119,
+            Property::SendingMode => 120,
+            Property::MdnGateway => 121,
+            Property::OriginalRecipient => 122,
+            Property::FinalRecipient => 123,
+            Property::OriginalMessageId => 124,
+            Property::Error => 125,
+            Property::ExtensionFields => 126,
+            Property::BayesClass => 127,
             Property::Digest(_) | Property::Data(_) => {
                 unreachable!("Property::Digest and Property::Data are not serializable")
             }
@@ -1340,6 +1473,30 @@ impl DeserializeFrom for Property {
             101 => Some(Property::WarnLimit),
             102 => Some(Property::SoftLimit),
             103 => Some(Property::Scope),
+            104 => Some(Property::Metadata),
+            105 => Some(Property::AddressBookIds),
+            106 => Some(Property::Uid),
+            107 => Some(Property::Kind),
+            108 => Some(Property::IsDefault),
+            109 => Some(Property::DavName),
+            110 => Some(Property::CalendarIds),
+            111 => Some(Property::Color),
+            112 => Some(Property::IsVisible),
+            113 => Some(Property::Start),
+            114 => Some(Property::End),
+            115 => Some(Property::Title),
+            116 => Some(Property::ForEmailId),
+            117 => Some(Property::IncludeOriginalMessage),
+            118 => Some(Property::ReportingUa),
+            119 => Some(Property::ActionMode),
+            120 => Some(Property::SendingMode),
+            121 => Some(Property::MdnGateway),
+            122 => Some(Property::OriginalRecipient),
+            123 => Some(Property::FinalRecipient),
+            124 => Some(Property::OriginalMessageId),
+            125 => Some(Property::Error),
+            126 => Some(Property::ExtensionFields),
+            127 => Some(Property::BayesClass),
             _ => None,
         }
     }
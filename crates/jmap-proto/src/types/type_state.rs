@@ -58,7 +58,15 @@ pub enum DataType {
     Quota = 11,
     #[serde(rename = "SieveScript")]
     SieveScript = 12,
-    None = 13,
+    #[serde(rename = "AddressBook")]
+    AddressBook = 13,
+    #[serde(rename = "ContactCard")]
+    ContactCard = 14,
+    #[serde(rename = "Calendar")]
+    Calendar = 15,
+    #[serde(rename = "CalendarEvent")]
+    CalendarEvent = 16,
+    None = 17,
 }
 
 impl BitmapItem for DataType {
@@ -87,6 +95,10 @@ impl From<u64> for DataType {
             10 => DataType::Mdn,
             11 => DataType::Quota,
             12 => DataType::SieveScript,
+            13 => DataType::AddressBook,
+            14 => DataType::ContactCard,
+            15 => DataType::Calendar,
+            16 => DataType::CalendarEvent,
             _ => {
                 debug_assert!(false, "Invalid type_state value: {}", value);
                 DataType::None
@@ -132,6 +144,10 @@ impl JsonObjectParser for DataType {
             0x004e_444d => Ok(DataType::Mdn),
             0x0061_746f_7551 => Ok(DataType::Quota),
             0x0074_7069_7263_5365_7665_6953 => Ok(DataType::SieveScript),
+            0x006b_6f6f_4273_7365_7264_6441 => Ok(DataType::AddressBook),
+            0x0064_7261_4374_6361_746e_6f43 => Ok(DataType::ContactCard),
+            0x7261_646e_656c_6143 => Ok(DataType::Calendar),
+            0x0074_6e65_7645_7261_646e_656c_6143 => Ok(DataType::CalendarEvent),
             _ => Err(parser.error_value()),
         }
     }
@@ -167,6 +183,10 @@ impl TryFrom<&str> for DataType {
             0x004e_444d => Ok(DataType::Mdn),
             0x0061_746f_7551 => Ok(DataType::Quota),
             0x0074_7069_7263_5365_7665_6953 => Ok(DataType::SieveScript),
+            0x006b_6f6f_4273_7365_7264_6441 => Ok(DataType::AddressBook),
+            0x0064_7261_4374_6361_746e_6f43 => Ok(DataType::ContactCard),
+            0x7261_646e_656c_6143 => Ok(DataType::Calendar),
+            0x0074_6e65_7645_7261_646e_656c_6143 => Ok(DataType::CalendarEvent),
             _ => Err(()),
         }
     }
@@ -188,6 +208,10 @@ impl DataType {
             DataType::Mdn => "MDN",
             DataType::Quota => "Quota",
             DataType::SieveScript => "SieveScript",
+            DataType::AddressBook => "AddressBook",
+            DataType::ContactCard => "ContactCard",
+            DataType::Calendar => "Calendar",
+            DataType::CalendarEvent => "CalendarEvent",
             DataType::None => "",
         }
     }
@@ -221,6 +245,10 @@ impl DeserializeFrom for DataType {
             10 => Some(DataType::Mdn),
             11 => Some(DataType::Quota),
             12 => Some(DataType::SieveScript),
+            13 => Some(DataType::AddressBook),
+            14 => Some(DataType::ContactCard),
+            15 => Some(DataType::Calendar),
+            16 => Some(DataType::CalendarEvent),
             _ => None,
         }
     }
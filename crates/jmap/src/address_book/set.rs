@@ -0,0 +1,425 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use jmap_proto::{
+    error::{
+        method::MethodError,
+        set::{SetError, SetErrorType},
+    },
+    method::set::{SetRequest, SetResponse},
+    object::{
+        contact::SetArguments,
+        index::{IndexAs, IndexProperty, ObjectIndexBuilder},
+        Object,
+    },
+    response::references::EvalObjectReferences,
+    types::{
+        collection::Collection,
+        id::Id,
+        property::Property,
+        state::StateChange,
+        type_state::DataType,
+        value::{MaybePatchValue, SetValue, Value},
+    },
+};
+use store::{
+    query::Filter,
+    roaring::RoaringBitmap,
+    write::{assert::HashedValue, log::ChangeLogBuilder, BatchBuilder},
+};
+
+use crate::{
+    contact::{index::ContactIndex, JSContactObject},
+    JMAP,
+};
+
+use super::{DEFAULT_ADDRESS_BOOK_DAV_NAME, DEFAULT_ADDRESS_BOOK_NAME};
+
+pub static SCHEMA: &[IndexProperty] = &[
+    IndexProperty::new(Property::Name)
+        .index_as(IndexAs::Text {
+            tokenize: false,
+            index: true,
+        })
+        .max_size(255)
+        .required(),
+    IndexProperty::new(Property::SortOrder).index_as(IndexAs::Integer),
+    IndexProperty::new(Property::DavName).index_as(IndexAs::Text {
+        tokenize: false,
+        index: true,
+    }),
+];
+
+impl JMAP {
+    pub async fn address_book_set(
+        &self,
+        mut request: SetRequest<SetArguments>,
+    ) -> Result<SetResponse, MethodError> {
+        let account_id = request.account_id.document_id();
+        let mut address_book_ids = self.address_book_get_or_create(account_id).await?;
+        let mut response = self
+            .prepare_set_response(&request, Collection::AddressBook)
+            .await?;
+        let will_destroy = request.unwrap_destroy();
+        let remove_contents = request
+            .arguments
+            .on_destroy_remove_contents
+            .unwrap_or(false);
+
+        // Process creates
+        let mut changes = ChangeLogBuilder::new();
+        for (id, object) in request.unwrap_create() {
+            if address_book_ids.len() as usize >= self.config.load().contact_max_address_books {
+                response.not_created.append(
+                    id,
+                    SetError::new(SetErrorType::OverQuota).with_description(
+                        "There are too many address books, please delete some before adding a new one.",
+                    ),
+                );
+                continue;
+            }
+
+            match self.address_book_set_item(object, None, &response) {
+                Ok(mut builder) => {
+                    let document_id = self
+                        .assign_document_id(account_id, Collection::AddressBook)
+                        .await?;
+                    builder.changes_mut().unwrap().append(
+                        Property::DavName,
+                        Value::Text(Id::from(document_id).to_string()),
+                    );
+                    let mut batch = BatchBuilder::new();
+                    batch
+                        .with_account_id(account_id)
+                        .with_collection(Collection::AddressBook)
+                        .create_document(document_id)
+                        .custom(builder);
+                    self.write_batch(batch).await?;
+                    address_book_ids.insert(document_id);
+                    changes.log_insert(Collection::AddressBook, document_id);
+                    response.created(id, document_id);
+                }
+                Err(err) => {
+                    response.not_created.append(id, err);
+                }
+            }
+        }
+
+        // Process updates
+        'update: for (id, object) in request.unwrap_update() {
+            // Make sure id won't be destroyed
+            if will_destroy.contains(&id) {
+                response.not_updated.append(id, SetError::will_destroy());
+                continue 'update;
+            }
+
+            // Obtain address book
+            let document_id = id.document_id();
+            let address_book = if let Some(address_book) = self
+                .get_property::<HashedValue<Object<Value>>>(
+                    account_id,
+                    Collection::AddressBook,
+                    document_id,
+                    Property::Value,
+                )
+                .await?
+            {
+                address_book
+            } else {
+                response.not_updated.append(id, SetError::not_found());
+                continue 'update;
+            };
+
+            match self.address_book_set_item(object, Some(address_book), &response) {
+                Ok(builder) => {
+                    let mut batch = BatchBuilder::new();
+                    batch
+                        .with_account_id(account_id)
+                        .with_collection(Collection::AddressBook)
+                        .update_document(document_id)
+                        .custom(builder);
+                    if !batch.is_empty() {
+                        match self.store.write(batch.build()).await {
+                            Ok(_) => {
+                                changes.log_update(Collection::AddressBook, document_id);
+                            }
+                            Err(store::Error::AssertValueFailed) => {
+                                response.not_updated.append(id, SetError::forbidden().with_description(
+                                    "Another process modified this address book, please try again.",
+                                ));
+                                continue 'update;
+                            }
+                            Err(err) => {
+                                tracing::error!(
+                                    event = "error",
+                                    context = "address_book_set",
+                                    account_id = account_id,
+                                    error = ?err,
+                                    "Failed to update address book(s).");
+                                return Err(MethodError::ServerPartialFail);
+                            }
+                        }
+                    }
+                    response.updated.append(id, None);
+                }
+                Err(err) => {
+                    response.not_updated.append(id, err);
+                }
+            }
+        }
+
+        // Process deletions
+        for id in will_destroy {
+            let document_id = id.document_id();
+            if !address_book_ids.contains(document_id) {
+                response.not_destroyed.append(id, SetError::not_found());
+                continue;
+            }
+
+            match self
+                .address_book_destroy(account_id, document_id, remove_contents, &mut changes)
+                .await?
+            {
+                Ok(_) => {
+                    response.destroyed.push(id);
+                }
+                Err(err) => {
+                    response.not_destroyed.append(id, err);
+                }
+            }
+        }
+
+        // Write changes
+        if !changes.is_empty() {
+            let has_card_changes = changes
+                .changes
+                .contains_key(&u8::from(Collection::ContactCard));
+            let change_id = self.commit_changes(account_id, changes).await?;
+            let state_change =
+                StateChange::new(account_id).with_change(DataType::AddressBook, change_id);
+            response.state_change = if has_card_changes {
+                state_change.with_change(DataType::ContactCard, change_id)
+            } else {
+                state_change
+            }
+            .into();
+            response.new_state = Some(change_id.into());
+        }
+
+        Ok(response)
+    }
+
+    pub async fn address_book_destroy(
+        &self,
+        account_id: u32,
+        document_id: u32,
+        remove_contents: bool,
+        changes: &mut ChangeLogBuilder,
+    ) -> Result<Result<(), SetError>, MethodError> {
+        // Obtain address book
+        let address_book = if let Some(address_book) = self
+            .get_property::<HashedValue<Object<Value>>>(
+                account_id,
+                Collection::AddressBook,
+                document_id,
+                Property::Value,
+            )
+            .await?
+        {
+            address_book
+        } else {
+            return Ok(Err(SetError::not_found()));
+        };
+
+        // Remove or unlink the cards in this address book
+        let card_ids = self
+            .filter(
+                account_id,
+                Collection::ContactCard,
+                vec![Filter::eq(Property::AddressBookIds, document_id)],
+            )
+            .await?
+            .results;
+        if !card_ids.is_empty() {
+            if !remove_contents {
+                return Ok(Err(SetError::new(SetErrorType::AddressBookHasContents)
+                    .with_description("Address book is not empty.")));
+            }
+
+            let mut batch = BatchBuilder::new();
+            batch
+                .with_account_id(account_id)
+                .with_collection(Collection::ContactCard);
+            for card_id in card_ids {
+                let card = if let Some(card) = self
+                    .get_property::<HashedValue<Object<Value>>>(
+                        account_id,
+                        Collection::ContactCard,
+                        card_id,
+                        Property::Value,
+                    )
+                    .await?
+                {
+                    card
+                } else {
+                    continue;
+                };
+                let mut address_book_ids = card.inner.address_book_ids();
+                address_book_ids.retain(|id| *id != document_id);
+                if address_book_ids.is_empty() {
+                    batch
+                        .delete_document(card_id)
+                        .custom(ContactIndex::delete(card));
+                    changes.log_delete(Collection::ContactCard, card_id);
+                } else {
+                    let mut new_card = card.inner.clone();
+                    new_card.set(
+                        Property::AddressBookIds,
+                        Value::List(
+                            address_book_ids
+                                .into_iter()
+                                .map(|id| Value::UnsignedInt(id as u64))
+                                .collect(),
+                        ),
+                    );
+                    batch
+                        .update_document(card_id)
+                        .custom(ContactIndex::update(card, new_card));
+                    changes.log_update(Collection::ContactCard, card_id);
+                }
+            }
+            self.write_batch(batch).await?;
+        }
+
+        // Delete address book
+        let mut batch = BatchBuilder::new();
+        batch
+            .with_account_id(account_id)
+            .with_collection(Collection::AddressBook)
+            .delete_document(document_id)
+            .custom(ObjectIndexBuilder::new(SCHEMA).with_current(address_book));
+        self.write_batch(batch).await?;
+        changes.log_delete(Collection::AddressBook, document_id);
+
+        Ok(Ok(()))
+    }
+
+    fn address_book_set_item(
+        &self,
+        changes_: Object<SetValue>,
+        update: Option<HashedValue<Object<Value>>>,
+        response: &SetResponse,
+    ) -> Result<ObjectIndexBuilder, SetError> {
+        // Parse properties
+        let mut changes = Object::with_capacity(changes_.properties.len());
+        for (property, value) in changes_.properties {
+            let value = match (&property, response.eval_object_references(value)?) {
+                (Property::Name, MaybePatchValue::Value(Value::Text(value))) => {
+                    let value = value.trim();
+                    if !value.is_empty()
+                        && value.len() < self.config.load().address_book_name_max_len
+                    {
+                        Value::Text(value.to_string())
+                    } else {
+                        return Err(SetError::invalid_properties()
+                            .with_property(Property::Name)
+                            .with_description(if !value.is_empty() {
+                                "Address book name is too long."
+                            } else {
+                                "Address book name cannot be empty."
+                            }));
+                    }
+                }
+                (Property::Description, MaybePatchValue::Value(Value::Text(value))) => {
+                    Value::Text(value)
+                }
+                (Property::SortOrder, MaybePatchValue::Value(Value::UnsignedInt(value))) => {
+                    Value::UnsignedInt(value)
+                }
+                (
+                    Property::IsDefault | Property::IsSubscribed,
+                    MaybePatchValue::Value(Value::Bool(value)),
+                ) => Value::Bool(value),
+                (
+                    Property::Description
+                    | Property::SortOrder
+                    | Property::IsDefault
+                    | Property::IsSubscribed,
+                    MaybePatchValue::Value(Value::Null),
+                ) => Value::Null,
+                _ => {
+                    return Err(SetError::invalid_properties()
+                        .with_property(property)
+                        .with_description("Invalid property or value.".to_string()))
+                }
+            };
+
+            changes.append(property, value);
+        }
+
+        Ok(ObjectIndexBuilder::new(SCHEMA)
+            .with_changes(changes)
+            .with_current_opt(update))
+    }
+
+    pub async fn address_book_get_or_create(
+        &self,
+        account_id: u32,
+    ) -> Result<RoaringBitmap, MethodError> {
+        let mut address_book_ids = self
+            .get_document_ids(account_id, Collection::AddressBook)
+            .await?
+            .unwrap_or_default();
+        if !address_book_ids.is_empty() {
+            return Ok(address_book_ids);
+        }
+
+        let document_id = self
+            .assign_document_id(account_id, Collection::AddressBook)
+            .await?;
+        let mut batch = BatchBuilder::new();
+        batch
+            .with_account_id(account_id)
+            .with_collection(Collection::AddressBook)
+            .create_document(document_id)
+            .custom(
+                ObjectIndexBuilder::new(SCHEMA).with_changes(
+                    Object::with_capacity(3)
+                        .with_property(Property::Name, DEFAULT_ADDRESS_BOOK_NAME)
+                        .with_property(Property::IsDefault, true)
+                        .with_property(Property::DavName, DEFAULT_ADDRESS_BOOK_DAV_NAME),
+                ),
+            );
+        self.store.write(batch.build()).await.map_err(|err| {
+            tracing::error!(
+                event = "error",
+                context = "address_book_get_or_create",
+                error = ?err,
+                "Failed to create address book.");
+            MethodError::ServerPartialFail
+        })?;
+        address_book_ids.insert(document_id);
+
+        Ok(address_book_ids)
+    }
+}
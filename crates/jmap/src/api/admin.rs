@@ -23,17 +23,30 @@
 
 use directory::{
     backend::internal::{lookup::DirectoryStore, manage::ManageDirectory, PrincipalUpdate},
+    core::{
+        app_password::AppPasswordStore,
+        mailing_list::{MailingList, MailingListStore},
+        totp::TotpStore,
+    },
     DirectoryError, ManagementError, Principal, QueryBy, Type,
 };
 use http_body_util::combinators::BoxBody;
 use hyper::{body::Bytes, Method, StatusCode};
 use jmap_proto::error::request::RequestError;
-use serde_json::json;
-use utils::config::ConfigKey;
+use serde_json::{json, Value};
+use smtp::core::dkim::algorithm_from_name;
+use utils::{
+    config::{ConfigKey, ServerProtocol},
+    listener::blocked::BLOCKED_IP_KEY,
+};
 
 use crate::{services::housekeeper, JMAP};
 
-use super::{http::ToHttpResponse, HttpRequest, JsonResponse};
+use super::{
+    http::ToHttpResponse,
+    settings::{is_secret_key, SettingsError, REDACTED_VALUE},
+    HttpRequest, JsonResponse,
+};
 
 #[derive(Debug, serde::Serialize, serde::Deserialize)]
 pub struct PrincipalResponse {
@@ -52,6 +65,28 @@ pub struct PrincipalResponse {
     pub description: Option<String>,
 }
 
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+pub struct AppPasswordRequest {
+    pub name: String,
+    pub expires: Option<u64>,
+    #[serde(default)]
+    pub protocols: Vec<ServerProtocol>,
+}
+
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+pub struct DkimSignature {
+    pub algorithm: String,
+    pub domain: String,
+    pub selector: String,
+    #[serde(rename = "privateKey")]
+    pub private_key: String,
+    #[serde(rename = "publicKey")]
+    pub public_key: Option<String>,
+    pub canonicalization: Option<String>,
+    #[serde(default)]
+    pub headers: Vec<String>,
+}
+
 impl JMAP {
     pub async fn handle_manage_request(
         &self,
@@ -119,6 +154,284 @@ impl JMAP {
                     Err(err) => map_directory_error(err),
                 }
             }
+            ("principal", Some(name), method)
+                if matches!(
+                    path.clone().next(),
+                    Some("app-passwords" | "totp" | "list" | "oidc")
+                ) =>
+            {
+                // Manage app passwords, second factor, linked identities and mailing list settings
+                let account_id = match self.store.get_account_id(name).await {
+                    Ok(Some(account_id)) => account_id,
+                    Ok(None) => {
+                        return RequestError::blank(
+                            StatusCode::NOT_FOUND.as_u16(),
+                            "Not found",
+                            "Account not found.",
+                        )
+                        .into_http_response();
+                    }
+                    Err(err) => {
+                        return map_directory_error(err);
+                    }
+                };
+
+                let result = match (path.next(), path.next(), method) {
+                    (Some("totp"), None, &Method::GET) => {
+                        self.store.get_totp(account_id).await.map(|totp| {
+                            json!(totp.map(|totp| json!({
+                                "enabled": totp.enabled,
+                                "recoveryCodes": totp.recovery_codes.len(),
+                            })))
+                        })
+                    }
+                    (Some("totp"), None, &Method::POST) => {
+                        match self.store.enroll_totp(account_id, name).await {
+                            Ok(Some(enrollment)) => Ok(json!(enrollment)),
+                            Ok(None) => {
+                                return RequestError::blank(
+                                    StatusCode::CONFLICT.as_u16(),
+                                    "Already enabled",
+                                    "Two-factor authentication is already enabled",
+                                )
+                                .into_http_response();
+                            }
+                            Err(err) => Err(err),
+                        }
+                    }
+                    (Some("totp"), Some("confirm"), &Method::POST) => {
+                        let code = body
+                            .and_then(|body| serde_json::from_slice::<Value>(&body).ok())
+                            .and_then(|body| {
+                                body.get("code")
+                                    .and_then(|v| v.as_str())
+                                    .map(|v| v.to_string())
+                            })
+                            .unwrap_or_default();
+                        match self.store.confirm_totp(account_id, &code).await {
+                            Ok(true) => Ok(json!([])),
+                            Ok(false) => {
+                                return RequestError::blank(
+                                    StatusCode::BAD_REQUEST.as_u16(),
+                                    "Invalid parameters",
+                                    "Invalid or expired authentication code",
+                                )
+                                .into_http_response();
+                            }
+                            Err(err) => Err(err),
+                        }
+                    }
+                    (Some("totp"), None, &Method::DELETE) => {
+                        match self.store.disable_totp(account_id).await {
+                            Ok(true) => Ok(json!([])),
+                            Ok(false) => {
+                                return RequestError::not_found().into_http_response();
+                            }
+                            Err(err) => Err(err),
+                        }
+                    }
+                    (Some("oidc"), Some(subject), &Method::POST) => {
+                        match self
+                            .directory
+                            .load()
+                            .link_oidc_subject(subject, account_id)
+                            .await
+                        {
+                            Ok(true) => Ok(json!([])),
+                            Ok(false) => {
+                                return RequestError::not_found().into_http_response();
+                            }
+                            Err(err) => Err(err),
+                        }
+                    }
+                    (Some("oidc"), Some(subject), &Method::DELETE) => {
+                        match self
+                            .directory
+                            .load()
+                            .unlink_oidc_subject(subject, account_id)
+                            .await
+                        {
+                            Ok(true) => Ok(json!([])),
+                            Ok(false) => {
+                                return RequestError::not_found().into_http_response();
+                            }
+                            Err(err) => Err(err),
+                        }
+                    }
+                    (Some("app-passwords"), None, &Method::GET) => self
+                        .store
+                        .list_app_passwords(account_id)
+                        .await
+                        .map(|app_passwords| json!(app_passwords)),
+                    (Some("app-passwords"), None, &Method::POST) => {
+                        let request = body.and_then(|body| {
+                            serde_json::from_slice::<AppPasswordRequest>(&body).ok()
+                        });
+                        match request {
+                            Some(request)
+                                if !request.name.is_empty()
+                                    && request.protocols.iter().all(|protocol| {
+                                        matches!(
+                                            protocol,
+                                            ServerProtocol::Imap
+                                                | ServerProtocol::Smtp
+                                                | ServerProtocol::Jmap
+                                                | ServerProtocol::ManageSieve
+                                        )
+                                    }) =>
+                            {
+                                self.store
+                                    .create_app_password(
+                                        account_id,
+                                        &request.name,
+                                        request.expires,
+                                        request.protocols,
+                                    )
+                                    .await
+                                    .map(|secret| {
+                                        json!({
+                                            "name": request.name,
+                                            "password": secret,
+                                        })
+                                    })
+                            }
+                            _ => {
+                                return RequestError::blank(
+                                    StatusCode::BAD_REQUEST.as_u16(),
+                                    "Invalid parameters",
+                                    "Failed to deserialize app password request",
+                                )
+                                .into_http_response();
+                            }
+                        }
+                    }
+                    (Some("list"), None, &Method::GET) => self
+                        .store
+                        .get_mailing_list(account_id)
+                        .await
+                        .map(|list| json!(list)),
+                    (Some("list"), None, &Method::POST) => {
+                        match self.store.query(QueryBy::Id(account_id), false).await {
+                            Ok(Some(principal)) if principal.typ == Type::List => {}
+                            Ok(_) => {
+                                return RequestError::blank(
+                                    StatusCode::BAD_REQUEST.as_u16(),
+                                    "Invalid parameters",
+                                    "Principal is not a mailing list",
+                                )
+                                .into_http_response();
+                            }
+                            Err(err) => return map_directory_error(err),
+                        }
+                        if let Some(list) =
+                            body.and_then(|body| serde_json::from_slice::<MailingList>(&body).ok())
+                        {
+                            self.store
+                                .set_mailing_list(account_id, Some(list))
+                                .await
+                                .map(|_| json!([]))
+                        } else {
+                            return RequestError::blank(
+                                StatusCode::BAD_REQUEST.as_u16(),
+                                "Invalid parameters",
+                                "Failed to deserialize mailing list settings",
+                            )
+                            .into_http_response();
+                        }
+                    }
+                    (Some("list"), None, &Method::DELETE) => self
+                        .store
+                        .set_mailing_list(account_id, None)
+                        .await
+                        .map(|_| json!([])),
+                    (Some("list"), Some("subscribers"), &Method::GET) => self
+                        .store
+                        .list_subscribers(account_id)
+                        .await
+                        .map(|subscribers| json!(subscribers)),
+                    (Some("list"), Some("subscribers"), &Method::POST) => {
+                        let email = body
+                            .and_then(|body| serde_json::from_slice::<Value>(&body).ok())
+                            .and_then(|body| {
+                                body.get("email")
+                                    .and_then(|v| v.as_str())
+                                    .map(|v| v.to_string())
+                            })
+                            .filter(|email| email.contains('@'));
+                        if let Some(email) = email {
+                            self.store
+                                .add_subscriber(account_id, &email)
+                                .await
+                                .map(|_| json!([]))
+                        } else {
+                            return RequestError::blank(
+                                StatusCode::BAD_REQUEST.as_u16(),
+                                "Invalid parameters",
+                                "Invalid subscriber address",
+                            )
+                            .into_http_response();
+                        }
+                    }
+                    (Some("list"), Some("subscribers"), &Method::DELETE) => {
+                        let email = path.next().unwrap_or_default();
+                        match self.store.remove_subscriber(account_id, email).await {
+                            Ok(true) => Ok(json!([])),
+                            Ok(false) => {
+                                return RequestError::not_found().into_http_response();
+                            }
+                            Err(err) => Err(err),
+                        }
+                    }
+                    (Some("list"), Some("moderation"), &Method::GET) => self
+                        .store
+                        .list_held_messages(account_id)
+                        .await
+                        .map(|messages| json!(messages)),
+                    (Some("list"), Some("moderation"), &Method::POST | &Method::DELETE) => {
+                        let ctx = match self.mailing_list_context(account_id).await {
+                            Ok(Some(ctx)) => ctx,
+                            Ok(None) => {
+                                return RequestError::not_found().into_http_response();
+                            }
+                            Err(err) => return map_directory_error(err),
+                        };
+                        let id = path
+                            .next()
+                            .and_then(|id| id.parse::<u64>().ok())
+                            .unwrap_or_default();
+                        match self
+                            .mailing_list_release(&ctx, id, method == Method::POST)
+                            .await
+                        {
+                            Ok(true) => Ok(json!([])),
+                            Ok(false) => {
+                                return RequestError::not_found().into_http_response();
+                            }
+                            Err(err) => Err(err),
+                        }
+                    }
+                    (Some("app-passwords"), Some(app_name), &Method::DELETE) => {
+                        match self.store.revoke_app_password(account_id, app_name).await {
+                            Ok(true) => Ok(json!([])),
+                            Ok(false) => {
+                                return RequestError::not_found().into_http_response();
+                            }
+                            Err(err) => Err(err),
+                        }
+                    }
+                    _ => {
+                        return RequestError::not_found().into_http_response();
+                    }
+                };
+
+                match result {
+                    Ok(data) => JsonResponse::new(json!({
+                        "data": data,
+                    }))
+                    .into_http_response(),
+                    Err(err) => map_directory_error(err),
+                }
+            }
             ("principal", Some(name), method) => {
                 // Fetch, update or delete principal
                 let account_id = match self.store.get_account_id(name).await {
@@ -258,6 +571,72 @@ impl JMAP {
                     Err(err) => map_directory_error(err),
                 }
             }
+            ("domain", Some(domain), method) if path.clone().next() == Some("dkim") => {
+                // Manage the generated DKIM keys of a domain
+                path.next();
+                let result = match (path.next(), method) {
+                    (None, &Method::GET) => self
+                        .smtp
+                        .load_full()
+                        .dkim_keys(domain.into())
+                        .await
+                        .map(|keys| json!(keys)),
+                    (None, &Method::POST) => {
+                        let algorithm = body
+                            .and_then(|body| serde_json::from_slice::<Value>(&body).ok())
+                            .and_then(|body| {
+                                body.get("algorithm")
+                                    .and_then(|v| v.as_str())
+                                    .map(|v| v.to_string())
+                            })
+                            .unwrap_or_else(|| "rsa-sha256".to_string());
+                        if let Some(algorithm) = algorithm_from_name(&algorithm) {
+                            self.smtp
+                                .load_full()
+                                .generate_dkim_key(domain, algorithm)
+                                .await
+                                .map(|key| json!(key))
+                        } else {
+                            return RequestError::blank(
+                                StatusCode::BAD_REQUEST.as_u16(),
+                                "Invalid parameters",
+                                format!("Unsupported algorithm {algorithm:?}"),
+                            )
+                            .into_http_response();
+                        }
+                    }
+                    (Some(selector), &Method::DELETE) => {
+                        match self
+                            .smtp
+                            .load_full()
+                            .delete_dkim_key(domain, selector)
+                            .await
+                        {
+                            Ok(true) => Ok(json!([])),
+                            Ok(false) => {
+                                return RequestError::not_found().into_http_response();
+                            }
+                            Err(err) => Err(err),
+                        }
+                    }
+                    _ => {
+                        return RequestError::not_found().into_http_response();
+                    }
+                };
+
+                match result {
+                    Ok(data) => JsonResponse::new(json!({
+                        "data": data,
+                    }))
+                    .into_http_response(),
+                    Err(err) => RequestError::blank(
+                        StatusCode::INTERNAL_SERVER_ERROR.as_u16(),
+                        "DKIM key operation failed",
+                        err.to_string(),
+                    )
+                    .into_http_response(),
+                }
+            }
             ("domain", Some(domain), &Method::POST) => {
                 // Create domain
                 match self.store.create_domain(domain).await {
@@ -300,17 +679,39 @@ impl JMAP {
                     .into_http_response(),
                 }
             }
-            ("reload", Some("config"), &Method::GET) => {
-                let _ = self
-                    .housekeeper_tx
-                    .send(housekeeper::Event::ReloadConfig)
-                    .await;
+            ("store", Some("rewrite"), &Method::GET) => {
+                // Rewriting every blob can take a while, run it in the background
+                let store = self.store.clone();
+                let blob_store = self.blob_store.clone();
+                tokio::spawn(async move {
+                    match store.rewrite_blobs(blob_store).await {
+                        Ok(result) => tracing::info!(
+                            context = "blob_store",
+                            event = "rewrite",
+                            "Rewrote {} blobs, {} failed.",
+                            result.rewritten,
+                            result.failed
+                        ),
+                        Err(err) => tracing::warn!(
+                            context = "blob_store",
+                            event = "error",
+                            "Failed to rewrite blobs: {err}"
+                        ),
+                    }
+                });
 
                 JsonResponse::new(json!({
                     "data": [],
                 }))
                 .into_http_response()
             }
+            ("reload", Some("config"), &Method::GET) => match self.reload_config().await {
+                Ok(_) => JsonResponse::new(json!({
+                    "data": [],
+                }))
+                .into_http_response(),
+                Err(err) => map_settings_error(err),
+            },
             ("reload", Some("certificates"), &Method::GET) => {
                 let _ = self
                     .housekeeper_tx
@@ -337,23 +738,16 @@ impl JMAP {
                 }
             }
             ("config", Some(prefix), &Method::DELETE) if !prefix.is_empty() => {
-                let result = match prefix.strip_suffix('.') {
-                    Some(prefix) if !prefix.is_empty() => {
-                        self.store.config_clear_prefix(prefix).await
-                    }
-                    _ => self.store.config_clear(prefix).await,
+                let clear = match prefix.strip_suffix('.') {
+                    Some(key) if !key.is_empty() => vec![key.to_string(), prefix.to_string()],
+                    _ => vec![prefix.to_string()],
                 };
-                match result {
+                match self.update_settings(vec![], clear).await {
                     Ok(_) => JsonResponse::new(json!({
                         "data": [],
                     }))
                     .into_http_response(),
-                    Err(err) => RequestError::blank(
-                        StatusCode::INTERNAL_SERVER_ERROR.as_u16(),
-                        "Config fetch failed",
-                        err.to_string(),
-                    )
-                    .into_http_response(),
+                    Err(err) => map_settings_error(err),
                 }
             }
             ("config", None, &Method::POST) => {
@@ -361,11 +755,12 @@ impl JMAP {
                     .and_then(|body| serde_json::from_slice::<Vec<(String, String)>>(&body).ok())
                 {
                     match self
-                        .store
-                        .config_set(
+                        .update_settings(
                             changes
                                 .into_iter()
-                                .map(|(key, value)| ConfigKey { key, value }),
+                                .map(|(key, value)| ConfigKey { key, value })
+                                .collect(),
+                            vec![],
                         )
                         .await
                     {
@@ -373,12 +768,7 @@ impl JMAP {
                             "data": [],
                         }))
                         .into_http_response(),
-                        Err(err) => RequestError::blank(
-                            StatusCode::INTERNAL_SERVER_ERROR.as_u16(),
-                            "Config update failed",
-                            err.to_string(),
-                        )
-                        .into_http_response(),
+                        Err(err) => map_settings_error(err),
                     }
                 } else {
                     RequestError::blank(
@@ -389,8 +779,129 @@ impl JMAP {
                     .into_http_response()
                 }
             }
+            ("settings", prefix, &Method::GET) => match self.effective_config().await {
+                Ok(config) => JsonResponse::new(json!({
+                    "data": config
+                        .keys
+                        .into_iter()
+                        .filter(|(key, _)| key.starts_with(prefix.unwrap_or_default()))
+                        .map(|(key, value)| {
+                            if is_secret_key(&key) {
+                                (key, REDACTED_VALUE.to_string())
+                            } else {
+                                (key, value)
+                            }
+                        })
+                        .collect::<Vec<_>>(),
+                }))
+                .into_http_response(),
+                Err(err) => map_settings_error(SettingsError::Store(err)),
+            },
+            ("listener", None, &Method::GET) => JsonResponse::new(json!({
+                "data": self.settings.listeners,
+            }))
+            .into_http_response(),
+            ("blocked", None, &Method::GET) => match self.effective_config().await {
+                Ok(config) => JsonResponse::new(json!({
+                    "data": config.set_values(BLOCKED_IP_KEY).collect::<Vec<_>>(),
+                }))
+                .into_http_response(),
+                Err(err) => map_settings_error(SettingsError::Store(err)),
+            },
+            ("blocked", Some(address), method @ (&Method::POST | &Method::DELETE)) => {
+                // Networks are written as "<address>/<mask>"
+                let address = std::iter::once(address)
+                    .chain(path)
+                    .collect::<Vec<_>>()
+                    .join("/");
+                let key = format!("{BLOCKED_IP_KEY}.{address}");
+                let result = if method == Method::POST {
+                    self.update_settings(
+                        vec![ConfigKey {
+                            key,
+                            value: String::new(),
+                        }],
+                        vec![],
+                    )
+                    .await
+                } else {
+                    match self.store.config_get(key.as_str()).await {
+                        Ok(Some(_)) => self.update_settings(vec![], vec![key]).await,
+                        Ok(None) => {
+                            return RequestError::blank(
+                                StatusCode::NOT_FOUND.as_u16(),
+                                "Not found",
+                                "Address is not blocked.",
+                            )
+                            .into_http_response();
+                        }
+                        Err(err) => Err(SettingsError::Store(err)),
+                    }
+                };
+                match result {
+                    Ok(_) => JsonResponse::new(json!({
+                        "data": [],
+                    }))
+                    .into_http_response(),
+                    Err(err) => map_settings_error(err),
+                }
+            }
+            ("dkim", None, &Method::GET) => match self.effective_config().await {
+                Ok(config) => JsonResponse::new(json!({
+                    "data": config
+                        .sub_keys("signature", ".algorithm")
+                        .map(|id| {
+                            json!({
+                                "id": id,
+                                "algorithm": config.value(("signature", id, "algorithm")),
+                                "domain": config.value(("signature", id, "domain")),
+                                "selector": config.value(("signature", id, "selector")),
+                            })
+                        })
+                        .collect::<Vec<_>>(),
+                }))
+                .into_http_response(),
+                Err(err) => map_settings_error(SettingsError::Store(err)),
+            },
+            ("dkim", Some(id), &Method::POST) if !id.is_empty() => {
+                if let Some(signature) =
+                    body.and_then(|body| serde_json::from_slice::<DkimSignature>(&body).ok())
+                {
+                    // Any previous signature with the same id is replaced
+                    match self
+                        .update_settings(signature.into_keys(id), vec![format!("signature.{id}.")])
+                        .await
+                    {
+                        Ok(_) => JsonResponse::new(json!({
+                            "data": [],
+                        }))
+                        .into_http_response(),
+                        Err(err) => map_settings_error(err),
+                    }
+                } else {
+                    RequestError::blank(
+                        StatusCode::BAD_REQUEST.as_u16(),
+                        "Invalid parameters",
+                        "Failed to deserialize signature",
+                    )
+                    .into_http_response()
+                }
+            }
+            ("dkim", Some(id), &Method::DELETE) if !id.is_empty() => {
+                match self
+                    .update_settings(vec![], vec![format!("signature.{id}.")])
+                    .await
+                {
+                    Ok(_) => JsonResponse::new(json!({
+                        "data": [],
+                    }))
+                    .into_http_response(),
+                    Err(err) => map_settings_error(err),
+                }
+            }
             (path_1 @ ("queue" | "report"), Some(path_2), &Method::GET) => {
                 self.smtp
+                    .load_full()
                     .handle_manage_request(req.uri(), req.method(), path_1, path_2)
                     .await
             }
@@ -399,6 +910,23 @@ impl JMAP {
     }
 }
 
+fn map_settings_error(err: SettingsError) -> hyper::Response<BoxBody<Bytes, hyper::Error>> {
+    match err {
+        SettingsError::Invalid(details) => RequestError::blank(
+            StatusCode::BAD_REQUEST.as_u16(),
+            "Invalid configuration",
+            details,
+        )
+        .into_http_response(),
+        SettingsError::Store(err) => RequestError::blank(
+            StatusCode::INTERNAL_SERVER_ERROR.as_u16(),
+            "Config update failed",
+            err.to_string(),
+        )
+        .into_http_response(),
+    }
+}
+
 fn map_directory_error(err: DirectoryError) -> hyper::Response<BoxBody<Bytes, hyper::Error>> {
     match err {
         DirectoryError::Management(err) => {
@@ -445,6 +973,37 @@ fn map_directory_error(err: DirectoryError) -> hyper::Response<BoxBody<Bytes, hy
     }
 }
 
+impl DkimSignature {
+    fn into_keys(self, id: &str) -> Vec<ConfigKey> {
+        let mut keys = vec![
+            ("algorithm", self.algorithm),
+            ("domain", self.domain),
+            ("selector", self.selector),
+            ("private-key", self.private_key),
+        ];
+        if let Some(public_key) = self.public_key {
+            keys.push(("public-key", public_key));
+        }
+        if let Some(canonicalization) = self.canonicalization {
+            keys.push(("canonicalization", canonicalization));
+        }
+        let mut keys = keys
+            .into_iter()
+            .map(|(name, value)| ConfigKey {
+                key: format!("signature.{id}.{name}"),
+                value,
+            })
+            .collect::<Vec<_>>();
+        for (pos, header) in self.headers.into_iter().enumerate() {
+            keys.push(ConfigKey {
+                key: format!("signature.{id}.headers.{pos:04}"),
+                value: header,
+            });
+        }
+        keys
+    }
+}
+
 impl From<Principal<String>> for PrincipalResponse {
     fn from(principal: Principal<String>) -> Self {
         PrincipalResponse {
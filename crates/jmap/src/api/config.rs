@@ -86,6 +86,7 @@ impl crate::Config {
             mail_max_size: settings
                 .property("jmap.email.max-size")?
                 .unwrap_or(75000000),
+            mail_max_messages: settings.property("jmap.email.max-messages")?.unwrap_or(0),
             mail_parse_max_items: settings
                 .property("jmap.email.parse.max-items")?
                 .unwrap_or(10),
@@ -95,6 +96,26 @@ impl crate::Config {
             sieve_max_scripts: settings
                 .property("sieve.untrusted.limits.max-scripts")?
                 .unwrap_or(256),
+            sieve_notify_rate: settings
+                .property_or_static("sieve.untrusted.notify.rate", "10/1h")?,
+            contact_max_size: settings
+                .property("jmap.contact.max-size")?
+                .unwrap_or(102400),
+            contact_max_address_books: settings
+                .property("jmap.contact.max-address-books")?
+                .unwrap_or(100),
+            address_book_name_max_len: settings
+                .property("jmap.contact.max-name-length")?
+                .unwrap_or(255),
+            calendar_max_size: settings
+                .property("jmap.calendar.max-size")?
+                .unwrap_or(524288),
+            calendar_max_calendars: settings
+                .property("jmap.calendar.max-calendars")?
+                .unwrap_or(100),
+            calendar_name_max_len: settings
+                .property("jmap.calendar.max-name-length")?
+                .unwrap_or(255),
             capabilities: BaseCapabilities::default(),
             session_cache_ttl: settings
                 .property("jmap.session.cache.ttl")?
@@ -104,6 +125,8 @@ impl crate::Config {
             rate_authenticate_req: settings
                 .property_or_static("jmap.rate-limit.authentication", "10/1m")?,
             rate_anonymous: settings.property_or_static("jmap.rate-limit.anonymous", "100/1m")?,
+            rate_registration: settings
+                .property_or_static("oauth.oidc.registration.rate-limit", "10/1h")?,
             rate_use_forwarded: settings
                 .property("jmap.rate-limit.use-forwarded")?
                 .unwrap_or(false),
@@ -132,6 +155,9 @@ impl crate::Config {
                 .property_or_static::<Duration>("oauth.expiry.refresh-token-renew", "4d")?
                 .as_secs(),
             oauth_max_auth_attempts: settings.property_or_static("oauth.auth.max-attempts", "3")?,
+            oauth_oidc_key: settings.text_file_contents("oauth.oidc.signature-key")?,
+            oauth_oidc_registration_token: settings
+                .text_file_contents("oauth.oidc.registration.token")?,
             event_source_throttle: settings
                 .property_or_static("jmap.event-source.throttle", "1s")?,
             web_socket_throttle: settings.property_or_static("jmap.web-socket.throttle", "1s")?,
@@ -141,6 +167,20 @@ impl crate::Config {
             principal_allow_lookups: settings
                 .property("jmap.principal.allow-lookups")?
                 .unwrap_or(true),
+            list_base_url: settings
+                .value("jmap.mailing-list.url")
+                .map(|url| url.trim_end_matches('/').to_string())
+                .or_else(|| {
+                    settings
+                        .value("server.hostname")
+                        .map(|hostname| format!("https://{hostname}"))
+                }),
+            list_moderation_expiry: settings
+                .property_or_static::<Duration>("jmap.mailing-list.moderation.expiry", "7d")?
+                .as_secs(),
+            list_confirm_expiry: settings
+                .property_or_static::<Duration>("jmap.mailing-list.confirm.expiry", "2d")?
+                .as_secs(),
             encrypt: settings.property_or_static("storage.encryption.enable", "true")?,
             encrypt_append: settings.property_or_static("storage.encryption.append", "false")?,
             spam_header: settings.value("storage.spam.header").and_then(|v| {
@@ -151,6 +191,10 @@ impl crate::Config {
                     )
                 })
             }),
+            spam_learn: settings.property_or_static("storage.spam.bayes.learn", "true")?,
+            spam_account_model: settings
+                .property_or_static("storage.spam.bayes.account-model", "false")?,
+            spam_threshold: settings.property_or_static("storage.spam.bayes.threshold", "0.9")?,
             http_headers: settings
                 .values("jmap.http.headers")
                 .map(|(_, v)| {
@@ -39,11 +39,18 @@ use jmap_proto::{
     types::{blob::BlobId, id::Id},
 };
 
-use utils::listener::{ServerInstance, SessionData, SessionManager, SessionStream};
+use utils::{
+    listener::{ServerInstance, SessionData, SessionManager, SessionStream},
+    metrics::{prometheus_metrics, PrometheusResult},
+};
 
 use crate::{
-    auth::{oauth::OAuthMetadata, AccessToken},
+    auth::{
+        oauth::{oidc::OpenIdMetadata, OAuthMetadata},
+        AccessToken,
+    },
     blob::{DownloadResponse, UploadResponse},
+    dav::DavResponse,
     services::state,
     websocket::upgrade::upgrade_websocket_connection,
     JMAP,
@@ -80,16 +87,20 @@ pub async fn parse_jmap_request(
 
             match (path.next().unwrap_or(""), req.method()) {
                 ("", &Method::POST) => {
-                    return match fetch_body(&mut req, jmap.config.request_max_size, &access_token)
-                        .await
-                        .ok_or_else(|| RequestError::limit(RequestLimitError::SizeRequest))
-                        .and_then(|bytes| {
-                            Request::parse(
-                                &bytes,
-                                jmap.config.request_max_calls,
-                                jmap.config.request_max_size,
-                            )
-                        }) {
+                    return match fetch_body(
+                        &mut req,
+                        jmap.config.load().request_max_size,
+                        &access_token,
+                    )
+                    .await
+                    .ok_or_else(|| RequestError::limit(RequestLimitError::SizeRequest))
+                    .and_then(|bytes| {
+                        Request::parse(
+                            &bytes,
+                            jmap.config.load().request_max_calls,
+                            jmap.config.load().request_max_size,
+                        )
+                    }) {
                         Ok(request) => {
                             //let _ = println!("<- {}", String::from_utf8_lossy(&bytes));
 
@@ -132,7 +143,7 @@ pub async fn parse_jmap_request(
                     {
                         return match fetch_body(
                             &mut req,
-                            jmap.config.upload_max_size,
+                            jmap.config.load().upload_max_size,
                             &access_token,
                         )
                         .await
@@ -172,36 +183,99 @@ pub async fn parse_jmap_request(
                 _ => (),
             }
         }
-        ".well-known" => match (path.next().unwrap_or(""), req.method()) {
-            ("jmap", &Method::GET) => {
-                // Authenticate request
-                let (_in_flight, access_token) =
-                    match jmap.authenticate_headers(&req, remote_ip).await {
-                        Ok(Some(session)) => session,
-                        Ok(None) => return RequestError::unauthorized().into_http_response(),
-                        Err(err) => return err.into_http_response(),
-                    };
+        ".well-known" => {
+            match (path.next().unwrap_or(""), req.method()) {
+                ("jmap", &Method::GET) => {
+                    // Authenticate request
+                    let (_in_flight, access_token) =
+                        match jmap.authenticate_headers(&req, remote_ip).await {
+                            Ok(Some(session)) => session,
+                            Ok(None) => return RequestError::unauthorized().into_http_response(),
+                            Err(err) => return err.into_http_response(),
+                        };
 
-                return match jmap.handle_session_resource(instance, access_token).await {
-                    Ok(session) => session.into_http_response(),
-                    Err(err) => err.into_http_response(),
-                };
-            }
-            ("oauth-authorization-server", &Method::GET) => {
-                let remote_addr = jmap.build_remote_addr(&req, remote_ip);
-                // Limit anonymous requests
-                return match jmap.is_anonymous_allowed(&remote_addr) {
-                    Ok(_) => {
-                        JsonResponse::new(OAuthMetadata::new(&instance.data)).into_http_response()
-                    }
-                    Err(err) => err.into_http_response(),
-                };
-            }
-            (_, &Method::OPTIONS) => {
-                return ().into_http_response();
+                    return match jmap.handle_session_resource(instance, access_token).await {
+                        Ok(session) => session.into_http_response(),
+                        Err(err) => err.into_http_response(),
+                    };
+                }
+                ("carddav", _) => {
+                    return DavResponse::redirect("/dav/card/").into_http_response();
+                }
+                ("caldav", _) => {
+                    return DavResponse::redirect("/dav/cal/").into_http_response();
+                }
+                ("openid-configuration", &Method::GET) => {
+                    let remote_addr = jmap.build_remote_addr(&req, remote_ip);
+                    // Limit anonymous requests
+                    return match jmap.is_anonymous_allowed(&remote_addr) {
+                        Ok(_) => JsonResponse::new(OpenIdMetadata::new(&instance.data))
+                            .into_http_response(),
+                        Err(err) => err.into_http_response(),
+                    };
+                }
+                ("acme-challenge", &Method::GET) => {
+                    let token = path.next().unwrap_or_default();
+                    return match jmap
+                        .settings
+                        .acme_managers
+                        .iter()
+                        .find_map(|acme| acme.http_challenge(token))
+                    {
+                        Some(key_authorization) => hyper::Response::builder()
+                            .status(StatusCode::OK)
+                            .header(header::CONTENT_TYPE, "application/octet-stream")
+                            .body(
+                                Full::new(Bytes::from(key_authorization))
+                                    .map_err(|never| match never {})
+                                    .boxed(),
+                            )
+                            .unwrap(),
+                        None => RequestError::not_found().into_http_response(),
+                    };
+                }
+                ("oauth-authorization-server", &Method::GET) => {
+                    let remote_addr = jmap.build_remote_addr(&req, remote_ip);
+                    // Limit anonymous requests
+                    return match jmap.is_anonymous_allowed(&remote_addr) {
+                        Ok(_) => JsonResponse::new(OAuthMetadata::new(&instance.data))
+                            .into_http_response(),
+                        Err(err) => err.into_http_response(),
+                    };
+                }
+                (_, &Method::OPTIONS) => {
+                    return ().into_http_response();
+                }
+                _ => (),
             }
-            _ => (),
-        },
+        }
+        "dav" => {
+            // Authenticate request
+            let (_in_flight, access_token) = match jmap.authenticate_headers(&req, remote_ip).await
+            {
+                Ok(Some(session)) => session,
+                Ok(None) => return DavResponse::unauthorized().into_http_response(),
+                Err(err) => return err.into_http_response(),
+            };
+
+            let resource = path.next();
+            let path = path
+                .filter(|p| !p.is_empty())
+                .map(|p| p.to_string())
+                .collect::<Vec<_>>();
+            let response = match resource {
+                Some("card") => {
+                    jmap.handle_card_request(&mut req, path, &access_token)
+                        .await
+                }
+                Some("cal") => jmap.handle_cal_request(&mut req, path, &access_token).await,
+                _ => return RequestError::not_found().into_http_response(),
+            };
+            return match response {
+                Ok(response) => response.into_http_response(),
+                Err(_) => RequestError::internal_server_error().into_http_response(),
+            };
+        }
         "auth" => {
             let remote_addr = jmap.build_remote_addr(&req, remote_ip);
 
@@ -239,9 +313,33 @@ pub async fn parse_jmap_request(
                         Err(err) => err.into_http_response(),
                     }
                 }
+                ("jwks.json", &Method::GET) => {
+                    return match jmap.is_anonymous_allowed(&remote_addr) {
+                        Ok(_) => jmap.handle_oidc_jwks().await,
+                        Err(err) => err.into_http_response(),
+                    }
+                }
+                ("register", &Method::POST) => {
+                    return match jmap.is_registration_allowed(&remote_addr) {
+                        Ok(_) => {
+                            jmap.handle_oidc_client_registration(&mut req, remote_ip)
+                                .await
+                        }
+                        Err(err) => err.into_http_response(),
+                    }
+                }
+                ("userinfo", &Method::GET | &Method::POST) => {
+                    return match jmap.authenticate_headers(&req, remote_ip).await {
+                        Ok(Some((_in_flight, access_token))) => {
+                            jmap.handle_oidc_userinfo(&access_token).await
+                        }
+                        Ok(None) => RequestError::unauthorized().into_http_response(),
+                        Err(err) => err.into_http_response(),
+                    }
+                }
                 ("token", &Method::POST) => {
                     return match jmap.is_anonymous_allowed(&remote_addr) {
-                        Ok(_) => jmap.handle_token_request(&mut req).await,
+                        Ok(_) => jmap.handle_token_request(&mut req, instance).await,
                         Err(err) => err.into_http_response(),
                     }
                 }
@@ -251,7 +349,19 @@ pub async fn parse_jmap_request(
                 _ => (),
             }
         }
-        "crypto" if jmap.config.encrypt => {
+        "list" => {
+            if let (Some("unsubscribe"), Some(token), &Method::GET | &Method::POST) =
+                (path.next(), path.next(), req.method())
+            {
+                let token = token.to_string();
+                let remote_addr = jmap.build_remote_addr(&req, remote_ip);
+                return match jmap.is_anonymous_allowed(&remote_addr) {
+                    Ok(_) => jmap.handle_list_unsubscribe(&mut req, &token).await,
+                    Err(err) => err.into_http_response(),
+                };
+            }
+        }
+        "crypto" if jmap.config.load().encrypt => {
             let remote_addr = jmap.build_remote_addr(&req, remote_ip);
 
             match *req.method() {
@@ -279,6 +389,14 @@ pub async fn parse_jmap_request(
 
             return jmap.handle_manage_request(&req, body).await;
         }
+        "metrics" if req.method() == Method::GET => {
+            return prometheus_metrics(
+                req.headers()
+                    .get(header::AUTHORIZATION)
+                    .and_then(|h| h.to_str().ok()),
+            )
+            .into_http_response();
+        }
         _ => (),
     }
     RequestError::not_found().into_http_response()
@@ -325,10 +443,10 @@ async fn handle_request<T: SessionStream>(jmap: Arc<JMAP>, session: SessionData<
                         parse_jmap_request(jmap.clone(), req, session.remote_ip, instance).await;
 
                     // Add custom headers
-                    if !jmap.config.http_headers.is_empty() {
+                    if !jmap.config.load().http_headers.is_empty() {
                         let headers = response.headers_mut();
 
-                        for (header, value) in &jmap.config.http_headers {
+                        for (header, value) in &jmap.config.load().http_headers {
                             headers.insert(header.clone(), value.clone());
                         }
                     }
@@ -485,6 +603,24 @@ impl ToHttpResponse for HtmlResponse {
     }
 }
 
+impl ToHttpResponse for PrometheusResult {
+    fn into_http_response(self) -> HttpResponse {
+        match self {
+            PrometheusResult::Metrics(metrics) => hyper::Response::builder()
+                .status(StatusCode::OK)
+                .header(header::CONTENT_TYPE, "text/plain; version=0.0.4")
+                .body(
+                    Full::new(Bytes::from(metrics))
+                        .map_err(|never| match never {})
+                        .boxed(),
+                )
+                .unwrap(),
+            PrometheusResult::Unauthorized => RequestError::unauthorized().into_http_response(),
+            PrometheusResult::Disabled => RequestError::not_found().into_http_response(),
+        }
+    }
+}
+
 impl ToHttpResponse for () {
     fn into_http_response(self) -> HttpResponse {
         hyper::Response::builder()
@@ -36,6 +36,7 @@ pub mod event_source;
 pub mod http;
 pub mod request;
 pub mod session;
+pub mod settings;
 
 #[derive(Clone)]
 pub struct JmapSessionManager {
@@ -21,7 +21,7 @@
  * for more details.
 */
 
-use std::sync::Arc;
+use std::{sync::Arc, time::Instant};
 
 use jmap_proto::{
     error::{method::MethodError, request::RequestError},
@@ -33,7 +33,10 @@ use jmap_proto::{
     response::{Response, ResponseMethod},
     types::collection::Collection,
 };
-use utils::listener::ServerInstance;
+use utils::{
+    listener::ServerInstance,
+    metrics::{metrics, KeyValue},
+};
 
 use crate::{auth::AccessToken, JMAP};
 
@@ -62,10 +65,15 @@ impl JMAP {
                 let mut next_call = None;
 
                 // Add response
-                match self
+                let method_start = Instant::now();
+                let method_response = self
                     .handle_method_call(call.method, &access_token, &mut next_call, instance)
-                    .await
-                {
+                    .await;
+                metrics().jmap_method_duration.record(
+                    method_start.elapsed().as_secs_f64(),
+                    &[KeyValue::new("method", call.name.as_str())],
+                );
+                match method_response {
                     Ok(mut method_response) => {
                         match &mut method_response {
                             ResponseMethod::Set(set_response) => {
@@ -173,7 +181,7 @@ impl JMAP {
                     self.vacation_response_get(req).await?.into()
                 }
                 get::RequestArguments::Principal => {
-                    if self.config.principal_allow_lookups || access_token.is_super_user() {
+                    if self.config.load().principal_allow_lookups || access_token.is_super_user() {
                         self.principal_get(req).await?.into()
                     } else {
                         return Err(MethodError::Forbidden(
@@ -186,6 +194,26 @@ impl JMAP {
 
                     self.quota_get(req, access_token).await?.into()
                 }
+                get::RequestArguments::AddressBook => {
+                    access_token.assert_is_member(req.account_id)?;
+
+                    self.address_book_get(req).await?.into()
+                }
+                get::RequestArguments::ContactCard => {
+                    access_token.assert_is_member(req.account_id)?;
+
+                    self.contact_card_get(req).await?.into()
+                }
+                get::RequestArguments::Calendar => {
+                    access_token.assert_is_member(req.account_id)?;
+
+                    self.calendar_get(req).await?.into()
+                }
+                get::RequestArguments::CalendarEvent => {
+                    access_token.assert_is_member(req.account_id)?;
+
+                    self.calendar_event_get(req).await?.into()
+                }
                 get::RequestArguments::Blob(arguments) => {
                     access_token.assert_is_member(req.account_id)?;
 
@@ -220,7 +248,7 @@ impl JMAP {
                     self.sieve_script_query(req).await?.into()
                 }
                 query::RequestArguments::Principal => {
-                    if self.config.principal_allow_lookups || access_token.is_super_user() {
+                    if self.config.load().principal_allow_lookups || access_token.is_super_user() {
                         self.principal_query(req).await?.into()
                     } else {
                         return Err(MethodError::Forbidden(
@@ -233,6 +261,16 @@ impl JMAP {
 
                     self.quota_query(req, access_token).await?.into()
                 }
+                query::RequestArguments::ContactCard => {
+                    access_token.assert_is_member(req.account_id)?;
+
+                    self.contact_card_query(req).await?.into()
+                }
+                query::RequestArguments::CalendarEvent => {
+                    access_token.assert_is_member(req.account_id)?;
+
+                    self.calendar_event_query(req).await?.into()
+                }
             },
             RequestMethod::Set(mut req) => match req.take_arguments() {
                 set::RequestArguments::Email => {
@@ -274,6 +312,32 @@ impl JMAP {
 
                     self.vacation_response_set(req).await?.into()
                 }
+                set::RequestArguments::AddressBook(arguments) => {
+                    access_token.assert_is_member(req.account_id)?;
+
+                    self.address_book_set(req.with_arguments(arguments))
+                        .await?
+                        .into()
+                }
+                set::RequestArguments::ContactCard => {
+                    access_token.assert_is_member(req.account_id)?;
+
+                    self.contact_card_set(req).await?.into()
+                }
+                set::RequestArguments::Calendar(arguments) => {
+                    access_token.assert_is_member(req.account_id)?;
+
+                    self.calendar_set(req.with_arguments(arguments))
+                        .await?
+                        .into()
+                }
+                set::RequestArguments::CalendarEvent(arguments) => {
+                    access_token.assert_is_member(req.account_id)?;
+
+                    self.calendar_event_set(req.with_arguments(arguments))
+                        .await?
+                        .into()
+                }
             },
             RequestMethod::Changes(req) => self.changes(req, access_token).await?.into(),
             RequestMethod::Copy(req) => {
@@ -319,6 +383,16 @@ impl JMAP {
 
                 self.blob_upload_many(req, access_token).await?.into()
             }
+            RequestMethod::SendMdn(req) => {
+                access_token.assert_is_member(req.account_id)?;
+
+                self.mdn_send(req, instance, next_call).await?.into()
+            }
+            RequestMethod::ParseMdn(req) => {
+                access_token.assert_has_access(req.account_id, Collection::Email)?;
+
+                self.mdn_parse(req, access_token).await?.into()
+            }
             RequestMethod::Echo(req) => req.into(),
             RequestMethod::Error(error) => return Err(error),
         })
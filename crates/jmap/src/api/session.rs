@@ -83,6 +83,8 @@ pub enum Capabilities {
     SieveAccount(SieveAccountCapabilities),
     SieveSession(SieveSessionCapabilities),
     Blob(BlobCapabilities),
+    Contacts(ContactsCapabilities),
+    Calendars(CalendarsCapabilities),
     Empty(EmptyCapabilities),
 }
 
@@ -174,6 +176,24 @@ pub struct BlobCapabilities {
     supported_digest_algorithms: Vec<&'static str>,
 }
 
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct ContactsCapabilities {
+    #[serde(rename(serialize = "maxAddressBooksPerCard"))]
+    max_address_books_per_card: Option<usize>,
+    #[serde(rename(serialize = "mayCreateAddressBook"))]
+    may_create_address_book: bool,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct CalendarsCapabilities {
+    #[serde(rename(serialize = "maxCalendarsPerEvent"))]
+    max_calendars_per_event: Option<usize>,
+    #[serde(rename(serialize = "maxParticipantsPerEvent"))]
+    max_participants_per_event: Option<usize>,
+    #[serde(rename(serialize = "mayCreateCalendar"))]
+    may_create_calendar: bool,
+}
+
 #[derive(Debug, Clone, Default, serde::Serialize)]
 pub struct EmptyCapabilities {}
 
@@ -189,7 +209,7 @@ impl JMAP {
         instance: Arc<ServerInstance>,
         access_token: Arc<AccessToken>,
     ) -> Result<Session, RequestError> {
-        let mut session = Session::new(&instance.data, &self.config.capabilities);
+        let mut session = Session::new(&instance.data, &self.config.load().capabilities);
         session.set_state(access_token.state());
         session.set_primary_account(
             access_token.primary_id().into(),
@@ -199,13 +219,14 @@ impl JMAP {
                 .clone()
                 .unwrap_or_else(|| access_token.name.clone()),
             None,
-            &self.config.capabilities.account,
+            &self.config.load().capabilities.account,
         );
 
         // Add secondary accounts
         for id in access_token.secondary_ids() {
-            let is_personal = !access_token.is_member(*id);
-            let is_readonly = is_personal
+            // Group accounts are fully writable by their members, accounts
+            // shared through ACLs are read-only unless items can be added
+            let is_readonly = !access_token.is_member(*id)
                 && self
                     .shared_documents(&access_token, *id, Collection::Mailbox, Acl::AddItems)
                     .await
@@ -214,15 +235,16 @@ impl JMAP {
             session.add_account(
                 (*id).into(),
                 self.directory
+                    .load()
                     .query(QueryBy::Id(*id), false)
                     .await
                     .unwrap_or_default()
                     .map(|p| p.name)
                     .unwrap_or_else(|| Id::from(*id).to_string()),
-                is_personal,
+                false,
                 is_readonly,
                 Some(&[Capability::Mail, Capability::Quota, Capability::Blob]),
-                &self.config.capabilities.account,
+                &self.config.load().capabilities.account,
             );
         }
 
@@ -307,6 +329,43 @@ impl crate::Config {
             Capability::Quota,
             Capabilities::Empty(EmptyCapabilities::default()),
         );
+
+        // Add MDN capabilities
+        self.capabilities.session.append(
+            Capability::Mdn,
+            Capabilities::Empty(EmptyCapabilities::default()),
+        );
+        self.capabilities.account.append(
+            Capability::Mdn,
+            Capabilities::Empty(EmptyCapabilities::default()),
+        );
+
+        // Add Contacts capabilities
+        self.capabilities.session.append(
+            Capability::Contacts,
+            Capabilities::Empty(EmptyCapabilities::default()),
+        );
+        self.capabilities.account.append(
+            Capability::Contacts,
+            Capabilities::Contacts(ContactsCapabilities {
+                max_address_books_per_card: None,
+                may_create_address_book: true,
+            }),
+        );
+
+        // Add Calendars capabilities
+        self.capabilities.session.append(
+            Capability::Calendars,
+            Capabilities::Empty(EmptyCapabilities::default()),
+        );
+        self.capabilities.account.append(
+            Capability::Calendars,
+            Capabilities::Calendars(CalendarsCapabilities {
+                max_calendars_per_event: None,
+                max_participants_per_event: None,
+                may_create_calendar: true,
+            }),
+        );
     }
 }
 
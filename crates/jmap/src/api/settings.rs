@@ -0,0 +1,304 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::{net::SocketAddr, sync::Arc};
+
+use arc_swap::ArcSwap;
+use directory::{core::config::ConfigDirectory, Directories};
+use smtp::{core::SMTP, queue, reporting};
+use store::{ahash::AHashMap, config::ConfigStore, Stores};
+use utils::{
+    acme::AcmeManager,
+    config::{Config, ConfigKey, Server, Servers},
+    listener::{blocked::BlockedIps, TcpAcceptor},
+};
+
+use crate::JMAP;
+
+pub struct Settings {
+    pub base: ArcSwap<Config>,
+    pub stores: ArcSwap<Stores>,
+    pub directories: ArcSwap<Directories>,
+    pub listeners: Vec<ListenerInfo>,
+    pub acceptors: AHashMap<String, Arc<ArcSwap<TcpAcceptor>>>,
+    pub acme_managers: Vec<Arc<AcmeManager>>,
+}
+
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+pub struct ListenerInfo {
+    pub id: String,
+    pub protocol: String,
+    pub hostname: String,
+    pub bind: Vec<SocketAddr>,
+    pub tls: bool,
+    #[serde(rename = "tlsImplicit")]
+    pub tls_implicit: bool,
+    #[serde(rename = "maxConnections")]
+    pub max_connections: u64,
+}
+
+#[derive(Debug)]
+pub enum SettingsError {
+    Invalid(String),
+    Store(store::Error),
+}
+
+impl Settings {
+    pub async fn new(
+        config: &Config,
+        stores: &Stores,
+        directories: &Directories,
+        servers: &Servers,
+    ) -> Result<Self, String> {
+        // The settings stored in the database are applied on top of the configuration
+        // file, keep the latter so the result of any change can be recomputed.
+        let mut base = config.clone();
+        for key in stores
+            .get_store(config, "storage.data")?
+            .config_list("")
+            .await
+            .map_err(|err| format!("Failed to read settings: {err}"))?
+            .keys
+            .into_keys()
+        {
+            base.keys.remove(&key);
+        }
+
+        Ok(Settings {
+            base: ArcSwap::from_pointee(base),
+            stores: ArcSwap::from_pointee(stores.clone()),
+            directories: ArcSwap::from_pointee(directories.clone()),
+            listeners: servers.inner.iter().map(ListenerInfo::from).collect(),
+            acceptors: servers
+                .inner
+                .iter()
+                .map(|server| (server.id.clone(), server.acceptor.clone()))
+                .collect(),
+            acme_managers: servers.acme_managers.clone(),
+        })
+    }
+}
+
+impl JMAP {
+    pub async fn effective_config(&self) -> store::Result<Config> {
+        let mut config = self.settings.base.load().as_ref().clone();
+        config.update(self.store.config_list("").await?);
+        Ok(config)
+    }
+
+    // Reads the configuration file again and applies the settings stored in the
+    // database on top of it. Nothing is replaced unless the whole configuration
+    // parses, sessions that are already in progress keep using the previous one.
+    pub async fn reload_config(&self) -> Result<(), SettingsError> {
+        let base = match Config::file_path() {
+            Some(path) => Config::read_file(path).map_err(SettingsError::Invalid)?,
+            None => self.settings.base.load().as_ref().clone(),
+        };
+        let mut config = base.clone();
+        config.update(
+            self.store
+                .config_list("")
+                .await
+                .map_err(SettingsError::Store)?,
+        );
+
+        // Parse the new configuration
+        let mut servers = config.parse_servers().map_err(SettingsError::Invalid)?;
+        servers.blocked_ips = self.directory.load().blocked_ips.clone();
+        BlockedIps::new()
+            .reload(&config)
+            .map_err(SettingsError::Invalid)?;
+        let jmap_config = crate::Config::new(&config).map_err(SettingsError::Invalid)?;
+        let stores = config
+            .reload_stores(&self.settings.stores.load_full())
+            .await
+            .map_err(SettingsError::Invalid)?;
+        let directories = config
+            .parse_directory(&stores, &servers, self.store.clone())
+            .await
+            .map_err(SettingsError::Invalid)?;
+        let directory = config
+            .value_require("storage.directory")
+            .and_then(|id| {
+                directories
+                    .directories
+                    .get(id)
+                    .cloned()
+                    .ok_or_else(|| format!("Unable to find directory {id:?}."))
+            })
+            .map_err(SettingsError::Invalid)?;
+        let smtp = Arc::new(
+            self.smtp
+                .load()
+                .reload(&config, &servers, &stores, &directories)
+                .map_err(SettingsError::Invalid)?,
+        );
+
+        // Replace the running configuration
+        self.config.store(Arc::new(jmap_config));
+        self.directory.store(directory);
+        self.smtp.store(smtp.clone());
+        let _ = smtp.queue.tx.send(queue::Event::Reload(smtp.clone())).await;
+        let _ = smtp
+            .report
+            .tx
+            .send(reporting::Event::Reload(smtp.clone()))
+            .await;
+        self.directory
+            .load()
+            .blocked_ips
+            .reload(&config)
+            .map_err(SettingsError::Invalid)?;
+
+        // New TLS settings apply to new connections, turning TLS on or off for a
+        // listener or moving it to ACME requires a restart.
+        for server in servers.inner {
+            let new_acceptor = server.acceptor.load_full();
+            match self.settings.acceptors.get(&server.id) {
+                Some(acceptor)
+                    if matches!(
+                        (acceptor.load().as_ref(), new_acceptor.as_ref()),
+                        (TcpAcceptor::Tls { .. }, TcpAcceptor::Tls { .. })
+                    ) =>
+                {
+                    acceptor.store(new_acceptor);
+                }
+                Some(acceptor)
+                    if std::mem::discriminant(acceptor.load().as_ref())
+                        == std::mem::discriminant(new_acceptor.as_ref()) => {}
+                _ => {
+                    tracing::warn!(
+                        context = "config",
+                        event = "reload",
+                        listener = server.id,
+                        "Listener changes will take effect after a restart."
+                    );
+                }
+            }
+        }
+
+        self.settings.base.store(Arc::new(base));
+        self.settings.stores.store(Arc::new(stores));
+        self.settings.directories.store(Arc::new(directories));
+
+        tracing::info!(
+            context = "config",
+            event = "reload",
+            "Configuration reloaded."
+        );
+
+        Ok(())
+    }
+
+    // Applies changes to the settings stored in the database after validating
+    // the resulting configuration. Keys to clear that end in a dot remove all
+    // the settings under that prefix.
+    pub async fn update_settings(
+        &self,
+        set: Vec<ConfigKey>,
+        clear: Vec<String>,
+    ) -> Result<(), SettingsError> {
+        let mut config = self
+            .effective_config()
+            .await
+            .map_err(SettingsError::Store)?;
+        for key in &clear {
+            if key.ends_with('.') {
+                config.keys.retain(|k, _| !k.starts_with(key.as_str()));
+            } else {
+                config.keys.remove(key);
+            }
+        }
+        for key in &set {
+            config.keys.insert(key.key.clone(), key.value.clone());
+        }
+        self.validate_config(&config)
+            .map_err(SettingsError::Invalid)?;
+
+        for key in clear {
+            if key.ends_with('.') {
+                self.store.config_clear_prefix(key).await
+            } else {
+                self.store.config_clear(key).await
+            }
+            .map_err(SettingsError::Store)?;
+        }
+        if !set.is_empty() {
+            self.store
+                .config_set(set.into_iter())
+                .await
+                .map_err(SettingsError::Store)?;
+        }
+
+        // Blocked addresses take effect immediately
+        self.directory
+            .load()
+            .blocked_ips
+            .reload_blocked_ips(&config)
+            .map_err(SettingsError::Invalid)
+    }
+
+    pub fn validate_config(&self, config: &Config) -> Result<(), String> {
+        let servers = config.parse_servers()?;
+        BlockedIps::new().reload(config)?;
+        crate::Config::new(config)?;
+        SMTP::validate_config(
+            config,
+            &servers,
+            &self.settings.stores.load(),
+            &self.settings.directories.load(),
+        )
+    }
+}
+
+impl From<&Server> for ListenerInfo {
+    fn from(server: &Server) -> Self {
+        ListenerInfo {
+            id: server.id.clone(),
+            protocol: server.protocol.to_string(),
+            hostname: server.hostname.clone(),
+            bind: server.listeners.iter().map(|l| l.addr).collect(),
+            tls: server.acceptor.load().is_tls(),
+            tls_implicit: server.tls_implicit,
+            max_connections: server.max_connections,
+        }
+    }
+}
+
+// Credentials are never returned by the settings API, only whether they are set
+pub const REDACTED_VALUE: &str = "********";
+
+pub fn is_secret_key(key: &str) -> bool {
+    key == "oauth.key"
+        || key.split('.').any(|part| {
+            matches!(
+                part,
+                "secret"
+                    | "password"
+                    | "private-key"
+                    | "secret-key"
+                    | "security-token"
+                    | "signature-key"
+            )
+        })
+}
@@ -44,8 +44,33 @@ use crate::JMAP;
 
 use super::AccessToken;
 
+const MAX_NESTED_GROUPS: usize = 256;
+
 impl JMAP {
     pub async fn update_access_token(&self, mut access_token: AccessToken) -> Option<AccessToken> {
+        // Members of a group are also members of the groups it belongs to
+        let mut pos = 0;
+        while pos < access_token.member_of.len() && access_token.member_of.len() < MAX_NESTED_GROUPS
+        {
+            if let Some(group) = self
+                .directory
+                .load()
+                .query(QueryBy::Id(access_token.member_of[pos]), true)
+                .await
+                .ok()
+                .flatten()
+            {
+                for member_of in group.member_of {
+                    if member_of != access_token.primary_id
+                        && !access_token.member_of.contains(&member_of)
+                    {
+                        access_token.member_of.push(member_of);
+                    }
+                }
+            }
+            pos += 1;
+        }
+
         for &grant_account_id in [access_token.primary_id]
             .iter()
             .chain(access_token.member_of.clone().iter())
@@ -351,6 +376,7 @@ impl JMAP {
             for item in value {
                 if let Some(principal) = self
                     .directory
+                    .load()
                     .query(QueryBy::Id(item.account_id), false)
                     .await
                     .unwrap_or_default()
@@ -420,6 +446,7 @@ impl JMAP {
             if let (Value::Text(account_name), Value::UnsignedInt(grants)) = (&item[0], &item[1]) {
                 match self
                     .directory
+                    .load()
                     .query(QueryBy::Name(account_name), false)
                     .await
                 {
@@ -459,6 +486,7 @@ impl JMAP {
         {
             match self
                 .directory
+                .load()
                 .query(QueryBy::Name(account_name), false)
                 .await
             {
@@ -23,12 +23,15 @@
 
 use std::{net::IpAddr, sync::Arc, time::Instant};
 
-use directory::{AuthResult, QueryBy};
+use directory::{
+    core::{scram::ScramSession, totp::TotpStore},
+    AuthResult, Principal, QueryBy,
+};
 use hyper::header;
 use jmap_proto::error::request::RequestError;
 use mail_parser::decoders::base64::base64_decode;
 use mail_send::Credentials;
-use utils::{listener::limiter::InFlight, map::ttl_dashmap::TtlMap};
+use utils::{config::ServerProtocol, listener::limiter::InFlight, map::ttl_dashmap::TtlMap};
 
 use crate::JMAP;
 
@@ -63,8 +66,9 @@ impl JMAP {
                             })
                         })
                     {
-                        if let AuthResult::Success(access_token) =
-                            self.authenticate_plain(&account, &secret, addr).await
+                        if let AuthResult::Success(access_token) = self
+                            .authenticate_plain(&account, &secret, addr, ServerProtocol::Jmap)
+                            .await
                         {
                             Some(access_token)
                         } else {
@@ -82,16 +86,13 @@ impl JMAP {
                     // Enforce anonymous rate limit for bearer auth requests
                     self.is_anonymous_allowed(&addr)?;
 
-                    match self.validate_access_token("access_token", &token).await {
-                        Ok((account_id, _, _)) => self.get_access_token(account_id).await,
-                        Err(err) => {
-                            tracing::debug!(
-                                context = "authenticate_headers",
-                                err = err,
-                                "Failed to validate access token."
-                            );
-                            None
-                        }
+                    if let AuthResult::Success(access_token) = self
+                        .authenticate_bearer(&token, addr, ServerProtocol::Jmap)
+                        .await
+                    {
+                        Some(access_token)
+                    } else {
+                        None
                     }
                 } else {
                     // Enforce anonymous rate limit
@@ -124,7 +125,7 @@ impl JMAP {
         self.sessions.insert_with_ttl(
             session_id,
             access_token.primary_id(),
-            Instant::now() + self.config.session_cache_ttl,
+            Instant::now() + self.config.load().session_cache_ttl,
         );
     }
 
@@ -132,7 +133,7 @@ impl JMAP {
         self.access_tokens.insert_with_ttl(
             access_token.primary_id(),
             access_token,
-            Instant::now() + self.config.session_cache_ttl,
+            Instant::now() + self.config.load().session_cache_ttl,
         );
     }
 
@@ -154,7 +155,7 @@ impl JMAP {
         req: &hyper::Request<hyper::body::Incoming>,
         remote_ip: IpAddr,
     ) -> IpAddr {
-        if !self.config.rate_use_forwarded {
+        if !self.config.load().rate_use_forwarded {
             remote_ip
         } else if let Some(forwarded_for) = req
             .headers()
@@ -175,20 +176,139 @@ impl JMAP {
         username: &str,
         secret: &str,
         remote_ip: IpAddr,
+        protocol: ServerProtocol,
+    ) -> AuthResult<AccessToken> {
+        let credentials = Credentials::Plain {
+            username: username.to_string(),
+            secret: secret.to_string(),
+        };
+        let result = self
+            .directory
+            .load()
+            .authenticate(&credentials, remote_ip, protocol, true)
+            .await;
+        self.map_plain_auth_result(result, remote_ip).await
+    }
+
+    // Interactive logins accept the account password of accounts with two-factor
+    // authentication enabled, provided that a valid second factor is also supplied.
+    pub async fn authenticate_interactive(
+        &self,
+        username: &str,
+        secret: &str,
+        otp: Option<&str>,
+        remote_ip: IpAddr,
     ) -> AuthResult<AccessToken> {
+        let credentials = Credentials::Plain {
+            username: username.to_string(),
+            secret: secret.to_string(),
+        };
+        let result = match self
+            .directory
+            .load()
+            .authenticate_first_factor(&credentials, remote_ip, ServerProtocol::Http, true)
+            .await
+        {
+            Ok(AuthResult::Success(principal))
+                if !self.verify_second_factor(principal.id, otp).await =>
+            {
+                Ok(AuthResult::Failure)
+            }
+            result => result,
+        };
+        self.map_plain_auth_result(result, remote_ip).await
+    }
+
+    async fn map_plain_auth_result(
+        &self,
+        result: directory::Result<AuthResult<Principal<u32>>>,
+        remote_ip: IpAddr,
+    ) -> AuthResult<AccessToken> {
+        match result {
+            Ok(AuthResult::Success(principal)) => self
+                .update_access_token(AccessToken::new(principal))
+                .await
+                .map_or(AuthResult::Failure, AuthResult::Success),
+            Ok(AuthResult::Failure) => {
+                let _ = self.is_auth_allowed_hard(&remote_ip);
+                AuthResult::Failure
+            }
+            Ok(AuthResult::Banned) => AuthResult::Banned,
+            Err(_) => AuthResult::Failure,
+        }
+    }
+
+    // Validates bearer tokens issued by this server and, when configured,
+    // by the directory's external OpenID Connect provider.
+    pub async fn authenticate_bearer(
+        &self,
+        token: &str,
+        remote_ip: IpAddr,
+        protocol: ServerProtocol,
+    ) -> AuthResult<AccessToken> {
+        match self.validate_access_token("access_token", token).await {
+            Ok((account_id, _, _)) => {
+                return self
+                    .get_access_token(account_id)
+                    .await
+                    .map_or(AuthResult::Failure, AuthResult::Success);
+            }
+            Err(err) => {
+                tracing::debug!(
+                    context = "authenticate",
+                    err = err,
+                    "Failed to validate access token."
+                );
+                if self.directory.load().oidc.is_none() {
+                    return AuthResult::Failure;
+                }
+            }
+        }
+
         match self
             .directory
+            .load()
             .authenticate(
-                &Credentials::Plain {
-                    username: username.to_string(),
-                    secret: secret.to_string(),
+                &Credentials::OAuthBearer {
+                    token: token.to_string(),
                 },
                 remote_ip,
+                protocol,
                 true,
             )
             .await
         {
-            Ok(AuthResult::Success(principal)) => AuthResult::Success(AccessToken::new(principal)),
+            Ok(AuthResult::Success(principal)) => self
+                .update_access_token(AccessToken::new(principal))
+                .await
+                .map_or(AuthResult::Failure, AuthResult::Success),
+            Ok(AuthResult::Failure) => {
+                let _ = self.is_auth_allowed_hard(&remote_ip);
+                AuthResult::Failure
+            }
+            Ok(AuthResult::Banned) => AuthResult::Banned,
+            Err(_) => AuthResult::Failure,
+        }
+    }
+
+    pub async fn authenticate_scram(
+        &self,
+        session: &mut ScramSession,
+        client_final: &[u8],
+        remote_ip: IpAddr,
+    ) -> AuthResult<(AccessToken, Vec<u8>)> {
+        match self
+            .directory
+            .load()
+            .scram_authenticate(session, client_final, remote_ip)
+            .await
+        {
+            Ok(AuthResult::Success((principal, server_final))) => self
+                .update_access_token(AccessToken::new(principal))
+                .await
+                .map_or(AuthResult::Failure, |access_token| {
+                    AuthResult::Success((access_token, server_final))
+                }),
             Ok(AuthResult::Failure) => {
                 let _ = self.is_auth_allowed_hard(&remote_ip);
                 AuthResult::Failure
@@ -198,10 +318,37 @@ impl JMAP {
         }
     }
 
+    // Verifies the second factor of interactive logins, accounts without
+    // two-factor authentication enabled do not require one.
+    pub async fn verify_second_factor(&self, account_id: u32, code: Option<&str>) -> bool {
+        match self.store.is_totp_enabled(account_id).await {
+            Ok(false) => true,
+            Ok(true) => match code.filter(|code| !code.is_empty()) {
+                Some(code) => self
+                    .store
+                    .verify_totp(account_id, code)
+                    .await
+                    .unwrap_or_default(),
+                None => false,
+            },
+            Err(err) => {
+                tracing::warn!(
+                    context = "auth",
+                    event = "error",
+                    account_id = account_id,
+                    reason = ?err,
+                    "Failed to obtain two-factor authentication settings"
+                );
+                false
+            }
+        }
+    }
+
     pub async fn get_access_token(&self, account_id: u32) -> Option<AccessToken> {
         // Create access token
         self.update_access_token(AccessToken::new(
             self.directory
+                .load()
                 .query(QueryBy::Id(account_id), true)
                 .await
                 .ok()??,
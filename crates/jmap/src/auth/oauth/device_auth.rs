@@ -45,8 +45,8 @@ use crate::{
 };
 
 use super::{
-    DeviceAuthResponse, FormData, OAuthCode, CLIENT_ID_MAX_LEN, DEVICE_CODE_LEN, OAUTH_HTML_FOOTER,
-    OAUTH_HTML_HEADER, OAUTH_HTML_LOGIN_CODE, OAUTH_HTML_LOGIN_FORM,
+    is_openid_scope, DeviceAuthResponse, FormData, OAuthCode, CLIENT_ID_MAX_LEN, DEVICE_CODE_LEN,
+    OAUTH_HTML_FOOTER, OAUTH_HTML_HEADER, OAUTH_HTML_LOGIN_CODE, OAUTH_HTML_LOGIN_FORM,
     OAUTH_HTML_LOGIN_HEADER_DEVICE, STATUS_PENDING, USER_CODE_ALPHABET, USER_CODE_LEN,
 };
 
@@ -58,11 +58,13 @@ impl JMAP {
         instance: Arc<ServerInstance>,
     ) -> HttpResponse {
         // Parse form
-        let client_id = match FormData::from_request(req, MAX_POST_LEN)
+        let (client_id, scope) = match FormData::from_request(req, MAX_POST_LEN)
             .await
-            .map(|mut p| p.remove("client_id"))
+            .map(|mut p| (p.remove("client_id"), p.remove("scope")))
         {
-            Ok(Some(client_id)) if client_id.len() < CLIENT_ID_MAX_LEN => client_id,
+            Ok((Some(client_id), scope)) if client_id.len() < CLIENT_ID_MAX_LEN => {
+                (client_id, scope)
+            }
             Err(err) => return err,
             _ => {
                 return HtmlResponse::with_status(
@@ -73,6 +75,17 @@ impl JMAP {
             }
         };
 
+        // OpenID Connect requests are only accepted from registered clients
+        if is_openid_scope(scope.as_deref())
+            && self.get_registered_client(&client_id).await.is_none()
+        {
+            return HtmlResponse::with_status(
+                StatusCode::BAD_REQUEST,
+                "Client ID is not registered.".to_string(),
+            )
+            .into_http_response();
+        }
+
         // Generate device code
         let device_code = thread_rng()
             .sample_iter(Alphanumeric)
@@ -100,8 +113,11 @@ impl JMAP {
             account_id: u32::MAX.into(),
             client_id,
             redirect_uri: None,
+            scope,
+            nonce: None,
         });
-        let expiry = Instant::now() + Duration::from_secs(self.config.oauth_expiry_user_code);
+        let expiry =
+            Instant::now() + Duration::from_secs(self.config.load().oauth_expiry_user_code);
         self.oauth_codes
             .insert_with_ttl(device_code.clone(), oauth_code.clone(), expiry);
         self.oauth_codes
@@ -113,7 +129,7 @@ impl JMAP {
             verification_uri_complete: format!("{}/auth/?code={}", instance.data, user_code),
             device_code,
             user_code,
-            expires_in: self.config.oauth_expiry_user_code,
+            expires_in: self.config.load().oauth_expiry_user_code,
             interval: 5,
         })
         .into_http_response()
@@ -171,14 +187,19 @@ impl JMAP {
             .get("code")
             .and_then(|code| self.oauth_codes.get_with_ttl(code))
         {
-            if (STATUS_PENDING..STATUS_PENDING + self.config.oauth_max_auth_attempts)
+            if (STATUS_PENDING..STATUS_PENDING + self.config.load().oauth_max_auth_attempts)
                 .contains(&oauth.status.load(atomic::Ordering::Relaxed))
             {
                 if let (Some(email), Some(password)) = (fields.get("email"), fields.get("password"))
                 {
-                    if let AuthResult::Success(id) =
-                        self.authenticate_plain(email, password, remote_addr).await
+                    let access_token = match self
+                        .authenticate_interactive(email, password, fields.get("otp"), remote_addr)
+                        .await
                     {
+                        AuthResult::Success(access_token) => Some(access_token),
+                        _ => None,
+                    };
+                    if let Some(id) = access_token {
                         oauth
                             .account_id
                             .store(id.primary_id(), atomic::Ordering::Relaxed);
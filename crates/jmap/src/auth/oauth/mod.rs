@@ -30,11 +30,12 @@ use serde::{Deserialize, Serialize};
 use crate::api::{http::ToHttpResponse, HtmlResponse, HttpRequest, HttpResponse};
 
 pub mod device_auth;
+pub mod oidc;
 pub mod token;
 pub mod user_code;
 
-const OAUTH_HTML_HEADER: &str = include_str!("../../../../../resources/htx/header.htx");
-const OAUTH_HTML_FOOTER: &str = include_str!("../../../../../resources/htx/footer.htx");
+pub(crate) const OAUTH_HTML_HEADER: &str = include_str!("../../../../../resources/htx/header.htx");
+pub(crate) const OAUTH_HTML_FOOTER: &str = include_str!("../../../../../resources/htx/footer.htx");
 const OAUTH_HTML_LOGIN_HEADER_CLIENT: &str =
     include_str!("../../../../../resources/htx/login_hdr_client.htx");
 const OAUTH_HTML_LOGIN_HEADER_DEVICE: &str =
@@ -58,7 +59,7 @@ const USER_CODE_LEN: usize = 8;
 const RANDOM_CODE_LEN: usize = 32;
 const CLIENT_ID_MAX_LEN: usize = 20;
 
-const MAX_POST_LEN: usize = 2048;
+pub(crate) const MAX_POST_LEN: usize = 2048;
 
 const USER_CODE_ALPHABET: &[u8] = b"ABCDEFGHJKLMNPQRSTUVWXYZ23456789"; // No 0, O, I, 1
 
@@ -78,6 +79,18 @@ pub struct OAuthCode {
     pub account_id: AtomicU32,
     pub client_id: String,
     pub redirect_uri: Option<String>,
+    pub scope: Option<String>,
+    pub nonce: Option<String>,
+}
+
+impl OAuthCode {
+    pub fn is_openid(&self) -> bool {
+        is_openid_scope(self.scope.as_deref())
+    }
+}
+
+pub fn is_openid_scope(scope: Option<&str>) -> bool {
+    scope.is_some_and(|scope| scope.split_ascii_whitespace().any(|s| s == "openid"))
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -144,6 +157,8 @@ pub enum TokenResponse {
         refresh_token: Option<String>,
         #[serde(skip_serializing_if = "Option::is_none")]
         scope: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        id_token: Option<String>,
     },
     Error {
         error: ErrorType,
@@ -0,0 +1,464 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::{net::IpAddr, sync::Arc, time::SystemTime};
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use directory::QueryBy;
+use hyper::{header, StatusCode};
+use rsa::{
+    pkcs1::DecodeRsaPrivateKey,
+    pkcs1v15::SigningKey,
+    pkcs8::{DecodePrivateKey, EncodePrivateKey, LineEnding},
+    signature::{SignatureEncoding, Signer},
+    traits::PublicKeyParts,
+    RsaPrivateKey,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sha2::Sha256;
+use store::{
+    blake3,
+    rand::{distributions::Alphanumeric, thread_rng, Rng},
+    write::{BatchBuilder, ValueClass},
+};
+use utils::{config::ConfigKey, constant_time_eq};
+
+use crate::{
+    api::{http::ToHttpResponse, HttpRequest, HttpResponse, JsonResponse},
+    auth::AccessToken,
+    JMAP,
+};
+
+use super::{fetch_body, CLIENT_ID_MAX_LEN, MAX_POST_LEN};
+
+const OIDC_KEY: &str = "oauth.oidc.signature-key";
+const OIDC_CLIENT_PREFIX: &str = "oauth.oidc.client.";
+const OIDC_KEY_SIZE: usize = 2048;
+
+pub struct OidcSigningKey {
+    pub key_id: String,
+    signing_key: SigningKey<Sha256>,
+    jwk: serde_json::Value,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct OpenIdMetadata {
+    pub issuer: String,
+    pub authorization_endpoint: String,
+    pub token_endpoint: String,
+    pub userinfo_endpoint: String,
+    pub jwks_uri: String,
+    pub registration_endpoint: String,
+    pub device_authorization_endpoint: String,
+    pub scopes_supported: Vec<String>,
+    pub response_types_supported: Vec<String>,
+    pub grant_types_supported: Vec<String>,
+    pub subject_types_supported: Vec<String>,
+    pub id_token_signing_alg_values_supported: Vec<String>,
+    pub token_endpoint_auth_methods_supported: Vec<String>,
+    pub claims_supported: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct IdTokenClaims {
+    pub iss: String,
+    pub sub: String,
+    pub aud: String,
+    pub exp: u64,
+    pub iat: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub nonce: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub preferred_username: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub email: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Userinfo {
+    pub sub: String,
+    pub name: String,
+    pub preferred_username: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub email: Option<String>,
+    pub emails: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ClientRegistration {
+    #[serde(default)]
+    pub client_id: String,
+    #[serde(default)]
+    pub client_id_issued_at: u64,
+    pub redirect_uris: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client_name: Option<String>,
+    #[serde(default)]
+    pub token_endpoint_auth_method: String,
+    #[serde(default)]
+    pub grant_types: Vec<String>,
+    #[serde(default)]
+    pub response_types: Vec<String>,
+}
+
+impl OpenIdMetadata {
+    pub fn new(base_url: &str) -> Self {
+        OpenIdMetadata {
+            issuer: base_url.to_string(),
+            authorization_endpoint: format!("{}/auth/code", base_url),
+            token_endpoint: format!("{}/auth/token", base_url),
+            userinfo_endpoint: format!("{}/auth/userinfo", base_url),
+            jwks_uri: format!("{}/auth/jwks.json", base_url),
+            registration_endpoint: format!("{}/auth/register", base_url),
+            device_authorization_endpoint: format!("{}/auth/device", base_url),
+            scopes_supported: vec![
+                "openid".to_string(),
+                "profile".to_string(),
+                "email".to_string(),
+                "offline_access".to_string(),
+            ],
+            response_types_supported: vec!["code".to_string()],
+            grant_types_supported: vec![
+                "authorization_code".to_string(),
+                "refresh_token".to_string(),
+                "urn:ietf:params:oauth:grant-type:device_code".to_string(),
+            ],
+            subject_types_supported: vec!["public".to_string()],
+            id_token_signing_alg_values_supported: vec!["RS256".to_string()],
+            token_endpoint_auth_methods_supported: vec!["none".to_string()],
+            claims_supported: vec![
+                "iss".to_string(),
+                "sub".to_string(),
+                "aud".to_string(),
+                "exp".to_string(),
+                "iat".to_string(),
+                "nonce".to_string(),
+                "name".to_string(),
+                "preferred_username".to_string(),
+                "email".to_string(),
+            ],
+        }
+    }
+}
+
+impl JMAP {
+    // JSON Web Key Set endpoint
+    pub async fn handle_oidc_jwks(&self) -> HttpResponse {
+        match self.oidc_signing_key().await {
+            Ok(key) => JsonResponse::new(json!({
+                "keys": [key.jwk],
+            }))
+            .into_http_response(),
+            Err(err) => {
+                tracing::error!(context = "oidc", event = "error", reason = err);
+                JsonResponse::with_status(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    json!({"error": "server_error"}),
+                )
+                .into_http_response()
+            }
+        }
+    }
+
+    // Userinfo endpoint
+    pub async fn handle_oidc_userinfo(&self, access_token: &AccessToken) -> HttpResponse {
+        let emails = match self
+            .directory
+            .load()
+            .query(QueryBy::Id(access_token.primary_id()), false)
+            .await
+        {
+            Ok(Some(principal)) => principal.emails,
+            _ => vec![],
+        };
+
+        JsonResponse::new(Userinfo {
+            sub: access_token.primary_id().to_string(),
+            name: access_token
+                .description
+                .clone()
+                .unwrap_or_else(|| access_token.name.clone()),
+            preferred_username: access_token.name.clone(),
+            email: emails.first().cloned(),
+            emails,
+        })
+        .into_http_response()
+    }
+
+    // Dynamic client registration endpoint (RFC 7591)
+    pub async fn handle_oidc_client_registration(
+        &self,
+        req: &mut HttpRequest,
+        remote_ip: IpAddr,
+    ) -> HttpResponse {
+        if !self.is_registration_authorized(req, remote_ip).await {
+            return JsonResponse::with_status(
+                StatusCode::UNAUTHORIZED,
+                json!({"error": "invalid_token"}),
+            )
+            .into_http_response();
+        }
+
+        let mut registration = match fetch_body(req, MAX_POST_LEN)
+            .await
+            .and_then(|body| serde_json::from_slice::<ClientRegistration>(&body).ok())
+        {
+            Some(registration) => registration,
+            None => {
+                return JsonResponse::with_status(
+                    StatusCode::BAD_REQUEST,
+                    json!({"error": "invalid_client_metadata"}),
+                )
+                .into_http_response();
+            }
+        };
+        if registration.redirect_uris.is_empty()
+            || registration
+                .redirect_uris
+                .iter()
+                .any(|uri| !uri.starts_with("https://"))
+        {
+            return JsonResponse::with_status(
+                StatusCode::BAD_REQUEST,
+                json!({"error": "invalid_redirect_uri"}),
+            )
+            .into_http_response();
+        }
+
+        registration.client_id = thread_rng()
+            .sample_iter(Alphanumeric)
+            .take(CLIENT_ID_MAX_LEN - 4)
+            .map(char::from)
+            .collect::<String>();
+        registration.client_id_issued_at = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        registration.token_endpoint_auth_method = "none".to_string();
+        registration.grant_types = vec![
+            "authorization_code".to_string(),
+            "refresh_token".to_string(),
+        ];
+        registration.response_types = vec!["code".to_string()];
+
+        match self
+            .store
+            .config_set(
+                [ConfigKey {
+                    key: format!("{OIDC_CLIENT_PREFIX}{}", registration.client_id),
+                    value: serde_json::to_string(&registration).unwrap_or_default(),
+                }]
+                .into_iter(),
+            )
+            .await
+        {
+            Ok(_) => {
+                JsonResponse::with_status(StatusCode::CREATED, registration).into_http_response()
+            }
+            Err(err) => {
+                tracing::error!(
+                    context = "oidc",
+                    event = "error",
+                    reason = ?err,
+                    "Failed to store client registration"
+                );
+                JsonResponse::with_status(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    json!({"error": "server_error"}),
+                )
+                .into_http_response()
+            }
+        }
+    }
+
+    // Registrations require the initial access token or administrator credentials
+    async fn is_registration_authorized(&self, req: &HttpRequest, remote_ip: IpAddr) -> bool {
+        if let (Some(expected), Some((mechanism, token))) = (
+            &self.config.load().oauth_oidc_registration_token,
+            req.headers()
+                .get(header::AUTHORIZATION)
+                .and_then(|h| h.to_str().ok())
+                .and_then(|h| h.split_once(' ')),
+        ) {
+            if mechanism.eq_ignore_ascii_case("bearer")
+                && constant_time_eq(expected.trim().as_bytes(), token.trim().as_bytes())
+            {
+                return true;
+            }
+        }
+
+        matches!(
+            self.authenticate_headers(req, remote_ip).await,
+            Ok(Some((_, access_token))) if access_token.is_super_user()
+        )
+    }
+
+    pub async fn get_registered_client(&self, client_id: &str) -> Option<ClientRegistration> {
+        self.store
+            .config_get(format!("{OIDC_CLIENT_PREFIX}{client_id}"))
+            .await
+            .ok()
+            .flatten()
+            .and_then(|registration| serde_json::from_str(&registration).ok())
+    }
+
+    pub async fn issue_id_token(
+        &self,
+        issuer: &str,
+        account_id: u32,
+        client_id: &str,
+        scope: &str,
+        nonce: Option<&str>,
+    ) -> Result<String, &'static str> {
+        let principal = self
+            .directory
+            .load()
+            .query(QueryBy::Id(account_id), false)
+            .await
+            .map_err(|_| "Temporary lookup error")?
+            .ok_or("Account no longer exists")?;
+        let has_scope = |name: &str| scope.split_ascii_whitespace().any(|s| s == name);
+        let now = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        let claims = IdTokenClaims {
+            iss: issuer.to_string(),
+            sub: account_id.to_string(),
+            aud: client_id.to_string(),
+            exp: now + self.config.load().oauth_expiry_token,
+            iat: now,
+            nonce: nonce.map(|nonce| nonce.to_string()),
+            name: has_scope("profile").then(|| {
+                principal
+                    .description
+                    .unwrap_or_else(|| principal.name.clone())
+            }),
+            preferred_username: has_scope("profile").then_some(principal.name),
+            email: has_scope("email")
+                .then(|| principal.emails.into_iter().next())
+                .flatten(),
+        };
+
+        let key = self.oidc_signing_key().await?;
+        let mut token = format!(
+            "{}.{}",
+            URL_SAFE_NO_PAD.encode(
+                json!({
+                    "alg": "RS256",
+                    "typ": "JWT",
+                    "kid": key.key_id,
+                })
+                .to_string()
+            ),
+            URL_SAFE_NO_PAD.encode(serde_json::to_string(&claims).unwrap_or_default())
+        );
+        let signature = key
+            .signing_key
+            .try_sign(token.as_bytes())
+            .map_err(|_| "Failed to sign ID token")?;
+        token.push('.');
+        token.push_str(&URL_SAFE_NO_PAD.encode(signature.to_bytes()));
+
+        Ok(token)
+    }
+
+    // Obtains the key used to sign ID tokens, which is either configured,
+    // or generated on first use and then persisted in the configuration store.
+    async fn oidc_signing_key(&self) -> Result<Arc<OidcSigningKey>, &'static str> {
+        if let Some(key) = self.oidc_key.get() {
+            return Ok(key.clone());
+        }
+
+        let pem = if let Some(pem) = &self.config.load().oauth_oidc_key {
+            pem.clone()
+        } else if let Some(pem) = self
+            .store
+            .config_get(OIDC_KEY)
+            .await
+            .map_err(|_| "Failed to obtain OIDC signature key")?
+        {
+            pem
+        } else {
+            let pem = tokio::task::spawn_blocking(|| {
+                RsaPrivateKey::new(&mut thread_rng(), OIDC_KEY_SIZE)
+                    .ok()
+                    .and_then(|key| key.to_pkcs8_pem(LineEnding::LF).ok())
+                    .map(|pem| pem.to_string())
+            })
+            .await
+            .ok()
+            .flatten()
+            .ok_or("Failed to generate OIDC signature key")?;
+
+            // Store the key only if no other node generated one in the meantime
+            let mut batch = BatchBuilder::new();
+            batch
+                .assert_value(ValueClass::Config(OIDC_KEY.as_bytes().to_vec()), ())
+                .set(
+                    ValueClass::Config(OIDC_KEY.as_bytes().to_vec()),
+                    pem.as_bytes().to_vec(),
+                );
+            match self.store.write(batch.build()).await {
+                Ok(_) => pem,
+                Err(store::Error::AssertValueFailed) => self
+                    .store
+                    .config_get(OIDC_KEY)
+                    .await
+                    .map_err(|_| "Failed to obtain OIDC signature key")?
+                    .ok_or("Failed to obtain OIDC signature key")?,
+                Err(_) => return Err("Failed to store OIDC signature key"),
+            }
+        };
+
+        let key = Arc::new(OidcSigningKey::from_pem(&pem)?);
+        Ok(self.oidc_key.get_or_init(|| key).clone())
+    }
+}
+
+impl OidcSigningKey {
+    pub fn from_pem(pem: &str) -> Result<Self, &'static str> {
+        let key = RsaPrivateKey::from_pkcs8_pem(pem)
+            .or_else(|_| RsaPrivateKey::from_pkcs1_pem(pem))
+            .map_err(|_| "Failed to parse OIDC signature key")?;
+        let n = key.n().to_bytes_be();
+        let e = key.e().to_bytes_be();
+        let key_id = blake3::hash(&n).to_hex()[..16].to_string();
+
+        Ok(OidcSigningKey {
+            jwk: json!({
+                "kty": "RSA",
+                "use": "sig",
+                "alg": "RS256",
+                "kid": key_id,
+                "n": URL_SAFE_NO_PAD.encode(n),
+                "e": URL_SAFE_NO_PAD.encode(e),
+            }),
+            key_id,
+            signing_key: SigningKey::<Sha256>::new(key),
+        })
+    }
+}
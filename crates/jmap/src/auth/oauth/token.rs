@@ -21,7 +21,10 @@
  * for more details.
 */
 
-use std::{sync::atomic, time::SystemTime};
+use std::{
+    sync::{atomic, Arc},
+    time::SystemTime,
+};
 
 use directory::QueryBy;
 use hyper::StatusCode;
@@ -33,6 +36,7 @@ use store::{
 };
 use utils::{
     codec::leb128::{Leb128Iterator, Leb128Vec},
+    listener::ServerInstance,
     map::ttl_dashmap::TtlMap,
 };
 
@@ -43,13 +47,17 @@ use crate::{
 };
 
 use super::{
-    ErrorType, FormData, TokenResponse, CLIENT_ID_MAX_LEN, MAX_POST_LEN, RANDOM_CODE_LEN,
-    STATUS_AUTHORIZED, STATUS_PENDING, STATUS_TOKEN_ISSUED,
+    ErrorType, FormData, OAuthCode, TokenResponse, CLIENT_ID_MAX_LEN, MAX_POST_LEN,
+    RANDOM_CODE_LEN, STATUS_AUTHORIZED, STATUS_PENDING, STATUS_TOKEN_ISSUED,
 };
 
 impl JMAP {
     // Token endpoint
-    pub async fn handle_token_request(&self, req: &mut HttpRequest) -> HttpResponse {
+    pub async fn handle_token_request(
+        &self,
+        req: &mut HttpRequest,
+        instance: Arc<ServerInstance>,
+    ) -> HttpResponse {
         // Parse form
         let params = match FormData::from_request(req, MAX_POST_LEN).await {
             Ok(params) => params,
@@ -71,22 +79,7 @@ impl JMAP {
                     {
                         TokenResponse::error(ErrorType::InvalidClient)
                     } else if oauth.status.load(atomic::Ordering::Relaxed) == STATUS_AUTHORIZED {
-                        // Mark this token as issued
-                        oauth
-                            .status
-                            .store(STATUS_TOKEN_ISSUED, atomic::Ordering::Relaxed);
-
-                        // Issue token
-                        self.issue_token(
-                            oauth.account_id.load(atomic::Ordering::Relaxed),
-                            &oauth.client_id,
-                            true,
-                        )
-                        .await
-                        .unwrap_or_else(|err| {
-                            tracing::error!("Failed to generate OAuth token: {}", err);
-                            TokenResponse::error(ErrorType::InvalidRequest)
-                        })
+                        self.issue_authorized_token(&oauth, &instance.data).await
                     } else {
                         TokenResponse::error(ErrorType::InvalidGrant)
                     }
@@ -110,26 +103,11 @@ impl JMAP {
                 } else {
                     match oauth.status.load(atomic::Ordering::Relaxed) {
                         STATUS_AUTHORIZED => {
-                            // Mark this token as issued
-                            oauth
-                                .status
-                                .store(STATUS_TOKEN_ISSUED, atomic::Ordering::Relaxed);
-
-                            // Issue token
-                            self.issue_token(
-                                oauth.account_id.load(atomic::Ordering::Relaxed),
-                                &oauth.client_id,
-                                true,
-                            )
-                            .await
-                            .unwrap_or_else(|err| {
-                                tracing::error!("Failed to generate OAuth token: {}", err);
-                                TokenResponse::error(ErrorType::InvalidRequest)
-                            })
+                            self.issue_authorized_token(&oauth, &instance.data).await
                         }
                         status
                             if (STATUS_PENDING
-                                ..STATUS_PENDING + self.config.oauth_max_auth_attempts)
+                                ..STATUS_PENDING + self.config.load().oauth_max_auth_attempts)
                                 .contains(&status) =>
                         {
                             TokenResponse::error(ErrorType::AuthorizationPending)
@@ -150,7 +128,7 @@ impl JMAP {
                         .issue_token(
                             account_id,
                             &client_id,
-                            time_left <= self.config.oauth_expiry_refresh_token_renew,
+                            time_left <= self.config.load().oauth_expiry_refresh_token_renew,
                         )
                         .await
                         .unwrap_or_else(|err| {
@@ -174,6 +152,50 @@ impl JMAP {
         .into_http_response()
     }
 
+    async fn issue_authorized_token(&self, oauth: &OAuthCode, issuer: &str) -> TokenResponse {
+        // Mark this token as issued
+        oauth
+            .status
+            .store(STATUS_TOKEN_ISSUED, atomic::Ordering::Relaxed);
+
+        // Issue token
+        let account_id = oauth.account_id.load(atomic::Ordering::Relaxed);
+        let mut response = self
+            .issue_token(account_id, &oauth.client_id, true)
+            .await
+            .unwrap_or_else(|err| {
+                tracing::error!("Failed to generate OAuth token: {}", err);
+                TokenResponse::error(ErrorType::InvalidRequest)
+            });
+
+        // Issue ID token for OpenID Connect requests
+        if let (TokenResponse::Granted { id_token, .. }, Some(scope)) = (
+            &mut response,
+            oauth.scope.as_deref().filter(|_| oauth.is_openid()),
+        ) {
+            match self
+                .issue_id_token(
+                    issuer,
+                    account_id,
+                    &oauth.client_id,
+                    scope,
+                    oauth.nonce.as_deref(),
+                )
+                .await
+            {
+                Ok(token) => {
+                    *id_token = token.into();
+                }
+                Err(err) => {
+                    tracing::error!("Failed to generate ID token: {}", err);
+                    return TokenResponse::error(ErrorType::InvalidRequest);
+                }
+            }
+        }
+
+        response
+    }
+
     async fn issue_token(
         &self,
         account_id: u32,
@@ -182,6 +204,7 @@ impl JMAP {
     ) -> Result<TokenResponse, &'static str> {
         let password_hash = self
             .directory
+            .load()
             .query(QueryBy::Id(account_id), false)
             .await
             .map_err(|_| "Temporary lookup error")?
@@ -197,23 +220,24 @@ impl JMAP {
                 account_id,
                 &password_hash,
                 client_id,
-                self.config.oauth_expiry_token,
+                self.config.load().oauth_expiry_token,
             )?,
             token_type: "bearer".to_string(),
-            expires_in: self.config.oauth_expiry_token,
+            expires_in: self.config.load().oauth_expiry_token,
             refresh_token: if with_refresh_token {
                 self.encode_access_token(
                     "refresh_token",
                     account_id,
                     &password_hash,
                     client_id,
-                    self.config.oauth_expiry_refresh_token,
+                    self.config.load().oauth_expiry_refresh_token,
                 )?
                 .into()
             } else {
                 None
             },
             scope: None,
+            id_token: None,
         })
     }
 
@@ -229,7 +253,7 @@ impl JMAP {
         if client_id.len() > CLIENT_ID_MAX_LEN {
             return Err("ClientId is too long");
         }
-        let key = self.config.oauth_key.clone();
+        let key = self.config.load().oauth_key.clone();
         let context = format!(
             "{} {} {} {}",
             grant_type, client_id, account_id, password_hash
@@ -301,6 +325,7 @@ impl JMAP {
 
         let password_hash = self
             .directory
+            .load()
             .query(QueryBy::Id(account_id), false)
             .await
             .map_err(|_| "Temporary lookup error")?
@@ -311,7 +336,7 @@ impl JMAP {
             .ok_or("Failed to obtain password hash")?;
 
         // Build context
-        let key = self.config.oauth_key.clone();
+        let key = self.config.load().oauth_key.clone();
         let context = format!(
             "{} {} {} {}",
             grant_type, client_id, account_id, password_hash
@@ -43,8 +43,8 @@ use crate::{
 };
 
 use super::{
-    FormData, OAuthCode, CLIENT_ID_MAX_LEN, DEVICE_CODE_LEN, MAX_POST_LEN, OAUTH_HTML_FOOTER,
-    OAUTH_HTML_HEADER, OAUTH_HTML_LOGIN_CODE_HIDDEN, OAUTH_HTML_LOGIN_FORM,
+    is_openid_scope, FormData, OAuthCode, CLIENT_ID_MAX_LEN, DEVICE_CODE_LEN, MAX_POST_LEN,
+    OAUTH_HTML_FOOTER, OAUTH_HTML_HEADER, OAUTH_HTML_LOGIN_CODE_HIDDEN, OAUTH_HTML_LOGIN_FORM,
     OAUTH_HTML_LOGIN_HEADER_CLIENT, OAUTH_HTML_LOGIN_HEADER_FAILED, STATUS_AUTHORIZED,
 };
 
@@ -78,6 +78,26 @@ impl JMAP {
             .into_http_response();
         }
 
+        // Registered clients may only redirect to their registered URIs, OpenID
+        // Connect requests are only accepted from registered clients.
+        match self.get_registered_client(client_id).await {
+            Some(client) if !client.redirect_uris.iter().any(|uri| uri == redirect_uri) => {
+                return HtmlResponse::with_status(
+                    StatusCode::BAD_REQUEST,
+                    "Redirect URI is not registered for this client.".to_string(),
+                )
+                .into_http_response();
+            }
+            None if is_openid_scope(params.get("scope").map(|s| s.as_str())) => {
+                return HtmlResponse::with_status(
+                    StatusCode::BAD_REQUEST,
+                    "Client ID is not registered.".to_string(),
+                )
+                .into_http_response();
+            }
+            _ => (),
+        }
+
         let mut cancel_link = format!("{}?error=access_denied", redirect_uri);
         if let Some(state) = params.get("state") {
             let _ = write!(cancel_link, "&state={}", state);
@@ -138,8 +158,9 @@ impl JMAP {
 
         // Authenticate user
         if let (Some(email), Some(password)) = (params.get("email"), params.get("password")) {
-            if let AuthResult::Success(access_token) =
-                self.authenticate_plain(email, password, remote_addr).await
+            if let AuthResult::Success(access_token) = self
+                .authenticate_interactive(email, password, params.get("otp"), remote_addr)
+                .await
             {
                 // Generate client code
                 let client_code = thread_rng()
@@ -160,8 +181,10 @@ impl JMAP {
                             .unwrap_or_default()
                             .to_string(),
                         redirect_uri: code_req.get("redirect_uri").cloned(),
+                        scope: code_req.get("scope").cloned(),
+                        nonce: code_req.get("nonce").cloned(),
                     }),
-                    Instant::now() + Duration::from_secs(self.config.oauth_expiry_auth_code),
+                    Instant::now() + Duration::from_secs(self.config.load().oauth_expiry_auth_code),
                 );
 
                 auth_code = client_code.into();
@@ -191,7 +214,7 @@ impl JMAP {
             let _ = write!(redirect_link, "&state={}", state);
         }
 
-        if auth_code.is_none() && (auth_attempts < self.config.oauth_max_auth_attempts) {
+        if auth_code.is_none() && (auth_attempts < self.config.load().oauth_max_auth_attempts) {
             let code = String::from_utf8(
                 base64_encode(
                     &bincode::serialize(&(auth_attempts + 1, code_req)).unwrap_or_default(),
@@ -40,6 +40,7 @@ pub struct AuthenticatedLimiter {
 pub struct AnonymousLimiter {
     request_limiter: RateLimiter,
     auth_limiter: RateLimiter,
+    registration_limiter: RateLimiter,
 }
 
 impl JMAP {
@@ -49,11 +50,13 @@ impl JMAP {
             .map(|limiter| limiter.clone())
             .unwrap_or_else(|| {
                 let limiter = Arc::new(AuthenticatedLimiter {
-                    request_limiter: RateLimiter::new(&self.config.rate_authenticated),
+                    request_limiter: RateLimiter::new(&self.config.load().rate_authenticated),
                     concurrent_requests: ConcurrencyLimiter::new(
-                        self.config.request_max_concurrent,
+                        self.config.load().request_max_concurrent,
+                    ),
+                    concurrent_uploads: ConcurrencyLimiter::new(
+                        self.config.load().upload_max_concurrent,
                     ),
-                    concurrent_uploads: ConcurrencyLimiter::new(self.config.upload_max_concurrent),
                 });
                 self.rate_limit_auth.insert(account_id, limiter.clone());
                 limiter
@@ -66,8 +69,9 @@ impl JMAP {
             .map(|limiter| limiter.clone())
             .unwrap_or_else(|| {
                 let limiter = Arc::new(AnonymousLimiter {
-                    request_limiter: RateLimiter::new(&self.config.rate_anonymous),
-                    auth_limiter: RateLimiter::new(&self.config.rate_authenticate_req),
+                    request_limiter: RateLimiter::new(&self.config.load().rate_anonymous),
+                    auth_limiter: RateLimiter::new(&self.config.load().rate_authenticate_req),
+                    registration_limiter: RateLimiter::new(&self.config.load().rate_registration),
                 });
                 self.rate_limit_unauth.insert(*addr, limiter.clone());
                 limiter
@@ -79,7 +83,7 @@ impl JMAP {
 
         if limiter
             .request_limiter
-            .is_allowed(&self.config.rate_authenticated)
+            .is_allowed(&self.config.load().rate_authenticated)
         {
             if let Some(in_flight_request) = limiter.concurrent_requests.is_allowed() {
                 Ok(in_flight_request)
@@ -99,7 +103,22 @@ impl JMAP {
         if self
             .get_anonymous_limiter(addr)
             .request_limiter
-            .is_allowed(&self.config.rate_anonymous)
+            .is_allowed(&self.config.load().rate_anonymous)
+        {
+            Ok(())
+        } else {
+            Err(RequestError::too_many_requests())
+        }
+    }
+
+    pub fn is_registration_allowed(&self, addr: &IpAddr) -> Result<(), RequestError> {
+        let limiter = self.get_anonymous_limiter(addr);
+        if limiter
+            .request_limiter
+            .is_allowed(&self.config.load().rate_anonymous)
+            && limiter
+                .registration_limiter
+                .is_allowed(&self.config.load().rate_registration)
         {
             Ok(())
         } else {
@@ -121,12 +140,19 @@ impl JMAP {
         }
     }
 
+    pub fn is_notify_allowed(&self, account_id: u32) -> bool {
+        self.rate_limit_notify
+            .entry(account_id)
+            .or_insert_with(|| Arc::new(RateLimiter::new(&self.config.load().sieve_notify_rate)))
+            .is_allowed(&self.config.load().sieve_notify_rate)
+    }
+
     pub fn is_auth_allowed_soft(&self, addr: &IpAddr) -> Result<(), RequestError> {
         match self.rate_limit_unauth.get(addr) {
             Some(limiter)
                 if !limiter
                     .auth_limiter
-                    .is_allowed_soft(&self.config.rate_authenticate_req) =>
+                    .is_allowed_soft(&self.config.load().rate_authenticate_req) =>
             {
                 Err(RequestError::too_many_auth_attempts())
             }
@@ -138,7 +164,7 @@ impl JMAP {
         if self
             .get_anonymous_limiter(addr)
             .auth_limiter
-            .is_allowed(&self.config.rate_authenticate_req)
+            .is_allowed(&self.config.load().rate_authenticate_req)
         {
             Ok(())
         } else {
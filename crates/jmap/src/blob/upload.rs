@@ -57,7 +57,7 @@ impl JMAP {
         };
         let account_id = request.account_id.document_id();
 
-        if request.create.len() > self.config.set_max_objects {
+        if request.create.len() > self.config.load().set_max_objects {
             return Err(MethodError::RequestTooLarge);
         }
 
@@ -131,14 +131,14 @@ impl JMAP {
                     DataSourceObject::Value(bytes) => bytes,
                 };
 
-                if bytes.len() + data.len() < self.config.upload_max_size {
+                if bytes.len() + data.len() < self.config.load().upload_max_size {
                     data.extend(bytes);
                 } else {
                     response.not_created.append(
                         create_id,
                         SetError::too_large().with_description(format!(
                             "Upload size exceeds maximum of {} bytes.",
-                            self.config.upload_max_size
+                            self.config.load().upload_max_size
                         )),
                     );
                     continue 'outer;
@@ -164,17 +164,18 @@ impl JMAP {
                 MethodError::ServerPartialFail
             })?;
 
-            if ((self.config.upload_tmp_quota_size > 0
-                && used.bytes + data.len() > self.config.upload_tmp_quota_size)
-                || (self.config.upload_tmp_quota_amount > 0
-                    && used.count + 1 > self.config.upload_tmp_quota_amount))
+            if ((self.config.load().upload_tmp_quota_size > 0
+                && used.bytes + data.len() > self.config.load().upload_tmp_quota_size)
+                || (self.config.load().upload_tmp_quota_amount > 0
+                    && used.count + 1 > self.config.load().upload_tmp_quota_amount))
                 && !access_token.is_super_user()
             {
                 response.not_created.append(
                     create_id,
                     SetError::over_quota().with_description(format!(
                         "You have exceeded the blob upload quota of {} files or {} bytes.",
-                        self.config.upload_tmp_quota_amount, self.config.upload_tmp_quota_size
+                        self.config.load().upload_tmp_quota_amount,
+                        self.config.load().upload_tmp_quota_size
                     )),
                 );
                 continue 'outer;
@@ -226,15 +227,15 @@ impl JMAP {
                 RequestError::internal_server_error()
             })?;
 
-        if ((self.config.upload_tmp_quota_size > 0
-            && used.bytes + data.len() > self.config.upload_tmp_quota_size)
-            || (self.config.upload_tmp_quota_amount > 0
-                && used.count + 1 > self.config.upload_tmp_quota_amount))
+        if ((self.config.load().upload_tmp_quota_size > 0
+            && used.bytes + data.len() > self.config.load().upload_tmp_quota_size)
+            || (self.config.load().upload_tmp_quota_amount > 0
+                && used.count + 1 > self.config.load().upload_tmp_quota_amount))
             && !access_token.is_super_user()
         {
             let err = Err(RequestError::over_blob_quota(
-                self.config.upload_tmp_quota_amount,
-                self.config.upload_tmp_quota_size,
+                self.config.load().upload_tmp_quota_amount,
+                self.config.load().upload_tmp_quota_size,
             ));
 
             #[cfg(feature = "test_mode")]
@@ -267,7 +268,7 @@ impl JMAP {
         // First reserve the hash
         let hash = BlobHash::from(data);
         let mut batch = BatchBuilder::new();
-        let until = now() + self.config.upload_tmp_ttl;
+        let until = now() + self.config.load().upload_tmp_ttl;
 
         batch.with_account_id(account_id).set(
             BlobOp::Reserve {
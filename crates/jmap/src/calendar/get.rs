@@ -0,0 +1,140 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use jmap_proto::{
+    error::method::MethodError,
+    method::get::{GetRequest, GetResponse, RequestArguments},
+    object::Object,
+    types::{collection::Collection, property::Property, value::Value},
+};
+
+use crate::JMAP;
+
+impl JMAP {
+    pub async fn calendar_get(
+        &self,
+        mut request: GetRequest<RequestArguments>,
+    ) -> Result<GetResponse, MethodError> {
+        let ids = request.unwrap_ids(self.config.load().get_max_objects)?;
+        let properties = request.unwrap_properties(&[
+            Property::Id,
+            Property::Name,
+            Property::Description,
+            Property::Color,
+            Property::SortOrder,
+            Property::IsDefault,
+            Property::IsSubscribed,
+            Property::IsVisible,
+            Property::MyRights,
+        ]);
+        let account_id = request.account_id.document_id();
+        let calendar_ids = self.calendar_get_or_create(account_id).await?;
+        let ids = if let Some(ids) = ids {
+            ids
+        } else {
+            calendar_ids
+                .iter()
+                .take(self.config.load().get_max_objects)
+                .map(Into::into)
+                .collect::<Vec<_>>()
+        };
+        let mut response = GetResponse {
+            account_id: request.account_id.into(),
+            state: self
+                .get_state(account_id, Collection::Calendar)
+                .await?
+                .into(),
+            list: Vec::with_capacity(ids.len()),
+            not_found: vec![],
+        };
+
+        for id in ids {
+            // Obtain the calendar object
+            let document_id = id.document_id();
+            if !calendar_ids.contains(document_id) {
+                response.not_found.push(id.into());
+                continue;
+            }
+            let mut values = if let Some(values) = self
+                .get_property::<Object<Value>>(
+                    account_id,
+                    Collection::Calendar,
+                    document_id,
+                    Property::Value,
+                )
+                .await?
+            {
+                values
+            } else {
+                response.not_found.push(id.into());
+                continue;
+            };
+            let mut result = Object::with_capacity(properties.len());
+            for property in &properties {
+                let value = match property {
+                    Property::Id => Value::Id(id),
+                    Property::Name | Property::Description | Property::Color => {
+                        values.remove(property)
+                    }
+                    Property::SortOrder => values
+                        .properties
+                        .remove(property)
+                        .unwrap_or(Value::UnsignedInt(0)),
+                    Property::IsDefault | Property::IsSubscribed => values
+                        .properties
+                        .remove(property)
+                        .unwrap_or(Value::Bool(false)),
+                    Property::IsVisible => values
+                        .properties
+                        .remove(property)
+                        .unwrap_or(Value::Bool(true)),
+                    Property::MyRights => {
+                        let mut rights = Object::with_capacity(11);
+                        for right in [
+                            "mayReadFreeBusy",
+                            "mayReadItems",
+                            "mayAddItems",
+                            "mayUpdatePrivate",
+                            "mayRSVP",
+                            "mayUpdateOwn",
+                            "mayUpdateAll",
+                            "mayRemoveOwn",
+                            "mayRemoveAll",
+                        ] {
+                            rights.append(Property::_T(right.to_string()), true);
+                        }
+                        rights
+                            .with_property(Property::_T("mayAdmin".to_string()), false)
+                            .with_property(Property::MayDelete, true)
+                            .into()
+                    }
+                    _ => Value::Null,
+                };
+                result.append(property.clone(), value);
+            }
+            response.list.push(result);
+        }
+
+        Ok(response)
+    }
+}
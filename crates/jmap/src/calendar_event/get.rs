@@ -0,0 +1,131 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use jmap_proto::{
+    error::method::MethodError,
+    method::get::{GetRequest, GetResponse, RequestArguments},
+    object::Object,
+    types::{collection::Collection, id::Id, property::Property, value::Value},
+};
+
+use crate::JMAP;
+
+use super::JSCalendarObject;
+
+impl JMAP {
+    pub async fn calendar_event_get(
+        &self,
+        mut request: GetRequest<RequestArguments>,
+    ) -> Result<GetResponse, MethodError> {
+        let ids = request.unwrap_ids(self.config.load().get_max_objects)?;
+        let properties = request.unwrap_properties(&[]);
+        let account_id = request.account_id.document_id();
+        self.calendar_get_or_create(account_id).await?;
+        let event_ids = self
+            .get_document_ids(account_id, Collection::CalendarEvent)
+            .await?
+            .unwrap_or_default();
+        let ids = if let Some(ids) = ids {
+            ids
+        } else {
+            event_ids
+                .iter()
+                .take(self.config.load().get_max_objects)
+                .map(Into::into)
+                .collect::<Vec<_>>()
+        };
+        let mut response = GetResponse {
+            account_id: request.account_id.into(),
+            state: self
+                .get_state(account_id, Collection::CalendarEvent)
+                .await?
+                .into(),
+            list: Vec::with_capacity(ids.len()),
+            not_found: vec![],
+        };
+
+        for id in ids {
+            // Obtain the event object
+            let document_id = id.document_id();
+            if !event_ids.contains(document_id) {
+                response.not_found.push(id.into());
+                continue;
+            }
+            let event = if let Some(event) = self
+                .get_property::<Object<Value>>(
+                    account_id,
+                    Collection::CalendarEvent,
+                    document_id,
+                    Property::Value,
+                )
+                .await?
+            {
+                event
+            } else {
+                response.not_found.push(id.into());
+                continue;
+            };
+
+            response.list.push(event_to_jmap(id, event, &properties));
+        }
+
+        Ok(response)
+    }
+}
+
+pub fn event_to_jmap(id: Id, event: Object<Value>, properties: &[Property]) -> Object<Value> {
+    let calendar_ids = event.calendar_ids();
+    let mut result = Object::with_capacity(event.properties.len());
+    result.append(Property::Id, Value::Id(id));
+
+    for (property, value) in event.properties {
+        match property {
+            Property::Id | Property::DavName => continue,
+            Property::CalendarIds => {
+                let mut ids = Object::with_capacity(calendar_ids.len());
+                for calendar_id in &calendar_ids {
+                    ids.append(
+                        Property::_T(Id::from(*calendar_id).to_string()),
+                        Value::Bool(true),
+                    );
+                }
+                result.append(Property::CalendarIds, ids);
+            }
+            property => {
+                if properties.is_empty()
+                    || properties
+                        .iter()
+                        .any(|p| p.to_string() == property.to_string())
+                {
+                    result.append(property, value);
+                }
+            }
+        }
+    }
+
+    if !properties.is_empty() && !properties.contains(&Property::CalendarIds) {
+        result.remove(&Property::CalendarIds);
+    }
+
+    result
+}
@@ -0,0 +1,1139 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use chrono::{NaiveDateTime, Utc};
+use jmap_proto::{object::Object, types::value::Value};
+
+use crate::{
+    contact::{flags, typed_object, JSContactObject, ObjectBuilder},
+    dav::content_line::{escape, fold_line, parse_line, split_list, unescape, unfold, ContentLine},
+};
+
+use super::{
+    format_duration, format_local_datetime, new_participant, parse_duration, parse_local_datetime,
+    participant_email, participant_has_role, strip_mailto, time_zone_offset, JSCalendarObject,
+    EVENT_TYPE,
+};
+
+// iCalendar properties that are rebuilt from the JSCalendar event on output
+const SKIP_PROPERTIES: [&str; 4] = ["BEGIN", "END", "LAST-MODIFIED", "DTEND"];
+
+const UTC_ZONES: [&str; 3] = ["Etc/UTC", "UTC", "GMT"];
+
+pub struct ICalendar {
+    pub method: Option<String>,
+    pub events: Vec<Object<Value>>,
+}
+
+#[derive(Default)]
+struct Component {
+    name: String,
+    lines: Vec<ContentLine>,
+    children: Vec<Component>,
+}
+
+// Converts an iCalendar object (RFC 5545) to JSCalendar events (RFC 8984). Instances
+// overriding a recurring event are merged into the event's recurrence overrides.
+pub fn parse_ical(data: &str) -> Option<ICalendar> {
+    let mut stack: Vec<Component> = Vec::new();
+    let mut calendar = None;
+
+    for line in unfold(data) {
+        let line = if let Some(line) = parse_line(&line) {
+            line
+        } else {
+            continue;
+        };
+        match line.name.as_str() {
+            "BEGIN" => {
+                stack.push(Component {
+                    name: line.value.to_ascii_uppercase(),
+                    ..Default::default()
+                });
+            }
+            "END" => {
+                let component = stack.pop()?;
+                if let Some(parent) = stack.last_mut() {
+                    parent.children.push(component);
+                } else if component.name == "VCALENDAR" {
+                    calendar = Some(component);
+                    break;
+                }
+            }
+            _ => {
+                if let Some(component) = stack.last_mut() {
+                    component.lines.push(line);
+                }
+            }
+        }
+    }
+
+    let calendar = calendar?;
+    let method = calendar
+        .lines
+        .iter()
+        .find(|line| line.name == "METHOD")
+        .map(|line| line.value.to_ascii_uppercase());
+
+    // Time zones are shared by all events in the object
+    let mut time_zones = Object::with_capacity(1);
+    for zone in calendar
+        .children
+        .iter()
+        .filter(|component| component.name == "VTIMEZONE")
+    {
+        if let Some(tz_id) = zone.lines.iter().find(|line| line.name == "TZID") {
+            time_zones.set_field(&tz_id.value, parse_time_zone(&tz_id.value, zone).into());
+        }
+    }
+
+    let mut events: Vec<Object<Value>> = Vec::new();
+    let mut overrides = Vec::new();
+    for component in calendar
+        .children
+        .iter()
+        .filter(|component| component.name == "VEVENT")
+    {
+        let event = parse_event(component, &time_zones);
+        if event.field("recurrenceId").is_some() {
+            overrides.push(event);
+        } else {
+            events.push(event);
+        }
+    }
+
+    for mut instance in overrides {
+        let master = events
+            .iter_mut()
+            .find(|event| event.text_field("uid") == instance.text_field("uid"));
+        if let Some(master) = master {
+            let recurrence_id = instance
+                .remove_field("recurrenceId")
+                .and_then(|id| id.try_unwrap_string())
+                .unwrap_or_default();
+            // Only keep the properties that differ from the main event
+            let mut patch = Object::with_capacity(instance.properties.len());
+            for (property, value) in instance.properties {
+                let name = property.to_string();
+                if !matches!(name.as_str(), "@type" | "uid" | "timeZones")
+                    && master.field(&name) != Some(&value)
+                {
+                    patch.append(property, value);
+                }
+            }
+            let mut recurrence_overrides = master
+                .remove_field("recurrenceOverrides")
+                .and_then(|overrides| overrides.try_unwrap_object())
+                .unwrap_or_else(|| Object::with_capacity(1));
+            recurrence_overrides.set_field(&recurrence_id, patch.into());
+            master.set_field("recurrenceOverrides", recurrence_overrides.into());
+        } else {
+            events.push(instance);
+        }
+    }
+
+    Some(ICalendar { method, events })
+}
+
+fn parse_event(component: &Component, time_zones: &Object<Value>) -> Object<Value> {
+    let mut event = typed_object(EVENT_TYPE);
+    if !time_zones.properties.is_empty() {
+        event.set_field("timeZones", time_zones.clone().into());
+    }
+
+    let mut participants = Object::with_capacity(0);
+    let mut recurrence_rules = Vec::new();
+    let mut recurrence_overrides = Object::with_capacity(0);
+    let mut keywords = Object::with_capacity(0);
+    let mut locations = Object::with_capacity(0);
+    let mut ical_props = Vec::new();
+    let mut end = None;
+
+    for line in &component.lines {
+        match line.name.as_str() {
+            "UID" => {
+                event.set_field("uid", Value::Text(line.value.clone()));
+            }
+            "SUMMARY" => {
+                event.set_field("title", Value::Text(unescape(&line.value)));
+            }
+            "DESCRIPTION" => {
+                event.set_field("description", Value::Text(unescape(&line.value)));
+            }
+            "LOCATION" => {
+                let id = format!("l{}", locations.properties.len() + 1);
+                locations.set_field(
+                    &id,
+                    typed_object("Location")
+                        .with_field("name", unescape(&line.value))
+                        .into(),
+                );
+            }
+            "DTSTART" => {
+                if let Some((start, time_zone, is_date)) = parse_datetime(line, &line.value) {
+                    event.set_field("start", Value::Text(format_local_datetime(&start)));
+                    if let Some(time_zone) = time_zone {
+                        event.set_field("timeZone", Value::Text(time_zone));
+                    }
+                    if is_date {
+                        event.set_field("showWithoutTime", Value::Bool(true));
+                    }
+                }
+            }
+            "DTEND" => {
+                end = parse_datetime(line, &line.value);
+            }
+            "DURATION" => {
+                event.set_field("duration", Value::Text(line.value.to_ascii_uppercase()));
+            }
+            "RECURRENCE-ID" => {
+                if let Some((recurrence_id, _, _)) = parse_datetime(line, &line.value) {
+                    event.set_field(
+                        "recurrenceId",
+                        Value::Text(format_local_datetime(&recurrence_id)),
+                    );
+                }
+            }
+            "RRULE" => match parse_rrule(&line.value) {
+                Some(rule) => recurrence_rules.push(rule.into()),
+                None => ical_props.push(ical_prop(line)),
+            },
+            "EXDATE" | "RDATE" => {
+                for value in line.value.split(',') {
+                    if let Some((date, _, _)) = parse_datetime(line, value) {
+                        let mut patch = Object::with_capacity(1);
+                        if line.name == "EXDATE" {
+                            patch.set_field("excluded", Value::Bool(true));
+                        }
+                        recurrence_overrides.set_field(&format_local_datetime(&date), patch.into());
+                    }
+                }
+            }
+            "STATUS" => {
+                event.set_field("status", Value::Text(line.value.to_ascii_lowercase()));
+            }
+            "SEQUENCE" => {
+                if let Ok(sequence) = line.value.trim().parse::<u64>() {
+                    event.set_field("sequence", Value::UnsignedInt(sequence));
+                }
+            }
+            "PRIORITY" => {
+                if let Ok(priority) = line.value.trim().parse::<u64>() {
+                    event.set_field("priority", Value::UnsignedInt(priority));
+                }
+            }
+            "TRANSP" => {
+                event.set_field(
+                    "freeBusyStatus",
+                    Value::Text(
+                        if line.value.eq_ignore_ascii_case("TRANSPARENT") {
+                            "free"
+                        } else {
+                            "busy"
+                        }
+                        .to_string(),
+                    ),
+                );
+            }
+            "CLASS" => {
+                event.set_field(
+                    "privacy",
+                    Value::Text(
+                        match line.value.to_ascii_uppercase().as_str() {
+                            "PRIVATE" => "private",
+                            "CONFIDENTIAL" => "secret",
+                            _ => "public",
+                        }
+                        .to_string(),
+                    ),
+                );
+            }
+            "CATEGORIES" => {
+                for category in split_list(&line.value) {
+                    keywords.set_field(&category, Value::Bool(true));
+                }
+            }
+            "DTSTAMP" | "CREATED" => {
+                if let Some((date, _, _)) = parse_datetime(line, &line.value) {
+                    event.set_field(
+                        if line.name == "DTSTAMP" {
+                            "updated"
+                        } else {
+                            "created"
+                        },
+                        Value::Text(format!("{}Z", format_local_datetime(&date))),
+                    );
+                }
+            }
+            "ORGANIZER" => {
+                let email = strip_mailto(&line.value).to_string();
+                event.set_field(
+                    "replyTo",
+                    Object::with_capacity(1)
+                        .with_field("imip", format!("mailto:{email}"))
+                        .into(),
+                );
+                let participant = participant_entry(&mut participants, line.param("CN"), &email);
+                set_role(participant, "owner");
+            }
+            "ATTENDEE" => {
+                let email = strip_mailto(&line.value).to_string();
+                let participant = participant_entry(&mut participants, line.param("CN"), &email);
+                match line.param("ROLE").map(|role| role.to_ascii_uppercase()) {
+                    Some(role) if role == "CHAIR" => {
+                        set_role(participant, "attendee");
+                        set_role(participant, "chair");
+                    }
+                    Some(role) if role == "OPT-PARTICIPANT" => {
+                        set_role(participant, "attendee");
+                        set_role(participant, "optional");
+                    }
+                    Some(role) if role == "NON-PARTICIPANT" => {
+                        set_role(participant, "informational");
+                    }
+                    _ => {
+                        set_role(participant, "attendee");
+                    }
+                }
+                if let Some(status) = line.param("PARTSTAT") {
+                    participant.set_field(
+                        "participationStatus",
+                        Value::Text(status.to_ascii_lowercase()),
+                    );
+                }
+                if let Some(kind) = line.param("CUTYPE") {
+                    participant.set_field("kind", Value::Text(kind.to_ascii_lowercase()));
+                }
+                if line
+                    .param("RSVP")
+                    .is_some_and(|rsvp| rsvp.eq_ignore_ascii_case("TRUE"))
+                {
+                    participant.set_field("expectReply", Value::Bool(true));
+                }
+            }
+            name if SKIP_PROPERTIES.contains(&name) => (),
+            _ => {
+                ical_props.push(ical_prop(line));
+            }
+        }
+    }
+
+    // JSCalendar events have a duration rather than an end time
+    if let (Some((end, end_zone, _)), Some(start)) = (
+        end,
+        event.text_field("start").and_then(parse_local_datetime),
+    ) {
+        let end = end.timestamp() - time_zone_offset(&event, end_zone.as_deref());
+        let start = start.timestamp() - time_zone_offset(&event, event.text_field("timeZone"));
+        if end > start {
+            event.set_field("duration", Value::Text(format_duration(end - start)));
+        }
+    }
+
+    let mut alerts = Object::with_capacity(0);
+    for alarm in component
+        .children
+        .iter()
+        .filter(|component| component.name == "VALARM")
+    {
+        if let Some(trigger) = alarm.lines.iter().find(|line| line.name == "TRIGGER") {
+            let trigger = if let Some((date, _, _)) = parse_datetime(trigger, &trigger.value) {
+                typed_object("AbsoluteTrigger")
+                    .with_field("when", format!("{}Z", format_local_datetime(&date)))
+            } else if parse_duration(&trigger.value).is_some() {
+                let mut offset =
+                    typed_object("OffsetTrigger").with_field("offset", trigger.value.as_str());
+                if trigger
+                    .param("RELATED")
+                    .is_some_and(|related| related.eq_ignore_ascii_case("END"))
+                {
+                    offset.set_field("relativeTo", Value::Text("end".to_string()));
+                }
+                offset
+            } else {
+                continue;
+            };
+            let action = alarm
+                .lines
+                .iter()
+                .find(|line| line.name == "ACTION")
+                .filter(|line| line.value.eq_ignore_ascii_case("EMAIL"))
+                .map_or("display", |_| "email");
+            let id = format!("a{}", alerts.properties.len() + 1);
+            alerts.set_field(
+                &id,
+                typed_object("Alert")
+                    .with_field("trigger", trigger)
+                    .with_field("action", action)
+                    .into(),
+            );
+        }
+    }
+
+    for (name, value) in [
+        ("participants", participants),
+        ("recurrenceOverrides", recurrence_overrides),
+        ("keywords", keywords),
+        ("locations", locations),
+        ("alerts", alerts),
+    ] {
+        if !value.properties.is_empty() {
+            event.set_field(name, value.into());
+        }
+    }
+    if !recurrence_rules.is_empty() {
+        event.set_field("recurrenceRules", Value::List(recurrence_rules));
+    }
+    if !ical_props.is_empty() {
+        event.set_field("iCalProps", Value::List(ical_props));
+    }
+
+    event
+}
+
+fn parse_time_zone(tz_id: &str, zone: &Component) -> Object<Value> {
+    let mut time_zone = typed_object("TimeZone").with_field("tzId", tz_id);
+    for (component_name, field) in [("STANDARD", "standard"), ("DAYLIGHT", "daylight")] {
+        let mut rules = Vec::new();
+        for component in zone
+            .children
+            .iter()
+            .filter(|component| component.name == component_name)
+        {
+            let mut rule = typed_object("TimeZoneRule");
+            for line in &component.lines {
+                match line.name.as_str() {
+                    "DTSTART" => {
+                        if let Some((start, _, _)) = parse_datetime(line, &line.value) {
+                            rule.set_field("start", Value::Text(format_local_datetime(&start)));
+                        }
+                    }
+                    "TZOFFSETFROM" => {
+                        rule.set_field("offsetFrom", Value::Text(line.value.clone()));
+                    }
+                    "TZOFFSETTO" => {
+                        rule.set_field("offsetTo", Value::Text(line.value.clone()));
+                    }
+                    "TZNAME" => {
+                        rule.set_field(
+                            "names",
+                            Object::with_capacity(1)
+                                .with_field(&line.value, true)
+                                .into(),
+                        );
+                    }
+                    "RRULE" => {
+                        if let Some(recurrence_rule) = parse_rrule(&line.value) {
+                            rule.set_field(
+                                "recurrenceRules",
+                                Value::List(vec![recurrence_rule.into()]),
+                            );
+                        }
+                    }
+                    _ => (),
+                }
+            }
+            rules.push(rule.into());
+        }
+        if !rules.is_empty() {
+            time_zone.set_field(field, Value::List(rules));
+        }
+    }
+    time_zone
+}
+
+// Parses an iCalendar RRULE. Rules with negative values can't be represented
+// as unsigned integers and are returned as None so they are kept verbatim.
+fn parse_rrule(value: &str) -> Option<Object<Value>> {
+    let mut rule = typed_object("RecurrenceRule");
+    for part in value.split(';') {
+        let (key, value) = part.split_once('=')?;
+        let key = key.to_ascii_uppercase();
+        match key.as_str() {
+            "FREQ" => {
+                rule.set_field("frequency", Value::Text(value.to_ascii_lowercase()));
+            }
+            "INTERVAL" | "COUNT" => {
+                rule.set_field(
+                    &key.to_ascii_lowercase(),
+                    Value::UnsignedInt(value.parse().ok()?),
+                );
+            }
+            "UNTIL" => {
+                let until = parse_ical_datetime(value)?.0;
+                rule.set_field("until", Value::Text(format_local_datetime(&until)));
+            }
+            "WKST" => {
+                rule.set_field("firstDayOfWeek", Value::Text(value.to_ascii_lowercase()));
+            }
+            "BYDAY" => {
+                let mut days = Vec::new();
+                for day in value.split(',') {
+                    let pos = day.len().checked_sub(2)?;
+                    let mut n_day =
+                        typed_object("NDay").with_field("day", day[pos..].to_ascii_lowercase());
+                    if pos > 0 {
+                        n_day.set_field(
+                            "nthOfPeriod",
+                            Value::UnsignedInt(day[..pos].trim_start_matches('+').parse().ok()?),
+                        );
+                    }
+                    days.push(n_day.into());
+                }
+                rule.set_field("byDay", Value::List(days));
+            }
+            "BYMONTH" => {
+                rule.set_field(
+                    "byMonth",
+                    Value::List(
+                        value
+                            .split(',')
+                            .map(|month| Value::Text(month.to_string()))
+                            .collect(),
+                    ),
+                );
+            }
+            "BYMONTHDAY" | "BYYEARDAY" | "BYWEEKNO" | "BYHOUR" | "BYMINUTE" | "BYSECOND"
+            | "BYSETPOS" => {
+                let field = match key.as_str() {
+                    "BYMONTHDAY" => "byMonthDay",
+                    "BYYEARDAY" => "byYearDay",
+                    "BYWEEKNO" => "byWeekNo",
+                    "BYHOUR" => "byHour",
+                    "BYMINUTE" => "byMinute",
+                    "BYSECOND" => "bySecond",
+                    _ => "bySetPosition",
+                };
+                let mut values = Vec::new();
+                for value in value.split(',') {
+                    values.push(Value::UnsignedInt(
+                        value.trim_start_matches('+').parse().ok()?,
+                    ));
+                }
+                rule.set_field(field, Value::List(values));
+            }
+            _ => return None,
+        }
+    }
+    rule.field("frequency").is_some().then_some(rule)
+}
+
+fn participant_entry<'x>(
+    participants: &'x mut Object<Value>,
+    name: Option<&str>,
+    email: &str,
+) -> &'x mut Object<Value> {
+    let id = participants
+        .properties
+        .iter()
+        .find(|(_, participant)| {
+            participant
+                .as_obj()
+                .and_then(participant_email)
+                .is_some_and(|address| address.eq_ignore_ascii_case(email))
+        })
+        .map(|(id, _)| id.to_string());
+    let id = if let Some(id) = id {
+        id
+    } else {
+        let id = format!("p{}", participants.properties.len() + 1);
+        participants.set_field(&id, new_participant(name, email).into());
+        id
+    };
+    participants
+        .field_mut(&id)
+        .and_then(|participant| participant.as_obj_mut())
+        .unwrap()
+}
+
+fn set_role(participant: &mut Object<Value>, role: &str) {
+    if let Some(Value::Object(roles)) = participant.field_mut("roles") {
+        roles.set_field(role, Value::Bool(true));
+    } else {
+        participant.set_field(
+            "roles",
+            Object::with_capacity(1).with_field(role, true).into(),
+        );
+    }
+}
+
+fn ical_prop(line: &ContentLine) -> Value {
+    let mut params = Object::with_capacity(line.params.len());
+    for (key, values) in &line.params {
+        params.set_field(&key.to_lowercase(), Value::Text(values.join(",")));
+    }
+    Value::List(vec![
+        Value::Text(line.name.to_lowercase()),
+        params.into(),
+        Value::Text("unknown".to_string()),
+        Value::Text(line.value.clone()),
+    ])
+}
+
+// Parses a DATE or DATE-TIME value, returning the local time, its time zone
+// and whether it is a date without a time.
+fn parse_datetime(
+    line: &ContentLine,
+    value: &str,
+) -> Option<(NaiveDateTime, Option<String>, bool)> {
+    let (date, is_utc, is_date) = parse_ical_datetime(value)?;
+    let time_zone = if is_utc {
+        Some("Etc/UTC".to_string())
+    } else if is_date {
+        None
+    } else {
+        line.param("TZID").map(|tz_id| tz_id.to_string())
+    };
+    Some((date, time_zone, is_date))
+}
+
+fn parse_ical_datetime(value: &str) -> Option<(NaiveDateTime, bool, bool)> {
+    let value = value.trim();
+    if let Some(value) = value.strip_suffix(['Z', 'z']) {
+        NaiveDateTime::parse_from_str(value, "%Y%m%dT%H%M%S")
+            .ok()
+            .map(|date| (date, true, false))
+    } else if value.len() == 8 {
+        chrono::NaiveDate::parse_from_str(value, "%Y%m%d")
+            .ok()
+            .and_then(|date| date.and_hms_opt(0, 0, 0))
+            .map(|date| (date, false, true))
+    } else {
+        NaiveDateTime::parse_from_str(value, "%Y%m%dT%H%M%S")
+            .ok()
+            .map(|date| (date, false, false))
+    }
+}
+
+// Converts a JSCalendar event to an iCalendar object, optionally for an iTIP method.
+pub fn build_ical(event: &Object<Value>, method: Option<&str>) -> String {
+    let mut ical = String::with_capacity(1024);
+    write_line(&mut ical, "BEGIN", &[], "VCALENDAR");
+    write_line(&mut ical, "VERSION", &[], "2.0");
+    write_line(
+        &mut ical,
+        "PRODID",
+        &[],
+        "-//Stalwart Labs Ltd.//Stalwart Mail Server//EN",
+    );
+    if let Some(method) = method {
+        write_line(&mut ical, "METHOD", &[], method);
+    }
+
+    for (tz_id, zone) in event
+        .field("timeZones")
+        .and_then(|zones| zones.as_obj())
+        .into_iter()
+        .flat_map(|zones| zones.properties.iter())
+    {
+        if let Some(zone) = zone.as_obj() {
+            write_time_zone(
+                &mut ical,
+                zone.text_field("tzId").unwrap_or(&tz_id.to_string()),
+                zone,
+            );
+        }
+    }
+
+    write_event(&mut ical, event, None);
+    for (recurrence_id, patch) in event
+        .field("recurrenceOverrides")
+        .and_then(|overrides| overrides.as_obj())
+        .into_iter()
+        .flat_map(|overrides| overrides.properties.iter())
+    {
+        let patch = match patch.as_obj() {
+            Some(patch) if !patch.properties.is_empty() && patch.field("excluded").is_none() => {
+                patch
+            }
+            _ => continue,
+        };
+        let mut instance = Object::with_capacity(event.properties.len());
+        for (property, value) in &event.properties {
+            if !matches!(
+                property.to_string().as_str(),
+                "recurrenceRules" | "recurrenceOverrides"
+            ) {
+                instance.append(property.clone(), value.clone());
+            }
+        }
+        for (property, value) in &patch.properties {
+            instance.set_field(&property.to_string(), value.clone());
+        }
+        write_event(&mut ical, &instance, Some(&recurrence_id.to_string()));
+    }
+
+    write_line(&mut ical, "END", &[], "VCALENDAR");
+    ical
+}
+
+fn write_event(ical: &mut String, event: &Object<Value>, recurrence_id: Option<&str>) {
+    write_line(ical, "BEGIN", &[], "VEVENT");
+    if let Some(uid) = event.text_field("uid") {
+        write_line(ical, "UID", &[], uid);
+    }
+    let updated = event
+        .text_field("updated")
+        .and_then(parse_local_datetime)
+        .unwrap_or_else(|| Utc::now().naive_utc());
+    write_line(ical, "DTSTAMP", &[], &format_utc(&updated));
+    if let Some(created) = event.text_field("created").and_then(parse_local_datetime) {
+        write_line(ical, "CREATED", &[], &format_utc(&created));
+    }
+    let sequence = event.sequence();
+    if sequence > 0 {
+        write_line(ical, "SEQUENCE", &[], &sequence.to_string());
+    }
+
+    let time_zone = event.text_field("timeZone");
+    let show_without_time = event
+        .field("showWithoutTime")
+        .and_then(|value| value.as_bool())
+        .unwrap_or(false);
+    if let Some(start) = event.text_field("start").and_then(parse_local_datetime) {
+        write_datetime(ical, "DTSTART", &start, time_zone, show_without_time);
+    }
+    if let Some(duration) = event.text_field("duration") {
+        write_line(ical, "DURATION", &[], duration);
+    }
+    if let Some(recurrence_id) = recurrence_id.and_then(parse_local_datetime) {
+        write_datetime(
+            ical,
+            "RECURRENCE-ID",
+            &recurrence_id,
+            time_zone,
+            show_without_time,
+        );
+    } else if recurrence_id.is_none() {
+        let has_verbatim_rule = ical_props(event).any(|(name, _, _)| name == "rrule");
+        for rule in event
+            .field("recurrenceRules")
+            .and_then(|rules| rules.as_list())
+            .into_iter()
+            .flatten()
+            .filter_map(|rule| rule.as_obj())
+            .filter(|_| !has_verbatim_rule)
+        {
+            write_line(ical, "RRULE", &[], &build_rrule(rule));
+        }
+        for (recurrence_id, patch) in event
+            .field("recurrenceOverrides")
+            .and_then(|overrides| overrides.as_obj())
+            .into_iter()
+            .flat_map(|overrides| overrides.properties.iter())
+        {
+            if let Some(date) = parse_local_datetime(&recurrence_id.to_string()) {
+                let name = if patch
+                    .as_obj()
+                    .and_then(|patch| patch.field("excluded"))
+                    .and_then(|excluded| excluded.as_bool())
+                    .unwrap_or(false)
+                {
+                    "EXDATE"
+                } else {
+                    "RDATE"
+                };
+                write_datetime(ical, name, &date, time_zone, show_without_time);
+            }
+        }
+    }
+
+    for (field, name) in [("title", "SUMMARY"), ("description", "DESCRIPTION")] {
+        if let Some(value) = event.text_field(field) {
+            write_line(ical, name, &[], &escape(value));
+        }
+    }
+    for location in map_values(event, "locations") {
+        if let Some(name) = location.text_field("name") {
+            write_line(ical, "LOCATION", &[], &escape(name));
+        }
+    }
+    if let Some(status) = event.text_field("status") {
+        write_line(ical, "STATUS", &[], &status.to_ascii_uppercase());
+    }
+    if let Some(status) = event.text_field("freeBusyStatus") {
+        write_line(
+            ical,
+            "TRANSP",
+            &[],
+            if status == "free" {
+                "TRANSPARENT"
+            } else {
+                "OPAQUE"
+            },
+        );
+    }
+    if let Some(privacy) = event.text_field("privacy") {
+        write_line(
+            ical,
+            "CLASS",
+            &[],
+            match privacy {
+                "private" => "PRIVATE",
+                "secret" => "CONFIDENTIAL",
+                _ => "PUBLIC",
+            },
+        );
+    }
+    if let Some(priority) = event
+        .field("priority")
+        .and_then(|priority| priority.try_cast_uint())
+    {
+        write_line(ical, "PRIORITY", &[], &priority.to_string());
+    }
+    let keywords = flags(event.field("keywords"))
+        .map(|(keyword, _)| escape(keyword))
+        .collect::<Vec<_>>();
+    if !keywords.is_empty() {
+        write_line(ical, "CATEGORIES", &[], &keywords.join(","));
+    }
+
+    let participants = event.participants();
+    if let Some(organizer) = event.organizer().or_else(|| {
+        participants
+            .iter()
+            .find(|(_, participant)| participant_has_role(participant, "owner"))
+            .and_then(|(_, participant)| participant_email(participant))
+    }) {
+        let name = participants
+            .iter()
+            .find(|(_, participant)| {
+                participant_email(participant)
+                    .is_some_and(|email| email.eq_ignore_ascii_case(organizer))
+            })
+            .and_then(|(_, participant)| participant.text_field("name"));
+        let mut params = Vec::new();
+        if let Some(name) = name {
+            params.push(("CN", name.to_string()));
+        }
+        write_line(ical, "ORGANIZER", &params, &format!("mailto:{organizer}"));
+    }
+    for (_, participant) in &participants {
+        let email = if let Some(email) = participant_email(participant) {
+            email
+        } else {
+            continue;
+        };
+        let role = if participant_has_role(participant, "chair") {
+            "CHAIR"
+        } else if participant_has_role(participant, "optional") {
+            "OPT-PARTICIPANT"
+        } else if participant_has_role(participant, "attendee") {
+            "REQ-PARTICIPANT"
+        } else if participant_has_role(participant, "informational") {
+            "NON-PARTICIPANT"
+        } else {
+            // Owners that are not attending are only written as the organizer
+            continue;
+        };
+        let mut params = Vec::with_capacity(5);
+        if let Some(name) = participant.text_field("name") {
+            params.push(("CN", name.to_string()));
+        }
+        if let Some(kind) = participant.text_field("kind") {
+            params.push(("CUTYPE", kind.to_ascii_uppercase()));
+        }
+        params.push(("ROLE", role.to_string()));
+        params.push((
+            "PARTSTAT",
+            participant
+                .text_field("participationStatus")
+                .unwrap_or("needs-action")
+                .to_ascii_uppercase(),
+        ));
+        if participant
+            .field("expectReply")
+            .and_then(|value| value.as_bool())
+            .unwrap_or(false)
+        {
+            params.push(("RSVP", "TRUE".to_string()));
+        }
+        write_line(ical, "ATTENDEE", &params, &format!("mailto:{email}"));
+    }
+
+    for (name, params, value) in ical_props(event) {
+        let mut line = name.to_uppercase();
+        for (key, value) in params {
+            line.push(';');
+            line.push_str(&key.to_uppercase());
+            line.push('=');
+            line.push_str(value);
+        }
+        write_line(ical, &line, &[], value);
+    }
+
+    for alert in map_values(event, "alerts") {
+        let trigger = if let Some(trigger) = alert.field("trigger").and_then(|t| t.as_obj()) {
+            trigger
+        } else {
+            continue;
+        };
+        write_line(ical, "BEGIN", &[], "VALARM");
+        if let Some(offset) = trigger.text_field("offset") {
+            let mut params = Vec::new();
+            if trigger.text_field("relativeTo") == Some("end") {
+                params.push(("RELATED", "END".to_string()));
+            }
+            write_line(ical, "TRIGGER", &params, offset);
+        } else if let Some(when) = trigger.text_field("when").and_then(parse_local_datetime) {
+            write_line(
+                ical,
+                "TRIGGER",
+                &[("VALUE", "DATE-TIME".to_string())],
+                &format_utc(&when),
+            );
+        }
+        if alert.text_field("action") == Some("email") {
+            write_line(ical, "ACTION", &[], "EMAIL");
+        } else {
+            write_line(ical, "ACTION", &[], "DISPLAY");
+            write_line(
+                ical,
+                "DESCRIPTION",
+                &[],
+                &escape(event.text_field("title").unwrap_or("Reminder")),
+            );
+        }
+        write_line(ical, "END", &[], "VALARM");
+    }
+
+    write_line(ical, "END", &[], "VEVENT");
+}
+
+fn write_time_zone(ical: &mut String, tz_id: &str, zone: &Object<Value>) {
+    write_line(ical, "BEGIN", &[], "VTIMEZONE");
+    write_line(ical, "TZID", &[], tz_id);
+    for (field, name) in [("standard", "STANDARD"), ("daylight", "DAYLIGHT")] {
+        for rule in zone
+            .field(field)
+            .and_then(|rules| rules.as_list())
+            .into_iter()
+            .flatten()
+            .filter_map(|rule| rule.as_obj())
+        {
+            write_line(ical, "BEGIN", &[], name);
+            let start = rule
+                .text_field("start")
+                .and_then(parse_local_datetime)
+                .map_or_else(
+                    || "19700101T000000".to_string(),
+                    |start| format_ical(&start),
+                );
+            write_line(ical, "DTSTART", &[], &start);
+            for (field, name) in [("offsetFrom", "TZOFFSETFROM"), ("offsetTo", "TZOFFSETTO")] {
+                if let Some(offset) = rule.text_field(field) {
+                    write_line(ical, name, &[], offset);
+                }
+            }
+            for (name, _) in flags(rule.field("names")) {
+                write_line(ical, "TZNAME", &[], name);
+            }
+            for recurrence_rule in rule
+                .field("recurrenceRules")
+                .and_then(|rules| rules.as_list())
+                .into_iter()
+                .flatten()
+                .filter_map(|rule| rule.as_obj())
+            {
+                write_line(ical, "RRULE", &[], &build_rrule(recurrence_rule));
+            }
+            write_line(ical, "END", &[], name);
+        }
+    }
+    write_line(ical, "END", &[], "VTIMEZONE");
+}
+
+fn build_rrule(rule: &Object<Value>) -> String {
+    let mut parts = vec![format!(
+        "FREQ={}",
+        rule.text_field("frequency")
+            .unwrap_or("daily")
+            .to_ascii_uppercase()
+    )];
+    for (field, name) in [("interval", "INTERVAL"), ("count", "COUNT")] {
+        if let Some(value) = rule.field(field).and_then(|value| value.try_cast_uint()) {
+            parts.push(format!("{name}={value}"));
+        }
+    }
+    if let Some(until) = rule.text_field("until").and_then(parse_local_datetime) {
+        parts.push(format!("UNTIL={}", format_ical(&until)));
+    }
+    if let Some(day) = rule.text_field("firstDayOfWeek") {
+        parts.push(format!("WKST={}", day.to_ascii_uppercase()));
+    }
+    let days = rule
+        .field("byDay")
+        .and_then(|days| days.as_list())
+        .into_iter()
+        .flatten()
+        .filter_map(|day| day.as_obj())
+        .filter_map(|day| {
+            let name = day.text_field("day")?.to_ascii_uppercase();
+            Some(
+                match day.field("nthOfPeriod").and_then(|n| n.try_cast_uint()) {
+                    Some(nth) => format!("{nth}{name}"),
+                    None => name,
+                },
+            )
+        })
+        .collect::<Vec<_>>();
+    if !days.is_empty() {
+        parts.push(format!("BYDAY={}", days.join(",")));
+    }
+    for (field, name) in [
+        ("byMonth", "BYMONTH"),
+        ("byMonthDay", "BYMONTHDAY"),
+        ("byYearDay", "BYYEARDAY"),
+        ("byWeekNo", "BYWEEKNO"),
+        ("byHour", "BYHOUR"),
+        ("byMinute", "BYMINUTE"),
+        ("bySecond", "BYSECOND"),
+        ("bySetPosition", "BYSETPOS"),
+    ] {
+        let values = rule
+            .field(field)
+            .and_then(|values| values.as_list())
+            .into_iter()
+            .flatten()
+            .filter_map(|value| match value {
+                Value::Text(value) => Some(value.clone()),
+                Value::UnsignedInt(value) => Some(value.to_string()),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        if !values.is_empty() {
+            parts.push(format!("{name}={}", values.join(",")));
+        }
+    }
+    parts.join(";")
+}
+
+fn write_datetime(
+    ical: &mut String,
+    name: &str,
+    date: &NaiveDateTime,
+    time_zone: Option<&str>,
+    is_date: bool,
+) {
+    if is_date {
+        write_line(
+            ical,
+            name,
+            &[("VALUE", "DATE".to_string())],
+            &date.format("%Y%m%d").to_string(),
+        );
+    } else {
+        match time_zone {
+            Some(time_zone) if UTC_ZONES.contains(&time_zone) => {
+                write_line(ical, name, &[], &format_utc(date));
+            }
+            Some(time_zone) => {
+                write_line(
+                    ical,
+                    name,
+                    &[("TZID", time_zone.to_string())],
+                    &format_ical(date),
+                );
+            }
+            None => {
+                write_line(ical, name, &[], &format_ical(date));
+            }
+        }
+    }
+}
+
+fn ical_props(event: &Object<Value>) -> impl Iterator<Item = (&str, Vec<(String, &str)>, &str)> {
+    event
+        .field("iCalProps")
+        .and_then(|props| props.as_list())
+        .into_iter()
+        .flatten()
+        .filter_map(|prop| match prop.as_list().map(|prop| prop.as_slice()) {
+            Some([name, params, _, value]) => Some((
+                name.as_string()?,
+                params
+                    .as_obj()
+                    .into_iter()
+                    .flat_map(|params| params.properties.iter())
+                    .filter_map(|(key, value)| Some((key.to_string(), value.as_string()?)))
+                    .collect(),
+                value.as_string()?,
+            )),
+            _ => None,
+        })
+}
+
+fn map_values<'x>(event: &'x Object<Value>, name: &str) -> impl Iterator<Item = &'x Object<Value>> {
+    event
+        .field(name)
+        .and_then(|map| map.as_obj())
+        .into_iter()
+        .flat_map(|map| map.properties.values())
+        .filter_map(|entry| entry.as_obj())
+}
+
+fn format_ical(date: &NaiveDateTime) -> String {
+    date.format("%Y%m%dT%H%M%S").to_string()
+}
+
+fn format_utc(date: &NaiveDateTime) -> String {
+    date.format("%Y%m%dT%H%M%SZ").to_string()
+}
+
+fn write_line(ical: &mut String, name: &str, params: &[(&str, String)], value: &str) {
+    let mut line = String::with_capacity(name.len() + value.len() + 16);
+    line.push_str(name);
+    for (key, value) in params {
+        line.push(';');
+        line.push_str(key);
+        line.push('=');
+        if value.contains([':', ';', ',']) {
+            line.push('"');
+            line.push_str(value);
+            line.push('"');
+        } else {
+            line.push_str(value);
+        }
+    }
+    line.push(':');
+    line.push_str(value);
+    fold_line(ical, &line);
+}
+
+// Returns the unescaped values of a property in the events of an iCalendar
+// object, used by CalDAV filters.
+pub fn ical_property_values(ical: &str, name: &str) -> Vec<String> {
+    let mut in_event = false;
+    let mut values = Vec::new();
+    for line in unfold(ical).iter().filter_map(|line| parse_line(line)) {
+        match line.name.as_str() {
+            "BEGIN" if line.value.eq_ignore_ascii_case("VEVENT") => in_event = true,
+            "END" if line.value.eq_ignore_ascii_case("VEVENT") => in_event = false,
+            _ if in_event && line.name.eq_ignore_ascii_case(name) => {
+                values.push(unescape(&line.value));
+            }
+            _ => (),
+        }
+    }
+    values
+}
@@ -0,0 +1,179 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use jmap_proto::{
+    object::Object,
+    types::{property::Property, value::Value},
+};
+use store::{
+    ahash::AHashSet,
+    write::{
+        assert::HashedValue, BatchBuilder, BitmapClass, BitmapHash, IntoOperations, Operation,
+        TokenizeText,
+    },
+    Serialize,
+};
+
+use crate::contact::JSContactObject;
+
+use super::{event_utc_range, participant_email, JSCalendarObject};
+
+pub struct EventIndex {
+    current: Option<HashedValue<Object<Value>>>,
+    changes: Option<Object<Value>>,
+}
+
+#[derive(Default)]
+struct EventTerms {
+    indexes: AHashSet<(u8, Vec<u8>)>,
+    tokens: AHashSet<(u8, String)>,
+}
+
+impl EventIndex {
+    pub fn insert(event: Object<Value>) -> Self {
+        EventIndex {
+            current: None,
+            changes: Some(event),
+        }
+    }
+
+    pub fn update(current: HashedValue<Object<Value>>, event: Object<Value>) -> Self {
+        EventIndex {
+            current: Some(current),
+            changes: Some(event),
+        }
+    }
+
+    pub fn delete(current: HashedValue<Object<Value>>) -> Self {
+        EventIndex {
+            current: Some(current),
+            changes: None,
+        }
+    }
+}
+
+impl IntoOperations for EventIndex {
+    fn build(self, batch: &mut BatchBuilder) {
+        let mut add = self
+            .changes
+            .as_ref()
+            .map(EventTerms::new)
+            .unwrap_or_default();
+        let mut remove = EventTerms::default();
+
+        if let Some(current) = &self.current {
+            batch.assert_value(Property::Value, current);
+            remove = EventTerms::new(&current.inner);
+
+            // Only write the terms that changed
+            add.indexes.retain(|term| !remove.indexes.remove(term));
+            add.tokens.retain(|term| !remove.tokens.remove(term));
+        }
+
+        for (terms, set) in [(add, true), (remove, false)] {
+            for (field, key) in terms.indexes {
+                batch.ops.push(Operation::Index { field, key, set });
+            }
+            for (field, token) in terms.tokens {
+                batch.ops.push(Operation::Bitmap {
+                    class: BitmapClass::Text {
+                        field,
+                        token: BitmapHash::new(token),
+                    },
+                    set,
+                });
+            }
+        }
+
+        if let Some(changes) = self.changes {
+            batch.set(Property::Value, changes.serialize());
+        } else {
+            batch.clear(Property::Value);
+        }
+    }
+}
+
+impl EventTerms {
+    fn new(event: &Object<Value>) -> Self {
+        let mut terms = EventTerms::default();
+
+        for calendar_id in event.calendar_ids() {
+            terms.index(Property::CalendarIds, calendar_id.serialize());
+        }
+        if let Some(uid) = event.text_field("uid") {
+            terms.index(Property::Uid, uid.serialize());
+        }
+        if let Some(dav_name) = event.get(&Property::DavName).as_string() {
+            terms.index(Property::DavName, dav_name.serialize());
+        }
+
+        // Recurring events without an end are indexed as ending at the end of time
+        let (start, end) = event_utc_range(event).unwrap_or((0, Some(0)));
+        terms.index(Property::Start, (start.max(0) as u64).serialize());
+        terms.index(
+            Property::End,
+            end.map_or(u64::MAX, |end| end.max(0) as u64).serialize(),
+        );
+
+        for (field, property) in [
+            ("title", Property::Title),
+            ("description", Property::Description),
+        ] {
+            if let Some(text) = event.text_field(field) {
+                terms.tokenize(property, text);
+            }
+        }
+        for location in event
+            .field("locations")
+            .and_then(|locations| locations.as_obj())
+            .into_iter()
+            .flat_map(|locations| locations.properties.values())
+            .filter_map(|location| location.as_obj())
+        {
+            if let Some(name) = location.text_field("name") {
+                terms.tokenize(Property::Location, name);
+            }
+        }
+        for (_, participant) in event.participants() {
+            if let Some(name) = participant.text_field("name") {
+                terms.tokenize(Property::Name, name);
+            }
+            if let Some(email) = participant_email(participant) {
+                terms.tokenize(Property::Email, email);
+            }
+        }
+
+        terms
+    }
+
+    fn index(&mut self, property: Property, key: Vec<u8>) {
+        self.indexes.insert((property.into(), key));
+    }
+
+    fn tokenize(&mut self, property: Property, text: &str) {
+        let field: u8 = property.into();
+        for token in text.to_tokens() {
+            self.tokens.insert((field, token));
+        }
+    }
+}
@@ -0,0 +1,440 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use directory::QueryBy;
+use jmap_proto::{
+    error::method::MethodError,
+    object::Object,
+    types::{
+        collection::Collection, id::Id, property::Property, state::StateChange,
+        type_state::DataType, value::Value,
+    },
+};
+use mail_builder::{headers::content_type::ContentType, mime::MimePart, MessageBuilder};
+use mail_parser::{Message, MimeHeaders};
+use smtp::core::{Session, SessionAddress};
+use store::{
+    query::Filter,
+    write::{assert::HashedValue, log::ChangeLogBuilder, BatchBuilder},
+};
+use utils::listener::stream::NullIo;
+
+use crate::{contact::JSContactObject, JMAP};
+
+use super::{
+    ical::{build_ical, parse_ical},
+    index::EventIndex,
+    participant_email, participant_has_role,
+    set::set_calendar_ids,
+    JSCalendarObject,
+};
+
+impl JMAP {
+    // Processes the iTIP messages (RFC 5546) carried by an iMIP message (RFC 6047).
+    // Scheduling messages are only accepted when the From address matches the
+    // envelope sender, which has to be authenticated either by SMTP AUTH or by
+    // an aligned DMARC pass.
+    pub async fn itip_ingest(
+        &self,
+        account_id: u32,
+        authenticated_sender: &str,
+        message: &Message<'_>,
+    ) {
+        let sender = if let Some(sender) = message
+            .from()
+            .and_then(|from| from.first())
+            .and_then(|addr| addr.address())
+            .filter(|sender| sender.eq_ignore_ascii_case(authenticated_sender))
+        {
+            sender.to_lowercase()
+        } else {
+            tracing::debug!(
+                context = "itip_ingest",
+                event = "skip",
+                account_id = account_id,
+                sender = authenticated_sender,
+                "Ignoring iMIP message whose From address does not match the authenticated sender."
+            );
+            return;
+        };
+
+        for part in &message.parts {
+            let is_calendar = part.content_type().is_some_and(|ct| {
+                ct.ctype().eq_ignore_ascii_case("text")
+                    && ct
+                        .subtype()
+                        .is_some_and(|st| st.eq_ignore_ascii_case("calendar"))
+            });
+            let ical = if let Some(ical) = part
+                .text_contents()
+                .filter(|_| is_calendar)
+                .and_then(parse_ical)
+            {
+                ical
+            } else {
+                continue;
+            };
+            let method = ical
+                .method
+                .or_else(|| {
+                    part.content_type()
+                        .and_then(|ct| ct.attribute("method"))
+                        .map(|method| method.to_ascii_uppercase())
+                })
+                .unwrap_or_default();
+
+            for event in ical.events {
+                if let Err(err) = self.itip_process(account_id, &sender, &method, event).await {
+                    tracing::debug!(
+                        context = "itip_ingest",
+                        event = "error",
+                        account_id = account_id,
+                        method = method,
+                        error = ?err,
+                        "Failed to process iTIP message.");
+                }
+            }
+            break;
+        }
+    }
+
+    async fn itip_process(
+        &self,
+        account_id: u32,
+        sender: &str,
+        method: &str,
+        mut event: Object<Value>,
+    ) -> Result<(), MethodError> {
+        let uid = if let Some(uid) = event.text_field("uid") {
+            uid.to_string()
+        } else {
+            return Ok(());
+        };
+        let current = self.calendar_event_by_uid(account_id, &uid).await?;
+
+        // Only the organizer may send requests and cancellations, and only
+        // attendees may reply. Updates to an existing event are checked against
+        // the organizer stored with it, as the sender controls the incoming one.
+        let is_organizer = event
+            .organizer()
+            .is_some_and(|organizer| organizer.eq_ignore_ascii_case(sender))
+            && current.as_ref().map_or(true, |(_, current)| {
+                current
+                    .inner
+                    .organizer()
+                    .is_some_and(|organizer| organizer.eq_ignore_ascii_case(sender))
+            });
+        let updated = match (method, current.as_ref()) {
+            ("REQUEST", _) if is_organizer => {
+                if let Some((_, current)) = &current {
+                    if event.sequence() < current.inner.sequence() {
+                        return Ok(());
+                    }
+                    for property in [Property::CalendarIds, Property::DavName] {
+                        event.set(property.clone(), current.inner.get(&property).clone());
+                    }
+                }
+                event
+            }
+            ("CANCEL", Some((_, current))) if is_organizer => {
+                let mut updated = current.inner.clone();
+                if let Some(recurrence_id) = event.text_field("recurrenceId") {
+                    let mut overrides = updated
+                        .remove_field("recurrenceOverrides")
+                        .and_then(|overrides| overrides.try_unwrap_object())
+                        .unwrap_or_else(|| Object::with_capacity(1));
+                    overrides.set_field(
+                        recurrence_id,
+                        Object::with_capacity(1)
+                            .with_property(Property::_T("excluded".to_string()), true)
+                            .into(),
+                    );
+                    updated.set_field("recurrenceOverrides", overrides.into());
+                } else {
+                    updated.set_field("status", Value::Text("cancelled".to_string()));
+                }
+                updated.set_field("sequence", Value::UnsignedInt(event.sequence()));
+                updated
+            }
+            ("REPLY", Some((_, current))) => {
+                let status = event
+                    .participants()
+                    .into_iter()
+                    .find(|(_, participant)| {
+                        participant_email(participant)
+                            .is_some_and(|email| email.eq_ignore_ascii_case(sender))
+                    })
+                    .and_then(|(_, participant)| participant.text_field("participationStatus"))
+                    .map(|status| status.to_string());
+                let mut updated = current.inner.clone();
+                match (status, updated.participant_mut(sender)) {
+                    (Some(status), Some(participant)) if event.field("recurrenceId").is_none() => {
+                        participant.set_field("participationStatus", Value::Text(status));
+                        participant.remove_field("expectReply");
+                    }
+                    _ => return Ok(()),
+                }
+                updated
+            }
+            _ => return Ok(()),
+        };
+
+        let mut batch = BatchBuilder::new();
+        let mut changes = ChangeLogBuilder::new();
+        batch
+            .with_account_id(account_id)
+            .with_collection(Collection::CalendarEvent);
+        if let Some((document_id, current)) = current {
+            batch
+                .update_document(document_id)
+                .custom(EventIndex::update(current, updated));
+            changes.log_update(Collection::CalendarEvent, document_id);
+        } else {
+            // New invitations are added to the default calendar
+            let calendar_id = self
+                .calendar_get_or_create(account_id)
+                .await?
+                .min()
+                .unwrap_or_default();
+            let document_id = self
+                .assign_document_id(account_id, Collection::CalendarEvent)
+                .await?;
+            let mut event = updated;
+            set_calendar_ids(&mut event, vec![calendar_id]);
+            event.set(
+                Property::DavName,
+                Value::Text(format!("{}.ics", Id::from(document_id))),
+            );
+            batch
+                .create_document(document_id)
+                .custom(EventIndex::insert(event));
+            changes.log_insert(Collection::CalendarEvent, document_id);
+        }
+        self.write_batch(batch).await?;
+        let change_id = self.commit_changes(account_id, changes).await?;
+        self.broadcast_state_change(
+            StateChange::new(account_id).with_change(DataType::CalendarEvent, change_id),
+        )
+        .await;
+
+        Ok(())
+    }
+
+    // Sends the scheduling messages for a created, updated or deleted event.
+    pub async fn itip_send(
+        &self,
+        account_id: u32,
+        current: Option<&Object<Value>>,
+        updated: Option<&Object<Value>>,
+    ) {
+        let event = if let Some(event) = updated.or(current) {
+            event
+        } else {
+            return;
+        };
+        let principal = match self
+            .directory
+            .load()
+            .query(QueryBy::Id(account_id), false)
+            .await
+        {
+            Ok(Some(principal)) if !principal.emails.is_empty() => principal,
+            _ => return,
+        };
+        let is_self = |email: &str| {
+            principal
+                .emails
+                .iter()
+                .any(|address| address.eq_ignore_ascii_case(email))
+        };
+        let from = principal.emails.first().unwrap().as_str();
+        let from_name = principal.description().unwrap_or_else(|| principal.name());
+        let title = event.text_field("title").unwrap_or("Event");
+
+        let mut messages = Vec::new();
+        if event.organizer().is_some_and(is_self) {
+            let attendees = |event: &Object<Value>| {
+                event
+                    .participants()
+                    .into_iter()
+                    .filter(|(_, participant)| {
+                        !participant_has_role(participant, "owner")
+                            || participant_has_role(participant, "attendee")
+                    })
+                    .filter_map(|(_, participant)| participant_email(participant))
+                    .filter(|email| !is_self(email))
+                    .map(|email| email.to_string())
+                    .collect::<Vec<_>>()
+            };
+            let is_cancelled =
+                updated.is_none_or(|event| event.text_field("status") == Some("cancelled"));
+            let recipients = attendees(event);
+            if is_cancelled {
+                messages.push(("CANCEL", format!("Cancelled: {title}"), recipients, event));
+            } else {
+                // Attendees removed from the event are sent a cancellation
+                let removed = current
+                    .map(|current| {
+                        attendees(current)
+                            .into_iter()
+                            .filter(|email| !recipients.contains(email))
+                            .collect::<Vec<_>>()
+                    })
+                    .unwrap_or_default();
+                if !removed.is_empty() {
+                    messages.push((
+                        "CANCEL",
+                        format!("Cancelled: {title}"),
+                        removed,
+                        current.unwrap(),
+                    ));
+                }
+                let subject = if current.is_some() {
+                    format!("Updated invitation: {title}")
+                } else {
+                    format!("Invitation: {title}")
+                };
+                messages.push(("REQUEST", subject, recipients, event));
+            }
+        }
+
+        // Attendees reply to the organizer when their participation status changes
+        let mut reply = None;
+        if let (Some(updated), Some(organizer)) = (
+            updated,
+            event.organizer().filter(|organizer| !is_self(organizer)),
+        ) {
+            if let Some((id, participant)) = updated
+                .participants()
+                .into_iter()
+                .find(|(_, participant)| participant_email(participant).is_some_and(is_self))
+            {
+                let status = participant.text_field("participationStatus");
+                let previous = current
+                    .and_then(|current| {
+                        current
+                            .participants()
+                            .into_iter()
+                            .find(|(current_id, _)| *current_id == id)
+                    })
+                    .and_then(|(_, participant)| participant.text_field("participationStatus"));
+                if status.is_some() && status != previous {
+                    let mut event = updated.clone();
+                    event.set_field(
+                        "participants",
+                        Object::with_capacity(1)
+                            .with_property(Property::_T(id.to_string()), participant.clone())
+                            .into(),
+                    );
+                    let subject = format!(
+                        "{}: {title}",
+                        match status.unwrap_or_default() {
+                            "accepted" => "Accepted",
+                            "declined" => "Declined",
+                            "tentative" => "Tentative",
+                            _ => "Reply",
+                        }
+                    );
+                    reply = Some((subject, organizer.to_string(), event));
+                }
+            }
+        }
+        if let Some((subject, organizer, event)) = &reply {
+            messages.push(("REPLY", subject.clone(), vec![organizer.clone()], event));
+        }
+
+        for (method, subject, recipients, event) in messages {
+            if recipients.is_empty() {
+                continue;
+            }
+            let ical = build_ical(event, Some(method));
+            let message = MessageBuilder::new()
+                .from((from_name, from))
+                .to(recipients
+                    .iter()
+                    .map(|recipient| recipient.as_str())
+                    .collect::<Vec<_>>())
+                .subject(subject.as_str())
+                .body(MimePart::new(
+                    "multipart/alternative",
+                    vec![
+                        MimePart::new("text/plain", subject.as_str()),
+                        MimePart::new(
+                            ContentType::new("text/calendar")
+                                .attribute("method", method)
+                                .attribute("charset", "utf-8"),
+                            ical.as_str(),
+                        ),
+                    ],
+                ))
+                .write_to_vec()
+                .unwrap_or_default();
+
+            let result = Session::<NullIo>::sieve(
+                self.smtp.load_full(),
+                SessionAddress::new(from.to_string()),
+                recipients.into_iter().map(SessionAddress::new).collect(),
+                message,
+            )
+            .queue_message()
+            .await;
+
+            tracing::debug!(
+                context = "itip_send",
+                event = "send_message",
+                account_id = account_id,
+                method = method,
+                smtp_response = std::str::from_utf8(&result).unwrap_or_default()
+            );
+        }
+    }
+
+    pub async fn calendar_event_by_uid(
+        &self,
+        account_id: u32,
+        uid: &str,
+    ) -> Result<Option<(u32, HashedValue<Object<Value>>)>, MethodError> {
+        if let Some(document_id) = self
+            .filter(
+                account_id,
+                Collection::CalendarEvent,
+                vec![Filter::eq(Property::Uid, uid)],
+            )
+            .await?
+            .results
+            .min()
+        {
+            Ok(self
+                .get_property::<HashedValue<Object<Value>>>(
+                    account_id,
+                    Collection::CalendarEvent,
+                    document_id,
+                    Property::Value,
+                )
+                .await?
+                .map(|event| (document_id, event)))
+        } else {
+            Ok(None)
+        }
+    }
+}
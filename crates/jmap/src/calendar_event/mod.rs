@@ -0,0 +1,323 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use chrono::{NaiveDate, NaiveDateTime};
+use jmap_proto::{
+    object::Object,
+    types::{property::Property, value::Value},
+};
+
+use crate::contact::{JSContactObject, ObjectBuilder};
+
+pub mod get;
+pub mod ical;
+pub mod index;
+pub mod itip;
+pub mod query;
+pub mod set;
+
+pub const EVENT_TYPE: &str = "Event";
+
+pub trait JSCalendarObject {
+    fn calendar_ids(&self) -> Vec<u32>;
+    fn participants(&self) -> Vec<(&str, &Object<Value>)>;
+    fn participant_mut(&mut self, email: &str) -> Option<&mut Object<Value>>;
+    fn organizer(&self) -> Option<&str>;
+    fn sequence(&self) -> u64;
+}
+
+impl JSCalendarObject for Object<Value> {
+    fn calendar_ids(&self) -> Vec<u32> {
+        self.properties
+            .get(&Property::CalendarIds)
+            .and_then(|ids| ids.as_list())
+            .map(|ids| {
+                ids.iter()
+                    .filter_map(|id| id.try_cast_uint().map(|id| id as u32))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    fn participants(&self) -> Vec<(&str, &Object<Value>)> {
+        self.field("participants")
+            .and_then(|participants| participants.as_obj())
+            .into_iter()
+            .flat_map(|participants| participants.properties.iter())
+            .filter_map(|(id, participant)| match (id, participant.as_obj()) {
+                (Property::_T(id), Some(participant)) => Some((id.as_str(), participant)),
+                _ => None,
+            })
+            .collect()
+    }
+
+    fn participant_mut(&mut self, email: &str) -> Option<&mut Object<Value>> {
+        let participants = match self.field_mut("participants") {
+            Some(Value::Object(participants)) => participants,
+            _ => return None,
+        };
+        participants
+            .properties
+            .values_mut()
+            .filter_map(|participant| match participant {
+                Value::Object(participant) => Some(participant),
+                _ => None,
+            })
+            .find(|participant| {
+                participant_email(participant).is_some_and(|addr| addr.eq_ignore_ascii_case(email))
+            })
+    }
+
+    fn organizer(&self) -> Option<&str> {
+        self.field("replyTo")
+            .and_then(|reply_to| reply_to.as_obj())
+            .and_then(|reply_to| reply_to.text_field("imip"))
+            .map(strip_mailto)
+    }
+
+    fn sequence(&self) -> u64 {
+        self.field("sequence")
+            .and_then(|sequence| sequence.try_cast_uint())
+            .unwrap_or(0)
+    }
+}
+
+pub fn participant_email(participant: &Object<Value>) -> Option<&str> {
+    participant.text_field("email").or_else(|| {
+        participant
+            .field("sendTo")
+            .and_then(|send_to| send_to.as_obj())
+            .and_then(|send_to| send_to.text_field("imip"))
+            .map(strip_mailto)
+    })
+}
+
+pub fn participant_has_role(participant: &Object<Value>, role: &str) -> bool {
+    participant
+        .field("roles")
+        .and_then(|roles| roles.as_obj())
+        .and_then(|roles| roles.field(role))
+        .and_then(|value| value.as_bool())
+        .unwrap_or(false)
+}
+
+pub fn strip_mailto(uri: &str) -> &str {
+    uri.get(..7)
+        .filter(|scheme| scheme.eq_ignore_ascii_case("mailto:"))
+        .map_or(uri, |_| &uri[7..])
+}
+
+pub fn new_participant(name: Option<&str>, email: &str) -> Object<Value> {
+    let mut participant = Object::with_capacity(5)
+        .with_field("@type", "Participant")
+        .with_field("email", email)
+        .with_field(
+            "sendTo",
+            Object::with_capacity(1).with_field("imip", format!("mailto:{email}")),
+        );
+    if let Some(name) = name {
+        participant.set_field("name", Value::Text(name.to_string()));
+    }
+    participant
+}
+
+// Parses a JSCalendar LocalDateTime or UTCDateTime, also accepting plain dates.
+pub fn parse_local_datetime(value: &str) -> Option<NaiveDateTime> {
+    let value = value.trim_end_matches('Z');
+    NaiveDateTime::parse_from_str(value, "%Y-%m-%dT%H:%M:%S")
+        .ok()
+        .or_else(|| {
+            NaiveDate::parse_from_str(value, "%Y-%m-%d")
+                .ok()
+                .and_then(|date| date.and_hms_opt(0, 0, 0))
+        })
+}
+
+pub fn format_local_datetime(value: &NaiveDateTime) -> String {
+    value.format("%Y-%m-%dT%H:%M:%S").to_string()
+}
+
+// Parses an ISO 8601 duration (RFC 8984, Section 1.4.6) into seconds.
+pub fn parse_duration(value: &str) -> Option<i64> {
+    let (sign, value) = match value.strip_prefix('-') {
+        Some(value) => (-1, value),
+        None => (1, value.strip_prefix('+').unwrap_or(value)),
+    };
+    let mut seconds = 0i64;
+    let mut number = String::new();
+    let mut in_time = false;
+    for ch in value.strip_prefix('P')?.chars() {
+        match ch {
+            '0'..='9' | '.' => number.push(ch),
+            'T' => in_time = true,
+            _ => {
+                let value = number.parse::<f64>().ok()? as i64;
+                number.clear();
+                seconds += value
+                    * match (ch, in_time) {
+                        ('W', false) => 7 * 86400,
+                        ('D', false) => 86400,
+                        ('H', true) => 3600,
+                        ('M', true) => 60,
+                        ('S', true) => 1,
+                        _ => return None,
+                    };
+            }
+        }
+    }
+    number.is_empty().then_some(sign * seconds)
+}
+
+pub fn format_duration(seconds: i64) -> String {
+    let mut result = String::with_capacity(12);
+    if seconds < 0 {
+        result.push('-');
+    }
+    let seconds = seconds.unsigned_abs();
+    let (days, hours, minutes, seconds) = (
+        seconds / 86400,
+        (seconds % 86400) / 3600,
+        (seconds % 3600) / 60,
+        seconds % 60,
+    );
+    result.push('P');
+    if days > 0 {
+        result.push_str(&format!("{days}D"));
+    }
+    if hours > 0 || minutes > 0 || seconds > 0 || days == 0 {
+        result.push('T');
+        if hours > 0 {
+            result.push_str(&format!("{hours}H"));
+        }
+        if minutes > 0 {
+            result.push_str(&format!("{minutes}M"));
+        }
+        if seconds > 0 || (hours == 0 && minutes == 0) {
+            result.push_str(&format!("{seconds}S"));
+        }
+    }
+    result
+}
+
+// Returns the UTC offset in seconds of a time zone. Only the zones defined in the
+// event itself are known, so IANA names without a definition are treated as UTC
+// and daylight saving time is not applied.
+pub fn time_zone_offset(event: &Object<Value>, time_zone: Option<&str>) -> i64 {
+    let time_zone = match time_zone {
+        Some(time_zone) => time_zone,
+        None => return 0,
+    };
+    event
+        .field("timeZones")
+        .and_then(|zones| zones.as_obj())
+        .and_then(|zones| {
+            zones
+                .field(time_zone)
+                .or_else(|| zones.field(&format!("/{time_zone}")))
+        })
+        .and_then(|zone| zone.as_obj())
+        .and_then(|zone| zone.field("standard"))
+        .and_then(|rules| rules.as_list())
+        .and_then(|rules| rules.first())
+        .and_then(|rule| rule.as_obj())
+        .and_then(|rule| rule.text_field("offsetTo"))
+        .and_then(parse_utc_offset)
+        .unwrap_or(0)
+}
+
+// Parses an iCalendar UTC offset such as "+0100" or "-053000" into seconds.
+pub fn parse_utc_offset(value: &str) -> Option<i64> {
+    let (sign, value) = match value.as_bytes().first()? {
+        b'+' => (1, &value[1..]),
+        b'-' => (-1, &value[1..]),
+        _ => return None,
+    };
+    let hours = value.get(..2)?.parse::<i64>().ok()?;
+    let minutes = value.get(2..4)?.parse::<i64>().ok()?;
+    let seconds = value
+        .get(4..6)
+        .and_then(|s| s.parse::<i64>().ok())
+        .unwrap_or(0);
+    Some(sign * (hours * 3600 + minutes * 60 + seconds))
+}
+
+// Returns the UTC start and end of an event, in seconds since the epoch. The end
+// covers all occurrences of a recurring event, or is None if it repeats forever.
+pub fn event_utc_range(event: &Object<Value>) -> Option<(i64, Option<i64>)> {
+    let start = parse_local_datetime(event.text_field("start")?)?.timestamp()
+        - time_zone_offset(event, event.text_field("timeZone"));
+    let duration = event
+        .text_field("duration")
+        .and_then(parse_duration)
+        .unwrap_or(0)
+        .max(0);
+
+    let mut end = Some(start + duration);
+    for rule in event
+        .field("recurrenceRules")
+        .and_then(|rules| rules.as_list())
+        .into_iter()
+        .flatten()
+        .filter_map(|rule| rule.as_obj())
+    {
+        let rule_end = if let Some(until) = rule.text_field("until").and_then(parse_local_datetime)
+        {
+            until.timestamp() - time_zone_offset(event, event.text_field("timeZone")) + duration
+        } else if let Some(count) = rule.field("count").and_then(|count| count.try_cast_uint()) {
+            let interval = rule
+                .field("interval")
+                .and_then(|interval| interval.try_cast_uint())
+                .unwrap_or(1)
+                .max(1) as i64;
+            // Use the longest period of each frequency so the range is never too short
+            let period = match rule.text_field("frequency").unwrap_or_default() {
+                "yearly" => 366 * 86400,
+                "monthly" => 31 * 86400,
+                "weekly" => 7 * 86400,
+                "daily" => 86400,
+                "hourly" => 3600,
+                "minutely" => 60,
+                _ => 1,
+            };
+            start + (count as i64) * interval * period + duration
+        } else {
+            return Some((start, None));
+        };
+        end = end.map(|end| end.max(rule_end));
+    }
+    for (recurrence_id, _) in event
+        .field("recurrenceOverrides")
+        .and_then(|overrides| overrides.as_obj())
+        .into_iter()
+        .flat_map(|overrides| overrides.properties.iter())
+    {
+        if let Some(recurrence_id) = parse_local_datetime(&recurrence_id.to_string()) {
+            let rdate_end = recurrence_id.timestamp()
+                - time_zone_offset(event, event.text_field("timeZone"))
+                + duration;
+            end = end.map(|end| end.max(rdate_end));
+        }
+    }
+
+    Some((start, end))
+}
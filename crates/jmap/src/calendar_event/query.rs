@@ -0,0 +1,122 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use jmap_proto::{
+    error::method::MethodError,
+    method::query::{
+        Comparator, Filter, QueryRequest, QueryResponse, RequestArguments, SortProperty,
+    },
+    types::{collection::Collection, property::Property},
+};
+use store::{
+    query::{self},
+    roaring::RoaringBitmap,
+};
+
+use crate::JMAP;
+
+impl JMAP {
+    pub async fn calendar_event_query(
+        &self,
+        mut request: QueryRequest<RequestArguments>,
+    ) -> Result<QueryResponse, MethodError> {
+        let account_id = request.account_id.document_id();
+        let mut filters = Vec::with_capacity(request.filter.len());
+
+        for cond in std::mem::take(&mut request.filter) {
+            match cond {
+                Filter::InCalendars(ids) => {
+                    filters.push(query::Filter::Or);
+                    for id in ids {
+                        filters.push(query::Filter::eq(Property::CalendarIds, id.document_id()));
+                    }
+                    filters.push(query::Filter::End);
+                }
+                Filter::Uid(uid) => filters.push(query::Filter::eq(Property::Uid, uid)),
+                Filter::After(date) => filters.push(query::Filter::gt(Property::End, date)),
+                Filter::Before(date) => filters.push(query::Filter::lt(Property::Start, date)),
+                Filter::Title(text) => {
+                    filters.push(query::Filter::has_text(Property::Title, &text))
+                }
+                Filter::Description(text) => {
+                    filters.push(query::Filter::has_text(Property::Description, &text))
+                }
+                Filter::Location(text) => {
+                    filters.push(query::Filter::has_text(Property::Location, &text))
+                }
+                Filter::Text(text) => {
+                    filters.push(query::Filter::Or);
+                    for property in [
+                        Property::Title,
+                        Property::Description,
+                        Property::Location,
+                        Property::Name,
+                        Property::Email,
+                    ] {
+                        filters.push(query::Filter::has_text(property, &text));
+                    }
+                    filters.push(query::Filter::End);
+                }
+                Filter::Id(ids) => {
+                    let mut set = RoaringBitmap::new();
+                    for id in ids {
+                        set.insert(id.document_id());
+                    }
+                    filters.push(query::Filter::is_in_set(set));
+                }
+                Filter::And | Filter::Or | Filter::Not | Filter::Close => {
+                    filters.push(cond.into());
+                }
+                other => return Err(MethodError::UnsupportedFilter(other.to_string())),
+            }
+        }
+
+        let result_set = self
+            .filter(account_id, Collection::CalendarEvent, filters)
+            .await?;
+
+        let (response, paginate) = self.build_query_response(&result_set, &request).await?;
+
+        if let Some(paginate) = paginate {
+            // Parse sort criteria
+            let mut comparators = Vec::with_capacity(request.sort.as_ref().map_or(1, |s| s.len()));
+            for comparator in request
+                .sort
+                .and_then(|s| if !s.is_empty() { s.into() } else { None })
+                .unwrap_or_else(|| vec![Comparator::ascending(SortProperty::Start)])
+            {
+                comparators.push(match comparator.property {
+                    SortProperty::Start => {
+                        query::Comparator::field(Property::Start, comparator.is_ascending)
+                    }
+                    other => return Err(MethodError::UnsupportedSort(other.to_string())),
+                });
+            }
+
+            // Sort results
+            self.sort(result_set, comparators, paginate, response).await
+        } else {
+            Ok(response)
+        }
+    }
+}
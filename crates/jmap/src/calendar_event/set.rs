@@ -0,0 +1,384 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use jmap_proto::{
+    error::{
+        method::MethodError,
+        set::{SetError, SetErrorType},
+    },
+    method::set::{SetRequest, SetResponse},
+    object::{calendar::EventSetArguments, Object},
+    response::references::EvalObjectReferences,
+    types::{
+        collection::Collection,
+        id::Id,
+        property::Property,
+        state::StateChange,
+        type_state::DataType,
+        value::{MaybePatchValue, SetValue, Value},
+    },
+};
+use store::{
+    roaring::RoaringBitmap,
+    write::{assert::HashedValue, log::ChangeLogBuilder, BatchBuilder},
+    Serialize,
+};
+
+use crate::{
+    contact::{generate_uid, set::patch_object, JSContactObject},
+    JMAP,
+};
+
+use super::{index::EventIndex, parse_local_datetime, JSCalendarObject, EVENT_TYPE};
+
+impl JMAP {
+    pub async fn calendar_event_set(
+        &self,
+        mut request: SetRequest<EventSetArguments>,
+    ) -> Result<SetResponse, MethodError> {
+        let account_id = request.account_id.document_id();
+        let calendar_ids = self.calendar_get_or_create(account_id).await?;
+        let event_ids = self
+            .get_document_ids(account_id, Collection::CalendarEvent)
+            .await?
+            .unwrap_or_default();
+        let mut response = self
+            .prepare_set_response(&request, Collection::CalendarEvent)
+            .await?;
+        let will_destroy = request.unwrap_destroy();
+        let send_scheduling_messages = request.arguments.send_scheduling_messages.unwrap_or(false);
+
+        // Process creates
+        let mut changes = ChangeLogBuilder::new();
+        for (id, object) in request.unwrap_create() {
+            match self.calendar_event_set_item(object, None, &calendar_ids, &response) {
+                Ok(mut event) => {
+                    let document_id = self
+                        .assign_document_id(account_id, Collection::CalendarEvent)
+                        .await?;
+                    event.set(
+                        Property::DavName,
+                        Value::Text(format!("{}.ics", Id::from(document_id))),
+                    );
+                    let uid = event.text_field("uid").unwrap_or_default().to_string();
+                    let scheduling = send_scheduling_messages.then(|| event.clone());
+                    let mut batch = BatchBuilder::new();
+                    batch
+                        .with_account_id(account_id)
+                        .with_collection(Collection::CalendarEvent)
+                        .create_document(document_id)
+                        .custom(EventIndex::insert(event));
+                    self.write_batch(batch).await?;
+                    changes.log_insert(Collection::CalendarEvent, document_id);
+                    if let Some(event) = scheduling {
+                        self.itip_send(account_id, None, Some(&event)).await;
+                    }
+                    response.created.insert(
+                        id,
+                        Object::with_capacity(2)
+                            .with_property(Property::Id, Value::Id(document_id.into()))
+                            .with_property(Property::Uid, uid),
+                    );
+                }
+                Err(err) => {
+                    response.not_created.append(id, err);
+                }
+            }
+        }
+
+        // Process updates
+        'update: for (id, object) in request.unwrap_update() {
+            // Make sure id won't be destroyed
+            if will_destroy.contains(&id) {
+                response.not_updated.append(id, SetError::will_destroy());
+                continue 'update;
+            }
+
+            // Obtain event
+            let document_id = id.document_id();
+            let current = if let Some(current) = self
+                .get_property::<HashedValue<Object<Value>>>(
+                    account_id,
+                    Collection::CalendarEvent,
+                    document_id,
+                    Property::Value,
+                )
+                .await?
+            {
+                current
+            } else {
+                response.not_updated.append(id, SetError::not_found());
+                continue 'update;
+            };
+
+            match self.calendar_event_set_item(
+                object,
+                Some(&current.inner),
+                &calendar_ids,
+                &response,
+            ) {
+                Ok(event) => {
+                    let scheduling =
+                        send_scheduling_messages.then(|| (current.inner.clone(), event.clone()));
+                    let mut batch = BatchBuilder::new();
+                    batch
+                        .with_account_id(account_id)
+                        .with_collection(Collection::CalendarEvent)
+                        .update_document(document_id)
+                        .custom(EventIndex::update(current, event));
+                    match self.store.write(batch.build()).await {
+                        Ok(_) => {
+                            changes.log_update(Collection::CalendarEvent, document_id);
+                            response.updated.append(id, None);
+                            if let Some((current, event)) = scheduling {
+                                self.itip_send(account_id, Some(&current), Some(&event))
+                                    .await;
+                            }
+                        }
+                        Err(store::Error::AssertValueFailed) => {
+                            response.not_updated.append(
+                                id,
+                                SetError::forbidden().with_description(
+                                    "Another process modified this event, please try again.",
+                                ),
+                            );
+                        }
+                        Err(err) => {
+                            tracing::error!(
+                                event = "error",
+                                context = "calendar_event_set",
+                                account_id = account_id,
+                                error = ?err,
+                                "Failed to update event(s).");
+                            return Err(MethodError::ServerPartialFail);
+                        }
+                    }
+                }
+                Err(err) => {
+                    response.not_updated.append(id, err);
+                }
+            }
+        }
+
+        // Process deletions
+        for id in will_destroy {
+            let document_id = id.document_id();
+            if !event_ids.contains(document_id) {
+                response.not_destroyed.append(id, SetError::not_found());
+                continue;
+            }
+
+            if let Some(event) = self.calendar_event_delete(account_id, document_id).await? {
+                changes.log_delete(Collection::CalendarEvent, document_id);
+                response.destroyed.push(id);
+                if send_scheduling_messages {
+                    self.itip_send(account_id, Some(&event), None).await;
+                }
+            } else {
+                response.not_destroyed.append(id, SetError::not_found());
+            }
+        }
+
+        // Write changes
+        if !changes.is_empty() {
+            let change_id = self.commit_changes(account_id, changes).await?;
+            response.state_change = StateChange::new(account_id)
+                .with_change(DataType::CalendarEvent, change_id)
+                .into();
+            response.new_state = Some(change_id.into());
+        }
+
+        Ok(response)
+    }
+
+    pub async fn calendar_event_delete(
+        &self,
+        account_id: u32,
+        document_id: u32,
+    ) -> Result<Option<Object<Value>>, MethodError> {
+        if let Some(current) = self
+            .get_property::<HashedValue<Object<Value>>>(
+                account_id,
+                Collection::CalendarEvent,
+                document_id,
+                Property::Value,
+            )
+            .await?
+        {
+            let event = current.inner.clone();
+            let mut batch = BatchBuilder::new();
+            batch
+                .with_account_id(account_id)
+                .with_collection(Collection::CalendarEvent)
+                .delete_document(document_id)
+                .custom(EventIndex::delete(current));
+            self.write_batch(batch).await?;
+            Ok(Some(event))
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn calendar_event_set_item(
+        &self,
+        changes: Object<SetValue>,
+        current: Option<&Object<Value>>,
+        calendar_ids: &RoaringBitmap,
+        response: &SetResponse,
+    ) -> Result<Object<Value>, SetError> {
+        let mut event = current
+            .cloned()
+            .unwrap_or_else(|| Object::with_capacity(changes.properties.len()));
+
+        for (property, value) in changes.properties {
+            match (property, response.eval_object_references(value)?) {
+                (Property::CalendarIds, MaybePatchValue::Value(Value::List(ids))) => {
+                    let mut event_calendar_ids = Vec::with_capacity(ids.len());
+                    for id in ids {
+                        let id = id.try_unwrap_id().map(|id| id.document_id());
+                        if let Some(id) = id.filter(|id| calendar_ids.contains(*id)) {
+                            event_calendar_ids.push(id);
+                        } else {
+                            return Err(SetError::invalid_properties()
+                                .with_property(Property::CalendarIds)
+                                .with_description("Calendar does not exist."));
+                        }
+                    }
+                    set_calendar_ids(&mut event, event_calendar_ids);
+                }
+                (Property::CalendarIds, MaybePatchValue::Patch(patch)) => {
+                    let mut event_calendar_ids = event.calendar_ids();
+                    let mut patch = patch.into_iter();
+                    if let Some(id) = patch.next().unwrap().try_unwrap_id() {
+                        let id = id.document_id();
+                        if patch.next().unwrap().try_unwrap_bool().unwrap_or_default() {
+                            if !calendar_ids.contains(id) {
+                                return Err(SetError::invalid_properties()
+                                    .with_property(Property::CalendarIds)
+                                    .with_description("Calendar does not exist."));
+                            } else if !event_calendar_ids.contains(&id) {
+                                event_calendar_ids.push(id);
+                            }
+                        } else {
+                            event_calendar_ids.retain(|calendar_id| *calendar_id != id);
+                        }
+                    }
+                    set_calendar_ids(&mut event, event_calendar_ids);
+                }
+                (Property::_T(pointer), MaybePatchValue::Value(value)) if pointer.contains('/') => {
+                    if current.is_none() || !patch_object(&mut event, &pointer, value) {
+                        return Err(SetError::new(SetErrorType::InvalidPatch)
+                            .with_property(Property::_T(pointer))
+                            .with_description("Invalid patch."));
+                    }
+                }
+                (Property::Id | Property::DavName, _) => {
+                    return Err(SetError::invalid_properties()
+                        .with_property(Property::Id)
+                        .with_description("Property cannot be set."));
+                }
+                (property, MaybePatchValue::Value(Value::Null)) => {
+                    event.remove_field(&property.to_string());
+                }
+                (property, MaybePatchValue::Value(value)) => {
+                    let name = property.to_string();
+                    if let Some(current) = event.field_mut(&name) {
+                        *current = value;
+                    } else {
+                        event.append(property, value);
+                    }
+                }
+                (property, _) => {
+                    return Err(SetError::invalid_properties()
+                        .with_property(property)
+                        .with_description("Invalid property or value."));
+                }
+            }
+        }
+
+        // Validate event
+        match event.text_field("@type") {
+            Some(EVENT_TYPE) => (),
+            None if current.is_none() => {
+                event.set_field("@type", Value::Text(EVENT_TYPE.to_string()));
+            }
+            _ => {
+                return Err(SetError::invalid_properties()
+                    .with_property(Property::_T("@type".to_string()))
+                    .with_description("Object type must be \"Event\"."));
+            }
+        }
+        match (
+            event.text_field("uid"),
+            current.and_then(|current| current.text_field("uid")),
+        ) {
+            (Some(uid), Some(current_uid)) if uid != current_uid => {
+                return Err(SetError::invalid_properties()
+                    .with_property(Property::Uid)
+                    .with_description("The uid of an event cannot be changed."));
+            }
+            (None, Some(_)) => {
+                return Err(SetError::invalid_properties()
+                    .with_property(Property::Uid)
+                    .with_description("The uid of an event cannot be removed."));
+            }
+            (None, None) => {
+                event.set_field("uid", Value::Text(generate_uid()));
+            }
+            _ => (),
+        }
+        if event
+            .text_field("start")
+            .and_then(parse_local_datetime)
+            .is_none()
+        {
+            return Err(SetError::invalid_properties()
+                .with_property(Property::Start)
+                .with_description("An event must have a valid start date."));
+        }
+        if event.calendar_ids().is_empty() {
+            return Err(SetError::invalid_properties()
+                .with_property(Property::CalendarIds)
+                .with_description("An event must belong to at least one calendar."));
+        }
+        if (&event).serialize().len() > self.config.load().calendar_max_size {
+            return Err(
+                SetError::too_large().with_description("Event exceeds the maximum allowed size.")
+            );
+        }
+
+        Ok(event)
+    }
+}
+
+pub fn set_calendar_ids(event: &mut Object<Value>, calendar_ids: Vec<u32>) {
+    event.set(
+        Property::CalendarIds,
+        Value::List(
+            calendar_ids
+                .into_iter()
+                .map(|id| Value::UnsignedInt(id as u64))
+                .collect(),
+        ),
+    );
+}
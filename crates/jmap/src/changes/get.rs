@@ -67,12 +67,32 @@ impl JMAP {
 
                 return Err(MethodError::CannotCalculateChanges);
             }
+            RequestArguments::AddressBook => {
+                access_token.assert_is_member(request.account_id)?;
+
+                Collection::AddressBook
+            }
+            RequestArguments::ContactCard => {
+                access_token.assert_is_member(request.account_id)?;
+
+                Collection::ContactCard
+            }
+            RequestArguments::Calendar => {
+                access_token.assert_is_member(request.account_id)?;
+
+                Collection::Calendar
+            }
+            RequestArguments::CalendarEvent => {
+                access_token.assert_is_member(request.account_id)?;
+
+                Collection::CalendarEvent
+            }
         };
 
-        let max_changes = if self.config.changes_max_results > 0
-            && self.config.changes_max_results < request.max_changes.unwrap_or(0)
+        let max_changes = if self.config.load().changes_max_results > 0
+            && self.config.load().changes_max_results < request.max_changes.unwrap_or(0)
         {
-            self.config.changes_max_results
+            self.config.load().changes_max_results
         } else {
             request.max_changes.unwrap_or(0)
         };
@@ -0,0 +1,131 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use jmap_proto::{
+    error::method::MethodError,
+    method::get::{GetRequest, GetResponse, RequestArguments},
+    object::Object,
+    types::{collection::Collection, id::Id, property::Property, value::Value},
+};
+
+use crate::JMAP;
+
+use super::JSContactObject;
+
+impl JMAP {
+    pub async fn contact_card_get(
+        &self,
+        mut request: GetRequest<RequestArguments>,
+    ) -> Result<GetResponse, MethodError> {
+        let ids = request.unwrap_ids(self.config.load().get_max_objects)?;
+        let properties = request.unwrap_properties(&[]);
+        let account_id = request.account_id.document_id();
+        self.address_book_get_or_create(account_id).await?;
+        let card_ids = self
+            .get_document_ids(account_id, Collection::ContactCard)
+            .await?
+            .unwrap_or_default();
+        let ids = if let Some(ids) = ids {
+            ids
+        } else {
+            card_ids
+                .iter()
+                .take(self.config.load().get_max_objects)
+                .map(Into::into)
+                .collect::<Vec<_>>()
+        };
+        let mut response = GetResponse {
+            account_id: request.account_id.into(),
+            state: self
+                .get_state(account_id, Collection::ContactCard)
+                .await?
+                .into(),
+            list: Vec::with_capacity(ids.len()),
+            not_found: vec![],
+        };
+
+        for id in ids {
+            // Obtain the card object
+            let document_id = id.document_id();
+            if !card_ids.contains(document_id) {
+                response.not_found.push(id.into());
+                continue;
+            }
+            let card = if let Some(card) = self
+                .get_property::<Object<Value>>(
+                    account_id,
+                    Collection::ContactCard,
+                    document_id,
+                    Property::Value,
+                )
+                .await?
+            {
+                card
+            } else {
+                response.not_found.push(id.into());
+                continue;
+            };
+
+            response.list.push(card_to_jmap(id, card, &properties));
+        }
+
+        Ok(response)
+    }
+}
+
+pub fn card_to_jmap(id: Id, card: Object<Value>, properties: &[Property]) -> Object<Value> {
+    let address_book_ids = card.address_book_ids();
+    let mut result = Object::with_capacity(card.properties.len());
+    result.append(Property::Id, Value::Id(id));
+
+    for (property, value) in card.properties {
+        match property {
+            Property::Id | Property::DavName => continue,
+            Property::AddressBookIds => {
+                let mut ids = Object::with_capacity(address_book_ids.len());
+                for address_book_id in &address_book_ids {
+                    ids.append(
+                        Property::_T(Id::from(*address_book_id).to_string()),
+                        Value::Bool(true),
+                    );
+                }
+                result.append(Property::AddressBookIds, ids);
+            }
+            property => {
+                if properties.is_empty()
+                    || properties
+                        .iter()
+                        .any(|p| p.to_string() == property.to_string())
+                {
+                    result.append(property, value);
+                }
+            }
+        }
+    }
+
+    if !properties.is_empty() && !properties.contains(&Property::AddressBookIds) {
+        result.remove(&Property::AddressBookIds);
+    }
+
+    result
+}
@@ -0,0 +1,214 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use jmap_proto::{
+    object::Object,
+    types::{property::Property, value::Value},
+};
+use store::{
+    ahash::AHashSet,
+    write::{
+        assert::HashedValue, BatchBuilder, BitmapClass, BitmapHash, IntoOperations, Operation,
+        TokenizeText,
+    },
+    Serialize,
+};
+
+use super::JSContactObject;
+
+const MAX_SORT_LEN: usize = 255;
+
+pub struct ContactIndex {
+    current: Option<HashedValue<Object<Value>>>,
+    changes: Option<Object<Value>>,
+}
+
+#[derive(Default)]
+struct ContactTerms {
+    indexes: AHashSet<(u8, Vec<u8>)>,
+    tokens: AHashSet<(u8, String)>,
+}
+
+impl ContactIndex {
+    pub fn insert(card: Object<Value>) -> Self {
+        ContactIndex {
+            current: None,
+            changes: Some(card),
+        }
+    }
+
+    pub fn update(current: HashedValue<Object<Value>>, card: Object<Value>) -> Self {
+        ContactIndex {
+            current: Some(current),
+            changes: Some(card),
+        }
+    }
+
+    pub fn delete(current: HashedValue<Object<Value>>) -> Self {
+        ContactIndex {
+            current: Some(current),
+            changes: None,
+        }
+    }
+}
+
+impl IntoOperations for ContactIndex {
+    fn build(self, batch: &mut BatchBuilder) {
+        let mut add = self
+            .changes
+            .as_ref()
+            .map(ContactTerms::new)
+            .unwrap_or_default();
+        let mut remove = ContactTerms::default();
+
+        if let Some(current) = &self.current {
+            batch.assert_value(Property::Value, current);
+            remove = ContactTerms::new(&current.inner);
+
+            // Only write the terms that changed
+            add.indexes.retain(|term| !remove.indexes.remove(term));
+            add.tokens.retain(|term| !remove.tokens.remove(term));
+        }
+
+        for (terms, set) in [(add, true), (remove, false)] {
+            for (field, key) in terms.indexes {
+                batch.ops.push(Operation::Index { field, key, set });
+            }
+            for (field, token) in terms.tokens {
+                batch.ops.push(Operation::Bitmap {
+                    class: BitmapClass::Text {
+                        field,
+                        token: BitmapHash::new(token),
+                    },
+                    set,
+                });
+            }
+        }
+
+        if let Some(changes) = self.changes {
+            batch.set(Property::Value, changes.serialize());
+        } else {
+            batch.clear(Property::Value);
+        }
+    }
+}
+
+impl ContactTerms {
+    fn new(card: &Object<Value>) -> Self {
+        let mut terms = ContactTerms::default();
+
+        for address_book_id in card.address_book_ids() {
+            terms.index(Property::AddressBookIds, address_book_id.serialize());
+        }
+        if let Some(uid) = card.text_field("uid") {
+            terms.index(Property::Uid, uid.serialize());
+        }
+        if let Some(dav_name) = card.get(&Property::DavName).as_string() {
+            terms.index(Property::DavName, dav_name.serialize());
+        }
+        terms.index(
+            Property::Kind,
+            card.text_field("kind")
+                .unwrap_or("individual")
+                .to_lowercase()
+                .serialize(),
+        );
+
+        // Index the display name for sorting and its words for searching
+        let mut sort_name = String::new();
+        if let Some(name) = card.field("name").and_then(|name| name.as_obj()) {
+            if let Some(full) = name.text_field("full") {
+                terms.tokenize(Property::Name, full);
+                sort_name = full.to_string();
+            }
+            for component in name
+                .field("components")
+                .and_then(|c| c.as_list())
+                .into_iter()
+                .flatten()
+            {
+                if let Some(value) = component
+                    .as_obj()
+                    .and_then(|component| component.text_field("value"))
+                {
+                    terms.tokenize(Property::Name, value);
+                    if name.text_field("full").is_none() {
+                        if !sort_name.is_empty() {
+                            sort_name.push(' ');
+                        }
+                        sort_name.push_str(value);
+                    }
+                }
+            }
+        }
+        for (map, field) in [("nicknames", "name"), ("organizations", "name")] {
+            for entry in map_entries(card, map) {
+                if let Some(value) = entry.text_field(field) {
+                    terms.tokenize(Property::Name, value);
+                }
+            }
+        }
+        for entry in map_entries(card, "emails") {
+            if let Some(address) = entry.text_field("address") {
+                terms.tokenize(Property::Email, address);
+                if sort_name.is_empty() {
+                    sort_name = address.to_string();
+                }
+            }
+        }
+
+        let mut sort_name = sort_name.to_lowercase();
+        if sort_name.len() > MAX_SORT_LEN {
+            let mut pos = MAX_SORT_LEN;
+            while !sort_name.is_char_boundary(pos) {
+                pos -= 1;
+            }
+            sort_name.truncate(pos);
+        }
+        terms.index(Property::Name, sort_name.serialize());
+
+        terms
+    }
+
+    fn index(&mut self, property: Property, key: Vec<u8>) {
+        self.indexes.insert((property.into(), key));
+    }
+
+    fn tokenize(&mut self, property: Property, text: &str) {
+        let field: u8 = property.into();
+        for token in text.to_tokens() {
+            self.tokens.insert((field, token));
+        }
+    }
+}
+
+pub fn map_entries<'x>(
+    card: &'x Object<Value>,
+    name: &str,
+) -> impl Iterator<Item = &'x Object<Value>> {
+    card.field(name)
+        .and_then(|map| map.as_obj())
+        .into_iter()
+        .flat_map(|map| map.properties.values())
+        .filter_map(|entry| entry.as_obj())
+}
@@ -0,0 +1,153 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use jmap_proto::{
+    object::Object,
+    types::{property::Property, value::Value},
+};
+use store::rand::{thread_rng, Rng};
+
+pub mod get;
+pub mod index;
+pub mod query;
+pub mod set;
+pub mod vcard;
+
+pub const CARD_TYPE: &str = "Card";
+pub const CARD_VERSION: &str = "1.0";
+
+// JSContact objects are stored verbatim, so their members are looked up by name
+// rather than by property. Internal properties are never exposed to clients.
+pub trait JSContactObject {
+    fn field(&self, name: &str) -> Option<&Value>;
+    fn field_mut(&mut self, name: &str) -> Option<&mut Value>;
+    fn set_field(&mut self, name: &str, value: Value);
+    fn remove_field(&mut self, name: &str) -> Option<Value>;
+    fn text_field(&self, name: &str) -> Option<&str>;
+    fn address_book_ids(&self) -> Vec<u32>;
+}
+
+impl JSContactObject for Object<Value> {
+    fn field(&self, name: &str) -> Option<&Value> {
+        self.properties
+            .iter()
+            .find(|(property, _)| is_field(property, name))
+            .map(|(_, value)| value)
+    }
+
+    fn field_mut(&mut self, name: &str) -> Option<&mut Value> {
+        self.properties
+            .iter_mut()
+            .find(|(property, _)| is_field(property, name))
+            .map(|(_, value)| value)
+    }
+
+    fn set_field(&mut self, name: &str, value: Value) {
+        if let Some(current) = self.field_mut(name) {
+            *current = value;
+        } else {
+            self.properties.append(Property::parse(name), value);
+        }
+    }
+
+    fn remove_field(&mut self, name: &str) -> Option<Value> {
+        let pos = self
+            .properties
+            .keys()
+            .position(|property| is_field(property, name))?;
+        Some(self.properties.swap_remove(pos))
+    }
+
+    fn text_field(&self, name: &str) -> Option<&str> {
+        self.field(name).and_then(|value| value.as_string())
+    }
+
+    fn address_book_ids(&self) -> Vec<u32> {
+        self.properties
+            .get(&Property::AddressBookIds)
+            .and_then(|ids| ids.as_list())
+            .map(|ids| {
+                ids.iter()
+                    .filter_map(|id| id.try_cast_uint().map(|id| id as u32))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+}
+
+pub trait ObjectBuilder {
+    fn with_field(self, name: &str, value: impl Into<Value>) -> Self;
+    fn with_fields(self, fields: Object<Value>) -> Self;
+}
+
+impl ObjectBuilder for Object<Value> {
+    fn with_field(mut self, name: &str, value: impl Into<Value>) -> Self {
+        self.set_field(name, value.into());
+        self
+    }
+
+    fn with_fields(mut self, fields: Object<Value>) -> Self {
+        for (property, value) in fields.properties {
+            self.properties.append(property, value);
+        }
+        self
+    }
+}
+
+pub fn typed_object(object_type: &str) -> Object<Value> {
+    Object::with_capacity(3).with_field("@type", object_type)
+}
+
+pub fn flags(value: Option<&Value>) -> impl Iterator<Item = (&str, &Value)> {
+    value
+        .and_then(|value| value.as_obj())
+        .into_iter()
+        .flat_map(|value| value.properties.iter())
+        .filter_map(|(key, value)| match key {
+            Property::_T(key) => Some((key.as_str(), value)),
+            _ => None,
+        })
+}
+
+fn is_field(property: &Property, name: &str) -> bool {
+    match property {
+        Property::_T(field) => field == name,
+        Property::Id | Property::AddressBookIds | Property::DavName => false,
+        property => property.to_string() == name,
+    }
+}
+
+pub fn generate_uid() -> String {
+    let mut bytes: [u8; 16] = thread_rng().gen();
+    bytes[6] = (bytes[6] & 0x0f) | 0x40;
+    bytes[8] = (bytes[8] & 0x3f) | 0x80;
+    let mut uid = String::with_capacity(45);
+    uid.push_str("urn:uuid:");
+    for (pos, byte) in bytes.iter().enumerate() {
+        if matches!(pos, 4 | 6 | 8 | 10) {
+            uid.push('-');
+        }
+        uid.push_str(&format!("{byte:02x}"));
+    }
+    uid
+}
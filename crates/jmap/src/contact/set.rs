@@ -0,0 +1,388 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use jmap_proto::{
+    error::{
+        method::MethodError,
+        set::{SetError, SetErrorType},
+    },
+    method::set::{RequestArguments, SetRequest, SetResponse},
+    object::Object,
+    response::references::EvalObjectReferences,
+    types::{
+        collection::Collection,
+        id::Id,
+        property::Property,
+        state::StateChange,
+        type_state::DataType,
+        value::{MaybePatchValue, SetValue, Value},
+    },
+};
+use store::{
+    roaring::RoaringBitmap,
+    write::{assert::HashedValue, log::ChangeLogBuilder, BatchBuilder},
+    Serialize,
+};
+
+use crate::JMAP;
+
+use super::{generate_uid, index::ContactIndex, JSContactObject, CARD_TYPE, CARD_VERSION};
+
+impl JMAP {
+    pub async fn contact_card_set(
+        &self,
+        mut request: SetRequest<RequestArguments>,
+    ) -> Result<SetResponse, MethodError> {
+        let account_id = request.account_id.document_id();
+        let address_book_ids = self.address_book_get_or_create(account_id).await?;
+        let card_ids = self
+            .get_document_ids(account_id, Collection::ContactCard)
+            .await?
+            .unwrap_or_default();
+        let mut response = self
+            .prepare_set_response(&request, Collection::ContactCard)
+            .await?;
+        let will_destroy = request.unwrap_destroy();
+
+        // Process creates
+        let mut changes = ChangeLogBuilder::new();
+        for (id, object) in request.unwrap_create() {
+            match self.contact_card_set_item(object, None, &address_book_ids, &response) {
+                Ok(mut card) => {
+                    let document_id = self
+                        .assign_document_id(account_id, Collection::ContactCard)
+                        .await?;
+                    card.set(
+                        Property::DavName,
+                        Value::Text(format!("{}.vcf", Id::from(document_id))),
+                    );
+                    let uid = card.text_field("uid").unwrap_or_default().to_string();
+                    let mut batch = BatchBuilder::new();
+                    batch
+                        .with_account_id(account_id)
+                        .with_collection(Collection::ContactCard)
+                        .create_document(document_id)
+                        .custom(ContactIndex::insert(card));
+                    self.write_batch(batch).await?;
+                    changes.log_insert(Collection::ContactCard, document_id);
+                    response.created.insert(
+                        id,
+                        Object::with_capacity(2)
+                            .with_property(Property::Id, Value::Id(document_id.into()))
+                            .with_property(Property::Uid, uid),
+                    );
+                }
+                Err(err) => {
+                    response.not_created.append(id, err);
+                }
+            }
+        }
+
+        // Process updates
+        'update: for (id, object) in request.unwrap_update() {
+            // Make sure id won't be destroyed
+            if will_destroy.contains(&id) {
+                response.not_updated.append(id, SetError::will_destroy());
+                continue 'update;
+            }
+
+            // Obtain card
+            let document_id = id.document_id();
+            let current = if let Some(current) = self
+                .get_property::<HashedValue<Object<Value>>>(
+                    account_id,
+                    Collection::ContactCard,
+                    document_id,
+                    Property::Value,
+                )
+                .await?
+            {
+                current
+            } else {
+                response.not_updated.append(id, SetError::not_found());
+                continue 'update;
+            };
+
+            match self.contact_card_set_item(
+                object,
+                Some(&current.inner),
+                &address_book_ids,
+                &response,
+            ) {
+                Ok(card) => {
+                    let mut batch = BatchBuilder::new();
+                    batch
+                        .with_account_id(account_id)
+                        .with_collection(Collection::ContactCard)
+                        .update_document(document_id)
+                        .custom(ContactIndex::update(current, card));
+                    match self.store.write(batch.build()).await {
+                        Ok(_) => {
+                            changes.log_update(Collection::ContactCard, document_id);
+                            response.updated.append(id, None);
+                        }
+                        Err(store::Error::AssertValueFailed) => {
+                            response.not_updated.append(
+                                id,
+                                SetError::forbidden().with_description(
+                                    "Another process modified this card, please try again.",
+                                ),
+                            );
+                        }
+                        Err(err) => {
+                            tracing::error!(
+                                event = "error",
+                                context = "contact_card_set",
+                                account_id = account_id,
+                                error = ?err,
+                                "Failed to update card(s).");
+                            return Err(MethodError::ServerPartialFail);
+                        }
+                    }
+                }
+                Err(err) => {
+                    response.not_updated.append(id, err);
+                }
+            }
+        }
+
+        // Process deletions
+        for id in will_destroy {
+            let document_id = id.document_id();
+            if !card_ids.contains(document_id) {
+                response.not_destroyed.append(id, SetError::not_found());
+                continue;
+            }
+
+            if self.contact_card_delete(account_id, document_id).await? {
+                changes.log_delete(Collection::ContactCard, document_id);
+                response.destroyed.push(id);
+            } else {
+                response.not_destroyed.append(id, SetError::not_found());
+            }
+        }
+
+        // Write changes
+        if !changes.is_empty() {
+            let change_id = self.commit_changes(account_id, changes).await?;
+            response.state_change = StateChange::new(account_id)
+                .with_change(DataType::ContactCard, change_id)
+                .into();
+            response.new_state = Some(change_id.into());
+        }
+
+        Ok(response)
+    }
+
+    pub async fn contact_card_delete(
+        &self,
+        account_id: u32,
+        document_id: u32,
+    ) -> Result<bool, MethodError> {
+        if let Some(current) = self
+            .get_property::<HashedValue<Object<Value>>>(
+                account_id,
+                Collection::ContactCard,
+                document_id,
+                Property::Value,
+            )
+            .await?
+        {
+            let mut batch = BatchBuilder::new();
+            batch
+                .with_account_id(account_id)
+                .with_collection(Collection::ContactCard)
+                .delete_document(document_id)
+                .custom(ContactIndex::delete(current));
+            self.write_batch(batch).await?;
+            Ok(true)
+        } else {
+            Ok(false)
+        }
+    }
+
+    fn contact_card_set_item(
+        &self,
+        changes: Object<SetValue>,
+        current: Option<&Object<Value>>,
+        address_book_ids: &RoaringBitmap,
+        response: &SetResponse,
+    ) -> Result<Object<Value>, SetError> {
+        let mut card = current
+            .cloned()
+            .unwrap_or_else(|| Object::with_capacity(changes.properties.len()));
+
+        for (property, value) in changes.properties {
+            match (property, response.eval_object_references(value)?) {
+                (Property::AddressBookIds, MaybePatchValue::Value(Value::List(ids))) => {
+                    let mut book_ids = Vec::with_capacity(ids.len());
+                    for id in ids {
+                        let id = id.try_unwrap_id().map(|id| id.document_id());
+                        if let Some(id) = id.filter(|id| address_book_ids.contains(*id)) {
+                            book_ids.push(id);
+                        } else {
+                            return Err(SetError::invalid_properties()
+                                .with_property(Property::AddressBookIds)
+                                .with_description("Address book does not exist."));
+                        }
+                    }
+                    set_address_book_ids(&mut card, book_ids);
+                }
+                (Property::AddressBookIds, MaybePatchValue::Patch(patch)) => {
+                    let mut book_ids = card.address_book_ids();
+                    let mut patch = patch.into_iter();
+                    if let Some(id) = patch.next().unwrap().try_unwrap_id() {
+                        let id = id.document_id();
+                        if patch.next().unwrap().try_unwrap_bool().unwrap_or_default() {
+                            if !address_book_ids.contains(id) {
+                                return Err(SetError::invalid_properties()
+                                    .with_property(Property::AddressBookIds)
+                                    .with_description("Address book does not exist."));
+                            } else if !book_ids.contains(&id) {
+                                book_ids.push(id);
+                            }
+                        } else {
+                            book_ids.retain(|book_id| *book_id != id);
+                        }
+                    }
+                    set_address_book_ids(&mut card, book_ids);
+                }
+                (Property::_T(pointer), MaybePatchValue::Value(value)) if pointer.contains('/') => {
+                    if current.is_none() || !patch_object(&mut card, &pointer, value) {
+                        return Err(SetError::new(SetErrorType::InvalidPatch)
+                            .with_property(Property::_T(pointer))
+                            .with_description("Invalid patch."));
+                    }
+                }
+                (Property::Id | Property::DavName, _) => {
+                    return Err(SetError::invalid_properties()
+                        .with_property(Property::Id)
+                        .with_description("Property cannot be set."));
+                }
+                (property, MaybePatchValue::Value(Value::Null)) => {
+                    card.remove_field(&property.to_string());
+                }
+                (property, MaybePatchValue::Value(value)) => {
+                    let name = property.to_string();
+                    if let Some(current) = card.field_mut(&name) {
+                        *current = value;
+                    } else {
+                        card.append(property, value);
+                    }
+                }
+                (property, _) => {
+                    return Err(SetError::invalid_properties()
+                        .with_property(property)
+                        .with_description("Invalid property or value."));
+                }
+            }
+        }
+
+        // Validate card
+        match card.text_field("@type") {
+            Some(CARD_TYPE) => (),
+            None if current.is_none() => {
+                card.set_field("@type", Value::Text(CARD_TYPE.to_string()));
+            }
+            _ => {
+                return Err(SetError::invalid_properties()
+                    .with_property(Property::_T("@type".to_string()))
+                    .with_description("Object type must be \"Card\"."));
+            }
+        }
+        if card.field("version").is_none() {
+            card.set_field("version", Value::Text(CARD_VERSION.to_string()));
+        }
+        match (
+            card.text_field("uid"),
+            current.and_then(|current| current.text_field("uid")),
+        ) {
+            (Some(uid), Some(current_uid)) if uid != current_uid => {
+                return Err(SetError::invalid_properties()
+                    .with_property(Property::Uid)
+                    .with_description("The uid of a card cannot be changed."));
+            }
+            (None, Some(_)) => {
+                return Err(SetError::invalid_properties()
+                    .with_property(Property::Uid)
+                    .with_description("The uid of a card cannot be removed."));
+            }
+            (None, None) => {
+                card.set_field("uid", Value::Text(generate_uid()));
+            }
+            _ => (),
+        }
+        if card.address_book_ids().is_empty() {
+            return Err(SetError::invalid_properties()
+                .with_property(Property::AddressBookIds)
+                .with_description("A card must belong to at least one address book."));
+        }
+        if (&card).serialize().len() > self.config.load().contact_max_size {
+            return Err(
+                SetError::too_large().with_description("Card exceeds the maximum allowed size.")
+            );
+        }
+
+        Ok(card)
+    }
+}
+
+pub fn set_address_book_ids(card: &mut Object<Value>, address_book_ids: Vec<u32>) {
+    card.set(
+        Property::AddressBookIds,
+        Value::List(
+            address_book_ids
+                .into_iter()
+                .map(|id| Value::UnsignedInt(id as u64))
+                .collect(),
+        ),
+    );
+}
+
+// Applies a JSON pointer patch (RFC 8620, Section 5.3) to a JSContact or JSCalendar object
+pub fn patch_object(mut object: &mut Object<Value>, pointer: &str, value: Value) -> bool {
+    let path = pointer
+        .split('/')
+        .map(|part| part.replace("~1", "/").replace("~0", "~"))
+        .collect::<Vec<_>>();
+    let (field, parents) = if let Some(path) = path.split_last() {
+        path
+    } else {
+        return false;
+    };
+
+    for part in parents {
+        object = match object.field_mut(part) {
+            Some(Value::Object(object)) => object,
+            _ => return false,
+        };
+    }
+
+    if matches!(value, Value::Null) {
+        object.remove_field(field);
+    } else {
+        object.set_field(field, value);
+    }
+
+    true
+}
@@ -0,0 +1,643 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use jmap_proto::{
+    object::Object,
+    types::{property::Property, value::Value},
+};
+
+use crate::dav::content_line::{
+    escape, fold_line, join_structured, parse_line, split_list, split_structured, unescape, unfold,
+    ContentLine,
+};
+
+use super::{
+    flags, index::map_entries, typed_object, JSContactObject, ObjectBuilder, CARD_TYPE,
+    CARD_VERSION,
+};
+
+// vCard properties that are rebuilt from the JSContact card on output
+const SKIP_PROPERTIES: [&str; 5] = ["BEGIN", "END", "VERSION", "PRODID", "REV"];
+
+const N_KINDS: [&str; 5] = ["surname", "given", "given2", "title", "credential"];
+const ADR_KINDS: [&str; 7] = [
+    "postOfficeBox",
+    "apartment",
+    "name",
+    "locality",
+    "region",
+    "postcode",
+    "country",
+];
+
+// Converts a vCard (versions 3.0 and 4.0) to a JSContact card (RFC 9553).
+pub fn parse_vcard(data: &str) -> Option<Object<Value>> {
+    let mut lines = Vec::new();
+    let mut in_card = false;
+
+    for line in unfold(data) {
+        let line = if let Some(line) = parse_line(&line) {
+            line
+        } else {
+            continue;
+        };
+        match line.name.as_str() {
+            "BEGIN" if line.value.eq_ignore_ascii_case("VCARD") => {
+                in_card = true;
+            }
+            "END" if in_card && line.value.eq_ignore_ascii_case("VCARD") => {
+                break;
+            }
+            _ if in_card => {
+                lines.push(line);
+            }
+            _ => (),
+        }
+    }
+
+    if !in_card {
+        return None;
+    }
+
+    let mut card = Object::with_capacity(lines.len() + 2);
+    card.set_field("@type", Value::Text(CARD_TYPE.to_string()));
+    card.set_field("version", Value::Text(CARD_VERSION.to_string()));
+
+    let mut name = Object::with_capacity(3);
+    let mut entries: Vec<(&str, &str, Value)> = Vec::new();
+    let mut keywords = Object::with_capacity(0);
+    let mut members = Object::with_capacity(0);
+    let mut vcard_props = Vec::new();
+
+    for line in lines {
+        match line.name.as_str() {
+            "UID" => {
+                card.set_field("uid", Value::Text(line.value));
+            }
+            "KIND" | "X-ADDRESSBOOKSERVER-KIND" => {
+                card.set_field("kind", Value::Text(line.value.to_lowercase()));
+            }
+            "FN" => {
+                name.set_field("full", Value::Text(unescape(&line.value)));
+            }
+            "N" => {
+                let mut components = Vec::new();
+                for (kind, values) in N_KINDS.iter().zip(split_structured(&line.value)) {
+                    for value in values {
+                        components.push(
+                            Object::with_capacity(2)
+                                .with_field("kind", *kind)
+                                .with_field("value", value)
+                                .into(),
+                        );
+                    }
+                }
+                if !components.is_empty() {
+                    name.set_field("components", Value::List(components));
+                }
+            }
+            "NICKNAME" => {
+                for nickname in split_list(&line.value) {
+                    entries.push((
+                        "nicknames",
+                        "k",
+                        typed_object("Nickname").with_field("name", nickname).into(),
+                    ));
+                }
+            }
+            "EMAIL" => {
+                let mut email =
+                    typed_object("EmailAddress").with_field("address", unescape(&line.value));
+                set_contexts(&mut email, &line, &[]);
+                entries.push(("emails", "e", email.into()));
+            }
+            "TEL" => {
+                let mut phone = typed_object("Phone").with_field(
+                    "number",
+                    unescape(line.value.strip_prefix("tel:").unwrap_or(&line.value)),
+                );
+                set_contexts(
+                    &mut phone,
+                    &line,
+                    &[
+                        "voice",
+                        "fax",
+                        "cell",
+                        "text",
+                        "video",
+                        "pager",
+                        "textphone",
+                    ],
+                );
+                entries.push(("phones", "p", phone.into()));
+            }
+            "ADR" => {
+                let mut components = Vec::new();
+                for (kind, values) in ADR_KINDS.iter().zip(split_structured(&line.value)) {
+                    for value in values {
+                        components.push(
+                            Object::with_capacity(2)
+                                .with_field("kind", *kind)
+                                .with_field("value", value)
+                                .into(),
+                        );
+                    }
+                }
+                let mut address =
+                    typed_object("Address").with_field("components", Value::List(components));
+                set_contexts(&mut address, &line, &[]);
+                entries.push(("addresses", "a", address.into()));
+            }
+            "ORG" => {
+                let mut values = split_structured(&line.value)
+                    .into_iter()
+                    .flatten()
+                    .collect::<Vec<_>>();
+                if !values.is_empty() {
+                    let mut org = typed_object("Organization").with_field("name", values.remove(0));
+                    if !values.is_empty() {
+                        org.set_field(
+                            "units",
+                            Value::List(
+                                values
+                                    .into_iter()
+                                    .map(|unit| {
+                                        typed_object("OrgUnit").with_field("name", unit).into()
+                                    })
+                                    .collect(),
+                            ),
+                        );
+                    }
+                    entries.push(("organizations", "o", org.into()));
+                }
+            }
+            "TITLE" | "ROLE" => {
+                entries.push((
+                    "titles",
+                    "t",
+                    typed_object("Title")
+                        .with_field("name", unescape(&line.value))
+                        .with_field(
+                            "kind",
+                            if line.name == "TITLE" {
+                                "title"
+                            } else {
+                                "role"
+                            },
+                        )
+                        .into(),
+                ));
+            }
+            "NOTE" => {
+                entries.push((
+                    "notes",
+                    "n",
+                    typed_object("Note")
+                        .with_field("note", unescape(&line.value))
+                        .into(),
+                ));
+            }
+            "BDAY" | "ANNIVERSARY" | "X-ANNIVERSARY" => {
+                if let Some(date) = parse_date(&line.value) {
+                    entries.push((
+                        "anniversaries",
+                        "k",
+                        typed_object("Anniversary")
+                            .with_field(
+                                "kind",
+                                if line.name == "BDAY" {
+                                    "birth"
+                                } else {
+                                    "wedding"
+                                },
+                            )
+                            .with_field("date", date)
+                            .into(),
+                    ));
+                }
+            }
+            "URL" => {
+                entries.push((
+                    "links",
+                    "l",
+                    typed_object("Link")
+                        .with_field("uri", unescape(&line.value))
+                        .into(),
+                ));
+            }
+            "CATEGORIES" => {
+                for keyword in split_list(&line.value) {
+                    keywords.set_field(&keyword, Value::Bool(true));
+                }
+            }
+            "PHOTO" => {
+                let uri = if line.value.contains(':') {
+                    line.value.clone()
+                } else {
+                    // vCard 3.0 inline photo
+                    let media_type = line
+                        .param("TYPE")
+                        .map(|t| format!("image/{}", t.to_lowercase()))
+                        .unwrap_or_else(|| "image/jpeg".to_string());
+                    format!("data:{media_type};base64,{}", line.value)
+                };
+                entries.push((
+                    "media",
+                    "m",
+                    typed_object("Media")
+                        .with_field("kind", "photo")
+                        .with_field("uri", uri)
+                        .into(),
+                ));
+            }
+            "MEMBER" | "X-ADDRESSBOOKSERVER-MEMBER" => {
+                members.set_field(&line.value, Value::Bool(true));
+            }
+            name if !SKIP_PROPERTIES.contains(&name) => {
+                let mut params = Object::with_capacity(line.params.len());
+                for (key, values) in &line.params {
+                    params.append(
+                        Property::_T(key.to_lowercase()),
+                        Value::Text(values.join(",")),
+                    );
+                }
+                vcard_props.push(Value::List(vec![
+                    Value::Text(name.to_lowercase()),
+                    params.into(),
+                    Value::Text("unknown".to_string()),
+                    Value::Text(line.value),
+                ]));
+            }
+            _ => (),
+        }
+    }
+
+    if !name.properties.is_empty() {
+        card.set_field("name", typed_object("Name").with_fields(name).into());
+    }
+    let mut maps: Vec<(&str, Object<Value>)> = Vec::new();
+    for (map_name, prefix, entry) in entries {
+        let map = if let Some((_, map)) = maps.iter_mut().find(|(name, _)| *name == map_name) {
+            map
+        } else {
+            maps.push((map_name, Object::with_capacity(1)));
+            &mut maps.last_mut().unwrap().1
+        };
+        let id = format!("{prefix}{}", map.properties.len() + 1);
+        map.properties.append(Property::_T(id), entry);
+    }
+    for (map_name, map) in maps {
+        card.set_field(map_name, map.into());
+    }
+    if !keywords.properties.is_empty() {
+        card.set_field("keywords", keywords.into());
+    }
+    if !members.properties.is_empty() {
+        card.set_field("members", members.into());
+    }
+    if !vcard_props.is_empty() {
+        card.set_field("vCardProps", Value::List(vcard_props));
+    }
+
+    Some(card)
+}
+
+// Converts a JSContact card to a vCard 3.0, the version all CardDAV clients support.
+pub fn build_vcard(card: &Object<Value>) -> String {
+    let mut vcard = String::with_capacity(512);
+    write_line(&mut vcard, "BEGIN", &[], "VCARD");
+    write_line(&mut vcard, "VERSION", &[], "3.0");
+    write_line(
+        &mut vcard,
+        "PRODID",
+        &[],
+        "-//Stalwart Labs Ltd.//Stalwart Mail Server//EN",
+    );
+    if let Some(uid) = card.text_field("uid") {
+        write_line(&mut vcard, "UID", &[], uid);
+    }
+    if let Some(kind) = card.text_field("kind").filter(|kind| *kind != "individual") {
+        write_line(&mut vcard, "X-ADDRESSBOOKSERVER-KIND", &[], kind);
+    }
+
+    // FN and N are mandatory in vCard 3.0
+    let name = card.field("name").and_then(|name| name.as_obj());
+    let mut n_values = vec![Vec::new(); N_KINDS.len()];
+    for component in components(name) {
+        if let Some(pos) = component
+            .text_field("kind")
+            .and_then(|kind| N_KINDS.iter().position(|k| *k == kind))
+        {
+            if let Some(value) = component.text_field("value") {
+                n_values[pos].push(escape(value));
+            }
+        }
+    }
+    let full_name = name
+        .and_then(|name| name.text_field("full"))
+        .map(|full| full.to_string())
+        .or_else(|| {
+            let name = [1, 2, 0]
+                .into_iter()
+                .flat_map(|pos| n_values[pos].iter())
+                .map(|value| unescape(value))
+                .collect::<Vec<_>>()
+                .join(" ");
+            (!name.is_empty()).then_some(name)
+        })
+        .or_else(|| {
+            map_entries(card, "organizations")
+                .find_map(|org| org.text_field("name"))
+                .map(|name| name.to_string())
+        })
+        .or_else(|| {
+            map_entries(card, "emails")
+                .find_map(|email| email.text_field("address"))
+                .map(|address| address.to_string())
+        })
+        .unwrap_or_default();
+    write_line(&mut vcard, "FN", &[], &escape(&full_name));
+    write_line(&mut vcard, "N", &[], &join_structured(n_values));
+
+    let nicknames = map_entries(card, "nicknames")
+        .filter_map(|nickname| nickname.text_field("name"))
+        .map(escape)
+        .collect::<Vec<_>>();
+    if !nicknames.is_empty() {
+        write_line(&mut vcard, "NICKNAME", &[], &nicknames.join(","));
+    }
+
+    for email in map_entries(card, "emails") {
+        if let Some(address) = email.text_field("address") {
+            let types = contexts_to_types(email, &["INTERNET"]);
+            write_line(&mut vcard, "EMAIL", &types, &escape(address));
+        }
+    }
+    for phone in map_entries(card, "phones") {
+        if let Some(number) = phone.text_field("number") {
+            let mut types = contexts_to_types(phone, &[]);
+            for (feature, _) in flags(phone.field("features")) {
+                types.push(if feature == "mobile" {
+                    "CELL".to_string()
+                } else {
+                    feature.to_uppercase()
+                });
+            }
+            write_line(&mut vcard, "TEL", &types, &escape(number));
+        }
+    }
+    for address in map_entries(card, "addresses") {
+        let mut adr_values = vec![Vec::new(); ADR_KINDS.len()];
+        for component in components(Some(address)) {
+            let pos = match component.text_field("kind").unwrap_or_default() {
+                "postOfficeBox" => 0,
+                "apartment" | "room" | "floor" | "building" => 1,
+                "locality" | "district" | "subdistrict" => 3,
+                "region" => 4,
+                "postcode" => 5,
+                "country" => 6,
+                "separator" => continue,
+                _ => 2,
+            };
+            if let Some(value) = component.text_field("value") {
+                adr_values[pos].push(escape(value));
+            }
+        }
+        let adr_values = adr_values
+            .into_iter()
+            .map(|values| vec![values.join(" ")])
+            .collect();
+        let types = contexts_to_types(address, &[]);
+        write_line(&mut vcard, "ADR", &types, &join_structured(adr_values));
+    }
+    for org in map_entries(card, "organizations") {
+        let mut values = vec![vec![escape(org.text_field("name").unwrap_or_default())]];
+        for unit in org
+            .field("units")
+            .and_then(|units| units.as_list())
+            .into_iter()
+            .flatten()
+        {
+            if let Some(unit) = unit.as_obj().and_then(|unit| unit.text_field("name")) {
+                values.push(vec![escape(unit)]);
+            }
+        }
+        write_line(&mut vcard, "ORG", &[], &join_structured(values));
+    }
+    for title in map_entries(card, "titles") {
+        if let Some(value) = title.text_field("name") {
+            let name = if title.text_field("kind") == Some("role") {
+                "ROLE"
+            } else {
+                "TITLE"
+            };
+            write_line(&mut vcard, name, &[], &escape(value));
+        }
+    }
+    for note in map_entries(card, "notes") {
+        if let Some(value) = note.text_field("note") {
+            write_line(&mut vcard, "NOTE", &[], &escape(value));
+        }
+    }
+    for anniversary in map_entries(card, "anniversaries") {
+        if let Some(date) = anniversary.field("date").and_then(format_date) {
+            let name = if anniversary.text_field("kind") == Some("birth") {
+                "BDAY"
+            } else {
+                "X-ANNIVERSARY"
+            };
+            write_line(&mut vcard, name, &[], &date);
+        }
+    }
+    for link in map_entries(card, "links") {
+        if let Some(uri) = link.text_field("uri") {
+            write_line(&mut vcard, "URL", &[], uri);
+        }
+    }
+    let keywords = flags(card.field("keywords"))
+        .map(|(keyword, _)| escape(keyword))
+        .collect::<Vec<_>>();
+    if !keywords.is_empty() {
+        write_line(&mut vcard, "CATEGORIES", &[], &keywords.join(","));
+    }
+    for media in map_entries(card, "media") {
+        if let (Some("photo"), Some(uri)) = (media.text_field("kind"), media.text_field("uri")) {
+            write_line(&mut vcard, "PHOTO;VALUE=URI", &[], uri);
+        }
+    }
+    for (member, _) in flags(card.field("members")) {
+        write_line(&mut vcard, "X-ADDRESSBOOKSERVER-MEMBER", &[], member);
+    }
+    for prop in card
+        .field("vCardProps")
+        .and_then(|props| props.as_list())
+        .into_iter()
+        .flatten()
+    {
+        if let Some([name, params, _, value]) = prop.as_list().map(|prop| prop.as_slice()) {
+            if let (Some(name), Some(value)) = (name.as_string(), value.as_string()) {
+                let mut line = name.to_uppercase();
+                for (key, value) in params
+                    .as_obj()
+                    .into_iter()
+                    .flat_map(|params| params.properties.iter())
+                {
+                    if let Some(value) = value.as_string() {
+                        line.push(';');
+                        line.push_str(&key.to_string().to_uppercase());
+                        line.push('=');
+                        line.push_str(value);
+                    }
+                }
+                write_line(&mut vcard, &line, &[], value);
+            }
+        }
+    }
+
+    write_line(&mut vcard, "END", &[], "VCARD");
+    vcard
+}
+
+// Returns the unescaped values of a vCard property, used by CardDAV filters.
+pub fn vcard_property_values(vcard: &str, name: &str) -> Vec<String> {
+    unfold(vcard)
+        .iter()
+        .filter_map(|line| parse_line(line))
+        .filter(|line| line.name.eq_ignore_ascii_case(name))
+        .map(|line| unescape(&line.value))
+        .collect()
+}
+
+fn set_contexts(object: &mut Object<Value>, line: &ContentLine, features: &[&str]) {
+    let mut contexts = Object::with_capacity(1);
+    let mut feature_list = Object::with_capacity(1);
+    let mut pref = line.param("PREF").and_then(|pref| pref.parse::<u64>().ok());
+
+    for value in line.types() {
+        match value.as_str() {
+            "work" => {
+                contexts.set_field("work", Value::Bool(true));
+            }
+            "home" => {
+                contexts.set_field("private", Value::Bool(true));
+            }
+            "pref" => {
+                pref = Some(1);
+            }
+            "cell" if features.contains(&"cell") => {
+                feature_list.set_field("mobile", Value::Bool(true));
+            }
+            feature if features.contains(&feature) => {
+                feature_list.set_field(feature, Value::Bool(true));
+            }
+            _ => (),
+        }
+    }
+
+    if !feature_list.properties.is_empty() {
+        object.set_field("features", feature_list.into());
+    }
+    if !contexts.properties.is_empty() {
+        object.set_field("contexts", contexts.into());
+    }
+    if let Some(pref) = pref {
+        object.set_field("pref", Value::UnsignedInt(pref));
+    }
+}
+
+fn contexts_to_types(object: &Object<Value>, defaults: &[&str]) -> Vec<String> {
+    let mut types = defaults
+        .iter()
+        .map(|value| value.to_string())
+        .collect::<Vec<_>>();
+    for (context, _) in flags(object.field("contexts")) {
+        match context {
+            "work" => types.push("WORK".to_string()),
+            "private" => types.push("HOME".to_string()),
+            _ => (),
+        }
+    }
+    if object.field("pref").and_then(|pref| pref.try_cast_uint()) == Some(1) {
+        types.push("PREF".to_string());
+    }
+    types
+}
+
+fn components(object: Option<&Object<Value>>) -> impl Iterator<Item = &Object<Value>> {
+    object
+        .and_then(|object| object.field("components"))
+        .and_then(|components| components.as_list())
+        .into_iter()
+        .flatten()
+        .filter_map(|component| component.as_obj())
+}
+
+fn parse_date(value: &str) -> Option<Value> {
+    let value = value.split('T').next().unwrap_or_default();
+    let (year, month_day) = if let Some(month_day) = value.strip_prefix("--") {
+        (None, month_day.replace('-', ""))
+    } else {
+        let value = value.replace('-', "");
+        (
+            Some(value.get(..4)?.parse::<u64>().ok()?),
+            value.get(4..)?.to_string(),
+        )
+    };
+    let month = month_day.get(..2)?.parse::<u64>().ok()?;
+    let day = month_day.get(2..4)?.parse::<u64>().ok()?;
+    let mut date = typed_object("PartialDate");
+    if let Some(year) = year {
+        date.set_field("year", Value::UnsignedInt(year));
+    }
+    date.set_field("month", Value::UnsignedInt(month));
+    date.set_field("day", Value::UnsignedInt(day));
+    Some(date.into())
+}
+
+fn format_date(value: &Value) -> Option<String> {
+    let date = value.as_obj()?;
+    if let Some(utc) = date.text_field("utc") {
+        return utc.get(..10).map(|date| date.to_string());
+    }
+    let month = date.field("month")?.try_cast_uint()?;
+    let day = date.field("day")?.try_cast_uint()?;
+    Some(
+        if let Some(year) = date.field("year").and_then(|year| year.try_cast_uint()) {
+            format!("{year:04}-{month:02}-{day:02}")
+        } else {
+            format!("--{month:02}{day:02}")
+        },
+    )
+}
+
+fn write_line(vcard: &mut String, name: &str, types: &[String], value: &str) {
+    let mut line = String::with_capacity(name.len() + value.len() + 16);
+    line.push_str(name);
+    if !types.is_empty() {
+        line.push_str(";TYPE=");
+        line.push_str(&types.join(","));
+    }
+    line.push(':');
+    line.push_str(value);
+    fold_line(vcard, &line);
+}
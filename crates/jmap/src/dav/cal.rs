@@ -0,0 +1,1006 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use chrono::NaiveDateTime;
+use hyper::{header, header::HeaderName, StatusCode};
+use jmap_proto::{
+    error::method::MethodError,
+    object::{index::ObjectIndexBuilder, Object},
+    types::{
+        collection::Collection, property::Property, state::StateChange, type_state::DataType,
+        value::Value,
+    },
+};
+use store::{
+    query::Filter,
+    write::{assert::HashedValue, log::ChangeLogBuilder, BatchBuilder},
+};
+
+use crate::{
+    api::{http::fetch_body, HttpRequest},
+    auth::AccessToken,
+    calendar::set::SCHEMA,
+    calendar_event::{
+        event_utc_range,
+        ical::{build_ical, ical_property_values, parse_ical},
+        index::EventIndex,
+        set::set_calendar_ids,
+    },
+    contact::{generate_uid, JSContactObject},
+    JMAP,
+};
+
+use super::{
+    dav_name, escape, etag, matches_etag, prop, requested_props, DavResponse, Depth, MultiStatus,
+    XmlElement, NS_CALDAV, NS_CALENDARSERVER, NS_DAV,
+};
+
+const CALDAV_ROOT: &str = "/dav/cal";
+const ICAL_CONTENT_TYPE: &str = "text/calendar; charset=utf-8";
+const NS_APPLE_ICAL: &str = "http://apple.com/ns/ical/";
+
+enum Resource {
+    Root,
+    Home,
+    Calendar {
+        calendar: Object<Value>,
+        ctag: String,
+    },
+    Event {
+        event: HashedValue<Object<Value>>,
+    },
+}
+
+impl JMAP {
+    pub async fn handle_cal_request(
+        &self,
+        req: &mut HttpRequest,
+        path: Vec<String>,
+        access_token: &AccessToken,
+    ) -> Result<DavResponse, MethodError> {
+        if req.method() == hyper::Method::OPTIONS {
+            return Ok(DavResponse::new(StatusCode::OK)
+                .with_header(
+                    HeaderName::from_static("dav"),
+                    "1, 3, calendar-access, calendar-auto-schedule",
+                )
+                .with_header(
+                    header::ALLOW,
+                    "OPTIONS, GET, PUT, DELETE, PROPFIND, PROPPATCH, REPORT, MKCOL, MKCALENDAR",
+                ));
+        }
+
+        // Only the calendars of the authenticated account are exposed
+        let path = path.iter().map(|p| p.as_str()).collect::<Vec<_>>();
+        if path
+            .first()
+            .is_some_and(|account| !account.eq_ignore_ascii_case(&access_token.name))
+        {
+            return Ok(DavResponse::new(StatusCode::FORBIDDEN));
+        }
+        let account_id = access_token.primary_id();
+        self.calendar_get_or_create(account_id).await?;
+
+        match (req.method().as_str(), path.as_slice()) {
+            ("PROPFIND", _) => self.cal_propfind(req, &path, access_token).await,
+            ("REPORT", [_, calendar_name]) => {
+                self.cal_report(req, calendar_name, access_token).await
+            }
+            ("GET", [_, calendar_name, event_name]) => {
+                if let Some(event) = self
+                    .event_by_name(account_id, calendar_name, event_name)
+                    .await?
+                {
+                    Ok(DavResponse::new(StatusCode::OK)
+                        .with_header(header::ETAG, etag(&event))
+                        .with_body(ICAL_CONTENT_TYPE, build_ical(&event.1.inner, None)))
+                } else {
+                    Ok(DavResponse::new(StatusCode::NOT_FOUND))
+                }
+            }
+            ("PUT", [_, calendar_name, event_name]) => {
+                self.cal_put(req, calendar_name, event_name, access_token)
+                    .await
+            }
+            ("DELETE", [_, calendar_name, event_name]) => {
+                if let Some((document_id, event)) = self
+                    .event_by_name(account_id, calendar_name, event_name)
+                    .await?
+                {
+                    if !matches_etag(req, header::IF_MATCH, &event).unwrap_or(true) {
+                        return Ok(DavResponse::new(StatusCode::PRECONDITION_FAILED));
+                    }
+                    let mut changes = ChangeLogBuilder::new();
+                    let deleted = self.calendar_event_delete(account_id, document_id).await?;
+                    if deleted.is_some() {
+                        changes.log_delete(Collection::CalendarEvent, document_id);
+                    }
+                    self.cal_commit(account_id, changes).await?;
+
+                    // Implicit scheduling (RFC 6638), attendees are notified of the cancellation
+                    if let Some(deleted) = deleted {
+                        self.itip_send(account_id, Some(&deleted), None).await;
+                    }
+                    Ok(DavResponse::new(StatusCode::NO_CONTENT))
+                } else {
+                    Ok(DavResponse::new(StatusCode::NOT_FOUND))
+                }
+            }
+            ("DELETE", [_, calendar_name]) => {
+                if let Some((document_id, _)) =
+                    self.calendar_by_name(account_id, calendar_name).await?
+                {
+                    let mut changes = ChangeLogBuilder::new();
+                    match self
+                        .calendar_destroy(account_id, document_id, true, &mut changes)
+                        .await?
+                    {
+                        Ok(_) => {
+                            self.cal_commit(account_id, changes).await?;
+                            Ok(DavResponse::new(StatusCode::NO_CONTENT))
+                        }
+                        Err(_) => Ok(DavResponse::new(StatusCode::CONFLICT)),
+                    }
+                } else {
+                    Ok(DavResponse::new(StatusCode::NOT_FOUND))
+                }
+            }
+            ("MKCOL" | "MKCALENDAR", [_, calendar_name]) => {
+                self.cal_mkcol(req, calendar_name, access_token).await
+            }
+            ("PROPPATCH", [_, calendar_name]) => {
+                self.cal_proppatch(req, calendar_name, access_token).await
+            }
+            _ => Ok(DavResponse::new(StatusCode::METHOD_NOT_ALLOWED)),
+        }
+    }
+
+    async fn cal_propfind(
+        &self,
+        req: &mut HttpRequest,
+        path: &[&str],
+        access_token: &AccessToken,
+    ) -> Result<DavResponse, MethodError> {
+        let depth = Depth::parse(req);
+        let request = match self.dav_read_xml(req, access_token).await {
+            Ok(request) => request,
+            Err(response) => return Ok(response),
+        };
+        let account_id = access_token.primary_id();
+        let home = format!("{CALDAV_ROOT}/{}/", access_token.name);
+
+        // Obtain the resources to list
+        let mut resources = Vec::new();
+        match path {
+            [] => {
+                resources.push((format!("{CALDAV_ROOT}/"), Resource::Root));
+                if depth == Depth::One {
+                    resources.push((home, Resource::Home));
+                }
+            }
+            [_] => {
+                resources.push((home.clone(), Resource::Home));
+                if depth == Depth::One {
+                    for (_, dav_name, calendar) in self.calendars(account_id).await? {
+                        resources.push((
+                            format!("{home}{dav_name}/"),
+                            self.calendar_resource(account_id, calendar).await?,
+                        ));
+                    }
+                }
+            }
+            [_, calendar_name] => {
+                if let Some((document_id, calendar)) =
+                    self.calendar_by_name(account_id, calendar_name).await?
+                {
+                    resources.push((
+                        format!("{home}{calendar_name}/"),
+                        self.calendar_resource(account_id, calendar).await?,
+                    ));
+                    if depth == Depth::One {
+                        for event in self.calendar_events(account_id, document_id).await? {
+                            resources.push((
+                                format!("{home}{calendar_name}/{}", dav_name(&event.inner)),
+                                Resource::Event { event },
+                            ));
+                        }
+                    }
+                } else {
+                    return Ok(DavResponse::new(StatusCode::NOT_FOUND));
+                }
+            }
+            [_, calendar_name, event_name] => {
+                if let Some((_, event)) = self
+                    .event_by_name(account_id, calendar_name, event_name)
+                    .await?
+                {
+                    resources.push((
+                        format!("{home}{calendar_name}/{event_name}"),
+                        Resource::Event { event },
+                    ));
+                } else {
+                    return Ok(DavResponse::new(StatusCode::NOT_FOUND));
+                }
+            }
+            _ => return Ok(DavResponse::new(StatusCode::NOT_FOUND)),
+        }
+
+        let props = request.as_ref().and_then(requested_props);
+        let mut response = MultiStatus::new();
+        for (href, resource) in &resources {
+            let default_props = resource.default_props();
+            self.cal_add_response(
+                &mut response,
+                href,
+                resource,
+                props
+                    .clone()
+                    .unwrap_or_else(|| default_props.iter().collect()),
+                access_token,
+            );
+        }
+
+        Ok(response.into_response())
+    }
+
+    async fn cal_report(
+        &self,
+        req: &mut HttpRequest,
+        calendar_name: &str,
+        access_token: &AccessToken,
+    ) -> Result<DavResponse, MethodError> {
+        let request = match self.dav_read_xml(req, access_token).await {
+            Ok(Some(request)) => request,
+            Ok(None) => return Ok(DavResponse::new(StatusCode::BAD_REQUEST)),
+            Err(response) => return Ok(response),
+        };
+        let account_id = access_token.primary_id();
+        let calendar_id = if let Some((document_id, _)) =
+            self.calendar_by_name(account_id, calendar_name).await?
+        {
+            document_id
+        } else {
+            return Ok(DavResponse::new(StatusCode::NOT_FOUND));
+        };
+        let calendar_href = format!("{CALDAV_ROOT}/{}/{calendar_name}/", access_token.name);
+        let default_props = [prop(NS_DAV, "getetag"), prop(NS_CALDAV, "calendar-data")];
+        let props = requested_props(&request).unwrap_or_else(|| default_props.iter().collect());
+        let mut response = MultiStatus::new();
+
+        if request.is(NS_CALDAV, "calendar-multiget") {
+            for href in request
+                .children
+                .iter()
+                .filter(|child| child.is(NS_DAV, "href"))
+            {
+                let event_name = href.text.rsplit('/').next().unwrap_or_default();
+                let event = if href
+                    .text
+                    .ends_with(&format!("/{calendar_name}/{event_name}"))
+                {
+                    self.event_by_name(account_id, calendar_name, event_name)
+                        .await?
+                } else {
+                    None
+                };
+                if let Some((_, event)) = event {
+                    self.cal_add_response(
+                        &mut response,
+                        &href.text,
+                        &Resource::Event { event },
+                        props.clone(),
+                        access_token,
+                    );
+                } else {
+                    response.add_status(&href.text, StatusCode::NOT_FOUND);
+                }
+            }
+        } else if request.is(NS_CALDAV, "calendar-query") {
+            let filter = request.child(NS_CALDAV, "filter");
+            for event in self.calendar_events(account_id, calendar_id).await? {
+                if filter.is_none_or(|filter| matches_filter(filter, &event.inner)) {
+                    let href = format!("{calendar_href}{}", dav_name(&event.inner));
+                    self.cal_add_response(
+                        &mut response,
+                        &href,
+                        &Resource::Event { event },
+                        props.clone(),
+                        access_token,
+                    );
+                }
+            }
+        } else {
+            return Ok(DavResponse::new(StatusCode::FORBIDDEN));
+        }
+
+        Ok(response.into_response())
+    }
+
+    async fn cal_put(
+        &self,
+        req: &mut HttpRequest,
+        calendar_name: &str,
+        event_name: &str,
+        access_token: &AccessToken,
+    ) -> Result<DavResponse, MethodError> {
+        let account_id = access_token.primary_id();
+        let calendar_id = if let Some((document_id, _)) =
+            self.calendar_by_name(account_id, calendar_name).await?
+        {
+            document_id
+        } else {
+            return Ok(DavResponse::new(StatusCode::CONFLICT));
+        };
+        let current = self
+            .event_by_name(account_id, calendar_name, event_name)
+            .await?;
+
+        // Validate preconditions
+        if let Some((_, current)) = &current {
+            if matches_etag(req, header::IF_NONE_MATCH, current).unwrap_or(false)
+                || !matches_etag(req, header::IF_MATCH, current).unwrap_or(true)
+            {
+                return Ok(DavResponse::new(StatusCode::PRECONDITION_FAILED));
+            }
+        } else if req.headers().contains_key(header::IF_MATCH) {
+            return Ok(DavResponse::new(StatusCode::PRECONDITION_FAILED));
+        }
+
+        // Parse iCalendar, a calendar object resource holds a single event (RFC 4791, Section 4.1)
+        let mut event = if let Some(ical) =
+            fetch_body(req, self.config.load().calendar_max_size, access_token).await
+        {
+            match std::str::from_utf8(&ical)
+                .ok()
+                .and_then(parse_ical)
+                .map(|ical| ical.events)
+            {
+                Some(events) if events.len() == 1 => events.into_iter().next().unwrap(),
+                _ => return Ok(DavResponse::new(StatusCode::BAD_REQUEST)),
+            }
+        } else {
+            return Ok(DavResponse::new(StatusCode::PAYLOAD_TOO_LARGE));
+        };
+
+        let mut changes = ChangeLogBuilder::new();
+        let mut batch = BatchBuilder::new();
+        batch
+            .with_account_id(account_id)
+            .with_collection(Collection::CalendarEvent);
+        let (document_id, status, current) = if let Some((document_id, current)) = current {
+            // Keep the properties that can't be expressed in iCalendar
+            for property in [Property::CalendarIds, Property::DavName] {
+                event.set(property.clone(), current.inner.get(&property).clone());
+            }
+            if event.text_field("uid") != current.inner.text_field("uid") {
+                return Ok(DavResponse::new(StatusCode::CONFLICT));
+            }
+            let previous = current.inner.clone();
+            batch
+                .update_document(document_id)
+                .custom(EventIndex::update(current, event));
+            match self.store.write(batch.build()).await {
+                Ok(_) => (),
+                Err(store::Error::AssertValueFailed) => {
+                    return Ok(DavResponse::new(StatusCode::PRECONDITION_FAILED));
+                }
+                Err(err) => {
+                    tracing::error!(
+                        event = "error",
+                        context = "cal_put",
+                        account_id = account_id,
+                        error = ?err,
+                        "Failed to update event.");
+                    return Err(MethodError::ServerPartialFail);
+                }
+            }
+            changes.log_update(Collection::CalendarEvent, document_id);
+            (document_id, StatusCode::NO_CONTENT, Some(previous))
+        } else {
+            if event.text_field("uid").is_none() {
+                event.set_field("uid", Value::Text(generate_uid()));
+            }
+            set_calendar_ids(&mut event, vec![calendar_id]);
+            event.set(Property::DavName, Value::Text(event_name.to_string()));
+            let document_id = self
+                .assign_document_id(account_id, Collection::CalendarEvent)
+                .await?;
+            batch
+                .create_document(document_id)
+                .custom(EventIndex::insert(event));
+            self.write_batch(batch).await?;
+            changes.log_insert(Collection::CalendarEvent, document_id);
+            (document_id, StatusCode::CREATED, None)
+        };
+        self.cal_commit(account_id, changes).await?;
+
+        // Return the new ETag and notify the attendees (RFC 6638, Section 3)
+        let mut response = DavResponse::new(status);
+        if let Some(event) = self
+            .get_property::<HashedValue<Object<Value>>>(
+                account_id,
+                Collection::CalendarEvent,
+                document_id,
+                Property::Value,
+            )
+            .await?
+        {
+            response = response.with_header(header::ETAG, format!("\"{}\"", event.hash));
+            self.itip_send(account_id, current.as_ref(), Some(&event.inner))
+                .await;
+        }
+        Ok(response)
+    }
+
+    async fn cal_mkcol(
+        &self,
+        req: &mut HttpRequest,
+        calendar_name: &str,
+        access_token: &AccessToken,
+    ) -> Result<DavResponse, MethodError> {
+        let request = match self.dav_read_xml(req, access_token).await {
+            Ok(request) => request,
+            Err(response) => return Ok(response),
+        };
+        let account_id = access_token.primary_id();
+        if self
+            .calendar_by_name(account_id, calendar_name)
+            .await?
+            .is_some()
+        {
+            return Ok(DavResponse::new(StatusCode::METHOD_NOT_ALLOWED));
+        } else if self
+            .get_document_ids(account_id, Collection::Calendar)
+            .await?
+            .map_or(0, |ids| ids.len() as usize)
+            >= self.config.load().calendar_max_calendars
+        {
+            return Ok(DavResponse::new(StatusCode::INSUFFICIENT_STORAGE));
+        }
+
+        // MKCALENDAR (RFC 4791) and extended MKCOL (RFC 5689) may include properties
+        let mut calendar = Object::with_capacity(3)
+            .with_property(Property::Name, calendar_name)
+            .with_property(Property::DavName, calendar_name);
+        for prop in request
+            .as_ref()
+            .and_then(|request| {
+                request
+                    .child(NS_DAV, "set")
+                    .or_else(|| request.child(NS_CALDAV, "set"))
+            })
+            .and_then(|set| set.child(NS_DAV, "prop"))
+            .into_iter()
+            .flat_map(|prop| prop.children.iter())
+        {
+            if let Some((property, value)) = calendar_prop(prop, self) {
+                calendar.set(property, value);
+            }
+        }
+
+        let document_id = self
+            .assign_document_id(account_id, Collection::Calendar)
+            .await?;
+        let mut batch = BatchBuilder::new();
+        batch
+            .with_account_id(account_id)
+            .with_collection(Collection::Calendar)
+            .create_document(document_id)
+            .custom(ObjectIndexBuilder::new(SCHEMA).with_changes(calendar));
+        self.write_batch(batch).await?;
+        let mut changes = ChangeLogBuilder::new();
+        changes.log_insert(Collection::Calendar, document_id);
+        self.cal_commit(account_id, changes).await?;
+
+        Ok(DavResponse::new(StatusCode::CREATED))
+    }
+
+    async fn cal_proppatch(
+        &self,
+        req: &mut HttpRequest,
+        calendar_name: &str,
+        access_token: &AccessToken,
+    ) -> Result<DavResponse, MethodError> {
+        let request = match self.dav_read_xml(req, access_token).await {
+            Ok(Some(request)) => request,
+            Ok(None) => return Ok(DavResponse::new(StatusCode::BAD_REQUEST)),
+            Err(response) => return Ok(response),
+        };
+        let account_id = access_token.primary_id();
+        let document_id = if let Some((document_id, _)) =
+            self.calendar_by_name(account_id, calendar_name).await?
+        {
+            document_id
+        } else {
+            return Ok(DavResponse::new(StatusCode::NOT_FOUND));
+        };
+        let current = self
+            .get_property::<HashedValue<Object<Value>>>(
+                account_id,
+                Collection::Calendar,
+                document_id,
+                Property::Value,
+            )
+            .await?
+            .ok_or(MethodError::ServerPartialFail)?;
+
+        let mut updated = Vec::new();
+        let mut rejected = Vec::new();
+        let mut changes = Object::with_capacity(2);
+        for action in &request.children {
+            let is_set = action.is(NS_DAV, "set");
+            for prop in action
+                .child(NS_DAV, "prop")
+                .into_iter()
+                .flat_map(|prop| prop.children.iter())
+            {
+                match calendar_prop(prop, self) {
+                    Some((property, value)) => {
+                        changes.set(property, if is_set { value } else { Value::Null });
+                        updated.push((prop, String::new()));
+                    }
+                    None => {
+                        rejected.push((prop, String::new()));
+                    }
+                }
+            }
+        }
+
+        // Changes are applied atomically, so a single rejected property fails the request
+        let mut response = MultiStatus::new();
+        let href = format!("{CALDAV_ROOT}/{}/{calendar_name}/", access_token.name);
+        if rejected.is_empty() {
+            let mut batch = BatchBuilder::new();
+            batch
+                .with_account_id(account_id)
+                .with_collection(Collection::Calendar)
+                .update_document(document_id)
+                .custom(
+                    ObjectIndexBuilder::new(SCHEMA)
+                        .with_current(current)
+                        .with_changes(changes),
+                );
+            if !batch.is_empty() {
+                self.write_batch(batch).await?;
+                let mut changes = ChangeLogBuilder::new();
+                changes.log_update(Collection::Calendar, document_id);
+                self.cal_commit(account_id, changes).await?;
+            }
+            response.add_response(&href, vec![(StatusCode::OK, updated)]);
+        } else {
+            response.add_response(
+                &href,
+                vec![
+                    (StatusCode::FORBIDDEN, rejected),
+                    (StatusCode::FAILED_DEPENDENCY, updated),
+                ],
+            );
+        }
+
+        Ok(response.into_response())
+    }
+
+    fn cal_add_response(
+        &self,
+        response: &mut MultiStatus,
+        href: &str,
+        resource: &Resource,
+        props: Vec<&XmlElement>,
+        access_token: &AccessToken,
+    ) {
+        let mut found = Vec::with_capacity(props.len());
+        let mut not_found = Vec::new();
+        for prop in props {
+            if let Some(value) = self.cal_prop(resource, prop, access_token) {
+                found.push((prop, value));
+            } else {
+                not_found.push((prop, String::new()));
+            }
+        }
+        response.add_response(
+            href,
+            vec![(StatusCode::OK, found), (StatusCode::NOT_FOUND, not_found)],
+        );
+    }
+
+    fn cal_prop(
+        &self,
+        resource: &Resource,
+        prop: &XmlElement,
+        access_token: &AccessToken,
+    ) -> Option<String> {
+        let home = format!(
+            "<D:href>{}</D:href>",
+            escape(&format!("{CALDAV_ROOT}/{}/", access_token.name))
+        );
+        match (prop.namespace.as_str(), prop.name.as_str(), resource) {
+            (NS_DAV, "current-user-principal", _) => Some(home),
+            (NS_DAV, "principal-URL", Resource::Home)
+            | (NS_CALDAV, "calendar-home-set", Resource::Root | Resource::Home) => Some(home),
+            (NS_DAV, "current-user-privilege-set", _) => Some(
+                concat!(
+                    "<D:privilege><D:read/></D:privilege>",
+                    "<D:privilege><D:write/></D:privilege>"
+                )
+                .to_string(),
+            ),
+            (NS_DAV, "resourcetype", resource) => Some(
+                match resource {
+                    Resource::Root => "<D:collection/>",
+                    Resource::Home => "<D:collection/><D:principal/>",
+                    Resource::Calendar { .. } => "<D:collection/><CAL:calendar/>",
+                    Resource::Event { .. } => "",
+                }
+                .to_string(),
+            ),
+            (NS_DAV, "displayname", Resource::Home) => {
+                Some(escape(&access_token.name).into_owned())
+            }
+            (NS_DAV, "displayname", Resource::Calendar { calendar, .. }) => calendar
+                .get(&Property::Name)
+                .as_string()
+                .map(|name| escape(name).into_owned()),
+            (NS_CALDAV, "calendar-description", Resource::Calendar { calendar, .. }) => {
+                calendar
+                    .get(&Property::Description)
+                    .as_string()
+                    .map(|description| escape(description).into_owned())
+            }
+            (NS_APPLE_ICAL, "calendar-color", Resource::Calendar { calendar, .. }) => calendar
+                .get(&Property::Color)
+                .as_string()
+                .map(|color| escape(color).into_owned()),
+            (NS_CALENDARSERVER, "getctag", Resource::Calendar { ctag, .. }) => {
+                Some(escape(ctag).into_owned())
+            }
+            (NS_DAV, "supported-report-set", Resource::Calendar { .. }) => Some(
+                concat!(
+                    "<D:supported-report><D:report><CAL:calendar-multiget/></D:report></D:supported-report>",
+                    "<D:supported-report><D:report><CAL:calendar-query/></D:report></D:supported-report>"
+                )
+                .to_string(),
+            ),
+            (NS_CALDAV, "supported-calendar-component-set", Resource::Calendar { .. }) => {
+                Some("<CAL:comp name=\"VEVENT\"/>".to_string())
+            }
+            (NS_CALDAV, "supported-calendar-data", Resource::Calendar { .. }) => Some(
+                "<CAL:calendar-data content-type=\"text/calendar\" version=\"2.0\"/>".to_string(),
+            ),
+            (NS_CALDAV, "max-resource-size", Resource::Calendar { .. }) => {
+                Some(self.config.load().calendar_max_size.to_string())
+            }
+            (NS_DAV, "getetag", Resource::Event { event, .. }) => {
+                Some(escape(&format!("\"{}\"", event.hash)).into_owned())
+            }
+            (NS_DAV, "getcontenttype", Resource::Event { .. }) => {
+                Some(ICAL_CONTENT_TYPE.to_string())
+            }
+            (NS_CALDAV, "calendar-data", Resource::Event { event, .. }) => {
+                Some(escape(&build_ical(&event.inner, None)).into_owned())
+            }
+            _ => None,
+        }
+    }
+
+    async fn cal_commit(
+        &self,
+        account_id: u32,
+        changes: ChangeLogBuilder,
+    ) -> Result<(), MethodError> {
+        if !changes.is_empty() {
+            let mut state_change = StateChange::new(account_id);
+            let data_types = [
+                (Collection::Calendar, DataType::Calendar),
+                (Collection::CalendarEvent, DataType::CalendarEvent),
+            ]
+            .into_iter()
+            .filter(|(collection, _)| changes.changes.contains_key(&u8::from(*collection)))
+            .map(|(_, data_type)| data_type)
+            .collect::<Vec<_>>();
+            let change_id = self.commit_changes(account_id, changes).await?;
+            for data_type in data_types {
+                state_change = state_change.with_change(data_type, change_id);
+            }
+            self.broadcast_state_change(state_change).await;
+        }
+        Ok(())
+    }
+
+    async fn calendars(
+        &self,
+        account_id: u32,
+    ) -> Result<Vec<(u32, String, Object<Value>)>, MethodError> {
+        let mut calendars = Vec::new();
+        for document_id in self
+            .get_document_ids(account_id, Collection::Calendar)
+            .await?
+            .unwrap_or_default()
+        {
+            if let Some(calendar) = self
+                .get_property::<Object<Value>>(
+                    account_id,
+                    Collection::Calendar,
+                    document_id,
+                    Property::Value,
+                )
+                .await?
+            {
+                calendars.push((document_id, dav_name(&calendar), calendar));
+            }
+        }
+        Ok(calendars)
+    }
+
+    async fn calendar_resource(
+        &self,
+        account_id: u32,
+        calendar: Object<Value>,
+    ) -> Result<Resource, MethodError> {
+        Ok(Resource::Calendar {
+            calendar,
+            ctag: self
+                .get_state(account_id, Collection::CalendarEvent)
+                .await?
+                .to_string(),
+        })
+    }
+
+    async fn calendar_by_name(
+        &self,
+        account_id: u32,
+        name: &str,
+    ) -> Result<Option<(u32, Object<Value>)>, MethodError> {
+        if let Some(document_id) = self
+            .filter(
+                account_id,
+                Collection::Calendar,
+                vec![Filter::eq(Property::DavName, name)],
+            )
+            .await?
+            .results
+            .min()
+        {
+            Ok(self
+                .get_property::<Object<Value>>(
+                    account_id,
+                    Collection::Calendar,
+                    document_id,
+                    Property::Value,
+                )
+                .await?
+                .map(|calendar| (document_id, calendar)))
+        } else {
+            Ok(None)
+        }
+    }
+
+    async fn calendar_events(
+        &self,
+        account_id: u32,
+        calendar_id: u32,
+    ) -> Result<Vec<HashedValue<Object<Value>>>, MethodError> {
+        let mut events = Vec::new();
+        for document_id in self
+            .filter(
+                account_id,
+                Collection::CalendarEvent,
+                vec![Filter::eq(Property::CalendarIds, calendar_id)],
+            )
+            .await?
+            .results
+        {
+            if let Some(event) = self
+                .get_property::<HashedValue<Object<Value>>>(
+                    account_id,
+                    Collection::CalendarEvent,
+                    document_id,
+                    Property::Value,
+                )
+                .await?
+            {
+                events.push(event);
+            }
+        }
+        Ok(events)
+    }
+
+    async fn event_by_name(
+        &self,
+        account_id: u32,
+        calendar_name: &str,
+        event_name: &str,
+    ) -> Result<Option<(u32, HashedValue<Object<Value>>)>, MethodError> {
+        let calendar_id = if let Some((document_id, _)) =
+            self.calendar_by_name(account_id, calendar_name).await?
+        {
+            document_id
+        } else {
+            return Ok(None);
+        };
+
+        if let Some(document_id) = self
+            .filter(
+                account_id,
+                Collection::CalendarEvent,
+                vec![
+                    Filter::eq(Property::CalendarIds, calendar_id),
+                    Filter::eq(Property::DavName, event_name),
+                ],
+            )
+            .await?
+            .results
+            .min()
+        {
+            Ok(self
+                .get_property::<HashedValue<Object<Value>>>(
+                    account_id,
+                    Collection::CalendarEvent,
+                    document_id,
+                    Property::Value,
+                )
+                .await?
+                .map(|event| (document_id, event)))
+        } else {
+            Ok(None)
+        }
+    }
+}
+
+impl Resource {
+    fn default_props(&self) -> Vec<XmlElement> {
+        let mut props = vec![
+            prop(NS_DAV, "resourcetype"),
+            prop(NS_DAV, "current-user-principal"),
+            prop(NS_DAV, "current-user-privilege-set"),
+        ];
+        match self {
+            Resource::Root => {
+                props.push(prop(NS_CALDAV, "calendar-home-set"));
+            }
+            Resource::Home => {
+                props.push(prop(NS_DAV, "displayname"));
+                props.push(prop(NS_DAV, "principal-URL"));
+                props.push(prop(NS_CALDAV, "calendar-home-set"));
+            }
+            Resource::Calendar { .. } => {
+                props.push(prop(NS_DAV, "displayname"));
+                props.push(prop(NS_DAV, "supported-report-set"));
+                props.push(prop(NS_CALENDARSERVER, "getctag"));
+                props.push(prop(NS_CALDAV, "calendar-description"));
+                props.push(prop(NS_CALDAV, "supported-calendar-component-set"));
+                props.push(prop(NS_CALDAV, "supported-calendar-data"));
+                props.push(prop(NS_CALDAV, "max-resource-size"));
+            }
+            Resource::Event { .. } => {
+                props.push(prop(NS_DAV, "getetag"));
+                props.push(prop(NS_DAV, "getcontenttype"));
+            }
+        }
+        props
+    }
+}
+
+fn calendar_prop(prop: &XmlElement, jmap: &JMAP) -> Option<(Property, Value)> {
+    match (prop.namespace.as_str(), prop.name.as_str()) {
+        (NS_DAV, "displayname") => {
+            let name = prop.text.trim();
+            (!name.is_empty() && name.len() < jmap.config.load().calendar_name_max_len)
+                .then(|| (Property::Name, Value::Text(name.to_string())))
+        }
+        (NS_CALDAV, "calendar-description") => Some((
+            Property::Description,
+            Value::Text(prop.text.trim().to_string()),
+        )),
+        (NS_APPLE_ICAL, "calendar-color") => {
+            Some((Property::Color, Value::Text(prop.text.trim().to_string())))
+        }
+        _ => None,
+    }
+}
+
+fn matches_filter(filter: &XmlElement, event: &Object<Value>) -> bool {
+    let ical = build_ical(event, None);
+    filter
+        .children
+        .iter()
+        .filter(|child| child.is(NS_CALDAV, "comp-filter"))
+        .all(|comp_filter| matches_comp_filter(comp_filter, event, &ical))
+}
+
+// All the conditions of a component filter must match (RFC 4791, Section 9.7.1)
+fn matches_comp_filter(filter: &XmlElement, event: &Object<Value>, ical: &str) -> bool {
+    let begin = format!("BEGIN:{}", filter.attribute("name").unwrap_or_default());
+    let exists = ical
+        .lines()
+        .any(|line| line.trim_end().eq_ignore_ascii_case(&begin));
+    if filter.child(NS_CALDAV, "is-not-defined").is_some() {
+        return !exists;
+    }
+
+    exists
+        && filter.children.iter().all(|child| {
+            if child.is(NS_CALDAV, "comp-filter") {
+                matches_comp_filter(child, event, ical)
+            } else if child.is(NS_CALDAV, "prop-filter") {
+                matches_prop_filter(child, ical)
+            } else if child.is(NS_CALDAV, "time-range") {
+                matches_time_range(child, event)
+            } else {
+                true
+            }
+        })
+}
+
+fn matches_time_range(filter: &XmlElement, event: &Object<Value>) -> bool {
+    let parse_utc = |name| {
+        filter.attribute(name).and_then(|value| {
+            NaiveDateTime::parse_from_str(value, "%Y%m%dT%H%M%SZ")
+                .ok()
+                .map(|value| value.timestamp())
+        })
+    };
+    let (start, end) = (parse_utc("start"), parse_utc("end"));
+
+    if let Some((event_start, event_end)) = event_utc_range(event) {
+        end.is_none_or(|end| event_start < end)
+            && start.is_none_or(|start| {
+                event_end.is_none_or(|event_end| {
+                    event_end > start || (event_end == event_start && event_start >= start)
+                })
+            })
+    } else {
+        false
+    }
+}
+
+fn matches_prop_filter(filter: &XmlElement, ical: &str) -> bool {
+    let values = ical_property_values(ical, filter.attribute("name").unwrap_or_default());
+    if filter.child(NS_CALDAV, "is-not-defined").is_some() {
+        return values.is_empty();
+    }
+    let mut results = filter
+        .children
+        .iter()
+        .filter(|child| child.is(NS_CALDAV, "text-match"))
+        .map(|text_match| {
+            let needle = text_match.text.to_lowercase();
+            let is_match = values.iter().any(|value| {
+                let value = value.to_lowercase();
+                match text_match.attribute("match-type").unwrap_or("contains") {
+                    "equals" => value == needle,
+                    "starts-with" => value.starts_with(&needle),
+                    "ends-with" => value.ends_with(&needle),
+                    _ => value.contains(&needle),
+                }
+            });
+            is_match != (text_match.attribute("negate-condition") == Some("yes"))
+        })
+        .peekable();
+    if results.peek().is_none() {
+        !values.is_empty()
+    } else if filter.attribute("test") == Some("allof") {
+        results.all(|result| result)
+    } else {
+        results.any(|result| result)
+    }
+}
@@ -0,0 +1,920 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use hyper::{header, header::HeaderName, StatusCode};
+use jmap_proto::{
+    error::method::MethodError,
+    object::{index::ObjectIndexBuilder, Object},
+    types::{
+        collection::Collection, property::Property, state::StateChange, type_state::DataType,
+        value::Value,
+    },
+};
+use store::{
+    query::Filter,
+    write::{assert::HashedValue, log::ChangeLogBuilder, BatchBuilder},
+};
+
+use crate::{
+    address_book::set::SCHEMA,
+    api::{http::fetch_body, HttpRequest},
+    auth::AccessToken,
+    contact::{
+        generate_uid,
+        index::ContactIndex,
+        set::set_address_book_ids,
+        vcard::{build_vcard, parse_vcard, vcard_property_values},
+        JSContactObject,
+    },
+    JMAP,
+};
+
+use super::{
+    dav_name, escape, etag, matches_etag, prop, requested_props, DavResponse, Depth, MultiStatus,
+    XmlElement, NS_CALENDARSERVER, NS_CARDDAV, NS_DAV,
+};
+
+const CARDDAV_ROOT: &str = "/dav/card";
+const VCARD_CONTENT_TYPE: &str = "text/vcard; charset=utf-8";
+
+enum Resource {
+    Root,
+    Home,
+    AddressBook {
+        address_book: Object<Value>,
+        ctag: String,
+    },
+    Card {
+        card: HashedValue<Object<Value>>,
+    },
+}
+
+impl JMAP {
+    pub async fn handle_card_request(
+        &self,
+        req: &mut HttpRequest,
+        path: Vec<String>,
+        access_token: &AccessToken,
+    ) -> Result<DavResponse, MethodError> {
+        if req.method() == hyper::Method::OPTIONS {
+            return Ok(DavResponse::new(StatusCode::OK)
+                .with_header(HeaderName::from_static("dav"), "1, 3, addressbook")
+                .with_header(
+                    header::ALLOW,
+                    "OPTIONS, GET, PUT, DELETE, PROPFIND, PROPPATCH, REPORT, MKCOL",
+                ));
+        }
+
+        // Only the address books of the authenticated account are exposed
+        let path = path.iter().map(|p| p.as_str()).collect::<Vec<_>>();
+        if path
+            .first()
+            .is_some_and(|account| !account.eq_ignore_ascii_case(&access_token.name))
+        {
+            return Ok(DavResponse::new(StatusCode::FORBIDDEN));
+        }
+        let account_id = access_token.primary_id();
+        self.address_book_get_or_create(account_id).await?;
+
+        match (req.method().as_str(), path.as_slice()) {
+            ("PROPFIND", _) => self.card_propfind(req, &path, access_token).await,
+            ("REPORT", [_, book_name]) => self.card_report(req, book_name, access_token).await,
+            ("GET", [_, book_name, card_name]) => {
+                if let Some(card) = self.card_by_name(account_id, book_name, card_name).await? {
+                    Ok(DavResponse::new(StatusCode::OK)
+                        .with_header(header::ETAG, etag(&card))
+                        .with_body(VCARD_CONTENT_TYPE, build_vcard(&card.1.inner)))
+                } else {
+                    Ok(DavResponse::new(StatusCode::NOT_FOUND))
+                }
+            }
+            ("PUT", [_, book_name, card_name]) => {
+                self.card_put(req, book_name, card_name, access_token).await
+            }
+            ("DELETE", [_, book_name, card_name]) => {
+                if let Some((document_id, card)) =
+                    self.card_by_name(account_id, book_name, card_name).await?
+                {
+                    if !matches_etag(req, header::IF_MATCH, &card).unwrap_or(true) {
+                        return Ok(DavResponse::new(StatusCode::PRECONDITION_FAILED));
+                    }
+                    let mut changes = ChangeLogBuilder::new();
+                    if self.contact_card_delete(account_id, document_id).await? {
+                        changes.log_delete(Collection::ContactCard, document_id);
+                    }
+                    self.card_commit(account_id, changes).await?;
+                    Ok(DavResponse::new(StatusCode::NO_CONTENT))
+                } else {
+                    Ok(DavResponse::new(StatusCode::NOT_FOUND))
+                }
+            }
+            ("DELETE", [_, book_name]) => {
+                if let Some((document_id, _)) =
+                    self.address_book_by_name(account_id, book_name).await?
+                {
+                    let mut changes = ChangeLogBuilder::new();
+                    match self
+                        .address_book_destroy(account_id, document_id, true, &mut changes)
+                        .await?
+                    {
+                        Ok(_) => {
+                            self.card_commit(account_id, changes).await?;
+                            Ok(DavResponse::new(StatusCode::NO_CONTENT))
+                        }
+                        Err(_) => Ok(DavResponse::new(StatusCode::CONFLICT)),
+                    }
+                } else {
+                    Ok(DavResponse::new(StatusCode::NOT_FOUND))
+                }
+            }
+            ("MKCOL", [_, book_name]) => self.card_mkcol(req, book_name, access_token).await,
+            ("PROPPATCH", [_, book_name]) => {
+                self.card_proppatch(req, book_name, access_token).await
+            }
+            _ => Ok(DavResponse::new(StatusCode::METHOD_NOT_ALLOWED)),
+        }
+    }
+
+    async fn card_propfind(
+        &self,
+        req: &mut HttpRequest,
+        path: &[&str],
+        access_token: &AccessToken,
+    ) -> Result<DavResponse, MethodError> {
+        let depth = Depth::parse(req);
+        let request = match self.dav_read_xml(req, access_token).await {
+            Ok(request) => request,
+            Err(response) => return Ok(response),
+        };
+        let account_id = access_token.primary_id();
+        let home = format!("{CARDDAV_ROOT}/{}/", access_token.name);
+
+        // Obtain the resources to list
+        let mut resources = Vec::new();
+        match path {
+            [] => {
+                resources.push((format!("{CARDDAV_ROOT}/"), Resource::Root));
+                if depth == Depth::One {
+                    resources.push((home, Resource::Home));
+                }
+            }
+            [_] => {
+                resources.push((home.clone(), Resource::Home));
+                if depth == Depth::One {
+                    for (_, dav_name, address_book) in self.address_books(account_id).await? {
+                        resources.push((
+                            format!("{home}{dav_name}/"),
+                            self.address_book_resource(account_id, address_book).await?,
+                        ));
+                    }
+                }
+            }
+            [_, book_name] => {
+                if let Some((document_id, address_book)) =
+                    self.address_book_by_name(account_id, book_name).await?
+                {
+                    resources.push((
+                        format!("{home}{book_name}/"),
+                        self.address_book_resource(account_id, address_book).await?,
+                    ));
+                    if depth == Depth::One {
+                        for card in self.address_book_cards(account_id, document_id).await? {
+                            resources.push((
+                                format!("{home}{book_name}/{}", dav_name(&card.inner)),
+                                Resource::Card { card },
+                            ));
+                        }
+                    }
+                } else {
+                    return Ok(DavResponse::new(StatusCode::NOT_FOUND));
+                }
+            }
+            [_, book_name, card_name] => {
+                if let Some((_, card)) = self.card_by_name(account_id, book_name, card_name).await?
+                {
+                    resources.push((
+                        format!("{home}{book_name}/{card_name}"),
+                        Resource::Card { card },
+                    ));
+                } else {
+                    return Ok(DavResponse::new(StatusCode::NOT_FOUND));
+                }
+            }
+            _ => return Ok(DavResponse::new(StatusCode::NOT_FOUND)),
+        }
+
+        let props = request.as_ref().and_then(requested_props);
+        let mut response = MultiStatus::new();
+        for (href, resource) in &resources {
+            let default_props = resource.default_props();
+            self.card_add_response(
+                &mut response,
+                href,
+                resource,
+                props
+                    .clone()
+                    .unwrap_or_else(|| default_props.iter().collect()),
+                access_token,
+            );
+        }
+
+        Ok(response.into_response())
+    }
+
+    async fn card_report(
+        &self,
+        req: &mut HttpRequest,
+        book_name: &str,
+        access_token: &AccessToken,
+    ) -> Result<DavResponse, MethodError> {
+        let request = match self.dav_read_xml(req, access_token).await {
+            Ok(Some(request)) => request,
+            Ok(None) => return Ok(DavResponse::new(StatusCode::BAD_REQUEST)),
+            Err(response) => return Ok(response),
+        };
+        let account_id = access_token.primary_id();
+        let book_id = if let Some((document_id, _)) =
+            self.address_book_by_name(account_id, book_name).await?
+        {
+            document_id
+        } else {
+            return Ok(DavResponse::new(StatusCode::NOT_FOUND));
+        };
+        let book_href = format!("{CARDDAV_ROOT}/{}/{book_name}/", access_token.name);
+        let default_props = [prop(NS_DAV, "getetag"), prop(NS_CARDDAV, "address-data")];
+        let props = requested_props(&request).unwrap_or_else(|| default_props.iter().collect());
+        let mut response = MultiStatus::new();
+
+        if request.is(NS_CARDDAV, "addressbook-multiget") {
+            for href in request
+                .children
+                .iter()
+                .filter(|child| child.is(NS_DAV, "href"))
+            {
+                let card_name = href.text.rsplit('/').next().unwrap_or_default();
+                let card = if href.text.ends_with(&format!("/{book_name}/{card_name}")) {
+                    self.card_by_name(account_id, book_name, card_name).await?
+                } else {
+                    None
+                };
+                if let Some((_, card)) = card {
+                    self.card_add_response(
+                        &mut response,
+                        &href.text,
+                        &Resource::Card { card },
+                        props.clone(),
+                        access_token,
+                    );
+                } else {
+                    response.add_status(&href.text, StatusCode::NOT_FOUND);
+                }
+            }
+        } else if request.is(NS_CARDDAV, "addressbook-query") {
+            let filter = request.child(NS_CARDDAV, "filter");
+            for card in self.address_book_cards(account_id, book_id).await? {
+                if filter.is_none_or(|filter| matches_filter(filter, &build_vcard(&card.inner))) {
+                    let href = format!("{book_href}{}", dav_name(&card.inner));
+                    self.card_add_response(
+                        &mut response,
+                        &href,
+                        &Resource::Card { card },
+                        props.clone(),
+                        access_token,
+                    );
+                }
+            }
+        } else {
+            return Ok(DavResponse::new(StatusCode::FORBIDDEN));
+        }
+
+        Ok(response.into_response())
+    }
+
+    async fn card_put(
+        &self,
+        req: &mut HttpRequest,
+        book_name: &str,
+        card_name: &str,
+        access_token: &AccessToken,
+    ) -> Result<DavResponse, MethodError> {
+        let account_id = access_token.primary_id();
+        let book_id = if let Some((document_id, _)) =
+            self.address_book_by_name(account_id, book_name).await?
+        {
+            document_id
+        } else {
+            return Ok(DavResponse::new(StatusCode::CONFLICT));
+        };
+        let current = self.card_by_name(account_id, book_name, card_name).await?;
+
+        // Validate preconditions
+        if let Some((_, current)) = &current {
+            if matches_etag(req, header::IF_NONE_MATCH, current).unwrap_or(false)
+                || !matches_etag(req, header::IF_MATCH, current).unwrap_or(true)
+            {
+                return Ok(DavResponse::new(StatusCode::PRECONDITION_FAILED));
+            }
+        } else if req.headers().contains_key(header::IF_MATCH) {
+            return Ok(DavResponse::new(StatusCode::PRECONDITION_FAILED));
+        }
+
+        // Parse vCard
+        let mut card = if let Some(card) =
+            fetch_body(req, self.config.load().contact_max_size, access_token).await
+        {
+            if let Some(card) = std::str::from_utf8(&card).ok().and_then(parse_vcard) {
+                card
+            } else {
+                return Ok(DavResponse::new(StatusCode::BAD_REQUEST));
+            }
+        } else {
+            return Ok(DavResponse::new(StatusCode::PAYLOAD_TOO_LARGE));
+        };
+
+        let mut changes = ChangeLogBuilder::new();
+        let mut batch = BatchBuilder::new();
+        batch
+            .with_account_id(account_id)
+            .with_collection(Collection::ContactCard);
+        let (document_id, status) = if let Some((document_id, current)) = current {
+            // Keep the properties that can't be expressed in vCard
+            for property in [Property::AddressBookIds, Property::DavName] {
+                card.set(property.clone(), current.inner.get(&property).clone());
+            }
+            if card.text_field("uid").is_none() {
+                if let Some(uid) = current.inner.text_field("uid") {
+                    card.set_field("uid", Value::Text(uid.to_string()));
+                }
+            }
+            batch
+                .update_document(document_id)
+                .custom(ContactIndex::update(current, card));
+            match self.store.write(batch.build()).await {
+                Ok(_) => (),
+                Err(store::Error::AssertValueFailed) => {
+                    return Ok(DavResponse::new(StatusCode::PRECONDITION_FAILED));
+                }
+                Err(err) => {
+                    tracing::error!(
+                        event = "error",
+                        context = "card_put",
+                        account_id = account_id,
+                        error = ?err,
+                        "Failed to update card.");
+                    return Err(MethodError::ServerPartialFail);
+                }
+            }
+            changes.log_update(Collection::ContactCard, document_id);
+            (document_id, StatusCode::NO_CONTENT)
+        } else {
+            if card.text_field("uid").is_none() {
+                card.set_field("uid", Value::Text(generate_uid()));
+            }
+            set_address_book_ids(&mut card, vec![book_id]);
+            card.set(Property::DavName, Value::Text(card_name.to_string()));
+            let document_id = self
+                .assign_document_id(account_id, Collection::ContactCard)
+                .await?;
+            batch
+                .create_document(document_id)
+                .custom(ContactIndex::insert(card));
+            self.write_batch(batch).await?;
+            changes.log_insert(Collection::ContactCard, document_id);
+            (document_id, StatusCode::CREATED)
+        };
+        self.card_commit(account_id, changes).await?;
+
+        // Return the new ETag
+        let mut response = DavResponse::new(status);
+        if let Some(card) = self
+            .get_property::<HashedValue<Object<Value>>>(
+                account_id,
+                Collection::ContactCard,
+                document_id,
+                Property::Value,
+            )
+            .await?
+        {
+            response = response.with_header(header::ETAG, format!("\"{}\"", card.hash));
+        }
+        Ok(response)
+    }
+
+    async fn card_mkcol(
+        &self,
+        req: &mut HttpRequest,
+        book_name: &str,
+        access_token: &AccessToken,
+    ) -> Result<DavResponse, MethodError> {
+        let request = match self.dav_read_xml(req, access_token).await {
+            Ok(request) => request,
+            Err(response) => return Ok(response),
+        };
+        let account_id = access_token.primary_id();
+        if self
+            .address_book_by_name(account_id, book_name)
+            .await?
+            .is_some()
+        {
+            return Ok(DavResponse::new(StatusCode::METHOD_NOT_ALLOWED));
+        } else if self
+            .get_document_ids(account_id, Collection::AddressBook)
+            .await?
+            .map_or(0, |ids| ids.len() as usize)
+            >= self.config.load().contact_max_address_books
+        {
+            return Ok(DavResponse::new(StatusCode::INSUFFICIENT_STORAGE));
+        }
+
+        // Extended MKCOL (RFC 5689) may include a display name
+        let mut address_book = Object::with_capacity(3)
+            .with_property(Property::Name, book_name)
+            .with_property(Property::DavName, book_name);
+        for prop in request
+            .as_ref()
+            .and_then(|request| request.child(NS_DAV, "set"))
+            .and_then(|set| set.child(NS_DAV, "prop"))
+            .into_iter()
+            .flat_map(|prop| prop.children.iter())
+        {
+            if let Some((property, value)) = address_book_prop(prop, self) {
+                address_book.set(property, value);
+            }
+        }
+
+        let document_id = self
+            .assign_document_id(account_id, Collection::AddressBook)
+            .await?;
+        let mut batch = BatchBuilder::new();
+        batch
+            .with_account_id(account_id)
+            .with_collection(Collection::AddressBook)
+            .create_document(document_id)
+            .custom(ObjectIndexBuilder::new(SCHEMA).with_changes(address_book));
+        self.write_batch(batch).await?;
+        let mut changes = ChangeLogBuilder::new();
+        changes.log_insert(Collection::AddressBook, document_id);
+        self.card_commit(account_id, changes).await?;
+
+        Ok(DavResponse::new(StatusCode::CREATED))
+    }
+
+    async fn card_proppatch(
+        &self,
+        req: &mut HttpRequest,
+        book_name: &str,
+        access_token: &AccessToken,
+    ) -> Result<DavResponse, MethodError> {
+        let request = match self.dav_read_xml(req, access_token).await {
+            Ok(Some(request)) => request,
+            Ok(None) => return Ok(DavResponse::new(StatusCode::BAD_REQUEST)),
+            Err(response) => return Ok(response),
+        };
+        let account_id = access_token.primary_id();
+        let document_id = if let Some((document_id, _)) =
+            self.address_book_by_name(account_id, book_name).await?
+        {
+            document_id
+        } else {
+            return Ok(DavResponse::new(StatusCode::NOT_FOUND));
+        };
+        let current = self
+            .get_property::<HashedValue<Object<Value>>>(
+                account_id,
+                Collection::AddressBook,
+                document_id,
+                Property::Value,
+            )
+            .await?
+            .ok_or(MethodError::ServerPartialFail)?;
+
+        let mut updated = Vec::new();
+        let mut rejected = Vec::new();
+        let mut changes = Object::with_capacity(2);
+        for action in &request.children {
+            let is_set = action.is(NS_DAV, "set");
+            for prop in action
+                .child(NS_DAV, "prop")
+                .into_iter()
+                .flat_map(|prop| prop.children.iter())
+            {
+                match address_book_prop(prop, self) {
+                    Some((property, value)) => {
+                        changes.set(property, if is_set { value } else { Value::Null });
+                        updated.push((prop, String::new()));
+                    }
+                    None => {
+                        rejected.push((prop, String::new()));
+                    }
+                }
+            }
+        }
+
+        // Changes are applied atomically, so a single rejected property fails the request
+        let mut response = MultiStatus::new();
+        let href = format!("{CARDDAV_ROOT}/{}/{book_name}/", access_token.name);
+        if rejected.is_empty() {
+            let mut batch = BatchBuilder::new();
+            batch
+                .with_account_id(account_id)
+                .with_collection(Collection::AddressBook)
+                .update_document(document_id)
+                .custom(
+                    ObjectIndexBuilder::new(SCHEMA)
+                        .with_current(current)
+                        .with_changes(changes),
+                );
+            if !batch.is_empty() {
+                self.write_batch(batch).await?;
+                let mut changes = ChangeLogBuilder::new();
+                changes.log_update(Collection::AddressBook, document_id);
+                self.card_commit(account_id, changes).await?;
+            }
+            response.add_response(&href, vec![(StatusCode::OK, updated)]);
+        } else {
+            response.add_response(
+                &href,
+                vec![
+                    (StatusCode::FORBIDDEN, rejected),
+                    (StatusCode::FAILED_DEPENDENCY, updated),
+                ],
+            );
+        }
+
+        Ok(response.into_response())
+    }
+
+    fn card_add_response(
+        &self,
+        response: &mut MultiStatus,
+        href: &str,
+        resource: &Resource,
+        props: Vec<&XmlElement>,
+        access_token: &AccessToken,
+    ) {
+        let mut found = Vec::with_capacity(props.len());
+        let mut not_found = Vec::new();
+        for prop in props {
+            if let Some(value) = self.card_prop(resource, prop, access_token) {
+                found.push((prop, value));
+            } else {
+                not_found.push((prop, String::new()));
+            }
+        }
+        response.add_response(
+            href,
+            vec![(StatusCode::OK, found), (StatusCode::NOT_FOUND, not_found)],
+        );
+    }
+
+    fn card_prop(
+        &self,
+        resource: &Resource,
+        prop: &XmlElement,
+        access_token: &AccessToken,
+    ) -> Option<String> {
+        let home = format!(
+            "<D:href>{}</D:href>",
+            escape(&format!("{CARDDAV_ROOT}/{}/", access_token.name))
+        );
+        match (prop.namespace.as_str(), prop.name.as_str(), resource) {
+            (NS_DAV, "current-user-principal", _) => Some(home),
+            (NS_DAV, "principal-URL", Resource::Home)
+            | (NS_CARDDAV, "addressbook-home-set", Resource::Root | Resource::Home) => Some(home),
+            (NS_DAV, "current-user-privilege-set", _) => Some(
+                concat!(
+                    "<D:privilege><D:read/></D:privilege>",
+                    "<D:privilege><D:write/></D:privilege>"
+                )
+                .to_string(),
+            ),
+            (NS_DAV, "resourcetype", resource) => Some(
+                match resource {
+                    Resource::Root => "<D:collection/>",
+                    Resource::Home => "<D:collection/><D:principal/>",
+                    Resource::AddressBook { .. } => "<D:collection/><C:addressbook/>",
+                    Resource::Card { .. } => "",
+                }
+                .to_string(),
+            ),
+            (NS_DAV, "displayname", Resource::Home) => {
+                Some(escape(&access_token.name).into_owned())
+            }
+            (NS_DAV, "displayname", Resource::AddressBook { address_book, .. }) => address_book
+                .get(&Property::Name)
+                .as_string()
+                .map(|name| escape(name).into_owned()),
+            (NS_CARDDAV, "addressbook-description", Resource::AddressBook { address_book, .. }) => {
+                address_book
+                    .get(&Property::Description)
+                    .as_string()
+                    .map(|description| escape(description).into_owned())
+            }
+            (NS_CALENDARSERVER, "getctag", Resource::AddressBook { ctag, .. }) => {
+                Some(escape(ctag).into_owned())
+            }
+            (NS_DAV, "supported-report-set", Resource::AddressBook { .. }) => Some(
+                concat!(
+                    "<D:supported-report><D:report><C:addressbook-multiget/></D:report></D:supported-report>",
+                    "<D:supported-report><D:report><C:addressbook-query/></D:report></D:supported-report>"
+                )
+                .to_string(),
+            ),
+            (NS_CARDDAV, "supported-address-data", Resource::AddressBook { .. }) => Some(
+                "<C:address-data-type content-type=\"text/vcard\" version=\"3.0\"/>".to_string(),
+            ),
+            (NS_CARDDAV, "max-resource-size", Resource::AddressBook { .. }) => {
+                Some(self.config.load().contact_max_size.to_string())
+            }
+            (NS_DAV, "getetag", Resource::Card { card, .. }) => {
+                Some(escape(&format!("\"{}\"", card.hash)).into_owned())
+            }
+            (NS_DAV, "getcontenttype", Resource::Card { .. }) => {
+                Some(VCARD_CONTENT_TYPE.to_string())
+            }
+            (NS_CARDDAV, "address-data", Resource::Card { card, .. }) => {
+                Some(escape(&build_vcard(&card.inner)).into_owned())
+            }
+            _ => None,
+        }
+    }
+
+    async fn card_commit(
+        &self,
+        account_id: u32,
+        changes: ChangeLogBuilder,
+    ) -> Result<(), MethodError> {
+        if !changes.is_empty() {
+            let mut state_change = StateChange::new(account_id);
+            let data_types = [
+                (Collection::AddressBook, DataType::AddressBook),
+                (Collection::ContactCard, DataType::ContactCard),
+            ]
+            .into_iter()
+            .filter(|(collection, _)| changes.changes.contains_key(&u8::from(*collection)))
+            .map(|(_, data_type)| data_type)
+            .collect::<Vec<_>>();
+            let change_id = self.commit_changes(account_id, changes).await?;
+            for data_type in data_types {
+                state_change = state_change.with_change(data_type, change_id);
+            }
+            self.broadcast_state_change(state_change).await;
+        }
+        Ok(())
+    }
+
+    async fn address_books(
+        &self,
+        account_id: u32,
+    ) -> Result<Vec<(u32, String, Object<Value>)>, MethodError> {
+        let mut address_books = Vec::new();
+        for document_id in self
+            .get_document_ids(account_id, Collection::AddressBook)
+            .await?
+            .unwrap_or_default()
+        {
+            if let Some(address_book) = self
+                .get_property::<Object<Value>>(
+                    account_id,
+                    Collection::AddressBook,
+                    document_id,
+                    Property::Value,
+                )
+                .await?
+            {
+                address_books.push((document_id, dav_name(&address_book), address_book));
+            }
+        }
+        Ok(address_books)
+    }
+
+    async fn address_book_resource(
+        &self,
+        account_id: u32,
+        address_book: Object<Value>,
+    ) -> Result<Resource, MethodError> {
+        Ok(Resource::AddressBook {
+            address_book,
+            ctag: self
+                .get_state(account_id, Collection::ContactCard)
+                .await?
+                .to_string(),
+        })
+    }
+
+    async fn address_book_by_name(
+        &self,
+        account_id: u32,
+        name: &str,
+    ) -> Result<Option<(u32, Object<Value>)>, MethodError> {
+        if let Some(document_id) = self
+            .filter(
+                account_id,
+                Collection::AddressBook,
+                vec![Filter::eq(Property::DavName, name)],
+            )
+            .await?
+            .results
+            .min()
+        {
+            Ok(self
+                .get_property::<Object<Value>>(
+                    account_id,
+                    Collection::AddressBook,
+                    document_id,
+                    Property::Value,
+                )
+                .await?
+                .map(|address_book| (document_id, address_book)))
+        } else {
+            Ok(None)
+        }
+    }
+
+    async fn address_book_cards(
+        &self,
+        account_id: u32,
+        address_book_id: u32,
+    ) -> Result<Vec<HashedValue<Object<Value>>>, MethodError> {
+        let mut cards = Vec::new();
+        for document_id in self
+            .filter(
+                account_id,
+                Collection::ContactCard,
+                vec![Filter::eq(Property::AddressBookIds, address_book_id)],
+            )
+            .await?
+            .results
+        {
+            if let Some(card) = self
+                .get_property::<HashedValue<Object<Value>>>(
+                    account_id,
+                    Collection::ContactCard,
+                    document_id,
+                    Property::Value,
+                )
+                .await?
+            {
+                cards.push(card);
+            }
+        }
+        Ok(cards)
+    }
+
+    async fn card_by_name(
+        &self,
+        account_id: u32,
+        book_name: &str,
+        card_name: &str,
+    ) -> Result<Option<(u32, HashedValue<Object<Value>>)>, MethodError> {
+        let address_book_id = if let Some((document_id, _)) =
+            self.address_book_by_name(account_id, book_name).await?
+        {
+            document_id
+        } else {
+            return Ok(None);
+        };
+
+        if let Some(document_id) = self
+            .filter(
+                account_id,
+                Collection::ContactCard,
+                vec![
+                    Filter::eq(Property::AddressBookIds, address_book_id),
+                    Filter::eq(Property::DavName, card_name),
+                ],
+            )
+            .await?
+            .results
+            .min()
+        {
+            Ok(self
+                .get_property::<HashedValue<Object<Value>>>(
+                    account_id,
+                    Collection::ContactCard,
+                    document_id,
+                    Property::Value,
+                )
+                .await?
+                .map(|card| (document_id, card)))
+        } else {
+            Ok(None)
+        }
+    }
+}
+
+impl Resource {
+    fn default_props(&self) -> Vec<XmlElement> {
+        let mut props = vec![
+            prop(NS_DAV, "resourcetype"),
+            prop(NS_DAV, "current-user-principal"),
+            prop(NS_DAV, "current-user-privilege-set"),
+        ];
+        match self {
+            Resource::Root => {
+                props.push(prop(NS_CARDDAV, "addressbook-home-set"));
+            }
+            Resource::Home => {
+                props.push(prop(NS_DAV, "displayname"));
+                props.push(prop(NS_DAV, "principal-URL"));
+                props.push(prop(NS_CARDDAV, "addressbook-home-set"));
+            }
+            Resource::AddressBook { .. } => {
+                props.push(prop(NS_DAV, "displayname"));
+                props.push(prop(NS_DAV, "supported-report-set"));
+                props.push(prop(NS_CALENDARSERVER, "getctag"));
+                props.push(prop(NS_CARDDAV, "addressbook-description"));
+                props.push(prop(NS_CARDDAV, "supported-address-data"));
+                props.push(prop(NS_CARDDAV, "max-resource-size"));
+            }
+            Resource::Card { .. } => {
+                props.push(prop(NS_DAV, "getetag"));
+                props.push(prop(NS_DAV, "getcontenttype"));
+            }
+        }
+        props
+    }
+}
+
+fn address_book_prop(prop: &XmlElement, jmap: &JMAP) -> Option<(Property, Value)> {
+    match (prop.namespace.as_str(), prop.name.as_str()) {
+        (NS_DAV, "displayname") => {
+            let name = prop.text.trim();
+            (!name.is_empty() && name.len() < jmap.config.load().address_book_name_max_len)
+                .then(|| (Property::Name, Value::Text(name.to_string())))
+        }
+        (NS_CARDDAV, "addressbook-description") => Some((
+            Property::Description,
+            Value::Text(prop.text.trim().to_string()),
+        )),
+        _ => None,
+    }
+}
+
+fn matches_filter(filter: &XmlElement, vcard: &str) -> bool {
+    let mut results = filter
+        .children
+        .iter()
+        .filter(|child| child.is(NS_CARDDAV, "prop-filter"))
+        .map(|prop_filter| matches_prop_filter(prop_filter, vcard))
+        .peekable();
+    if results.peek().is_none() {
+        true
+    } else if filter.attribute("test") == Some("allof") {
+        results.all(|result| result)
+    } else {
+        results.any(|result| result)
+    }
+}
+
+fn matches_prop_filter(filter: &XmlElement, vcard: &str) -> bool {
+    let values = vcard_property_values(vcard, filter.attribute("name").unwrap_or_default());
+    if filter.child(NS_CARDDAV, "is-not-defined").is_some() {
+        return values.is_empty();
+    }
+    let mut results = filter
+        .children
+        .iter()
+        .filter(|child| child.is(NS_CARDDAV, "text-match"))
+        .map(|text_match| {
+            let needle = text_match.text.to_lowercase();
+            let is_match = values.iter().any(|value| {
+                let value = value.to_lowercase();
+                match text_match.attribute("match-type").unwrap_or("contains") {
+                    "equals" => value == needle,
+                    "starts-with" => value.starts_with(&needle),
+                    "ends-with" => value.ends_with(&needle),
+                    _ => value.contains(&needle),
+                }
+            });
+            is_match != (text_match.attribute("negate-condition") == Some("yes"))
+        })
+        .peekable();
+    if results.peek().is_none() {
+        !values.is_empty()
+    } else if filter.attribute("test") == Some("allof") {
+        results.all(|result| result)
+    } else {
+        results.any(|result| result)
+    }
+}
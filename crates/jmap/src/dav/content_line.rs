@@ -0,0 +1,215 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+// Content lines as defined by vCard (RFC 6350) and iCalendar (RFC 5545)
+
+pub struct ContentLine {
+    pub name: String,
+    pub params: Vec<(String, Vec<String>)>,
+    pub value: String,
+}
+
+impl ContentLine {
+    pub fn param(&self, name: &str) -> Option<&str> {
+        self.params
+            .iter()
+            .find(|(key, _)| key == name)
+            .and_then(|(_, values)| values.first())
+            .map(|value| value.as_str())
+    }
+
+    pub fn types(&self) -> impl Iterator<Item = String> + '_ {
+        self.params
+            .iter()
+            .filter(|(key, _)| key == "TYPE")
+            .flat_map(|(_, values)| values.iter())
+            .map(|value| value.to_lowercase())
+    }
+}
+
+pub fn unfold(data: &str) -> Vec<String> {
+    let mut lines: Vec<String> = Vec::new();
+    for line in data.split('\n') {
+        let line = line.strip_suffix('\r').unwrap_or(line);
+        if let Some(continuation) = line.strip_prefix([' ', '\t']) {
+            if let Some(last) = lines.last_mut() {
+                last.push_str(continuation);
+            }
+        } else if !line.is_empty() {
+            lines.push(line.to_string());
+        }
+    }
+    lines
+}
+
+pub fn parse_line(line: &str) -> Option<ContentLine> {
+    let mut in_quote = false;
+    let mut colon = None;
+    for (pos, ch) in line.char_indices() {
+        match ch {
+            '"' => in_quote = !in_quote,
+            ':' if !in_quote => {
+                colon = Some(pos);
+                break;
+            }
+            _ => (),
+        }
+    }
+    let colon = colon?;
+    let mut parts = split_quoted(&line[..colon], ';').into_iter();
+    let name = parts.next()?;
+    let name = name
+        .rsplit_once('.')
+        .map_or(name.as_str(), |(_, name)| name)
+        .to_ascii_uppercase();
+    let params = parts
+        .map(|part| {
+            let (key, values) = part.split_once('=').unwrap_or(("TYPE", part.as_str()));
+            (
+                key.to_ascii_uppercase(),
+                split_quoted(values, ',')
+                    .into_iter()
+                    .map(|value| value.trim_matches('"').to_string())
+                    .collect(),
+            )
+        })
+        .collect();
+
+    Some(ContentLine {
+        name,
+        params,
+        value: line[colon + 1..].to_string(),
+    })
+}
+
+fn split_quoted(value: &str, separator: char) -> Vec<String> {
+    let mut parts = Vec::new();
+    let mut part = String::new();
+    let mut in_quote = false;
+    for ch in value.chars() {
+        if ch == '"' {
+            in_quote = !in_quote;
+            part.push(ch);
+        } else if ch == separator && !in_quote {
+            parts.push(std::mem::take(&mut part));
+        } else {
+            part.push(ch);
+        }
+    }
+    parts.push(part);
+    parts
+}
+
+pub fn split_escaped(value: &str, separator: char) -> Vec<String> {
+    let mut parts = Vec::new();
+    let mut part = String::new();
+    let mut chars = value.chars();
+    while let Some(ch) = chars.next() {
+        match ch {
+            '\\' => match chars.next() {
+                Some('n' | 'N') => part.push('\n'),
+                Some(ch) => part.push(ch),
+                None => (),
+            },
+            ch if ch == separator => {
+                parts.push(std::mem::take(&mut part));
+            }
+            ch => part.push(ch),
+        }
+    }
+    parts.push(part);
+    parts
+}
+
+pub fn unescape(value: &str) -> String {
+    split_escaped(value, '\0').pop().unwrap_or_default()
+}
+
+pub fn split_list(value: &str) -> Vec<String> {
+    split_escaped(value, ',')
+        .into_iter()
+        .map(|value| value.trim().to_string())
+        .filter(|value| !value.is_empty())
+        .collect()
+}
+
+pub fn split_structured(value: &str) -> Vec<Vec<String>> {
+    let mut components = Vec::new();
+    let mut component = String::new();
+    let mut chars = value.chars();
+    while let Some(ch) = chars.next() {
+        match ch {
+            '\\' => {
+                // Keep escapes so list separators can be told apart
+                component.push(ch);
+                if let Some(ch) = chars.next() {
+                    component.push(ch);
+                }
+            }
+            ';' => {
+                components.push(split_list(&std::mem::take(&mut component)));
+            }
+            ch => component.push(ch),
+        }
+    }
+    components.push(split_list(&component));
+    components
+}
+
+pub fn join_structured(values: Vec<Vec<String>>) -> String {
+    values
+        .into_iter()
+        .map(|values| values.join(","))
+        .collect::<Vec<_>>()
+        .join(";")
+}
+
+pub fn escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for ch in value.chars() {
+        match ch {
+            '\\' | ',' | ';' => {
+                escaped.push('\\');
+                escaped.push(ch);
+            }
+            '\n' => escaped.push_str("\\n"),
+            '\r' => (),
+            ch => escaped.push(ch),
+        }
+    }
+    escaped
+}
+
+pub fn fold_line(out: &mut String, line: &str) {
+    // Fold lines longer than 75 octets
+    let mut line_len = 0;
+    for ch in line.chars() {
+        if line_len + ch.len_utf8() > 75 {
+            out.push_str("\r\n ");
+            line_len = 1;
+        }
+        out.push(ch);
+        line_len += ch.len_utf8();
+    }
+    out.push_str("\r\n");
+}
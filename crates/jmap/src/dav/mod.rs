@@ -0,0 +1,380 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::borrow::Cow;
+
+use http_body_util::{BodyExt, Full};
+use hyper::{body::Bytes, header, header::HeaderName, StatusCode};
+use jmap_proto::{
+    object::Object,
+    types::{property::Property, value::Value},
+};
+use quick_xml::{
+    events::{BytesStart, Event},
+    name::{Namespace, ResolveResult},
+    NsReader,
+};
+
+use store::write::assert::HashedValue;
+
+use crate::{
+    api::{
+        http::{fetch_body, ToHttpResponse},
+        HttpRequest, HttpResponse,
+    },
+    auth::AccessToken,
+    JMAP,
+};
+
+pub mod cal;
+pub mod card;
+pub mod content_line;
+
+pub const NS_DAV: &str = "DAV:";
+pub const NS_CARDDAV: &str = "urn:ietf:params:xml:ns:carddav";
+pub const NS_CALDAV: &str = "urn:ietf:params:xml:ns:caldav";
+pub const NS_CALENDARSERVER: &str = "http://calendarserver.org/ns/";
+
+pub struct DavResponse {
+    status: StatusCode,
+    headers: Vec<(header::HeaderName, String)>,
+    content_type: Option<&'static str>,
+    body: Vec<u8>,
+}
+
+#[derive(Debug, Default)]
+pub struct XmlElement {
+    pub namespace: String,
+    pub name: String,
+    pub attributes: Vec<(String, String)>,
+    pub children: Vec<XmlElement>,
+    pub text: String,
+}
+
+pub struct MultiStatus {
+    body: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Depth {
+    Zero,
+    One,
+}
+
+impl DavResponse {
+    pub fn new(status: StatusCode) -> Self {
+        DavResponse {
+            status,
+            headers: Vec::new(),
+            content_type: None,
+            body: Vec::new(),
+        }
+    }
+
+    pub fn with_header(mut self, name: header::HeaderName, value: impl Into<String>) -> Self {
+        self.headers.push((name, value.into()));
+        self
+    }
+
+    pub fn with_body(mut self, content_type: &'static str, body: impl Into<Vec<u8>>) -> Self {
+        self.content_type = Some(content_type);
+        self.body = body.into();
+        self
+    }
+
+    pub fn unauthorized() -> Self {
+        DavResponse::new(StatusCode::UNAUTHORIZED).with_header(
+            header::WWW_AUTHENTICATE,
+            "Basic realm=\"Stalwart Mail Server\"",
+        )
+    }
+
+    pub fn redirect(location: &str) -> Self {
+        DavResponse::new(StatusCode::MOVED_PERMANENTLY).with_header(header::LOCATION, location)
+    }
+}
+
+impl ToHttpResponse for DavResponse {
+    fn into_http_response(self) -> HttpResponse {
+        let mut response = hyper::Response::builder().status(self.status);
+        for (name, value) in self.headers {
+            response = response.header(name, value);
+        }
+        if let Some(content_type) = self.content_type {
+            response = response.header(header::CONTENT_TYPE, content_type);
+        }
+        response
+            .body(
+                Full::new(Bytes::from(self.body))
+                    .map_err(|never| match never {})
+                    .boxed(),
+            )
+            .unwrap()
+    }
+}
+
+impl XmlElement {
+    pub fn parse(bytes: &[u8]) -> Option<XmlElement> {
+        let mut reader = NsReader::from_reader(bytes);
+        reader.trim_text(true);
+        let mut stack: Vec<XmlElement> = Vec::new();
+
+        loop {
+            let element = match reader.read_resolved_event().ok()? {
+                (namespace, Event::Start(start)) => {
+                    stack.push(XmlElement::new(namespace, &start));
+                    continue;
+                }
+                (namespace, Event::Empty(start)) => XmlElement::new(namespace, &start),
+                (_, Event::End(_)) => stack.pop()?,
+                (_, Event::Text(text)) => {
+                    if let Some(element) = stack.last_mut() {
+                        element.text.push_str(&text.unescape().ok()?);
+                    }
+                    continue;
+                }
+                (_, Event::CData(text)) => {
+                    if let Some(element) = stack.last_mut() {
+                        element.text.push_str(&String::from_utf8_lossy(&text));
+                    }
+                    continue;
+                }
+                (_, Event::Eof) => return None,
+                _ => continue,
+            };
+
+            if let Some(parent) = stack.last_mut() {
+                parent.children.push(element);
+            } else {
+                return Some(element);
+            }
+        }
+    }
+
+    fn new(namespace: ResolveResult, start: &BytesStart) -> Self {
+        XmlElement {
+            namespace: match namespace {
+                ResolveResult::Bound(Namespace(namespace)) => {
+                    String::from_utf8_lossy(namespace).into_owned()
+                }
+                _ => String::new(),
+            },
+            name: String::from_utf8_lossy(start.local_name().as_ref()).into_owned(),
+            attributes: start
+                .attributes()
+                .flatten()
+                .filter_map(|attribute| {
+                    Some((
+                        String::from_utf8_lossy(attribute.key.local_name().as_ref()).into_owned(),
+                        attribute.unescape_value().ok()?.into_owned(),
+                    ))
+                })
+                .collect(),
+            children: Vec::new(),
+            text: String::new(),
+        }
+    }
+
+    pub fn is(&self, namespace: &str, name: &str) -> bool {
+        self.namespace == namespace && self.name == name
+    }
+
+    pub fn child(&self, namespace: &str, name: &str) -> Option<&XmlElement> {
+        self.children.iter().find(|child| child.is(namespace, name))
+    }
+
+    pub fn attribute(&self, name: &str) -> Option<&str> {
+        self.attributes
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+impl MultiStatus {
+    pub fn new() -> Self {
+        let mut body = String::with_capacity(1024);
+        body.push_str(concat!(
+            "<?xml version=\"1.0\" encoding=\"utf-8\"?>",
+            "<D:multistatus xmlns:D=\"DAV:\" ",
+            "xmlns:C=\"urn:ietf:params:xml:ns:carddav\" ",
+            "xmlns:CAL=\"urn:ietf:params:xml:ns:caldav\" ",
+            "xmlns:CS=\"http://calendarserver.org/ns/\">"
+        ));
+        MultiStatus { body }
+    }
+
+    pub fn add_response(
+        &mut self,
+        href: &str,
+        propstats: Vec<(StatusCode, Vec<(&XmlElement, String)>)>,
+    ) {
+        self.body.push_str("<D:response><D:href>");
+        self.body.push_str(&escape(href));
+        self.body.push_str("</D:href>");
+        for (status, props) in propstats {
+            if !props.is_empty() {
+                self.body.push_str("<D:propstat><D:prop>");
+                for (prop, value) in props {
+                    write_prop(&mut self.body, prop, &value);
+                }
+                self.body.push_str("</D:prop>");
+                self.push_status(status);
+                self.body.push_str("</D:propstat>");
+            }
+        }
+        self.body.push_str("</D:response>");
+    }
+
+    pub fn add_status(&mut self, href: &str, status: StatusCode) {
+        self.body.push_str("<D:response><D:href>");
+        self.body.push_str(&escape(href));
+        self.body.push_str("</D:href>");
+        self.push_status(status);
+        self.body.push_str("</D:response>");
+    }
+
+    fn push_status(&mut self, status: StatusCode) {
+        self.body.push_str("<D:status>HTTP/1.1 ");
+        self.body.push_str(status.as_str());
+        self.body.push(' ');
+        self.body
+            .push_str(status.canonical_reason().unwrap_or_default());
+        self.body.push_str("</D:status>");
+    }
+
+    pub fn into_response(mut self) -> DavResponse {
+        self.body.push_str("</D:multistatus>");
+        DavResponse::new(StatusCode::MULTI_STATUS)
+            .with_body("application/xml; charset=utf-8", self.body)
+    }
+}
+
+impl Default for MultiStatus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl JMAP {
+    pub async fn dav_read_xml(
+        &self,
+        req: &mut HttpRequest,
+        access_token: &AccessToken,
+    ) -> Result<Option<XmlElement>, DavResponse> {
+        match fetch_body(req, self.config.load().request_max_size, access_token).await {
+            Some(body) if body.iter().all(|ch| ch.is_ascii_whitespace()) => Ok(None),
+            Some(body) => XmlElement::parse(&body)
+                .map(Some)
+                .ok_or_else(|| DavResponse::new(StatusCode::BAD_REQUEST)),
+            None => Err(DavResponse::new(StatusCode::PAYLOAD_TOO_LARGE)),
+        }
+    }
+}
+
+impl Depth {
+    pub fn parse(req: &HttpRequest) -> Self {
+        match req.headers().get("Depth").and_then(|h| h.to_str().ok()) {
+            Some("0") => Depth::Zero,
+            _ => Depth::One,
+        }
+    }
+}
+
+// Returns the properties requested by a PROPFIND or REPORT,
+// or None when all properties were requested.
+pub fn requested_props(request: &XmlElement) -> Option<Vec<&XmlElement>> {
+    request
+        .child(NS_DAV, "prop")
+        .map(|prop| prop.children.iter().collect())
+}
+
+pub fn prop(namespace: &str, name: &str) -> XmlElement {
+    XmlElement {
+        namespace: namespace.to_string(),
+        name: name.to_string(),
+        ..Default::default()
+    }
+}
+
+pub fn dav_name(object: &Object<Value>) -> String {
+    object
+        .get(&Property::DavName)
+        .as_string()
+        .unwrap_or_default()
+        .to_string()
+}
+
+pub fn etag((_, object): &(u32, HashedValue<Object<Value>>)) -> String {
+    format!("\"{}\"", object.hash)
+}
+
+// Returns whether a conditional header matches the resource's ETag, or None if it is missing.
+pub fn matches_etag(
+    req: &HttpRequest,
+    header: HeaderName,
+    object: &HashedValue<Object<Value>>,
+) -> Option<bool> {
+    let value = req.headers().get(header)?.to_str().ok()?;
+    let etag = format!("\"{}\"", object.hash);
+    Some(
+        value
+            .split(',')
+            .map(|value| value.trim())
+            .any(|value| value == "*" || value == etag),
+    )
+}
+
+pub fn escape(text: &str) -> Cow<'_, str> {
+    quick_xml::escape::escape(text)
+}
+
+fn write_prop(xml: &mut String, prop: &XmlElement, value: &str) {
+    let (prefix, xmlns) = match prop.namespace.as_str() {
+        NS_DAV => ("D", None),
+        NS_CARDDAV => ("C", None),
+        NS_CALDAV => ("CAL", None),
+        NS_CALENDARSERVER => ("CS", None),
+        namespace => ("X", Some(namespace)),
+    };
+    xml.push('<');
+    xml.push_str(prefix);
+    xml.push(':');
+    xml.push_str(&prop.name);
+    if let Some(xmlns) = xmlns {
+        xml.push_str(" xmlns:X=\"");
+        xml.push_str(&escape(xmlns));
+        xml.push('"');
+    }
+    if value.is_empty() {
+        xml.push_str("/>");
+    } else {
+        xml.push('>');
+        xml.push_str(value);
+        xml.push_str("</");
+        xml.push_str(prefix);
+        xml.push(':');
+        xml.push_str(&prop.name);
+        xml.push('>');
+    }
+}
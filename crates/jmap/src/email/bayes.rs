@@ -0,0 +1,295 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use jmap_proto::types::{collection::Collection, keyword::Keyword, property::Property};
+use mail_parser::{parsers::fields::thread::thread_name, Message, MessageParser, MimeHeaders};
+use nlp::bayes::BayesClassifier;
+use store::write::{BatchBuilder, ValueClass, F_VALUE};
+
+use crate::{
+    mailbox::{UidMailbox, JUNK_ID, TRASH_ID},
+    services::housekeeper::Event,
+    Bincode, JMAP,
+};
+
+use super::{metadata::MessageMetadata, set::TagManager};
+
+const CLASS_HAM: u32 = 0;
+const CLASS_SPAM: u32 = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SpamTraining {
+    pub is_spam: bool,
+}
+
+impl SpamTraining {
+    /// Determines whether a change in the mailboxes or keywords of a message
+    /// is a Junk/Not Junk action that should train the Bayes classifier.
+    ///
+    /// A message is learned as spam when it is moved into the Junk mailbox or
+    /// flagged as `$junk`, and as ham when it is moved out of Junk into any
+    /// folder other than Trash or flagged as `$notjunk`. The class a message
+    /// was last trained as is stored with it, so repeating an action has no
+    /// effect and switching classes reverts the earlier training.
+    pub fn from_changes(
+        mailboxes: Option<&TagManager<UidMailbox>>,
+        keywords: Option<&TagManager<Keyword>>,
+    ) -> Option<Self> {
+        let mut is_spam = false;
+        let mut is_ham = false;
+
+        if let Some(keywords) = keywords {
+            is_spam = keywords.added().contains(&Keyword::Junk);
+            is_ham = keywords.added().contains(&Keyword::NotJunk);
+        }
+
+        if let Some(mailboxes) = mailboxes {
+            let junk_id = UidMailbox::from(JUNK_ID);
+            if mailboxes.added().contains(&junk_id) {
+                is_spam = true;
+            } else if mailboxes.removed().contains(&junk_id)
+                && mailboxes
+                    .current()
+                    .iter()
+                    .any(|mailbox| mailbox.mailbox_id != TRASH_ID)
+            {
+                is_ham = true;
+            }
+        }
+
+        if is_spam != is_ham {
+            Some(SpamTraining { is_spam })
+        } else {
+            None
+        }
+    }
+
+    fn class(&self) -> u32 {
+        if self.is_spam {
+            CLASS_SPAM
+        } else {
+            CLASS_HAM
+        }
+    }
+}
+
+impl JMAP {
+    pub async fn schedule_spam_training(
+        &self,
+        account_id: u32,
+        document_id: u32,
+        training: SpamTraining,
+    ) {
+        if !self.config.load().spam_learn {
+            return;
+        }
+
+        if let Err(err) = self
+            .housekeeper_tx
+            .send(Event::SpamTrain {
+                account_id,
+                document_id,
+                training,
+            })
+            .await
+        {
+            tracing::warn!("Failed to send spam training event to housekeeper: {}", err);
+        }
+    }
+
+    pub async fn spam_train(&self, account_id: u32, document_id: u32, training: SpamTraining) {
+        if !self.config.load().spam_learn {
+            return;
+        }
+
+        // Skip messages that were already trained as this class
+        let class = training.class();
+        let previous_class = match self
+            .get_property::<u32>(
+                account_id,
+                Collection::Email,
+                document_id,
+                &Property::BayesClass,
+            )
+            .await
+        {
+            Ok(previous_class) if previous_class != Some(class) => previous_class,
+            _ => return,
+        };
+
+        // Fetch message
+        let raw_message = match self
+            .get_property::<Bincode<MessageMetadata>>(
+                account_id,
+                Collection::Email,
+                document_id,
+                &Property::BodyStructure,
+            )
+            .await
+        {
+            Ok(Some(metadata)) => {
+                match self.get_blob(&metadata.inner.blob_hash, 0..u32::MAX).await {
+                    Ok(Some(raw_message)) => raw_message,
+                    _ => return,
+                }
+            }
+            _ => return,
+        };
+        let text = match MessageParser::default()
+            .parse(&raw_message)
+            .and_then(|message| spam_text(&message))
+        {
+            Some(text) => text,
+            None => return,
+        };
+
+        // Record the new class, unless another task trained the message in the meantime
+        let mut batch = BatchBuilder::new();
+        batch
+            .with_account_id(account_id)
+            .with_collection(Collection::Email)
+            .update_document(document_id);
+        if let Some(previous_class) = previous_class {
+            batch.assert_value(
+                ValueClass::Property(Property::BayesClass.into()),
+                previous_class,
+            );
+        } else {
+            batch.assert_value(ValueClass::Property(Property::BayesClass.into()), ());
+        }
+        batch.value(Property::BayesClass, class, F_VALUE);
+        match self.store.write(batch.build()).await {
+            Ok(_) => (),
+            Err(store::Error::AssertValueFailed) => return,
+            Err(err) => {
+                tracing::warn!(
+                    context = "bayes",
+                    event = "error",
+                    account_id = account_id,
+                    document_id = document_id,
+                    reason = ?err,
+                    "Failed to store Bayes training class."
+                );
+                return;
+            }
+        }
+
+        // Train global and personal models, reverting any previous training
+        let smtp = self.smtp.load_full();
+        let store = &smtp.queue.config.lookup_store;
+        let mut models = vec![None];
+        if self.config.load().spam_account_model {
+            models.push(Some(account_id));
+        }
+        for model in models {
+            if previous_class.is_some() {
+                if let Err(err) = smtp
+                    .bayes_train(store, model, &text, !training.is_spam, false)
+                    .await
+                {
+                    tracing::warn!(
+                        context = "bayes",
+                        event = "error",
+                        account_id = account_id,
+                        document_id = document_id,
+                        reason = %err,
+                        "Failed to untrain Bayes model."
+                    );
+                    return;
+                }
+            }
+            if let Err(err) = smtp
+                .bayes_train(store, model, &text, training.is_spam, true)
+                .await
+            {
+                tracing::warn!(
+                    context = "bayes",
+                    event = "error",
+                    account_id = account_id,
+                    document_id = document_id,
+                    reason = %err,
+                    "Failed to train Bayes model."
+                );
+                return;
+            }
+        }
+    }
+
+    pub async fn spam_classify(&self, account_id: u32, message: &Message<'_>) -> bool {
+        if let Some(text) = spam_text(message) {
+            let smtp = self.smtp.load_full();
+            match smtp
+                .bayes_classify(
+                    &smtp.queue.config.lookup_store,
+                    account_id,
+                    &text,
+                    &BayesClassifier::default(),
+                )
+                .await
+            {
+                Ok(Some(score)) => {
+                    tracing::debug!(
+                        context = "bayes",
+                        event = "classify",
+                        account_id = account_id,
+                        score = score,
+                    );
+                    return score >= self.config.load().spam_threshold;
+                }
+                Ok(None) => (),
+                Err(err) => {
+                    tracing::warn!(
+                        context = "bayes",
+                        event = "error",
+                        account_id = account_id,
+                        reason = %err,
+                        "Failed to classify message."
+                    );
+                }
+            }
+        }
+
+        false
+    }
+}
+
+fn spam_text(message: &Message<'_>) -> Option<String> {
+    if message
+        .root_part()
+        .is_content_type("multipart", "encrypted")
+    {
+        // Encrypted messages can't be used for training
+        return None;
+    }
+
+    let mut text = thread_name(message.subject().unwrap_or_default()).to_string();
+    if let Some(body) = message.body_text(0) {
+        text.push_str(body.as_ref());
+    }
+
+    if !text.is_empty() {
+        Some(text)
+    } else {
+        None
+    }
+}
@@ -310,8 +310,9 @@ impl JMAP {
         };
 
         // Check quota
-        if account_quota > 0
-            && metadata.size as i64 + self.get_used_quota(account_id).await? > account_quota
+        if (account_quota > 0
+            && metadata.size as i64 + self.get_used_quota(account_id).await? > account_quota)
+            || !self.has_message_quota(account_id).await?
         {
             return Ok(Err(SetError::over_quota()));
         }
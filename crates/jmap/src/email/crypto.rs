@@ -53,6 +53,7 @@ use store::{
     write::{BatchBuilder, ToBitmaps, F_CLEAR, F_VALUE},
     Deserialize, Serialize,
 };
+use utils::config::ServerProtocol;
 
 const CRYPT_HTML_HEADER: &str = include_str!("../../../../resources/htx/crypto_header.htx");
 const CRYPT_HTML_FOOTER: &str = include_str!("../../../../resources/htx/crypto_footer.htx");
@@ -686,8 +687,9 @@ impl JMAP {
             }
 
             // Authenticate
-            let token = if let AuthResult::Success(token) =
-                self.authenticate_plain(email, password, remote_addr).await
+            let token = if let AuthResult::Success(token) = self
+                .authenticate_plain(email, password, remote_addr, ServerProtocol::Http)
+                .await
             {
                 token
             } else {
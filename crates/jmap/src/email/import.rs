@@ -141,7 +141,9 @@ impl JMAP {
                     keywords: email.keywords,
                     received_at: email.received_at.map(|r| r.into()),
                     skip_duplicates: true,
-                    encrypt: self.config.encrypt && self.config.encrypt_append,
+                    encrypt: self.config.load().encrypt && self.config.load().encrypt_append,
+                    authenticated_sender: None,
+                    is_delivery: false,
                 })
                 .await
             {
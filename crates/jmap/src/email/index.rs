@@ -415,8 +415,10 @@ impl<'x> IntoOperations for EmailIndexBuilder<'x> {
             batch.value(Property::BodyStructure, &self.inner, F_VALUE);
             0
         } else {
-            // Delete metadata
-            batch.value(Property::BodyStructure, (), F_VALUE | F_CLEAR);
+            // Delete metadata and Bayes training class
+            batch
+                .value(Property::BodyStructure, (), F_VALUE | F_CLEAR)
+                .value(Property::BayesClass, (), F_VALUE | F_CLEAR);
             F_CLEAR
         };
         let metadata = &self.inner.inner;
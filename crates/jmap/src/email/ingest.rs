@@ -76,6 +76,8 @@ pub struct IngestEmail<'x> {
     pub received_at: Option<u64>,
     pub skip_duplicates: bool,
     pub encrypt: bool,
+    pub authenticated_sender: Option<&'x str>,
+    pub is_delivery: bool,
 }
 
 const MAX_RETRIES: u32 = 10;
@@ -98,6 +100,13 @@ impl JMAP {
         {
             return Err(IngestError::OverQuota);
         }
+        if !self
+            .has_message_quota(params.account_id)
+            .await
+            .map_err(|_| IngestError::Temporary)?
+        {
+            return Err(IngestError::OverQuota);
+        }
 
         // Parse message
         let mut raw_message = Cow::from(params.raw_message);
@@ -107,7 +116,7 @@ impl JMAP {
         })?;
 
         // Check for Spam headers
-        if let Some((header_name, header_value)) = &self.config.spam_header {
+        if let Some((header_name, header_value)) = &self.config.load().spam_header {
             if params.mailbox_ids == [INBOX_ID]
                 && message.root_part().headers().iter().any(|header| {
                     &header.name == header_name
@@ -121,6 +130,15 @@ impl JMAP {
             }
         }
 
+        // Classify delivered messages using the account's personal Bayes model
+        if self.config.load().spam_account_model
+            && params.is_delivery
+            && params.mailbox_ids == [INBOX_ID]
+            && self.spam_classify(params.account_id, &message).await
+        {
+            params.mailbox_ids[0] = JUNK_ID;
+        }
+
         // Obtain message references and thread name
         let thread_id = {
             let mut references = Vec::with_capacity(5);
@@ -205,6 +223,14 @@ impl JMAP {
             }
         };
 
+        // Apply scheduling messages (RFC 6047) to the recipient's calendar
+        if let Some(sender) = params
+            .authenticated_sender
+            .filter(|_| params.is_delivery && params.mailbox_ids != [JUNK_ID])
+        {
+            self.itip_ingest(params.account_id, sender, &message).await;
+        }
+
         // Encrypt message
         if params.encrypt && !message.is_encrypted() {
             if let Some(encrypt_params) = self
@@ -21,6 +21,7 @@
  * for more details.
 */
 
+pub mod bayes;
 pub mod body;
 pub mod copy;
 pub mod crypto;
@@ -65,6 +65,7 @@ use crate::{
 };
 
 use super::{
+    bayes::SpamTraining,
     headers::{BuildHeader, ValueToHeader},
     index::EmailIndexBuilder,
     ingest::IngestEmail,
@@ -600,8 +601,9 @@ impl JMAP {
                                 // Check attachment sizes
                                 if !is_multipart {
                                     size_attachments += parts.last().unwrap().size();
-                                    if self.config.mail_attachments_max_size > 0
-                                        && size_attachments > self.config.mail_attachments_max_size
+                                    if self.config.load().mail_attachments_max_size > 0
+                                        && size_attachments
+                                            > self.config.load().mail_attachments_max_size
                                     {
                                         response.not_created.append(
                                             id,
@@ -609,7 +611,7 @@ impl JMAP {
                                                 .with_property(property)
                                                 .with_description(format!(
                                                     "Message exceeds maximum size of {} bytes.",
-                                                    self.config.mail_attachments_max_size
+                                                    self.config.load().mail_attachments_max_size
                                                 )),
                                         );
                                         continue 'create;
@@ -742,7 +744,9 @@ impl JMAP {
                     keywords,
                     received_at,
                     skip_duplicates: false,
-                    encrypt: self.config.encrypt && self.config.encrypt_append,
+                    encrypt: self.config.load().encrypt && self.config.load().encrypt_append,
+                    authenticated_sender: None,
+                    is_delivery: false,
                 })
                 .await
             {
@@ -859,6 +863,9 @@ impl JMAP {
                 continue 'update;
             }
 
+            // Check for Junk/Not Junk actions
+            let spam_training = SpamTraining::from_changes(Some(&mailboxes), Some(&keywords));
+
             // Log change
             batch.update_document(document_id);
             let mut changed_mailboxes = AHashSet::new();
@@ -971,6 +978,12 @@ impl JMAP {
             if !batch.is_empty() {
                 match self.store.write(batch.build()).await {
                     Ok(_) => {
+                        // Train spam classifier
+                        if let Some(spam_training) = spam_training {
+                            self.schedule_spam_training(account_id, document_id, spam_training)
+                                .await;
+                        }
+
                         // Add to updated list
                         response.updated.append(id, None);
                     }
@@ -42,7 +42,7 @@ impl JMAP {
         &self,
         mut request: GetRequest<RequestArguments>,
     ) -> Result<GetResponse, MethodError> {
-        let ids = request.unwrap_ids(self.config.get_max_objects)?;
+        let ids = request.unwrap_ids(self.config.load().get_max_objects)?;
         let properties = request.unwrap_properties(&[
             Property::Id,
             Property::Name,
@@ -60,7 +60,7 @@ impl JMAP {
         } else {
             identity_ids
                 .iter()
-                .take(self.config.get_max_objects)
+                .take(self.config.load().get_max_objects)
                 .map(Into::into)
                 .collect::<Vec<_>>()
         };
@@ -130,6 +130,7 @@ impl JMAP {
         // Obtain principal
         let principal = self
             .directory
+            .load()
             .query(QueryBy::Id(account_id), false)
             .await
             .map_err(|err| {
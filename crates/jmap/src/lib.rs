@@ -22,13 +22,18 @@
 */
 
 use std::{
-    collections::hash_map::RandomState, fmt::Display, net::IpAddr, sync::Arc, time::Duration,
+    collections::hash_map::RandomState,
+    fmt::Display,
+    net::IpAddr,
+    sync::{Arc, OnceLock},
+    time::Duration,
 };
 
 use ::sieve::{Compiler, Runtime};
-use api::session::BaseCapabilities;
+use api::{session::BaseCapabilities, settings::Settings};
+use arc_swap::ArcSwap;
 use auth::{
-    oauth::OAuthCode,
+    oauth::{oidc::OidcSigningKey, OAuthCode},
     rate_limit::{AnonymousLimiter, AuthenticatedLimiter},
     AccessToken,
 };
@@ -61,18 +66,25 @@ use tokio::sync::mpsc;
 use utils::{
     config::{Rate, Servers},
     ipc::DeliveryEvent,
+    listener::limiter::RateLimiter,
     map::ttl_dashmap::{TtlDashMap, TtlMap},
     snowflake::SnowflakeIdGenerator,
     UnwrapFailure,
 };
 
+pub mod address_book;
 pub mod api;
 pub mod auth;
 pub mod blob;
+pub mod calendar;
+pub mod calendar_event;
 pub mod changes;
+pub mod contact;
+pub mod dav;
 pub mod email;
 pub mod identity;
 pub mod mailbox;
+pub mod mdn;
 pub mod principal;
 pub mod push;
 pub mod quota;
@@ -89,8 +101,8 @@ pub struct JMAP {
     pub store: Store,
     pub blob_store: BlobStore,
     pub fts_store: FtsStore,
-    pub config: Config,
-    pub directory: Arc<Directory>,
+    pub config: ArcSwap<Config>,
+    pub directory: ArcSwap<Directory>,
 
     pub sessions: TtlDashMap<String, u32>,
     pub access_tokens: TtlDashMap<u32, Arc<AccessToken>>,
@@ -98,15 +110,19 @@ pub struct JMAP {
 
     pub rate_limit_auth: DashMap<u32, Arc<AuthenticatedLimiter>>,
     pub rate_limit_unauth: DashMap<IpAddr, Arc<AnonymousLimiter>>,
+    pub rate_limit_notify: DashMap<u32, Arc<RateLimiter>>,
 
     pub oauth_codes: TtlDashMap<String, Arc<OAuthCode>>,
+    pub oidc_key: OnceLock<Arc<OidcSigningKey>>,
 
     pub state_tx: mpsc::Sender<state::Event>,
     pub housekeeper_tx: mpsc::Sender<housekeeper::Event>,
-    pub smtp: Arc<SMTP>,
+    pub smtp: Arc<ArcSwap<SMTP>>,
 
     pub sieve_compiler: Compiler,
     pub sieve_runtime: Runtime<()>,
+
+    pub settings: Settings,
 }
 
 pub struct Config {
@@ -134,14 +150,25 @@ pub struct Config {
     pub mail_attachments_max_size: usize,
     pub mail_parse_max_items: usize,
     pub mail_max_size: usize,
+    pub mail_max_messages: u64,
 
     pub sieve_max_script_name: usize,
     pub sieve_max_scripts: usize,
+    pub sieve_notify_rate: Rate,
+
+    pub contact_max_size: usize,
+    pub contact_max_address_books: usize,
+    pub address_book_name_max_len: usize,
+
+    pub calendar_max_size: usize,
+    pub calendar_max_calendars: usize,
+    pub calendar_name_max_len: usize,
 
     pub session_cache_ttl: Duration,
     pub rate_authenticated: Rate,
     pub rate_authenticate_req: Rate,
     pub rate_anonymous: Rate,
+    pub rate_registration: Rate,
     pub rate_use_forwarded: bool,
 
     pub event_source_throttle: Duration,
@@ -158,8 +185,13 @@ pub struct Config {
     pub oauth_expiry_refresh_token: u64,
     pub oauth_expiry_refresh_token_renew: u64,
     pub oauth_max_auth_attempts: u32,
+    pub oauth_oidc_key: Option<String>,
+    pub oauth_oidc_registration_token: Option<String>,
 
     pub spam_header: Option<(HeaderName<'static>, String)>,
+    pub spam_learn: bool,
+    pub spam_account_model: bool,
+    pub spam_threshold: f64,
 
     pub http_headers: Vec<(hyper::header::HeaderName, hyper::header::HeaderValue)>,
 
@@ -168,6 +200,10 @@ pub struct Config {
 
     pub principal_allow_lookups: bool,
 
+    pub list_base_url: Option<String>,
+    pub list_moderation_expiry: u64,
+    pub list_confirm_expiry: u64,
+
     pub capabilities: BaseCapabilities,
 }
 
@@ -189,7 +225,7 @@ impl JMAP {
         directories: &Directories,
         servers: &mut Servers,
         delivery_rx: mpsc::Receiver<DeliveryEvent>,
-        smtp: Arc<SMTP>,
+        smtp: Arc<ArcSwap<SMTP>>,
     ) -> Result<Arc<Self>, String> {
         // Init state manager and housekeeper
         let (state_tx, state_rx) = init_state_manager();
@@ -198,16 +234,19 @@ impl JMAP {
             .property::<u64>("global.shared-map.shard")?
             .unwrap_or(32)
             .next_power_of_two() as usize;
+        let settings = Settings::new(config, stores, directories, servers).await?;
 
         let jmap_server = Arc::new(JMAP {
-            directory: directories
-                .directories
-                .get(config.value_require("storage.directory")?)
-                .failed(&format!(
-                    "Unable to find directory '{}'",
-                    config.value_require("storage.directory")?
-                ))
-                .clone(),
+            directory: ArcSwap::new(
+                directories
+                    .directories
+                    .get(config.value_require("storage.directory")?)
+                    .failed(&format!(
+                        "Unable to find directory '{}'",
+                        config.value_require("storage.directory")?
+                    ))
+                    .clone(),
+            ),
             snowflake_id: config
                 .property::<u64>("storage.cluster.node-id")?
                 .map(SnowflakeIdGenerator::with_node_id)
@@ -215,7 +254,7 @@ impl JMAP {
             store: stores.get_store(config, "storage.data")?,
             fts_store: stores.get_fts_store(config, "storage.fts")?,
             blob_store: stores.get_blob_store(config, "storage.blob")?,
-            config: Config::new(config).failed("Invalid configuration file"),
+            config: ArcSwap::from_pointee(Config::new(config).failed("Invalid configuration file")),
             sessions: TtlDashMap::with_capacity(
                 config.property("jmap.session.cache.size")?.unwrap_or(100),
                 shard_amount,
@@ -238,10 +277,18 @@ impl JMAP {
                 RandomState::default(),
                 shard_amount,
             ),
+            rate_limit_notify: DashMap::with_capacity_and_hasher_and_shard_amount(
+                config
+                    .property("sieve.untrusted.notify.cache.size")?
+                    .unwrap_or(1024),
+                RandomState::default(),
+                shard_amount,
+            ),
             oauth_codes: TtlDashMap::with_capacity(
                 config.property("oauth.cache.size")?.unwrap_or(128),
                 shard_amount,
             ),
+            oidc_key: OnceLock::new(),
             state_tx,
             housekeeper_tx,
             smtp,
@@ -392,6 +439,7 @@ impl JMAP {
                 .with_env_variable("version", env!("CARGO_PKG_VERSION"))
                 .with_env_variable("location", "MS")
                 .with_env_variable("phase", "during"),
+            settings,
         });
 
         // Spawn delivery manager
@@ -564,7 +612,7 @@ impl JMAP {
         collection: Collection,
     ) -> Result<SetResponse, MethodError> {
         Ok(
-            SetResponse::from_request(request, self.config.set_max_objects)?.with_state(
+            SetResponse::from_request(request, self.config.load().set_max_objects)?.with_state(
                 self.assert_state(
                     request.account_id.document_id(),
                     collection,
@@ -584,6 +632,7 @@ impl JMAP {
             access_token.quota as i64
         } else {
             self.directory
+                .load()
                 .query(QueryBy::Id(account_id), false)
                 .await
                 .map_err(|err| {
@@ -600,6 +649,15 @@ impl JMAP {
         })
     }
 
+    pub async fn has_message_quota(&self, account_id: u32) -> Result<bool, MethodError> {
+        Ok(self.config.load().mail_max_messages == 0
+            || self
+                .get_document_ids(account_id, Collection::Email)
+                .await?
+                .map_or(0, |ids| ids.len())
+                < self.config.load().mail_max_messages)
+    }
+
     pub async fn get_used_quota(&self, account_id: u32) -> Result<i64, MethodError> {
         self.store
             .get_counter(DirectoryClass::UsedQuota(account_id))
@@ -665,15 +723,15 @@ impl JMAP {
         let total = result_set.results.len() as usize;
         let (limit_total, limit) = if let Some(limit) = request.limit {
             if limit > 0 {
-                let limit = std::cmp::min(limit, self.config.query_max_results);
+                let limit = std::cmp::min(limit, self.config.load().query_max_results);
                 (std::cmp::min(limit, total), limit)
             } else {
                 (0, 0)
             }
         } else {
             (
-                std::cmp::min(self.config.query_max_results, total),
-                self.config.query_max_results,
+                std::cmp::min(self.config.load().query_max_results, total),
+                self.config.load().query_max_results,
             )
         };
         Ok((
@@ -40,7 +40,7 @@ impl JMAP {
         mut request: GetRequest<RequestArguments>,
         access_token: &AccessToken,
     ) -> Result<GetResponse, MethodError> {
-        let ids = request.unwrap_ids(self.config.get_max_objects)?;
+        let ids = request.unwrap_ids(self.config.load().get_max_objects)?;
         let properties = request.unwrap_properties(&[
             Property::Id,
             Property::Name,
@@ -67,7 +67,7 @@ impl JMAP {
         } else {
             mailbox_ids
                 .iter()
-                .take(self.config.get_max_objects)
+                .take(self.config.load().get_max_objects)
                 .map(Into::into)
                 .collect::<Vec<_>>()
         };
@@ -336,7 +336,7 @@ impl JMAP {
                 }
             })
             .collect::<Vec<_>>();
-        if path.is_empty() || path.len() > self.config.mailbox_max_depth {
+        if path.is_empty() || path.len() > self.config.load().mailbox_max_depth {
             return Ok(None);
         }
 
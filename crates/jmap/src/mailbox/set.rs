@@ -495,6 +495,7 @@ impl JMAP {
                 .with_collection(Collection::Mailbox)
                 .delete_document(document_id)
                 .value(Property::EmailIds, (), F_VALUE | F_CLEAR)
+                .value(Property::Metadata, (), F_VALUE | F_CLEAR)
                 .custom(ObjectIndexBuilder::new(SCHEMA).with_current(mailbox));
 
             match self.store.write(batch.build()).await {
@@ -542,7 +543,7 @@ impl JMAP {
             let value = match (&property, value) {
                 (Property::Name, MaybePatchValue::Value(Value::Text(value))) => {
                     let value = value.trim();
-                    if !value.is_empty() && value.len() < self.config.mailbox_name_max_len {
+                    if !value.is_empty() && value.len() < self.config.load().mailbox_name_max_len {
                         Value::Text(value.to_string())
                     } else {
                         return Ok(Err(SetError::invalid_properties()
@@ -633,7 +634,7 @@ impl JMAP {
                 .map_or(u32::MAX, |(mailbox_id, _)| *mailbox_id + 1);
             let mut mailbox_parent_id = mailbox_parent_id.document_id();
             let mut success = false;
-            for depth in 0..self.config.mailbox_max_depth {
+            for depth in 0..self.config.load().mailbox_max_depth {
                 if mailbox_parent_id == current_mailbox_id {
                     return Ok(Err(SetError::invalid_properties()
                         .with_property(Property::ParentId)
@@ -903,7 +904,7 @@ impl JMAP {
                 .with_collection(Collection::Mailbox);
 
             for name in path {
-                if name.len() > self.config.mailbox_name_max_len {
+                if name.len() > self.config.load().mailbox_name_max_len {
                     return Ok(None);
                 }
 
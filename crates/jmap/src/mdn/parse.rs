@@ -0,0 +1,251 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use jmap_proto::{
+    error::method::MethodError,
+    method::parse::{ParseMdnRequest, ParseMdnResponse},
+    object::Object,
+    types::{collection::Collection, id::Id, property::Property, value::Value},
+};
+use mail_parser::{HeaderName, MessageParser, MimeHeaders};
+use store::query::Filter;
+use utils::map::vec_map::VecMap;
+
+use crate::{auth::AccessToken, JMAP};
+
+impl JMAP {
+    pub async fn mdn_parse(
+        &self,
+        request: ParseMdnRequest,
+        access_token: &AccessToken,
+    ) -> Result<ParseMdnResponse, MethodError> {
+        if request.blob_ids.len() > self.config.load().mail_parse_max_items {
+            return Err(MethodError::RequestTooLarge);
+        }
+        let account_id = request.account_id.document_id();
+        let mut response = ParseMdnResponse {
+            account_id: request.account_id,
+            parsed: VecMap::with_capacity(request.blob_ids.len()),
+            not_parsable: vec![],
+            not_found: vec![],
+        };
+
+        for blob_id in request.blob_ids {
+            // Fetch raw message to parse
+            let raw_message = match self.blob_download(&blob_id, access_token).await? {
+                Some(raw_message) => raw_message,
+                None => {
+                    response.not_found.push(blob_id);
+                    continue;
+                }
+            };
+            let message = if let Some(message) = MessageParser::new().parse(&raw_message) {
+                message
+            } else {
+                response.not_parsable.push(blob_id);
+                continue;
+            };
+
+            // Locate the machine-readable part of the report
+            let report = if let Some(report) = message
+                .parts
+                .iter()
+                .find(|part| {
+                    part.content_type().is_some_and(|ct| {
+                        ct.ctype().eq_ignore_ascii_case("message")
+                            && ct.subtype().is_some_and(|st| {
+                                st.eq_ignore_ascii_case("disposition-notification")
+                            })
+                    })
+                })
+                .and_then(|part| MessageParser::new().parse_headers(part.contents()))
+            {
+                report
+            } else {
+                response.not_parsable.push(blob_id);
+                continue;
+            };
+
+            let mut mdn = Object::with_capacity(12)
+                .with_property(
+                    Property::Subject,
+                    message
+                        .subject()
+                        .map_or(Value::Null, |subject| subject.to_string().into()),
+                )
+                .with_property(
+                    Property::TextBody,
+                    message
+                        .body_text(0)
+                        .map_or(Value::Null, |text| text.into_owned().into()),
+                )
+                .with_property(
+                    Property::IncludeOriginalMessage,
+                    message.parts.iter().any(|part| {
+                        part.content_type().is_some_and(|ct| {
+                            matches!(
+                                (ct.ctype(), ct.subtype()),
+                                (ctype, Some(subtype)) if (ctype.eq_ignore_ascii_case("message")
+                                    && (subtype.eq_ignore_ascii_case("rfc822")
+                                        || subtype.eq_ignore_ascii_case("global")))
+                                    || (ctype.eq_ignore_ascii_case("text")
+                                        && subtype.eq_ignore_ascii_case("rfc822-headers"))
+                            )
+                        })
+                    }),
+                );
+            let mut disposition = None;
+            let mut original_message_id = None;
+            let mut errors = Vec::new();
+            let mut extension_fields = VecMap::new();
+
+            for header in report.root_part().headers() {
+                let value = header
+                    .value
+                    .as_text()
+                    .unwrap_or_default()
+                    .trim()
+                    .to_string();
+                let name = if let HeaderName::Other(name) = &header.name {
+                    name.as_ref()
+                } else {
+                    extension_fields.append(header.name.to_string(), Value::Text(value));
+                    continue;
+                };
+
+                match name.to_ascii_lowercase().as_str() {
+                    "reporting-ua" => mdn.append(Property::ReportingUa, value),
+                    "mdn-gateway" => mdn.append(Property::MdnGateway, value),
+                    "original-recipient" => mdn.append(Property::OriginalRecipient, value),
+                    "final-recipient" => mdn.append(Property::FinalRecipient, value),
+                    "original-message-id" => {
+                        original_message_id = value
+                            .trim_start_matches('<')
+                            .trim_end_matches('>')
+                            .to_string()
+                            .into();
+                        mdn.append(Property::OriginalMessageId, value);
+                    }
+                    "disposition" => {
+                        disposition = parse_disposition(&value);
+                    }
+                    "error" => {
+                        errors.push(Value::Text(value));
+                    }
+                    _ => {
+                        extension_fields.append(name.to_string(), Value::Text(value));
+                    }
+                }
+            }
+
+            if let Some(disposition) = disposition {
+                mdn.append(Property::Disposition, disposition);
+            } else {
+                response.not_parsable.push(blob_id);
+                continue;
+            }
+            mdn.append(
+                Property::Error,
+                if !errors.is_empty() {
+                    Value::List(errors)
+                } else {
+                    Value::Null
+                },
+            );
+            mdn.append(
+                Property::ExtensionFields,
+                if !extension_fields.is_empty() {
+                    Value::Object(Object {
+                        properties: extension_fields
+                            .into_iter()
+                            .map(|(name, value)| (Property::_T(name), value))
+                            .collect(),
+                    })
+                } else {
+                    Value::Null
+                },
+            );
+
+            // Find the email this MDN refers to
+            let for_email_id = if let Some(original_message_id) =
+                original_message_id.filter(|id| !id.is_empty())
+            {
+                if let Some(document_id) = self
+                    .filter(
+                        account_id,
+                        Collection::Email,
+                        vec![Filter::eq(Property::MessageId, original_message_id)],
+                    )
+                    .await?
+                    .results
+                    .min()
+                {
+                    self.get_property::<u32>(
+                        account_id,
+                        Collection::Email,
+                        document_id,
+                        Property::ThreadId,
+                    )
+                    .await?
+                    .map(|thread_id| Id::from_parts(thread_id, document_id))
+                } else {
+                    None
+                }
+            } else {
+                None
+            };
+            mdn.append(
+                Property::ForEmailId,
+                for_email_id.map_or(Value::Null, Value::Id),
+            );
+
+            response.parsed.append(blob_id, mdn);
+        }
+
+        Ok(response)
+    }
+}
+
+// Parses a Disposition field, e.g. "manual-action/MDN-sent-manually; displayed"
+fn parse_disposition(value: &str) -> Option<Object<Value>> {
+    let (modes, type_) = value.split_once(';')?;
+    let (action_mode, sending_mode) = modes.split_once('/')?;
+    let type_ = type_
+        .split_once('/')
+        .map_or(type_, |(type_, _)| type_)
+        .trim()
+        .to_ascii_lowercase();
+
+    Some(
+        Object::with_capacity(3)
+            .with_property(
+                Property::ActionMode,
+                action_mode.trim().to_ascii_lowercase(),
+            )
+            .with_property(
+                Property::SendingMode,
+                sending_mode.trim().to_ascii_lowercase(),
+            )
+            .with_property(Property::Type, type_),
+    )
+}
@@ -0,0 +1,512 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::{
+    borrow::Cow,
+    collections::{HashMap, HashSet},
+    sync::Arc,
+};
+
+use jmap_proto::{
+    error::{
+        method::MethodError,
+        set::{SetError, SetErrorType},
+    },
+    method::{
+        send::{SendMdnRequest, SendMdnResponse},
+        set::{self, SetRequest},
+    },
+    object::{mdn::Mdn, Object},
+    request::{
+        method::{MethodFunction, MethodName, MethodObject},
+        reference::MaybeReference,
+        Call, RequestMethod,
+    },
+    types::{
+        collection::Collection,
+        id::Id,
+        keyword::Keyword,
+        property::Property,
+        value::{SetValue, Value},
+    },
+};
+use mail_builder::{
+    headers::{content_type::ContentType, HeaderType},
+    mime::{make_boundary, BodyPart, MimePart},
+    MessageBuilder,
+};
+use mail_parser::{HeaderName, HeaderValue, MessageParser};
+use smtp::core::{Session, SessionData, State};
+use smtp_proto::{MailFrom, RcptTo};
+use utils::{
+    listener::{stream::NullIo, ServerInstance},
+    map::vec_map::VecMap,
+};
+
+use crate::{email::metadata::MessageMetadata, identity::set::sanitize_email, Bincode, JMAP};
+
+struct MdnIdentity {
+    name: Option<String>,
+    email: String,
+}
+
+impl JMAP {
+    pub async fn mdn_send(
+        &self,
+        request: SendMdnRequest,
+        instance: &Arc<ServerInstance>,
+        next_call: &mut Option<Call<RequestMethod>>,
+    ) -> Result<SendMdnResponse, MethodError> {
+        if request.send.len() > self.config.load().set_max_objects {
+            return Err(MethodError::RequestTooLarge);
+        }
+        let account_id = request.account_id.document_id();
+        let mut response = SendMdnResponse {
+            account_id: request.account_id,
+            sent: VecMap::with_capacity(request.send.len()),
+            not_sent: VecMap::new(),
+        };
+
+        // Fetch identity
+        let identity = if let Some(mut identity) = self
+            .get_property::<Object<Value>>(
+                account_id,
+                Collection::Identity,
+                request.identity_id.document_id(),
+                Property::Value,
+            )
+            .await?
+        {
+            MdnIdentity {
+                name: identity
+                    .properties
+                    .remove(&Property::Name)
+                    .and_then(|name| name.try_unwrap_string())
+                    .filter(|name| !name.is_empty()),
+                email: identity
+                    .properties
+                    .remove(&Property::Email)
+                    .and_then(|email| email.try_unwrap_string())
+                    .unwrap_or_default(),
+            }
+        } else {
+            return Err(MethodError::InvalidArguments(
+                "Identity not found.".to_string(),
+            ));
+        };
+
+        // Send MDNs
+        let mut sent_email_ids = HashMap::new();
+        let mut sent_in_request = HashSet::new();
+        for (id, mdn) in request.send {
+            match self
+                .send_mdn(account_id, &identity, mdn, &sent_in_request, instance)
+                .await?
+            {
+                Ok((email_id, mdn)) => {
+                    sent_in_request.insert(email_id);
+                    sent_email_ids.insert(id.clone(), email_id);
+                    response.sent.append(id, mdn);
+                }
+                Err(err) => {
+                    response.not_sent.append(id, err);
+                }
+            }
+        }
+
+        // Set the $mdnsent keyword and apply any updates requested by the client
+        if !sent_email_ids.is_empty() {
+            let mut update = request
+                .on_success_update_email
+                .unwrap_or_default()
+                .into_iter()
+                .filter_map(|(id, value)| {
+                    (
+                        match id {
+                            MaybeReference::Value(id) => id,
+                            MaybeReference::Reference(id_ref) => *(sent_email_ids.get(&id_ref)?),
+                        },
+                        value,
+                    )
+                        .into()
+                })
+                .collect::<VecMap<Id, Object<SetValue>>>();
+            for email_id in sent_email_ids.into_values() {
+                update
+                    .get_mut_or_insert_with(email_id, || Object {
+                        properties: VecMap::new(),
+                    })
+                    .properties
+                    .append(
+                        Property::Keywords,
+                        SetValue::Patch(vec![Value::Keyword(Keyword::MdnSent), Value::Bool(true)]),
+                    );
+            }
+
+            *next_call = Call {
+                id: String::new(),
+                name: MethodName::new(MethodObject::Email, MethodFunction::Set),
+                method: RequestMethod::Set(SetRequest {
+                    account_id: request.account_id,
+                    if_in_state: None,
+                    create: None,
+                    update: update.into(),
+                    destroy: None,
+                    arguments: set::RequestArguments::Email,
+                }),
+            }
+            .into();
+        }
+
+        Ok(response)
+    }
+
+    async fn send_mdn(
+        &self,
+        account_id: u32,
+        identity: &MdnIdentity,
+        mdn: Mdn,
+        sent_in_request: &HashSet<Id>,
+        instance: &Arc<ServerInstance>,
+    ) -> Result<Result<(Id, Object<Value>), SetError>, MethodError> {
+        // Validate properties
+        if !mdn.invalid_properties.is_empty() {
+            return Ok(Err(SetError::invalid_properties()
+                .with_properties(mdn.invalid_properties)
+                .with_description("Invalid or server-set properties.")));
+        }
+        let email_id = if let Some(email_id) = mdn.for_email_id {
+            email_id
+        } else {
+            return Ok(Err(SetError::invalid_properties()
+                .with_property(Property::ForEmailId)
+                .with_description("forEmailId is required.")));
+        };
+        let disposition = match &mdn.disposition {
+            Some(disposition)
+                if matches!(
+                    disposition.action_mode.as_str(),
+                    "manual-action" | "automatic-action"
+                ) && matches!(
+                    disposition.sending_mode.as_str(),
+                    "mdn-sent-manually" | "mdn-sent-automatically"
+                ) && matches!(
+                    disposition.type_.as_str(),
+                    "deleted" | "dispatched" | "displayed" | "processed"
+                ) =>
+            {
+                disposition
+            }
+            _ => {
+                return Ok(Err(SetError::invalid_properties()
+                    .with_property(Property::Disposition)
+                    .with_description("Missing or invalid disposition.")));
+            }
+        };
+
+        // Reject values that would add or break fields in the report
+        let mut invalid_properties = Vec::new();
+        if mdn.reporting_ua.as_deref().is_some_and(has_line_break) {
+            invalid_properties.push(Property::ReportingUa);
+        }
+        if mdn.final_recipient.as_deref().is_some_and(has_line_break) {
+            invalid_properties.push(Property::FinalRecipient);
+        }
+        if mdn
+            .error
+            .as_ref()
+            .is_some_and(|errors| errors.iter().any(|error| has_line_break(error)))
+        {
+            invalid_properties.push(Property::Error);
+        }
+        if mdn.extension_fields.as_ref().is_some_and(|fields| {
+            fields.iter().any(|(name, value)| {
+                name.is_empty()
+                    || !name.bytes().all(|ch| ch.is_ascii_graphic() && ch != b':')
+                    || has_line_break(value)
+            })
+        }) {
+            invalid_properties.push(Property::ExtensionFields);
+        }
+        if !invalid_properties.is_empty() {
+            return Ok(Err(SetError::invalid_properties()
+                .with_properties(invalid_properties)
+                .with_description(
+                    "Field names and values cannot contain line breaks.",
+                )));
+        }
+
+        // Make sure an MDN was not already sent for this message, the $mdnsent
+        // keyword is only set once all MDNs in the request have been processed
+        if sent_in_request.contains(&email_id) {
+            return Ok(Err(SetError::new(SetErrorType::MdnAlreadySent)
+                .with_description("An MDN was already sent for this message.")));
+        }
+        let document_id = email_id.document_id();
+        match self
+            .get_property::<Vec<Keyword>>(
+                account_id,
+                Collection::Email,
+                document_id,
+                Property::Keywords,
+            )
+            .await?
+        {
+            Some(keywords) if keywords.contains(&Keyword::MdnSent) => {
+                return Ok(Err(SetError::new(SetErrorType::MdnAlreadySent)
+                    .with_description("An MDN was already sent for this message.")));
+            }
+            Some(_) => (),
+            None => {
+                return Ok(Err(SetError::not_found()
+                    .with_property(Property::ForEmailId)
+                    .with_description("Email not found.")));
+            }
+        }
+
+        // Obtain raw message
+        let raw_message = if let Some(raw_message) = self
+            .get_property::<Bincode<MessageMetadata>>(
+                account_id,
+                Collection::Email,
+                document_id,
+                Property::BodyStructure,
+            )
+            .await?
+        {
+            self.get_blob(&raw_message.inner.blob_hash, 0..u32::MAX)
+                .await?
+        } else {
+            None
+        };
+        let raw_message = if let Some(raw_message) = raw_message {
+            raw_message
+        } else {
+            return Ok(Err(SetError::not_found()
+                .with_property(Property::ForEmailId)
+                .with_description("Blob for email not found.")));
+        };
+        let dnt_header = HeaderName::Other(Cow::Borrowed("Disposition-Notification-To"));
+        let orcpt_header = HeaderName::Other(Cow::Borrowed("Original-Recipient"));
+        let message = MessageParser::new()
+            .header_address(dnt_header.clone())
+            .header_raw(orcpt_header.clone())
+            .header_id(HeaderName::MessageId)
+            .header_text(HeaderName::Subject)
+            .parse_headers(&raw_message)
+            .unwrap_or_default();
+
+        // Obtain the MDN recipients
+        let mut rcpt_to: Vec<RcptTo<String>> = Vec::new();
+        if let Some(HeaderValue::Address(addr)) = message.header(dnt_header) {
+            for address in addr.iter() {
+                if let Some(address) = address.address().and_then(sanitize_email) {
+                    if !rcpt_to.iter().any(|rcpt| rcpt.address == address) {
+                        rcpt_to.push(RcptTo {
+                            address,
+                            ..Default::default()
+                        });
+                    }
+                }
+            }
+        }
+        if rcpt_to.is_empty() {
+            return Ok(Err(SetError::invalid_properties()
+                .with_property(Property::ForEmailId)
+                .with_description(
+                    "Email does not request a disposition notification.",
+                )));
+        }
+
+        // Build the MDN (RFC 8098)
+        let original_subject = message.subject().unwrap_or_default();
+        let original_message_id = message.message_id().map(|id| format!("<{id}>"));
+        let original_recipient = message
+            .header(orcpt_header)
+            .and_then(|value| value.as_text())
+            .map(|value| value.trim().to_string());
+        let final_recipient = mdn
+            .final_recipient
+            .unwrap_or_else(|| format!("rfc822; {}", identity.email));
+        let mut report = format!(
+            "Reporting-UA: {}\r\n",
+            mdn.reporting_ua
+                .unwrap_or_else(|| format!("{}; Stalwart JMAP", instance.hostname))
+        );
+        if let Some(original_recipient) = &original_recipient {
+            report.push_str(&format!("Original-Recipient: {original_recipient}\r\n"));
+        }
+        report.push_str(&format!("Final-Recipient: {final_recipient}\r\n"));
+        if let Some(original_message_id) = &original_message_id {
+            report.push_str(&format!("Original-Message-ID: {original_message_id}\r\n"));
+        }
+        report.push_str(&format!(
+            "Disposition: {}/{}; {}\r\n",
+            disposition.action_mode, disposition.sending_mode, disposition.type_
+        ));
+        for error in mdn.error.unwrap_or_default() {
+            report.push_str(&format!("Error: {error}\r\n"));
+        }
+        for (name, value) in mdn.extension_fields.unwrap_or_default() {
+            report.push_str(&format!("{name}: {value}\r\n"));
+        }
+
+        let subject = mdn.subject.unwrap_or_else(|| {
+            format!(
+                "{}: {original_subject}",
+                match disposition.type_.as_str() {
+                    "displayed" => "Read",
+                    "deleted" => "Deleted",
+                    "dispatched" => "Dispatched",
+                    _ => "Processed",
+                }
+            )
+        });
+        let text_body = mdn.text_body.unwrap_or_else(|| {
+            format!(
+                "This is a disposition notification for the message \"{original_subject}\" sent to {}.\r\nThe message was {}.\r\n",
+                identity.email, disposition.type_
+            )
+        });
+        let mut parts = vec![
+            MimePart::new(
+                ContentType::new("text/plain"),
+                BodyPart::Text(text_body.into()),
+            ),
+            MimePart::new(
+                ContentType::new("message/disposition-notification"),
+                BodyPart::Text(report.into()),
+            ),
+        ];
+        if mdn.include_original_message {
+            // Message parts cannot be base64 encoded (RFC 2046, section 5.2.1)
+            let encoding = if raw_message.is_ascii() {
+                "7bit"
+            } else {
+                "8bit"
+            };
+            parts.push(
+                MimePart::new(
+                    ContentType::new("message/rfc822"),
+                    BodyPart::Binary(raw_message.as_slice().into()),
+                )
+                .transfer_encoding(encoding),
+            );
+        }
+        let mut builder = MessageBuilder::new()
+            .to(rcpt_to
+                .iter()
+                .map(|rcpt| rcpt.address.as_str())
+                .collect::<Vec<_>>())
+            .message_id(format!(
+                "{}@{}",
+                make_boundary("."),
+                identity
+                    .email
+                    .rsplit_once('@')
+                    .map_or(instance.hostname.as_str(), |(_, domain)| domain)
+            ))
+            .subject(subject)
+            .body(MimePart::new(
+                ContentType::new("multipart/report")
+                    .attribute("report-type", "disposition-notification"),
+                BodyPart::Multipart(parts),
+            ));
+        builder = if let Some(name) = &identity.name {
+            builder.from((name.as_str(), identity.email.as_str()))
+        } else {
+            builder.from(identity.email.as_str())
+        };
+        if let Some(message_id) = message.message_id() {
+            builder = builder.in_reply_to(message_id).references(message_id);
+        }
+        if disposition.sending_mode == "mdn-sent-automatically" {
+            builder = builder.header("Auto-Submitted", HeaderType::Text("auto-replied".into()));
+        }
+        let message = builder.write_to_vec().unwrap_or_default();
+
+        // Begin local SMTP session
+        let mut session = Session::<NullIo>::local(
+            self.smtp.load_full(),
+            instance.clone(),
+            SessionData::default(),
+        );
+
+        // MDNs are sent with a null return path (RFC 8098, section 2.1)
+        let _ = session.handle_mail_from(MailFrom::default()).await;
+        if let Some(error) = session.has_failed() {
+            return Ok(Err(SetError::new(SetErrorType::ForbiddenMailFrom)
+                .with_description(format!(
+                    "Server rejected MAIL-FROM: {}",
+                    error.trim()
+                ))));
+        }
+
+        // RCPT TO
+        let mut has_success = false;
+        let mut last_error = String::new();
+        for rcpt in rcpt_to {
+            let _ = session.handle_rcpt_to(rcpt).await;
+            if let Some(error) = session.has_failed() {
+                last_error = error;
+            } else {
+                has_success = true;
+            }
+        }
+        if !has_success {
+            return Ok(Err(SetError::new(SetErrorType::ForbiddenToSend)
+                .with_description(format!(
+                    "Server rejected RCPT-TO: {}",
+                    last_error.trim()
+                ))));
+        }
+
+        // DATA
+        session.data.message = message;
+        let response = session.queue_message().await;
+        if !matches!(session.state, State::Accepted(_)) {
+            return Ok(Err(SetError::new(SetErrorType::ForbiddenToSend)
+                .with_description(format!(
+                    "Server rejected DATA: {}",
+                    std::str::from_utf8(&response).unwrap_or_default().trim()
+                ))));
+        }
+
+        // Return server-set properties
+        let mut result = Object::with_capacity(3);
+        result.append(Property::FinalRecipient, final_recipient);
+        if let Some(original_recipient) = original_recipient {
+            result.append(Property::OriginalRecipient, original_recipient);
+        }
+        result.append(
+            Property::OriginalMessageId,
+            original_message_id.map_or(Value::Null, Value::from),
+        );
+
+        Ok(Ok((email_id, result)))
+    }
+}
+
+fn has_line_break(value: &str) -> bool {
+    value.contains(['\r', '\n'])
+}
@@ -36,7 +36,7 @@ impl JMAP {
         &self,
         mut request: GetRequest<RequestArguments>,
     ) -> Result<GetResponse, MethodError> {
-        let ids = request.unwrap_ids(self.config.get_max_objects)?;
+        let ids = request.unwrap_ids(self.config.load().get_max_objects)?;
         let properties = request.unwrap_properties(&[
             Property::Id,
             Property::Type,
@@ -55,7 +55,7 @@ impl JMAP {
         } else {
             email_submission_ids
                 .iter()
-                .take(self.config.get_max_objects)
+                .take(self.config.load().get_max_objects)
                 .map(Into::into)
                 .collect::<Vec<_>>()
         };
@@ -70,6 +70,7 @@ impl JMAP {
             // Obtain the principal
             let principal = if let Some(principal) = self
                 .directory
+                .load()
                 .query(QueryBy::Id(id.document_id()), false)
                 .await
                 .map_err(|_| MethodError::ServerPartialFail)?
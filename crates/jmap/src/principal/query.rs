@@ -49,6 +49,7 @@ impl JMAP {
                 Filter::Name(name) => {
                     if let Some(principal) = self
                         .directory
+                        .load()
                         .query(QueryBy::Name(name.as_str()), false)
                         .await
                         .map_err(|_| MethodError::ServerPartialFail)?
@@ -68,6 +69,7 @@ impl JMAP {
                     let mut ids = RoaringBitmap::new();
                     for id in self
                         .directory
+                        .load()
                         .email_to_ids(&email)
                         .await
                         .map_err(|_| MethodError::ServerPartialFail)?
@@ -44,7 +44,7 @@ impl JMAP {
         mut request: GetRequest<RequestArguments>,
         access_token: &AccessToken,
     ) -> Result<GetResponse, MethodError> {
-        let ids = request.unwrap_ids(self.config.get_max_objects)?;
+        let ids = request.unwrap_ids(self.config.load().get_max_objects)?;
         let properties = request.unwrap_properties(&[
             Property::Id,
             Property::DeviceClientId,
@@ -62,7 +62,7 @@ impl JMAP {
         } else {
             push_ids
                 .iter()
-                .take(self.config.get_max_objects)
+                .take(self.config.load().get_max_objects)
                 .map(Into::into)
                 .collect::<Vec<_>>()
         };
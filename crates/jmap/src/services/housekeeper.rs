@@ -23,15 +23,15 @@
 
 use std::sync::Arc;
 
+use directory::core::mailing_list::MailingListStore;
 use tokio::sync::mpsc;
 use utils::{
     config::{cron::SimpleCron, Config, Servers},
-    listener::blocked::BLOCKED_IP_KEY,
     map::ttl_dashmap::TtlMap,
     UnwrapFailure,
 };
 
-use crate::JMAP;
+use crate::{email::bayes::SpamTraining, JMAP};
 
 use super::IPC_CHANNEL_BUFFER;
 
@@ -41,6 +41,11 @@ pub enum Event {
     ReloadConfig,
     IndexStart,
     IndexDone,
+    SpamTrain {
+        account_id: u32,
+        document_id: u32,
+        training: SpamTraining,
+    },
     #[cfg(feature = "test_mode")]
     IndexIsActive(tokio::sync::oneshot::Sender<bool>),
     Exit,
@@ -107,30 +112,15 @@ pub fn spawn_housekeeper(
                         });
                     }
                     Event::ReloadConfig => {
-                        // Future releases will support reloading the configuration
-                        // for now, we just reload the blocked IP addresses
                         let core = core.clone();
-                        let blocked_ips = blocked_ips.clone();
                         tokio::spawn(async move {
-                            match core.store.config_list(BLOCKED_IP_KEY).await {
-                                Ok(config) => {
-                                    if let Err(err) = blocked_ips.reload_blocked_ips(&config) {
-                                        tracing::error!(
-                                            context = "store",
-                                            event = "error",
-                                            error = ?err,
-                                            "Failed to reload configuration."
-                                        );
-                                    }
-                                }
-                                Err(err) => {
-                                    tracing::error!(
-                                        context = "store",
-                                        event = "error",
-                                        error = ?err,
-                                        "Failed to reload configuration."
-                                    );
-                                }
+                            if let Err(err) = core.reload_config().await {
+                                tracing::error!(
+                                    context = "config",
+                                    event = "error",
+                                    error = ?err,
+                                    "Failed to reload configuration."
+                                );
                             }
                         });
                     }
@@ -156,6 +146,16 @@ pub fn spawn_housekeeper(
                             index_busy = false;
                         }
                     }
+                    Event::SpamTrain {
+                        account_id,
+                        document_id,
+                        training,
+                    } => {
+                        let core = core.clone();
+                        tokio::spawn(async move {
+                            core.spam_train(account_id, document_id, training).await;
+                        });
+                    }
                     #[cfg(feature = "test_mode")]
                     Event::IndexIsActive(tx) => {
                         tx.send(index_busy).ok();
@@ -187,6 +187,16 @@ pub fn spawn_housekeeper(
                         .retain(|_, limiter| limiter.is_active());
                     core.rate_limit_unauth
                         .retain(|_, limiter| limiter.is_active());
+                    core.rate_limit_notify
+                        .retain(|_, limiter| limiter.is_active());
+                    if let Err(err) = core.store.purge_held_messages().await {
+                        tracing::warn!(
+                            context = "mailing_list",
+                            event = "error",
+                            error = ?err,
+                            "Failed to purge expired held messages."
+                        );
+                    }
                 });
             }
         }
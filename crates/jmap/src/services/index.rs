@@ -114,7 +114,7 @@ impl JMAP {
 
                         // Index message
                         let document =
-                            FtsDocument::with_default_language(self.config.default_language)
+                            FtsDocument::with_default_language(self.config.load().default_language)
                                 .with_account_id(key.account_id)
                                 .with_collection(Collection::Email)
                                 .with_document_id(key.document_id)
@@ -55,9 +55,31 @@ impl JMAP {
 
         // Obtain the UIDs for each recipient
         let mut recipients = Vec::with_capacity(message.recipients.len());
+        let mut list_results = Vec::with_capacity(message.recipients.len());
         let mut deliver_names = AHashMap::with_capacity(message.recipients.len());
         for rcpt in &message.recipients {
-            let uids = self.directory.email_to_ids(rcpt).await.unwrap_or_default();
+            // Lists with mailing list settings are handled by the list manager
+            let list_result = self
+                .mailing_list_deliver(
+                    &raw_message,
+                    &message.sender_address,
+                    message.sender_authenticated,
+                    rcpt,
+                )
+                .await;
+            if list_result.is_some() {
+                list_results.push(list_result);
+                recipients.push(vec![]);
+                continue;
+            }
+
+            let uids = self
+                .directory
+                .load()
+                .email_to_ids(rcpt)
+                .await
+                .unwrap_or_default();
+            list_results.push(None);
             for uid in &uids {
                 deliver_names.insert(*uid, (DeliveryResult::Success, rcpt));
             }
@@ -72,6 +94,7 @@ impl JMAP {
                     self.sieve_script_ingest(
                         &raw_message,
                         &message.sender_address,
+                        message.sender_authenticated,
                         rcpt,
                         *uid,
                         active_script,
@@ -79,16 +102,17 @@ impl JMAP {
                     .await
                 }
                 Ok(None) => {
-                    let account_quota = match self.directory.query(QueryBy::Id(*uid), false).await {
-                        Ok(Some(p)) => p.quota as i64,
-                        Ok(None) => 0,
-                        Err(_) => {
-                            *status = DeliveryResult::TemporaryFailure {
-                                reason: "Transient server failure.".into(),
-                            };
-                            continue;
-                        }
-                    };
+                    let account_quota =
+                        match self.directory.load().query(QueryBy::Id(*uid), false).await {
+                            Ok(Some(p)) => p.quota as i64,
+                            Ok(None) => 0,
+                            Err(_) => {
+                                *status = DeliveryResult::TemporaryFailure {
+                                    reason: "Transient server failure.".into(),
+                                };
+                                continue;
+                            }
+                        };
 
                     self.email_ingest(IngestEmail {
                         raw_message: &raw_message,
@@ -99,7 +123,11 @@ impl JMAP {
                         keywords: vec![],
                         received_at: None,
                         skip_duplicates: true,
-                        encrypt: self.config.encrypt,
+                        encrypt: self.config.load().encrypt,
+                        authenticated_sender: message
+                            .sender_authenticated
+                            .then_some(message.sender_address.as_str()),
+                        is_delivery: true,
                     })
                     .await
                 }
@@ -149,7 +177,12 @@ impl JMAP {
         // Build result
         recipients
             .into_iter()
-            .map(|names| {
+            .zip(list_results)
+            .map(|(names, list_result)| {
+                if let Some(list_result) = list_result {
+                    return list_result;
+                }
+
                 match names.len() {
                     1 => {
                         // Delivery to single recipient
@@ -0,0 +1,991 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::net::Ipv4Addr;
+
+use base64::{engine::general_purpose, Engine};
+use directory::{
+    backend::internal::{lookup::DirectoryStore, manage::ManageDirectory},
+    core::mailing_list::{FromRewrite, HeldMessage, MailingList, MailingListStore, PostingPolicy},
+    QueryBy,
+};
+use hyper::StatusCode;
+use jmap_proto::types::{state::StateChange, type_state::DataType};
+use mail_auth::{dmarc::Policy, AuthenticatedMessage};
+use mail_builder::{
+    headers::{address::Address, text::Text, Header},
+    MessageBuilder,
+};
+use mail_parser::{HeaderName, Message, MessageParser};
+use smtp::core::{Session, SessionAddress};
+use store::{
+    ahash::AHashSet,
+    blake3,
+    write::{now, BatchBuilder, BlobOp},
+    BlobHash, Serialize,
+};
+use utils::{ipc::DeliveryResult, listener::stream::NullIo};
+
+use crate::{
+    api::{http::ToHttpResponse, HtmlResponse, HttpRequest, HttpResponse},
+    auth::oauth::{FormData, MAX_POST_LEN, OAUTH_HTML_FOOTER, OAUTH_HTML_HEADER},
+    email::ingest::IngestEmail,
+    mailbox::INBOX_ID,
+    JMAP,
+};
+
+const LIST_HTML_UNSUBSCRIBE: &str = include_str!("../../../../resources/htx/list_unsubscribe.htx");
+const LIST_HTML_UNSUBSCRIBED: &str =
+    include_str!("../../../../resources/htx/list_unsubscribed.htx");
+
+const TOKEN_MAC_LEN: usize = 16;
+
+pub struct ListContext {
+    pub list_id: u32,
+    pub list: MailingList,
+    pub name: String,
+    pub domain: String,
+    pub description: Option<String>,
+    pub quota: i64,
+}
+
+impl ListContext {
+    pub fn address(&self) -> String {
+        format!("{}@{}", self.name, self.domain)
+    }
+
+    pub fn command_address(&self, command: &str) -> String {
+        format!("{}+{}@{}", self.name, command, self.domain)
+    }
+
+    fn list_id_header(&self) -> String {
+        format!("<{}.{}>", self.name, self.domain)
+    }
+
+    fn display_name(&self) -> &str {
+        self.description.as_deref().unwrap_or(&self.name)
+    }
+
+    // Moderator privileges require an authenticated envelope sender
+    fn is_moderator(&self, address: &str, is_authenticated: bool) -> bool {
+        is_authenticated
+            && self
+                .list
+                .moderators
+                .iter()
+                .any(|moderator| moderator.eq_ignore_ascii_case(address))
+    }
+}
+
+impl JMAP {
+    // Handles messages addressed to a list that has mailing list settings,
+    // returns None when the recipient should be expanded as a regular list
+    pub async fn mailing_list_deliver(
+        &self,
+        raw_message: &[u8],
+        sender: &str,
+        sender_authenticated: bool,
+        rcpt: &str,
+    ) -> Option<DeliveryResult> {
+        let (local_part, domain) = rcpt.rsplit_once('@')?;
+        let (name, command) = local_part
+            .split_once('+')
+            .map_or((local_part, None), |(name, command)| (name, Some(command)));
+        let list_id = match self
+            .store
+            .get_list_id(&format!("{name}@{domain}").to_lowercase())
+            .await
+        {
+            Ok(Some(list_id)) => list_id,
+            Ok(None) => return None,
+            Err(err) => {
+                tracing::error!(
+                    context = "mailing_list",
+                    event = "error",
+                    rcpt = rcpt,
+                    reason = ?err,
+                    "Failed to lookup mailing list."
+                );
+                return Some(temporary_failure());
+            }
+        };
+        let ctx = match self.mailing_list_context(list_id).await {
+            Ok(Some(ctx)) => ctx,
+            Ok(None) => return None,
+            Err(err) => {
+                tracing::error!(
+                    context = "mailing_list",
+                    event = "error",
+                    rcpt = rcpt,
+                    reason = ?err,
+                    "Failed to obtain mailing list settings."
+                );
+                return Some(temporary_failure());
+            }
+        };
+
+        let sender = sender.to_lowercase();
+        let result = match command.map(|command| command.to_lowercase()).as_deref() {
+            None => {
+                self.mailing_list_post(&ctx, raw_message, &sender, sender_authenticated)
+                    .await
+            }
+            Some(_) if sender.is_empty() => {
+                // Null sender, most likely a bounce
+                Ok(DeliveryResult::Success)
+            }
+            Some("bounces") => {
+                tracing::debug!(
+                    context = "mailing_list",
+                    event = "bounce",
+                    list = ctx.address(),
+                    from = sender,
+                    "Discarding message sent to the bounce address."
+                );
+                Ok(DeliveryResult::Success)
+            }
+            Some("subscribe" | "join") => {
+                let token = self.list_confirm_token(
+                    ctx.list_id,
+                    "subscribe",
+                    &sender,
+                    now() + self.config.load().list_confirm_expiry,
+                );
+                self.mailing_list_notice(
+                    &ctx,
+                    vec![sender],
+                    format!("Confirm your subscription to {}", ctx.address()),
+                    format!(
+                        concat!(
+                            "Somebody, probably you, asked to subscribe this address to the ",
+                            "{} mailing list.\r\n\r\nTo confirm, reply to this message or ",
+                            "send an empty message to <{}>.\r\n\r\nIf you did not request ",
+                            "this subscription, please ignore this message.\r\n"
+                        ),
+                        ctx.address(),
+                        ctx.command_address(&format!("confirm-{token}"))
+                    ),
+                    Some(ctx.command_address(&format!("confirm-{token}"))),
+                )
+                .await;
+                Ok(DeliveryResult::Success)
+            }
+            Some(command) if command.starts_with("confirm-") => {
+                if self.is_valid_confirm_token(
+                    ctx.list_id,
+                    "subscribe",
+                    &sender,
+                    &command["confirm-".len()..],
+                ) {
+                    self.store
+                        .add_subscriber(ctx.list_id, &sender)
+                        .await
+                        .map(|_| DeliveryResult::Success)
+                } else {
+                    Ok(DeliveryResult::PermanentFailure {
+                        code: [5, 7, 1],
+                        reason: "Invalid or expired confirmation token.".into(),
+                    })
+                }
+            }
+            Some("unsubscribe" | "leave") => {
+                // The envelope sender can be forged, so removal is only
+                // performed once the subscriber confirms the request
+                let token = self.list_confirm_token(
+                    ctx.list_id,
+                    "unsubscribe",
+                    &sender,
+                    now() + self.config.load().list_confirm_expiry,
+                );
+                self.mailing_list_notice(
+                    &ctx,
+                    vec![sender],
+                    format!("Confirm your unsubscription from {}", ctx.address()),
+                    format!(
+                        concat!(
+                            "Somebody, probably you, asked to remove this address from the ",
+                            "{} mailing list.\r\n\r\nTo confirm, reply to this message or ",
+                            "send an empty message to <{}>.\r\n\r\nIf you did not request ",
+                            "to be removed, please ignore this message.\r\n"
+                        ),
+                        ctx.address(),
+                        ctx.command_address(&format!("unsubscribe-{token}"))
+                    ),
+                    Some(ctx.command_address(&format!("unsubscribe-{token}"))),
+                )
+                .await;
+                Ok(DeliveryResult::Success)
+            }
+            Some(command) if command.starts_with("unsubscribe-") => {
+                if self.is_valid_confirm_token(
+                    ctx.list_id,
+                    "unsubscribe",
+                    &sender,
+                    &command["unsubscribe-".len()..],
+                ) {
+                    match self.store.remove_subscriber(ctx.list_id, &sender).await {
+                        Ok(true) => {
+                            self.mailing_list_notice(
+                                &ctx,
+                                vec![sender],
+                                format!("Unsubscribed from {}", ctx.address()),
+                                format!(
+                                    "This address has been removed from the {} mailing list.\r\n",
+                                    ctx.address()
+                                ),
+                                None,
+                            )
+                            .await;
+                            Ok(DeliveryResult::Success)
+                        }
+                        Ok(false) => Ok(DeliveryResult::Success),
+                        Err(err) => Err(err),
+                    }
+                } else {
+                    Ok(DeliveryResult::PermanentFailure {
+                        code: [5, 7, 1],
+                        reason: "Invalid or expired confirmation token.".into(),
+                    })
+                }
+            }
+            Some(command) if command.starts_with("approve-") || command.starts_with("reject-") => {
+                let (action, token) = command.split_once('-').unwrap();
+                let id = token
+                    .split_once('-')
+                    .and_then(|(id, _)| id.parse::<u64>().ok())
+                    .filter(|id| token == self.list_moderation_token(ctx.list_id, *id));
+                if !ctx.is_moderator(&sender, sender_authenticated) {
+                    Ok(DeliveryResult::PermanentFailure {
+                        code: [5, 7, 1],
+                        reason: "Only list moderators may approve or reject messages.".into(),
+                    })
+                } else if let Some(id) = id {
+                    self.mailing_list_release(&ctx, id, action == "approve")
+                        .await
+                        .map(|_| DeliveryResult::Success)
+                } else {
+                    Ok(DeliveryResult::PermanentFailure {
+                        code: [5, 1, 1],
+                        reason: "Invalid held message id.".into(),
+                    })
+                }
+            }
+            Some(_) => Ok(DeliveryResult::PermanentFailure {
+                code: [5, 1, 1],
+                reason: "Unknown mailing list command.".into(),
+            }),
+        };
+
+        Some(result.unwrap_or_else(|err| {
+            tracing::error!(
+                context = "mailing_list",
+                event = "error",
+                list = ctx.address(),
+                reason = ?err,
+                "Failed to process mailing list message."
+            );
+            temporary_failure()
+        }))
+    }
+
+    pub async fn mailing_list_context(
+        &self,
+        list_id: u32,
+    ) -> directory::Result<Option<ListContext>> {
+        let list = if let Some(list) = self.store.get_mailing_list(list_id).await? {
+            list
+        } else {
+            return Ok(None);
+        };
+        let principal =
+            if let Some(principal) = self.store.query(QueryBy::Id(list_id), false).await? {
+                principal
+            } else {
+                return Ok(None);
+            };
+
+        Ok(principal
+            .emails
+            .first()
+            .and_then(|address| address.rsplit_once('@'))
+            .map(|(name, domain)| ListContext {
+                list_id,
+                list,
+                name: name.to_string(),
+                domain: domain.to_string(),
+                description: principal.description.clone(),
+                quota: principal.quota as i64,
+            }))
+    }
+
+    async fn mailing_list_post(
+        &self,
+        ctx: &ListContext,
+        raw_message: &[u8],
+        sender: &str,
+        sender_authenticated: bool,
+    ) -> directory::Result<DeliveryResult> {
+        let message = if let Some(message) = MessageParser::new().parse(raw_message) {
+            message
+        } else {
+            return Ok(DeliveryResult::PermanentFailure {
+                code: [5, 5, 0],
+                reason: "Failed to parse e-mail message.".into(),
+            });
+        };
+
+        // Avoid mail loops
+        let list_id_header = ctx.list_id_header();
+        if sender.is_empty()
+            || message.root_part().headers().iter().any(|header| {
+                header.name.as_str().eq_ignore_ascii_case("List-Id")
+                    && std::str::from_utf8(
+                        raw_message
+                            .get(header.offset_start..header.offset_end)
+                            .unwrap_or_default(),
+                    )
+                    .unwrap_or_default()
+                    .contains(&list_id_header)
+            })
+        {
+            tracing::debug!(
+                context = "mailing_list",
+                event = "loop",
+                list = ctx.address(),
+                from = sender,
+                "Discarding message that already went through this list."
+            );
+            return Ok(DeliveryResult::Success);
+        }
+
+        // Apply posting policy
+        if !ctx.is_moderator(sender, sender_authenticated) {
+            match ctx.list.policy {
+                PostingPolicy::Open => (),
+                PostingPolicy::MembersOnly => {
+                    if !self.is_list_member(ctx, sender).await? {
+                        return Ok(DeliveryResult::PermanentFailure {
+                            code: [5, 7, 1],
+                            reason: "Only list members may post to this list.".into(),
+                        });
+                    }
+                }
+                PostingPolicy::AnnounceOnly => {
+                    return Ok(DeliveryResult::PermanentFailure {
+                        code: [5, 7, 1],
+                        reason: "Only list moderators may post to this list.".into(),
+                    });
+                }
+                PostingPolicy::Moderated => {
+                    return self
+                        .mailing_list_hold(ctx, raw_message, &message, sender)
+                        .await
+                        .map(|_| DeliveryResult::Success);
+                }
+            }
+        }
+
+        self.mailing_list_distribute(ctx, raw_message, &message)
+            .await
+            .map(|_| DeliveryResult::Success)
+    }
+
+    async fn mailing_list_hold(
+        &self,
+        ctx: &ListContext,
+        raw_message: &[u8],
+        message: &Message<'_>,
+        sender: &str,
+    ) -> directory::Result<()> {
+        // Reserve the blob until the message expires
+        let hash = BlobHash::from(raw_message);
+        let received = now();
+        let expires = received + self.config.load().list_moderation_expiry;
+        let mut batch = BatchBuilder::new();
+        batch.with_account_id(ctx.list_id).set(
+            BlobOp::Reserve {
+                hash: hash.clone(),
+                until: expires,
+            },
+            0u32.serialize(),
+        );
+        self.store.write(batch.build()).await?;
+        if !self.store.blob_exists(&hash).await? {
+            self.blob_store.put_blob(hash.as_ref(), raw_message).await?;
+            let mut batch = BatchBuilder::new();
+            batch.set(BlobOp::Commit { hash: hash.clone() }, Vec::new());
+            self.store.write(batch.build()).await?;
+        }
+
+        let held = HeldMessage {
+            id: self.snowflake_id.generate().unwrap_or(received),
+            sender: sender.to_string(),
+            subject: message.subject().unwrap_or_default().to_string(),
+            blob_hash: hash,
+            size: raw_message.len(),
+            received,
+            expires,
+        };
+        let id = held.id;
+        let subject = held.subject.clone();
+        self.store.hold_message(ctx.list_id, held).await?;
+
+        // Notify moderators
+        self.mailing_list_notice(
+            ctx,
+            ctx.list.moderators.clone(),
+            format!("Message to {} awaiting moderation", ctx.address()),
+            format!(
+                concat!(
+                    "A message from <{}> with subject \"{}\" is awaiting moderation.\r\n\r\n",
+                    "To approve it, send an empty message to <{}>.\r\n",
+                    "To reject it, send an empty message to <{}>.\r\n"
+                ),
+                sender,
+                subject,
+                ctx.command_address(&format!(
+                    "approve-{}",
+                    self.list_moderation_token(ctx.list_id, id)
+                )),
+                ctx.command_address(&format!(
+                    "reject-{}",
+                    self.list_moderation_token(ctx.list_id, id)
+                )),
+            ),
+            None,
+        )
+        .await;
+
+        Ok(())
+    }
+
+    // Approves or rejects a held message, returns false if it does not exist
+    pub async fn mailing_list_release(
+        &self,
+        ctx: &ListContext,
+        id: u64,
+        approve: bool,
+    ) -> directory::Result<bool> {
+        let held = if let Some(held) = self.store.take_held_message(ctx.list_id, id).await? {
+            held
+        } else {
+            return Ok(false);
+        };
+
+        if approve {
+            if let Some(raw_message) = self
+                .blob_store
+                .get_blob(held.blob_hash.as_ref(), 0..u32::MAX)
+                .await?
+            {
+                if let Some(message) = MessageParser::new().parse(&raw_message) {
+                    self.mailing_list_distribute(ctx, &raw_message, &message)
+                        .await?;
+                }
+            }
+        } else {
+            self.mailing_list_notice(
+                ctx,
+                vec![held.sender],
+                format!("Message to {} rejected", ctx.address()),
+                format!(
+                    "Your message with subject \"{}\" was rejected by the moderators of {}.\r\n",
+                    held.subject,
+                    ctx.address()
+                ),
+                None,
+            )
+            .await;
+        }
+
+        Ok(true)
+    }
+
+    async fn mailing_list_distribute(
+        &self,
+        ctx: &ListContext,
+        raw_message: &[u8],
+        message: &Message<'_>,
+    ) -> directory::Result<()> {
+        let headers = self.mailing_list_headers(ctx, raw_message, message).await;
+        let body = raw_message
+            .get(message.root_part().offset_body..)
+            .unwrap_or_default();
+
+        // Each recipient gets its own one-click unsubscribe link
+        let return_path = ctx.command_address("bounces");
+        for rcpt in self.list_recipients(ctx).await? {
+            let mut raw_message = Vec::with_capacity(headers.len() + body.len() + 256);
+            raw_message.extend_from_slice(b"List-Unsubscribe: <mailto:");
+            raw_message.extend_from_slice(ctx.command_address("unsubscribe").as_bytes());
+            raw_message.push(b'>');
+            if let Some(base_url) = &self.config.load().list_base_url {
+                raw_message.extend_from_slice(b",\r\n\t<");
+                raw_message.extend_from_slice(base_url.as_bytes());
+                raw_message.extend_from_slice(b"/list/unsubscribe/");
+                raw_message
+                    .extend_from_slice(self.list_unsubscribe_token(ctx.list_id, &rcpt).as_bytes());
+                raw_message
+                    .extend_from_slice(b">\r\nList-Unsubscribe-Post: List-Unsubscribe=One-Click");
+            }
+            raw_message.extend_from_slice(b"\r\n");
+            raw_message.extend_from_slice(&headers);
+            raw_message.extend_from_slice(b"\r\n");
+            raw_message.extend_from_slice(body);
+
+            let result = Session::<NullIo>::sieve(
+                self.smtp.load_full(),
+                SessionAddress::new(return_path.clone()),
+                vec![SessionAddress::new(rcpt)],
+                raw_message,
+            )
+            .queue_message()
+            .await;
+
+            tracing::debug!(
+                context = "mailing_list",
+                event = "distribute",
+                list = ctx.address(),
+                smtp_response = std::str::from_utf8(&result).unwrap_or_default()
+            );
+        }
+
+        // Store a copy in the list's archive
+        if ctx.list.archive {
+            let mut raw_message = Vec::with_capacity(headers.len() + body.len() + 2);
+            raw_message.extend_from_slice(&headers);
+            raw_message.extend_from_slice(b"\r\n");
+            raw_message.extend_from_slice(body);
+
+            match self
+                .email_ingest(IngestEmail {
+                    raw_message: &raw_message,
+                    message: MessageParser::new().parse(&raw_message),
+                    account_id: ctx.list_id,
+                    account_quota: ctx.quota,
+                    mailbox_ids: vec![INBOX_ID],
+                    keywords: vec![],
+                    received_at: None,
+                    skip_duplicates: true,
+                    encrypt: false,
+                    authenticated_sender: None,
+                    is_delivery: false,
+                })
+                .await
+            {
+                Ok(ingested_message) => {
+                    if ingested_message.change_id != u64::MAX {
+                        self.broadcast_state_change(
+                            StateChange::new(ctx.list_id)
+                                .with_change(DataType::EmailDelivery, ingested_message.change_id)
+                                .with_change(DataType::Email, ingested_message.change_id)
+                                .with_change(DataType::Mailbox, ingested_message.change_id)
+                                .with_change(DataType::Thread, ingested_message.change_id),
+                        )
+                        .await;
+                    }
+                }
+                Err(err) => {
+                    tracing::warn!(
+                        context = "mailing_list",
+                        event = "error",
+                        list = ctx.address(),
+                        reason = ?err,
+                        "Failed to archive mailing list message."
+                    );
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn mailing_list_headers(
+        &self,
+        ctx: &ListContext,
+        raw_message: &[u8],
+        message: &Message<'_>,
+    ) -> Vec<u8> {
+        let from_header = message
+            .root_part()
+            .headers()
+            .iter()
+            .find(|header| header.name == HeaderName::From);
+        let from = message.from().and_then(|from| from.first());
+        let rewrite_from = match ctx.list.rewrite_from {
+            FromRewrite::Never => false,
+            FromRewrite::Always => true,
+            FromRewrite::Dmarc => self.has_strict_dmarc_policy(raw_message).await,
+        } && from_header.is_some();
+        let subject_prefix = ctx.list.subject_prefix.as_deref().filter(|prefix| {
+            !message
+                .subject()
+                .unwrap_or_default()
+                .contains(prefix.trim())
+        });
+
+        let mut headers = Vec::with_capacity(message.root_part().offset_body + 512);
+        write_header(&mut headers, "List-Id", &{
+            let mut list_id = Vec::new();
+            if let Some(description) = &ctx.description {
+                let _ = Text::new(description.as_str()).write_header(&mut list_id, 0);
+                list_id.truncate(list_id.len().saturating_sub(2));
+                list_id.push(b' ');
+            }
+            list_id.extend_from_slice(ctx.list_id_header().as_bytes());
+            list_id
+        });
+        write_header(
+            &mut headers,
+            "List-Post",
+            if ctx.list.policy == PostingPolicy::AnnounceOnly {
+                b"NO".to_vec()
+            } else {
+                format!("<mailto:{}>", ctx.address()).into_bytes()
+            }
+            .as_slice(),
+        );
+        write_header(
+            &mut headers,
+            "List-Subscribe",
+            format!("<mailto:{}>", ctx.command_address("subscribe")).as_bytes(),
+        );
+        write_header(&mut headers, "Precedence", b"list");
+
+        if let (true, Some(from_header)) = (rewrite_from, from_header) {
+            let name = from
+                .and_then(|from| from.name().or_else(|| from.address()))
+                .unwrap_or_default();
+            headers.extend_from_slice(b"From: ");
+            let _ = Address::new_address(
+                format!("{} via {}", name, ctx.display_name()).into(),
+                ctx.address(),
+            )
+            .write_header(&mut headers, 6);
+            headers.extend_from_slice(b"Reply-To:");
+            headers.extend_from_slice(
+                raw_message
+                    .get(from_header.offset_start..from_header.offset_end)
+                    .unwrap_or_default(),
+            );
+        }
+
+        if let Some(prefix) = subject_prefix {
+            headers.extend_from_slice(b"Subject: ");
+            let _ = Text::new(format!(
+                "{} {}",
+                prefix.trim(),
+                message.subject().unwrap_or_default()
+            ))
+            .write_header(&mut headers, 9);
+        }
+
+        for header in message.root_part().headers() {
+            let name = header.name.as_str();
+            if name.len() > 5 && name[..5].eq_ignore_ascii_case("List-")
+                || name.eq_ignore_ascii_case("Precedence")
+                || (rewrite_from && matches!(header.name, HeaderName::From | HeaderName::ReplyTo))
+                || (subject_prefix.is_some() && header.name == HeaderName::Subject)
+            {
+                continue;
+            }
+            headers.extend_from_slice(
+                raw_message
+                    .get(header.offset_field..header.offset_end)
+                    .unwrap_or_default(),
+            );
+        }
+
+        headers
+    }
+
+    async fn list_recipients(&self, ctx: &ListContext) -> directory::Result<Vec<String>> {
+        let mut recipients = Vec::new();
+        let mut seen = AHashSet::new();
+        for member_id in self.store.get_members(ctx.list_id).await? {
+            if let Some(address) = self
+                .store
+                .query(QueryBy::Id(member_id), false)
+                .await?
+                .and_then(|principal| principal.emails.into_iter().next())
+            {
+                if seen.insert(address.to_lowercase()) {
+                    recipients.push(address);
+                }
+            }
+        }
+        for address in self.store.list_subscribers(ctx.list_id).await? {
+            if seen.insert(address.clone()) {
+                recipients.push(address);
+            }
+        }
+
+        Ok(recipients)
+    }
+
+    async fn is_list_member(&self, ctx: &ListContext, address: &str) -> directory::Result<bool> {
+        for member_id in self.store.get_members(ctx.list_id).await? {
+            if self
+                .store
+                .query(QueryBy::Id(member_id), false)
+                .await?
+                .is_some_and(|principal| {
+                    principal
+                        .emails
+                        .iter()
+                        .any(|email| email.eq_ignore_ascii_case(address))
+                })
+            {
+                return Ok(true);
+            }
+        }
+
+        Ok(self
+            .store
+            .list_subscribers(ctx.list_id)
+            .await?
+            .iter()
+            .any(|email| email == address))
+    }
+
+    // Returns true if the From domain publishes a quarantine or reject DMARC policy
+    async fn has_strict_dmarc_policy(&self, raw_message: &[u8]) -> bool {
+        let message = if let Some(message) = AuthenticatedMessage::parse(raw_message) {
+            message
+        } else {
+            return false;
+        };
+
+        // Without SPF or DKIM results the output carries the policy that would be
+        // applied to the From domain, looked up through the organizational domain
+        let resolver = &self.smtp.load_full().resolvers.dns;
+        let spf_output = resolver
+            .verify_spf_helo(Ipv4Addr::LOCALHOST.into(), "localhost", "localhost")
+            .await;
+        matches!(
+            resolver
+                .verify_dmarc(&message, &[], "", &spf_output)
+                .await
+                .policy(),
+            Policy::Quarantine | Policy::Reject
+        )
+    }
+
+    async fn mailing_list_notice(
+        &self,
+        ctx: &ListContext,
+        rcpts: Vec<String>,
+        subject: String,
+        body: String,
+        reply_to: Option<String>,
+    ) {
+        if rcpts.is_empty() {
+            return;
+        }
+
+        let return_path = ctx.command_address("bounces");
+        let mut builder = MessageBuilder::new()
+            .from((ctx.display_name().to_string(), return_path.clone()))
+            .to(rcpts
+                .iter()
+                .map(|rcpt| Address::new_address(None::<&str>, rcpt.as_str()))
+                .collect::<Vec<_>>())
+            .header("Auto-Submitted", Text::new("auto-replied"))
+            .header("List-Id", Text::new(ctx.list_id_header()))
+            .subject(subject)
+            .text_body(body);
+        if let Some(reply_to) = reply_to {
+            builder = builder.reply_to(reply_to);
+        }
+
+        let raw_message = builder.write_to_vec().unwrap_or_default();
+        let result = Session::<NullIo>::sieve(
+            self.smtp.load_full(),
+            SessionAddress::new(return_path),
+            rcpts.into_iter().map(SessionAddress::new).collect(),
+            raw_message,
+        )
+        .queue_message()
+        .await;
+
+        tracing::debug!(
+            context = "mailing_list",
+            event = "notice",
+            list = ctx.address(),
+            smtp_response = std::str::from_utf8(&result).unwrap_or_default()
+        );
+    }
+
+    fn list_mac(&self, list_id: u32, purpose: &str, address: &str) -> blake3::Hash {
+        let mut hasher = blake3::Hasher::new_keyed(&blake3::derive_key(
+            "Stalwart Mailing List",
+            self.config.load().oauth_key.as_bytes(),
+        ));
+        hasher.update(purpose.as_bytes());
+        hasher.update(&list_id.to_be_bytes());
+        hasher.update(address.as_bytes());
+        hasher.finalize()
+    }
+
+    // Confirmation tokens carry their expiration time
+    fn list_confirm_token(
+        &self,
+        list_id: u32,
+        purpose: &str,
+        address: &str,
+        expires: u64,
+    ) -> String {
+        format!(
+            "{expires}-{}",
+            &self
+                .list_mac(list_id, purpose, &format!("{expires}:{address}"))
+                .to_hex()[..20]
+        )
+    }
+
+    fn is_valid_confirm_token(
+        &self,
+        list_id: u32,
+        purpose: &str,
+        address: &str,
+        token: &str,
+    ) -> bool {
+        token
+            .split_once('-')
+            .and_then(|(expires, _)| expires.parse::<u64>().ok())
+            .is_some_and(|expires| {
+                expires >= now()
+                    && token == self.list_confirm_token(list_id, purpose, address, expires)
+            })
+    }
+
+    // Moderation commands are only accepted with the token sent to the moderators
+    fn list_moderation_token(&self, list_id: u32, id: u64) -> String {
+        format!(
+            "{id}-{}",
+            &self.list_mac(list_id, "moderate", &id.to_string()).to_hex()[..20]
+        )
+    }
+
+    pub fn list_unsubscribe_token(&self, list_id: u32, address: &str) -> String {
+        let mut token =
+            Vec::with_capacity(std::mem::size_of::<u32>() + TOKEN_MAC_LEN + address.len());
+        token.extend_from_slice(&list_id.to_be_bytes());
+        token.extend_from_slice(
+            &self.list_mac(list_id, "unsubscribe", address).as_bytes()[..TOKEN_MAC_LEN],
+        );
+        token.extend_from_slice(address.as_bytes());
+        general_purpose::URL_SAFE_NO_PAD.encode(token)
+    }
+
+    fn parse_unsubscribe_token(&self, token: &str) -> Option<(u32, String)> {
+        let token = general_purpose::URL_SAFE_NO_PAD.decode(token).ok()?;
+        let list_id = u32::from_be_bytes(token.get(..4)?.try_into().ok()?);
+        let mac = token.get(4..4 + TOKEN_MAC_LEN)?;
+        let address = std::str::from_utf8(token.get(4 + TOKEN_MAC_LEN..)?).ok()?;
+
+        if self.list_mac(list_id, "unsubscribe", address).as_bytes()[..TOKEN_MAC_LEN] == *mac {
+            Some((list_id, address.to_string()))
+        } else {
+            None
+        }
+    }
+
+    // One-click unsubscribe (RFC 8058), GET requests show a confirmation form
+    pub async fn handle_list_unsubscribe(
+        &self,
+        req: &mut HttpRequest,
+        token: &str,
+    ) -> HttpResponse {
+        let (list_id, address) = if let Some(result) = self.parse_unsubscribe_token(token) {
+            result
+        } else {
+            return HtmlResponse::with_status(
+                StatusCode::BAD_REQUEST,
+                "Invalid unsubscribe link.".to_string(),
+            )
+            .into_http_response();
+        };
+
+        let body = if req.method() == hyper::Method::POST {
+            let params = match FormData::from_request(req, MAX_POST_LEN).await {
+                Ok(params) => params,
+                Err(err) => return err,
+            };
+            if params.get("List-Unsubscribe") != Some("One-Click") {
+                return HtmlResponse::with_status(
+                    StatusCode::BAD_REQUEST,
+                    "Invalid unsubscribe request.".to_string(),
+                )
+                .into_http_response();
+            }
+
+            if let Err(err) = self.store.remove_subscriber(list_id, &address).await {
+                tracing::error!(
+                    context = "mailing_list",
+                    event = "error",
+                    reason = ?err,
+                    "Failed to remove subscriber."
+                );
+                return HtmlResponse::with_status(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "Temporary server failure.".to_string(),
+                )
+                .into_http_response();
+            }
+
+            LIST_HTML_UNSUBSCRIBED.replace("@@@", &html_escape(&address))
+        } else {
+            LIST_HTML_UNSUBSCRIBE.replace("@@@", &html_escape(&address))
+        };
+
+        let mut response = String::with_capacity(
+            OAUTH_HTML_HEADER.len() + body.len() + OAUTH_HTML_FOOTER.len() + token.len(),
+        );
+        response.push_str(&OAUTH_HTML_HEADER.replace("@@@", &format!("/list/unsubscribe/{token}")));
+        response.push_str(&body);
+        response.push_str(OAUTH_HTML_FOOTER);
+
+        HtmlResponse::new(response).into_http_response()
+    }
+}
+
+fn write_header(output: &mut Vec<u8>, name: &str, value: &[u8]) {
+    output.extend_from_slice(name.as_bytes());
+    output.extend_from_slice(b": ");
+    output.extend_from_slice(value);
+    output.extend_from_slice(b"\r\n");
+}
+
+fn html_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn temporary_failure() -> DeliveryResult {
+    DeliveryResult::TemporaryFailure {
+        reason: "Transient server failure.".into(),
+    }
+}
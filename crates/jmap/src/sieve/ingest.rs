@@ -47,6 +47,7 @@ struct SieveMessage<'x> {
     pub raw_message: Cow<'x, [u8]>,
     pub file_into: Vec<u32>,
     pub flags: Vec<Keyword>,
+    pub is_notification: bool,
 }
 
 impl JMAP {
@@ -55,6 +56,7 @@ impl JMAP {
         &self,
         raw_message: &[u8],
         envelope_from: &str,
+        envelope_from_authenticated: bool,
         envelope_to: &str,
         account_id: u32,
         mut active_script: ActiveScript,
@@ -79,17 +81,21 @@ impl JMAP {
         let mut instance = self.sieve_runtime.filter_parsed(message);
 
         // Set account name and obtain quota
-        let (account_quota, mail_from) =
-            match self.directory.query(QueryBy::Id(account_id), false).await {
-                Ok(Some(p)) => {
-                    instance.set_user_full_name(p.description().unwrap_or_else(|| p.name()));
-                    (p.quota as i64, p.emails.into_iter().next())
-                }
-                Ok(None) => (0, None),
-                Err(_) => {
-                    return Err(IngestError::Temporary);
-                }
-            };
+        let (account_quota, mail_from) = match self
+            .directory
+            .load()
+            .query(QueryBy::Id(account_id), false)
+            .await
+        {
+            Ok(Some(p)) => {
+                instance.set_user_full_name(p.description().unwrap_or_else(|| p.name()));
+                (p.quota as i64, p.emails.into_iter().next())
+            }
+            Ok(None) => (0, None),
+            Err(_) => {
+                return Err(IngestError::Temporary);
+            }
+        };
 
         // Set account address
         let mail_from = mail_from.unwrap_or_else(|| envelope_to.to_string());
@@ -110,6 +116,7 @@ impl JMAP {
             raw_message: raw_message.into(),
             file_into: Vec::new(),
             flags: Vec::new(),
+            is_notification: false,
         }];
         let now = now();
         let mut ingested_message = IngestedEmail {
@@ -327,9 +334,19 @@ impl JMAP {
                     } => {
                         input = true.into();
                         if let Some(message) = messages.get(message_id) {
-                            if message.raw_message.len() <= self.config.mail_max_size {
+                            if message.is_notification && !self.is_notify_allowed(account_id) {
+                                tracing::debug!(
+                                    context = "sieve_script_ingest",
+                                    event = "notify_rate_limited",
+                                    account_id = account_id,
+                                    from = mail_from.as_str(),
+                                    "Notification rate limit exceeded."
+                                );
+                                continue;
+                            } else if message.raw_message.len() <= self.config.load().mail_max_size
+                            {
                                 let result = Session::<NullIo>::sieve(
-                                    self.smtp.clone(),
+                                    self.smtp.load_full(),
                                     SessionAddress::new(mail_from.clone()),
                                     match recipient {
                                         Recipient::Address(rcpt) => vec![SessionAddress::new(rcpt)],
@@ -357,7 +374,7 @@ impl JMAP {
                                     event = "message_too_large",
                                     from = mail_from.as_str(),
                                     size = message.raw_message.len(),
-                                    max_size = self.config.mail_max_size
+                                    max_size = self.config.load().mail_max_size
                                 );
                             }
                         } else {
@@ -370,18 +387,40 @@ impl JMAP {
                             continue;
                         }
                     }
+                    Event::Notify { method, .. } => {
+                        // The interpreter turns "mailto" notifications into a CreatedMessage
+                        // followed by a SendMessage event, this event is only received for
+                        // other methods or once the script exceeded its outgoing message limit
+                        tracing::debug!(
+                            context = "sieve_script_ingest",
+                            event = "notify_discarded",
+                            account_id = account_id,
+                            method = method.as_str(),
+                            "Discarding notification, method not supported or limit exceeded."
+                        );
+                        input = false.into();
+                    }
                     Event::ListContains { .. }
                     | Event::Function { .. }
-                    | Event::Notify { .. }
                     | Event::SetEnvelope { .. } => {
                         // Not allowed
                         input = false.into();
                     }
                     Event::CreatedMessage { message, .. } => {
+                        // Messages generated by enotify are subject to rate limiting
+                        let is_notification = MessageParser::new()
+                            .parse_headers(message.as_slice())
+                            .and_then(|message| {
+                                message
+                                    .header_raw("Auto-Submitted")
+                                    .map(|value| value.trim().eq_ignore_ascii_case("auto-notified"))
+                            })
+                            .unwrap_or(false);
                         messages.push(SieveMessage {
                             raw_message: message.into(),
                             file_into: Vec::new(),
                             flags: Vec::new(),
+                            is_notification,
                         });
                         input = true.into();
                     }
@@ -441,7 +480,9 @@ impl JMAP {
                         keywords: sieve_message.flags,
                         received_at: None,
                         skip_duplicates: true,
-                        encrypt: self.config.encrypt,
+                        encrypt: self.config.load().encrypt,
+                        authenticated_sender: envelope_from_authenticated.then_some(envelope_from),
+                        is_delivery: true,
                     })
                     .await
                 {
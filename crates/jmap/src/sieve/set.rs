@@ -97,7 +97,7 @@ impl JMAP {
         // Process creates
         let mut changes = ChangeLogBuilder::new();
         for (id, object) in request.unwrap_create() {
-            if sieve_ids.len() as usize <= self.config.sieve_max_scripts {
+            if sieve_ids.len() as usize <= self.config.load().sieve_max_scripts {
                 match self.sieve_set_item(object, None, &ctx).await? {
                     Ok((mut builder, Some(blob))) => {
                         // Obtain document id
@@ -443,7 +443,7 @@ impl JMAP {
             };
             let value = match (&property, value) {
                 (Property::Name, MaybePatchValue::Value(Value::Text(value))) => {
-                    if value.len() > self.config.sieve_max_script_name {
+                    if value.len() > self.config.load().sieve_max_script_name {
                         return Ok(Err(SetError::invalid_properties()
                             .with_property(property)
                             .with_description("Script name is too long.")));
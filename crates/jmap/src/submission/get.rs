@@ -37,7 +37,7 @@ impl JMAP {
         &self,
         mut request: GetRequest<RequestArguments>,
     ) -> Result<GetResponse, MethodError> {
-        let ids = request.unwrap_ids(self.config.get_max_objects)?;
+        let ids = request.unwrap_ids(self.config.load().get_max_objects)?;
         let properties = request.unwrap_properties(&[
             Property::Id,
             Property::EmailId,
@@ -60,7 +60,7 @@ impl JMAP {
         } else {
             email_submission_ids
                 .iter()
-                .take(self.config.get_max_objects)
+                .take(self.config.load().get_max_objects)
                 .map(Into::into)
                 .collect::<Vec<_>>()
         };
@@ -101,6 +101,7 @@ impl JMAP {
             let (result_tx, result_rx) = oneshot::channel();
             if self
                 .smtp
+                .load_full()
                 .queue
                 .tx
                 .send(queue::Event::Manage(QueueRequest::Status {
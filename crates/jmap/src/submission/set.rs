@@ -81,7 +81,7 @@ impl JMAP {
         next_call: &mut Option<Call<RequestMethod>>,
     ) -> Result<SetResponse, MethodError> {
         let account_id = request.account_id.document_id();
-        let mut response = SetResponse::from_request(&request, self.config.set_max_objects)?;
+        let mut response = SetResponse::from_request(&request, self.config.load().set_max_objects)?;
         let will_destroy = request.unwrap_destroy();
 
         // Process creates
@@ -179,6 +179,7 @@ impl JMAP {
                     let (result_tx, result_rx) = oneshot::channel();
                     if self
                         .smtp
+                        .load_full()
                         .queue
                         .tx
                         .send(queue::Event::Manage(QueueRequest::Cancel {
@@ -578,11 +579,11 @@ impl JMAP {
         // Obtain raw message
         let message = if let Some(message) = self.get_blob(&metadata.blob_hash, 0..u32::MAX).await?
         {
-            if message.len() > self.config.mail_max_size {
+            if message.len() > self.config.load().mail_max_size {
                 return Ok(Err(SetError::new(SetErrorType::InvalidEmail)
                     .with_description(format!(
                         "Message exceeds maximum size of {} bytes.",
-                        self.config.mail_max_size
+                        self.config.load().mail_max_size
                     ))));
             }
 
@@ -594,8 +595,11 @@ impl JMAP {
         };
 
         // Begin local SMTP session
-        let mut session =
-            Session::<NullIo>::local(self.smtp.clone(), instance.clone(), SessionData::default());
+        let mut session = Session::<NullIo>::local(
+            self.smtp.load_full(),
+            instance.clone(),
+            SessionData::default(),
+        );
 
         // MAIL FROM
         let _ = session.handle_mail_from(mail_from).await;
@@ -53,9 +53,9 @@ impl JMAP {
         );
 
         // Set timeouts
-        let throttle = self.config.web_socket_throttle;
-        let timeout = self.config.web_socket_timeout;
-        let heartbeat = self.config.web_socket_heartbeat;
+        let throttle = self.config.load().web_socket_throttle;
+        let timeout = self.config.load().web_socket_timeout;
+        let heartbeat = self.config.load().web_socket_heartbeat;
         let mut last_request = Instant::now();
         let mut last_changes_sent = Instant::now() - throttle;
         let mut last_heartbeat = Instant::now() - heartbeat;
@@ -91,8 +91,8 @@ impl JMAP {
                                 Message::Text(text) => {
                                     let response = match WebSocketMessage::parse(
                                         text.as_bytes(),
-                                        self.config.request_max_calls,
-                                        self.config.request_max_size,
+                                        self.config.load().request_max_calls,
+                                        self.config.load().request_max_size,
                                     ) {
                                         Ok(WebSocketMessage::Request(request)) => {
                                             match self
@@ -32,7 +32,9 @@ use store::config::ConfigStore;
 use tokio::sync::mpsc;
 use utils::{
     config::{Config, ServerProtocol},
-    enable_tracing, wait_for_shutdown, UnwrapFailure,
+    enable_tracing,
+    metrics::enable_metrics,
+    wait_for_shutdown, UnwrapFailure,
 };
 
 #[cfg(not(target_env = "msvc"))]
@@ -56,6 +58,9 @@ async fn main() -> std::io::Result<()> {
     )
     .failed("Failed to enable tracing");
 
+    // Enable metrics
+    enable_metrics(&config).failed("Failed to enable metrics");
+
     // Bind ports and drop privileges
     let mut servers = config.parse_servers().failed("Invalid configuration");
     servers.bind(&config);
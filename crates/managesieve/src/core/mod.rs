@@ -26,6 +26,7 @@ pub mod session;
 
 use std::{borrow::Cow, net::IpAddr, sync::Arc};
 
+use directory::core::scram::ScramSession;
 use imap::core::IMAP;
 use imap_proto::receiver::{CommandParser, Receiver};
 use jmap::{auth::AccessToken, JMAP};
@@ -42,6 +43,7 @@ pub struct Session<T: AsyncRead + AsyncWrite> {
     pub stream: T,
     pub span: tracing::Span,
     pub in_flight: InFlight,
+    pub sasl: Option<ScramSession>,
 }
 
 pub enum State {
@@ -142,7 +144,7 @@ pub enum ResponseCode {
     QuotaMaxScripts,
     QuotaMaxSize,
     Referral,
-    Sasl,
+    Sasl(String),
     TransitionNeeded,
     TryLater,
     Active,
@@ -161,7 +163,12 @@ impl ResponseCode {
             ResponseCode::QuotaMaxScripts => b"QUOTA/MAXSCRIPTS",
             ResponseCode::QuotaMaxSize => b"QUOTA/MAXSIZE",
             ResponseCode::Referral => b"REFERRAL",
-            ResponseCode::Sasl => b"SASL",
+            ResponseCode::Sasl(data) => {
+                buf.extend_from_slice(b"SASL \"");
+                buf.extend_from_slice(data.as_bytes());
+                buf.push(b'"');
+                return;
+            }
             ResponseCode::TransitionNeeded => b"TRANSITION-NEEDED",
             ResponseCode::TryLater => b"TRYLATER",
             ResponseCode::Active => b"ACTIVE",
@@ -48,6 +48,7 @@ impl SessionManager for ManageSieveSessionManager {
                 stream: session.stream,
                 in_flight: session.in_flight,
                 remote_addr: session.remote_ip,
+                sasl: None,
             };
 
             if session
@@ -55,7 +56,7 @@ impl SessionManager for ManageSieveSessionManager {
                 .await
                 .is_ok()
                 && session.handle_conn().await
-                && session.instance.acceptor.is_tls()
+                && session.instance.acceptor.load().is_tls()
             {
                 if let Ok(mut session) = session.into_tls().await {
                     session.handle_conn().await;
@@ -152,6 +153,7 @@ impl<T: SessionStream> Session<T> {
             imap: self.imap,
             receiver: self.receiver,
             remote_addr: self.remote_addr,
+            sasl: None,
         })
     }
 }
@@ -23,20 +23,26 @@
 
 use std::sync::Arc;
 
-use directory::AuthResult;
+use directory::{
+    core::scram::{ScramAlgorithm, ScramSession},
+    AuthResult,
+};
 use imap::op::authenticate::{decode_challenge_oauth, decode_challenge_plain};
 use imap_proto::{
     protocol::authenticate::Mechanism,
     receiver::{self, Request},
 };
+use jmap::auth::AccessToken;
+use mail_builder::encoders::base64::base64_encode;
 use mail_parser::decoders::base64::base64_decode;
 use mail_send::Credentials;
-use utils::listener::SessionStream;
+use utils::{config::ServerProtocol, listener::SessionStream};
 
-use crate::core::{Command, Session, State, StatusResponse};
+use crate::core::{Command, ResponseCode, Session, State, StatusResponse};
 
 impl<T: SessionStream> Session<T> {
     pub async fn handle_authenticate(&mut self, request: Request<Command>) -> crate::op::OpResult {
+        let sasl = self.sasl.take();
         if request.tokens.is_empty() {
             return Err(StatusResponse::no("Authentication mechanism missing."));
         }
@@ -60,15 +66,16 @@ impl<T: SessionStream> Session<T> {
                     }
                     .map_err(StatusResponse::no))?
                 } else {
-                    self.receiver.request = receiver::Request {
-                        tag: String::new(),
-                        command: Command::Authenticate,
-                        tokens: vec![receiver::Token::Argument(mechanism.into_bytes())],
-                    };
-                    self.receiver.state = receiver::State::Argument { last_ch: b' ' };
+                    self.continue_authenticate(mechanism);
                     return Ok(b"{0}\r\n".to_vec());
                 }
             }
+            Mechanism::ScramSha1
+            | Mechanism::ScramSha256
+            | Mechanism::ScramSha1Plus
+            | Mechanism::ScramSha256Plus => {
+                return self.handle_scram(mechanism, params.pop(), sasl).await;
+            }
             _ => {
                 return Err(StatusResponse::no(
                     "Authentication mechanism not supported.",
@@ -76,23 +83,19 @@ impl<T: SessionStream> Session<T> {
             }
         };
 
-        // Throttle authentication requests
-        if self.jmap.is_auth_allowed_soft(&self.remote_addr).is_err() {
-            tracing::debug!(parent: &self.span,
-                event = "disconnect",
-                "Too many authentication attempts, disconnecting.",
-            );
-            return Err(StatusResponse::bye(
-                "Too many authentication requests from this IP address.",
-            ));
-        }
+        self.is_auth_allowed()?;
 
         // Authenticate
         let access_token = match credentials {
             Credentials::Plain { username, secret } | Credentials::XOauth2 { username, secret } => {
                 match self
                     .jmap
-                    .authenticate_plain(&username, &secret, self.remote_addr)
+                    .authenticate_plain(
+                        &username,
+                        &secret,
+                        self.remote_addr,
+                        ServerProtocol::ManageSieve,
+                    )
                     .await
                 {
                     AuthResult::Success(token) => Some(token),
@@ -107,23 +110,131 @@ impl<T: SessionStream> Session<T> {
             Credentials::OAuthBearer { token } => {
                 match self
                     .jmap
-                    .validate_access_token("access_token", &token)
+                    .authenticate_bearer(&token, self.remote_addr, ServerProtocol::ManageSieve)
                     .await
                 {
-                    Ok((account_id, _, _)) => self.jmap.get_access_token(account_id).await,
-                    Err(err) => {
-                        tracing::debug!(
-                            parent: &self.span,
-                            context = "authenticate",
-                            err = err,
-                            "Failed to validate access token."
-                        );
-                        None
+                    AuthResult::Success(token) => Some(token),
+                    AuthResult::Failure => None,
+                    AuthResult::Banned => {
+                        return Err(StatusResponse::bye(
+                            "Too many authentication requests from this IP address.",
+                        ))
                     }
                 }
             }
         };
 
+        self.finish_authenticate(access_token, None).await
+    }
+
+    async fn handle_scram(
+        &mut self,
+        mechanism: Mechanism,
+        response: Option<String>,
+        sasl: Option<ScramSession>,
+    ) -> crate::op::OpResult {
+        let response = match response {
+            Some(response) if response == "*" => {
+                return Err(StatusResponse::no("Authentication cancelled."));
+            }
+            Some(response) => Some(
+                base64_decode(response.as_bytes())
+                    .ok_or_else(|| StatusResponse::no("Failed to decode challenge."))?,
+            ),
+            None => None,
+        };
+
+        match (sasl, response) {
+            (None, None) => {
+                self.continue_authenticate(mechanism);
+                Ok(b"{0}\r\n".to_vec())
+            }
+            (None, Some(client_first)) => {
+                self.is_auth_allowed()?;
+
+                let (algorithm, is_plus) = match mechanism {
+                    Mechanism::ScramSha1 => (ScramAlgorithm::Sha1, false),
+                    Mechanism::ScramSha1Plus => (ScramAlgorithm::Sha1, true),
+                    Mechanism::ScramSha256Plus => (ScramAlgorithm::Sha256, true),
+                    _ => (ScramAlgorithm::Sha256, false),
+                };
+                let mut session = ScramSession::new(
+                    algorithm,
+                    is_plus,
+                    self.stream
+                        .tls_server_end_point(&self.instance.acceptor.load()),
+                );
+                match self
+                    .jmap
+                    .directory
+                    .load()
+                    .scram_challenge(&mut session, &client_first, true)
+                    .await
+                {
+                    Ok(Some(server_first)) => {
+                        self.sasl = session.into();
+                        self.continue_authenticate(mechanism);
+
+                        let mut response = Vec::with_capacity(server_first.len() * 4 / 3 + 6);
+                        response.push(b'"');
+                        response
+                            .extend_from_slice(&base64_encode(&server_first).unwrap_or_default());
+                        response.extend_from_slice(b"\"\r\n");
+                        Ok(response)
+                    }
+                    Ok(None) => Err(StatusResponse::no("Invalid SCRAM client-first message.")),
+                    Err(_) => Err(StatusResponse::database_failure()),
+                }
+            }
+            (Some(mut session), Some(client_final)) => {
+                match self
+                    .jmap
+                    .authenticate_scram(&mut session, &client_final, self.remote_addr)
+                    .await
+                {
+                    AuthResult::Success((access_token, server_final)) => {
+                        self.finish_authenticate(access_token.into(), server_final.into())
+                            .await
+                    }
+                    AuthResult::Failure => self.finish_authenticate(None, None).await,
+                    AuthResult::Banned => Err(StatusResponse::bye(
+                        "Too many authentication requests from this IP address.",
+                    )),
+                }
+            }
+            (Some(_), None) => self.finish_authenticate(None, None).await,
+        }
+    }
+
+    fn continue_authenticate(&mut self, mechanism: Mechanism) {
+        self.receiver.request = receiver::Request {
+            tag: String::new(),
+            command: Command::Authenticate,
+            tokens: vec![receiver::Token::Argument(mechanism.into_bytes())],
+        };
+        self.receiver.state = receiver::State::Argument { last_ch: b' ' };
+    }
+
+    fn is_auth_allowed(&self) -> Result<(), StatusResponse> {
+        // Throttle authentication requests
+        if self.jmap.is_auth_allowed_soft(&self.remote_addr).is_err() {
+            tracing::debug!(parent: &self.span,
+                event = "disconnect",
+                "Too many authentication attempts, disconnecting.",
+            );
+            Err(StatusResponse::bye(
+                "Too many authentication requests from this IP address.",
+            ))
+        } else {
+            Ok(())
+        }
+    }
+
+    async fn finish_authenticate(
+        &mut self,
+        access_token: Option<AccessToken>,
+        server_final: Option<Vec<u8>>,
+    ) -> crate::op::OpResult {
         if let Some(access_token) = access_token {
             // Enforce concurrency limits
             let in_flight = self
@@ -142,7 +253,14 @@ impl<T: SessionStream> Session<T> {
                     in_flight,
                 };
 
-                self.handle_capability("Authentication successful").await
+                let mut response = StatusResponse::ok("Authentication successful");
+                if let Some(server_final) = server_final {
+                    response = response.with_code(ResponseCode::Sasl(
+                        String::from_utf8(base64_encode(&server_final).unwrap_or_default())
+                            .unwrap_or_default(),
+                    ));
+                }
+                self.capabilities(response).await
             } else {
                 tracing::debug!(parent: &self.span,
                     event = "disconnect",
@@ -28,6 +28,10 @@ use crate::core::{Session, StatusResponse};
 
 impl<T: SessionStream> Session<T> {
     pub async fn handle_capability(&self, message: &'static str) -> super::OpResult {
+        self.capabilities(StatusResponse::ok(message)).await
+    }
+
+    pub async fn capabilities(&self, status: StatusResponse) -> super::OpResult {
         let mut response = Vec::with_capacity(128);
         response.extend_from_slice(b"\"IMPLEMENTATION\" \"Stalwart ManageSieve v");
         response.extend_from_slice(env!("CARGO_PKG_VERSION").as_bytes());
@@ -37,11 +41,14 @@ impl<T: SessionStream> Session<T> {
             response.extend_from_slice(b"\"SASL\" \"\"\r\n");
             response.extend_from_slice(b"\"STARTTLS\"\r\n");
         } else {
-            response.extend_from_slice(b"\"SASL\" \"PLAIN OAUTHBEARER\"\r\n");
+            response.extend_from_slice(
+                b"\"SASL\" \"PLAIN OAUTHBEARER SCRAM-SHA-256 SCRAM-SHA-256-PLUS SCRAM-SHA-1 SCRAM-SHA-1-PLUS\"\r\n",
+            );
         };
         if let Some(sieve) = self
             .jmap
             .config
+            .load()
             .capabilities
             .account
             .iter()
@@ -70,6 +77,6 @@ impl<T: SessionStream> Session<T> {
             response.extend_from_slice(b"\"SIEVE\" \"\"\r\n");
         }
 
-        Ok(StatusResponse::ok(message).serialize(response))
+        Ok(status.serialize(response))
     }
 }
@@ -35,8 +35,8 @@ use utils::config::{
 
 use super::{
     if_block::ConfigIf, ArcAuthConfig, ArcSealer, ConfigContext, DkimAuthConfig,
-    DkimCanonicalization, DkimSigner, DmarcAuthConfig, EnvelopeKey, IfBlock, IpRevAuthConfig,
-    MailAuthConfig, SpfAuthConfig, VerifyStrategy,
+    DkimCanonicalization, DkimRotation, DkimSigner, DmarcAuthConfig, EnvelopeKey, IfBlock,
+    IpRevAuthConfig, MailAuthConfig, SpfAuthConfig, VerifyStrategy,
 };
 
 pub trait ConfigAuth {
@@ -74,6 +74,15 @@ impl ConfigAuth for Config {
                     )?
                     .unwrap_or_default()
                     .map_if_block(&ctx.signers, "auth.dkim.sign", "signature")?,
+                managed: self
+                    .parse_if_block("auth.dkim.managed", ctx, &envelope_sender_keys)?
+                    .unwrap_or_default(),
+                rotate: DkimRotation {
+                    interval: self.property("auth.dkim.rotate.interval")?,
+                    grace: self.property_or_static("auth.dkim.rotate.grace", "2d")?,
+                    retire: self.property_or_static("auth.dkim.rotate.retire", "7d")?,
+                    frequency: self.property_or_static("auth.dkim.rotate.frequency", "0 3 *")?,
+                },
             },
             arc: ArcAuthConfig {
                 verify: self
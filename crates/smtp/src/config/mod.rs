@@ -50,8 +50,8 @@ use mail_send::Credentials;
 use regex::Regex;
 use sieve::Sieve;
 use smtp_proto::MtPriority;
-use store::{LookupStore, Store, Stores};
-use utils::config::{ipmask::IpAddrMask, DynValue, Rate, Server, ServerProtocol};
+use store::{BlobStore, LookupStore, Store, Stores};
+use utils::config::{cron::SimpleCron, ipmask::IpAddrMask, DynValue, Rate, Server, ServerProtocol};
 
 use crate::{core::Lookup, inbound::milter};
 
@@ -318,8 +318,11 @@ pub struct RelayHost {
 }
 
 pub struct QueueConfig {
+    pub backend: QueueBackend,
     pub path: IfBlock<PathBuf>,
     pub hash: IfBlock<u64>,
+    pub lock_expiry: Duration,
+    pub poll_interval: Duration,
 
     // Schedule
     pub retry: IfBlock<Vec<Duration>>,
@@ -346,9 +349,17 @@ pub struct QueueConfig {
     // Default store and directory
     pub directory: Arc<Directory>,
     pub data_store: Store,
+    pub blob_store: BlobStore,
     pub lookup_store: LookupStore,
 }
 
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum QueueBackend {
+    #[default]
+    FileSystem,
+    Store,
+}
+
 pub struct QueueOutboundSourceIp {
     pub ipv4: IfBlock<Vec<Ipv4Addr>>,
     pub ipv6: IfBlock<Vec<Ipv6Addr>>,
@@ -371,6 +382,7 @@ pub struct ReportAnalysis {
     pub addresses: Vec<AddressMatch>,
     pub forward: bool,
     pub store: Option<PathBuf>,
+    pub retention: Duration,
     pub report_id: AtomicU64,
 }
 
@@ -496,6 +508,15 @@ pub enum ArcSealer {
 pub struct DkimAuthConfig {
     pub verify: IfBlock<VerifyStrategy>,
     pub sign: IfBlock<Vec<MaybeDynValue<DkimSigner>>>,
+    pub managed: IfBlock<bool>,
+    pub rotate: DkimRotation,
+}
+
+pub struct DkimRotation {
+    pub interval: Option<Duration>,
+    pub grace: Duration,
+    pub retire: Duration,
+    pub frequency: SimpleCron,
 }
 
 pub struct ArcAuthConfig {
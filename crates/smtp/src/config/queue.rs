@@ -83,13 +83,22 @@ impl ConfigQueue for Config {
 
         let default_hostname = self.value_require("server.hostname")?;
 
+        let backend = self
+            .property::<QueueBackend>("queue.backend")?
+            .unwrap_or_default();
+
         let config = QueueConfig {
-            path: self
-                .parse_if_block("queue.path", ctx, &sender_envelope_keys)?
-                .ok_or("Missing \"queue.path\" property.")?,
+            backend,
+            path: match self.parse_if_block("queue.path", ctx, &sender_envelope_keys)? {
+                Some(path) => path,
+                None if backend == QueueBackend::Store => IfBlock::default(),
+                None => return Err("Missing \"queue.path\" property.".to_string()),
+            },
             hash: self
                 .parse_if_block("queue.hash", ctx, &sender_envelope_keys)?
                 .unwrap_or_else(|| IfBlock::new(32)),
+            lock_expiry: self.property_or_static("queue.store.lock-expiry", "15m")?,
+            poll_interval: self.property_or_static("queue.store.poll-interval", "30s")?,
 
             retry: self
                 .parse_if_block("queue.schedule.retry", ctx, &host_envelope_keys)?
@@ -215,6 +224,7 @@ impl ConfigQueue for Config {
                 })?
                 .clone(),
             data_store: ctx.stores.get_store(self, "storage.data")?,
+            blob_store: ctx.stores.get_blob_store(self, "storage.blob")?,
             lookup_store: self
                 .value_or_default("storage.lookup", "storage.data")
                 .and_then(|id| ctx.stores.lookup_stores.get(id))
@@ -465,6 +475,20 @@ impl From<&Host> for RelayHost {
     }
 }
 
+impl ParseValue for QueueBackend {
+    fn parse_value(key: impl AsKey, value: &str) -> super::Result<Self> {
+        match value {
+            "fs" | "file-system" | "filesystem" => Ok(QueueBackend::FileSystem),
+            "store" | "database" => Ok(QueueBackend::Store),
+            _ => Err(format!(
+                "Invalid queue backend {:?} for key {:?}.",
+                value,
+                key.as_key()
+            )),
+        }
+    }
+}
+
 impl ParseValue for RequireOptional {
     fn parse_value(key: impl AsKey, value: &str) -> super::Result<Self> {
         match value {
@@ -21,6 +21,8 @@
  * for more details.
 */
 
+use std::time::Duration;
+
 use super::{
     if_block::ConfigIf, AddressMatch, AggregateFrequency, AggregateReport, ConfigContext,
     EnvelopeKey, IfBlock, Report, ReportAnalysis, ReportConfig,
@@ -97,6 +99,9 @@ impl ConfigReport for Config {
                 addresses,
                 forward: self.property("report.analysis.forward")?.unwrap_or(false),
                 store: self.property("report.analysis.store")?,
+                retention: self
+                    .property("report.analysis.retention")?
+                    .unwrap_or_else(|| Duration::from_secs(30 * 86400)),
                 report_id: 0.into(),
             },
         })
@@ -50,6 +50,7 @@ pub trait ConfigSieve {
 pub struct SieveContext {
     pub psl: PublicSuffix,
     pub bayes_cache: BayesTokenCache,
+    pub bayes_account_cache: BayesTokenCache,
     pub remote_lists: RemoteLists,
 }
 
@@ -81,6 +82,11 @@ impl ConfigSieve for Config {
                 self.property_or_static("bayes.cache.ttl.positive", "1h")?,
                 self.property_or_static("bayes.cache.ttl.negative", "1h")?,
             ),
+            bayes_account_cache: BayesTokenCache::new(
+                self.property_or_static("bayes.account-cache.capacity", "8192")?,
+                self.property_or_static("bayes.account-cache.ttl.positive", "1h")?,
+                self.property_or_static("bayes.account-cache.ttl.negative", "1h")?,
+            ),
             remote_lists: Default::default(),
         };
 
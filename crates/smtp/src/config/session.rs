@@ -532,11 +532,11 @@ impl ParseValue for Mechanism {
                 "PLAIN" => AUTH_PLAIN,
                 "XOAUTH2" => AUTH_XOAUTH2,
                 "OAUTHBEARER" => AUTH_OAUTHBEARER,
-                /*"SCRAM-SHA-256-PLUS" => AUTH_SCRAM_SHA_256_PLUS,
+                "SCRAM-SHA-256-PLUS" => AUTH_SCRAM_SHA_256_PLUS,
                 "SCRAM-SHA-256" => AUTH_SCRAM_SHA_256,
                 "SCRAM-SHA-1-PLUS" => AUTH_SCRAM_SHA_1_PLUS,
                 "SCRAM-SHA-1" => AUTH_SCRAM_SHA_1,
-                "XOAUTH" => AUTH_XOAUTH,
+                /*"XOAUTH" => AUTH_XOAUTH,
                 "9798-M-DSA-SHA1" => AUTH_9798_M_DSA_SHA1,
                 "9798-M-ECDSA-SHA1" => AUTH_9798_M_ECDSA_SHA1,
                 "9798-M-RSA-SHA1-ENC" => AUTH_9798_M_RSA_SHA1_ENC,
@@ -38,12 +38,16 @@ use mail_send::Credentials;
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use tokio::sync::oneshot;
 
-use utils::listener::{limiter::InFlight, SessionData, SessionManager, SessionStream};
+use utils::{
+    config::ServerProtocol,
+    listener::{limiter::InFlight, SessionData, SessionManager, SessionStream},
+};
 
 use crate::{
     queue::{self, instant_to_timestamp, InstantFromTimestamp, QueueId, Status},
     reporting::{
         self,
+        analysis::{Format, IncomingReportId},
         scheduler::{ReportKey, ReportPolicy, ReportType, ReportValue},
     },
 };
@@ -153,6 +157,41 @@ pub struct Report {
     pub size: usize,
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+pub struct IncomingReportSummary {
+    #[serde(rename = "type")]
+    pub type_: String,
+    pub domains: Vec<String>,
+    pub reporter: String,
+    pub from: String,
+    pub subject: String,
+    #[serde(deserialize_with = "deserialize_datetime")]
+    #[serde(serialize_with = "serialize_datetime")]
+    pub received: DateTime,
+    #[serde(deserialize_with = "deserialize_maybe_datetime")]
+    #[serde(serialize_with = "serialize_maybe_datetime")]
+    pub range_from: Option<DateTime>,
+    #[serde(deserialize_with = "deserialize_maybe_datetime")]
+    #[serde(serialize_with = "serialize_maybe_datetime")]
+    pub range_to: Option<DateTime>,
+    pub pass: u64,
+    pub fail: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub report: Option<serde_json::Value>,
+}
+
+#[derive(Debug, Default)]
+pub struct IncomingReportFilter {
+    pub format: Option<Format>,
+    pub domain: Option<String>,
+    pub reporter: Option<String>,
+    pub after: Option<u64>,
+    pub before: Option<u64>,
+    pub min_pass: u64,
+    pub min_fail: u64,
+}
+
 impl SessionManager for SmtpAdminSessionManager {
     fn handle<T: SessionStream>(
         self,
@@ -160,7 +199,7 @@ impl SessionManager for SmtpAdminSessionManager {
     ) -> impl std::future::Future<Output = ()> + Send {
         handle_request(
             session.stream,
-            self.inner,
+            self.inner.load_full(),
             session.remote_ip,
             session.in_flight,
         )
@@ -242,7 +281,12 @@ impl SMTP {
                         .queue
                         .config
                         .directory
-                        .authenticate(&Credentials::Plain { username, secret }, remote_addr, false)
+                        .authenticate(
+                            &Credentials::Plain { username, secret },
+                            remote_addr,
+                            ServerProtocol::Http,
+                            false,
+                        )
                         .await
                     {
                         Ok(AuthResult::Success(principal)) if principal.typ == Type::Superuser => {
@@ -642,6 +686,125 @@ impl SMTP {
                     Some(error) => error.into_bad_request(),
                 }
             }
+            (&Method::GET, "report", "incoming") => {
+                let mut filter = IncomingReportFilter::default();
+                let mut error = None;
+
+                if let Some(query) = uri.query() {
+                    for (key, value) in form_urlencoded::parse(query.as_bytes()) {
+                        match key.as_ref() {
+                            "type" => match Format::parse(value.as_ref()) {
+                                Some(format) => {
+                                    filter.format = format.into();
+                                }
+                                None => {
+                                    error = format!("Invalid report type {value:?}.").into();
+                                    break;
+                                }
+                            },
+                            "domain" => {
+                                filter.domain = value.to_lowercase().into();
+                            }
+                            "reporter" => {
+                                filter.reporter = value.to_lowercase().into();
+                            }
+                            "after" | "before" => match value.parse_datetime() {
+                                Ok(timestamp) => {
+                                    if key == "after" {
+                                        filter.after = timestamp.into();
+                                    } else {
+                                        filter.before = timestamp.into();
+                                    }
+                                }
+                                Err(reason) => {
+                                    error = reason.into();
+                                    break;
+                                }
+                            },
+                            "min-pass" | "min-fail" => match value.parse() {
+                                Ok(count) => {
+                                    if key == "min-pass" {
+                                        filter.min_pass = count;
+                                    } else {
+                                        filter.min_fail = count;
+                                    }
+                                }
+                                Err(_) => {
+                                    error = format!("Invalid count {value:?}.").into();
+                                    break;
+                                }
+                            },
+                            _ => {
+                                error = format!("Invalid parameter {key:?}.").into();
+                                break;
+                            }
+                        }
+                    }
+                }
+
+                match error {
+                    None => match self.list_incoming_reports(&filter).await {
+                        Ok(ids) => (
+                            StatusCode::OK,
+                            serde_json::to_string(&Response { data: ids }).unwrap_or_default(),
+                        ),
+                        Err(err) => err.into_internal_error(),
+                    },
+                    Some(error) => error.into_bad_request(),
+                }
+            }
+            (&Method::GET, "report", "incoming-status") => {
+                let mut report_ids = Vec::new();
+                let mut error = None;
+
+                if let Some(query) = uri.query() {
+                    for (key, value) in form_urlencoded::parse(query.as_bytes()) {
+                        match key.as_ref() {
+                            "id" | "ids" => match value.parse_incoming_report_ids() {
+                                Ok(ids) => {
+                                    report_ids = ids;
+                                }
+                                Err(reason) => {
+                                    error = reason.into();
+                                    break;
+                                }
+                            },
+                            _ => {
+                                error = format!("Invalid parameter {key:?}.").into();
+                                break;
+                            }
+                        }
+                    }
+                }
+
+                match error {
+                    None => {
+                        let mut reports = Vec::with_capacity(report_ids.len());
+                        let mut result = Ok(());
+                        for report_id in &report_ids {
+                            match self.incoming_report(report_id).await {
+                                Ok(report) => {
+                                    reports.push(report);
+                                }
+                                Err(err) => {
+                                    result = Err(err);
+                                    break;
+                                }
+                            }
+                        }
+
+                        match result {
+                            Ok(_) => (
+                                StatusCode::OK,
+                                serde_json::to_string(&Response { data: reports })
+                                    .unwrap_or_default(),
+                            ),
+                            Err(err) => err.into_internal_error(),
+                        }
+                    }
+                    Some(error) => error.into_bad_request(),
+                }
+            }
             _ => (
                 StatusCode::NOT_FOUND,
                 format!(
@@ -847,10 +1010,41 @@ impl Display for ReportKey {
     }
 }
 
+impl IncomingReportFilter {
+    pub fn matches(&self, report: &IncomingReportSummary) -> bool {
+        let received = report.received.to_timestamp();
+
+        self.domain.as_ref().is_none_or(|domain| {
+            report
+                .domains
+                .iter()
+                .any(|d| d.eq_ignore_ascii_case(domain))
+        }) && self.reporter.as_ref().is_none_or(|reporter| {
+            report.reporter.to_lowercase().contains(reporter)
+                || report.from.to_lowercase().contains(reporter)
+        }) && self.after.is_none_or(|after| {
+            report
+                .range_to
+                .as_ref()
+                .map_or(received, |dt| dt.to_timestamp())
+                >= after as i64
+        }) && self.before.is_none_or(|before| {
+            report
+                .range_from
+                .as_ref()
+                .map_or(received, |dt| dt.to_timestamp())
+                <= before as i64
+        }) && report.pass >= self.min_pass
+            && report.fail >= self.min_fail
+    }
+}
+
 trait ParseValues {
     fn parse_timestamp(&self) -> Result<Instant, String>;
+    fn parse_datetime(&self) -> Result<u64, String>;
     fn parse_queue_ids(&self) -> Result<Vec<QueueId>, String>;
     fn parse_report_ids(&self) -> Result<Vec<ReportKey>, String>;
+    fn parse_incoming_report_ids(&self) -> Result<Vec<IncomingReportId>, String>;
 }
 
 impl ParseValues for Cow<'_, str> {
@@ -865,6 +1059,12 @@ impl ParseValues for Cow<'_, str> {
         Err(format!("Invalid timestamp {self:?}."))
     }
 
+    fn parse_datetime(&self) -> Result<u64, String> {
+        DateTime::parse_rfc3339(self.as_ref())
+            .map(|dt| dt.to_timestamp() as u64)
+            .ok_or_else(|| format!("Invalid timestamp {self:?}."))
+    }
+
     fn parse_queue_ids(&self) -> Result<Vec<QueueId>, String> {
         let mut ids = Vec::new();
         for id in self.split(',') {
@@ -909,6 +1109,23 @@ impl ParseValues for Cow<'_, str> {
         }
         Ok(ids)
     }
+
+    fn parse_incoming_report_ids(&self) -> Result<Vec<IncomingReportId>, String> {
+        let mut ids = Vec::new();
+        for id in self.split(',') {
+            if !id.is_empty() {
+                match IncomingReportId::parse(id) {
+                    Some(report_id) => {
+                        ids.push(report_id);
+                    }
+                    None => {
+                        return Err(format!("Failed to parse id {id:?}."));
+                    }
+                }
+            }
+        }
+        Ok(ids)
+    }
 }
 
 trait BadRequest {
@@ -927,6 +1144,27 @@ impl BadRequest for String {
     }
 }
 
+trait InternalError {
+    fn into_internal_error(self) -> (StatusCode, String);
+}
+
+impl InternalError for store::Error {
+    fn into_internal_error(self) -> (StatusCode, String) {
+        tracing::warn!(
+            context = "management",
+            event = "error",
+            reason = %self,
+            "Data store error."
+        );
+
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "{\"error\": \"internal-error\", \"details\": \"Failed to access data store.\"}"
+                .to_string(),
+        )
+    }
+}
+
 fn is_zero(num: &i16) -> bool {
     *num == 0
 }
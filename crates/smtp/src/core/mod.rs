@@ -29,6 +29,7 @@ use std::{
 };
 
 use ahash::AHashMap;
+use arc_swap::ArcSwap;
 use dashmap::DashMap;
 use directory::Directory;
 use mail_auth::{common::lru::LruCache, IprevOutput, Resolver, SpfOutput};
@@ -49,7 +50,7 @@ use tokio_rustls::TlsConnector;
 use tracing::Span;
 use utils::{
     ipc::DeliveryEvent,
-    listener::{limiter::InFlight, stream::NullIo, ServerInstance, TcpAcceptor},
+    listener::{limiter::InFlight, stream::NullIo, ServerInstance},
 };
 
 use crate::{
@@ -67,8 +68,12 @@ use crate::{
     scripts::plugins::lookup::VariableExists,
 };
 
-use self::throttle::{Limiter, ThrottleKey, ThrottleKeyHasherBuilder};
+use self::{
+    dkim::DkimKeys,
+    throttle::{Limiter, ThrottleKey, ThrottleKeyHasherBuilder},
+};
 
+pub mod dkim;
 pub mod if_block;
 pub mod management;
 pub mod params;
@@ -77,38 +82,47 @@ pub mod worker;
 
 #[derive(Clone)]
 pub struct SmtpSessionManager {
-    pub inner: Arc<SMTP>,
+    pub inner: Arc<ArcSwap<SMTP>>,
 }
 
 #[derive(Clone)]
 pub struct SmtpAdminSessionManager {
-    pub inner: Arc<SMTP>,
+    pub inner: Arc<ArcSwap<SMTP>>,
 }
 
 impl SmtpSessionManager {
-    pub fn new(inner: Arc<SMTP>) -> Self {
+    pub fn new(inner: Arc<ArcSwap<SMTP>>) -> Self {
         Self { inner }
     }
 }
 
 impl SmtpAdminSessionManager {
-    pub fn new(inner: Arc<SMTP>) -> Self {
+    pub fn new(inner: Arc<ArcSwap<SMTP>>) -> Self {
         Self { inner }
     }
 }
 
+// The core is replaced as a whole when the configuration is reloaded, the
+// thread pool, throttles, queue and DKIM keys are shared between both copies.
 pub struct SMTP {
-    pub worker_pool: rayon::ThreadPool,
+    pub worker_pool: Arc<rayon::ThreadPool>,
     pub session: SessionCore,
     pub queue: QueueCore,
     pub resolvers: Resolvers,
     pub mail_auth: MailAuthConfig,
+    pub dkim: Arc<DkimKeys>,
     pub report: ReportCore,
     pub sieve: SieveCore,
     #[cfg(feature = "local_delivery")]
     pub delivery_tx: mpsc::Sender<DeliveryEvent>,
 }
 
+impl std::fmt::Debug for SMTP {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SMTP").finish_non_exhaustive()
+    }
+}
+
 pub struct SieveCore {
     pub runtime: Runtime<SieveContext>,
     pub scripts: AHashMap<String, Arc<Sieve>>,
@@ -134,15 +148,16 @@ pub struct DnsCache {
 
 pub struct SessionCore {
     pub config: SessionConfig,
-    pub throttle: DashMap<ThrottleKey, Limiter, ThrottleKeyHasherBuilder>,
+    pub throttle: Arc<DashMap<ThrottleKey, Limiter, ThrottleKeyHasherBuilder>>,
 }
 
 pub struct QueueCore {
     pub config: QueueConfig,
-    pub throttle: DashMap<ThrottleKey, Limiter, ThrottleKeyHasherBuilder>,
-    pub quota: DashMap<ThrottleKey, Arc<QuotaLimiter>, ThrottleKeyHasherBuilder>,
+    pub throttle: Arc<DashMap<ThrottleKey, Limiter, ThrottleKeyHasherBuilder>>,
+    pub quota: Arc<DashMap<ThrottleKey, Arc<QuotaLimiter>, ThrottleKeyHasherBuilder>>,
     pub tx: mpsc::Sender<queue::Event>,
-    pub id_seq: AtomicU32,
+    pub id_seq: Arc<AtomicU32>,
+    pub node_id: u64,
     pub connectors: TlsConnectors,
 }
 
@@ -423,7 +438,7 @@ static ref SIEVE: Arc<ServerInstance> = Arc::new(utils::listener::ServerInstance
     protocol: utils::config::ServerProtocol::Lmtp,
     hostname: "localhost".to_string(),
     data: "localhost".to_string(),
-    acceptor: TcpAcceptor::Plain,
+    acceptor: Default::default(),
     limiter: utils::listener::limiter::ConcurrencyLimiter::new(0),
     shutdown_rx: tokio::sync::watch::channel(false).1,
     proxy_networks: vec![],
@@ -21,24 +21,40 @@
  * for more details.
 */
 
-use directory::AuthResult;
+use directory::{
+    core::scram::{ScramAlgorithm, ScramSession},
+    AuthResult, Principal,
+};
+use mail_builder::encoders::base64::base64_encode;
 use mail_parser::decoders::base64::base64_decode;
 use mail_send::Credentials;
-use smtp_proto::{IntoString, AUTH_LOGIN, AUTH_OAUTHBEARER, AUTH_PLAIN, AUTH_XOAUTH2};
-use tokio::io::{AsyncRead, AsyncWrite};
+use smtp_proto::{
+    IntoString, AUTH_LOGIN, AUTH_OAUTHBEARER, AUTH_PLAIN, AUTH_SCRAM_SHA_1, AUTH_SCRAM_SHA_1_PLUS,
+    AUTH_SCRAM_SHA_256, AUTH_SCRAM_SHA_256_PLUS, AUTH_XOAUTH2,
+};
+use utils::listener::SessionStream;
 
 use crate::core::Session;
 
 pub struct SaslToken {
     mechanism: u64,
     credentials: Credentials<String>,
+    scram: Option<Box<ScramSession>>,
+    principal: Option<Box<Principal<u32>>>,
 }
 
 impl SaslToken {
     pub fn from_mechanism(mechanism: u64) -> Option<SaslToken> {
         match mechanism {
-            AUTH_PLAIN | AUTH_LOGIN => SaslToken {
+            AUTH_PLAIN
+            | AUTH_LOGIN
+            | AUTH_SCRAM_SHA_1
+            | AUTH_SCRAM_SHA_1_PLUS
+            | AUTH_SCRAM_SHA_256
+            | AUTH_SCRAM_SHA_256_PLUS => SaslToken {
                 mechanism,
+                scram: None,
+                principal: None,
                 credentials: Credentials::Plain {
                     username: String::new(),
                     secret: String::new(),
@@ -47,6 +63,8 @@ impl SaslToken {
             .into(),
             AUTH_OAUTHBEARER => SaslToken {
                 mechanism,
+                scram: None,
+                principal: None,
                 credentials: Credentials::OAuthBearer {
                     token: String::new(),
                 },
@@ -54,6 +72,8 @@ impl SaslToken {
             .into(),
             AUTH_XOAUTH2 => SaslToken {
                 mechanism,
+                scram: None,
+                principal: None,
                 credentials: Credentials::XOauth2 {
                     username: String::new(),
                     secret: String::new(),
@@ -65,7 +85,7 @@ impl SaslToken {
     }
 }
 
-impl<T: AsyncWrite + AsyncRead + Unpin> Session<T> {
+impl<T: SessionStream> Session<T> {
     pub async fn handle_sasl_response(
         &mut self,
         token: &mut SaslToken,
@@ -83,6 +103,21 @@ impl<T: AsyncWrite + AsyncRead + Unpin> Session<T> {
                         return Ok(true);
                     }
                 }
+                (
+                    AUTH_SCRAM_SHA_1
+                    | AUTH_SCRAM_SHA_1_PLUS
+                    | AUTH_SCRAM_SHA_256
+                    | AUTH_SCRAM_SHA_256_PLUS,
+                    Credentials::Plain { username, .. },
+                ) => {
+                    if let Some(principal) = token.principal.take() {
+                        let authenticated_as = username.clone();
+                        return self.authenticated(authenticated_as, *principal).await;
+                    } else if token.scram.is_none() {
+                        self.write(b"334 \r\n").await?;
+                        return Ok(true);
+                    }
+                }
                 _ => (),
             }
         } else if let Some(response) = base64_decode(response) {
@@ -166,6 +201,93 @@ impl<T: AsyncWrite + AsyncRead + Unpin> Session<T> {
                         _ => (),
                     }
                 }
+                (
+                    AUTH_SCRAM_SHA_1
+                    | AUTH_SCRAM_SHA_1_PLUS
+                    | AUTH_SCRAM_SHA_256
+                    | AUTH_SCRAM_SHA_256_PLUS,
+                    Credentials::Plain { username, .. },
+                ) if token.principal.is_none() => {
+                    let directory = if let Some(directory) = &self.params.auth_directory {
+                        directory.clone()
+                    } else {
+                        self.write(b"454 4.7.0 Temporary authentication failure\r\n")
+                            .await?;
+                        return Ok(false);
+                    };
+
+                    if let Some(scram) = &mut token.scram {
+                        // Verify client-final message
+                        match directory
+                            .scram_authenticate(scram, &response, self.data.remote_ip)
+                            .await
+                        {
+                            Ok(AuthResult::Success((principal, server_final))) => {
+                                *username = principal.name.clone();
+                                token.principal = Box::new(principal).into();
+                                return self.write_challenge(&server_final).await;
+                            }
+                            Ok(AuthResult::Failure) => {
+                                tracing::debug!(
+                                    parent: &self.span,
+                                    context = "auth",
+                                    event = "authenticate",
+                                    result = "failed"
+                                );
+
+                                return self
+                                    .auth_error(
+                                        b"535 5.7.8 Authentication credentials invalid.\r\n",
+                                    )
+                                    .await;
+                            }
+                            Ok(AuthResult::Banned) => {
+                                tracing::debug!(
+                                    parent: &self.span,
+                                    context = "auth",
+                                    event = "authenticate",
+                                    result = "banned"
+                                );
+
+                                return Err(());
+                            }
+                            Err(_) => {
+                                self.write(b"454 4.7.0 Temporary authentication failure\r\n")
+                                    .await?;
+                                return Ok(false);
+                            }
+                        }
+                    } else {
+                        // Process client-first message
+                        let (algorithm, is_plus) = match token.mechanism {
+                            AUTH_SCRAM_SHA_1 => (ScramAlgorithm::Sha1, false),
+                            AUTH_SCRAM_SHA_1_PLUS => (ScramAlgorithm::Sha1, true),
+                            AUTH_SCRAM_SHA_256_PLUS => (ScramAlgorithm::Sha256, true),
+                            _ => (ScramAlgorithm::Sha256, false),
+                        };
+                        let mut scram = Box::new(ScramSession::new(
+                            algorithm,
+                            is_plus,
+                            self.stream
+                                .tls_server_end_point(&self.instance.acceptor.load()),
+                        ));
+                        match directory
+                            .scram_challenge(&mut scram, &response, false)
+                            .await
+                        {
+                            Ok(Some(server_first)) => {
+                                token.scram = scram.into();
+                                return self.write_challenge(&server_first).await;
+                            }
+                            Ok(None) => (),
+                            Err(_) => {
+                                self.write(b"454 4.7.0 Temporary authentication failure\r\n")
+                                    .await?;
+                                return Ok(false);
+                            }
+                        }
+                    }
+                }
 
                 _ => (),
             }
@@ -174,6 +296,15 @@ impl<T: AsyncWrite + AsyncRead + Unpin> Session<T> {
         self.auth_error(b"500 5.5.6 Invalid challenge.\r\n").await
     }
 
+    async fn write_challenge(&mut self, challenge: &[u8]) -> Result<bool, ()> {
+        let mut buf = Vec::with_capacity(challenge.len() * 4 / 3 + 8);
+        buf.extend_from_slice(b"334 ");
+        buf.extend_from_slice(&base64_encode(challenge).unwrap_or_default());
+        buf.extend_from_slice(b"\r\n");
+        self.write(&buf).await?;
+        Ok(true)
+    }
+
     pub async fn authenticate(&mut self, credentials: Credentials<String>) -> Result<bool, ()> {
         if let Some(lookup) = &self.params.auth_directory {
             let authenticated_as = match &credentials {
@@ -183,27 +314,16 @@ impl<T: AsyncWrite + AsyncRead + Unpin> Session<T> {
             };
 
             match lookup
-                .authenticate(&credentials, self.data.remote_ip, false)
+                .authenticate(
+                    &credentials,
+                    self.data.remote_ip,
+                    self.instance.protocol,
+                    false,
+                )
                 .await
             {
                 Ok(AuthResult::Success(principal)) => {
-                    tracing::debug!(
-                        parent: &self.span,
-                        context = "auth",
-                        event = "authenticate",
-                        result = "success"
-                    );
-
-                    self.data.authenticated_as = authenticated_as.to_lowercase();
-                    self.data.authenticated_emails = principal
-                        .emails
-                        .into_iter()
-                        .map(|e| e.trim().to_lowercase())
-                        .collect();
-                    self.eval_post_auth_params().await;
-                    self.write(b"235 2.7.0 Authentication succeeded.\r\n")
-                        .await?;
-                    return Ok(false);
+                    return self.authenticated(authenticated_as, principal).await;
                 }
                 Ok(AuthResult::Failure) => {
                     tracing::debug!(
@@ -243,6 +363,30 @@ impl<T: AsyncWrite + AsyncRead + Unpin> Session<T> {
         Ok(false)
     }
 
+    async fn authenticated(
+        &mut self,
+        authenticated_as: String,
+        principal: Principal<u32>,
+    ) -> Result<bool, ()> {
+        tracing::debug!(
+            parent: &self.span,
+            context = "auth",
+            event = "authenticate",
+            result = "success"
+        );
+
+        self.data.authenticated_as = authenticated_as.to_lowercase();
+        self.data.authenticated_emails = principal
+            .emails
+            .into_iter()
+            .map(|e| e.trim().to_lowercase())
+            .collect();
+        self.eval_post_auth_params().await;
+        self.write(b"235 2.7.0 Authentication succeeded.\r\n")
+            .await?;
+        Ok(false)
+    }
+
     pub async fn auth_error(&mut self, response: &[u8]) -> Result<bool, ()> {
         tokio::time::sleep(self.params.auth_errors_wait).await;
         self.data.auth_errors += 1;
@@ -38,6 +38,7 @@ use sieve::runtime::Variable;
 use smtp_proto::{
     MAIL_BY_RETURN, RCPT_NOTIFY_DELAY, RCPT_NOTIFY_FAILURE, RCPT_NOTIFY_NEVER, RCPT_NOTIFY_SUCCESS,
 };
+use store::BlobHash;
 use tokio::{io::AsyncWriteExt, process::Command};
 use utils::listener::SessionStream;
 
@@ -169,6 +170,14 @@ impl<T: SessionStream> Session<T> {
         // Build authentication results header
         let mail_from = self.data.mail_from.as_ref().unwrap();
         let mut auth_results = AuthenticationResults::new(&self.instance.hostname);
+
+        // The envelope sender is verified by SMTP AUTH or by an SPF pass aligned with the From domain
+        let mut is_sender_authenticated = !self.data.authenticated_as.is_empty()
+            && (self.data.authenticated_as == mail_from.address_lcase
+                || self
+                    .data
+                    .authenticated_emails
+                    .contains(&mail_from.address_lcase));
         if !dkim_output.is_empty() {
             auth_results = auth_results.with_dkim_results(&dkim_output, auth_message.from())
         }
@@ -220,6 +229,7 @@ impl<T: SessionStream> Session<T> {
 
                 // Add to DMARC output to the Authentication-Results header
                 auth_results = auth_results.with_dmarc_result(&dmarc_output);
+                is_sender_authenticated |= dmarc_output.spf_result() == &DmarcResult::Pass;
                 let dmarc_result = if dmarc_output.spf_result() == &DmarcResult::Pass
                     || dmarc_output.dkim_result() == &DmarcResult::Pass
                 {
@@ -276,6 +286,7 @@ impl<T: SessionStream> Session<T> {
             }
             _ => (None, None),
         };
+        let authenticated_sender = is_sender_authenticated.then(|| mail_from.address_lcase.clone());
 
         // Analyze reports
         if self.is_report() {
@@ -496,6 +507,9 @@ impl<T: SessionStream> Session<T> {
         let mail_from = self.data.mail_from.clone().unwrap();
         let rcpt_to = std::mem::take(&mut self.data.rcpt_to);
         let mut message = self.build_message(mail_from, rcpt_to).await;
+        if authenticated_sender.as_ref() == Some(&message.return_path_lcase) {
+            message.flags |= queue::MAIL_SENDER_AUTHENTICATED;
+        }
 
         // Add Received header
         if *dc.add_received.eval(self).await {
@@ -560,8 +574,14 @@ impl<T: SessionStream> Session<T> {
         }
 
         // DKIM sign
+        let mut signers = ac.dkim.sign.eval_and_capture(self).await.into_value(self);
+        if *ac.dkim.managed.eval(self).await {
+            if let Some((_, domain)) = auth_message.from().rsplit_once('@') {
+                signers.extend(self.core.dkim.signers(domain));
+            }
+        }
         let raw_message = edited_message.unwrap_or(raw_message);
-        for signer in ac.dkim.sign.eval_and_capture(self).await.into_value(self) {
+        for signer in signers {
             match signer.sign_chained(&[headers.as_ref(), &raw_message]) {
                 Ok(signature) => {
                     signature.write_header(&mut headers);
@@ -615,6 +635,8 @@ impl<T: SessionStream> Session<T> {
         let mut message = Box::new(Message {
             id: self.core.queue.queue_id(),
             path: PathBuf::new(),
+            blob_hash: BlobHash::default(),
+            event_due: 0,
             created: SystemTime::now()
                 .duration_since(SystemTime::UNIX_EPOCH)
                 .map_or(0, |d| d.as_secs()),
@@ -157,6 +157,9 @@ impl<T: SessionStream> Session<T> {
                 if !self.stream.is_tls() && !self.params.auth_plain_text {
                     response.auth_mechanisms &= !(AUTH_PLAIN | AUTH_LOGIN);
                 }
+                if !self.stream.is_tls() {
+                    response.auth_mechanisms &= !(AUTH_SCRAM_SHA_1_PLUS | AUTH_SCRAM_SHA_256_PLUS);
+                }
                 if response.auth_mechanisms != 0 {
                     response.capabilities |= EXT_AUTH;
                 }
@@ -140,7 +140,7 @@ impl<T: SessionStream> Session<T> {
                             }
                             Request::StartTls => {
                                 if !self.stream.is_tls() {
-                                    if self.instance.acceptor.is_tls() {
+                                    if self.instance.acceptor.load().is_tls() {
                                         self.write(b"220 2.0.0 Ready to start TLS.\r\n").await?;
                                         #[cfg(any(test, feature = "test_mode"))]
                                         if self.data.helo_domain.contains("badtls") {
@@ -39,7 +39,7 @@ impl SessionManager for SmtpSessionManager {
     ) -> impl std::future::Future<Output = ()> + Send {
         // Create session
         let mut session = Session {
-            core: self.inner,
+            core: self.inner.load_full(),
             instance: session.instance,
             state: State::default(),
             span: session.span,
@@ -54,7 +54,7 @@ impl SessionManager for SmtpSessionManager {
             if session.is_allowed().await
                 && session.init_conn().await
                 && session.handle_conn().await
-                && session.instance.acceptor.is_tls()
+                && session.instance.acceptor.load().is_tls()
             {
                 if let Ok(mut session) = session.into_tls().await {
                     session.handle_conn().await;
@@ -65,15 +65,12 @@ impl SessionManager for SmtpSessionManager {
 
     #[allow(clippy::manual_async_fn)]
     fn shutdown(&self) -> impl std::future::Future<Output = ()> + Send {
-        async {
-            let _ = self.inner.queue.tx.send(queue::Event::Stop).await;
-            let _ = self.inner.report.tx.send(reporting::Event::Stop).await;
+        let core = self.inner.load_full();
+        async move {
+            let _ = core.queue.tx.send(queue::Event::Stop).await;
+            let _ = core.report.tx.send(reporting::Event::Stop).await;
             #[cfg(feature = "local_delivery")]
-            let _ = self
-                .inner
-                .delivery_tx
-                .send(utils::ipc::DeliveryEvent::Stop)
-                .await;
+            let _ = core.delivery_tx.send(utils::ipc::DeliveryEvent::Stop).await;
         }
     }
 }
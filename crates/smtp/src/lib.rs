@@ -22,10 +22,13 @@
 */
 
 use crate::core::{
-    throttle::ThrottleKeyHasherBuilder, QueueCore, ReportCore, SessionCore, TlsConnectors, SMTP,
+    dkim::{DkimKeys, SpawnDkimRotation},
+    throttle::ThrottleKeyHasherBuilder,
+    QueueCore, ReportCore, SessionCore, TlsConnectors, SMTP,
 };
 use std::sync::Arc;
 
+use arc_swap::ArcSwap;
 use config::{
     auth::ConfigAuth, queue::ConfigQueue, remote::ConfigHost, report::ConfigReport,
     resolver::ConfigResolver, scripts::ConfigSieve, session::ConfigSession, ConfigContext, Host,
@@ -60,36 +63,9 @@ impl SMTP {
         stores: &Stores,
         directory: &Directories,
         #[cfg(feature = "local_delivery")] delivery_tx: mpsc::Sender<utils::ipc::DeliveryEvent>,
-    ) -> Result<Arc<Self>, String> {
-        // Read configuration parameters
-        let mut config_ctx = ConfigContext::new(&servers.inner);
-        config_ctx.directory = directory.clone();
-        config_ctx.stores = stores.clone();
-
-        // Parse remote hosts
-        config.parse_remote_hosts(&mut config_ctx)?;
-
-        // Add local delivery host
-        #[cfg(feature = "local_delivery")]
-        {
-            config_ctx.hosts.insert(
-                "local".to_string(),
-                Host {
-                    address: String::new(),
-                    port: 0,
-                    protocol: ServerProtocol::Jmap,
-                    concurrency: Default::default(),
-                    timeout: Default::default(),
-                    tls_implicit: Default::default(),
-                    tls_allow_invalid_certs: Default::default(),
-                    username: Default::default(),
-                    secret: Default::default(),
-                },
-            );
-        }
-
+    ) -> Result<Arc<ArcSwap<Self>>, String> {
         // Parse configuration
-        config.parse_signatures(&mut config_ctx)?;
+        let mut config_ctx = Self::parse_context(config, servers, stores, directory)?;
         let sieve_config = config.parse_sieve(&mut config_ctx)?;
         let session_config = config.parse_session_config(&config_ctx)?;
         let queue_config = config.parse_queue(&config_ctx)?;
@@ -100,46 +76,51 @@ impl SMTP {
         let (queue_tx, queue_rx) = mpsc::channel(1024);
         let (report_tx, report_rx) = mpsc::channel(1024);
         let core = Arc::new(SMTP {
-            worker_pool: rayon::ThreadPoolBuilder::new()
-                .num_threads(
-                    config
-                        .property::<usize>("global.thread-pool")?
-                        .filter(|v| *v > 0)
-                        .unwrap_or_else(num_cpus::get),
-                )
-                .build()
-                .unwrap(),
+            worker_pool: Arc::new(
+                rayon::ThreadPoolBuilder::new()
+                    .num_threads(
+                        config
+                            .property::<usize>("global.thread-pool")?
+                            .filter(|v| *v > 0)
+                            .unwrap_or_else(num_cpus::get),
+                    )
+                    .build()
+                    .unwrap(),
+            ),
             resolvers: config.build_resolvers().failed("Failed to build resolvers"),
             session: SessionCore {
                 config: session_config,
-                throttle: DashMap::with_capacity_and_hasher_and_shard_amount(
+                throttle: Arc::new(DashMap::with_capacity_and_hasher_and_shard_amount(
                     config.property("global.shared-map.capacity")?.unwrap_or(2),
                     ThrottleKeyHasherBuilder::default(),
                     config
                         .property::<u64>("global.shared-map.shard")?
                         .unwrap_or(32)
                         .next_power_of_two() as usize,
-                ),
+                )),
             },
             queue: QueueCore {
                 config: queue_config,
-                throttle: DashMap::with_capacity_and_hasher_and_shard_amount(
+                throttle: Arc::new(DashMap::with_capacity_and_hasher_and_shard_amount(
                     config.property("global.shared-map.capacity")?.unwrap_or(2),
                     ThrottleKeyHasherBuilder::default(),
                     config
                         .property::<u64>("global.shared-map.shard")?
                         .unwrap_or(32)
                         .next_power_of_two() as usize,
-                ),
-                id_seq: 0.into(),
-                quota: DashMap::with_capacity_and_hasher_and_shard_amount(
+                )),
+                id_seq: Arc::new(0.into()),
+                node_id: config
+                    .property::<u64>("storage.cluster.node-id")?
+                    .unwrap_or_default(),
+                quota: Arc::new(DashMap::with_capacity_and_hasher_and_shard_amount(
                     config.property("global.shared-map.capacity")?.unwrap_or(2),
                     ThrottleKeyHasherBuilder::default(),
                     config
                         .property::<u64>("global.shared-map.shard")?
                         .unwrap_or(32)
                         .next_power_of_two() as usize,
-                ),
+                )),
                 tx: queue_tx,
                 connectors: TlsConnectors {
                     pki_verify: build_tls_connector(false),
@@ -151,6 +132,7 @@ impl SMTP {
                 config: report_config,
             },
             mail_auth: mail_auth_config,
+            dkim: Arc::new(DkimKeys::default()),
             sieve: sieve_config,
             #[cfg(feature = "local_delivery")]
             delivery_tx,
@@ -162,6 +144,113 @@ impl SMTP {
         // Spawn report manager
         report_rx.spawn(core.clone(), core.report.read_reports().await);
 
+        // Spawn DKIM key rotation
+        let core = Arc::new(ArcSwap::new(core));
+        core.spawn_dkim_rotation();
+
         Ok(core)
     }
+
+    // Builds a new core from a reloaded configuration. The queue and report
+    // managers, throttles and DKIM keys of the running core are carried over.
+    pub fn reload(
+        &self,
+        config: &Config,
+        servers: &Servers,
+        stores: &Stores,
+        directory: &Directories,
+    ) -> Result<Self, String> {
+        let mut config_ctx = Self::parse_context(config, servers, stores, directory)?;
+        let sieve_config = config.parse_sieve(&mut config_ctx)?;
+        let session_config = config.parse_session_config(&config_ctx)?;
+        let queue_config = config.parse_queue(&config_ctx)?;
+        let mail_auth_config = config.parse_mail_auth(&config_ctx)?;
+        let report_config = config.parse_reports(&config_ctx)?;
+
+        Ok(SMTP {
+            worker_pool: self.worker_pool.clone(),
+            resolvers: config.build_resolvers()?,
+            session: SessionCore {
+                config: session_config,
+                throttle: self.session.throttle.clone(),
+            },
+            queue: QueueCore {
+                config: queue_config,
+                throttle: self.queue.throttle.clone(),
+                quota: self.queue.quota.clone(),
+                tx: self.queue.tx.clone(),
+                id_seq: self.queue.id_seq.clone(),
+                node_id: self.queue.node_id,
+                connectors: TlsConnectors {
+                    pki_verify: build_tls_connector(false),
+                    dummy_verify: build_tls_connector(true),
+                },
+            },
+            report: ReportCore {
+                tx: self.report.tx.clone(),
+                config: report_config,
+            },
+            mail_auth: mail_auth_config,
+            dkim: self.dkim.clone(),
+            sieve: sieve_config,
+            #[cfg(feature = "local_delivery")]
+            delivery_tx: self.delivery_tx.clone(),
+        })
+    }
+
+    // Runs the same parsers as `init` without building the core, so configuration
+    // changes can be rejected before they are stored.
+    pub fn validate_config(
+        config: &Config,
+        servers: &Servers,
+        stores: &Stores,
+        directory: &Directories,
+    ) -> Result<(), String> {
+        let mut config_ctx = Self::parse_context(config, servers, stores, directory)?;
+        config.parse_sieve(&mut config_ctx)?;
+        config.parse_session_config(&config_ctx)?;
+        config.parse_queue(&config_ctx)?;
+        config.parse_mail_auth(&config_ctx)?;
+        config.parse_reports(&config_ctx)?;
+        config.build_resolvers()?;
+        Ok(())
+    }
+
+    fn parse_context<'x>(
+        config: &Config,
+        servers: &'x Servers,
+        stores: &Stores,
+        directory: &Directories,
+    ) -> Result<ConfigContext<'x>, String> {
+        let mut config_ctx = ConfigContext::new(&servers.inner);
+        config_ctx.directory = directory.clone();
+        config_ctx.stores = stores.clone();
+
+        // Parse remote hosts
+        config.parse_remote_hosts(&mut config_ctx)?;
+
+        // Add local delivery host
+        #[cfg(feature = "local_delivery")]
+        {
+            config_ctx.hosts.insert(
+                "local".to_string(),
+                Host {
+                    address: String::new(),
+                    port: 0,
+                    protocol: ServerProtocol::Jmap,
+                    concurrency: Default::default(),
+                    timeout: Default::default(),
+                    tls_implicit: Default::default(),
+                    tls_allow_invalid_certs: Default::default(),
+                    username: Default::default(),
+                    secret: Default::default(),
+                },
+            );
+        }
+
+        // Parse signatures
+        config.parse_signatures(&mut config_ctx)?;
+
+        Ok(config_ctx)
+    }
 }
@@ -33,7 +33,10 @@ use mail_auth::{
 };
 use mail_send::SmtpClient;
 use smtp_proto::MAIL_REQUIRETLS;
-use utils::config::ServerProtocol;
+use utils::{
+    config::ServerProtocol,
+    metrics::{metrics, KeyValue},
+};
 
 use crate::{
     config::{AggregateFrequency, TlsStrategy},
@@ -66,7 +69,7 @@ impl DeliveryAttempt {
             let due = self.message.next_delivery_event();
             if due > Instant::now() {
                 // Save changes to disk
-                self.message.save_changes().await;
+                self.message.save_changes(&core.queue.config).await;
 
                 queue.schedule(Schedule {
                     due,
@@ -76,7 +79,7 @@ impl DeliveryAttempt {
             }
         } else {
             // All message recipients expired, do not re-queue. (DSN has been already sent)
-            self.message.remove().await;
+            self.message.remove(&core.queue.config).await;
             return;
         }
 
@@ -93,7 +96,7 @@ impl DeliveryAttempt {
                 .await
             {
                 // Save changes to disk
-                self.message.save_changes().await;
+                self.message.save_changes(&core.queue.config).await;
 
                 match err {
                     throttle::Error::Concurrency { limiter } => {
@@ -114,6 +117,8 @@ impl DeliveryAttempt {
             }
         }
 
+        // Keep track of messages being delivered, so they are not picked up again
+        queue.in_flight.insert(self.message.id);
         tokio::spawn(async move {
             let queue_config = &core.queue.config;
             let mut on_hold = Vec::new();
@@ -170,6 +175,7 @@ impl DeliveryAttempt {
                             .deliver_local(
                                 recipients.iter_mut().filter(|r| r.domain_idx == domain_idx),
                                 &core.delivery_tx,
+                                queue_config,
                                 &span,
                             )
                             .await;
@@ -624,6 +630,7 @@ impl DeliveryAttempt {
                         // Obtail session parameters
                         let params = SessionParams {
                             span: &span,
+                            queue_config,
                             credentials: remote_host.credentials(),
                             is_smtp: remote_host.is_smtp(),
                             hostname: envelope.mx,
@@ -942,7 +949,7 @@ impl DeliveryAttempt {
                 self.message.release_quota();
 
                 // Save changes to disk
-                self.message.save_changes().await;
+                self.message.save_changes(&core.queue.config).await;
 
                 tracing::info!(
                     parent: &span,
@@ -962,7 +969,7 @@ impl DeliveryAttempt {
                 self.message.release_quota();
 
                 // Save changes to disk
-                self.message.save_changes().await;
+                self.message.save_changes(&core.queue.config).await;
 
                 tracing::info!(
                     parent: &span,
@@ -978,7 +985,7 @@ impl DeliveryAttempt {
                 })
             } else {
                 // Delete message from queue
-                self.message.remove().await;
+                self.message.remove(&core.queue.config).await;
 
                 tracing::info!(
                     parent: &span,
@@ -987,7 +994,7 @@ impl DeliveryAttempt {
                     "Delivery completed."
                 );
 
-                WorkerResult::Done
+                WorkerResult::Done(self.message.id)
             };
             if core.queue.tx.send(Event::Done(result)).await.is_err() {
                 tracing::warn!(
@@ -1060,6 +1067,18 @@ impl Domain {
     pub fn set_status(&mut self, status: impl Into<Status<(), Error>>, schedule: &[Duration]) {
         self.status = status.into();
         self.changed = true;
+        metrics().delivery_attempts.add(
+            1,
+            &[KeyValue::new(
+                "outcome",
+                match &self.status {
+                    Status::Completed(_) => "completed",
+                    Status::TemporaryFailure(_) => "temporary-failure",
+                    Status::PermanentFailure(_) => "permanent-failure",
+                    Status::Scheduled => "scheduled",
+                },
+            )],
+        );
         if matches!(
             &self.status,
             Status::TemporaryFailure(_) | Status::Scheduled
@@ -23,10 +23,14 @@
 
 use smtp_proto::Response;
 use tokio::sync::{mpsc, oneshot};
-use utils::ipc::{DeliveryEvent, DeliveryResult, IngestMessage};
+use utils::ipc::{DeliveryEvent, DeliveryResult, IngestMessage, MessageData};
 
-use crate::queue::{
-    Error, ErrorDetails, HostResponse, Message, Recipient, Status, RCPT_STATUS_CHANGED,
+use crate::{
+    config::{QueueBackend, QueueConfig},
+    queue::{
+        Error, ErrorDetails, HostResponse, Message, Recipient, Status, MAIL_SENDER_AUTHENTICATED,
+        RCPT_STATUS_CHANGED,
+    },
 };
 
 impl Message {
@@ -34,6 +38,7 @@ impl Message {
         &self,
         recipients: impl Iterator<Item = &mut Recipient>,
         delivery_tx: &mpsc::Sender<DeliveryEvent>,
+        config: &QueueConfig,
         span: &tracing::Span,
     ) -> Status<(), Error> {
         // Prepare recipients list
@@ -54,6 +59,18 @@ impl Message {
             pending_recipients.push(rcpt);
         }
 
+        // Obtain message contents
+        let message_data = match config.backend {
+            QueueBackend::FileSystem => MessageData::File {
+                message_path: self.path.clone(),
+                message_size: self.size,
+            },
+            QueueBackend::Store => match self.read_raw_message(config, 0..self.size, span).await {
+                Some(raw_message) => MessageData::from_bytes(raw_message),
+                None => return Status::local_error(),
+            },
+        };
+
         // Create oneshot channel
         let (result_tx, result_rx) = oneshot::channel();
 
@@ -62,11 +79,9 @@ impl Message {
             .send(DeliveryEvent::Ingest {
                 message: IngestMessage {
                     sender_address: self.return_path_lcase.clone(),
+                    sender_authenticated: (self.flags & MAIL_SENDER_AUTHENTICATED) != 0,
                     recipients: recipient_addresses,
-                    message_data: utils::ipc::MessageData::File {
-                        message_path: self.path.clone(),
-                        message_size: self.size,
-                    },
+                    message_data,
                 },
                 result_tx,
             })
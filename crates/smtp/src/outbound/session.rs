@@ -30,14 +30,13 @@ use smtp_proto::{
 use std::fmt::Write;
 use std::time::Duration;
 use tokio::{
-    fs,
     io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
     net::TcpStream,
 };
 use tokio_rustls::{client::TlsStream, TlsConnector};
 
 use crate::{
-    config::{RequireOptional, TlsStrategy},
+    config::{QueueConfig, RequireOptional, TlsStrategy},
     queue::{ErrorDetails, HostResponse, RCPT_STATUS_CHANGED},
 };
 
@@ -45,6 +44,7 @@ use crate::queue::{Error, Message, Recipient, Status};
 
 pub struct SessionParams<'x> {
     pub span: &'x tracing::Span,
+    pub queue_config: &'x QueueConfig,
     pub hostname: &'x str,
     pub credentials: Option<&'x Credentials<String>>,
     pub is_smtp: bool,
@@ -532,26 +532,10 @@ pub async fn send_message<T: AsyncRead + AsyncWrite + Unpin>(
     bdat_cmd: &Option<String>,
     params: &SessionParams<'_>,
 ) -> Result<(), Status<(), Error>> {
-    let mut raw_message = vec![0u8; message.size];
-    let mut file = fs::File::open(&message.path).await.map_err(|err| {
-        tracing::error!(parent: params.span,
-                            context = "queue", 
-                            event = "error", 
-                            "Failed to open message file {}: {}", 
-                            message.path.display(),
-                            err);
-        Status::TemporaryFailure(Error::Io("Queue system error.".to_string()))
-    })?;
-    file.read_exact(&mut raw_message).await.map_err(|err| {
-        tracing::error!(parent: params.span,
-                            context = "queue", 
-                            event = "error", 
-                            "Failed to read {} bytes file {} from disk: {}", 
-                            message.size,
-                            message.path.display(),
-                            err);
-        Status::TemporaryFailure(Error::Io("Queue system error.".to_string()))
-    })?;
+    let raw_message = message
+        .read_raw_message(params.queue_config, 0..message.size, params.span)
+        .await
+        .ok_or_else(|| Status::TemporaryFailure(Error::Io("Queue system error.".to_string())))?;
     tokio::time::timeout(params.timeout_data, async {
         if let Some(bdat_cmd) = bdat_cmd {
             write_chunks(smtp_client, &[bdat_cmd.as_bytes(), &raw_message]).await
@@ -31,8 +31,6 @@ use smtp_proto::{
 };
 use std::fmt::Write;
 use std::time::{Duration, Instant};
-use tokio::fs::File;
-use tokio::io::AsyncReadExt;
 
 use crate::config::QueueConfig;
 use crate::core::QueueCore;
@@ -261,59 +259,37 @@ impl DeliveryAttempt {
         let dsn = dsn_header + &dsn;
 
         // Fetch up to 1024 bytes of message headers
-        let headers = match File::open(&self.message.path).await {
-            Ok(mut file) => {
-                let mut buf = vec![0u8; std::cmp::min(self.message.size, 1024)];
-                match file.read(&mut buf).await {
-                    Ok(br) => {
-                        let mut prev_ch = 0;
-                        let mut last_lf = br;
-                        for (pos, &ch) in buf.iter().enumerate() {
-                            match ch {
-                                b'\n' => {
-                                    last_lf = pos + 1;
-                                    if prev_ch != b'\n' {
-                                        prev_ch = ch;
-                                    } else {
-                                        break;
-                                    }
-                                }
-                                b'\r' => (),
-                                0 => break,
-                                _ => {
-                                    prev_ch = ch;
-                                }
+        let headers = match self
+            .message
+            .read_raw_message(config, 0..1024, &self.span)
+            .await
+        {
+            Some(mut buf) => {
+                let mut prev_ch = 0;
+                let mut last_lf = buf.len();
+                for (pos, &ch) in buf.iter().enumerate() {
+                    match ch {
+                        b'\n' => {
+                            last_lf = pos + 1;
+                            if prev_ch != b'\n' {
+                                prev_ch = ch;
+                            } else {
+                                break;
                             }
                         }
-                        if last_lf < 1024 {
-                            buf.truncate(last_lf);
+                        b'\r' => (),
+                        0 => break,
+                        _ => {
+                            prev_ch = ch;
                         }
-                        String::from_utf8(buf).unwrap_or_default()
-                    }
-                    Err(err) => {
-                        tracing::error!(
-                            parent: &self.span,
-                            context = "queue",
-                            event = "error",
-                            "Failed to read from {}: {}",
-                            self.message.path.display(),
-                            err
-                        );
-                        String::new()
                     }
                 }
+                if last_lf < 1024 {
+                    buf.truncate(last_lf);
+                }
+                String::from_utf8(buf).unwrap_or_default()
             }
-            Err(err) => {
-                tracing::error!(
-                    parent: &self.span,
-                    context = "queue",
-                    event = "error",
-                    "Failed to open file {}: {}",
-                    self.message.path.display(),
-                    err
-                );
-                String::new()
-            }
+            None => String::new(),
         };
 
         // Build message
@@ -27,13 +27,21 @@ use std::{
     time::{Duration, Instant},
 };
 
-use ahash::AHashMap;
+use ahash::{AHashMap, AHashSet};
 use smtp_proto::Response;
+use store::{
+    write::{key::DeserializeBigEndian, QueueClass, QueueEvent, RawValue},
+    IterateParams, ValueKey, U64_LEN,
+};
 use tokio::sync::mpsc;
-
-use crate::core::{
-    management::{self},
-    QueueCore, SMTP,
+use utils::metrics::set_queue_stats;
+
+use crate::{
+    config::QueueBackend,
+    core::{
+        management::{self},
+        QueueCore, SMTP,
+    },
 };
 
 use super::{
@@ -48,19 +56,28 @@ pub struct Queue {
     pub scheduled: BinaryHeap<Schedule<QueueId>>,
     pub on_hold: Vec<OnHold<QueueId>>,
     pub messages: AHashMap<QueueId, Box<Message>>,
+    pub in_flight: AHashSet<QueueId>,
+    metrics_updated: Instant,
+    poll_interval: Option<Duration>,
+    next_poll: Instant,
 }
 
+const METRICS_UPDATE_INTERVAL: Duration = Duration::from_secs(15);
+
 impl SpawnQueue for mpsc::Receiver<Event> {
-    fn spawn(mut self, core: Arc<SMTP>, mut queue: Queue) {
+    fn spawn(mut self, mut core: Arc<SMTP>, mut queue: Queue) {
         tokio::spawn(async move {
             loop {
                 let result = tokio::time::timeout(queue.wake_up_time(), self.recv()).await;
 
+                // Pick up messages queued or rescheduled by other nodes
+                if queue.is_poll_due() {
+                    core.queue.poll_queue(&mut queue).await;
+                }
+
                 // Deliver scheduled messages
                 while let Some(message) = queue.next_due() {
-                    DeliveryAttempt::from(message)
-                        .try_deliver(core.clone(), &mut queue)
-                        .await;
+                    deliver(&core, &mut queue, message).await;
                 }
 
                 match result {
@@ -68,15 +85,11 @@ impl SpawnQueue for mpsc::Receiver<Event> {
                         Event::Queue(item) => {
                             // Deliver any concurrency limited messages
                             while let Some(message) = queue.next_on_hold() {
-                                DeliveryAttempt::from(message)
-                                    .try_deliver(core.clone(), &mut queue)
-                                    .await;
+                                deliver(&core, &mut queue, message).await;
                             }
 
                             if item.due <= Instant::now() {
-                                DeliveryAttempt::from(item.inner)
-                                    .try_deliver(core.clone(), &mut queue)
-                                    .await;
+                                deliver(&core, &mut queue, item.inner).await;
                             } else {
                                 queue.schedule(item);
                             }
@@ -84,12 +97,12 @@ impl SpawnQueue for mpsc::Receiver<Event> {
                         Event::Done(result) => {
                             // A worker is done, try delivering concurrency limited messages
                             while let Some(message) = queue.next_on_hold() {
-                                DeliveryAttempt::from(message)
-                                    .try_deliver(core.clone(), &mut queue)
-                                    .await;
+                                deliver(&core, &mut queue, message).await;
                             }
                             match result {
-                                WorkerResult::Done => (),
+                                WorkerResult::Done(queue_id) => {
+                                    queue.in_flight.remove(&queue_id);
+                                }
                                 WorkerResult::Retry(schedule) => {
                                     queue.schedule(schedule);
                                 }
@@ -228,15 +241,15 @@ impl SpawnQueue for mpsc::Receiver<Event> {
                                                             | Status::Scheduled
                                                     )
                                                 }) {
-                                                    message.save_changes().await;
+                                                    message.save_changes(&core.queue.config).await;
                                                 } else {
-                                                    message.remove().await;
+                                                    message.remove(&core.queue.config).await;
                                                     queue.messages.remove(queue_id);
                                                 }
                                             }
                                         }
                                     } else if let Some(message) = queue.messages.remove(queue_id) {
-                                        message.remove().await;
+                                        message.remove(&core.queue.config).await;
                                         found = true;
                                     }
                                     result.push(found);
@@ -272,7 +285,7 @@ impl SpawnQueue for mpsc::Receiver<Event> {
 
                                         if found {
                                             queue.on_hold.retain(|oh| &oh.message != queue_id);
-                                            message.save_changes().await;
+                                            message.save_changes(&core.queue.config).await;
                                             if let Some(next_event) = message.next_event() {
                                                 queue.scheduled.push(Schedule {
                                                     due: next_event,
@@ -286,18 +299,33 @@ impl SpawnQueue for mpsc::Receiver<Event> {
                                 let _ = result_tx.send(result);
                             }
                         },
+                        Event::Reload(new_core) => {
+                            core = new_core;
+                        }
                         Event::Stop => break,
                     },
                     Ok(None) => break,
                     Err(_) => (),
                 }
+
+                queue.update_metrics();
             }
         });
     }
 }
 
+async fn deliver(core: &Arc<SMTP>, queue: &mut Queue, message: Box<Message>) {
+    // Messages held by another node are dropped here and picked up again by the next poll
+    if let Some(message) = message.lock(&core.queue.config).await {
+        DeliveryAttempt::from(message)
+            .try_deliver(core.clone(), queue)
+            .await;
+    }
+}
+
 impl Queue {
     pub fn schedule(&mut self, message: Schedule<Box<Message>>) {
+        self.in_flight.remove(&message.inner.id);
         self.scheduled.push(Schedule {
             due: message.due,
             inner: message.inner.id,
@@ -306,6 +334,7 @@ impl Queue {
     }
 
     pub fn on_hold(&mut self, message: OnHold<Box<Message>>) {
+        self.in_flight.remove(&message.message.id);
         self.on_hold.push(OnHold {
             next_due: message.next_due,
             limiters: message.limiters,
@@ -338,15 +367,46 @@ impl Queue {
             .and_then(|pos| self.messages.remove(&self.on_hold.remove(pos).message))
     }
 
+    pub fn update_metrics(&mut self) {
+        // Finding the oldest message requires a full scan, limit how often it runs
+        let now = Instant::now();
+        if self.metrics_updated + METRICS_UPDATE_INTERVAL <= now {
+            self.metrics_updated = now;
+            set_queue_stats(
+                self.messages.len() as u64,
+                self.messages
+                    .values()
+                    .map(|message| message.created)
+                    .min()
+                    .unwrap_or_default(),
+            );
+        }
+    }
+
+    pub fn is_poll_due(&self) -> bool {
+        self.poll_interval.is_some() && self.next_poll <= Instant::now()
+    }
+
     pub fn wake_up_time(&self) -> Duration {
-        self.scheduled
+        let wake_up = self
+            .scheduled
             .peek()
             .map(|item| {
                 item.due
                     .checked_duration_since(Instant::now())
                     .unwrap_or(self.short_wait)
             })
-            .unwrap_or(self.long_wait)
+            .unwrap_or(self.long_wait);
+
+        if self.poll_interval.is_some() {
+            wake_up.min(
+                self.next_poll
+                    .checked_duration_since(Instant::now())
+                    .unwrap_or(self.short_wait),
+            )
+        } else {
+            wake_up
+        }
     }
 }
 
@@ -509,6 +569,20 @@ impl QueueCore {
         for message in messages {
             match message.await {
                 Ok(Ok(mut message)) => {
+                    if self.config.backend == QueueBackend::Store {
+                        // Move the message to the data store, it is scheduled below
+                        if !message.migrate_to_store(&self.config).await {
+                            tracing::warn!(
+                                context = "queue",
+                                event = "error",
+                                "Queue file {} could not be migrated to the data store, \
+                                 it will be retried on the next restart.",
+                                message.path.display()
+                            );
+                        }
+                        continue;
+                    }
+
                     // Reserve quota
                     self.has_quota(&mut message).await;
 
@@ -540,8 +614,149 @@ impl QueueCore {
             }
         }
 
+        // Load messages from the data store
+        if self.config.backend == QueueBackend::Store {
+            queue.poll_interval = Some(self.config.poll_interval);
+            queue.next_poll = Instant::now() + self.config.poll_interval;
+
+            let mut messages = Vec::new();
+            let from_key = ValueKey::from(QueueClass::Message(0));
+            let to_key = ValueKey::from(QueueClass::Message(u64::MAX));
+            if let Err(err) = self
+                .config
+                .data_store
+                .iterate(
+                    IterateParams::new(from_key, to_key).ascending(),
+                    |key, value| {
+                        let queue_id = key.deserialize_be_u64(key.len() - U64_LEN)?;
+                        if let Some(message) = Message::deserialize_store(queue_id, value) {
+                            messages.push(message);
+                        } else {
+                            tracing::warn!(
+                                context = "queue",
+                                event = "error",
+                                "Failed to deserialize queued message {}",
+                                queue_id
+                            );
+                        }
+                        Ok(true)
+                    },
+                )
+                .await
+            {
+                tracing::error!(
+                    context = "queue",
+                    event = "error",
+                    "Failed to read queued messages from the data store: {}",
+                    err
+                );
+            }
+
+            for mut message in messages {
+                // Reserve quota
+                self.has_quota(&mut message).await;
+
+                // Schedule message
+                queue.schedule(Schedule {
+                    due: message.next_event().unwrap_or_else(|| {
+                        tracing::warn!(
+                            context = "queue",
+                            event = "warn",
+                            "No due events found for message {}",
+                            message.id
+                        );
+                        Instant::now()
+                    }),
+                    inner: Box::new(message),
+                });
+            }
+        }
+
         queue
     }
+
+    pub async fn poll_queue(&self, queue: &mut Queue) {
+        let now = Instant::now();
+        queue.next_poll = now + self.config.poll_interval;
+
+        // Find due messages that are neither scheduled nor being delivered by this node
+        let mut queue_ids = Vec::new();
+        let from_key = ValueKey::from(QueueClass::MessageEvent(QueueEvent {
+            due: 0,
+            queue_id: 0,
+        }));
+        let to_key = ValueKey::from(QueueClass::MessageEvent(QueueEvent {
+            due: store::write::now(),
+            queue_id: u64::MAX,
+        }));
+        if let Err(err) = self
+            .config
+            .data_store
+            .iterate(
+                IterateParams::new(from_key, to_key).ascending().no_values(),
+                |key, _| {
+                    let queue_id = key.deserialize_be_u64(key.len() - U64_LEN)?;
+                    if !queue.messages.contains_key(&queue_id)
+                        && !queue.in_flight.contains(&queue_id)
+                    {
+                        queue_ids.push(queue_id);
+                    }
+                    Ok(true)
+                },
+            )
+            .await
+        {
+            tracing::error!(
+                context = "queue",
+                event = "error",
+                "Failed to poll queue events from the data store: {}",
+                err
+            );
+            return;
+        }
+
+        for queue_id in queue_ids {
+            let mut message = match self
+                .config
+                .data_store
+                .get_value::<RawValue>(ValueKey::from(QueueClass::Message(queue_id)))
+                .await
+            {
+                Ok(Some(RawValue(bytes))) => match Message::deserialize_store(queue_id, &bytes) {
+                    Some(message) => message,
+                    None => {
+                        tracing::warn!(
+                            context = "queue",
+                            event = "error",
+                            "Failed to deserialize queued message {}",
+                            queue_id
+                        );
+                        continue;
+                    }
+                },
+                Ok(None) => continue,
+                Err(err) => {
+                    tracing::error!(
+                        context = "queue",
+                        event = "error",
+                        "Failed to read queued message {}: {}",
+                        queue_id,
+                        err
+                    );
+                    continue;
+                }
+            };
+
+            // Reserve quota
+            self.has_quota(&mut message).await;
+
+            // Schedule message
+            queue.schedule(Schedule {
+                due: message.next_event().unwrap_or(now),
+                inner: Box::new(message),
+            });
+        }
+    }
 }
 
 impl Default for Queue {
@@ -552,6 +767,10 @@ impl Default for Queue {
             scheduled: BinaryHeap::with_capacity(128),
             on_hold: Vec::with_capacity(128),
             messages: AHashMap::with_capacity(128),
+            in_flight: AHashSet::with_capacity(128),
+            metrics_updated: Instant::now(),
+            poll_interval: None,
+            next_poll: Instant::now(),
         }
     }
 }
@@ -31,12 +31,16 @@ use std::{
 
 use serde::{Deserialize, Serialize};
 use smtp_proto::Response;
+use store::BlobHash;
 use utils::{
     config::KeyLookup,
     listener::limiter::{ConcurrencyLimiter, InFlight},
 };
 
-use crate::{config::EnvelopeKey, core::management};
+use crate::{
+    config::EnvelopeKey,
+    core::{management, SMTP},
+};
 
 pub mod dsn;
 pub mod manager;
@@ -52,12 +56,13 @@ pub enum Event {
     Queue(Schedule<Box<Message>>),
     Manage(management::QueueRequest),
     Done(WorkerResult),
+    Reload(Arc<SMTP>),
     Stop,
 }
 
 #[derive(Debug)]
 pub enum WorkerResult {
-    Done,
+    Done(QueueId),
     Retry(Schedule<Box<Message>>),
     OnHold(OnHold<Box<Message>>),
 }
@@ -80,6 +85,8 @@ pub struct Message {
     pub id: QueueId,
     pub created: u64,
     pub path: PathBuf,
+    pub blob_hash: BlobHash,
+    pub event_due: u64,
 
     pub return_path: String,
     pub return_path_lcase: String,
@@ -119,6 +126,9 @@ pub struct Recipient {
 pub const RCPT_DSN_SENT: u64 = 1 << 32;
 pub const RCPT_STATUS_CHANGED: u64 = 2 << 32;
 
+// Set when the envelope sender was verified by SMTP AUTH or an aligned SPF pass
+pub const MAIL_SENDER_AUTHENTICATED: u64 = 1 << 32;
+
 #[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub enum Status<T, E> {
     #[serde(rename = "scheduled")]
@@ -27,6 +27,7 @@ use std::io::SeekFrom;
 use std::path::PathBuf;
 use std::slice::Iter;
 use std::{fmt::Write, time::Instant};
+use store::{BlobHash, BLOB_HASH_LEN, U64_LEN};
 use tokio::fs;
 use tokio::fs::File;
 use tokio::io::{AsyncReadExt, AsyncSeekExt};
@@ -108,6 +109,38 @@ impl Message {
         buf.into_bytes()
     }
 
+    pub fn serialize_store(&self) -> Vec<u8> {
+        let metadata = self.serialize();
+        let mut buf = Vec::with_capacity(BLOB_HASH_LEN + (U64_LEN * 2) + metadata.len());
+        buf.extend_from_slice(self.blob_hash.as_ref());
+        buf.extend_from_slice(&(self.size as u64).to_be_bytes());
+        buf.extend_from_slice(&self.event_due.to_be_bytes());
+        buf.extend_from_slice(&metadata);
+        buf
+    }
+
+    pub fn deserialize_store(id: u64, bytes: &[u8]) -> Option<Self> {
+        let blob_hash = BlobHash::try_from_hash_slice(bytes.get(..BLOB_HASH_LEN)?).ok()?;
+        let size = u64::from_be_bytes(
+            bytes
+                .get(BLOB_HASH_LEN..BLOB_HASH_LEN + U64_LEN)?
+                .try_into()
+                .ok()?,
+        );
+        let event_due = u64::from_be_bytes(
+            bytes
+                .get(BLOB_HASH_LEN + U64_LEN..BLOB_HASH_LEN + (U64_LEN * 2))?
+                .try_into()
+                .ok()?,
+        );
+        let mut message = Self::deserialize(bytes.get(BLOB_HASH_LEN + (U64_LEN * 2)..)?)?;
+        message.id = id;
+        message.blob_hash = blob_hash;
+        message.size = size as usize;
+        message.event_due = event_due;
+        Some(message)
+    }
+
     pub async fn from_path(path: PathBuf) -> Result<Self, String> {
         let filename = path
             .file_name()
@@ -184,6 +217,8 @@ impl Message {
         let mut message = Message {
             id: 0,
             path: PathBuf::new(),
+            blob_hash: BlobHash::default(),
+            event_due: 0,
             created,
             return_path_domain: return_path_lcase.domain_part().to_string(),
             return_path_lcase,
@@ -24,17 +24,26 @@
 use crate::queue::DomainPart;
 use mail_auth::common::base32::Base32Writer;
 use mail_auth::common::headers::Writer;
+use std::ops::Range;
 use std::path::PathBuf;
 use std::sync::atomic::Ordering;
 use std::time::Instant;
 use std::time::{Duration, SystemTime};
+use store::write::{now, BatchBuilder, BlobOp, QueueClass, QueueEvent, RawValue};
+use store::{BlobHash, Serialize, ValueKey};
 use tokio::fs::OpenOptions;
+use tokio::io::AsyncReadExt;
 use tokio::{fs, io::AsyncWriteExt};
 
-use crate::config::QueueConfig;
+use crate::config::{QueueBackend, QueueConfig};
 use crate::core::QueueCore;
 
-use super::{Domain, Event, Message, Recipient, Schedule, SimpleEnvelope, Status};
+use super::{
+    instant_to_timestamp, Domain, Event, Message, Recipient, Schedule, SimpleEnvelope, Status,
+};
+
+// Collection id used to link queued message blobs
+pub const QUEUE_BLOB_COLLECTION: u8 = u8::MAX;
 
 impl QueueCore {
     pub async fn queue_message(
@@ -52,71 +61,23 @@ impl QueueCore {
             message.size = raw_message.len() + raw_headers.as_ref().map_or(0, |h| h.len());
         }
 
-        // Build path
-        message.path = self.config.path.eval(message.as_ref()).await.clone();
-        let hash = *self.config.hash.eval(message.as_ref()).await;
-        if hash > 0 {
-            message.path.push((message.id % hash).to_string());
-        }
-        let _ = fs::create_dir(&message.path).await;
-
-        // Encode file name
-        let mut encoder = Base32Writer::with_capacity(20);
-        encoder.write(&message.id.to_le_bytes()[..]);
-        encoder.write(&(message.size as u32).to_le_bytes()[..]);
-        let mut file = encoder.finalize();
-        file.push_str(".msg");
-        message.path.push(file);
-
-        // Serialize metadata
-        let metadata = message.serialize();
-
         // Save message
-        let mut file = match fs::File::create(&message.path).await {
-            Ok(file) => file,
-            Err(err) => {
-                tracing::error!(
-                    parent: span,
-                    context = "queue",
-                    event = "error",
-                    "Failed to create file {}: {}",
-                    message.path.display(),
-                    err
-                );
-                return false;
+        let is_saved = match self.config.backend {
+            QueueBackend::FileSystem => {
+                message
+                    .write_spool_file(&self.config, raw_headers, raw_message, span)
+                    .await
             }
-        };
-
-        let iter = if let Some(raw_headers) = raw_headers {
-            [raw_headers, raw_message, &metadata].into_iter()
-        } else {
-            [raw_message, &metadata, b""].into_iter()
-        };
-
-        for bytes in iter {
-            if !bytes.is_empty() {
-                if let Err(err) = file.write_all(bytes).await {
-                    tracing::error!(
-                        parent: span,
-                        context = "queue",
-                        event = "error",
-                        "Failed to write to file {}: {}",
-                        message.path.display(),
-                        err
-                    );
-                    return false;
+            QueueBackend::Store => {
+                let mut raw_data = Vec::with_capacity(message.size);
+                if let Some(raw_headers) = raw_headers {
+                    raw_data.extend_from_slice(raw_headers);
                 }
+                raw_data.extend_from_slice(raw_message);
+                message.write_store(&self.config, &raw_data, span).await
             }
-        }
-        if let Err(err) = file.flush().await {
-            tracing::error!(
-                parent: span,
-                context = "queue",
-                event = "error",
-                "Failed to flush file {}: {}",
-                message.path.display(),
-                err
-            );
+        };
+        if !is_saved {
             return false;
         }
 
@@ -162,7 +123,9 @@ impl QueueCore {
             .map_or(0, |d| d.as_secs())
             .saturating_sub(946684800)
             & 0xFFFFFFFF)
-            | (self.id_seq.fetch_add(1, Ordering::Relaxed) as u64) << 32
+            | ((self.node_id & 0xFF) << 24
+                | (self.id_seq.fetch_add(1, Ordering::Relaxed) as u64 & 0xFFFFFF))
+                << 32
     }
 }
 
@@ -175,6 +138,8 @@ impl Message {
         Box::new(Message {
             id: 0,
             path: PathBuf::new(),
+            blob_hash: BlobHash::default(),
+            event_due: 0,
             created: SystemTime::now()
                 .duration_since(SystemTime::UNIX_EPOCH)
                 .map(|d| d.as_secs())
@@ -238,35 +203,510 @@ impl Message {
             .await;
     }
 
-    pub async fn save_changes(&mut self) {
-        let buf = self.serialize_changes();
-        if !buf.is_empty() {
-            let err = match OpenOptions::new().append(true).open(&self.path).await {
-                Ok(mut file) => match file.write_all(&buf).await {
-                    Ok(_) => return,
-                    Err(err) => err,
-                },
-                Err(err) => err,
-            };
+    pub async fn read_raw_message(
+        &self,
+        config: &QueueConfig,
+        range: Range<usize>,
+        span: &tracing::Span,
+    ) -> Option<Vec<u8>> {
+        let range = range.start..std::cmp::min(range.end, self.size);
+
+        match config.backend {
+            QueueBackend::FileSystem => {
+                let mut file = match fs::File::open(&self.path).await {
+                    Ok(file) => file,
+                    Err(err) => {
+                        tracing::error!(
+                            parent: span,
+                            context = "queue",
+                            event = "error",
+                            "Failed to open message file {}: {}",
+                            self.path.display(),
+                            err
+                        );
+                        return None;
+                    }
+                };
+                let mut raw_message = vec![0u8; range.end];
+                match file.read_exact(&mut raw_message).await {
+                    Ok(_) => {
+                        if range.start > 0 {
+                            raw_message.drain(..range.start);
+                        }
+                        Some(raw_message)
+                    }
+                    Err(err) => {
+                        tracing::error!(
+                            parent: span,
+                            context = "queue",
+                            event = "error",
+                            "Failed to read {} bytes file {} from disk: {}",
+                            range.end,
+                            self.path.display(),
+                            err
+                        );
+                        None
+                    }
+                }
+            }
+            QueueBackend::Store => {
+                match config
+                    .blob_store
+                    .get_blob(
+                        self.blob_hash.as_ref(),
+                        range.start as u32..range.end as u32,
+                    )
+                    .await
+                {
+                    Ok(Some(raw_message)) => Some(raw_message),
+                    Ok(None) => {
+                        tracing::error!(
+                            parent: span,
+                            context = "queue",
+                            event = "error",
+                            "Blob {:?} for queued message {} not found.",
+                            self.blob_hash,
+                            self.id
+                        );
+                        None
+                    }
+                    Err(err) => {
+                        tracing::error!(
+                            parent: span,
+                            context = "queue",
+                            event = "error",
+                            "Failed to fetch blob for queued message {}: {}",
+                            self.id,
+                            err
+                        );
+                        None
+                    }
+                }
+            }
+        }
+    }
+
+    pub async fn save_changes(&mut self, config: &QueueConfig) {
+        match config.backend {
+            QueueBackend::FileSystem => {
+                let buf = self.serialize_changes();
+                if !buf.is_empty() {
+                    let err = match OpenOptions::new().append(true).open(&self.path).await {
+                        Ok(mut file) => match file.write_all(&buf).await {
+                            Ok(_) => return,
+                            Err(err) => err,
+                        },
+                        Err(err) => err,
+                    };
+                    tracing::error!(
+                        context = "queue",
+                        event = "error",
+                        "Failed to write to {}: {}",
+                        self.path.display(),
+                        err
+                    );
+                }
+            }
+            QueueBackend::Store => {
+                // Clear change flags, the full message is always written
+                self.serialize_changes();
+
+                let mut batch = BatchBuilder::new();
+                self.build_store_batch(&mut batch);
+                batch.clear(QueueClass::MessageLock(self.id));
+                if let Err(err) = config.data_store.write(batch.build()).await {
+                    tracing::error!(
+                        context = "queue",
+                        event = "error",
+                        "Failed to update queued message {}: {}",
+                        self.id,
+                        err
+                    );
+                }
+            }
+        }
+    }
+
+    pub async fn remove(&self, config: &QueueConfig) {
+        match config.backend {
+            QueueBackend::FileSystem => {
+                if let Err(err) = fs::remove_file(&self.path).await {
+                    tracing::error!(
+                        context = "queue",
+                        event = "error",
+                        "Failed to delete queued message {}: {}",
+                        self.path.display(),
+                        err
+                    );
+                }
+            }
+            QueueBackend::Store => {
+                let mut batch = BatchBuilder::new();
+                batch.clear(QueueClass::Message(self.id));
+                batch.clear(QueueClass::MessageLock(self.id));
+                self.with_blob_link(&mut batch).clear(BlobOp::Link {
+                    hash: self.blob_hash.clone(),
+                });
+                if self.event_due != 0 {
+                    batch.clear(QueueClass::MessageEvent(QueueEvent {
+                        due: self.event_due,
+                        queue_id: self.id,
+                    }));
+                }
+                if let Err(err) = config.data_store.write(batch.build()).await {
+                    tracing::error!(
+                        context = "queue",
+                        event = "error",
+                        "Failed to delete queued message {}: {}",
+                        self.id,
+                        err
+                    );
+                }
+            }
+        }
+    }
+
+    /// Acquires a delivery lease on a message stored in the data store, so that
+    /// nodes sharing the store never deliver the same message concurrently.
+    /// Returns the latest stored version of the message, or `None` when another
+    /// node holds the lease or the message was already removed from the queue.
+    pub async fn lock(self: Box<Self>, config: &QueueConfig) -> Option<Box<Self>> {
+        if config.backend == QueueBackend::FileSystem {
+            return Some(self);
+        }
+
+        let now = now();
+        let lock = match config
+            .data_store
+            .get_value::<u64>(ValueKey::from(QueueClass::MessageLock(self.id)))
+            .await
+        {
+            Ok(Some(expires)) if expires > now => {
+                tracing::debug!(
+                    context = "queue",
+                    event = "locked",
+                    id = self.id,
+                    "Message is being delivered by another node."
+                );
+                return None;
+            }
+            Ok(lock) => lock,
+            Err(err) => {
+                tracing::error!(
+                    context = "queue",
+                    event = "error",
+                    "Failed to read lock for queued message {}: {}",
+                    self.id,
+                    err
+                );
+                return None;
+            }
+        };
+
+        let mut batch = BatchBuilder::new();
+        if let Some(lock) = lock {
+            batch.assert_value(QueueClass::MessageLock(self.id), lock);
+        } else {
+            batch.assert_value(QueueClass::MessageLock(self.id), ());
+        }
+        batch.set(
+            QueueClass::MessageLock(self.id),
+            (now + config.lock_expiry.as_secs()).serialize(),
+        );
+        match config.data_store.write(batch.build()).await {
+            Ok(_) => (),
+            Err(store::Error::AssertValueFailed) => {
+                tracing::debug!(
+                    context = "queue",
+                    event = "locked",
+                    id = self.id,
+                    "Message was locked by another node."
+                );
+                return None;
+            }
+            Err(err) => {
+                tracing::error!(
+                    context = "queue",
+                    event = "error",
+                    "Failed to lock queued message {}: {}",
+                    self.id,
+                    err
+                );
+                return None;
+            }
+        }
+
+        // Another node might have updated or delivered the message since it was loaded
+        let stored = match config
+            .data_store
+            .get_value::<RawValue>(ValueKey::from(QueueClass::Message(self.id)))
+            .await
+        {
+            Ok(Some(RawValue(bytes))) => Message::deserialize_store(self.id, &bytes),
+            Ok(None) => None,
+            Err(err) => {
+                tracing::error!(
+                    context = "queue",
+                    event = "error",
+                    "Failed to read queued message {}: {}",
+                    self.id,
+                    err
+                );
+                None
+            }
+        };
+
+        if let Some(mut message) = stored {
+            message.queue_refs = self.queue_refs;
+            Some(Box::new(message))
+        } else {
+            let mut batch = BatchBuilder::new();
+            batch.clear(QueueClass::MessageLock(self.id));
+            if let Err(err) = config.data_store.write(batch.build()).await {
+                tracing::error!(
+                    context = "queue",
+                    event = "error",
+                    "Failed to unlock queued message {}: {}",
+                    self.id,
+                    err
+                );
+            }
+            None
+        }
+    }
+
+    pub(super) async fn migrate_to_store(&mut self, config: &QueueConfig) -> bool {
+        let mut raw_message = match fs::read(&self.path).await {
+            Ok(raw_message) if raw_message.len() >= self.size => raw_message,
+            Ok(_) => {
+                tracing::error!(
+                    context = "queue",
+                    event = "error",
+                    "Queue file {} is truncated.",
+                    self.path.display()
+                );
+                return false;
+            }
+            Err(err) => {
+                tracing::error!(
+                    context = "queue",
+                    event = "error",
+                    "Failed to read queue file {}: {}",
+                    self.path.display(),
+                    err
+                );
+                return false;
+            }
+        };
+        raw_message.truncate(self.size);
+
+        if self
+            .write_store(config, &raw_message, &tracing::Span::current())
+            .await
+        {
+            tracing::info!(
+                context = "queue",
+                event = "migrate",
+                id = self.id,
+                "Migrated queue file {} to the data store.",
+                self.path.display()
+            );
+            if let Err(err) = fs::remove_file(&self.path).await {
+                tracing::error!(
+                    context = "queue",
+                    event = "error",
+                    "Failed to delete queue file {}: {}",
+                    self.path.display(),
+                    err
+                );
+            }
+            true
+        } else {
+            false
+        }
+    }
+
+    async fn write_spool_file(
+        &mut self,
+        config: &QueueConfig,
+        raw_headers: Option<&[u8]>,
+        raw_message: &[u8],
+        span: &tracing::Span,
+    ) -> bool {
+        // Build path
+        self.path = config.path.eval(self).await.clone();
+        let hash = *config.hash.eval(self).await;
+        if hash > 0 {
+            self.path.push((self.id % hash).to_string());
+        }
+        let _ = fs::create_dir(&self.path).await;
+
+        // Encode file name
+        let mut encoder = Base32Writer::with_capacity(20);
+        encoder.write(&self.id.to_le_bytes()[..]);
+        encoder.write(&(self.size as u32).to_le_bytes()[..]);
+        let mut file = encoder.finalize();
+        file.push_str(".msg");
+        self.path.push(file);
+
+        // Serialize metadata
+        let metadata = self.serialize();
+
+        // Save message
+        let mut file = match fs::File::create(&self.path).await {
+            Ok(file) => file,
+            Err(err) => {
+                tracing::error!(
+                    parent: span,
+                    context = "queue",
+                    event = "error",
+                    "Failed to create file {}: {}",
+                    self.path.display(),
+                    err
+                );
+                return false;
+            }
+        };
+
+        let iter = if let Some(raw_headers) = raw_headers {
+            [raw_headers, raw_message, &metadata].into_iter()
+        } else {
+            [raw_message, &metadata, b""].into_iter()
+        };
+
+        for bytes in iter {
+            if !bytes.is_empty() {
+                if let Err(err) = file.write_all(bytes).await {
+                    tracing::error!(
+                        parent: span,
+                        context = "queue",
+                        event = "error",
+                        "Failed to write to file {}: {}",
+                        self.path.display(),
+                        err
+                    );
+                    return false;
+                }
+            }
+        }
+        if let Err(err) = file.flush().await {
             tracing::error!(
+                parent: span,
                 context = "queue",
                 event = "error",
-                "Failed to write to {}: {}",
+                "Failed to flush file {}: {}",
                 self.path.display(),
                 err
             );
+            return false;
         }
+
+        true
     }
 
-    pub async fn remove(&self) {
-        if let Err(err) = fs::remove_file(&self.path).await {
+    pub(super) async fn write_store(
+        &mut self,
+        config: &QueueConfig,
+        raw_message: &[u8],
+        span: &tracing::Span,
+    ) -> bool {
+        self.blob_hash = BlobHash::from(raw_message);
+
+        // Upload the message to the blob store, unless it already exists
+        let blob_exists = match config.data_store.blob_exists(&self.blob_hash).await {
+            Ok(blob_exists) => blob_exists,
+            Err(err) => {
+                tracing::error!(
+                    parent: span,
+                    context = "queue",
+                    event = "error",
+                    "Failed to verify blob hash existence: {}",
+                    err
+                );
+                return false;
+            }
+        };
+        if !blob_exists {
+            if let Err(err) = config
+                .blob_store
+                .put_blob(self.blob_hash.as_ref(), raw_message)
+                .await
+            {
+                tracing::error!(
+                    parent: span,
+                    context = "queue",
+                    event = "error",
+                    "Failed to store message blob: {}",
+                    err
+                );
+                return false;
+            }
+        }
+
+        // Commit the blob and write the message metadata
+        let mut batch = BatchBuilder::new();
+        batch.set(
+            BlobOp::Commit {
+                hash: self.blob_hash.clone(),
+            },
+            Vec::new(),
+        );
+        self.with_blob_link(&mut batch).set(
+            BlobOp::Link {
+                hash: self.blob_hash.clone(),
+            },
+            Vec::new(),
+        );
+        self.build_store_batch(&mut batch);
+
+        if let Err(err) = config.data_store.write(batch.build()).await {
             tracing::error!(
+                parent: span,
                 context = "queue",
                 event = "error",
-                "Failed to delete queued message {}: {}",
-                self.path.display(),
+                "Failed to write queued message {}: {}",
+                self.id,
                 err
             );
+            return false;
         }
+
+        true
+    }
+
+    fn build_store_batch(&mut self, batch: &mut BatchBuilder) {
+        let now = Instant::now();
+        let next_due = self
+            .next_event()
+            .map_or(0, |due| instant_to_timestamp(now, due));
+
+        if self.event_due != next_due {
+            if self.event_due != 0 {
+                batch.clear(QueueClass::MessageEvent(QueueEvent {
+                    due: self.event_due,
+                    queue_id: self.id,
+                }));
+            }
+            if next_due != 0 {
+                batch.set(
+                    QueueClass::MessageEvent(QueueEvent {
+                        due: next_due,
+                        queue_id: self.id,
+                    }),
+                    Vec::new(),
+                );
+            }
+            self.event_due = next_due;
+        }
+
+        batch.set(QueueClass::Message(self.id), self.serialize_store());
+    }
+
+    fn with_blob_link<'x>(&self, batch: &'x mut BatchBuilder) -> &'x mut BatchBuilder {
+        // Queue ids are 64-bit wide, split them across the account and document ids
+        batch
+            .with_account_id((self.id >> 32) as u32)
+            .with_collection(QUEUE_BLOB_COLLECTION)
+            .update_document(self.id as u32)
     }
 }
@@ -24,6 +24,7 @@
 use std::{
     borrow::Cow,
     collections::hash_map::Entry,
+    fmt::Display,
     io::{Cursor, Read},
     sync::{atomic::Ordering, Arc},
     time::SystemTime,
@@ -35,9 +36,18 @@ use mail_auth::{
     report::{tlsrpt::TlsReport, ActionDisposition, DmarcResult, Feedback, Report},
     zip,
 };
-use mail_parser::{DateTime, MessageParser, MimeHeaders, PartType};
+use mail_parser::{DateTime, Message, MessageParser, MimeHeaders, PartType};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use store::{
+    write::{key::DeserializeBigEndian, now, BatchBuilder, RawValue, ReportClass, ValueClass},
+    IterateParams, ValueKey, U64_LEN,
+};
+use tokio::runtime::Handle;
 
-use crate::core::SMTP;
+use crate::core::{
+    management::{IncomingReportFilter, IncomingReportSummary},
+    SMTP,
+};
 
 enum Compression {
     None,
@@ -45,12 +55,35 @@ enum Compression {
     Zip,
 }
 
-enum Format {
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
     Dmarc,
     Tls,
     Arf,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IncomingReportId {
+    pub format: Format,
+    pub id: u64,
+    pub expires: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct IncomingReport<T> {
+    pub from: String,
+    pub to: Vec<String>,
+    pub subject: String,
+    pub received: u64,
+    pub report: T,
+}
+
+enum ParsedReport {
+    Dmarc(Report),
+    Tls(TlsReport),
+    Arf(Box<Feedback<'static>>),
+}
+
 struct ReportData<'x> {
     compression: Compression,
     format: Format,
@@ -64,6 +97,7 @@ pub trait AnalyzeReport {
 impl AnalyzeReport for Arc<SMTP> {
     fn analyze_report(&self, message: Arc<Vec<u8>>) {
         let core = self.clone();
+        let handle = Handle::current();
         self.worker_pool.spawn(move || {
             let message = if let Some(message) = MessageParser::default().parse(message.as_ref()) {
                 message
@@ -213,10 +247,11 @@ impl AnalyzeReport for Arc<SMTP> {
                     }
                 };
 
-                match report.format {
+                let parsed = match report.format {
                     Format::Dmarc => match Report::parse_xml(&data) {
                         Ok(report) => {
                             report.log();
+                            ParsedReport::Dmarc(report)
                         }
                         Err(err) => {
                             tracing::debug!(
@@ -231,6 +266,7 @@ impl AnalyzeReport for Arc<SMTP> {
                     Format::Tls => match TlsReport::parse_json(&data) {
                         Ok(report) => {
                             report.log();
+                            ParsedReport::Tls(report)
                         }
                         Err(err) => {
                             tracing::debug!(
@@ -245,6 +281,7 @@ impl AnalyzeReport for Arc<SMTP> {
                     Format::Arf => match Feedback::parse_arf(&data) {
                         Some(report) => {
                             report.log();
+                            ParsedReport::Arf(Box::new(report.into_owned()))
                         }
                         None => {
                             tracing::debug!(
@@ -255,6 +292,59 @@ impl AnalyzeReport for Arc<SMTP> {
                             continue;
                         }
                     },
+                };
+
+                // Store report in the data store
+                let retention = core.report.config.analysis.retention.as_secs();
+                if retention > 0 {
+                    let now = now();
+                    let report_id = IncomingReportId {
+                        format: report.format,
+                        id: core.queue.queue_id(),
+                        expires: now + retention,
+                    };
+                    let value = match parsed {
+                        ParsedReport::Dmarc(report) => {
+                            bincode::serialize(&IncomingReport::new(&message, now, report))
+                        }
+                        ParsedReport::Tls(report) => {
+                            bincode::serialize(&IncomingReport::new(&message, now, report))
+                        }
+                        ParsedReport::Arf(report) => {
+                            bincode::serialize(&IncomingReport::new(&message, now, report))
+                        }
+                    };
+
+                    match value {
+                        Ok(value) => {
+                            let mut batch = BatchBuilder::new();
+                            batch.set(ValueClass::Report(report_id.class()), value);
+                            let core = core.clone();
+                            let from = from.to_string();
+                            handle.spawn(async move {
+                                if let Err(err) =
+                                    core.queue.config.data_store.write(batch.build()).await
+                                {
+                                    tracing::warn!(
+                                        context = "report",
+                                        event = "error",
+                                        from = from,
+                                        "Failed to store incoming report: {}",
+                                        err
+                                    );
+                                }
+                            });
+                        }
+                        Err(err) => {
+                            tracing::warn!(
+                                context = "report",
+                                event = "error",
+                                from = from,
+                                "Failed to serialize incoming report: {}",
+                                err
+                            );
+                        }
+                    }
                 }
 
                 // Save report
@@ -301,6 +391,351 @@ impl AnalyzeReport for Arc<SMTP> {
     }
 }
 
+impl SMTP {
+    pub async fn list_incoming_reports(
+        &self,
+        filter: &IncomingReportFilter,
+    ) -> store::Result<Vec<String>> {
+        let now = now();
+        let mut ids = Vec::new();
+
+        for format in [Format::Dmarc, Format::Tls, Format::Arf] {
+            if filter.format.is_some_and(|f| f != format) {
+                continue;
+            }
+
+            let from_key = ValueKey::from(
+                IncomingReportId {
+                    format,
+                    id: 0,
+                    expires: now,
+                }
+                .class(),
+            );
+            let to_key = ValueKey::from(
+                IncomingReportId {
+                    format,
+                    id: u64::MAX,
+                    expires: u64::MAX,
+                }
+                .class(),
+            );
+            self.queue
+                .config
+                .data_store
+                .iterate(
+                    IterateParams::new(from_key, to_key).ascending(),
+                    |key, value| {
+                        let report_id = IncomingReportId {
+                            format,
+                            id: key.deserialize_be_u64(key.len() - U64_LEN)?,
+                            expires: key.deserialize_be_u64(key.len() - (U64_LEN * 2))?,
+                        };
+                        if let Some(summary) = report_id.summary(value, false) {
+                            if filter.matches(&summary) {
+                                ids.push(report_id.to_string());
+                            }
+                        } else {
+                            tracing::debug!(
+                                context = "report",
+                                event = "error",
+                                "Failed to deserialize incoming report {}",
+                                report_id
+                            );
+                        }
+                        Ok(true)
+                    },
+                )
+                .await?;
+        }
+
+        Ok(ids)
+    }
+
+    pub async fn incoming_report(
+        &self,
+        report_id: &IncomingReportId,
+    ) -> store::Result<Option<IncomingReportSummary>> {
+        Ok(self
+            .queue
+            .config
+            .data_store
+            .get_value::<RawValue>(ValueKey::from(report_id.class()))
+            .await?
+            .and_then(|value| report_id.summary(&value.0, true)))
+    }
+
+    pub async fn purge_incoming_reports(&self) -> store::Result<()> {
+        let now = now();
+        let mut expired = Vec::new();
+
+        for format in [Format::Dmarc, Format::Tls, Format::Arf] {
+            let from_key = ValueKey::from(
+                IncomingReportId {
+                    format,
+                    id: 0,
+                    expires: 0,
+                }
+                .class(),
+            );
+            let to_key = ValueKey::from(
+                IncomingReportId {
+                    format,
+                    id: u64::MAX,
+                    expires: now,
+                }
+                .class(),
+            );
+            self.queue
+                .config
+                .data_store
+                .iterate(
+                    IterateParams::new(from_key, to_key).ascending().no_values(),
+                    |key, _| {
+                        expired.push(IncomingReportId {
+                            format,
+                            id: key.deserialize_be_u64(key.len() - U64_LEN)?,
+                            expires: key.deserialize_be_u64(key.len() - (U64_LEN * 2))?,
+                        });
+                        Ok(true)
+                    },
+                )
+                .await?;
+        }
+
+        for chunk in expired.chunks(1000) {
+            let mut batch = BatchBuilder::new();
+            for report_id in chunk {
+                batch.clear(ValueClass::Report(report_id.class()));
+            }
+            self.queue.config.data_store.write(batch.build()).await?;
+        }
+
+        Ok(())
+    }
+}
+
+impl<T> IncomingReport<T> {
+    fn new(message: &Message<'_>, received: u64, report: T) -> Self {
+        IncomingReport {
+            from: message
+                .from()
+                .and_then(|a| a.last())
+                .and_then(|a| a.address())
+                .unwrap_or_default()
+                .to_lowercase(),
+            to: message
+                .to()
+                .map(|a| {
+                    a.iter()
+                        .filter_map(|a| a.address())
+                        .map(|a| a.to_lowercase())
+                        .collect()
+                })
+                .unwrap_or_default(),
+            subject: message.subject().unwrap_or_default().to_string(),
+            received,
+            report,
+        }
+    }
+}
+
+impl<T: ReportSummary + Serialize> IncomingReport<T> {
+    fn summary(&self, type_: &str, full: bool) -> IncomingReportSummary {
+        let (pass, fail) = self.report.results();
+        let (range_from, range_to) = self.report.date_range().map_or((None, None), |(from, to)| {
+            (
+                DateTime::from_timestamp(from).into(),
+                DateTime::from_timestamp(to).into(),
+            )
+        });
+
+        IncomingReportSummary {
+            type_: type_.to_string(),
+            domains: self.report.domains(),
+            reporter: self.report.reporter().unwrap_or(&self.from).to_string(),
+            from: self.from.clone(),
+            subject: self.subject.clone(),
+            received: DateTime::from_timestamp(self.received as i64),
+            range_from,
+            range_to,
+            pass,
+            fail,
+            report: if full {
+                serde_json::to_value(&self.report).ok()
+            } else {
+                None
+            },
+        }
+    }
+}
+
+impl IncomingReportId {
+    pub fn parse(value: &str) -> Option<Self> {
+        let mut parts = value.split('!');
+        let report_id = IncomingReportId {
+            format: Format::parse(parts.next()?)?,
+            id: parts.next()?.parse().ok()?,
+            expires: parts.next()?.parse().ok()?,
+        };
+        if parts.next().is_none() {
+            Some(report_id)
+        } else {
+            None
+        }
+    }
+
+    pub fn class(&self) -> ReportClass {
+        match self.format {
+            Format::Dmarc => ReportClass::Dmarc {
+                id: self.id,
+                expires: self.expires,
+            },
+            Format::Tls => ReportClass::Tls {
+                id: self.id,
+                expires: self.expires,
+            },
+            Format::Arf => ReportClass::Arf {
+                id: self.id,
+                expires: self.expires,
+            },
+        }
+    }
+
+    fn summary(&self, value: &[u8], full: bool) -> Option<IncomingReportSummary> {
+        fn deserialize<T: ReportSummary + Serialize + DeserializeOwned>(
+            value: &[u8],
+            type_: &str,
+            full: bool,
+        ) -> Option<IncomingReportSummary> {
+            bincode::deserialize::<IncomingReport<T>>(value)
+                .ok()
+                .map(|report| report.summary(type_, full))
+        }
+
+        match self.format {
+            Format::Dmarc => deserialize::<Report>(value, "dmarc", full),
+            Format::Tls => deserialize::<TlsReport>(value, "tls", full),
+            Format::Arf => deserialize::<Feedback<'static>>(value, "arf", full),
+        }
+    }
+}
+
+impl Display for IncomingReportId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let prefix = match self.format {
+            Format::Dmarc => 'd',
+            Format::Tls => 't',
+            Format::Arf => 'a',
+        };
+        write!(f, "{prefix}!{}!{}", self.id, self.expires)
+    }
+}
+
+impl Format {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "dmarc" | "d" => Some(Format::Dmarc),
+            "tls" | "t" => Some(Format::Tls),
+            "arf" | "a" => Some(Format::Arf),
+            _ => None,
+        }
+    }
+}
+
+pub trait ReportSummary {
+    fn domains(&self) -> Vec<String>;
+    fn reporter(&self) -> Option<&str>;
+    fn date_range(&self) -> Option<(i64, i64)>;
+    fn results(&self) -> (u64, u64);
+}
+
+impl ReportSummary for Report {
+    fn domains(&self) -> Vec<String> {
+        vec![self.domain().to_lowercase()]
+    }
+
+    fn reporter(&self) -> Option<&str> {
+        Some(self.org_name()).filter(|name| !name.is_empty())
+    }
+
+    fn date_range(&self) -> Option<(i64, i64)> {
+        Some((self.date_range_begin() as i64, self.date_range_end() as i64))
+    }
+
+    fn results(&self) -> (u64, u64) {
+        let mut pass = 0;
+        let mut fail = 0;
+        for record in self.records() {
+            let count = std::cmp::max(record.count(), 1) as u64;
+            if record.dmarc_dkim_result() == DmarcResult::Pass
+                || record.dmarc_spf_result() == DmarcResult::Pass
+            {
+                pass += count;
+            } else {
+                fail += count;
+            }
+        }
+        (pass, fail)
+    }
+}
+
+impl ReportSummary for TlsReport {
+    fn domains(&self) -> Vec<String> {
+        let mut domains = Vec::with_capacity(self.policies.len());
+        for policy in &self.policies {
+            let domain = policy.policy.policy_domain.to_lowercase();
+            if !domains.contains(&domain) {
+                domains.push(domain);
+            }
+        }
+        domains
+    }
+
+    fn reporter(&self) -> Option<&str> {
+        self.organization_name
+            .as_deref()
+            .or(self.contact_info.as_deref())
+    }
+
+    fn date_range(&self) -> Option<(i64, i64)> {
+        Some((
+            self.date_range.start_datetime.to_timestamp(),
+            self.date_range.end_datetime.to_timestamp(),
+        ))
+    }
+
+    fn results(&self) -> (u64, u64) {
+        self.policies.iter().fold((0, 0), |(pass, fail), policy| {
+            (
+                pass + policy.summary.total_success as u64,
+                fail + policy.summary.total_failure as u64,
+            )
+        })
+    }
+}
+
+impl ReportSummary for Feedback<'_> {
+    fn domains(&self) -> Vec<String> {
+        self.reported_domain()
+            .iter()
+            .map(|domain| domain.to_lowercase())
+            .collect()
+    }
+
+    fn reporter(&self) -> Option<&str> {
+        self.reporting_mta()
+    }
+
+    fn date_range(&self) -> Option<(i64, i64)> {
+        self.arrival_date().map(|date| (date, date))
+    }
+
+    fn results(&self) -> (u64, u64) {
+        (0, std::cmp::max(self.incidents(), 1) as u64)
+    }
+}
+
 trait LogReport {
     fn log(&self);
 }
@@ -57,6 +57,7 @@ pub enum Event {
     Dmarc(Box<DmarcEvent>),
     Tls(Box<TlsEvent>),
     Manage(management::ReportRequest),
+    Reload(Arc<SMTP>),
     Stop,
 }
 
@@ -83,7 +83,7 @@ pub struct ReportPolicy<T> {
 }
 
 impl SpawnReport for mpsc::Receiver<Event> {
-    fn spawn(mut self, core: Arc<SMTP>, mut scheduler: Scheduler) {
+    fn spawn(mut self, mut core: Arc<SMTP>, mut scheduler: Scheduler) {
         tokio::spawn(async move {
             let mut last_cleanup = Instant::now();
 
@@ -156,6 +156,9 @@ impl SpawnReport for mpsc::Receiver<Event> {
                                 let _ = result_tx.send(result);
                             }
                         },
+                        Event::Reload(new_core) => {
+                            core = new_core;
+                        }
                         Event::Stop => break,
                     },
                     Ok(None) => break,
@@ -176,6 +179,18 @@ impl SpawnReport for mpsc::Receiver<Event> {
                         if last_cleanup.elapsed().as_secs() >= 86400 {
                             last_cleanup = Instant::now();
                             core.spawn_cleanup();
+
+                            let core = core.clone();
+                            tokio::spawn(async move {
+                                if let Err(err) = core.purge_incoming_reports().await {
+                                    tracing::warn!(
+                                        context = "report",
+                                        event = "error",
+                                        "Failed to purge expired incoming reports: {}",
+                                        err
+                                    );
+                                }
+                            });
                         }
                     }
                 }
@@ -29,10 +29,10 @@ use nlp::{
     tokenizers::osb::{OsbToken, OsbTokenizer},
 };
 use sieve::{runtime::Variable, FunctionMap};
-use store::{write::key::KeySerializer, LookupKey, LookupStore, LookupValue, U64_LEN};
+use store::{write::key::KeySerializer, LookupKey, LookupStore, LookupValue, U32_LEN, U64_LEN};
 use tokio::runtime::Handle;
 
-use crate::config::scripts::SieveContext;
+use crate::{config::scripts::SieveContext, core::SMTP};
 
 use super::{lookup::VariableExists, PluginContext};
 
@@ -84,79 +84,21 @@ fn train(ctx: PluginContext<'_>, is_train: bool) -> Variable {
     if text.is_empty() {
         return false.into();
     }
-    let handle = ctx.handle;
-    let ctx = ctx.core.sieve.runtime.context();
-
-    // Train the model
-    let mut model = BayesModel::default();
-    model.train(
-        OsbTokenizer::new(BayesTokenizer::new(text.as_ref(), &ctx.psl), 5),
-        is_spam,
-    );
-    if model.weights.is_empty() {
-        return false.into();
-    }
 
     tracing::debug!(
         parent: span,
         context = "sieve:bayes_train",
-        event = "train",
+        event = if is_train { "train" } else { "untrain" },
         is_spam = is_spam,
-        num_tokens = model.weights.len(),
     );
 
-    // Update weight and invalidate cache
-    if is_train {
-        for (hash, weights) in model.weights {
-            if handle
-                .block_on(
-                    store.key_set(
-                        KeySerializer::new(U64_LEN)
-                            .write(hash.h1)
-                            .write(hash.h2)
-                            .finalize(),
-                        LookupValue::Counter {
-                            num: weights.into(),
-                        },
-                    ),
-                )
-                .is_err()
-            {
-                return false.into();
-            }
-            ctx.bayes_cache.invalidate(&hash);
-        }
-
-        // Update training counts
-        let weights = if is_spam {
-            Weights { spam: 1, ham: 0 }
-        } else {
-            Weights { spam: 0, ham: 1 }
-        };
-        if handle
-            .block_on(
-                store.key_set(
-                    KeySerializer::new(U64_LEN)
-                        .write(0u64)
-                        .write(0u64)
-                        .finalize(),
-                    LookupValue::Counter {
-                        num: weights.into(),
-                    },
-                ),
-            )
-            .is_err()
-        {
-            return false.into();
-        }
-    } else {
-        //TODO: Implement untrain
-        return false.into();
-    }
-
-    ctx.bayes_cache.invalidate(&TokenHash::default());
-
-    true.into()
+    ctx.handle
+        .block_on(
+            ctx.core
+                .bayes_train(store, None, text.as_ref(), is_spam, is_train),
+        )
+        .unwrap_or(false)
+        .into()
 }
 
 pub fn exec_classify(ctx: PluginContext<'_>) -> Variable {
@@ -319,6 +261,215 @@ pub fn exec_is_balanced(ctx: PluginContext<'_>) -> Variable {
     result.into()
 }
 
+impl SMTP {
+    /// Trains (or untrains) the Bayes model using the provided text. When an
+    /// account id is provided, the account's personal model is updated instead
+    /// of the global one.
+    pub async fn bayes_train(
+        &self,
+        store: &LookupStore,
+        account_id: Option<u32>,
+        text: &str,
+        is_spam: bool,
+        is_train: bool,
+    ) -> store::Result<bool> {
+        let ctx = self.sieve.runtime.context();
+
+        // Tokenize the text
+        let mut model = BayesModel::default();
+        model.train(
+            OsbTokenizer::new(BayesTokenizer::new(text, &ctx.psl), 5),
+            is_spam,
+        );
+        if model.weights.is_empty() {
+            return Ok(false);
+        }
+
+        tracing::debug!(
+            context = "bayes",
+            event = if is_train { "train" } else { "untrain" },
+            account_id = account_id,
+            is_spam = is_spam,
+            num_tokens = model.weights.len(),
+        );
+
+        // Update weights and invalidate cache
+        for (hash, weights) in model.weights {
+            store
+                .key_set(
+                    bayes_key(account_id, &hash),
+                    LookupValue::Counter {
+                        num: weights.into_counter(is_train),
+                    },
+                )
+                .await?;
+            ctx.bayes_cache(account_id)
+                .invalidate(&hash.for_account(account_id));
+        }
+
+        // Update training counts
+        let weights = if is_spam {
+            Weights { spam: 1, ham: 0 }
+        } else {
+            Weights { spam: 0, ham: 1 }
+        };
+        store
+            .key_set(
+                bayes_key(account_id, &TokenHash::default()),
+                LookupValue::Counter {
+                    num: weights.into_counter(is_train),
+                },
+            )
+            .await?;
+        ctx.bayes_cache(account_id)
+            .invalidate(&TokenHash::default().for_account(account_id));
+
+        Ok(true)
+    }
+
+    /// Classifies the provided text combining the weights of the global model
+    /// with the ones from the account's personal model.
+    pub async fn bayes_classify(
+        &self,
+        store: &LookupStore,
+        account_id: u32,
+        text: &str,
+        classifier: &BayesClassifier,
+    ) -> store::Result<Option<f64>> {
+        let ctx = self.sieve.runtime.context();
+
+        // Obtain training counts
+        let account_learns = self
+            .bayes_weights(store, account_id.into(), TokenHash::default())
+            .await?;
+        let learns = self
+            .bayes_weights(store, None, TokenHash::default())
+            .await?
+            .combine(account_learns);
+        if learns.spam < classifier.min_learns || learns.ham < classifier.min_learns {
+            return Ok(None);
+        }
+
+        // Obtain token weights, skipping the personal model if it was never trained
+        let has_account_model = account_learns != Weights::default();
+        let tokens = OsbTokenizer::<_, TokenHash>::new(BayesTokenizer::new(text, &ctx.psl), 5)
+            .collect::<Vec<_>>();
+        let mut weights = Vec::with_capacity(tokens.len());
+        for token in tokens {
+            let mut token_weights = self.bayes_weights(store, None, token.inner).await?;
+            if has_account_model {
+                token_weights = token_weights.combine(
+                    self.bayes_weights(store, account_id.into(), token.inner)
+                        .await?,
+                );
+            }
+            weights.push(OsbToken {
+                inner: token_weights,
+                idx: token.idx,
+            });
+        }
+
+        Ok(classifier.classify(weights.into_iter(), learns.ham, learns.spam))
+    }
+
+    async fn bayes_weights(
+        &self,
+        store: &LookupStore,
+        account_id: Option<u32>,
+        hash: TokenHash,
+    ) -> store::Result<Weights> {
+        let cache = self.sieve.runtime.context().bayes_cache(account_id);
+        let cache_key = hash.for_account(account_id);
+        if let Some(weights) = cache.get(&cache_key) {
+            return Ok(weights.unwrap_or_default());
+        }
+
+        let weights = if let LookupValue::Counter { num } = store
+            .key_get::<VariableExists>(LookupKey::Counter(bayes_key(account_id, &hash)))
+            .await?
+        {
+            Some(Weights::from(num))
+        } else {
+            None
+        };
+
+        if let Some(weights) = weights {
+            cache.insert_positive(cache_key, weights);
+        } else {
+            cache.insert_negative(cache_key);
+        }
+
+        Ok(weights.unwrap_or_default())
+    }
+}
+
+impl SieveContext {
+    fn bayes_cache(&self, account_id: Option<u32>) -> &BayesTokenCache {
+        if account_id.is_some() {
+            &self.bayes_account_cache
+        } else {
+            &self.bayes_cache
+        }
+    }
+}
+
+trait AccountTokenHash {
+    fn for_account(self, account_id: Option<u32>) -> TokenHash;
+}
+
+impl AccountTokenHash for TokenHash {
+    // Mixes the account id into the token hash so that the personal models of
+    // all accounts can share a single cache.
+    fn for_account(self, account_id: Option<u32>) -> TokenHash {
+        if let Some(account_id) = account_id {
+            TokenHash {
+                h1: self.h1 ^ (account_id as u64).wrapping_mul(0x9e37_79b9_7f4a_7c15),
+                h2: self.h2,
+            }
+        } else {
+            self
+        }
+    }
+}
+
+fn bayes_key(account_id: Option<u32>, hash: &TokenHash) -> Vec<u8> {
+    if let Some(account_id) = account_id {
+        KeySerializer::new(U32_LEN + U64_LEN * 2)
+            .write(account_id)
+            .write(hash.h1)
+            .write(hash.h2)
+            .finalize()
+    } else {
+        KeySerializer::new(U64_LEN * 2)
+            .write(hash.h1)
+            .write(hash.h2)
+            .finalize()
+    }
+}
+
+trait WeightsCounter {
+    fn into_counter(self, is_train: bool) -> i64;
+    fn combine(self, other: Weights) -> Weights;
+}
+
+impl WeightsCounter for Weights {
+    fn into_counter(self, is_train: bool) -> i64 {
+        let num = i64::from(self);
+        if is_train {
+            num
+        } else {
+            -num
+        }
+    }
+
+    fn combine(self, other: Weights) -> Weights {
+        Weights {
+            spam: self.spam.saturating_add(other.spam),
+            ham: self.ham.saturating_add(other.ham),
+        }
+    }
+}
+
 trait LookupOrInsert {
     fn get_or_update(
         &self,
@@ -105,6 +105,28 @@ impl FsStore {
         Ok(())
     }
 
+    pub(crate) async fn replace_blob(&self, key: &[u8], data: &[u8]) -> crate::Result<()> {
+        // Write to a uniquely named temporary file first so the blob is never left
+        // half written, even when it is being replaced concurrently
+        let blob_path = self.build_path(key);
+        let mut temp_path = blob_path.clone();
+        temp_path.set_extension(format!("{:016x}.tmp", rand::random::<u64>()));
+        fs::create_dir_all(blob_path.parent().unwrap()).await?;
+
+        let result = async {
+            let mut blob_file = File::create(&temp_path).await?;
+            blob_file.write_all(data).await?;
+            blob_file.sync_all().await?;
+            fs::rename(&temp_path, &blob_path).await
+        }
+        .await;
+        if result.is_err() {
+            let _ = fs::remove_file(&temp_path).await;
+        }
+
+        result.map_err(Into::into)
+    }
+
     pub(crate) async fn delete_blob(&self, key: &[u8]) -> crate::Result<bool> {
         let blob_path = self.build_path(key);
         if fs::metadata(&blob_path).await.is_ok() {
@@ -27,6 +27,7 @@ use utils::config::{cron::SimpleCron, Config};
 
 use crate::{
     backend::{fs::FsStore, memory::MemoryStore},
+    crypto::BlobEncryption,
     write::purge::{PurgeSchedule, PurgeStore},
     LookupStore, QueryStore, Store, Stores,
 };
@@ -58,6 +59,7 @@ use crate::backend::redis::RedisStore;
 #[allow(async_fn_in_trait)]
 pub trait ConfigStore {
     async fn parse_stores(&self) -> utils::config::Result<Stores>;
+    async fn reload_stores(&self, current: &Stores) -> utils::config::Result<Stores>;
     async fn parse_purge_schedules(
         &self,
         stores: &Stores,
@@ -67,149 +69,12 @@ pub trait ConfigStore {
 }
 
 impl ConfigStore for Config {
-    #[allow(unused_variables)]
-    #[allow(unreachable_code)]
     async fn parse_stores(&self) -> utils::config::Result<Stores> {
-        let mut config = Stores::default();
-
-        for id in self.sub_keys("store", ".type") {
-            // Parse store
-            if self.property_or_static::<bool>(("store", id, "disable"), "false")? {
-                tracing::debug!("Skipping disabled store {id:?}.");
-                continue;
-            }
-            let protocol = self
-                .value_require(("store", id, "type"))?
-                .to_ascii_lowercase();
-            let prefix = ("store", id);
-            let store_id = id.to_string();
-
-            let lookup_store: Store = match protocol.as_str() {
-                #[cfg(feature = "rocks")]
-                "rocksdb" => {
-                    let db: Store = RocksDbStore::open(self, prefix).await?.into();
-                    config.stores.insert(store_id.clone(), db.clone());
-                    config
-                        .fts_stores
-                        .insert(store_id.clone(), db.clone().into());
-                    config
-                        .blob_stores
-                        .insert(store_id.clone(), db.clone().into());
-                    config.lookup_stores.insert(store_id, db.into());
-                    continue;
-                }
-                #[cfg(feature = "foundation")]
-                "foundationdb" => {
-                    let db: Store = FdbStore::open(self, prefix).await?.into();
-                    config.stores.insert(store_id.clone(), db.clone());
-                    config
-                        .fts_stores
-                        .insert(store_id.clone(), db.clone().into());
-                    config
-                        .blob_stores
-                        .insert(store_id.clone(), db.clone().into());
-                    config.lookup_stores.insert(store_id, db.into());
-                    continue;
-                }
-                #[cfg(feature = "postgres")]
-                "postgresql" => {
-                    let db: Store = PostgresStore::open(self, prefix).await?.into();
-                    config.stores.insert(store_id.clone(), db.clone());
-                    config
-                        .fts_stores
-                        .insert(store_id.clone(), db.clone().into());
-                    config
-                        .blob_stores
-                        .insert(store_id.clone(), db.clone().into());
-                    db
-                }
-                #[cfg(feature = "mysql")]
-                "mysql" => {
-                    let db: Store = MysqlStore::open(self, prefix).await?.into();
-                    config.stores.insert(store_id.clone(), db.clone());
-                    config
-                        .fts_stores
-                        .insert(store_id.clone(), db.clone().into());
-                    config
-                        .blob_stores
-                        .insert(store_id.clone(), db.clone().into());
-                    db
-                }
-                #[cfg(feature = "sqlite")]
-                "sqlite" => {
-                    let db: Store = SqliteStore::open(self, prefix).await?.into();
-                    config.stores.insert(store_id.clone(), db.clone());
-                    config
-                        .fts_stores
-                        .insert(store_id.clone(), db.clone().into());
-                    config
-                        .blob_stores
-                        .insert(store_id.clone(), db.clone().into());
-                    db
-                }
-                "fs" => {
-                    config
-                        .blob_stores
-                        .insert(store_id, FsStore::open(self, prefix).await?.into());
-                    continue;
-                }
-                #[cfg(feature = "s3")]
-                "s3" => {
-                    config
-                        .blob_stores
-                        .insert(store_id, S3Store::open(self, prefix).await?.into());
-                    continue;
-                }
-                #[cfg(feature = "elastic")]
-                "elasticsearch" => {
-                    config.fts_stores.insert(
-                        store_id,
-                        ElasticSearchStore::open(self, prefix).await?.into(),
-                    );
-                    continue;
-                }
-                #[cfg(feature = "redis")]
-                "redis" => {
-                    config
-                        .lookup_stores
-                        .insert(store_id, RedisStore::open(self, prefix).await?.into());
-                    continue;
-                }
-                "memory" => {
-                    config
-                        .lookup_stores
-                        .insert(store_id, MemoryStore::open(self, prefix).await?.into());
-                    continue;
-                }
-
-                unknown => {
-                    tracing::debug!("Unknown directory type: {unknown:?}");
-                    continue;
-                }
-            };
-
-            // Add queries as lookup stores
-            let lookup_store: LookupStore = lookup_store.into();
-            for lookup_id in self.sub_keys(("store", id, "query"), "") {
-                config.lookup_stores.insert(
-                    format!("{store_id}/{lookup_id}"),
-                    LookupStore::Query(Arc::new(QueryStore {
-                        store: lookup_store.clone(),
-                        query: self.property_require(("store", id, "query", lookup_id))?,
-                    })),
-                );
-            }
-            config.lookup_stores.insert(store_id, lookup_store.clone());
-
-            // Run init queries on database
-            for (_, query) in self.values(("store", id, "init.execute")) {
-                if let Err(err) = lookup_store.query::<usize>(query, Vec::new()).await {
-                    tracing::warn!("Failed to initialize store {id:?}: {err}");
-                }
-            }
-        }
+        build_stores(self, None).await
+    }
 
-        Ok(config)
+    async fn reload_stores(&self, current: &Stores) -> utils::config::Result<Stores> {
+        build_stores(self, Some(current)).await
     }
 
     async fn parse_purge_schedules(
@@ -267,6 +132,197 @@ impl ConfigStore for Config {
     }
 }
 
+#[allow(unused_variables)]
+#[allow(unreachable_code)]
+async fn build_stores(config: &Config, current: Option<&Stores>) -> utils::config::Result<Stores> {
+    let mut stores = Stores::default();
+
+    for id in config.sub_keys("store", ".type") {
+        // Parse store
+        if config.property_or_static::<bool>(("store", id, "disable"), "false")? {
+            tracing::debug!("Skipping disabled store {id:?}.");
+            continue;
+        }
+        let protocol = config
+            .value_require(("store", id, "type"))?
+            .to_ascii_lowercase();
+        let prefix = ("store", id);
+        let store_id = id.to_string();
+
+        // Stores that are already open can't be opened a second time, the running
+        // instances are kept on reload and only their queries are rebuilt.
+        if let Some(current) = current {
+            if let Some(blob_store) = current.blob_stores.get(id) {
+                stores
+                    .blob_stores
+                    .insert(store_id.clone(), blob_store.clone());
+            }
+            if let Some(fts_store) = current.fts_stores.get(id) {
+                stores
+                    .fts_stores
+                    .insert(store_id.clone(), fts_store.clone());
+            }
+            if let Some(store) = current.stores.get(id) {
+                stores.stores.insert(store_id.clone(), store.clone());
+                let lookup_store = current
+                    .lookup_stores
+                    .get(id)
+                    .cloned()
+                    .unwrap_or_else(|| store.clone().into());
+                parse_queries(config, &mut stores, id, &lookup_store)?;
+                stores.lookup_stores.insert(store_id, lookup_store);
+                continue;
+            } else if stores.blob_stores.contains_key(id) || stores.fts_stores.contains_key(id) {
+                continue;
+            }
+        }
+
+        let lookup_store: Store = match protocol.as_str() {
+            #[cfg(feature = "rocks")]
+            "rocksdb" => {
+                let db: Store = RocksDbStore::open(config, prefix).await?.into();
+                stores.stores.insert(store_id.clone(), db.clone());
+                stores
+                    .fts_stores
+                    .insert(store_id.clone(), db.clone().into());
+                stores
+                    .blob_stores
+                    .insert(store_id.clone(), db.clone().into());
+                stores.lookup_stores.insert(store_id, db.into());
+                continue;
+            }
+            #[cfg(feature = "foundation")]
+            "foundationdb" => {
+                let db: Store = FdbStore::open(config, prefix).await?.into();
+                stores.stores.insert(store_id.clone(), db.clone());
+                stores
+                    .fts_stores
+                    .insert(store_id.clone(), db.clone().into());
+                stores
+                    .blob_stores
+                    .insert(store_id.clone(), db.clone().into());
+                stores.lookup_stores.insert(store_id, db.into());
+                continue;
+            }
+            #[cfg(feature = "postgres")]
+            "postgresql" => {
+                let db: Store = PostgresStore::open(config, prefix).await?.into();
+                stores.stores.insert(store_id.clone(), db.clone());
+                stores
+                    .fts_stores
+                    .insert(store_id.clone(), db.clone().into());
+                stores
+                    .blob_stores
+                    .insert(store_id.clone(), db.clone().into());
+                db
+            }
+            #[cfg(feature = "mysql")]
+            "mysql" => {
+                let db: Store = MysqlStore::open(config, prefix).await?.into();
+                stores.stores.insert(store_id.clone(), db.clone());
+                stores
+                    .fts_stores
+                    .insert(store_id.clone(), db.clone().into());
+                stores
+                    .blob_stores
+                    .insert(store_id.clone(), db.clone().into());
+                db
+            }
+            #[cfg(feature = "sqlite")]
+            "sqlite" => {
+                let db: Store = SqliteStore::open(config, prefix).await?.into();
+                stores.stores.insert(store_id.clone(), db.clone());
+                stores
+                    .fts_stores
+                    .insert(store_id.clone(), db.clone().into());
+                stores
+                    .blob_stores
+                    .insert(store_id.clone(), db.clone().into());
+                db
+            }
+            "fs" => {
+                stores
+                    .blob_stores
+                    .insert(store_id, FsStore::open(config, prefix).await?.into());
+                continue;
+            }
+            #[cfg(feature = "s3")]
+            "s3" => {
+                stores
+                    .blob_stores
+                    .insert(store_id, S3Store::open(config, prefix).await?.into());
+                continue;
+            }
+            #[cfg(feature = "elastic")]
+            "elasticsearch" => {
+                stores.fts_stores.insert(
+                    store_id,
+                    ElasticSearchStore::open(config, prefix).await?.into(),
+                );
+                continue;
+            }
+            #[cfg(feature = "redis")]
+            "redis" => {
+                stores
+                    .lookup_stores
+                    .insert(store_id, RedisStore::open(config, prefix).await?.into());
+                continue;
+            }
+            "memory" => {
+                stores
+                    .lookup_stores
+                    .insert(store_id, MemoryStore::open(config, prefix).await?.into());
+                continue;
+            }
+
+            unknown => {
+                tracing::debug!("Unknown directory type: {unknown:?}");
+                continue;
+            }
+        };
+
+        // Add queries as lookup stores
+        let lookup_store: LookupStore = lookup_store.into();
+        parse_queries(config, &mut stores, id, &lookup_store)?;
+        stores.lookup_stores.insert(store_id, lookup_store.clone());
+
+        // Run init queries on database
+        for (_, query) in config.values(("store", id, "init.execute")) {
+            if let Err(err) = lookup_store.query::<usize>(query, Vec::new()).await {
+                tracing::warn!("Failed to initialize store {id:?}: {err}");
+            }
+        }
+    }
+
+    // Blob compression and encryption are applied on top of any backend
+    for (id, blob_store) in stores.blob_stores.iter_mut() {
+        blob_store.compression =
+            config.property_or_static(("store", id.as_str(), "compression"), "none")?;
+        blob_store.encryption = BlobEncryption::open(config, ("store", id.as_str()))?.map(Arc::new);
+    }
+
+    Ok(stores)
+}
+
+fn parse_queries(
+    config: &Config,
+    stores: &mut Stores,
+    id: &str,
+    lookup_store: &LookupStore,
+) -> utils::config::Result<()> {
+    for lookup_id in config.sub_keys(("store", id, "query"), "") {
+        stores.lookup_stores.insert(
+            format!("{id}/{lookup_id}"),
+            LookupStore::Query(Arc::new(QueryStore {
+                store: lookup_store.clone(),
+                query: config.property_require(("store", id, "query", lookup_id))?,
+            })),
+        );
+    }
+
+    Ok(())
+}
+
 impl From<crate::Error> for String {
     fn from(err: crate::Error) -> Self {
         match err {
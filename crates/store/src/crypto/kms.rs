@@ -0,0 +1,154 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+use std::time::Duration;
+
+use base64::{engine::general_purpose, Engine};
+use serde::{Deserialize, Serialize};
+use utils::config::{utils::AsKey, Config};
+
+// Client for the AWS KMS JSON protocol, which is also implemented by
+// local stand-ins such as local-kms.
+pub struct KmsClient {
+    url: String,
+    key_id: String,
+    client: reqwest::Client,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct EncryptRequest<'x> {
+    key_id: &'x str,
+    plaintext: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct EncryptResponse {
+    ciphertext_blob: String,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct DecryptRequest<'x> {
+    key_id: &'x str,
+    ciphertext_blob: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct DecryptResponse {
+    plaintext: String,
+}
+
+impl KmsClient {
+    pub fn open(config: &Config, prefix: impl AsKey) -> utils::config::Result<Self> {
+        let prefix = prefix.as_key();
+        let key_id = config.value_require((&prefix, "key-id"))?.to_string();
+        if key_id.is_empty() || key_id.len() > u8::MAX as usize {
+            return Err(format!(
+                "Invalid KMS key id for key {:?}",
+                (&prefix, "key-id").as_key()
+            ));
+        }
+
+        Ok(KmsClient {
+            url: config.value_require((&prefix, "url"))?.to_string(),
+            key_id,
+            client: reqwest::Client::builder()
+                .timeout(config.property_or_static::<Duration>((&prefix, "timeout"), "10s")?)
+                .danger_accept_invalid_certs(
+                    config.property_or_static((&prefix, "allow-invalid-certs"), "false")?,
+                )
+                .build()
+                .map_err(|err| format!("Failed to create KMS client: {err}"))?,
+        })
+    }
+
+    pub fn key_id(&self) -> &str {
+        &self.key_id
+    }
+
+    pub async fn encrypt(&self, plaintext: &[u8]) -> crate::Result<Vec<u8>> {
+        let response: EncryptResponse = self
+            .send(
+                "TrentService.Encrypt",
+                &EncryptRequest {
+                    key_id: &self.key_id,
+                    plaintext: general_purpose::STANDARD.encode(plaintext),
+                },
+            )
+            .await?;
+
+        general_purpose::STANDARD
+            .decode(response.ciphertext_blob)
+            .map_err(|err| crate::Error::InternalError(format!("Invalid KMS response: {err}")))
+    }
+
+    pub async fn decrypt(&self, key_id: &str, ciphertext: &[u8]) -> crate::Result<Vec<u8>> {
+        let response: DecryptResponse = self
+            .send(
+                "TrentService.Decrypt",
+                &DecryptRequest {
+                    key_id,
+                    ciphertext_blob: general_purpose::STANDARD.encode(ciphertext),
+                },
+            )
+            .await?;
+
+        general_purpose::STANDARD
+            .decode(response.plaintext)
+            .map_err(|err| crate::Error::InternalError(format!("Invalid KMS response: {err}")))
+    }
+
+    async fn send<T: serde::de::DeserializeOwned>(
+        &self,
+        target: &str,
+        request: &impl Serialize,
+    ) -> crate::Result<T> {
+        let response = self
+            .client
+            .post(&self.url)
+            .header("Content-Type", "application/x-amz-json-1.1")
+            .header("X-Amz-Target", target)
+            .body(serde_json::to_string(request).unwrap_or_default())
+            .send()
+            .await
+            .map_err(|err| crate::Error::InternalError(format!("KMS request failed: {err}")))?;
+
+        let status = response.status();
+        let body = response
+            .text()
+            .await
+            .map_err(|err| crate::Error::InternalError(format!("KMS request failed: {err}")))?;
+        if status.is_success() {
+            serde_json::from_str(&body)
+                .map_err(|err| crate::Error::InternalError(format!("Invalid KMS response: {err}")))
+        } else {
+            Err(crate::Error::InternalError(format!(
+                "KMS error code {}: {}",
+                status.as_u16(),
+                body
+            )))
+        }
+    }
+}
@@ -0,0 +1,328 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+pub mod kms;
+
+use aes_gcm::{
+    aead::{generic_array::GenericArray, Aead, Payload},
+    Aes256Gcm, KeyInit,
+};
+use ahash::AHashMap;
+use base64::{engine::general_purpose, Engine};
+use lru_cache::LruCache;
+use parking_lot::Mutex;
+use rand::RngCore;
+use utils::config::{utils::AsKey, Config};
+
+use self::kms::KmsClient;
+
+// Encrypted blobs are stored as:
+//
+//   magic | version (u8) | key id length (u8) | key id | wrapped key length (u16) | wrapped key | nonce | ciphertext
+//
+// The data key is unique to each blob and is wrapped by the master key, so
+// rotating the master key only requires rewriting the header of each blob.
+// The blob key is authenticated along with the ciphertext, which prevents
+// encrypted blobs from being swapped or copied under a different key.
+pub const ENCRYPTION_MARKER: u8 = 0xf5;
+const ENCRYPTION_MAGIC: &[u8] = &[ENCRYPTION_MARKER, b'S', b'E', b'B'];
+const ENCRYPTION_VERSION: u8 = 1;
+const NONCE_LEN: usize = 12;
+const KEY_LEN: usize = 32;
+const TAG_LEN: usize = 16;
+
+pub struct BlobEncryption {
+    provider: KeyProvider,
+    data_keys: Mutex<DataKeyCache>,
+    require: bool,
+}
+
+// Unwrapped data keys indexed by master key id and wrapped data key
+type DataKeyCache = LruCache<(String, Vec<u8>), [u8; KEY_LEN]>;
+
+enum KeyProvider {
+    KeyFile(KeyFile),
+    Kms(KmsClient),
+}
+
+struct KeyFile {
+    active: String,
+    keys: AHashMap<String, [u8; KEY_LEN]>,
+}
+
+pub struct EncryptedBlob<'x> {
+    pub key_id: &'x str,
+    pub wrapped_key: &'x [u8],
+    pub nonce: &'x [u8],
+    pub ciphertext: &'x [u8],
+}
+
+impl BlobEncryption {
+    pub fn open(config: &Config, prefix: impl AsKey) -> utils::config::Result<Option<Self>> {
+        let prefix = prefix.as_key();
+        let provider = match config.value((&prefix, "encryption.type")) {
+            Some("keyfile") => KeyProvider::KeyFile(KeyFile::open(
+                config.value_require((&prefix, "encryption.path"))?,
+            )?),
+            Some("kms") => KeyProvider::Kms(KmsClient::open(config, (&prefix, "encryption"))?),
+            Some("none") | None => return Ok(None),
+            Some(other) => {
+                return Err(format!(
+                    "Invalid encryption type {other:?} for key {:?}",
+                    (&prefix, "encryption.type").as_key()
+                ))
+            }
+        };
+
+        Ok(Some(BlobEncryption {
+            provider,
+            data_keys: Mutex::new(LruCache::new(
+                config.property_or_static((&prefix, "encryption.cache-size"), "1024")?,
+            )),
+            require: config.property_or_static((&prefix, "encryption.require"), "false")?,
+        }))
+    }
+
+    /// Whether reading unencrypted blobs should fail rather than succeed.
+    pub fn is_required(&self) -> bool {
+        self.require
+    }
+
+    pub fn active_key_id(&self) -> &str {
+        match &self.provider {
+            KeyProvider::KeyFile(keys) => &keys.active,
+            KeyProvider::Kms(kms) => kms.key_id(),
+        }
+    }
+
+    pub async fn encrypt(&self, key: &[u8], data: &[u8]) -> crate::Result<Vec<u8>> {
+        let mut data_key = [0u8; KEY_LEN];
+        let mut nonce = [0u8; NONCE_LEN];
+        rand::thread_rng().fill_bytes(&mut data_key);
+        rand::thread_rng().fill_bytes(&mut nonce);
+
+        let ciphertext = aes_256_gcm(&data_key)
+            .encrypt(
+                GenericArray::from_slice(&nonce),
+                Payload {
+                    msg: data,
+                    aad: key,
+                },
+            )
+            .map_err(|_| crate::Error::InternalError("Failed to encrypt blob".to_string()))?;
+        let wrapped_key = self.wrap_key(&data_key).await?;
+
+        Ok(EncryptedBlob {
+            key_id: self.active_key_id(),
+            wrapped_key: &wrapped_key,
+            nonce: &nonce,
+            ciphertext: &ciphertext,
+        }
+        .serialize())
+    }
+
+    pub async fn decrypt(&self, key: &[u8], blob: &EncryptedBlob<'_>) -> crate::Result<Vec<u8>> {
+        let data_key = self.unwrap_key(blob.key_id, blob.wrapped_key).await?;
+        aes_256_gcm(&data_key)
+            .decrypt(
+                GenericArray::from_slice(blob.nonce),
+                Payload {
+                    msg: blob.ciphertext,
+                    aad: key,
+                },
+            )
+            .map_err(|_| crate::Error::InternalError("Failed to decrypt blob".to_string()))
+    }
+
+    /// Wraps the data key of an encrypted blob with the active master key,
+    /// the ciphertext is left untouched.
+    pub async fn rewrap(&self, blob: &EncryptedBlob<'_>) -> crate::Result<Vec<u8>> {
+        let data_key = self.unwrap_key(blob.key_id, blob.wrapped_key).await?;
+        let wrapped_key = self.wrap_key(&data_key).await?;
+
+        Ok(EncryptedBlob {
+            key_id: self.active_key_id(),
+            wrapped_key: &wrapped_key,
+            nonce: blob.nonce,
+            ciphertext: blob.ciphertext,
+        }
+        .serialize())
+    }
+
+    async fn wrap_key(&self, data_key: &[u8]) -> crate::Result<Vec<u8>> {
+        match &self.provider {
+            KeyProvider::KeyFile(keys) => {
+                let mut nonce = [0u8; NONCE_LEN];
+                rand::thread_rng().fill_bytes(&mut nonce);
+                let mut wrapped_key = nonce.to_vec();
+                wrapped_key.extend(
+                    aes_256_gcm(&keys.keys[&keys.active])
+                        .encrypt(GenericArray::from_slice(&nonce), data_key)
+                        .map_err(|_| {
+                            crate::Error::InternalError("Failed to wrap data key".to_string())
+                        })?,
+                );
+                Ok(wrapped_key)
+            }
+            KeyProvider::Kms(kms) => kms.encrypt(data_key).await,
+        }
+    }
+
+    async fn unwrap_key(&self, key_id: &str, wrapped_key: &[u8]) -> crate::Result<[u8; KEY_LEN]> {
+        // Avoid a KMS round trip each time a blob is read
+        let cache_key = (key_id.to_string(), wrapped_key.to_vec());
+        if let Some(data_key) = self.data_keys.lock().get_mut(&cache_key) {
+            return Ok(*data_key);
+        }
+
+        let data_key = match &self.provider {
+            KeyProvider::KeyFile(keys) => {
+                let master_key = keys.keys.get(key_id).ok_or_else(|| {
+                    crate::Error::InternalError(format!(
+                        "Master key {key_id:?} not found in key file"
+                    ))
+                })?;
+                if wrapped_key.len() <= NONCE_LEN {
+                    return Err(crate::Error::InternalError(
+                        "Invalid wrapped data key".to_string(),
+                    ));
+                }
+                aes_256_gcm(master_key)
+                    .decrypt(
+                        GenericArray::from_slice(&wrapped_key[..NONCE_LEN]),
+                        &wrapped_key[NONCE_LEN..],
+                    )
+                    .map_err(|_| {
+                        crate::Error::InternalError("Failed to unwrap data key".to_string())
+                    })?
+            }
+            KeyProvider::Kms(kms) => kms.decrypt(key_id, wrapped_key).await?,
+        };
+
+        let data_key = <[u8; KEY_LEN]>::try_from(data_key)
+            .map_err(|_| crate::Error::InternalError("Invalid data key length".to_string()))?;
+        self.data_keys.lock().insert(cache_key, data_key);
+
+        Ok(data_key)
+    }
+}
+
+impl<'x> EncryptedBlob<'x> {
+    pub fn parse(data: &'x [u8]) -> Option<Self> {
+        let data = data.strip_prefix(ENCRYPTION_MAGIC)?;
+        if *data.first()? != ENCRYPTION_VERSION {
+            return None;
+        }
+        let key_id_len = *data.get(1)? as usize;
+        let key_id = std::str::from_utf8(data.get(2..2 + key_id_len)?).ok()?;
+        let pos = 2 + key_id_len;
+        let wrapped_key_len = u16::from_be_bytes(data.get(pos..pos + 2)?.try_into().ok()?) as usize;
+        let pos = pos + 2;
+        let wrapped_key = data.get(pos..pos + wrapped_key_len)?;
+        let pos = pos + wrapped_key_len;
+        let nonce = data.get(pos..pos + NONCE_LEN)?;
+        let ciphertext = data.get(pos + NONCE_LEN..)?;
+
+        if !key_id.is_empty() && !wrapped_key.is_empty() && ciphertext.len() >= TAG_LEN {
+            Some(EncryptedBlob {
+                key_id,
+                wrapped_key,
+                nonce,
+                ciphertext,
+            })
+        } else {
+            None
+        }
+    }
+
+    fn serialize(&self) -> Vec<u8> {
+        let mut data = Vec::with_capacity(
+            ENCRYPTION_MAGIC.len()
+                + 1
+                + 1
+                + self.key_id.len()
+                + 2
+                + self.wrapped_key.len()
+                + self.nonce.len()
+                + self.ciphertext.len(),
+        );
+        data.extend_from_slice(ENCRYPTION_MAGIC);
+        data.push(ENCRYPTION_VERSION);
+        data.push(self.key_id.len() as u8);
+        data.extend_from_slice(self.key_id.as_bytes());
+        data.extend_from_slice(&(self.wrapped_key.len() as u16).to_be_bytes());
+        data.extend_from_slice(self.wrapped_key);
+        data.extend_from_slice(self.nonce);
+        data.extend_from_slice(self.ciphertext);
+        data
+    }
+}
+
+impl KeyFile {
+    // Each line contains a key id and a base64 encoded 256-bit key separated by a colon,
+    // the first key is used for new blobs and the remaining ones are kept for decryption.
+    fn open(path: &str) -> utils::config::Result<Self> {
+        let contents = std::fs::read_to_string(path)
+            .map_err(|err| format!("Failed to read key file {path:?}: {err}"))?;
+        let mut active = None;
+        let mut keys = AHashMap::new();
+
+        for (line_num, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (key_id, key) = line
+                .split_once(':')
+                .map(|(key_id, key)| (key_id.trim(), key.trim()))
+                .filter(|(key_id, _)| !key_id.is_empty() && key_id.len() <= u8::MAX as usize)
+                .ok_or_else(|| {
+                    format!("Invalid key id in key file {path:?}, line {}", line_num + 1)
+                })?;
+            let key = general_purpose::STANDARD
+                .decode(key)
+                .ok()
+                .and_then(|key| <[u8; KEY_LEN]>::try_from(key).ok())
+                .ok_or_else(|| {
+                    format!(
+                        "Invalid key in key file {path:?}, line {}: expected a base64 encoded 256-bit key",
+                        line_num + 1
+                    )
+                })?;
+            if keys.insert(key_id.to_string(), key).is_some() {
+                return Err(format!("Duplicate key id {key_id:?} in key file {path:?}"));
+            }
+            if active.is_none() {
+                active = Some(key_id.to_string());
+            }
+        }
+
+        Ok(KeyFile {
+            active: active.ok_or_else(|| format!("No keys found in key file {path:?}"))?,
+            keys,
+        })
+    }
+}
+
+fn aes_256_gcm(key: &[u8]) -> Aes256Gcm {
+    Aes256Gcm::new(GenericArray::from_slice(key))
+}
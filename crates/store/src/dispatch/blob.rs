@@ -20,13 +20,196 @@
  * purchasing a commercial license. Please contact licensing@stalw.art
  * for more details.
 */
+use std::{ops::Range, time::Instant};
 
-use std::ops::Range;
+use utils::{
+    config::{utils::AsKey, utils::ParseValue},
+    metrics::{metrics, KeyValue},
+};
 
-use crate::{BlobStore, Store};
+use crate::{
+    crypto::{EncryptedBlob, ENCRYPTION_MARKER},
+    BlobBackend, BlobStore, CompressionAlgo, Store,
+};
+
+// Compressed blobs start with a marker byte that can never begin a UTF-8
+// encoded message, which allows them to coexist with uncompressed blobs.
+const LZ4_MARKER: u8 = 0xc0;
+const ZSTD_MARKER: u8 = 0xc1;
+
+struct DecodedBlob {
+    data: Vec<u8>,
+    compression: CompressionAlgo,
+    key_id: Option<String>,
+}
 
 impl BlobStore {
+    pub fn with_compression(mut self, compression: CompressionAlgo) -> Self {
+        self.compression = compression;
+        self
+    }
+
     pub async fn get_blob(&self, key: &[u8], range: Range<u32>) -> crate::Result<Option<Vec<u8>>> {
+        let start = Instant::now();
+
+        // Compressed or encrypted blobs have to be retrieved in full before the range can be extracted
+        let is_full_read = range.start == 0 && range.end == u32::MAX;
+        let result = if is_full_read
+            || self.compression != CompressionAlgo::None
+            || self.encryption.is_some()
+        {
+            self.get_decoded_blob(key, range).await
+        } else {
+            // Read from the start of the blob in order to detect blobs written while
+            // compression or encryption were enabled
+            match self.backend.get_blob(key, 0..range.end).await {
+                Ok(Some(data)) if is_encoded(&data) => self.get_decoded_blob(key, range).await,
+                Ok(Some(data)) => Ok(Some(read_range(data, range.start..u32::MAX))),
+                result => result,
+            }
+        };
+
+        metrics().store_read_duration.record(
+            start.elapsed().as_secs_f64(),
+            &[KeyValue::new("operation", "get_blob")],
+        );
+
+        result
+    }
+
+    pub async fn put_blob(&self, key: &[u8], data: &[u8]) -> crate::Result<()> {
+        let start = Instant::now();
+        let result = match self.encode(key, data).await? {
+            Some(encoded) => self.backend.put_blob(key, &encoded).await,
+            None => self.backend.put_blob(key, data).await,
+        };
+        metrics().store_write_duration.record(
+            start.elapsed().as_secs_f64(),
+            &[KeyValue::new("operation", "put_blob")],
+        );
+        metrics().blob_size.record(data.len() as u64, &[]);
+
+        result
+    }
+
+    pub async fn delete_blob(&self, key: &[u8]) -> crate::Result<bool> {
+        self.backend.delete_blob(key).await
+    }
+
+    /// Rewrites a stored blob using the compression algorithm and master key
+    /// configured for this store, returns `true` if the blob was rewritten.
+    pub async fn rewrite_blob(&self, key: &[u8]) -> crate::Result<bool> {
+        let raw = if let Some(raw) = self.backend.get_blob(key, 0..u32::MAX).await? {
+            raw
+        } else {
+            return Ok(false);
+        };
+        let decoded = self.decode(key, raw.clone()).await?;
+        let active_key_id = self
+            .encryption
+            .as_ref()
+            .map(|encryption| encryption.active_key_id());
+
+        if decoded.compression == self.compression {
+            match (decoded.key_id.as_deref(), active_key_id, &self.encryption) {
+                (current, active, _) if current == active => return Ok(false),
+                (Some(_), Some(_), Some(encryption)) => {
+                    // Only the data key needs to be wrapped with the new master key
+                    let blob = EncryptedBlob::parse(&raw).unwrap();
+                    self.backend
+                        .replace_blob(key, &encryption.rewrap(&blob).await?)
+                        .await?;
+                    return Ok(true);
+                }
+                _ => (),
+            }
+        }
+
+        match self.encode(key, &decoded.data).await? {
+            Some(encoded) => self.backend.replace_blob(key, &encoded).await?,
+            None if decoded.compression != CompressionAlgo::None || decoded.key_id.is_some() => {
+                self.backend.replace_blob(key, &decoded.data).await?
+            }
+            None => return Ok(false),
+        }
+
+        Ok(true)
+    }
+
+    async fn get_decoded_blob(
+        &self,
+        key: &[u8],
+        range: Range<u32>,
+    ) -> crate::Result<Option<Vec<u8>>> {
+        let decoded = match self.backend.get_blob(key, 0..u32::MAX).await? {
+            Some(data) => self.decode(key, data).await?,
+            None => return Ok(None),
+        };
+
+        // Unencrypted blobs are either written before encryption was enabled or
+        // were placed in the backend by someone else
+        match &self.encryption {
+            Some(encryption) if decoded.key_id.is_none() => {
+                if encryption.is_required() {
+                    return Err(crate::Error::InternalError(
+                        "Blob is not encrypted but encryption is required".to_string(),
+                    ));
+                }
+                tracing::debug!(
+                    context = "blob_store",
+                    event = "unencrypted",
+                    key = ?key,
+                    "Read unencrypted blob from an encrypted blob store."
+                );
+            }
+            _ => (),
+        }
+
+        Ok(Some(read_range(decoded.data, range)))
+    }
+
+    async fn encode(&self, key: &[u8], data: &[u8]) -> crate::Result<Option<Vec<u8>>> {
+        let compressed = match compress(self.compression, data)? {
+            // Blobs that could be mistaken for an encoded one are never stored as is
+            None if is_encoded(data) => compress(CompressionAlgo::Lz4, data)?,
+            compressed => compressed,
+        };
+        match &self.encryption {
+            Some(encryption) => encryption
+                .encrypt(key, compressed.as_deref().unwrap_or(data))
+                .await
+                .map(Some),
+            None => Ok(compressed),
+        }
+    }
+
+    async fn decode(&self, key: &[u8], data: Vec<u8>) -> crate::Result<DecodedBlob> {
+        let (data, key_id) = if let Some(blob) = EncryptedBlob::parse(&data) {
+            if let Some(encryption) = &self.encryption {
+                (
+                    encryption.decrypt(key, &blob).await?,
+                    Some(blob.key_id.to_string()),
+                )
+            } else {
+                return Err(crate::Error::InternalError(
+                    "Blob is encrypted but no encryption key is configured".to_string(),
+                ));
+            }
+        } else {
+            (data, None)
+        };
+        let (data, compression) = decompress(data);
+
+        Ok(DecodedBlob {
+            data,
+            compression,
+            key_id,
+        })
+    }
+}
+
+impl BlobBackend {
+    async fn get_blob(&self, key: &[u8], range: Range<u32>) -> crate::Result<Option<Vec<u8>>> {
         match self {
             Self::Store(store) => match store {
                 #[cfg(feature = "sqlite")]
@@ -46,7 +229,7 @@ impl BlobStore {
         }
     }
 
-    pub async fn put_blob(&self, key: &[u8], data: &[u8]) -> crate::Result<()> {
+    async fn put_blob(&self, key: &[u8], data: &[u8]) -> crate::Result<()> {
         match self {
             Self::Store(store) => match store {
                 #[cfg(feature = "sqlite")]
@@ -66,7 +249,15 @@ impl BlobStore {
         }
     }
 
-    pub async fn delete_blob(&self, key: &[u8]) -> crate::Result<bool> {
+    // Unlike put_blob, always overwrites existing blobs
+    async fn replace_blob(&self, key: &[u8], data: &[u8]) -> crate::Result<()> {
+        match self {
+            Self::Fs(store) => store.replace_blob(key, data).await,
+            _ => self.put_blob(key, data).await,
+        }
+    }
+
+    async fn delete_blob(&self, key: &[u8]) -> crate::Result<bool> {
         match self {
             Self::Store(store) => match store {
                 #[cfg(feature = "sqlite")]
@@ -86,3 +277,87 @@ impl BlobStore {
         }
     }
 }
+
+fn compress(compression: CompressionAlgo, data: &[u8]) -> crate::Result<Option<Vec<u8>>> {
+    let compressed = match compression {
+        CompressionAlgo::None => return Ok(None),
+        CompressionAlgo::Lz4 => {
+            let mut compressed = Vec::with_capacity(data.len() / 2 + 1);
+            compressed.push(LZ4_MARKER);
+            compressed.extend_from_slice(&lz4_flex::compress_prepend_size(data));
+            compressed
+        }
+        CompressionAlgo::Zstd => {
+            let mut compressed = Vec::with_capacity(data.len() / 2 + 1);
+            compressed.push(ZSTD_MARKER);
+            zstd::stream::copy_encode(data, &mut compressed, 0).map_err(|err| {
+                crate::Error::InternalError(format!("Failed to compress blob: {err}"))
+            })?;
+            compressed
+        }
+    };
+
+    // Incompressible blobs are stored as is, unless they could be mistaken for an encoded one
+    Ok(if compressed.len() < data.len() || is_encoded(data) {
+        Some(compressed)
+    } else {
+        None
+    })
+}
+
+fn decompress(data: Vec<u8>) -> (Vec<u8>, CompressionAlgo) {
+    let result = match data.first() {
+        Some(&LZ4_MARKER) => lz4_flex::decompress_size_prepended(&data[1..])
+            .map(|data| (data, CompressionAlgo::Lz4))
+            .map_err(|err| err.to_string()),
+        Some(&ZSTD_MARKER) => zstd::stream::decode_all(&data[1..])
+            .map(|data| (data, CompressionAlgo::Zstd))
+            .map_err(|err| err.to_string()),
+        _ => return (data, CompressionAlgo::None),
+    };
+
+    match result {
+        Ok(result) => result,
+        Err(err) => {
+            // Uncompressed blobs written before compression was enabled
+            tracing::debug!(
+                context = "blob_store",
+                event = "error",
+                "Failed to decompress blob, returning it as is: {err}"
+            );
+            (data, CompressionAlgo::None)
+        }
+    }
+}
+
+fn is_encoded(data: &[u8]) -> bool {
+    matches!(
+        data.first(),
+        Some(&LZ4_MARKER | &ZSTD_MARKER | &ENCRYPTION_MARKER)
+    )
+}
+
+fn read_range(data: Vec<u8>, range: Range<u32>) -> Vec<u8> {
+    if range.start == 0 && range.end == u32::MAX {
+        data
+    } else {
+        data.get(range.start as usize..std::cmp::min(data.len(), range.end as usize))
+            .unwrap_or_default()
+            .to_vec()
+    }
+}
+
+impl ParseValue for CompressionAlgo {
+    fn parse_value(key: impl AsKey, value: &str) -> utils::config::Result<Self> {
+        match value {
+            "lz4" => Ok(CompressionAlgo::Lz4),
+            "zstd" => Ok(CompressionAlgo::Zstd),
+            "none" | "false" | "disable" | "disabled" => Ok(CompressionAlgo::None),
+            _ => Err(format!(
+                "Invalid value for compression algorithm {key:?}: {value:?}",
+                key = key.as_key(),
+                value = value
+            )),
+        }
+    }
+}
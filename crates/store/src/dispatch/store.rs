@@ -21,9 +21,13 @@
  * for more details.
 */
 
-use std::ops::{BitAndAssign, Range};
+use std::{
+    ops::{BitAndAssign, Range},
+    time::Instant,
+};
 
 use roaring::RoaringBitmap;
+use utils::metrics::{metrics, KeyValue};
 
 use crate::{
     write::{key::KeySerializer, AnyKey, Batch, BitmapClass, ValueClass},
@@ -42,7 +46,8 @@ impl Store {
     where
         U: Deserialize + 'static,
     {
-        match self {
+        let start = Instant::now();
+        let result = match self {
             #[cfg(feature = "sqlite")]
             Self::SQLite(store) => store.get_value(key).await,
             #[cfg(feature = "foundation")]
@@ -53,7 +58,13 @@ impl Store {
             Self::MySQL(store) => store.get_value(key).await,
             #[cfg(feature = "rocks")]
             Self::RocksDb(store) => store.get_value(key).await,
-        }
+        };
+        metrics().store_read_duration.record(
+            start.elapsed().as_secs_f64(),
+            &[KeyValue::new("operation", "get_value")],
+        );
+
+        result
     }
 
     pub async fn get_values<U>(&self, key: Vec<impl Key>) -> crate::Result<Vec<Option<U>>>
@@ -73,7 +84,8 @@ impl Store {
         &self,
         key: BitmapKey<BitmapClass>,
     ) -> crate::Result<Option<RoaringBitmap>> {
-        match self {
+        let start = Instant::now();
+        let result = match self {
             #[cfg(feature = "sqlite")]
             Self::SQLite(store) => store.get_bitmap(key).await,
             #[cfg(feature = "foundation")]
@@ -84,7 +96,13 @@ impl Store {
             Self::MySQL(store) => store.get_bitmap(key).await,
             #[cfg(feature = "rocks")]
             Self::RocksDb(store) => store.get_bitmap(key).await,
-        }
+        };
+        metrics().store_read_duration.record(
+            start.elapsed().as_secs_f64(),
+            &[KeyValue::new("operation", "get_bitmap")],
+        );
+
+        result
     }
 
     pub async fn get_bitmaps_intersection(
@@ -114,7 +132,8 @@ impl Store {
         params: IterateParams<T>,
         cb: impl for<'x> FnMut(&'x [u8], &'x [u8]) -> crate::Result<bool> + Sync + Send,
     ) -> crate::Result<()> {
-        match self {
+        let start = Instant::now();
+        let result = match self {
             #[cfg(feature = "sqlite")]
             Self::SQLite(store) => store.iterate(params, cb).await,
             #[cfg(feature = "foundation")]
@@ -125,14 +144,21 @@ impl Store {
             Self::MySQL(store) => store.iterate(params, cb).await,
             #[cfg(feature = "rocks")]
             Self::RocksDb(store) => store.iterate(params, cb).await,
-        }
+        };
+        metrics().store_read_duration.record(
+            start.elapsed().as_secs_f64(),
+            &[KeyValue::new("operation", "iterate")],
+        );
+
+        result
     }
 
     pub async fn get_counter(
         &self,
         key: impl Into<ValueKey<ValueClass>> + Sync + Send,
     ) -> crate::Result<i64> {
-        match self {
+        let start = Instant::now();
+        let result = match self {
             #[cfg(feature = "sqlite")]
             Self::SQLite(store) => store.get_counter(key).await,
             #[cfg(feature = "foundation")]
@@ -143,7 +169,13 @@ impl Store {
             Self::MySQL(store) => store.get_counter(key).await,
             #[cfg(feature = "rocks")]
             Self::RocksDb(store) => store.get_counter(key).await,
-        }
+        };
+        metrics().store_read_duration.record(
+            start.elapsed().as_secs_f64(),
+            &[KeyValue::new("operation", "get_counter")],
+        );
+
+        result
     }
 
     pub async fn write(&self, batch: Batch) -> crate::Result<()> {
@@ -227,7 +259,8 @@ impl Store {
             return Ok(());
         }
 
-        match self {
+        let start = Instant::now();
+        let result = match self {
             #[cfg(feature = "sqlite")]
             Self::SQLite(store) => store.write(batch).await,
             #[cfg(feature = "foundation")]
@@ -238,7 +271,13 @@ impl Store {
             Self::MySQL(store) => store.write(batch).await,
             #[cfg(feature = "rocks")]
             Self::RocksDb(store) => store.write(batch).await,
-        }
+        };
+        metrics().store_write_duration.record(
+            start.elapsed().as_secs_f64(),
+            &[KeyValue::new("operation", "write")],
+        );
+
+        result
     }
 
     pub async fn purge_bitmaps(&self) -> crate::Result<()> {
@@ -25,6 +25,7 @@ use std::{borrow::Cow, fmt::Display, sync::Arc};
 
 pub mod backend;
 pub mod config;
+pub mod crypto;
 pub mod dispatch;
 pub mod fts;
 pub mod query;
@@ -34,6 +35,7 @@ pub use ahash;
 use ahash::AHashMap;
 use backend::{fs::FsStore, memory::MemoryStore};
 pub use blake3;
+use crypto::BlobEncryption;
 pub use parking_lot;
 pub use rand;
 pub use roaring;
@@ -211,13 +213,28 @@ pub enum Store {
 }
 
 #[derive(Clone)]
-pub enum BlobStore {
+pub struct BlobStore {
+    pub backend: BlobBackend,
+    pub compression: CompressionAlgo,
+    pub encryption: Option<Arc<BlobEncryption>>,
+}
+
+#[derive(Clone)]
+pub enum BlobBackend {
     Store(Store),
     Fs(Arc<FsStore>),
     #[cfg(feature = "s3")]
     S3(Arc<S3Store>),
 }
 
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum CompressionAlgo {
+    #[default]
+    None,
+    Lz4,
+    Zstd,
+}
+
 #[derive(Clone)]
 pub enum FtsStore {
     Store(Store),
@@ -276,14 +293,24 @@ impl From<RocksDbStore> for Store {
 
 impl From<FsStore> for BlobStore {
     fn from(store: FsStore) -> Self {
-        Self::Fs(Arc::new(store))
+        BlobBackend::Fs(Arc::new(store)).into()
     }
 }
 
 #[cfg(feature = "s3")]
 impl From<S3Store> for BlobStore {
     fn from(store: S3Store) -> Self {
-        Self::S3(Arc::new(store))
+        BlobBackend::S3(Arc::new(store)).into()
+    }
+}
+
+impl From<BlobBackend> for BlobStore {
+    fn from(backend: BlobBackend) -> Self {
+        BlobStore {
+            backend,
+            compression: CompressionAlgo::None,
+            encryption: None,
+        }
     }
 }
 
@@ -309,7 +336,7 @@ impl From<Store> for FtsStore {
 
 impl From<Store> for BlobStore {
     fn from(store: Store) -> Self {
-        Self::Store(store)
+        BlobBackend::Store(store).into()
     }
 }
 
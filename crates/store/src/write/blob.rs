@@ -36,6 +36,12 @@ pub struct BlobQuota {
     pub count: usize,
 }
 
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct BlobRewrite {
+    pub rewritten: usize,
+    pub failed: usize,
+}
+
 impl Store {
     pub async fn blob_exists(
         &self,
@@ -131,6 +137,95 @@ impl Store {
         self.get_value::<()>(key).await.map(|v| v.is_some())
     }
 
+    pub async fn rewrite_blobs(&self, blob_store: BlobStore) -> crate::Result<BlobRewrite> {
+        // Committed blobs are obtained in batches to avoid loading every hash into memory
+        const BATCH_SIZE: usize = 1000;
+        let mut from_key = ValueKey {
+            account_id: 0,
+            collection: 0,
+            document_id: 0,
+            class: ValueClass::Blob(BlobOp::Link {
+                hash: BlobHash::default(),
+            }),
+        };
+        let to_key = ValueKey {
+            account_id: u32::MAX,
+            collection: u8::MAX,
+            document_id: u32::MAX,
+            class: ValueClass::Blob(BlobOp::Link {
+                hash: BlobHash::new_max(),
+            }),
+        };
+        let mut result = BlobRewrite::default();
+
+        loop {
+            let mut hashes = Vec::with_capacity(BATCH_SIZE);
+            self.iterate(
+                IterateParams::new(from_key, to_key.clone())
+                    .ascending()
+                    .no_values(),
+                |key, _| {
+                    if key.deserialize_be_u32(key.len() - U32_LEN)? == u32::MAX {
+                        hashes.push(
+                            BlobHash::try_from_hash_slice(
+                                key.get(1..1 + BLOB_HASH_LEN).ok_or_else(|| {
+                                    crate::Error::InternalError(format!(
+                                        "Invalid key {key:?} in blob hash tables"
+                                    ))
+                                })?,
+                            )
+                            .unwrap(),
+                        );
+                    }
+
+                    Ok(hashes.len() < BATCH_SIZE)
+                },
+            )
+            .await?;
+
+            // Rewrite blobs using the configured compression algorithm and master key,
+            // a single unreadable blob should not prevent the remaining ones from being rewritten
+            let is_last_batch = hashes.len() < BATCH_SIZE;
+            let last_hash = hashes.last().cloned();
+            for hash in hashes {
+                // Skip blobs purged in the meantime
+                let rewritten = match self.blob_exists(&hash).await {
+                    Ok(true) => blob_store.rewrite_blob(hash.as_ref()).await,
+                    Ok(false) => Ok(false),
+                    Err(err) => Err(err),
+                };
+
+                match rewritten {
+                    Ok(true) => result.rewritten += 1,
+                    Ok(false) => (),
+                    Err(err) => {
+                        tracing::warn!(
+                            context = "blob_store",
+                            event = "error",
+                            "Failed to rewrite blob {hash:?}: {err}"
+                        );
+                        result.failed += 1;
+                    }
+                }
+            }
+
+            // Continue after the last key of the last blob in this batch
+            match last_hash {
+                Some(hash) if !is_last_batch => {
+                    from_key = ValueKey {
+                        account_id: u32::MAX,
+                        collection: u8::MAX,
+                        document_id: u32::MAX,
+                        class: ValueClass::Blob(BlobOp::Link { hash }),
+                    };
+                }
+                _ => break,
+            }
+        }
+
+        Ok(result)
+    }
+
     pub async fn purge_blobs(&self, blob_store: BlobStore) -> crate::Result<()> {
         // Remove expired temporary blobs
         let from_key = ValueKey {
@@ -30,7 +30,9 @@ use crate::{
     WITH_SUBSPACE,
 };
 
-use super::{AnyKey, BitmapClass, BlobOp, DirectoryClass, TagValue, ValueClass};
+use super::{
+    AnyKey, BitmapClass, BlobOp, DirectoryClass, QueueClass, ReportClass, TagValue, ValueClass,
+};
 
 pub struct KeySerializer {
     pub buf: Vec<u8>,
@@ -276,6 +278,25 @@ impl<T: AsRef<ValueClass> + Sync + Send> Key for ValueKey<T> {
                     .write(self.document_id),
             },
             ValueClass::Config(key) => serializer.write(8u8).write(key.as_slice()),
+            ValueClass::Queue(queue) => match queue {
+                QueueClass::Message(queue_id) => serializer.write(9u8).write(*queue_id),
+                QueueClass::MessageEvent(event) => serializer
+                    .write(10u8)
+                    .write(event.due)
+                    .write(event.queue_id),
+                QueueClass::MessageLock(queue_id) => serializer.write(14u8).write(*queue_id),
+            },
+            ValueClass::Report(report) => match report {
+                ReportClass::Tls { id, expires } => {
+                    serializer.write(11u8).write(*expires).write(*id)
+                }
+                ReportClass::Dmarc { id, expires } => {
+                    serializer.write(12u8).write(*expires).write(*id)
+                }
+                ReportClass::Arf { id, expires } => {
+                    serializer.write(13u8).write(*expires).write(*id)
+                }
+            },
             ValueClass::Directory(directory) => match directory {
                 DirectoryClass::NameToId(name) => serializer.write(20u8).write(name.as_slice()),
                 DirectoryClass::EmailToId(email) => serializer.write(21u8).write(email.as_slice()),
@@ -296,6 +317,30 @@ impl<T: AsRef<ValueClass> + Sync + Send> Key for ValueKey<T> {
                     .write(26u8)
                     .write(*principal_id)
                     .write(*has_member),
+                DirectoryClass::Totp(uid) => serializer.write(28u8).write_leb128(*uid),
+                DirectoryClass::AppPassword { principal_id, name } => serializer
+                    .write(27u8)
+                    .write(*principal_id)
+                    .write(name.as_slice()),
+                DirectoryClass::MailingList(uid) => serializer.write(29u8).write_leb128(*uid),
+                DirectoryClass::ListSubscriber { list_id, email } => serializer
+                    .write(30u8)
+                    .write(*list_id)
+                    .write(email.as_slice()),
+                DirectoryClass::ListModeration { list_id, id } => {
+                    serializer.write(31u8).write(*list_id).write(*id)
+                }
+                DirectoryClass::OidcSubject(subject) => {
+                    serializer.write(32u8).write(subject.as_slice())
+                }
+                DirectoryClass::AppPasswordHash { principal_id, hash } => serializer
+                    .write(33u8)
+                    .write(*principal_id)
+                    .write(hash.as_slice()),
+                DirectoryClass::AppPasswordUsage { principal_id, name } => serializer
+                    .write(34u8)
+                    .write(*principal_id)
+                    .write(name.as_slice()),
             },
         }
         .finalize()
@@ -429,15 +474,29 @@ impl ValueClass {
             ValueClass::Directory(d) => match d {
                 DirectoryClass::NameToId(v)
                 | DirectoryClass::EmailToId(v)
-                | DirectoryClass::Domain(v) => v.len(),
-                DirectoryClass::Principal(_) | DirectoryClass::UsedQuota(_) => U32_LEN,
+                | DirectoryClass::Domain(v)
+                | DirectoryClass::OidcSubject(v) => v.len(),
+                DirectoryClass::Principal(_)
+                | DirectoryClass::UsedQuota(_)
+                | DirectoryClass::Totp(_)
+                | DirectoryClass::MailingList(_) => U32_LEN,
                 DirectoryClass::Members { .. } | DirectoryClass::MemberOf { .. } => U32_LEN * 2,
+                DirectoryClass::AppPassword { name, .. }
+                | DirectoryClass::AppPasswordUsage { name, .. } => U32_LEN + name.len(),
+                DirectoryClass::AppPasswordHash { hash, .. } => U32_LEN + hash.len(),
+                DirectoryClass::ListSubscriber { email, .. } => U32_LEN + email.len(),
+                DirectoryClass::ListModeration { .. } => U32_LEN + U64_LEN,
             },
             ValueClass::Blob(op) => match op {
                 BlobOp::Reserve { .. } => BLOB_HASH_LEN + U64_LEN + U32_LEN + 1,
                 BlobOp::Commit { .. } | BlobOp::Link { .. } => BLOB_HASH_LEN + U32_LEN * 2 + 2,
             },
             ValueClass::IndexEmail { .. } => U64_LEN * 2,
+            ValueClass::Queue(q) => match q {
+                QueueClass::Message(_) | QueueClass::MessageLock(_) => U64_LEN,
+                QueueClass::MessageEvent(_) => U64_LEN * 2,
+            },
+            ValueClass::Report(_) => U64_LEN * 2,
         }
     }
 }
@@ -475,3 +534,37 @@ impl From<BlobOp> for ValueClass {
         ValueClass::Blob(value)
     }
 }
+
+impl From<QueueClass> for ValueClass {
+    fn from(value: QueueClass) -> Self {
+        ValueClass::Queue(value)
+    }
+}
+
+impl From<ReportClass> for ValueClass {
+    fn from(value: ReportClass) -> Self {
+        ValueClass::Report(value)
+    }
+}
+
+impl From<ReportClass> for ValueKey<ValueClass> {
+    fn from(value: ReportClass) -> Self {
+        ValueKey {
+            account_id: 0,
+            collection: 0,
+            document_id: 0,
+            class: ValueClass::Report(value),
+        }
+    }
+}
+
+impl From<QueueClass> for ValueKey<ValueClass> {
+    fn from(value: QueueClass) -> Self {
+        ValueKey {
+            account_id: 0,
+            collection: 0,
+            document_id: 0,
+            class: ValueClass::Queue(value),
+        }
+    }
+}
@@ -138,6 +138,8 @@ pub enum ValueClass {
     Blob(BlobOp),
     IndexEmail(u64),
     Config(Vec<u8>),
+    Queue(QueueClass),
+    Report(ReportClass),
 }
 
 #[derive(Debug, PartialEq, Clone, Eq, Hash)]
@@ -146,11 +148,39 @@ pub enum DirectoryClass {
     EmailToId(Vec<u8>),
     MemberOf { principal_id: u32, member_of: u32 },
     Members { principal_id: u32, has_member: u32 },
+    AppPassword { principal_id: u32, name: Vec<u8> },
+    AppPasswordHash { principal_id: u32, hash: Vec<u8> },
+    AppPasswordUsage { principal_id: u32, name: Vec<u8> },
+    Totp(u32),
+    MailingList(u32),
+    ListSubscriber { list_id: u32, email: Vec<u8> },
+    ListModeration { list_id: u32, id: u64 },
+    OidcSubject(Vec<u8>),
     Domain(Vec<u8>),
     Principal(u32),
     UsedQuota(u32),
 }
 
+#[derive(Debug, PartialEq, Clone, Eq, Hash)]
+pub enum QueueClass {
+    Message(u64),
+    MessageEvent(QueueEvent),
+    MessageLock(u64),
+}
+
+#[derive(Debug, PartialEq, Clone, Copy, Eq, Hash)]
+pub struct QueueEvent {
+    pub due: u64,
+    pub queue_id: u64,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy, Eq, Hash)]
+pub enum ReportClass {
+    Tls { id: u64, expires: u64 },
+    Dmarc { id: u64, expires: u64 },
+    Arf { id: u64, expires: u64 },
+}
+
 #[derive(Debug, PartialEq, Eq, Hash, Default)]
 pub enum ValueOp {
     Set(Vec<u8>),
@@ -486,6 +516,14 @@ impl Deserialize for () {
     }
 }
 
+pub struct RawValue(pub Vec<u8>);
+
+impl Deserialize for RawValue {
+    fn deserialize(bytes: &[u8]) -> crate::Result<Self> {
+        Ok(RawValue(bytes.to_vec()))
+    }
+}
+
 pub trait IntoOperations {
     fn build(self, batch: &mut BatchBuilder);
 }
@@ -16,7 +16,7 @@ use rustls_pki_types::{CertificateDer, PrivateKeyDer, PrivatePkcs8KeyDer};
 use serde::{Deserialize, Serialize};
 use serde_json::json;
 
-use super::jose::{key_authorization_sha256, sign, JoseError};
+use super::jose::{key_authorization, key_authorization_sha256, sign, JoseError};
 
 pub const LETS_ENCRYPT_STAGING_DIRECTORY: &str =
     "https://acme-staging-v02.api.letsencrypt.org/directory";
@@ -138,18 +138,11 @@ impl Account {
         Ok(self.request(&url, "").await?.1)
     }
 
-    pub fn tls_alpn_01<'a>(
+    pub fn tls_alpn_01(
         &self,
-        challenges: &'a [Challenge],
+        challenge: &Challenge,
         domain: String,
-    ) -> Result<(&'a Challenge, CertifiedKey), DirectoryError> {
-        let challenge = challenges
-            .iter()
-            .find(|c| c.typ == ChallengeType::TlsAlpn01);
-        let challenge = match challenge {
-            Some(challenge) => challenge,
-            None => return Err(DirectoryError::NoTlsAlpn01Challenge),
-        };
+    ) -> Result<CertifiedKey, DirectoryError> {
         let mut params = rcgen::CertificateParams::new(vec![domain]);
         let key_auth = key_authorization_sha256(&self.key_pair, &challenge.token)?;
         params.alg = &PKCS_ECDSA_P256_SHA256;
@@ -159,9 +152,20 @@ impl Account {
             cert.serialize_private_key_der(),
         )))
         .unwrap();
-        let certified_key =
-            CertifiedKey::new(vec![CertificateDer::from(cert.serialize_der()?)], pk);
-        Ok((challenge, certified_key))
+        Ok(CertifiedKey::new(
+            vec![CertificateDer::from(cert.serialize_der()?)],
+            pk,
+        ))
+    }
+
+    pub fn http_01(&self, challenge: &Challenge) -> Result<String, DirectoryError> {
+        key_authorization(&self.key_pair, &challenge.token).map_err(Into::into)
+    }
+
+    pub fn dns_01(&self, challenge: &Challenge) -> Result<String, DirectoryError> {
+        key_authorization_sha256(&self.key_pair, &challenge.token)
+            .map(|digest| URL_SAFE_NO_PAD.encode(digest.as_ref()))
+            .map_err(Into::into)
     }
 }
 
@@ -187,7 +191,7 @@ impl Directory {
     }
 }
 
-#[derive(Debug, Deserialize, Eq, PartialEq)]
+#[derive(Debug, Clone, Copy, Deserialize, Eq, PartialEq)]
 pub enum ChallengeType {
     #[serde(rename = "http-01")]
     Http01,
@@ -225,6 +229,15 @@ pub struct Auth {
     pub challenges: Vec<Challenge>,
 }
 
+impl Auth {
+    pub fn challenge(&self, typ: ChallengeType) -> Result<&Challenge, DirectoryError> {
+        self.challenges
+            .iter()
+            .find(|c| c.typ == typ)
+            .ok_or(DirectoryError::NoChallenge(typ))
+    }
+}
+
 #[derive(Debug, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub enum AuthStatus {
@@ -271,7 +284,7 @@ pub enum DirectoryError {
     KeyRejected(KeyRejected),
     Crypto(Unspecified),
     MissingHeader(&'static str),
-    NoTlsAlpn01Challenge,
+    NoChallenge(ChallengeType),
 }
 
 #[allow(unused_mut)]
@@ -0,0 +1,423 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::{
+    fmt::Debug,
+    net::SocketAddr,
+    str::FromStr,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use hickory_proto::{
+    error::ProtoError,
+    op::{update_message, Message, MessageType, ResponseCode},
+    rr::{
+        dnssec::{rdata::tsig::TsigAlgorithm, tsig::TSigner},
+        rdata::TXT,
+        Name, RData, Record, RecordSet,
+    },
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpStream, UdpSocket},
+};
+
+/// Publishes ACME DNS-01 challenge records using RFC 2136 dynamic updates,
+/// optionally authenticated with TSIG (RFC 8945).
+#[derive(Clone)]
+pub struct DnsUpdater {
+    pub server: SocketAddr,
+    pub protocol: DnsProtocol,
+    pub zone: Name,
+    pub tsig: Option<TSigner>,
+    pub ttl: u32,
+    pub timeout: Duration,
+    pub propagation_delay: Duration,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DnsProtocol {
+    Udp,
+    Tcp,
+}
+
+#[derive(Debug)]
+pub enum DnsError {
+    Io(std::io::Error),
+    Proto(ProtoError),
+    Timeout,
+    NotInZone(String),
+    InvalidResponse,
+    Rejected(ResponseCode),
+}
+
+impl DnsUpdater {
+    pub fn new(server: SocketAddr, zone: &str) -> Result<Self, DnsError> {
+        Ok(DnsUpdater {
+            server,
+            protocol: DnsProtocol::Udp,
+            zone: fqdn(zone)?,
+            tsig: None,
+            ttl: 60,
+            timeout: Duration::from_secs(30),
+            propagation_delay: Duration::ZERO,
+        })
+    }
+
+    pub fn with_tsig(
+        mut self,
+        key_name: &str,
+        key: Vec<u8>,
+        algorithm: TsigAlgorithm,
+    ) -> Result<Self, DnsError> {
+        self.tsig = TSigner::new(key, algorithm, fqdn(key_name)?, 300)?.into();
+        Ok(self)
+    }
+
+    pub fn is_in_zone(&self, domain: &str) -> bool {
+        fqdn(&challenge_name(domain)).is_ok_and(|name| self.zone.zone_of(&name))
+    }
+
+    /// Adds a TXT record with the provided value to the challenge RRset of a domain.
+    pub async fn add_challenge(&self, domain: &str, value: &str) -> Result<(), DnsError> {
+        let rrset = self.txt_rrset(domain, value, self.ttl)?;
+        self.send(update_message::append(
+            rrset,
+            self.zone.clone(),
+            false,
+            false,
+        ))
+        .await
+    }
+
+    /// Removes a previously published TXT record, leaving any other values in place.
+    pub async fn remove_challenge(&self, domain: &str, value: &str) -> Result<(), DnsError> {
+        let rrset = self.txt_rrset(domain, value, 0)?;
+        self.send(update_message::delete_by_rdata(
+            rrset,
+            self.zone.clone(),
+            false,
+        ))
+        .await
+    }
+
+    fn txt_rrset(&self, domain: &str, value: &str, ttl: u32) -> Result<RecordSet, DnsError> {
+        let name = challenge_name(domain);
+        let name = fqdn(&name)?;
+        if self.zone.zone_of(&name) {
+            Ok(RecordSet::from(Record::from_rdata(
+                name,
+                ttl,
+                RData::TXT(TXT::new(vec![value.to_string()])),
+            )))
+        } else {
+            Err(DnsError::NotInZone(name.to_string()))
+        }
+    }
+
+    async fn send(&self, mut message: Message) -> Result<(), DnsError> {
+        if let Some(tsig) = &self.tsig {
+            let now = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |d| d.as_secs()) as u32;
+            message.finalize(tsig, now)?;
+        }
+        let request = message.to_vec()?;
+
+        let response = tokio::time::timeout(self.timeout, async {
+            match self.protocol {
+                DnsProtocol::Udp => {
+                    let socket = UdpSocket::bind(if self.server.is_ipv4() {
+                        "0.0.0.0:0"
+                    } else {
+                        "[::]:0"
+                    })
+                    .await?;
+                    socket.connect(self.server).await?;
+                    socket.send(&request).await?;
+                    let mut buf = vec![0u8; 4096];
+                    let len = socket.recv(&mut buf).await?;
+                    buf.truncate(len);
+                    Ok::<_, std::io::Error>(buf)
+                }
+                DnsProtocol::Tcp => {
+                    let mut stream = TcpStream::connect(self.server).await?;
+                    stream
+                        .write_all(&(request.len() as u16).to_be_bytes())
+                        .await?;
+                    stream.write_all(&request).await?;
+                    let len = stream.read_u16().await? as usize;
+                    let mut buf = vec![0u8; len];
+                    stream.read_exact(&mut buf).await?;
+                    Ok(buf)
+                }
+            }
+        })
+        .await
+        .map_err(|_| DnsError::Timeout)??;
+
+        let response = Message::from_vec(&response)?;
+        if response.id() != message.id() || response.message_type() != MessageType::Response {
+            Err(DnsError::InvalidResponse)
+        } else if response.response_code() != ResponseCode::NoError {
+            Err(DnsError::Rejected(response.response_code()))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+pub fn challenge_name(domain: &str) -> String {
+    format!(
+        "_acme-challenge.{}",
+        domain.strip_prefix("*.").unwrap_or(domain)
+    )
+}
+
+fn fqdn(name: &str) -> Result<Name, DnsError> {
+    let mut name = Name::from_str(name.trim())?;
+    name.set_fqdn(true);
+    Ok(name)
+}
+
+impl Debug for DnsUpdater {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("DnsUpdater")
+            .field("server", &self.server)
+            .field("protocol", &self.protocol)
+            .field("zone", &self.zone)
+            .field(
+                "tsig",
+                &self
+                    .tsig
+                    .as_ref()
+                    .map(|tsig| tsig.signer_name().to_string()),
+            )
+            .field("ttl", &self.ttl)
+            .finish()
+    }
+}
+
+impl From<std::io::Error> for DnsError {
+    fn from(err: std::io::Error) -> Self {
+        Self::Io(err)
+    }
+}
+
+impl From<ProtoError> for DnsError {
+    fn from(err: ProtoError) -> Self {
+        Self::Proto(err)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use hickory_proto::{
+        op::{Message, MessageType, ResponseCode, UpdateMessage},
+        rr::{
+            dnssec::{rdata::tsig::TsigAlgorithm, tsig::TSigner},
+            DNSClass, Name, RData,
+        },
+    };
+    use tokio::{net::UdpSocket, sync::mpsc};
+
+    use crate::{acme::ChallengeSettings, config::Config};
+
+    use super::{DnsError, DnsUpdater};
+
+    const KEY: &[u8] = b"stalwart-acme-tsig-test-key-0001";
+
+    #[tokio::test]
+    async fn dns_update() {
+        // Start a DNS server that only accepts updates signed with the test key
+        let socket = Arc::new(UdpSocket::bind("127.0.0.1:0").await.unwrap());
+        let server = socket.local_addr().unwrap();
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            let signer = TSigner::new(
+                KEY.to_vec(),
+                TsigAlgorithm::HmacSha256,
+                Name::from_ascii("acme-key.").unwrap(),
+                300,
+            )
+            .unwrap();
+            let mut buf = vec![0u8; 4096];
+            loop {
+                let (len, peer) = socket.recv_from(&mut buf).await.unwrap();
+                let request = Message::from_vec(&buf[..len]).unwrap();
+                let rcode = if signer.verify_message_byte(None, &buf[..len], true).is_ok() {
+                    let updates = request
+                        .updates()
+                        .iter()
+                        .map(|record| {
+                            (
+                                request.queries()[0].name().to_string(),
+                                record.name().to_string(),
+                                record.dns_class(),
+                                record.ttl(),
+                                match record.data() {
+                                    Some(RData::TXT(txt)) => txt.to_string(),
+                                    other => panic!("Unexpected record data {other:?}"),
+                                },
+                            )
+                        })
+                        .collect::<Vec<_>>();
+                    tx.send(updates).unwrap();
+                    ResponseCode::NoError
+                } else {
+                    ResponseCode::NotAuth
+                };
+                let mut response = Message::new();
+                response
+                    .set_id(request.id())
+                    .set_message_type(MessageType::Response)
+                    .set_op_code(request.op_code())
+                    .set_response_code(rcode);
+                socket
+                    .send_to(&response.to_vec().unwrap(), peer)
+                    .await
+                    .unwrap();
+            }
+        });
+
+        // Parse the updater from the configuration
+        let config = Config::new(&format!(
+            concat!(
+                "[acme.\"test\"]\n",
+                "directory = \"https://localhost:4000/directory\"\n",
+                "contact = [\"postmaster@example.org\"]\n",
+                "cache = \"/tmp/acme-cache\"\n",
+                "domains = [\"*.example.org\", \"example.org\"]\n",
+                "challenge = \"dns-01\"\n",
+                "[acme.\"test\".dns]\n",
+                "server = \"{}\"\n",
+                "port = {}\n",
+                "zone = \"example.org\"\n",
+                "ttl = 30\n",
+                "[acme.\"test\".dns.tsig]\n",
+                "name = \"acme-key\"\n",
+                "secret = \"c3RhbHdhcnQtYWNtZS10c2lnLXRlc3Qta2V5LTAwMDE=\"\n",
+            ),
+            server.ip(),
+            server.port()
+        ))
+        .unwrap();
+        let acme = config.parse_acmes().unwrap().remove("test").unwrap();
+        assert_eq!(acme.domains, ["*.example.org", "example.org"]);
+        let updater = match &acme.challenge {
+            ChallengeSettings::Dns01(updater) => updater.clone(),
+            other => panic!("Unexpected challenge {other:?}"),
+        };
+        assert_eq!(updater.server, server);
+        assert!(!updater.is_in_zone("example.com"));
+
+        // Wildcard and base domain share the same challenge name
+        updater
+            .add_challenge("*.example.org", "proof-wildcard")
+            .await
+            .unwrap();
+        updater
+            .add_challenge("example.org", "proof-base")
+            .await
+            .unwrap();
+        updater
+            .remove_challenge("*.example.org", "proof-wildcard")
+            .await
+            .unwrap();
+        for (class, ttl, value) in [
+            (DNSClass::IN, 30, "proof-wildcard"),
+            (DNSClass::IN, 30, "proof-base"),
+            (DNSClass::NONE, 0, "proof-wildcard"),
+        ] {
+            assert_eq!(
+                rx.recv().await.unwrap(),
+                vec![(
+                    "example.org.".to_string(),
+                    "_acme-challenge.example.org.".to_string(),
+                    class,
+                    ttl,
+                    value.to_string()
+                )]
+            );
+        }
+
+        // Names outside the zone are not sent
+        assert!(matches!(
+            updater.add_challenge("example.com", "proof").await,
+            Err(DnsError::NotInZone(_))
+        ));
+
+        // Updates signed with the wrong key or unsigned are rejected
+        for updater in [
+            DnsUpdater::new(server, "example.org")
+                .unwrap()
+                .with_tsig("acme-key", b"wrong-key".to_vec(), TsigAlgorithm::HmacSha256)
+                .unwrap(),
+            DnsUpdater::new(server, "example.org").unwrap(),
+        ] {
+            assert!(matches!(
+                updater.add_challenge("example.org", "proof").await,
+                Err(DnsError::Rejected(ResponseCode::NotAuth))
+            ));
+        }
+        assert!(rx.try_recv().is_err());
+    }
+
+    #[test]
+    fn acme_challenge_config() {
+        for (challenge, domains, expected) in [
+            ("http-01", "[\"mail.example.org\"]", Ok("Http01")),
+            ("tls-alpn-01", "[\"mail.example.org\"]", Ok("TlsAlpn01")),
+            (
+                "http-01",
+                "[\"*.example.org\"]",
+                Err("Wildcard domain \"*.example.org\" requires the dns-01 challenge for acme.test."),
+            ),
+            (
+                "dns-02",
+                "[\"mail.example.org\"]",
+                Err("Invalid challenge type \"dns-02\" for acme.test."),
+            ),
+        ] {
+            let config = Config::new(&format!(
+                concat!(
+                    "[acme.\"test\"]\n",
+                    "contact = [\"postmaster@example.org\"]\n",
+                    "cache = \"/tmp/acme-cache\"\n",
+                    "domains = {}\n",
+                    "challenge = \"{}\"\n",
+                ),
+                domains, challenge
+            ))
+            .unwrap();
+            assert_eq!(
+                config
+                    .parse_acmes()
+                    .map(|mut acmes| format!("{:?}", acmes.remove("test").unwrap().challenge)),
+                expected.map(|e| e.to_string()).map_err(|e| e.to_string())
+            );
+        }
+    }
+}
@@ -23,6 +23,7 @@
 
 pub mod cache;
 pub mod directory;
+pub mod dns;
 pub mod jose;
 pub mod order;
 pub mod resolver;
@@ -46,7 +47,8 @@ use tokio::sync::watch;
 use crate::config::tls::build_self_signed_cert;
 
 use self::{
-    directory::Account,
+    directory::{Account, ChallengeType},
+    dns::DnsUpdater,
     order::{CertParseError, OrderError},
 };
 
@@ -56,12 +58,21 @@ pub struct AcmeManager {
     contact: Vec<String>,
     renew_before: chrono::Duration,
     cache_path: PathBuf,
+    pub(crate) challenge: ChallengeSettings,
     account_key: ArcSwap<Vec<u8>>,
     auth_keys: Mutex<AHashMap<String, Arc<CertifiedKey>>>,
+    http_tokens: Mutex<AHashMap<String, String>>,
     order_in_progress: AtomicBool,
     cert: ArcSwap<CertifiedKey>,
 }
 
+#[derive(Debug, Clone)]
+pub enum ChallengeSettings {
+    TlsAlpn01,
+    Http01,
+    Dns01(DnsUpdater),
+}
+
 #[derive(Debug)]
 pub enum AcmeError {
     CertCacheLoad(std::io::Error),
@@ -80,6 +91,7 @@ impl AcmeManager {
         contact: Vec<String>,
         renew_before: Duration,
         cache_path: PathBuf,
+        challenge: ChallengeSettings,
     ) -> crate::config::Result<Self> {
         Ok(AcmeManager {
             directory_url,
@@ -95,8 +107,10 @@ impl AcmeManager {
                 .collect(),
             renew_before: chrono::Duration::from_std(renew_before).unwrap(),
             cache_path,
+            challenge,
             account_key: ArcSwap::from_pointee(Vec::new()),
             auth_keys: Mutex::new(AHashMap::new()),
+            http_tokens: Mutex::new(AHashMap::new()),
             order_in_progress: false.into(),
             cert: ArcSwap::from_pointee(build_self_signed_cert(&domains)?),
             domains,
@@ -124,6 +138,20 @@ impl AcmeManager {
     pub fn has_order_in_progress(&self) -> bool {
         self.order_in_progress.load(Ordering::Relaxed)
     }
+
+    pub fn http_challenge(&self, token: &str) -> Option<String> {
+        self.http_tokens.lock().get(token).cloned()
+    }
+}
+
+impl ChallengeSettings {
+    pub fn challenge_type(&self) -> ChallengeType {
+        match self {
+            ChallengeSettings::TlsAlpn01 => ChallengeType::TlsAlpn01,
+            ChallengeSettings::Http01 => ChallengeType::Http01,
+            ChallengeSettings::Dns01(_) => ChallengeType::Dns01,
+        }
+    }
 }
 
 pub trait SpawnAcme {
@@ -200,6 +228,7 @@ impl Debug for AcmeManager {
             .field("domains", &self.domains)
             .field("contact", &self.contact)
             .field("cache_path", &self.cache_path)
+            .field("challenge", &self.challenge)
             .field("account_key", &self.account_key)
             .finish()
     }
@@ -15,13 +15,15 @@ use x509_parser::parse_x509_certificate;
 use crate::acme::directory::Identifier;
 
 use super::directory::{Account, Auth, AuthStatus, Directory, DirectoryError, Order, OrderStatus};
+use super::dns::DnsError;
 use super::jose::JoseError;
-use super::{AcmeError, AcmeManager};
+use super::{AcmeError, AcmeManager, ChallengeSettings};
 
 #[derive(Debug)]
 pub enum OrderError {
     Acme(DirectoryError),
     Rcgen(rcgen::Error),
+    Dns(DnsError),
     BadOrder(Order),
     BadAuth(Auth),
     TooManyAttemptsAuth(String),
@@ -193,24 +195,69 @@ impl AcmeManager {
 
     async fn authorize(&self, account: &Account, url: &String) -> Result<(), OrderError> {
         let auth = account.auth(url).await?;
-        let (domain, challenge_url) = match auth.status {
+        let (domain, challenge_url, dns_proof) = match auth.status {
             AuthStatus::Pending => {
-                let Identifier::Dns(domain) = auth.identifier;
+                let Identifier::Dns(domain) = &auth.identifier;
+                let domain = domain.clone();
                 tracing::info!(
                     context = "acme",
                     event = "challenge",
                     domain = domain,
+                    challenge = ?self.challenge.challenge_type(),
                     "Requesting challenge for domain {domain}"
                 );
-                let (challenge, auth_key) =
-                    account.tls_alpn_01(&auth.challenges, domain.clone())?;
-                self.set_auth_key(domain.clone(), Arc::new(auth_key));
-                account.challenge(&challenge.url).await?;
-                (domain, challenge.url.clone())
+                let challenge = auth.challenge(self.challenge.challenge_type())?;
+                let mut dns_proof = None;
+                match &self.challenge {
+                    ChallengeSettings::TlsAlpn01 => {
+                        let auth_key = account.tls_alpn_01(challenge, domain.clone())?;
+                        self.set_auth_key(domain.clone(), Arc::new(auth_key));
+                    }
+                    ChallengeSettings::Http01 => {
+                        let key_authorization = account.http_01(challenge)?;
+                        self.set_http_token(challenge.token.clone(), key_authorization);
+                    }
+                    ChallengeSettings::Dns01(updater) => {
+                        let proof = account.dns_01(challenge)?;
+                        updater.add_challenge(&domain, &proof).await?;
+                        if !updater.propagation_delay.is_zero() {
+                            tokio::time::sleep(updater.propagation_delay).await;
+                        }
+                        dns_proof = proof.into();
+                    }
+                }
+                (domain, challenge.url.clone(), dns_proof)
             }
             AuthStatus::Valid => return Ok(()),
             _ => return Err(OrderError::BadAuth(auth)),
         };
+
+        let result = self.validate(account, url, &domain, &challenge_url).await;
+
+        // Remove the DNS record once the authorization has been processed
+        if let (ChallengeSettings::Dns01(updater), Some(proof)) = (&self.challenge, dns_proof) {
+            if let Err(err) = updater.remove_challenge(&domain, &proof).await {
+                tracing::warn!(
+                    context = "acme",
+                    event = "error",
+                    domain = domain,
+                    reason = ?err,
+                    "Failed to remove DNS challenge record for domain {domain}"
+                );
+            }
+        }
+
+        result
+    }
+
+    async fn validate(
+        &self,
+        account: &Account,
+        url: &String,
+        domain: &String,
+        challenge_url: &String,
+    ) -> Result<(), OrderError> {
+        account.challenge(challenge_url).await?;
         for i in 0u64..5 {
             tokio::time::sleep(Duration::from_secs(1u64 << i)).await;
             let auth = account.auth(url).await?;
@@ -223,13 +270,13 @@ impl AcmeManager {
                         attempt = i,
                         "Authorization for domain {domain} is still pending",
                     );
-                    account.challenge(&challenge_url).await?
+                    account.challenge(challenge_url).await?
                 }
                 AuthStatus::Valid => return Ok(()),
                 _ => return Err(OrderError::BadAuth(auth)),
             }
         }
-        Err(OrderError::TooManyAttemptsAuth(domain))
+        Err(OrderError::TooManyAttemptsAuth(domain.clone()))
     }
 }
 
@@ -269,6 +316,12 @@ impl From<DirectoryError> for OrderError {
     }
 }
 
+impl From<DnsError> for OrderError {
+    fn from(err: DnsError) -> Self {
+        Self::Dns(err)
+    }
+}
+
 impl From<rcgen::Error> for OrderError {
     fn from(err: rcgen::Error) -> Self {
         Self::Rcgen(err)
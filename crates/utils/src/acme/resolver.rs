@@ -28,6 +28,8 @@ use rustls::{
     sign::CertifiedKey,
 };
 
+use crate::listener::tls::ServerCertificates;
+
 use super::{directory::ACME_TLS_ALPN_NAME, AcmeManager};
 
 impl AcmeManager {
@@ -35,10 +37,14 @@ impl AcmeManager {
         self.cert.store(cert);
         self.order_in_progress.store(false, Ordering::Relaxed);
         self.auth_keys.lock().clear();
+        self.http_tokens.lock().clear();
     }
     pub(crate) fn set_auth_key(&self, domain: String, cert: Arc<CertifiedKey>) {
         self.auth_keys.lock().insert(domain, cert);
     }
+    pub(crate) fn set_http_token(&self, token: String, key_authorization: String) {
+        self.http_tokens.lock().insert(token, key_authorization);
+    }
 }
 
 impl ResolvesServerCert for AcmeManager {
@@ -70,6 +76,12 @@ impl ResolvesServerCert for AcmeManager {
     }
 }
 
+impl ServerCertificates for AcmeManager {
+    fn certificate(&self, _: Option<&str>) -> Option<Arc<CertifiedKey>> {
+        self.cert.load().clone().into()
+    }
+}
+
 pub trait IsTlsAlpnChallenge {
     fn is_tls_alpn_challenge(&self) -> bool;
 }
@@ -26,6 +26,7 @@ use std::{io::Write, slice::Iter};
 use super::leb128::{Leb128Iterator, Leb128Writer};
 
 pub static BASE32_ALPHABET: &[u8] = b"abcdefghijklmnopqrstuvwxyz792013";
+pub static BASE32_RFC4648_ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
 pub static BASE32_INVERSE: [u8; 256] = [
     255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255,
     255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255,
@@ -46,6 +47,7 @@ pub struct Base32Writer {
     last_byte: u8,
     pos: usize,
     result: String,
+    alphabet: &'static [u8],
 }
 
 impl Base32Writer {
@@ -61,9 +63,19 @@ impl Base32Writer {
             result: String::with_capacity((capacity + 3) / 4 * 5),
             last_byte: 0,
             pos: 0,
+            alphabet: BASE32_ALPHABET,
         }
     }
 
+    // Encodes using the standard alphabet, without padding
+    pub fn rfc4648_from_bytes(bytes: impl AsRef<[u8]>) -> Self {
+        let bytes = bytes.as_ref();
+        let mut writer = Base32Writer::with_capacity(bytes.len());
+        writer.alphabet = BASE32_RFC4648_ALPHABET;
+        writer.write_all(bytes).unwrap();
+        writer
+    }
+
     pub fn push_char(&mut self, ch: char) {
         self.result.push(ch);
     }
@@ -90,10 +102,10 @@ impl Base32Writer {
             _ => unreachable!(),
         };
 
-        self.result.push(char::from(BASE32_ALPHABET[ch1 as usize]));
+        self.result.push(char::from(self.alphabet[ch1 as usize]));
         if !is_remainder {
             if ch2 != u8::MAX {
-                self.result.push(char::from(BASE32_ALPHABET[ch2 as usize]));
+                self.result.push(char::from(self.alphabet[ch2 as usize]));
             }
             self.last_byte = byte;
             self.pos += 1;
@@ -219,4 +231,22 @@ mod tests {
             assert_eq!(bytes, bytes_result);
         }
     }
+
+    #[test]
+    fn base32_rfc4648() {
+        for (input, expected) in [
+            ("", ""),
+            ("f", "MY"),
+            ("fo", "MZXQ"),
+            ("foo", "MZXW6"),
+            ("foob", "MZXW6YQ"),
+            ("fooba", "MZXW6YTB"),
+            ("foobar", "MZXW6YTBOI"),
+        ] {
+            assert_eq!(
+                Base32Writer::rfc4648_from_bytes(input.as_bytes()).finalize(),
+                expected
+            );
+        }
+    }
 }
@@ -24,6 +24,7 @@
 use std::{net::SocketAddr, sync::Arc};
 
 use ahash::AHashMap;
+use arc_swap::ArcSwap;
 use rustls::{
     crypto::ring::{
         cipher_suite::{
@@ -41,10 +42,10 @@ use tokio::net::TcpSocket;
 use tokio_rustls::TlsAcceptor;
 
 use crate::{
-    acme::{directory::ACME_TLS_ALPN_NAME, AcmeManager},
+    acme::{directory::ACME_TLS_ALPN_NAME, AcmeManager, ChallengeSettings},
     listener::{
         blocked::BlockedIps,
-        tls::{Certificate, CertificateResolver},
+        tls::{Certificate, CertificateResolver, ServerCertificates},
         TcpAcceptor,
     },
     UnwrapFailure,
@@ -219,20 +220,25 @@ impl Config {
 
             // Build resolver
             let mut acme_acceptor = None;
-            let resolver: Arc<dyn ResolvesServerCert> = if let Some(acme_id) =
+            let (resolver, certificates): (
+                Arc<dyn ResolvesServerCert>,
+                Arc<dyn ServerCertificates>,
+            ) = if let Some(acme_id) =
                 self.value_or_default(("server.listener", id, "tls.acme"), "server.tls.acme")
             {
                 let acme = acmes.get(acme_id).ok_or_else(|| {
                     format!("Undefined ACME id {acme_id:?} for listener {id:?}.",)
                 })?;
 
-                // Check if this port is used to receive ACME challenges
+                // Check if this port is used to receive TLS-ALPN-01 challenges
                 let acme_port = self.property_or_static::<u16>(("acme", acme_id, "port"), "443")?;
-                if listeners.iter().any(|l| l.addr.port() == acme_port) {
+                if matches!(acme.challenge, ChallengeSettings::TlsAlpn01)
+                    && listeners.iter().any(|l| l.addr.port() == acme_port)
+                {
                     acme_acceptor = Some(acme.clone());
                 }
 
-                acme.clone()
+                (acme.clone(), acme.clone())
             } else {
                 let cert_id = self
                     .value_or_default(
@@ -275,7 +281,8 @@ impl Config {
                     }
                 }
 
-                Arc::new(resolver)
+                let resolver = Arc::new(resolver);
+                (resolver.clone(), resolver)
             };
 
             // Build cert provider
@@ -315,7 +322,10 @@ impl Config {
                     manager,
                 }
             } else {
-                TcpAcceptor::Tls(TlsAcceptor::from(Arc::new(config)))
+                TcpAcceptor::Tls {
+                    acceptor: TlsAcceptor::from(Arc::new(config)),
+                    certificates,
+                }
             };
 
             (
@@ -375,7 +385,7 @@ impl Config {
                 .unwrap_or(8192),
             protocol,
             listeners,
-            acceptor,
+            acceptor: Arc::new(ArcSwap::from_pointee(acceptor)),
             tls_implicit,
             proxy_networks,
             blocked_ips,
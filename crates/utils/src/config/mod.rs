@@ -34,11 +34,12 @@ use std::{
     collections::BTreeMap,
     fmt::Display,
     net::{IpAddr, Ipv4Addr, SocketAddr},
-    sync::Arc,
+    sync::{Arc, OnceLock},
     time::Duration,
 };
 
 use ahash::{AHashMap, AHashSet};
+use arc_swap::ArcSwap;
 use tokio::net::TcpSocket;
 
 use crate::{
@@ -71,7 +72,7 @@ pub struct Server {
     pub listeners: Vec<Listener>,
     pub proxy_networks: Vec<IpAddrMask>,
     pub blocked_ips: Arc<BlockedIps>,
-    pub acceptor: TcpAcceptor,
+    pub acceptor: Arc<ArcSwap<TcpAcceptor>>,
     pub tls_implicit: bool,
     pub max_connections: u64,
 }
@@ -96,7 +97,8 @@ pub struct Listener {
     pub nodelay: bool,
 }
 
-#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum ServerProtocol {
     #[default]
     Smtp,
@@ -160,6 +162,8 @@ impl Display for ServerProtocol {
 
 pub type Result<T> = std::result::Result<T, String>;
 
+static CONFIG_PATH: OnceLock<String> = OnceLock::new();
+
 impl Config {
     pub fn init() -> Self {
         let mut config_path = None;
@@ -184,15 +188,23 @@ impl Config {
         }
 
         // Read main configuration file
+        let config_path = CONFIG_PATH
+            .get_or_init(|| config_path.failed("Missing parameter --config=<path-to-config>."));
+        Config::read_file(config_path).failed("Invalid configuration file")
+    }
+
+    // Path of the configuration file the server was started with, used
+    // to read it again when the configuration is reloaded.
+    pub fn file_path() -> Option<&'static str> {
+        CONFIG_PATH.get().map(|path| path.as_str())
+    }
+
+    pub fn read_file(path: &str) -> Result<Self> {
         let mut config = Config::default();
-        config
-            .parse(
-                &std::fs::read_to_string(
-                    config_path.failed("Missing parameter --config=<path-to-config>."),
-                )
-                .failed("Could not read configuration file"),
-            )
-            .failed("Invalid configuration file");
+        config.parse(
+            &std::fs::read_to_string(path)
+                .map_err(|err| format!("Could not read configuration file {path:?}: {err}"))?,
+        )?;
 
         // Extract macros and includes
         let mut keys = BTreeMap::new();
@@ -212,20 +224,20 @@ impl Config {
         // Include files
         config.keys = keys;
         for mut include in includes {
-            include.replace_macros("include.files", &macros);
+            include.replace_macros("include.files", &macros)?;
             config
-                .parse(&std::fs::read_to_string(&include).failed(&format!(
-                    "Could not read included configuration file {include:?}"
-                )))
-                .failed(&format!("Invalid included configuration file {include:?}"));
+                .parse(&std::fs::read_to_string(&include).map_err(|err| {
+                    format!("Could not read included configuration file {include:?}: {err}")
+                })?)
+                .map_err(|err| format!("Invalid included configuration file {include:?}: {err}"))?;
         }
 
         // Replace macros
         for (key, value) in &mut config.keys {
-            value.replace_macros(key, &macros);
+            value.replace_macros(key, &macros)?;
         }
 
-        config
+        Ok(config)
     }
 
     pub fn update(&mut self, config: Self) {
@@ -234,11 +246,11 @@ impl Config {
 }
 
 trait ReplaceMacros: Sized {
-    fn replace_macros(&mut self, key: &str, macros: &AHashMap<String, String>);
+    fn replace_macros(&mut self, key: &str, macros: &AHashMap<String, String>) -> Result<()>;
 }
 
 impl ReplaceMacros for String {
-    fn replace_macros(&mut self, key: &str, macros: &AHashMap<String, String>) {
+    fn replace_macros(&mut self, key: &str, macros: &AHashMap<String, String>) -> Result<()> {
         if self.contains("%{") {
             let mut result = String::with_capacity(self.len());
             let mut value = self.as_str();
@@ -253,12 +265,10 @@ impl ReplaceMacros for String {
                             result.push_str(macro_value);
                             value = rest;
                         } else {
-                            failed(&format!("Unknown macro {macro_name:?} for key {key:?}"));
+                            return Err(format!("Unknown macro {macro_name:?} for key {key:?}"));
                         }
                     } else {
-                        failed(&format!(
-                            "Unterminated macro name {value:?} for key {key:?}"
-                        ));
+                        return Err(format!("Unterminated macro name {value:?} for key {key:?}"));
                     }
                 } else {
                     result.push_str(value);
@@ -268,5 +278,7 @@ impl ReplaceMacros for String {
 
             *self = result;
         }
+
+        Ok(())
     }
 }
@@ -21,10 +21,18 @@
  * for more details.
 */
 
-use std::{io::Cursor, path::PathBuf, sync::Arc, time::Duration};
+use std::{
+    io::Cursor,
+    net::{IpAddr, SocketAddr},
+    path::PathBuf,
+    sync::Arc,
+    time::Duration,
+};
 
 use ahash::AHashMap;
 use arc_swap::ArcSwap;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use hickory_proto::rr::dnssec::rdata::tsig::TsigAlgorithm;
 use rcgen::generate_simple_self_signed;
 use rustls::{
     crypto::ring::sign::any_supported_type,
@@ -36,7 +44,11 @@ use rustls_pemfile::{certs, read_one, Item};
 use rustls_pki_types::PrivateKeyDer;
 
 use crate::{
-    acme::{directory::LETS_ENCRYPT_PRODUCTION_DIRECTORY, AcmeManager},
+    acme::{
+        directory::LETS_ENCRYPT_PRODUCTION_DIRECTORY,
+        dns::{DnsProtocol, DnsUpdater},
+        AcmeManager, ChallengeSettings,
+    },
     listener::tls::Certificate,
 };
 
@@ -104,9 +116,23 @@ impl Config {
                 return Err(format!("Missing contact for acme.{acme_id}."));
             }
 
-            // Find which domains are covered by this ACME manager
-            let mut domains = Vec::new();
-            for id in self.sub_keys("server.listener", ".protocol") {
+            // Use the configured domains, or find which domains are covered by this ACME manager
+            let mut domains = self
+                .values(("acme", acme_id, "domains"))
+                .filter_map(|(_, v)| {
+                    let v = v.trim().to_lowercase();
+                    if !v.is_empty() {
+                        Some(v)
+                    } else {
+                        None
+                    }
+                })
+                .collect::<Vec<_>>();
+            let use_listeners = domains.is_empty();
+            for id in self
+                .sub_keys("server.listener", ".protocol")
+                .filter(|_| use_listeners)
+            {
                 match (
                     self.value_or_default(("server.listener", id, "tls.acme"), "server.tls.acme"),
                     self.value_or_default(("server.listener", id, "hostname"), "server.hostname"),
@@ -122,6 +148,41 @@ impl Config {
                 }
             }
 
+            // Parse challenge type
+            let challenge = match self
+                .value(("acme", acme_id, "challenge"))
+                .unwrap_or("tls-alpn-01")
+                .trim()
+                .to_ascii_lowercase()
+                .as_str()
+            {
+                "tls-alpn-01" => ChallengeSettings::TlsAlpn01,
+                "http-01" => ChallengeSettings::Http01,
+                "dns-01" => ChallengeSettings::Dns01(self.parse_dns_updater(acme_id)?),
+                challenge => {
+                    return Err(format!(
+                        "Invalid challenge type {challenge:?} for acme.{acme_id}."
+                    ))
+                }
+            };
+            for domain in &domains {
+                match &challenge {
+                    ChallengeSettings::Dns01(updater) if !updater.is_in_zone(domain) => {
+                        return Err(format!(
+                            "Domain {domain:?} is not part of DNS zone {} for acme.{acme_id}.",
+                            updater.zone
+                        ));
+                    }
+                    ChallengeSettings::Dns01(_) => (),
+                    _ if domain.starts_with("*.") => {
+                        return Err(format!(
+                            "Wildcard domain {domain:?} requires the dns-01 challenge for acme.{acme_id}."
+                        ));
+                    }
+                    _ => (),
+                }
+            }
+
             acmes.insert(
                 acme_id.to_string(),
                 Arc::new(AcmeManager::new(
@@ -130,12 +191,68 @@ impl Config {
                     contact,
                     renew_before,
                     cache,
+                    challenge,
                 )?),
             );
         }
 
         Ok(acmes)
     }
+
+    fn parse_dns_updater(&self, acme_id: &str) -> super::Result<DnsUpdater> {
+        let server = SocketAddr::new(
+            self.property_require::<IpAddr>(("acme", acme_id, "dns.server"))?,
+            self.property_or_static(("acme", acme_id, "dns.port"), "53")?,
+        );
+        let zone = self.value_require(("acme", acme_id, "dns.zone"))?;
+        let mut updater = DnsUpdater::new(server, zone)
+            .map_err(|err| format!("Invalid DNS zone {zone:?} for acme.{acme_id}: {err:?}"))?;
+        updater.protocol = match self
+            .value(("acme", acme_id, "dns.protocol"))
+            .unwrap_or("udp")
+        {
+            "udp" => DnsProtocol::Udp,
+            "tcp" => DnsProtocol::Tcp,
+            protocol => {
+                return Err(format!(
+                    "Invalid DNS protocol {protocol:?} for acme.{acme_id}."
+                ))
+            }
+        };
+        updater.ttl = self.property_or_static(("acme", acme_id, "dns.ttl"), "60")?;
+        updater.timeout = self.property_or_static(("acme", acme_id, "dns.timeout"), "30s")?;
+        updater.propagation_delay = self
+            .property::<Option<Duration>>(("acme", acme_id, "dns.propagation-delay"))?
+            .flatten()
+            .unwrap_or_default();
+
+        if let Some(key_name) = self.value(("acme", acme_id, "dns.tsig.name")) {
+            let secret = STANDARD
+                .decode(
+                    self.value_require(("acme", acme_id, "dns.tsig.secret"))?
+                        .trim(),
+                )
+                .map_err(|_| format!("Invalid base64 TSIG secret for acme.{acme_id}."))?;
+            let algorithm = match self
+                .value(("acme", acme_id, "dns.tsig.algorithm"))
+                .unwrap_or("hmac-sha256")
+            {
+                "hmac-sha256" => TsigAlgorithm::HmacSha256,
+                "hmac-sha384" => TsigAlgorithm::HmacSha384,
+                "hmac-sha512" => TsigAlgorithm::HmacSha512,
+                algorithm => {
+                    return Err(format!(
+                        "Invalid TSIG algorithm {algorithm:?} for acme.{acme_id}."
+                    ))
+                }
+            };
+            updater = updater
+                .with_tsig(key_name, secret, algorithm)
+                .map_err(|err| format!("Invalid TSIG key for acme.{acme_id}: {err:?}"))?;
+        }
+
+        Ok(updater)
+    }
 }
 
 pub(crate) fn build_certified_key(
@@ -39,6 +39,7 @@ pub enum DeliveryEvent {
 #[derive(Debug)]
 pub struct IngestMessage {
     pub sender_address: String,
+    pub sender_authenticated: bool,
     pub recipients: Vec<String>,
     pub message_data: MessageData,
 }
@@ -91,6 +92,12 @@ impl IngestMessage {
 }
 
 impl MessageData {
+    pub fn from_bytes(bytes: Vec<u8>) -> Self {
+        MessageData::Bytes(Box::pin(futures::stream::once(async move {
+            Ok(Bytes::from(bytes))
+        })))
+    }
+
     pub fn read_message(&mut self) -> BoxedByteStream {
         match std::mem::replace(self, MessageData::Empty) {
             MessageData::File {
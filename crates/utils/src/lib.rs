@@ -31,6 +31,7 @@ pub mod config;
 pub mod ipc;
 pub mod listener;
 pub mod map;
+pub mod metrics;
 pub mod snowflake;
 pub mod suffixlist;
 
@@ -86,6 +87,10 @@ impl<T, E: std::fmt::Display> UnwrapFailure<T> for Result<T, E> {
     }
 }
 
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (a, b)| acc | (a ^ b)) == 0
+}
+
 pub fn failed(message: &str) -> ! {
     tracing::error!("{message}");
     eprintln!("{message}");
@@ -34,7 +34,10 @@ use ahash::{AHashMap, AHashSet};
 use arc_swap::{ArcSwap, ArcSwapOption};
 use parking_lot::{Mutex, RwLock};
 
-use crate::config::{ipmask::IpAddrMask, utils::ParseKey, Config, ConfigKey, Rate};
+use crate::{
+    config::{ipmask::IpAddrMask, utils::ParseKey, Config, ConfigKey, Rate},
+    metrics::metrics,
+};
 
 use super::limiter::RateLimiter;
 
@@ -110,6 +113,7 @@ impl BlockedIps {
                     .is_allowed(rate);
 
             if !is_allowed {
+                metrics().fail2ban_bans.add(1, &[]);
                 self.ip_addresses.write().insert(ip);
                 return Some(ConfigKey {
                     key: format!("{}.{}", BLOCKED_IP_KEY, ip),
@@ -0,0 +1,252 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::{
+    borrow::Cow,
+    io,
+    pin::Pin,
+    task::{ready, Context, Poll},
+};
+
+use flate2::{Compress, Compression, Decompress, FlushCompress, FlushDecompress, Status};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+use super::{SessionStream, TcpAcceptor};
+
+const READ_BUF_SIZE: usize = 8192;
+
+// Raw DEFLATE (RFC 1951) stream, as used by IMAP COMPRESS (RFC 4978).
+// Every flush emits a sync flush block so the peer can decode all data
+// written so far without waiting for more input.
+pub struct DeflateStream<T> {
+    inner: T,
+    compress: Compress,
+    decompress: Decompress,
+    read_buf: Box<[u8]>,
+    read_pos: usize,
+    read_len: usize,
+    write_buf: Vec<u8>,
+    write_pos: usize,
+    needs_flush: bool,
+}
+
+impl<T> DeflateStream<T> {
+    pub fn new(inner: T) -> Self {
+        DeflateStream {
+            inner,
+            compress: Compress::new(Compression::default(), false),
+            decompress: Decompress::new(false),
+            read_buf: vec![0; READ_BUF_SIZE].into_boxed_slice(),
+            read_pos: 0,
+            read_len: 0,
+            write_buf: Vec::with_capacity(READ_BUF_SIZE),
+            write_pos: 0,
+            needs_flush: false,
+        }
+    }
+
+    pub fn get_ref(&self) -> &T {
+        &self.inner
+    }
+}
+
+impl<T: AsyncWrite + Unpin> DeflateStream<T> {
+    fn poll_write_pending(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        while self.write_pos < self.write_buf.len() {
+            let bytes_written = ready!(
+                Pin::new(&mut self.inner).poll_write(cx, &self.write_buf[self.write_pos..])
+            )?;
+            if bytes_written == 0 {
+                return Poll::Ready(Err(io::ErrorKind::WriteZero.into()));
+            }
+            self.write_pos += bytes_written;
+        }
+        self.write_buf.clear();
+        self.write_pos = 0;
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<T: AsyncRead + Unpin> AsyncRead for DeflateStream<T> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        if buf.remaining() == 0 {
+            return Poll::Ready(Ok(()));
+        }
+
+        loop {
+            if this.read_pos < this.read_len {
+                let total_in = this.decompress.total_in();
+                let total_out = this.decompress.total_out();
+                let status = this
+                    .decompress
+                    .decompress(
+                        &this.read_buf[this.read_pos..this.read_len],
+                        buf.initialize_unfilled(),
+                        FlushDecompress::None,
+                    )
+                    .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+                let bytes_in = (this.decompress.total_in() - total_in) as usize;
+                let bytes_out = (this.decompress.total_out() - total_out) as usize;
+                this.read_pos += bytes_in;
+                buf.advance(bytes_out);
+
+                if bytes_out > 0 || status == Status::StreamEnd {
+                    return Poll::Ready(Ok(()));
+                } else if bytes_in > 0 {
+                    continue;
+                }
+            }
+
+            // Read more compressed data
+            if this.read_pos > 0 {
+                this.read_buf.copy_within(this.read_pos..this.read_len, 0);
+                this.read_len -= this.read_pos;
+                this.read_pos = 0;
+            }
+            let mut read_buf = ReadBuf::new(&mut this.read_buf[this.read_len..]);
+            ready!(Pin::new(&mut this.inner).poll_read(cx, &mut read_buf))?;
+            let bytes_read = read_buf.filled().len();
+            if bytes_read > 0 {
+                this.read_len += bytes_read;
+            } else {
+                return Poll::Ready(Ok(()));
+            }
+        }
+    }
+}
+
+impl<T: AsyncWrite + Unpin> AsyncWrite for DeflateStream<T> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        ready!(this.poll_write_pending(cx))?;
+
+        let total_in = this.compress.total_in();
+        loop {
+            let bytes_in = (this.compress.total_in() - total_in) as usize;
+            if bytes_in == buf.len() {
+                break;
+            }
+            this.write_buf.reserve((buf.len() - bytes_in).max(64));
+            this.compress
+                .compress_vec(&buf[bytes_in..], &mut this.write_buf, FlushCompress::None)
+                .map_err(io::Error::other)?;
+        }
+        this.needs_flush = true;
+
+        Poll::Ready(Ok(buf.len()))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        if this.needs_flush {
+            ready!(this.poll_write_pending(cx))?;
+            loop {
+                this.write_buf.reserve(64);
+                this.compress
+                    .compress_vec(&[], &mut this.write_buf, FlushCompress::Sync)
+                    .map_err(io::Error::other)?;
+                if this.write_buf.len() < this.write_buf.capacity() {
+                    break;
+                }
+            }
+            this.needs_flush = false;
+        }
+        ready!(this.poll_write_pending(cx))?;
+        Pin::new(&mut this.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        ready!(self.as_mut().poll_flush(cx))?;
+        Pin::new(&mut self.get_mut().inner).poll_shutdown(cx)
+    }
+}
+
+impl<T: SessionStream> SessionStream for DeflateStream<T> {
+    fn is_tls(&self) -> bool {
+        self.inner.is_tls()
+    }
+
+    fn tls_version_and_cipher(&self) -> (Cow<'static, str>, Cow<'static, str>) {
+        self.inner.tls_version_and_cipher()
+    }
+
+    fn tls_server_end_point(&self, acceptor: &TcpAcceptor) -> Option<Vec<u8>> {
+        self.inner.tls_server_end_point(acceptor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    use super::DeflateStream;
+
+    #[tokio::test]
+    async fn deflate_stream() {
+        let (client, server) = tokio::io::duplex(1024);
+        let mut client = DeflateStream::new(client);
+        let mut server = DeflateStream::new(server);
+        let mut buf = vec![0u8; 128 * 1024];
+
+        // Data written before a flush is readable by the peer
+        for message in [
+            b"a001 OK COMPRESS completed\r\n".to_vec(),
+            b"* 1 FETCH (FLAGS (\\Seen))\r\n".repeat(1000),
+            (0..=255u8).cycle().take(100_000).collect::<Vec<_>>(),
+        ] {
+            let (_, received) = tokio::join!(
+                async {
+                    server.write_all(&message).await.unwrap();
+                    server.flush().await.unwrap();
+                },
+                async {
+                    let mut received = Vec::new();
+                    while received.len() < message.len() {
+                        let bytes_read = client.read(&mut buf).await.unwrap();
+                        assert_ne!(bytes_read, 0);
+                        received.extend_from_slice(&buf[..bytes_read]);
+                    }
+                    received
+                }
+            );
+            assert_eq!(received, message);
+        }
+
+        // Closing the stream signals EOF
+        client.write_all(b"a002 LOGOUT\r\n").await.unwrap();
+        client.shutdown().await.unwrap();
+        let bytes_read = server.read(&mut buf).await.unwrap();
+        assert_eq!(&buf[..bytes_read], b"a002 LOGOUT\r\n");
+        drop(client);
+        assert_eq!(server.read(&mut buf).await.unwrap(), 0);
+    }
+}
@@ -41,6 +41,7 @@ use crate::{
     config::{Config, Listener, Server, ServerProtocol, Servers},
     failed,
     listener::SessionData,
+    metrics::register_listener,
     UnwrapFailure,
 };
 
@@ -68,6 +69,11 @@ impl Server {
             shutdown_rx,
         });
         let is_tls = self.tls_implicit;
+        register_listener(
+            &instance.id,
+            instance.protocol,
+            instance.limiter.concurrent.clone(),
+        );
         let has_proxies = !instance.proxy_networks.is_empty();
 
         // Spawn listeners
@@ -334,7 +340,7 @@ impl ServerInstance {
         stream: T,
         span: &Span,
     ) -> Result<TlsStream<T>, ()> {
-        match self.acceptor.accept(stream).await {
+        match self.acceptor.load_full().accept(stream).await {
             TcpAcceptorResult::Tls(accept) => match accept.await {
                 Ok(stream) => {
                     tracing::info!(
@@ -27,6 +27,7 @@ use crate::{
     acme::AcmeManager,
     config::{ipmask::IpAddrMask, ServerProtocol},
 };
+use arc_swap::ArcSwap;
 use rustls::ServerConfig;
 use std::fmt::Debug;
 use tokio::{
@@ -38,9 +39,11 @@ use tokio_rustls::{Accept, TlsAcceptor};
 use self::{
     blocked::BlockedIps,
     limiter::{ConcurrencyLimiter, InFlight},
+    tls::ServerCertificates,
 };
 
 pub mod blocked;
+pub mod deflate;
 pub mod limiter;
 pub mod listen;
 pub mod stream;
@@ -52,7 +55,7 @@ pub struct ServerInstance {
     pub protocol: ServerProtocol,
     pub hostname: String,
     pub data: String,
-    pub acceptor: TcpAcceptor,
+    pub acceptor: Arc<ArcSwap<TcpAcceptor>>,
     pub limiter: ConcurrencyLimiter,
     pub proxy_networks: Vec<IpAddrMask>,
     pub blocked_ips: Arc<BlockedIps>,
@@ -61,7 +64,10 @@ pub struct ServerInstance {
 
 #[derive(Default)]
 pub enum TcpAcceptor {
-    Tls(TlsAcceptor),
+    Tls {
+        acceptor: TlsAcceptor,
+        certificates: Arc<dyn ServerCertificates>,
+    },
     Acme {
         challenge: Arc<ServerConfig>,
         default: Arc<ServerConfig>,
@@ -94,6 +100,7 @@ pub struct SessionData<T: SessionStream> {
 pub trait SessionStream: AsyncRead + AsyncWrite + Unpin + 'static + Sync + Send {
     fn is_tls(&self) -> bool;
     fn tls_version_and_cipher(&self) -> (Cow<'static, str>, Cow<'static, str>);
+    fn tls_server_end_point(&self, acceptor: &TcpAcceptor) -> Option<Vec<u8>>;
 }
 
 pub trait SessionManager: Sync + Send + 'static + Clone {
@@ -102,7 +109,8 @@ pub trait SessionManager: Sync + Send + 'static + Clone {
 
         tokio::spawn(async move {
             if is_tls {
-                match session.instance.acceptor.accept(session.stream).await {
+                let acceptor = session.instance.acceptor.load_full();
+                match acceptor.accept(session.stream).await {
                     TcpAcceptorResult::Tls(accept) => match accept.await {
                         Ok(stream) => {
                             let session = SessionData {
@@ -151,7 +159,7 @@ pub trait SessionManager: Sync + Send + 'static + Clone {
 impl Debug for TcpAcceptor {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            Self::Tls(_) => f.debug_tuple("Tls").finish(),
+            Self::Tls { .. } => f.debug_tuple("Tls").finish(),
             Self::Acme {
                 challenge,
                 default,
@@ -30,7 +30,7 @@ use tokio::{
 };
 use tokio_rustls::server::TlsStream;
 
-use super::SessionStream;
+use super::{SessionStream, TcpAcceptor};
 
 impl SessionStream for TcpStream {
     fn is_tls(&self) -> bool {
@@ -40,6 +40,10 @@ impl SessionStream for TcpStream {
     fn tls_version_and_cipher(&self) -> (Cow<'static, str>, Cow<'static, str>) {
         (Cow::Borrowed(""), Cow::Borrowed(""))
     }
+
+    fn tls_server_end_point(&self, _: &TcpAcceptor) -> Option<Vec<u8>> {
+        None
+    }
 }
 
 impl<T: SessionStream> SessionStream for TlsStream<T> {
@@ -79,6 +83,10 @@ impl<T: SessionStream> SessionStream for TlsStream<T> {
             .into(),
         )
     }
+
+    fn tls_server_end_point(&self, acceptor: &TcpAcceptor) -> Option<Vec<u8>> {
+        acceptor.tls_server_end_point(self.get_ref().1.server_name())
+    }
 }
 
 impl SessionStream for ProxiedStream<TcpStream> {
@@ -99,6 +107,10 @@ impl SessionStream for ProxiedStream<TcpStream> {
             })
             .unwrap_or((Cow::Borrowed("unknown"), Cow::Borrowed("unknown")))
     }
+
+    fn tls_server_end_point(&self, _: &TcpAcceptor) -> Option<Vec<u8>> {
+        None
+    }
 }
 
 #[derive(Default)]
@@ -157,4 +169,8 @@ impl SessionStream for NullIo {
             std::borrow::Cow::Borrowed(""),
         )
     }
+
+    fn tls_server_end_point(&self, _: &TcpAcceptor) -> Option<Vec<u8>> {
+        None
+    }
 }
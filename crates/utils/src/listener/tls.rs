@@ -29,6 +29,7 @@ use std::{
 
 use ahash::AHashMap;
 use arc_swap::ArcSwap;
+use ring::digest;
 use rustls::{
     client::verify_server_name,
     server::{ClientHello, ParsedCertificate, ResolvesServerCert},
@@ -39,6 +40,13 @@ use rustls::{
 use rustls_pki_types::{DnsName, ServerName};
 use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt};
 use tokio_rustls::{Accept, LazyConfigAcceptor, TlsAcceptor};
+use x509_parser::{
+    oid_registry::{
+        OID_PKCS1_SHA384WITHRSA, OID_PKCS1_SHA512WITHRSA, OID_SIG_ECDSA_WITH_SHA384,
+        OID_SIG_ECDSA_WITH_SHA512,
+    },
+    parse_x509_certificate,
+};
 
 use crate::{acme::resolver::IsTlsAlpnChallenge, config::tls::build_certified_key};
 
@@ -79,6 +87,20 @@ impl CertificateResolver {
     }
 }
 
+pub trait ServerCertificates: Sync + Send {
+    fn certificate(&self, server_name: Option<&str>) -> Option<Arc<CertifiedKey>>;
+}
+
+impl ServerCertificates for CertificateResolver {
+    fn certificate(&self, server_name: Option<&str>) -> Option<Arc<CertifiedKey>> {
+        if let Some(cert) = server_name.and_then(|name| self.sni.get(name)) {
+            cert.cert.load().clone().into()
+        } else {
+            self.cert.cert.load().clone().into()
+        }
+    }
+}
+
 impl ResolvesServerCert for CertificateResolver {
     fn resolve(&self, hello: ClientHello<'_>) -> Option<Arc<CertifiedKey>> {
         if !self.sni.is_empty() {
@@ -96,7 +118,7 @@ impl TcpAcceptor {
         IO: SessionStream,
     {
         match self {
-            TcpAcceptor::Tls(acceptor) => TcpAcceptorResult::Tls(acceptor.accept(stream)),
+            TcpAcceptor::Tls { acceptor, .. } => TcpAcceptorResult::Tls(acceptor.accept(stream)),
             TcpAcceptor::Acme {
                 challenge,
                 default,
@@ -150,7 +172,34 @@ impl TcpAcceptor {
     }
 
     pub fn is_tls(&self) -> bool {
-        matches!(self, TcpAcceptor::Tls(_) | TcpAcceptor::Acme { .. })
+        matches!(self, TcpAcceptor::Tls { .. } | TcpAcceptor::Acme { .. })
+    }
+
+    // Returns the "tls-server-end-point" channel binding (RFC 5929) of the
+    // certificate presented for the given server name.
+    pub fn tls_server_end_point(&self, server_name: Option<&str>) -> Option<Vec<u8>> {
+        let certificate = match self {
+            TcpAcceptor::Tls { certificates, .. } => certificates.certificate(server_name),
+            TcpAcceptor::Acme { manager, .. } => manager.certificate(server_name),
+            TcpAcceptor::Plain => None,
+        }?;
+        let der = certificate.end_entity_cert().ok()?;
+        let algorithm = match parse_x509_certificate(der.as_ref())
+            .ok()?
+            .1
+            .signature_algorithm
+            .algorithm
+        {
+            oid if oid == OID_PKCS1_SHA384WITHRSA || oid == OID_SIG_ECDSA_WITH_SHA384 => {
+                &digest::SHA384
+            }
+            oid if oid == OID_PKCS1_SHA512WITHRSA || oid == OID_SIG_ECDSA_WITH_SHA512 => {
+                &digest::SHA512
+            }
+            _ => &digest::SHA256,
+        };
+
+        Some(digest::digest(algorithm, der.as_ref()).as_ref().to_vec())
     }
 }
 
@@ -0,0 +1,351 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, OnceLock,
+    },
+    time::{Duration, SystemTime},
+};
+
+use opentelemetry::{
+    global,
+    metrics::{Counter, Histogram, MeterProvider as _, ObservableGauge},
+};
+use opentelemetry_otlp::WithExportConfig;
+use opentelemetry_sdk::{
+    metrics::{
+        reader::{DefaultAggregationSelector, DefaultTemporalitySelector},
+        MeterProvider, PeriodicReader,
+    },
+    runtime, Resource,
+};
+use opentelemetry_semantic_conventions::resource::{SERVICE_NAME, SERVICE_VERSION};
+use parking_lot::Mutex;
+use prometheus::{Encoder, Registry, TextEncoder};
+
+use crate::config::{Config, ServerProtocol};
+
+pub use opentelemetry::KeyValue;
+
+pub struct Metrics {
+    pub delivery_attempts: Counter<u64>,
+    pub auth_failures: Counter<u64>,
+    pub fail2ban_bans: Counter<u64>,
+    pub jmap_method_duration: Histogram<f64>,
+    pub store_read_duration: Histogram<f64>,
+    pub store_write_duration: Histogram<f64>,
+    pub blob_size: Histogram<u64>,
+    _queue_messages: ObservableGauge<u64>,
+    _queue_oldest_age: ObservableGauge<u64>,
+    _active_sessions: ObservableGauge<u64>,
+}
+
+struct ListenerSessions {
+    id: String,
+    protocol: ServerProtocol,
+    concurrent: Arc<AtomicU64>,
+}
+
+pub struct Prometheus {
+    registry: Registry,
+    auth: Option<String>,
+}
+
+static METRICS: OnceLock<Metrics> = OnceLock::new();
+static PROMETHEUS: OnceLock<Prometheus> = OnceLock::new();
+static LISTENERS: Mutex<Vec<ListenerSessions>> = Mutex::new(Vec::new());
+static QUEUE_MESSAGES: AtomicU64 = AtomicU64::new(0);
+static QUEUE_OLDEST: AtomicU64 = AtomicU64::new(0);
+
+pub fn enable_metrics(config: &Config) -> crate::config::Result<()> {
+    let mut provider = MeterProvider::builder().with_resource(Resource::new(vec![
+        KeyValue::new(SERVICE_NAME, "stalwart-mail".to_string()),
+        KeyValue::new(SERVICE_VERSION, env!("CARGO_PKG_VERSION").to_string()),
+    ]));
+    let mut has_readers = false;
+
+    // Prometheus exporter
+    if config.property_or_static::<bool>("global.metrics.prometheus.enable", "false")? {
+        let registry = Registry::new();
+        let exporter = opentelemetry_prometheus::exporter()
+            .with_registry(registry.clone())
+            .build()
+            .map_err(|err| format!("Failed to build Prometheus exporter: {err}"))?;
+        let auth = match (
+            config.value("global.metrics.prometheus.auth.username"),
+            config.value("global.metrics.prometheus.auth.secret"),
+        ) {
+            (Some(username), Some(secret)) => Some(format!(
+                "Basic {}",
+                base64::Engine::encode(
+                    &base64::engine::general_purpose::STANDARD,
+                    format!("{username}:{secret}")
+                )
+            )),
+            (None, None) => None,
+            _ => {
+                return Err(
+                    "Both \"global.metrics.prometheus.auth.username\" and \"global.metrics.prometheus.auth.secret\" must be specified."
+                        .to_string(),
+                )
+            }
+        };
+        let _ = PROMETHEUS.set(Prometheus { registry, auth });
+        provider = provider.with_reader(exporter);
+        has_readers = true;
+    }
+
+    // OpenTelemetry exporter
+    if let Some(transport) = config.value("global.metrics.open-telemetry.transport") {
+        let exporter = match transport {
+            "grpc" => {
+                let mut exporter = opentelemetry_otlp::new_exporter().tonic();
+                if let Some(endpoint) = config.value("global.metrics.open-telemetry.endpoint") {
+                    exporter = exporter.with_endpoint(endpoint);
+                }
+                opentelemetry_otlp::MetricsExporterBuilder::from(exporter)
+            }
+            "http" => {
+                let mut headers = HashMap::new();
+                for (_, value) in config.values("global.metrics.open-telemetry.headers") {
+                    if let Some((key, value)) = value.split_once(':') {
+                        headers.insert(key.trim().to_string(), value.trim().to_string());
+                    } else {
+                        return Err(format!("Invalid open-telemetry header {value:?}"));
+                    }
+                }
+                let mut exporter = opentelemetry_otlp::new_exporter()
+                    .http()
+                    .with_endpoint(config.value_require("global.metrics.open-telemetry.endpoint")?);
+                if !headers.is_empty() {
+                    exporter = exporter.with_headers(headers);
+                }
+                opentelemetry_otlp::MetricsExporterBuilder::from(exporter)
+            }
+            transport => {
+                return Err(format!(
+                    "Unsupported open-telemetry transport {transport:?}"
+                ));
+            }
+        }
+        .build_metrics_exporter(
+            Box::new(DefaultTemporalitySelector::new()),
+            Box::new(DefaultAggregationSelector::new()),
+        )
+        .map_err(|err| format!("Failed to build open-telemetry metrics exporter: {err}"))?;
+
+        provider = provider.with_reader(
+            PeriodicReader::builder(exporter, runtime::Tokio)
+                .with_interval(config.property_or_static::<Duration>(
+                    "global.metrics.open-telemetry.interval",
+                    "1m",
+                )?)
+                .build(),
+        );
+        has_readers = true;
+    }
+
+    if has_readers {
+        global::set_meter_provider(provider.build());
+    }
+
+    Ok(())
+}
+
+pub fn metrics() -> &'static Metrics {
+    METRICS.get_or_init(|| {
+        let meter = global::meter_provider().meter("stalwart-mail");
+
+        Metrics {
+            delivery_attempts: meter
+                .u64_counter("smtp.delivery.attempts")
+                .with_description("Outbound delivery attempts by outcome")
+                .init(),
+            auth_failures: meter
+                .u64_counter("server.auth.failures")
+                .with_description("Failed authentication attempts")
+                .init(),
+            fail2ban_bans: meter
+                .u64_counter("server.fail2ban.bans")
+                .with_description("IP addresses banned after too many failed login attempts")
+                .init(),
+            jmap_method_duration: meter
+                .f64_histogram("jmap.method.duration")
+                .with_description("JMAP method call latency")
+                .with_unit(opentelemetry::metrics::Unit::new("s"))
+                .init(),
+            store_read_duration: meter
+                .f64_histogram("store.read.duration")
+                .with_description("Data store read latency")
+                .with_unit(opentelemetry::metrics::Unit::new("s"))
+                .init(),
+            store_write_duration: meter
+                .f64_histogram("store.write.duration")
+                .with_description("Data store write latency")
+                .with_unit(opentelemetry::metrics::Unit::new("s"))
+                .init(),
+            blob_size: meter
+                .u64_histogram("store.blob.size")
+                .with_description("Size of blobs written to the blob store")
+                .with_unit(opentelemetry::metrics::Unit::new("By"))
+                .init(),
+            _queue_messages: meter
+                .u64_observable_gauge("smtp.queue.messages")
+                .with_description("Messages in the outbound queue")
+                .with_callback(|observer| {
+                    observer.observe(QUEUE_MESSAGES.load(Ordering::Relaxed), &[])
+                })
+                .init(),
+            _queue_oldest_age: meter
+                .u64_observable_gauge("smtp.queue.oldest_message_age")
+                .with_description("Age of the oldest message in the outbound queue")
+                .with_unit(opentelemetry::metrics::Unit::new("s"))
+                .with_callback(|observer| {
+                    let oldest = QUEUE_OLDEST.load(Ordering::Relaxed);
+                    observer.observe(
+                        if oldest > 0 {
+                            now().saturating_sub(oldest)
+                        } else {
+                            0
+                        },
+                        &[],
+                    )
+                })
+                .init(),
+            _active_sessions: meter
+                .u64_observable_gauge("server.sessions.active")
+                .with_description("Active sessions per listener")
+                .with_callback(|observer| {
+                    for listener in LISTENERS.lock().iter() {
+                        observer.observe(
+                            listener.concurrent.load(Ordering::Relaxed),
+                            &[
+                                KeyValue::new("listener", listener.id.clone()),
+                                KeyValue::new("protocol", listener.protocol.to_string()),
+                            ],
+                        );
+                    }
+                })
+                .init(),
+        }
+    })
+}
+
+pub fn prometheus_metrics(authorization: Option<&str>) -> PrometheusResult {
+    if let Some(prometheus) = PROMETHEUS.get() {
+        if prometheus.auth.as_ref().is_none_or(|auth| {
+            authorization.is_some_and(|authorization| {
+                crate::constant_time_eq(auth.as_bytes(), authorization.as_bytes())
+            })
+        }) {
+            let mut buf = Vec::new();
+            match TextEncoder::new().encode(&prometheus.registry.gather(), &mut buf) {
+                Ok(_) => PrometheusResult::Metrics(buf),
+                Err(err) => {
+                    tracing::error!(
+                        context = "metrics",
+                        event = "error",
+                        "Failed to encode Prometheus metrics: {}",
+                        err
+                    );
+                    PrometheusResult::Disabled
+                }
+            }
+        } else {
+            PrometheusResult::Unauthorized
+        }
+    } else {
+        PrometheusResult::Disabled
+    }
+}
+
+pub enum PrometheusResult {
+    Metrics(Vec<u8>),
+    Unauthorized,
+    Disabled,
+}
+
+pub fn register_listener(id: &str, protocol: ServerProtocol, concurrent: Arc<AtomicU64>) {
+    LISTENERS.lock().push(ListenerSessions {
+        id: id.to_string(),
+        protocol,
+        concurrent,
+    });
+}
+
+pub fn set_queue_stats(messages: u64, oldest_created: u64) {
+    QUEUE_MESSAGES.store(messages, Ordering::Relaxed);
+    QUEUE_OLDEST.store(oldest_created, Ordering::Relaxed);
+}
+
+fn now() -> u64 {
+    SystemTime::UNIX_EPOCH
+        .elapsed()
+        .unwrap_or_default()
+        .as_secs()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::Config;
+
+    use super::*;
+
+    #[test]
+    fn prometheus_export() {
+        let config = Config::new(
+            r#"[global.metrics.prometheus]
+enable = true
+auth.username = "admin"
+auth.secret = "secret"
+"#,
+        )
+        .unwrap();
+        enable_metrics(&config).unwrap();
+
+        metrics().auth_failures.add(1, &[]);
+        metrics()
+            .delivery_attempts
+            .add(1, &[KeyValue::new("outcome", "completed")]);
+
+        assert!(matches!(
+            prometheus_metrics(None),
+            PrometheusResult::Unauthorized
+        ));
+        assert!(matches!(
+            prometheus_metrics(Some("Basic YWRtaW46d3Jvbmc=")),
+            PrometheusResult::Unauthorized
+        ));
+        match prometheus_metrics(Some("Basic YWRtaW46c2VjcmV0")) {
+            PrometheusResult::Metrics(bytes) => {
+                let text = String::from_utf8(bytes).unwrap();
+                assert!(text.contains("auth_failures"), "{text}");
+                assert!(text.contains("outcome=\"completed\""), "{text}");
+            }
+            _ => panic!("Expected metrics"),
+        }
+    }
+}
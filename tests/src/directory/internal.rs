@@ -21,22 +21,31 @@
  * for more details.
 */
 
+use std::sync::Arc;
+
 use directory::{
     backend::internal::{
         lookup::DirectoryStore, manage::ManageDirectory, PrincipalField, PrincipalUpdate,
         PrincipalValue,
     },
-    DirectoryError, ManagementError, Principal, QueryBy, Type,
+    core::{
+        app_password::AppPasswordStore,
+        scram::{ScramAlgorithm, ScramSession},
+        totp::{totp_code, TotpStore},
+    },
+    AddressMapping, AuthResult, Directory, DirectoryError, DirectoryInner, ManagementError,
+    Principal, QueryBy, Type,
 };
 use jmap_proto::types::collection::Collection;
 use mail_send::Credentials;
 use store::{
     roaring::RoaringBitmap,
-    write::{BatchBuilder, BitmapClass, ValueClass},
+    write::{assert::HashedValue, now, BatchBuilder, BitmapClass, DirectoryClass, ValueClass},
     BitmapKey, ValueKey,
 };
+use utils::config::ServerProtocol;
 
-use crate::directory::DirectoryTest;
+use crate::directory::{DirectoryTest, ScramClient};
 
 #[tokio::test]
 async fn internal_directory() {
@@ -631,5 +640,368 @@ async fn internal_directory() {
                 .unwrap(),
             Some("hello".to_string())
         );
+
+        // Successful plain text logins upgrade the stored secrets with SCRAM keys
+        let secrets = store
+            .query(QueryBy::Name("jane"), false)
+            .await
+            .unwrap()
+            .unwrap()
+            .secrets;
+        assert_eq!(secrets.len(), 4, "{secrets:?}");
+        assert_eq!(&secrets[..2], &["my_secret", "my_secret2"]);
+        assert!(secrets[2].starts_with("{SCRAM-SHA-256}4096:"));
+        assert!(secrets[3].starts_with("{SCRAM-SHA-1}4096:"));
+        assert!(store
+            .query(
+                QueryBy::Credentials(&Credentials::new(
+                    "jane".to_string(),
+                    "my_secret".to_string()
+                )),
+                false
+            )
+            .await
+            .unwrap()
+            .is_some());
+
+        // SCRAM authentication
+        let directory = Directory {
+            store: DirectoryInner::Internal(store.clone()),
+            catch_all: AddressMapping::Disable,
+            subaddressing: AddressMapping::Disable,
+            cache: None,
+            oidc: None,
+            blocked_ips: Arc::new(Default::default()),
+            scram_dummy_key: b"secret".to_vec(),
+        };
+        assert_eq!(
+            scram_login(&directory, "jane", "my_secret", None)
+                .await
+                .map(|p| p.name),
+            Some("jane".to_string())
+        );
+        assert_eq!(
+            scram_login(&directory, "jane", "my_secret", Some(b"server-cert-hash"))
+                .await
+                .map(|p| p.name),
+            Some("jane".to_string())
+        );
+        assert_eq!(
+            scram_login(&directory, "jane", "wrong_password", None).await,
+            None
+        );
+        assert_eq!(
+            scram_login(&directory, "unknown", "my_secret", None).await,
+            None
+        );
+
+        // Tampered client-final messages are rejected
+        for tamper in [
+            (|m: &str| m.replacen(",r=", ",r=x", 1)) as fn(&str) -> String,
+            |m: &str| m.replacen("c=biws", "c=eSws", 1),
+            |m: &str| {
+                let (message, _) = m.rsplit_once(",p=").unwrap();
+                format!("{message},p=AAAA")
+            },
+        ] {
+            assert!(!scram_tampered_login(&directory, "jane", "my_secret", tamper).await);
+        }
+
+        // Unknown accounts are always challenged with the same salt
+        let mut salts = Vec::new();
+        for username in ["unknown", "UNKNOWN", "unknown", "other"] {
+            let mut session = ScramSession::new(ScramAlgorithm::Sha256, false, None);
+            let server_first = directory
+                .scram_challenge(
+                    &mut session,
+                    format!("n,,n={username},r=abcdef").as_bytes(),
+                    false,
+                )
+                .await
+                .unwrap()
+                .unwrap();
+            let server_first = String::from_utf8(server_first).unwrap();
+            salts.push(server_first.split_once(",s=").unwrap().1.to_string());
+        }
+        assert_eq!(salts[0], salts[1]);
+        assert_eq!(salts[0], salts[2]);
+        assert_ne!(salts[0], salts[3]);
+
+        // Clients using a -PLUS mechanism must request channel binding
+        let mut session = ScramSession::new(
+            ScramAlgorithm::Sha256,
+            true,
+            Some(b"server-cert-hash".to_vec()),
+        );
+        assert!(directory
+            .scram_challenge(&mut session, b"n,,n=jane,r=abcdef", false)
+            .await
+            .unwrap()
+            .is_none());
+
+        // Upgrading to SCRAM secrets does not undo a concurrent password change
+        let jane_id = store.get_account_id("jane").await.unwrap().unwrap();
+        let stale_principal = store
+            .get_value::<HashedValue<Principal<u32>>>(ValueKey::from(ValueClass::Directory(
+                DirectoryClass::Principal(jane_id),
+            )))
+            .await
+            .unwrap()
+            .unwrap();
+        for secret in ["new_secret", "my_secret"] {
+            store
+                .update_account(
+                    QueryBy::Id(jane_id),
+                    vec![PrincipalUpdate::set(
+                        PrincipalField::Secrets,
+                        PrincipalValue::StringList(vec![secret.to_string()]),
+                    )],
+                )
+                .await
+                .unwrap();
+            store
+                .add_scram_secrets(jane_id, &stale_principal, "stale_secret")
+                .await;
+            assert_eq!(
+                plain_login(&directory, "jane", secret, ServerProtocol::Imap).await,
+                Some("jane".to_string())
+            );
+        }
+        assert_eq!(
+            scram_login(&directory, "jane", "stale_secret", None).await,
+            None
+        );
+
+        // Application passwords
+        let app_secret = store
+            .create_app_password(jane_id, "phone", None, vec![ServerProtocol::Imap])
+            .await
+            .unwrap();
+        assert_eq!(
+            store
+                .create_app_password(jane_id, "phone", None, vec![])
+                .await,
+            Err(DirectoryError::Management(ManagementError::AlreadyExists {
+                field: PrincipalField::AppPasswords,
+                value: "phone".to_string()
+            }))
+        );
+        let expired_secret = store
+            .create_app_password(jane_id, "laptop", Some(1), vec![])
+            .await
+            .unwrap();
+        assert_eq!(
+            plain_login(&directory, "jane", &app_secret, ServerProtocol::Imap).await,
+            Some("jane".to_string())
+        );
+        assert_eq!(
+            plain_login(&directory, "jane", &app_secret, ServerProtocol::Smtp).await,
+            None
+        );
+        assert_eq!(
+            plain_login(&directory, "jane", &expired_secret, ServerProtocol::Imap).await,
+            None
+        );
+        assert_eq!(
+            plain_login(&directory, "jane", "my_secret", ServerProtocol::Smtp).await,
+            Some("jane".to_string())
+        );
+        let app_passwords = store.list_app_passwords(jane_id).await.unwrap();
+        assert_eq!(
+            app_passwords
+                .iter()
+                .map(|p| (p.name.as_str(), p.last_ip.map(|ip| ip.to_string())))
+                .collect::<Vec<_>>(),
+            vec![("laptop", None), ("phone", Some("127.0.0.1".to_string()))]
+        );
+        assert_eq!(app_passwords[1].protocols, vec![ServerProtocol::Imap]);
+        assert!(app_passwords[1].last_used.is_some());
+        assert!(store.revoke_app_password(jane_id, "phone").await.unwrap());
+        assert!(!store.revoke_app_password(jane_id, "phone").await.unwrap());
+        assert_eq!(
+            plain_login(&directory, "jane", &app_secret, ServerProtocol::Imap).await,
+            None
+        );
+
+        // A new app password with the same name does not inherit the previous one
+        let new_secret = store
+            .create_app_password(jane_id, "phone", None, vec![])
+            .await
+            .unwrap();
+        assert_ne!(new_secret, app_secret);
+        assert_eq!(
+            plain_login(&directory, "jane", &app_secret, ServerProtocol::Imap).await,
+            None
+        );
+        let app_passwords = store.list_app_passwords(jane_id).await.unwrap();
+        assert_eq!(app_passwords[1].name, "phone");
+        assert_eq!(app_passwords[1].last_used, None);
+        assert_eq!(app_passwords[1].last_ip, None);
+        assert_eq!(
+            plain_login(&directory, "jane", &new_secret, ServerProtocol::Imap).await,
+            Some("jane".to_string())
+        );
+        assert!(store.revoke_app_password(jane_id, "phone").await.unwrap());
+
+        // Two-factor authentication
+        let enrollment = store.enroll_totp(jane_id, "jane").await.unwrap().unwrap();
+        assert!(enrollment.uri.starts_with(&format!(
+            "otpauth://totp/Stalwart:jane?secret={}",
+            enrollment.secret
+        )));
+        assert_eq!(enrollment.recovery_codes.len(), 8);
+        assert!(!store.is_totp_enabled(jane_id).await.unwrap());
+        let totp_secret = store.get_totp(jane_id).await.unwrap().unwrap().secret;
+        assert!(!store.confirm_totp(jane_id, "000000x").await.unwrap());
+        let code = totp_code(&totp_secret, now());
+        assert!(store.confirm_totp(jane_id, &code).await.unwrap());
+        assert!(store.is_totp_enabled(jane_id).await.unwrap());
+        assert!(store.enroll_totp(jane_id, "jane").await.unwrap().is_none());
+        assert!(!store
+            .confirm_totp(jane_id, &totp_code(&totp_secret, now() + 30))
+            .await
+            .unwrap());
+
+        // The account password is only accepted by logins that verify the second factor
+        for protocol in [ServerProtocol::Imap, ServerProtocol::Http] {
+            assert_eq!(
+                plain_login(&directory, "jane", "my_secret", protocol).await,
+                None
+            );
+        }
+        assert!(matches!(
+            directory
+                .authenticate_first_factor(
+                    &Credentials::Plain {
+                        username: "jane".to_string(),
+                        secret: "my_secret".to_string(),
+                    },
+                    "127.0.0.1".parse().unwrap(),
+                    ServerProtocol::Http,
+                    false,
+                )
+                .await
+                .unwrap(),
+            AuthResult::Success(_)
+        ));
+        assert_eq!(
+            scram_login(&directory, "jane", "my_secret", None).await,
+            None
+        );
+        let app_secret = store
+            .create_app_password(jane_id, "tablet", None, vec![])
+            .await
+            .unwrap();
+        assert_eq!(
+            plain_login(&directory, "jane", &app_secret, ServerProtocol::Imap).await,
+            Some("jane".to_string())
+        );
+
+        // Codes can't be replayed and recovery codes are single use
+        assert!(!store.verify_totp(jane_id, &code).await.unwrap());
+        assert!(store
+            .verify_totp(jane_id, &totp_code(&totp_secret, now() + 30))
+            .await
+            .unwrap());
+        let recovery_code = &enrollment.recovery_codes[0];
+        assert!(store.verify_totp(jane_id, recovery_code).await.unwrap());
+        assert!(!store.verify_totp(jane_id, recovery_code).await.unwrap());
+        assert!(store.disable_totp(jane_id).await.unwrap());
+        assert_eq!(
+            plain_login(&directory, "jane", "my_secret", ServerProtocol::Imap).await,
+            Some("jane".to_string())
+        );
+
+        // App passwords are removed along with the account
+        store.delete_account(QueryBy::Id(jane_id)).await.unwrap();
+        assert!(store.list_app_passwords(jane_id).await.unwrap().is_empty());
     }
 }
+
+async fn plain_login(
+    directory: &Directory,
+    username: &str,
+    secret: &str,
+    protocol: ServerProtocol,
+) -> Option<String> {
+    match directory
+        .authenticate(
+            &Credentials::Plain {
+                username: username.to_string(),
+                secret: secret.to_string(),
+            },
+            "127.0.0.1".parse().unwrap(),
+            protocol,
+            false,
+        )
+        .await
+        .unwrap()
+    {
+        AuthResult::Success(principal) => Some(principal.name),
+        _ => None,
+    }
+}
+
+async fn scram_login(
+    directory: &Directory,
+    username: &str,
+    password: &str,
+    channel_binding: Option<&[u8]>,
+) -> Option<Principal<u32>> {
+    let mut session = ScramSession::new(
+        ScramAlgorithm::Sha256,
+        channel_binding.is_some(),
+        channel_binding.map(|cb| cb.to_vec()),
+    );
+    let mut client = ScramClient::new(username, channel_binding);
+    let server_first = directory
+        .scram_challenge(&mut session, client.client_first().as_bytes(), false)
+        .await
+        .unwrap()
+        .unwrap();
+    let client_final = client.client_final(password, std::str::from_utf8(&server_first).unwrap());
+
+    match directory
+        .scram_authenticate(
+            &mut session,
+            client_final.as_bytes(),
+            "127.0.0.1".parse().unwrap(),
+        )
+        .await
+        .unwrap()
+    {
+        AuthResult::Success((principal, server_final)) => {
+            assert_eq!(server_final, client.server_final().into_bytes());
+            Some(principal)
+        }
+        _ => None,
+    }
+}
+
+async fn scram_tampered_login(
+    directory: &Directory,
+    username: &str,
+    password: &str,
+    tamper: fn(&str) -> String,
+) -> bool {
+    let mut session = ScramSession::new(ScramAlgorithm::Sha256, false, None);
+    let mut client = ScramClient::new(username, None);
+    let server_first = directory
+        .scram_challenge(&mut session, client.client_first().as_bytes(), false)
+        .await
+        .unwrap()
+        .unwrap();
+    let client_final = client.client_final(password, std::str::from_utf8(&server_first).unwrap());
+
+    matches!(
+        directory
+            .scram_authenticate(
+                &mut session,
+                tamper(&client_final).as_bytes(),
+                "127.0.0.1".parse().unwrap(),
+            )
+            .await
+            .unwrap(),
+        AuthResult::Success(_)
+    )
+}
@@ -24,18 +24,22 @@
 pub mod imap;
 pub mod internal;
 pub mod ldap;
+pub mod oidc;
 pub mod smtp;
 pub mod sql;
 
 use ::smtp::core::Lookup;
+use base64::{engine::general_purpose, Engine};
 use directory::{
     backend::internal::manage::ManageDirectory, core::config::ConfigDirectory, AddressMapping,
     Directories, Principal,
 };
+use hmac::{Hmac, Mac};
 use mail_send::Credentials;
 use rustls::ServerConfig;
 use rustls_pemfile::{certs, pkcs8_private_keys};
 use rustls_pki_types::PrivateKeyDer;
+use sha2::{Digest, Sha256};
 use std::{borrow::Cow, io::BufReader, path::PathBuf, sync::Arc};
 use store::{config::ConfigStore, LookupStore, Store, Stores};
 use tokio_rustls::TlsAcceptor;
@@ -413,6 +417,90 @@ XzVV5pwOxkIDBWDIqMUfwJDChBKfpw==
 -----END PRIVATE KEY-----
 ";
 
+pub struct ScramClient {
+    gs2_header: String,
+    client_first_bare: String,
+    channel_binding: Vec<u8>,
+    server_signature: Vec<u8>,
+}
+
+impl ScramClient {
+    pub fn new(username: &str, channel_binding: Option<&[u8]>) -> Self {
+        ScramClient {
+            gs2_header: if channel_binding.is_some() {
+                "p=tls-server-end-point,,"
+            } else {
+                "n,,"
+            }
+            .to_string(),
+            client_first_bare: format!("n={username},r=rOprNGfwEbeRWgbNEkqO"),
+            channel_binding: channel_binding.unwrap_or_default().to_vec(),
+            server_signature: vec![],
+        }
+    }
+
+    pub fn client_first(&self) -> String {
+        format!("{}{}", self.gs2_header, self.client_first_bare)
+    }
+
+    pub fn client_final(&mut self, password: &str, server_first: &str) -> String {
+        let mut nonce = "";
+        let mut salt = vec![];
+        let mut iterations = 0;
+        for attribute in server_first.split(',') {
+            match attribute.split_once('=').unwrap() {
+                ("r", value) => nonce = value,
+                ("s", value) => salt = general_purpose::STANDARD.decode(value).unwrap(),
+                ("i", value) => iterations = value.parse().unwrap(),
+                _ => panic!("Unexpected attribute {attribute:?}"),
+            }
+        }
+        assert!(nonce.starts_with("rOprNGfwEbeRWgbNEkqO"));
+
+        let mut salted_password = [0u8; 32];
+        pbkdf2::pbkdf2_hmac::<Sha256>(password.as_bytes(), &salt, iterations, &mut salted_password);
+        let client_key = hmac_sha256(&salted_password, b"Client Key");
+        let stored_key = Sha256::digest(&client_key);
+        let mut cbind_input = self.gs2_header.as_bytes().to_vec();
+        cbind_input.extend_from_slice(&self.channel_binding);
+        let client_final_without_proof = format!(
+            "c={},r={nonce}",
+            general_purpose::STANDARD.encode(cbind_input)
+        );
+        let auth_message = format!(
+            "{},{server_first},{client_final_without_proof}",
+            self.client_first_bare
+        );
+        let proof = client_key
+            .iter()
+            .zip(hmac_sha256(&stored_key, auth_message.as_bytes()))
+            .map(|(a, b)| a ^ b)
+            .collect::<Vec<_>>();
+        self.server_signature = hmac_sha256(
+            &hmac_sha256(&salted_password, b"Server Key"),
+            auth_message.as_bytes(),
+        );
+
+        format!(
+            "{client_final_without_proof},p={}",
+            general_purpose::STANDARD.encode(proof)
+        )
+    }
+
+    pub fn server_final(&self) -> String {
+        format!(
+            "v={}",
+            general_purpose::STANDARD.encode(&self.server_signature)
+        )
+    }
+}
+
+fn hmac_sha256(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).unwrap();
+    mac.update(data);
+    mac.finalize().into_bytes().to_vec()
+}
+
 pub fn dummy_tls_acceptor() -> Arc<TlsAcceptor> {
     // Init server config builder with safe defaults
     let config = ServerConfig::builder().with_no_client_auth();
@@ -0,0 +1,404 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::SystemTime,
+};
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use bytes::Bytes;
+use directory::{
+    backend::internal::manage::ManageDirectory, core::config::ConfigDirectory, AuthResult,
+    Principal, QueryBy,
+};
+use http_body_util::{BodyExt, Full};
+use hyper::{body, server::conn::http1, service::service_fn};
+use hyper_util::rt::TokioIo;
+use mail_send::Credentials;
+use rsa::{
+    pkcs1v15::SigningKey,
+    pkcs8::DecodePrivateKey,
+    signature::{SignatureEncoding, Signer},
+    traits::PublicKeyParts,
+    RsaPrivateKey,
+};
+use serde_json::json;
+use sha2::Sha256;
+use store::config::ConfigStore;
+use tokio::net::TcpListener;
+use utils::config::{ServerProtocol, Servers};
+
+use crate::store::TempDir;
+
+const CONFIG: &str = r#"
+[store."sqlite"]
+type = "sqlite"
+path = "{TMP}/oidc.db"
+
+[directory."jwks"]
+type = "internal"
+store = "sqlite"
+
+[directory."jwks".oidc]
+issuer = "https://idp.example.org/"
+audience = "stalwart"
+jwks.url = "http://127.0.0.1:{PORT}/jwks"
+provision = true
+
+[directory."introspect"]
+type = "internal"
+store = "sqlite"
+
+[directory."introspect".oidc]
+issuer = "https://idp.example.org"
+audience = "stalwart"
+method = "introspect"
+introspect.url = "http://127.0.0.1:{PORT}/introspect"
+introspect.client-id = "stalwart"
+introspect.client-secret = "secret"
+"#;
+
+#[tokio::test]
+async fn oidc_directory() {
+    // Spawn mock identity provider
+    let key =
+        RsaPrivateKey::from_pkcs8_pem(include_str!("../../resources/jmap/oidc_privatekey.pem"))
+            .unwrap();
+    let jwks = json!({
+        "keys": [{
+            "kty": "RSA",
+            "use": "sig",
+            "alg": "RS256",
+            "kid": "test-key",
+            "n": URL_SAFE_NO_PAD.encode(key.n().to_bytes_be()),
+            "e": URL_SAFE_NO_PAD.encode(key.e().to_bytes_be()),
+        }]
+    })
+    .to_string();
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let jwks_fetches = Arc::new(AtomicUsize::new(0));
+    let jwks_fetches_ = jwks_fetches.clone();
+    tokio::spawn(async move {
+        loop {
+            let (stream, _) = listener.accept().await.unwrap();
+            let jwks = jwks.clone();
+            let jwks_fetches = jwks_fetches_.clone();
+            tokio::spawn(async move {
+                let _ = http1::Builder::new()
+                    .keep_alive(false)
+                    .serve_connection(
+                        TokioIo::new(stream),
+                        service_fn(|req: hyper::Request<body::Incoming>| {
+                            let jwks = jwks.clone();
+                            let jwks_fetches = jwks_fetches.clone();
+                            async move {
+                                let response = if req.uri().path() == "/jwks" {
+                                    jwks_fetches.fetch_add(1, Ordering::Relaxed);
+                                    jwks
+                                } else {
+                                    let body = req.collect().await.unwrap().to_bytes();
+                                    let body = String::from_utf8_lossy(&body);
+                                    if body.contains("token=opaque-valid") {
+                                        json!({
+                                            "active": true,
+                                            "iss": "https://idp.example.org",
+                                            "aud": "stalwart",
+                                            "sub": "c7a1e4",
+                                            "preferred_username": "jane",
+                                            "email": "jane@example.org",
+                                        })
+                                    } else {
+                                        json!({"active": false})
+                                    }
+                                    .to_string()
+                                };
+                                Ok::<_, hyper::Error>(hyper::Response::new(Full::new(Bytes::from(
+                                    response,
+                                ))))
+                            }
+                        }),
+                    )
+                    .await;
+            });
+        }
+    });
+
+    // Parse directories
+    let temp_dir = TempDir::new("oidc_directory_tests", true);
+    let config = utils::config::Config::new(
+        &CONFIG
+            .replace("{TMP}", &temp_dir.path.to_string_lossy())
+            .replace("{PORT}", &port.to_string()),
+    )
+    .unwrap();
+    let stores = config.parse_stores().await.unwrap();
+    let store = stores.stores.get("sqlite").unwrap().clone();
+    let directories = config
+        .parse_directory(&stores, &Servers::default(), store.clone())
+        .await
+        .unwrap();
+    let jwks_directory = directories.directories.get("jwks").unwrap();
+    let introspect_directory = directories.directories.get("introspect").unwrap();
+    store.create_domain("example.org").await.unwrap();
+
+    let signing_key = SigningKey::<Sha256>::new(key);
+    let now = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+    let sign = |kid: &str, claims: serde_json::Value| {
+        let message = format!(
+            "{}.{}",
+            URL_SAFE_NO_PAD.encode(json!({"alg": "RS256", "typ": "JWT", "kid": kid}).to_string()),
+            URL_SAFE_NO_PAD.encode(claims.to_string())
+        );
+        let signature = signing_key.sign(message.as_bytes()).to_bytes();
+        format!("{message}.{}", URL_SAFE_NO_PAD.encode(signature))
+    };
+    let claims = json!({
+        "iss": "https://idp.example.org/",
+        "sub": "8f0d0b2c",
+        "aud": ["stalwart", "account"],
+        "exp": now + 300,
+        "iat": now,
+        "preferred_username": "jdoe",
+        "email": "JDoe@example.org",
+        "email_verified": true,
+        "name": "John Doe",
+    });
+    let token = sign("test-key", claims.clone());
+
+    // Valid tokens provision the account on first use
+    assert_eq!(store.get_account_id("jdoe").await.unwrap(), None);
+    let principal = oidc_login(jwks_directory, bearer(&token)).await.unwrap();
+    assert_eq!(
+        principal,
+        Principal {
+            id: principal.id,
+            name: "jdoe".to_string(),
+            emails: vec!["jdoe@example.org".to_string()],
+            description: Some("John Doe".to_string()),
+            ..Default::default()
+        }
+    );
+    assert_eq!(
+        store.get_account_id("jdoe").await.unwrap(),
+        Some(principal.id)
+    );
+    assert_eq!(
+        oidc_login(jwks_directory, bearer(&token))
+            .await
+            .map(|p| p.id),
+        Some(principal.id)
+    );
+
+    // SMTP sends the full OAUTHBEARER message
+    assert_eq!(
+        oidc_login(
+            jwks_directory,
+            bearer(&format!("n,a=jdoe,\x01auth=Bearer {token}\x01\x01"))
+        )
+        .await
+        .map(|p| p.id),
+        Some(principal.id)
+    );
+
+    // XOAUTH2 requires the username to match the token
+    for (username, expect) in [
+        ("jdoe", Some(principal.id)),
+        ("jdoe@example.org", Some(principal.id)),
+        ("jane", None),
+    ] {
+        assert_eq!(
+            oidc_login(
+                jwks_directory,
+                Credentials::XOauth2 {
+                    username: username.to_string(),
+                    secret: token.clone(),
+                }
+            )
+            .await
+            .map(|p| p.id),
+            expect,
+            "{username}"
+        );
+    }
+
+    // Other identities can't take over existing accounts
+    for invalid_claims in [
+        json!({"sub": "3b9e77aa"}),
+        json!({"sub": "3b9e77aa", "preferred_username": null}),
+    ] {
+        let mut claims = claims.clone();
+        for (name, value) in invalid_claims.as_object().unwrap() {
+            claims[name] = value.clone();
+        }
+        assert_eq!(
+            oidc_login(jwks_directory, bearer(&sign("test-key", claims.clone()))).await,
+            None,
+            "{claims}"
+        );
+    }
+
+    // Unverified email addresses are ignored
+    let mut unverified_claims = claims.clone();
+    unverified_claims["sub"] = json!("5d2c90f1");
+    unverified_claims["preferred_username"] = json!(null);
+    unverified_claims["email"] = json!("jsmith@example.org");
+    unverified_claims["email_verified"] = json!(false);
+    assert_eq!(
+        oidc_login(jwks_directory, bearer(&sign("test-key", unverified_claims))).await,
+        None
+    );
+    assert_eq!(
+        store.get_account_id("jsmith@example.org").await.unwrap(),
+        None
+    );
+
+    // Invalid tokens should fail
+    for (kid, invalid_claims) in [
+        ("test-key", json!({"iss": "https://evil.example.org"})),
+        ("test-key", json!({"aud": "other"})),
+        ("test-key", json!({"aud": null})),
+        ("test-key", json!({"exp": now - 3600})),
+        ("test-key", json!({"nbf": now + 3600})),
+        ("unknown-key", json!({})),
+    ] {
+        let mut claims = claims.clone();
+        for (name, value) in invalid_claims.as_object().unwrap() {
+            claims[name] = value.clone();
+        }
+        assert_eq!(
+            oidc_login(jwks_directory, bearer(&sign(kid, claims.clone()))).await,
+            None,
+            "{claims}"
+        );
+    }
+    let (message, _) = token.rsplit_once('.').unwrap();
+    assert_eq!(
+        oidc_login(
+            jwks_directory,
+            bearer(&format!("{message}.{}", URL_SAFE_NO_PAD.encode([0u8; 256])))
+        )
+        .await,
+        None
+    );
+    assert_eq!(oidc_login(jwks_directory, bearer("not-a-jwt")).await, None);
+
+    // Unknown key ids do not trigger a new fetch of the key set
+    assert_eq!(
+        oidc_login(jwks_directory, bearer(&sign("other-key", claims.clone()))).await,
+        None
+    );
+    assert_eq!(jwks_fetches.load(Ordering::Relaxed), 1);
+
+    // Passwords are still validated by the directory
+    assert_eq!(
+        oidc_login(
+            jwks_directory,
+            Credentials::Plain {
+                username: "jdoe".to_string(),
+                secret: token.clone(),
+            }
+        )
+        .await,
+        None
+    );
+
+    // Token introspection does not provision accounts unless enabled
+    assert_eq!(
+        oidc_login(introspect_directory, bearer("opaque-valid")).await,
+        None
+    );
+    let jane_id = store
+        .create_account(Principal {
+            name: "jane".to_string(),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+    // Existing accounts are only accessible once linked to the identity
+    assert_eq!(
+        oidc_login(introspect_directory, bearer("opaque-valid")).await,
+        None
+    );
+    assert!(introspect_directory
+        .link_oidc_subject("c7a1e4", jane_id)
+        .await
+        .unwrap());
+    assert_eq!(
+        oidc_login(introspect_directory, bearer("opaque-valid"))
+            .await
+            .map(|p| p.name),
+        Some("jane".to_string())
+    );
+    assert_eq!(
+        oidc_login(introspect_directory, bearer("opaque-revoked")).await,
+        None
+    );
+    assert!(introspect_directory
+        .unlink_oidc_subject("c7a1e4", jane_id)
+        .await
+        .unwrap());
+    assert_eq!(
+        oidc_login(introspect_directory, bearer("opaque-valid")).await,
+        None
+    );
+
+    // Clean up
+    for name in ["jdoe", "jane"] {
+        store.delete_account(QueryBy::Name(name)).await.unwrap();
+    }
+    temp_dir.delete();
+}
+
+fn bearer(token: &str) -> Credentials<String> {
+    Credentials::OAuthBearer {
+        token: token.to_string(),
+    }
+}
+
+async fn oidc_login(
+    directory: &directory::Directory,
+    credentials: Credentials<String>,
+) -> Option<Principal<u32>> {
+    match directory
+        .authenticate(
+            &credentials,
+            "127.0.0.1".parse().unwrap(),
+            ServerProtocol::Imap,
+            false,
+        )
+        .await
+        .unwrap()
+    {
+        AuthResult::Success(principal) => Some(principal),
+        _ => None,
+    }
+}
@@ -80,6 +80,27 @@ pub async fn test(mut imap_john: &mut ImapConnection, _imap_check: &mut ImapConn
     imap_jane.send("UNSELECT").await;
     imap_jane.assert_read(Type::Tagged, ResponseType::Ok).await;
 
+    // Members of Support are also members of the groups Support belongs to
+    imap_jane.send("LIST \"\" \"*\"").await;
+    imap_jane
+        .assert_read(Type::Tagged, ResponseType::Ok)
+        .await
+        .assert_contains("Shared Folders/staff@example.com/Inbox");
+    imap_jane
+        .send("MYRIGHTS \"Shared Folders/staff@example.com/Inbox\"")
+        .await;
+    imap_jane
+        .assert_read(Type::Tagged, ResponseType::Ok)
+        .await
+        .assert_equals("* MYRIGHTS \"Shared Folders/staff@example.com/Inbox\" rliteswkxp");
+    assert_append_message(
+        &mut imap_jane,
+        "Shared Folders/staff@example.com/Inbox",
+        "From: jane\r\n\r\nstaff meeting",
+        ResponseType::Ok,
+    )
+    .await;
+
     // John should have no shared folders
     imap_john.send("LIST \"\" \"*\"").await;
     imap_john
@@ -21,11 +21,14 @@
  * for more details.
 */
 
+use base64::{engine::general_purpose, Engine};
 use imap::op::authenticate::decode_challenge_oauth;
 use imap_proto::ResponseType;
 use mail_parser::decoders::base64::base64_decode;
 use mail_send::Credentials;
 
+use crate::directory::ScramClient;
+
 use super::{AssertResult, ImapConnection, Type};
 
 pub async fn test(imap: &mut ImapConnection, _imap_check: &mut ImapConnection) {
@@ -52,6 +55,42 @@ pub async fn test(imap: &mut ImapConnection, _imap_check: &mut ImapConnection) {
     imap.assert_read(Type::Continuation, ResponseType::Ok).await;
     imap.send_untagged("AGJvYXR5AG1jYm9hdGZhY2U=").await;
     imap.assert_read(Type::Tagged, ResponseType::No).await;
+
+    // Test SCRAM-SHA-256
+    let mut imap = ImapConnection::connect(b"_z ").await;
+    imap.assert_read(Type::Untagged, ResponseType::Ok).await;
+    for (password, expected_response) in [("wrong", ResponseType::No), ("secret", ResponseType::Ok)]
+    {
+        let mut client = ScramClient::new("scram", None);
+        imap.send(&format!(
+            "AUTHENTICATE SCRAM-SHA-256 {}",
+            general_purpose::STANDARD.encode(client.client_first())
+        ))
+        .await;
+        let server_first =
+            scram_challenge(imap.assert_read(Type::Continuation, ResponseType::Ok).await);
+        imap.send_untagged(
+            &general_purpose::STANDARD.encode(client.client_final(password, &server_first)),
+        )
+        .await;
+        if expected_response == ResponseType::Ok {
+            assert_eq!(
+                scram_challenge(imap.assert_read(Type::Continuation, ResponseType::Ok).await),
+                client.server_final()
+            );
+            imap.send_untagged("").await;
+        }
+        imap.assert_read(Type::Tagged, expected_response).await;
+    }
+    imap.send("LOGOUT").await;
+    imap.assert_read(Type::Untagged, ResponseType::Bye).await;
+}
+
+fn scram_challenge(lines: Vec<String>) -> String {
+    String::from_utf8(
+        base64_decode(lines.last().unwrap().strip_prefix("+ ").unwrap().as_bytes()).unwrap(),
+    )
+    .unwrap()
 }
 
 #[test]
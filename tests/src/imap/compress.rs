@@ -0,0 +1,125 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::time::Duration;
+
+use tokio::{
+    io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader},
+    net::TcpStream,
+};
+use utils::listener::deflate::DeflateStream;
+
+pub async fn test() {
+    // Authenticate over a plain connection
+    let mut stream = TcpStream::connect("127.0.0.1:9991").await.unwrap();
+    assert!(read_line(&mut stream).await.starts_with("* OK"));
+    stream.write_all(b"c1 COMPRESS DEFLATE\r\n").await.unwrap();
+    assert!(read_line(&mut stream).await.starts_with("c1 NO"));
+    stream
+        .write_all(b"c2 AUTHENTICATE PLAIN {32+}\r\nAGpkb2VAZXhhbXBsZS5jb20Ac2VjcmV0\r\n")
+        .await
+        .unwrap();
+    let mut line = read_line(&mut stream).await;
+    while line.starts_with("* ") {
+        line = read_line(&mut stream).await;
+    }
+    assert!(line.starts_with("c2 OK"), "{line}");
+
+    // Capabilities should include COMPRESS=DEFLATE
+    stream.write_all(b"c3 CAPABILITY\r\n").await.unwrap();
+    assert!(read_line(&mut stream).await.contains("COMPRESS=DEFLATE"));
+    assert!(read_line(&mut stream).await.starts_with("c3 OK"));
+
+    // Unsupported algorithms are rejected
+    stream.write_all(b"c4 COMPRESS LZ4\r\n").await.unwrap();
+    assert!(read_line(&mut stream).await.starts_with("c4 BAD"));
+
+    // Enable compression
+    stream.write_all(b"c5 COMPRESS DEFLATE\r\n").await.unwrap();
+    assert_eq!(read_line(&mut stream).await, "c5 OK DEFLATE active");
+
+    let mut stream = BufReader::new(DeflateStream::new(stream));
+    for (command, expected) in [
+        ("c6 NOOP", "c6 OK"),
+        ("c7 COMPRESS DEFLATE", "c7 NO [COMPRESSIONACTIVE]"),
+        ("c8 SELECT INBOX", "c8 OK"),
+    ] {
+        stream
+            .get_mut()
+            .write_all(format!("{command}\r\n").as_bytes())
+            .await
+            .unwrap();
+        stream.get_mut().flush().await.unwrap();
+
+        let mut lines = Vec::new();
+        loop {
+            let mut line = String::new();
+            tokio::time::timeout(Duration::from_millis(1500), stream.read_line(&mut line))
+                .await
+                .unwrap()
+                .unwrap();
+            if line.is_empty() {
+                panic!("Connection closed: {lines:?}");
+            }
+            let is_done = !line.starts_with("* ");
+            lines.push(line);
+            if is_done {
+                break;
+            }
+        }
+        assert!(
+            lines.last().unwrap().starts_with(expected),
+            "Expected {expected:?} but got {lines:?}"
+        );
+    }
+
+    // Fetch a large response
+    stream
+        .get_mut()
+        .write_all(b"c9 FETCH 1:* (FLAGS BODY.PEEK[])\r\nc10 LOGOUT\r\n")
+        .await
+        .unwrap();
+    stream.get_mut().flush().await.unwrap();
+    let mut response = String::new();
+    tokio::time::timeout(
+        Duration::from_millis(3000),
+        stream.read_to_string(&mut response),
+    )
+    .await
+    .unwrap()
+    .unwrap();
+    assert!(response.contains("c9 OK"), "{response}");
+    assert!(response.contains("* BYE"), "{response}");
+}
+
+async fn read_line(stream: &mut TcpStream) -> String {
+    let mut line = Vec::new();
+    while !line.ends_with(b"\r\n") {
+        let ch = tokio::time::timeout(Duration::from_millis(1500), stream.read_u8())
+            .await
+            .unwrap()
+            .unwrap();
+        line.push(ch);
+    }
+    String::from_utf8(line).unwrap().trim_end().to_string()
+}
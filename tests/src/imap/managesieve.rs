@@ -23,15 +23,20 @@
 
 use std::time::Duration;
 
+use base64::{engine::general_purpose, Engine};
 use imap_proto::ResponseType;
+use mail_parser::decoders::base64::base64_decode;
 use mail_send::smtp::tls::build_tls_connector;
 use rustls_pki_types::ServerName;
+use sha2::{Digest, Sha256};
 use tokio::{
     io::{AsyncBufReadExt, AsyncWriteExt, BufReader, Lines, ReadHalf, WriteHalf},
     net::TcpStream,
 };
 use tokio_rustls::client::TlsStream;
 
+use crate::directory::ScramClient;
+
 use super::AssertResult;
 
 pub async fn test() {
@@ -152,27 +157,58 @@ pub async fn test() {
         .await
         .assert_count("minimalist script", 0)
         .assert_count("holidays", 0);
+
+    // Authenticate using SCRAM-SHA-256-PLUS
+    let mut sieve = SieveConnection::connect().await;
+    sieve.assert_read(ResponseType::Ok).await;
+    let mut client = ScramClient::new("scram", Some(&sieve.channel_binding));
+    sieve
+        .send(&format!(
+            "AUTHENTICATE \"SCRAM-SHA-256-PLUS\" \"{}\"",
+            general_purpose::STANDARD.encode(client.client_first())
+        ))
+        .await;
+    let server_first = sieve.reader.next_line().await.unwrap().unwrap();
+    let server_first =
+        String::from_utf8(base64_decode(server_first.trim_matches('"').as_bytes()).unwrap())
+            .unwrap();
+    sieve
+        .send(&format!(
+            "\"{}\"",
+            general_purpose::STANDARD.encode(client.client_final("secret", &server_first))
+        ))
+        .await;
+    sieve
+        .assert_read(ResponseType::Ok)
+        .await
+        .assert_contains(&format!(
+            "(SASL \"{}\")",
+            general_purpose::STANDARD.encode(client.server_final())
+        ));
 }
 
 pub struct SieveConnection {
     reader: Lines<BufReader<ReadHalf<TlsStream<TcpStream>>>>,
     writer: WriteHalf<TlsStream<TcpStream>>,
+    channel_binding: Vec<u8>,
 }
 
 impl SieveConnection {
     pub async fn connect() -> Self {
-        let (reader, writer) = tokio::io::split(
-            build_tls_connector(true)
-                .connect(
-                    ServerName::try_from("imap.example.org").unwrap().to_owned(),
-                    TcpStream::connect("127.0.0.1:4190").await.unwrap(),
-                )
-                .await
-                .unwrap(),
-        );
+        let stream = build_tls_connector(true)
+            .connect(
+                ServerName::try_from("imap.example.org").unwrap().to_owned(),
+                TcpStream::connect("127.0.0.1:4190").await.unwrap(),
+            )
+            .await
+            .unwrap();
+        let channel_binding =
+            Sha256::digest(stream.get_ref().1.peer_certificates().unwrap()[0].as_ref()).to_vec();
+        let (reader, writer) = tokio::io::split(stream);
         SieveConnection {
             reader: BufReader::new(reader).lines(),
             writer,
+            channel_binding,
         }
     }
 
@@ -0,0 +1,238 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use imap_proto::ResponseType;
+
+use super::{AssertResult, ImapConnection, Type};
+
+pub async fn test(imap: &mut ImapConnection, _imap_check: &mut ImapConnection) {
+    // Set and retrieve mailbox annotations
+    imap.send(
+        "SETMETADATA INBOX (/private/comment \"My comment\" /shared/comment \"Our comment\")",
+    )
+    .await;
+    imap.assert_read(Type::Tagged, ResponseType::Ok).await;
+    imap.send("GETMETADATA INBOX (/private/comment /shared/comment /shared/missing)")
+        .await;
+    imap.assert_read(Type::Tagged, ResponseType::Ok)
+        .await
+        .assert_contains("\"/private/comment\" \"My comment\"")
+        .assert_contains("\"/shared/comment\" \"Our comment\"")
+        .assert_count("/shared/missing", 0);
+
+    // Test MAXSIZE
+    imap.send("GETMETADATA (MAXSIZE 10) INBOX (/private/comment /shared/comment)")
+        .await;
+    imap.assert_read(Type::Tagged, ResponseType::Ok)
+        .await
+        .assert_contains("\"/private/comment\" \"My comment\"")
+        .assert_count("/shared/comment", 0)
+        .assert_response_code("METADATA LONGENTRIES 11");
+    imap.send(&format!(
+        "SETMETADATA INBOX (/private/large {{5000+}}\r\n{})",
+        "a".repeat(5000)
+    ))
+    .await;
+    imap.assert_read(Type::Tagged, ResponseType::No)
+        .await
+        .assert_response_code("METADATA MAXSIZE 4096");
+
+    // Test DEPTH
+    imap.send("SETMETADATA INBOX (/private/vendor/a \"1\" /private/vendor/a/b \"2\")")
+        .await;
+    imap.assert_read(Type::Tagged, ResponseType::Ok).await;
+    imap.send("GETMETADATA (DEPTH 1) INBOX /private/vendor")
+        .await;
+    imap.assert_read(Type::Tagged, ResponseType::Ok)
+        .await
+        .assert_contains("\"/private/vendor/a\" \"1\"")
+        .assert_count("/private/vendor/a/b", 0);
+    imap.send("GETMETADATA (DEPTH infinity) INBOX /private/vendor")
+        .await;
+    imap.assert_read(Type::Tagged, ResponseType::Ok)
+        .await
+        .assert_contains("\"/private/vendor/a\" \"1\"")
+        .assert_contains("\"/private/vendor/a/b\" \"2\"");
+
+    // Remove entries
+    imap.send("SETMETADATA INBOX (/private/comment NIL /private/vendor/a NIL)")
+        .await;
+    imap.assert_read(Type::Tagged, ResponseType::Ok).await;
+    imap.send("GETMETADATA (DEPTH infinity) INBOX (/private/comment /private/vendor)")
+        .await;
+    imap.assert_read(Type::Tagged, ResponseType::Ok)
+        .await
+        .assert_count("\"/private/comment\"", 0)
+        .assert_count("\"/private/vendor/a\"", 0)
+        .assert_contains("\"/private/vendor/a/b\" \"2\"");
+
+    // Server annotations
+    imap.send("SETMETADATA \"\" (/private/vendor/client \"settings\")")
+        .await;
+    imap.assert_read(Type::Tagged, ResponseType::Ok).await;
+    imap.send("GETMETADATA \"\" /private/vendor/client").await;
+    imap.assert_read(Type::Tagged, ResponseType::Ok)
+        .await
+        .assert_contains("* METADATA \"\" (\"/private/vendor/client\" \"settings\")");
+    imap.send("SETMETADATA \"\" (/shared/admin \"mailto:admin@example.com\")")
+        .await;
+    imap.assert_read(Type::Tagged, ResponseType::No)
+        .await
+        .assert_response_code("NOPERM");
+    imap.send("GETMETADATA \"Does not exist\" /shared/comment")
+        .await;
+    imap.assert_read(Type::Tagged, ResponseType::No)
+        .await
+        .assert_response_code("NONEXISTENT");
+
+    // Share a mailbox with Jane
+    imap.send("CREATE \"Metadata Test\"").await;
+    imap.assert_read(Type::Tagged, ResponseType::Ok).await;
+    imap.send(
+        "SETMETADATA \"Metadata Test\" (/shared/comment \"Shared\" /private/comment \"John's\")",
+    )
+    .await;
+    imap.assert_read(Type::Tagged, ResponseType::Ok).await;
+
+    let mut imap_jane = ImapConnection::connect(b"_w ").await;
+    imap_jane
+        .assert_read(Type::Untagged, ResponseType::Ok)
+        .await;
+    imap_jane
+        .send("AUTHENTICATE PLAIN {40+}\r\nAGphbmUuc21pdGhAZXhhbXBsZS5jb20Ac2VjcmV0")
+        .await;
+    imap_jane.assert_read(Type::Tagged, ResponseType::Ok).await;
+    imap.send("SETACL \"Metadata Test\" jane.smith@example.com l")
+        .await;
+    imap.assert_read(Type::Tagged, ResponseType::Ok).await;
+
+    imap_jane.send("LIST \"\" \"*\"").await;
+    imap_jane
+        .assert_read(Type::Tagged, ResponseType::Ok)
+        .await
+        .assert_contains("Shared Folders/jdoe@example.com/Metadata Test");
+
+    // Lookup access is not enough to read annotations
+    let mailbox = "\"Shared Folders/jdoe@example.com/Metadata Test\"";
+    imap_jane
+        .send(&format!("GETMETADATA {mailbox} /shared/comment"))
+        .await;
+    imap_jane
+        .assert_read(Type::Tagged, ResponseType::No)
+        .await
+        .assert_response_code("NOPERM");
+
+    // Read access allows reading shared and writing private annotations
+    imap.send("SETACL \"Metadata Test\" jane.smith@example.com lr")
+        .await;
+    imap.assert_read(Type::Tagged, ResponseType::Ok).await;
+    imap_jane
+        .send(&format!(
+            "GETMETADATA {mailbox} (/shared/comment /private/comment)"
+        ))
+        .await;
+    imap_jane
+        .assert_read(Type::Tagged, ResponseType::Ok)
+        .await
+        .assert_contains("\"/shared/comment\" \"Shared\"")
+        .assert_count("John's", 0);
+    imap_jane
+        .send(&format!(
+            "SETMETADATA {mailbox} (/shared/comment \"Jane's\")"
+        ))
+        .await;
+    imap_jane
+        .assert_read(Type::Tagged, ResponseType::No)
+        .await
+        .assert_response_code("NOPERM");
+    imap_jane
+        .send(&format!(
+            "SETMETADATA {mailbox} (/private/comment \"Jane's\")"
+        ))
+        .await;
+    imap_jane.assert_read(Type::Tagged, ResponseType::Ok).await;
+    imap_jane
+        .send(&format!("GETMETADATA {mailbox} /private/comment"))
+        .await;
+    imap_jane
+        .assert_read(Type::Tagged, ResponseType::Ok)
+        .await
+        .assert_contains("\"/private/comment\" \"Jane's\"");
+    imap.send("GETMETADATA \"Metadata Test\" /private/comment")
+        .await;
+    imap.assert_read(Type::Tagged, ResponseType::Ok)
+        .await
+        .assert_contains("\"/private/comment\" \"John's\"")
+        .assert_count("Jane's", 0);
+
+    // The entry limit applies to each user's private entries separately
+    imap.send(&format!(
+        "SETMETADATA \"Metadata Test\" ({})",
+        (0..99)
+            .map(|i| format!("/private/entry{i} \"{i}\""))
+            .collect::<Vec<_>>()
+            .join(" ")
+    ))
+    .await;
+    imap.assert_read(Type::Tagged, ResponseType::Ok).await;
+    imap.send("SETMETADATA \"Metadata Test\" (/private/overflow \"1\")")
+        .await;
+    imap.assert_read(Type::Tagged, ResponseType::No)
+        .await
+        .assert_response_code("METADATA TOOMANY");
+    imap_jane
+        .send(&format!(
+            "SETMETADATA {mailbox} (/private/other \"Jane's\")"
+        ))
+        .await;
+    imap_jane.assert_read(Type::Tagged, ResponseType::Ok).await;
+
+    // Write access allows changing shared annotations
+    imap.send("SETACL \"Metadata Test\" jane.smith@example.com lrw")
+        .await;
+    imap.assert_read(Type::Tagged, ResponseType::Ok).await;
+    imap_jane
+        .send(&format!(
+            "SETMETADATA {mailbox} (/shared/comment \"Jane's\")"
+        ))
+        .await;
+    imap_jane.assert_read(Type::Tagged, ResponseType::Ok).await;
+    imap.send("GETMETADATA \"Metadata Test\" /shared/comment")
+        .await;
+    imap.assert_read(Type::Tagged, ResponseType::Ok)
+        .await
+        .assert_contains("\"/shared/comment\" \"Jane's\"");
+
+    // Deleting the mailbox removes its annotations
+    imap.send("DELETE \"Metadata Test\"").await;
+    imap.assert_read(Type::Tagged, ResponseType::Ok).await;
+    imap.send("CREATE \"Metadata Test\"").await;
+    imap.assert_read(Type::Tagged, ResponseType::Ok).await;
+    imap.send("GETMETADATA \"Metadata Test\" (/shared/comment /private/comment)")
+        .await;
+    imap.assert_read(Type::Tagged, ResponseType::Ok)
+        .await
+        .assert_count("* METADATA", 0);
+    imap.send("DELETE \"Metadata Test\"").await;
+    imap.assert_read(Type::Tagged, ResponseType::Ok).await;
+}
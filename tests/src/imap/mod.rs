@@ -25,12 +25,16 @@ pub mod acl;
 pub mod append;
 pub mod basic;
 pub mod body_structure;
+pub mod compress;
 pub mod condstore;
 pub mod copy_move;
 pub mod fetch;
 pub mod idle;
 pub mod mailbox;
 pub mod managesieve;
+pub mod metadata;
+pub mod notify;
+pub mod quota;
 pub mod search;
 pub mod store;
 pub mod thread;
@@ -40,7 +44,13 @@ use std::{path::PathBuf, sync::Arc, time::Duration};
 use ::managesieve::core::ManageSieveSessionManager;
 use ::store::config::ConfigStore;
 use ahash::AHashSet;
-use directory::{backend::internal::manage::ManageDirectory, core::config::ConfigDirectory};
+use directory::{
+    backend::internal::manage::ManageDirectory,
+    core::{
+        config::ConfigDirectory,
+        scram::{ScramAlgorithm, ScramSecret},
+    },
+};
 use imap::core::{ImapSessionManager, IMAP};
 use imap_proto::ResponseType;
 use jmap::{api::JmapSessionManager, services::IPC_CHANNEL_BUFFER, JMAP};
@@ -183,6 +193,9 @@ directory = "auth"
 [jmap.protocol]
 set.max-objects = 100000
 
+[jmap.email]
+max-messages = 100000
+
 [jmap.protocol.request]
 max-concurrent = 8
 
@@ -335,18 +348,35 @@ async fn init_imap_tests(store_id: &str, delete_if_exists: bool) -> IMAPTest {
     lookup
         .create_test_user_with_email("jdoe@example.com", "secret", "John Doe")
         .await;
+    lookup
+        .create_test_user(
+            "scram",
+            &ScramSecret::new(ScramAlgorithm::Sha256, "secret").to_string(),
+            "SCRAM User",
+        )
+        .await;
     lookup
         .create_test_user_with_email("jane.smith@example.com", "secret", "Jane Smith")
         .await;
     lookup
         .create_test_user_with_email("foobar@example.com", "secret", "Bill Foobar")
         .await;
+    lookup
+        .create_test_user_with_email("popeye@example.com", "secret", "Popeye")
+        .await;
+    lookup.set_test_quota("popeye@example.com", 2048).await;
     lookup
         .create_test_group_with_email("support@example.com", "Support Group")
         .await;
     lookup
         .add_to_group("jane.smith@example.com", "support@example.com")
         .await;
+    lookup
+        .create_test_group_with_email("staff@example.com", "Staff Group")
+        .await;
+    lookup
+        .add_to_group("support@example.com", "staff@example.com")
+        .await;
 
     if delete_if_exists {
         jmap.store.destroy().await;
@@ -422,6 +452,10 @@ pub async fn imap_tests() {
     idle::test(&mut imap, &mut imap_check).await;
     condstore::test(&mut imap, &mut imap_check).await;
     acl::test(&mut imap, &mut imap_check).await;
+    quota::test(&mut imap, &mut imap_check).await;
+    metadata::test(&mut imap, &mut imap_check).await;
+    notify::test(&mut imap, &mut imap_check).await;
+    compress::test().await;
 
     // Logout
     for imap in [&mut imap, &mut imap_check] {
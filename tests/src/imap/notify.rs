@@ -0,0 +1,201 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use imap_proto::ResponseType;
+
+use super::{AssertResult, ImapConnection, Type};
+
+pub async fn test(imap: &mut ImapConnection, imap_check: &mut ImapConnection) {
+    // Unsupported events should be rejected
+    imap.send("NOTIFY SET (personal (MessageNew MessageExpunge AnnotationChange))")
+        .await;
+    imap.assert_read(Type::Tagged, ResponseType::No)
+        .await
+        .assert_contains("BADEVENT");
+    imap.send("NOTIFY SET (personal (MessageNew))").await;
+    imap.assert_read(Type::Tagged, ResponseType::Bad).await;
+
+    // Subscribe to changes and expect an initial status
+    imap.send("CREATE \"Notify Test\"").await;
+    imap.assert_read(Type::Tagged, ResponseType::Ok).await;
+    imap.send(concat!(
+        "NOTIFY SET STATUS (mailboxes \"Notify Test\" (MessageNew MessageExpunge)) ",
+        "(personal (MailboxName SubscriptionChange))"
+    ))
+    .await;
+    imap.assert_read(Type::Tagged, ResponseType::Ok)
+        .await
+        .assert_contains("STATUS \"Notify Test\"")
+        .assert_contains("MESSAGES 0")
+        .assert_contains("UIDNEXT 1")
+        .assert_count("STATUS", 1);
+
+    // Insert a message and expect a status update
+    let message = "From: test@domain.com\nSubject: Test\n\nTest message\n";
+    imap_check
+        .send(&format!("APPEND \"Notify Test\" {{{}}}", message.len()))
+        .await;
+    imap_check
+        .assert_read(Type::Continuation, ResponseType::Ok)
+        .await;
+    imap_check.send_untagged(message).await;
+    imap_check.assert_read(Type::Tagged, ResponseType::Ok).await;
+    imap.assert_read(Type::Status, ResponseType::Ok)
+        .await
+        .assert_contains("STATUS \"Notify Test\"")
+        .assert_contains("MESSAGES 1")
+        .assert_contains("UNSEEN 1")
+        .assert_contains("UIDNEXT 2");
+
+    // Mailbox creation, subscription and deletion
+    imap_check.send("CREATE \"Notify Test/Child\"").await;
+    imap_check.assert_read(Type::Tagged, ResponseType::Ok).await;
+    imap.assert_read(Type::Status, ResponseType::Ok)
+        .await
+        .assert_contains("LIST () \"/\" \"Notify Test/Child\"");
+    imap_check.send("SUBSCRIBE \"Notify Test/Child\"").await;
+    imap_check.assert_read(Type::Tagged, ResponseType::Ok).await;
+    imap.assert_read(Type::Status, ResponseType::Ok)
+        .await
+        .assert_contains("LIST (\\Subscribed) \"/\" \"Notify Test/Child\"");
+    imap_check.send("DELETE \"Notify Test/Child\"").await;
+    imap_check.assert_read(Type::Tagged, ResponseType::Ok).await;
+    imap.assert_read(Type::Status, ResponseType::Ok)
+        .await
+        .assert_contains("LIST (\\NonExistent) \"/\" \"Notify Test/Child\"");
+
+    // Changes to the selected mailbox include the requested attributes
+    imap.send("SELECT \"Notify Test\"").await;
+    imap.assert_read(Type::Tagged, ResponseType::Ok).await;
+    imap.send(concat!(
+        "NOTIFY SET (selected (MessageNew (UID FLAGS) MessageExpunge FlagChange)) ",
+        "(personal (MessageNew MessageExpunge))"
+    ))
+    .await;
+    imap.assert_read(Type::Tagged, ResponseType::Ok).await;
+    imap_check
+        .send(&format!("APPEND \"Notify Test\" {{{}}}", message.len()))
+        .await;
+    imap_check
+        .assert_read(Type::Continuation, ResponseType::Ok)
+        .await;
+    imap_check.send_untagged(message).await;
+    imap_check.assert_read(Type::Tagged, ResponseType::Ok).await;
+    imap.assert_read(Type::Status, ResponseType::Ok)
+        .await
+        .assert_contains("* 2 EXISTS");
+    imap.assert_read(Type::Status, ResponseType::Ok)
+        .await
+        .assert_contains("* 2 FETCH")
+        .assert_contains("UID 2");
+
+    // Changes to other mailboxes are reported while idling
+    imap.send("IDLE").await;
+    imap.assert_read(Type::Continuation, ResponseType::Ok).await;
+    imap_check
+        .send(&format!("APPEND INBOX {{{}}}", message.len()))
+        .await;
+    imap_check
+        .assert_read(Type::Continuation, ResponseType::Ok)
+        .await;
+    imap_check.send_untagged(message).await;
+    imap_check.assert_read(Type::Tagged, ResponseType::Ok).await;
+    imap.assert_read(Type::Status, ResponseType::Ok)
+        .await
+        .assert_contains("STATUS \"INBOX\"");
+    imap.send_untagged("DONE").await;
+    imap.assert_read(Type::Tagged, ResponseType::Ok).await;
+
+    // Changes in shared and group mailboxes are reported
+    let mut jane = ImapConnection::connect(b"_n ").await;
+    let mut jane_check = ImapConnection::connect(b"_m ").await;
+    for imap in [&mut jane, &mut jane_check] {
+        imap.assert_read(Type::Untagged, ResponseType::Ok).await;
+        imap.send("AUTHENTICATE PLAIN {40+}\r\nAGphbmUuc21pdGhAZXhhbXBsZS5jb20Ac2VjcmV0")
+            .await;
+        imap.assert_read(Type::Tagged, ResponseType::Ok).await;
+    }
+    jane.send("NOTIFY SET (subtree \"Shared Folders\" (MessageNew MessageExpunge))")
+        .await;
+    jane.assert_read(Type::Tagged, ResponseType::Ok).await;
+    for mailbox_name in [
+        "Shared Folders/support@example.com/Inbox",
+        "Shared Folders/staff@example.com/Inbox",
+    ] {
+        jane_check
+            .send(&format!("APPEND \"{mailbox_name}\" {{{}}}", message.len()))
+            .await;
+        jane_check
+            .assert_read(Type::Continuation, ResponseType::Ok)
+            .await;
+        jane_check.send_untagged(message).await;
+        jane_check.assert_read(Type::Tagged, ResponseType::Ok).await;
+        jane.assert_read(Type::Status, ResponseType::Ok)
+            .await
+            .assert_contains(&format!("STATUS \"{mailbox_name}\""));
+    }
+
+    // Changes in mailboxes shared with the user are reported
+    imap.send("SETACL \"Notify Test\" jane.smith@example.com lr")
+        .await;
+    imap.assert_read(Type::Tagged, ResponseType::Ok).await;
+    jane.send("NOTIFY SET (subtree \"Shared Folders\" (MessageNew MessageExpunge))")
+        .await;
+    jane.assert_read(Type::Tagged, ResponseType::Ok).await;
+    imap_check
+        .send(&format!("APPEND \"Notify Test\" {{{}}}", message.len()))
+        .await;
+    imap_check
+        .assert_read(Type::Continuation, ResponseType::Ok)
+        .await;
+    imap_check.send_untagged(message).await;
+    imap_check.assert_read(Type::Tagged, ResponseType::Ok).await;
+    jane.assert_read(Type::Status, ResponseType::Ok)
+        .await
+        .assert_contains("STATUS \"Shared Folders/jdoe@example.com/Notify Test\"");
+
+    // Mailboxes shared after enabling notifications are also reported
+    imap.send("SETACL INBOX jane.smith@example.com lr").await;
+    imap.assert_read(Type::Tagged, ResponseType::Ok).await;
+    imap_check
+        .send(&format!("APPEND INBOX {{{}}}", message.len()))
+        .await;
+    imap_check
+        .assert_read(Type::Continuation, ResponseType::Ok)
+        .await;
+    imap_check.send_untagged(message).await;
+    imap_check.assert_read(Type::Tagged, ResponseType::Ok).await;
+    jane.assert_read(Type::Status, ResponseType::Ok)
+        .await
+        .assert_contains("STATUS \"Shared Folders/jdoe@example.com/Inbox\"");
+    imap.send("DELETEACL INBOX jane.smith@example.com").await;
+    imap.assert_read(Type::Tagged, ResponseType::Ok).await;
+
+    // Disable notifications and cleanup
+    imap.send("NOTIFY NONE").await;
+    imap.assert_read(Type::Tagged, ResponseType::Ok).await;
+    imap.send("UNSELECT").await;
+    imap.assert_read(Type::Tagged, ResponseType::Ok).await;
+    imap.send("DELETE \"Notify Test\"").await;
+    imap.assert_read(Type::Tagged, ResponseType::Ok).await;
+}
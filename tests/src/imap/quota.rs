@@ -0,0 +1,99 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use imap_proto::ResponseType;
+
+use super::{append::assert_append_message, AssertResult, ImapConnection, Type};
+
+pub async fn test(imap: &mut ImapConnection, _imap_check: &mut ImapConnection) {
+    // Accounts without a storage limit only report the message count
+    imap.send("CAPABILITY").await;
+    imap.assert_read(Type::Tagged, ResponseType::Ok)
+        .await
+        .assert_contains("QUOTA=RES-STORAGE")
+        .assert_contains("QUOTA=RES-MESSAGE");
+    imap.send("GETQUOTAROOT INBOX").await;
+    imap.assert_read(Type::Tagged, ResponseType::Ok)
+        .await
+        .assert_equals("* QUOTAROOT \"INBOX\" \"\"")
+        .assert_contains("* QUOTA \"\" (MESSAGE ")
+        .assert_count("STORAGE", 0);
+    imap.send("GETQUOTA \"\"").await;
+    imap.assert_read(Type::Tagged, ResponseType::Ok)
+        .await
+        .assert_contains("* QUOTA \"\" (MESSAGE ");
+    imap.send("GETQUOTA \"Does not exist\"").await;
+    imap.assert_read(Type::Tagged, ResponseType::No)
+        .await
+        .assert_response_code("NONEXISTENT");
+
+    // Connect to an account with a 2KB storage quota
+    let mut imap = ImapConnection::connect(b"_q ").await;
+    imap.assert_read(Type::Untagged, ResponseType::Ok).await;
+    imap.send("AUTHENTICATE PLAIN {36+}\r\nAHBvcGV5ZUBleGFtcGxlLmNvbQBzZWNyZXQ=")
+        .await;
+    imap.assert_read(Type::Tagged, ResponseType::Ok).await;
+
+    imap.send("GETQUOTAROOT INBOX").await;
+    imap.assert_read(Type::Tagged, ResponseType::Ok)
+        .await
+        .assert_contains("* QUOTAROOT \"INBOX\" \"\"")
+        .assert_contains("* QUOTA \"\" (STORAGE 0 2 MESSAGE 0 100000)");
+
+    // Fill the quota
+    let message = format!("Subject: Quota test\r\n\r\n{}", "a".repeat(1477));
+    assert_eq!(message.len(), 1500);
+    assert_append_message(&mut imap, "INBOX", &message, ResponseType::Ok).await;
+    imap.send("GETQUOTA \"\"").await;
+    imap.assert_read(Type::Tagged, ResponseType::Ok)
+        .await
+        .assert_contains("* QUOTA \"\" (STORAGE 2 2 MESSAGE 1 100000)");
+    assert_append_message(&mut imap, "INBOX", &message, ResponseType::No)
+        .await
+        .assert_response_code("OVERQUOTA");
+
+    // STATUS should be consistent with the quota usage
+    imap.send("SELECT INBOX").await;
+    imap.assert_read(Type::Tagged, ResponseType::Ok).await;
+    imap.send("STORE 1 +FLAGS (\\Deleted)").await;
+    imap.assert_read(Type::Tagged, ResponseType::Ok).await;
+    imap.send("STATUS INBOX (MESSAGES SIZE DELETED DELETED-STORAGE)")
+        .await;
+    imap.assert_read(Type::Tagged, ResponseType::Ok)
+        .await
+        .assert_contains("(MESSAGES 1 SIZE 1500 DELETED 1 DELETED-STORAGE 2)");
+
+    // Expunging releases the quota
+    imap.send("EXPUNGE").await;
+    imap.assert_read(Type::Tagged, ResponseType::Ok).await;
+    imap.send("GETQUOTA \"\"").await;
+    imap.assert_read(Type::Tagged, ResponseType::Ok)
+        .await
+        .assert_contains("* QUOTA \"\" (STORAGE 0 2 MESSAGE 0 100000)");
+
+    // Quotas can't be changed over IMAP
+    imap.send("SETQUOTA \"\" (STORAGE 4096)").await;
+    imap.assert_read(Type::Tagged, ResponseType::No)
+        .await
+        .assert_response_code("NOPERM");
+}
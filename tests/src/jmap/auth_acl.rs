@@ -710,6 +710,35 @@ pub async fn test(params: &mut JMAPTest) {
         .account(&sales_id.to_string())
         .is_none());
 
+    // Members of Sales are also members of the groups Sales belongs to
+    params
+        .directory
+        .create_test_group_with_email("staff@example.com", "Staff Group")
+        .await;
+    params
+        .directory
+        .add_to_group("sales@example.com", "staff@example.com")
+        .await;
+    let staff_id = Id::from(
+        server
+            .store
+            .get_or_create_account_id("staff@example.com")
+            .await
+            .unwrap(),
+    );
+    server.access_tokens.clear();
+    john_client.refresh_session().await.unwrap();
+    bill_client.refresh_session().await.unwrap();
+    let john_session = john_client.session();
+    let staff_account = john_session.account(&staff_id.to_string()).unwrap();
+    assert_eq!(staff_account.name(), "staff@example.com");
+    assert!(!staff_account.is_personal());
+    assert!(!staff_account.is_read_only());
+    assert!(bill_client
+        .session()
+        .account(&staff_id.to_string())
+        .is_none());
+
     // Insert a message in Sales's inbox
     let blob_id = john_client
         .set_default_account_id(&sales_id.to_string())
@@ -787,7 +816,7 @@ pub async fn test(params: &mut JMAPTest) {
     );
 
     // Destroy test account data
-    for id in [john_id, bill_id, jane_id, sales_id] {
+    for id in [john_id, bill_id, jane_id, sales_id, staff_id] {
         params.client.set_default_account_id(&id.to_string());
         destroy_all_mailboxes(params).await;
     }
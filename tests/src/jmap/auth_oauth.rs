@@ -23,16 +23,27 @@
 
 use std::time::{Duration, Instant};
 
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
 use bytes::Bytes;
 use directory::backend::internal::manage::ManageDirectory;
-use jmap::auth::oauth::{DeviceAuthResponse, ErrorType, OAuthMetadata, TokenResponse};
+use jmap::auth::oauth::{
+    oidc::{ClientRegistration, IdTokenClaims, OpenIdMetadata, Userinfo},
+    DeviceAuthResponse, ErrorType, OAuthMetadata, TokenResponse,
+};
 use jmap_client::{
     client::{Client, Credentials},
     mailbox::query::Filter,
 };
 use jmap_proto::types::id::Id;
 use reqwest::{header, redirect::Policy};
+use rsa::{
+    pkcs1v15::{Signature, VerifyingKey},
+    signature::Verifier,
+    BigUint, RsaPublicKey,
+};
 use serde::de::DeserializeOwned;
+use serde_json::json;
+use sha2::Sha256;
 use store::ahash::AHashMap;
 
 use crate::jmap::{assert_is_empty, mailbox::destroy_all_mailboxes};
@@ -141,10 +152,162 @@ pub async fn test(params: &mut JMAPTest) {
         .ids()
         .is_empty());
 
+    // ------------------------
+    // OpenID Connect
+    // ------------------------
+
+    // Obtain OpenID metadata
+    let oidc_metadata: OpenIdMetadata =
+        get("https://127.0.0.1:8899/.well-known/openid-configuration").await;
+    assert_eq!(oidc_metadata.issuer, "https://127.0.0.1:8899");
+    assert_eq!(
+        oidc_metadata.authorization_endpoint,
+        metadata.authorization_endpoint
+    );
+
+    // Registering a client requires the initial access token or admin credentials
+    for authorization in [
+        None,
+        Some("Bearer wrong-secret"),
+        Some("Basic amRvZUBleGFtcGxlLmNvbToxMjM0NQ=="),
+    ] {
+        assert_eq!(
+            post_json(
+                &oidc_metadata.registration_endpoint,
+                authorization,
+                json!({"redirect_uris": ["https://localhost"]}),
+            )
+            .await
+            .0,
+            401
+        );
+    }
+
+    // Register a client, redirect URIs have to be HTTPS
+    assert_eq!(
+        post_json(
+            &oidc_metadata.registration_endpoint,
+            Some("Bearer registration-secret"),
+            json!({"redirect_uris": ["http://localhost"]}),
+        )
+        .await
+        .0,
+        400
+    );
+    let (status, registration) = post_json(
+        &oidc_metadata.registration_endpoint,
+        Some("Basic YWRtaW46c2VjcmV0"),
+        json!({"redirect_uris": ["https://localhost"], "client_name": "Test Client"}),
+    )
+    .await;
+    assert_eq!(status, 201);
+    let registration: ClientRegistration = serde_json::from_value(registration).unwrap();
+    assert_eq!(registration.client_name.as_deref(), Some("Test Client"));
+
+    // Unregistered redirect URIs should be rejected
+    let auth_endpoint = format!(
+        "{}?response_type=code&client_id={}&state=abc&redirect_uri=https://some-other.url",
+        metadata.authorization_endpoint, registration.client_id
+    );
+    assert!(String::from_utf8_lossy(&get_bytes(&auth_endpoint).await).contains("not registered"));
+
+    // Unregistered clients cannot request the openid scope
+    let auth_endpoint = format!(
+        "{}?response_type=code&client_id=OAuthyMcOAuthFace&state=abc&redirect_uri=https://localhost&scope=openid%20email",
+        metadata.authorization_endpoint
+    );
+    assert!(String::from_utf8_lossy(&get_bytes(&auth_endpoint).await).contains("not registered"));
+
+    // Authorize using the openid scope
+    let auth_endpoint = format!(
+        "{}?response_type=code&client_id={}&state=abc&redirect_uri=https://localhost&scope=openid%20email&nonce=n-0S6_WzA2Mj",
+        metadata.authorization_endpoint, registration.client_id
+    );
+    auth_request.insert(
+        "code".to_string(),
+        parse_code_input(get_bytes(&auth_endpoint).await),
+    );
+    let code = parse_code_redirect(
+        post_expect_redirect(&metadata.authorization_endpoint, &auth_request).await,
+        "abc",
+    );
+    let response = post::<TokenResponse>(
+        &metadata.token_endpoint,
+        &AHashMap::from_iter([
+            ("client_id".to_string(), registration.client_id.to_string()),
+            ("redirect_uri".to_string(), "https://localhost".to_string()),
+            ("grant_type".to_string(), "authorization_code".to_string()),
+            ("code".to_string(), code),
+        ]),
+    )
+    .await;
+    let id_token = match &response {
+        TokenResponse::Granted { id_token, .. } => id_token.clone().expect("missing id_token"),
+        TokenResponse::Error { error } => panic!("Expected granted, got {:?}", error),
+    };
+    let (token, _, _) = unwrap_token_response(response);
+
+    // Verify the ID token signature using the published key set
+    let jwks: serde_json::Value = get(&oidc_metadata.jwks_uri).await;
+    let claims: IdTokenClaims =
+        serde_json::from_slice(&verify_id_token(&id_token, &jwks["keys"][0])).unwrap();
+    assert_eq!(claims.iss, "https://127.0.0.1:8899");
+    assert_eq!(claims.aud, registration.client_id);
+    assert_eq!(
+        claims.sub,
+        Id::from_bytes(john_id.as_bytes())
+            .unwrap()
+            .document_id()
+            .to_string()
+    );
+    assert_eq!(claims.nonce.as_deref(), Some("n-0S6_WzA2Mj"));
+    assert_eq!(claims.email.as_deref(), Some("jdoe@example.com"));
+    assert_eq!(claims.name, None);
+
+    // Fetch user info using the access token
+    let userinfo: Userinfo = serde_json::from_slice(
+        &reqwest::Client::builder()
+            .timeout(Duration::from_millis(500))
+            .danger_accept_invalid_certs(true)
+            .build()
+            .unwrap_or_default()
+            .get(&oidc_metadata.userinfo_endpoint)
+            .bearer_auth(&token)
+            .send()
+            .await
+            .unwrap()
+            .bytes()
+            .await
+            .unwrap(),
+    )
+    .unwrap();
+    assert_eq!(userinfo.sub, claims.sub);
+    assert_eq!(userinfo.preferred_username, "jdoe@example.com");
+    assert_eq!(userinfo.name, "John Doe");
+    assert_eq!(userinfo.email.as_deref(), Some("jdoe@example.com"));
+    server
+        .store
+        .config_clear(format!("oauth.oidc.client.{}", registration.client_id))
+        .await
+        .unwrap();
+
     // ------------------------
     // Device code flow
     // ------------------------
 
+    // Unregistered clients cannot request the openid scope
+    assert!(String::from_utf8_lossy(
+        &post_bytes(
+            &metadata.device_authorization_endpoint,
+            &AHashMap::from_iter([
+                ("client_id".to_string(), "1234".to_string()),
+                ("scope".to_string(), "openid".to_string()),
+            ]),
+        )
+        .await
+    )
+    .contains("not registered"));
+
     // Request a device code
     let device_code_params = AHashMap::from_iter([("client_id".to_string(), "1234".to_string())]);
     let device_response: DeviceAuthResponse =
@@ -358,6 +521,46 @@ async fn post_expect_redirect(url: &str, params: &AHashMap<String, String>) -> S
         .to_string()
 }
 
+async fn post_json(
+    url: &str,
+    authorization: Option<&str>,
+    body: serde_json::Value,
+) -> (u16, serde_json::Value) {
+    let mut request = reqwest::Client::builder()
+        .timeout(Duration::from_millis(500))
+        .danger_accept_invalid_certs(true)
+        .build()
+        .unwrap_or_default()
+        .post(url)
+        .body(body.to_string());
+    if let Some(authorization) = authorization {
+        request = request.header("Authorization", authorization);
+    }
+    let response = request.send().await.unwrap();
+    (
+        response.status().as_u16(),
+        serde_json::from_slice(&response.bytes().await.unwrap()).unwrap(),
+    )
+}
+
+fn verify_id_token(token: &str, jwk: &serde_json::Value) -> Vec<u8> {
+    let decode = |value: &str| URL_SAFE_NO_PAD.decode(value).unwrap();
+    let (message, signature) = token.rsplit_once('.').unwrap();
+    let (_, payload) = message.split_once('.').unwrap();
+    let public_key = RsaPublicKey::new(
+        BigUint::from_bytes_be(&decode(jwk["n"].as_str().unwrap())),
+        BigUint::from_bytes_be(&decode(jwk["e"].as_str().unwrap())),
+    )
+    .unwrap();
+    VerifyingKey::<Sha256>::new(public_key)
+        .verify(
+            message.as_bytes(),
+            &Signature::try_from(decode(signature).as_slice()).unwrap(),
+        )
+        .expect("Invalid ID token signature");
+    decode(payload)
+}
+
 async fn get_bytes(url: &str) -> Bytes {
     reqwest::Client::builder()
         .timeout(Duration::from_millis(500))
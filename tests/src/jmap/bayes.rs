@@ -0,0 +1,145 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::time::Duration;
+
+use jmap::mailbox::{INBOX_ID, JUNK_ID};
+use jmap_proto::types::id::Id;
+use nlp::bayes::{TokenHash, Weights};
+use store::{
+    write::{key::KeySerializer, ValueClass},
+    LookupStore, ValueKey, U64_LEN,
+};
+
+use crate::jmap::{assert_is_empty, mailbox::destroy_all_mailboxes};
+
+use super::JMAPTest;
+
+pub async fn test(params: &mut JMAPTest) {
+    println!("Running Bayes training tests...");
+    let server = params.server.clone();
+    let client = &mut params.client;
+    client.set_default_account_id(Id::from(1u64));
+    let inbox_id = Id::from(INBOX_ID).to_string();
+    let junk_id = Id::from(JUNK_ID).to_string();
+
+    let email_id = client
+        .email_import(
+            concat!(
+                "From: bill@example.com\r\n",
+                "To: jdoe@example.com\r\n",
+                "Subject: Cheap watches and pills\r\n",
+                "\r\n",
+                "Buy cheap replica watches and pills today, limited offer!",
+            )
+            .as_bytes()
+            .to_vec(),
+            [&inbox_id],
+            None::<Vec<&str>>,
+            None,
+        )
+        .await
+        .unwrap()
+        .take_id();
+    expect_learns(&server, 0, 0).await;
+
+    // Moving a message to Junk trains it as spam
+    client
+        .email_set_mailboxes(&email_id, [&junk_id])
+        .await
+        .unwrap();
+    expect_learns(&server, 1, 0).await;
+
+    // Flagging it as $notjunk reverts the spam training and trains it as ham
+    client
+        .email_set_keyword(&email_id, "$notjunk", true)
+        .await
+        .unwrap();
+    expect_learns(&server, 0, 1).await;
+
+    // Changing the flag to $junk reverts the previous ham training
+    client
+        .email_set_keyword(&email_id, "$junk", true)
+        .await
+        .unwrap();
+    expect_learns(&server, 1, 0).await;
+
+    // Moving it back to the Inbox trains it as ham and reverts the $junk flag
+    client
+        .email_set_mailboxes(&email_id, [&inbox_id])
+        .await
+        .unwrap();
+    expect_learns(&server, 0, 1).await;
+
+    // Repeating an action or changing other keywords has no effect
+    for set in [false, true] {
+        client
+            .email_set_keyword(&email_id, "$notjunk", set)
+            .await
+            .unwrap();
+    }
+    expect_learns(&server, 0, 1).await;
+    client
+        .email_set_keyword(&email_id, "$seen", true)
+        .await
+        .unwrap();
+    expect_learns(&server, 0, 1).await;
+
+    destroy_all_mailboxes(params).await;
+    assert_is_empty(server).await;
+}
+
+// Training runs in the background, so wait for the counters to settle
+async fn expect_learns(server: &jmap::JMAP, spam: u32, ham: u32) {
+    let expected = Weights { spam, ham };
+    let mut current = learns(server).await;
+    for _ in 0..50 {
+        if current == expected {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        current = learns(server).await;
+    }
+    assert_eq!(current, expected);
+}
+
+async fn learns(server: &jmap::JMAP) -> Weights {
+    let key = KeySerializer::new(U64_LEN * 2)
+        .write(TokenHash::default().h1)
+        .write(TokenHash::default().h2)
+        .finalize();
+    match &server.smtp.load().queue.config.lookup_store {
+        LookupStore::Store(store) => Weights::from(
+            store
+                .get_counter(ValueKey {
+                    account_id: 0,
+                    collection: 0,
+                    document_id: 0,
+                    class: ValueClass::Key(key),
+                })
+                .await
+                .unwrap(),
+        ),
+        _ => unreachable!(),
+    }
+}
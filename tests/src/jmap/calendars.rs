@@ -0,0 +1,642 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::time::Duration;
+
+use base64::{engine::general_purpose, Engine};
+use directory::backend::internal::manage::ManageDirectory;
+use jmap::JMAP;
+use jmap_proto::types::id::Id;
+use reqwest::{header, Method, StatusCode};
+use serde_json::Value;
+use utils::ipc::{IngestMessage, MessageData};
+
+use crate::jmap::{assert_is_empty, jmap_json_request, mailbox::destroy_all_mailboxes};
+
+use super::JMAPTest;
+
+const USER: &str = "ellen@example.com";
+const SECRET: &str = "secret";
+
+pub async fn test(params: &mut JMAPTest) {
+    println!("Running Calendars tests...");
+    let server = params.server.clone();
+    params
+        .directory
+        .create_test_user_with_email(USER, SECRET, "Ellen Ripley")
+        .await;
+    let account_id =
+        Id::from(server.store.get_or_create_account_id(USER).await.unwrap()).to_string();
+
+    // The default calendar is created on first access
+    let response = request(
+        r#"[["Calendar/get", {"accountId": "$$"}, "0"]]"#,
+        &account_id,
+    )
+    .await;
+    assert_eq!(
+        response
+            .pointer("/methodResponses/0/1/list/0/name")
+            .and_then(|v| v.as_str()),
+        Some("Personal"),
+        "{response}"
+    );
+    assert_eq!(
+        response
+            .pointer("/methodResponses/0/1/list/0/isDefault")
+            .and_then(|v| v.as_bool()),
+        Some(true),
+        "{response}"
+    );
+    let default_id = pointer_str(&response, "/methodResponses/0/1/list/0/id");
+
+    // Create a second calendar
+    let response = request(
+        r##"[["Calendar/set", {"accountId": "$$", "create": {
+            "k1": {"name": "Work", "color": "#ff0000"}
+        }}, "0"]]"##,
+        &account_id,
+    )
+    .await;
+    let work_id = pointer_str(&response, "/methodResponses/0/1/created/k1/id");
+
+    // Create events
+    let response = request(
+        r#"[["CalendarEvent/set", {"accountId": "$$", "create": {
+            "e1": {
+                "@type": "Event",
+                "calendarIds": {"%%work%%": true},
+                "title": "Team meeting",
+                "start": "2024-03-01T10:00:00",
+                "duration": "PT1H",
+                "locations": {"l1": {"@type": "Location", "name": "Room 101"}}
+            },
+            "e2": {
+                "calendarIds": {"%%default%%": true},
+                "title": "Standup",
+                "start": "2024-01-01T09:00:00",
+                "duration": "PT15M",
+                "recurrenceRules": [{"@type": "RecurrenceRule", "frequency": "weekly"}]
+            },
+            "e3": {
+                "calendarIds": {"%%default%%": true},
+                "title": "Undated"
+            },
+            "e4": {
+                "title": "Nowhere",
+                "start": "2024-01-01T09:00:00"
+            }
+        }}, "0"]]"#
+            .replace("%%work%%", &work_id)
+            .replace("%%default%%", &default_id),
+        &account_id,
+    )
+    .await;
+    let meeting_id = pointer_str(&response, "/methodResponses/0/1/created/e1/id");
+    let standup_id = pointer_str(&response, "/methodResponses/0/1/created/e2/id");
+    assert!(
+        !pointer_str(&response, "/methodResponses/0/1/created/e1/uid").is_empty(),
+        "{response}"
+    );
+    for id in ["e3", "e4"] {
+        assert_eq!(
+            response
+                .pointer(&format!("/methodResponses/0/1/notCreated/{id}/type"))
+                .and_then(|v| v.as_str()),
+            Some("invalidProperties"),
+            "{response}"
+        );
+    }
+
+    // Query events
+    for (filter, expected) in [
+        (
+            format!("{{\"inCalendars\": [\"{work_id}\"]}}"),
+            vec![meeting_id.as_str()],
+        ),
+        (
+            "{\"title\": \"standup\"}".to_string(),
+            vec![standup_id.as_str()],
+        ),
+        (
+            "{\"text\": \"room\"}".to_string(),
+            vec![meeting_id.as_str()],
+        ),
+        (
+            "{\"after\": \"2024-06-01T00:00:00Z\"}".to_string(),
+            vec![standup_id.as_str()],
+        ),
+        (
+            "{\"before\": \"2024-02-01T00:00:00Z\"}".to_string(),
+            vec![standup_id.as_str()],
+        ),
+        (
+            "null".to_string(),
+            vec![standup_id.as_str(), meeting_id.as_str()],
+        ),
+    ] {
+        assert_eq!(query(&account_id, &filter).await, expected, "{filter}");
+    }
+
+    // Fetch and patch an event
+    let response = request(
+        r#"[["CalendarEvent/get", {"accountId": "$$", "ids": ["%%"]}, "0"]]"#
+            .replace("%%", &meeting_id),
+        &account_id,
+    )
+    .await;
+    assert_eq!(
+        response
+            .pointer("/methodResponses/0/1/list/0/locations/l1/name")
+            .and_then(|v| v.as_str()),
+        Some("Room 101"),
+        "{response}"
+    );
+    let state = pointer_str(&response, "/methodResponses/0/1/state");
+    let response = request(
+        r#"[["CalendarEvent/set", {"accountId": "$$", "update": {"%%": {
+            "title": "Quarterly meeting",
+            "locations/l1/name": "Room 202"
+        }}}, "0"]]"#
+            .replace("%%", &meeting_id),
+        &account_id,
+    )
+    .await;
+    assert!(
+        response
+            .pointer(&format!("/methodResponses/0/1/updated/{meeting_id}"))
+            .is_some(),
+        "{response}"
+    );
+    assert_eq!(
+        query(&account_id, "{\"title\": \"quarterly\"}").await,
+        vec![meeting_id.as_str()]
+    );
+    let response = request(
+        r#"[["CalendarEvent/changes", {"accountId": "$$", "sinceState": "%%"}, "0"]]"#
+            .replace("%%", &state),
+        &account_id,
+    )
+    .await;
+    assert_eq!(
+        response
+            .pointer("/methodResponses/0/1/updated/0")
+            .and_then(|v| v.as_str()),
+        Some(meeting_id.as_str()),
+        "{response}"
+    );
+
+    // CalDAV: list calendars
+    let (status, _, body) = dav(
+        "PROPFIND",
+        &format!("/dav/cal/{USER}/"),
+        &[("Depth", "1")],
+        concat!(
+            "<?xml version=\"1.0\" encoding=\"utf-8\"?>",
+            "<D:propfind xmlns:D=\"DAV:\"><D:prop>",
+            "<D:displayname/><D:resourcetype/>",
+            "</D:prop></D:propfind>"
+        ),
+    )
+    .await;
+    assert_eq!(status, StatusCode::MULTI_STATUS, "{body}");
+    assert!(
+        body.contains("<D:displayname>Personal</D:displayname>"),
+        "{body}"
+    );
+    assert!(
+        body.contains("<D:displayname>Work</D:displayname>"),
+        "{body}"
+    );
+    assert!(body.contains("<CAL:calendar/>"), "{body}");
+
+    // CalDAV: fetch an event created over JMAP
+    let (status, headers, body) = dav(
+        "GET",
+        &format!("/dav/cal/{USER}/default/{standup_id}.ics"),
+        &[],
+        "",
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK, "{body}");
+    assert!(headers.contains_key(header::ETAG));
+    assert!(body.contains("SUMMARY:Standup\r\n"), "{body}");
+    assert!(body.contains("RRULE:FREQ=WEEKLY"), "{body}");
+
+    // CalDAV: create an event
+    let ical = concat!(
+        "BEGIN:VCALENDAR\r\n",
+        "VERSION:2.0\r\n",
+        "PRODID:-//Test//EN\r\n",
+        "BEGIN:VEVENT\r\n",
+        "UID:dav-event-1\r\n",
+        "DTSTAMP:20240101T000000Z\r\n",
+        "DTSTART:20240415T090000Z\r\n",
+        "DURATION:PT30M\r\n",
+        "SUMMARY:Dentist\r\n",
+        "END:VEVENT\r\n",
+        "END:VCALENDAR\r\n"
+    );
+    let href = format!("/dav/cal/{USER}/default/dentist.ics");
+    let (status, headers, body) = dav("PUT", &href, &[("If-None-Match", "*")], ical).await;
+    assert_eq!(status, StatusCode::CREATED, "{body}");
+    let etag = headers
+        .get(header::ETAG)
+        .unwrap()
+        .to_str()
+        .unwrap()
+        .to_string();
+    let (status, _, _) = dav("PUT", &href, &[("If-None-Match", "*")], ical).await;
+    assert_eq!(status, StatusCode::PRECONDITION_FAILED);
+    let (status, _, _) = dav("PUT", &href, &[], "BEGIN:VCALENDAR\r\nEND:VCALENDAR\r\n").await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    // The new event is visible over JMAP
+    let response = request(
+        r##"[["CalendarEvent/query", {"accountId": "$$", "filter": {"uid": "dav-event-1"}}, "0"],
+            ["CalendarEvent/get", {"accountId": "$$", "#ids": {
+                "resultOf": "0", "name": "CalendarEvent/query", "path": "/ids"
+            }}, "1"]]"##,
+        &account_id,
+    )
+    .await;
+    assert_eq!(
+        response
+            .pointer("/methodResponses/1/1/list/0/title")
+            .and_then(|v| v.as_str()),
+        Some("Dentist"),
+        "{response}"
+    );
+
+    // CalDAV: multiget and time-range query reports
+    let (status, _, body) = dav(
+        "REPORT",
+        &format!("/dav/cal/{USER}/default/"),
+        &[],
+        &format!(
+            concat!(
+                "<?xml version=\"1.0\" encoding=\"utf-8\"?>",
+                "<C:calendar-multiget xmlns:D=\"DAV:\" ",
+                "xmlns:C=\"urn:ietf:params:xml:ns:caldav\">",
+                "<D:prop><D:getetag/><C:calendar-data/></D:prop>",
+                "<D:href>{}</D:href><D:href>{}</D:href>",
+                "</C:calendar-multiget>"
+            ),
+            href,
+            format_args!("/dav/cal/{USER}/default/missing.ics")
+        ),
+    )
+    .await;
+    assert_eq!(status, StatusCode::MULTI_STATUS, "{body}");
+    assert!(body.contains(&etag.replace('"', "&quot;")), "{body}");
+    assert!(body.contains("SUMMARY:Dentist"), "{body}");
+    assert!(body.contains("404 Not Found"), "{body}");
+
+    // The weekly standup repeats forever, the meeting is outside the range
+    for (calendar, expected, not_expected) in [
+        (
+            "default",
+            vec!["dentist.ics".to_string(), format!("{standup_id}.ics")],
+            vec![],
+        ),
+        (work_id.as_str(), vec![], vec![format!("{meeting_id}.ics")]),
+    ] {
+        let (status, _, body) = dav(
+            "REPORT",
+            &format!("/dav/cal/{USER}/{calendar}/"),
+            &[("Depth", "1")],
+            concat!(
+                "<?xml version=\"1.0\" encoding=\"utf-8\"?>",
+                "<C:calendar-query xmlns:D=\"DAV:\" ",
+                "xmlns:C=\"urn:ietf:params:xml:ns:caldav\">",
+                "<D:prop><D:getetag/></D:prop>",
+                "<C:filter><C:comp-filter name=\"VCALENDAR\">",
+                "<C:comp-filter name=\"VEVENT\">",
+                "<C:time-range start=\"20240415T000000Z\" end=\"20240416T000000Z\"/>",
+                "</C:comp-filter></C:comp-filter></C:filter>",
+                "</C:calendar-query>"
+            ),
+        )
+        .await;
+        assert_eq!(status, StatusCode::MULTI_STATUS, "{body}");
+        for href in expected {
+            assert!(body.contains(&href), "{body}");
+        }
+        for href in not_expected {
+            assert!(!body.contains(&href), "{body}");
+        }
+    }
+
+    // CalDAV: delete an event
+    let (status, _, _) = dav("DELETE", &href, &[("If-Match", &etag)], "").await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+    let (status, _, _) = dav("GET", &href, &[], "").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    // CalDAV: create and remove a calendar
+    let (status, _, _) = dav(
+        "MKCALENDAR",
+        &format!("/dav/cal/{USER}/holidays/"),
+        &[],
+        concat!(
+            "<?xml version=\"1.0\" encoding=\"utf-8\"?>",
+            "<C:mkcalendar xmlns:D=\"DAV:\" xmlns:C=\"urn:ietf:params:xml:ns:caldav\">",
+            "<D:set><D:prop><D:displayname>Holidays</D:displayname></D:prop></D:set>",
+            "</C:mkcalendar>"
+        ),
+    )
+    .await;
+    assert_eq!(status, StatusCode::CREATED);
+    let (status, _, body) = dav(
+        "PROPFIND",
+        &format!("/dav/cal/{USER}/holidays/"),
+        &[("Depth", "0")],
+        "",
+    )
+    .await;
+    assert_eq!(status, StatusCode::MULTI_STATUS, "{body}");
+    assert!(
+        body.contains("<D:displayname>Holidays</D:displayname>"),
+        "{body}"
+    );
+    let (status, _, _) = dav("DELETE", &format!("/dav/cal/{USER}/holidays/"), &[], "").await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+
+    // Other accounts cannot be accessed
+    let (status, _, _) = dav("PROPFIND", "/dav/cal/jdoe@example.com/", &[], "").await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    // Invitations are ignored unless the sender is authenticated and matches the From address
+    for (envelope_from, authenticated) in [("boss@remote.org", false), ("mallory@remote.org", true)]
+    {
+        deliver_imip(
+            &server,
+            envelope_from,
+            authenticated,
+            &imip_message("boss@remote.org", "REQUEST", 0, "Budget review"),
+        )
+        .await;
+    }
+    let response = request(
+        r#"[["CalendarEvent/query", {"accountId": "$$", "filter": {"uid": "imip-test-1"}}, "0"]]"#,
+        &account_id,
+    )
+    .await;
+    assert_eq!(
+        response
+            .pointer("/methodResponses/0/1/ids")
+            .and_then(|v| v.as_array())
+            .map(|ids| ids.len()),
+        Some(0),
+        "{response}"
+    );
+
+    // Invitations received by e-mail are added to the default calendar
+    deliver_imip(
+        &server,
+        "boss@remote.org",
+        true,
+        &imip_message("boss@remote.org", "REQUEST", 0, "Budget review"),
+    )
+    .await;
+    let response = request(
+        r##"[["CalendarEvent/query", {"accountId": "$$", "filter": {"uid": "imip-test-1"}}, "0"],
+            ["CalendarEvent/get", {"accountId": "$$", "#ids": {
+                "resultOf": "0", "name": "CalendarEvent/query", "path": "/ids"
+            }}, "1"]]"##,
+        &account_id,
+    )
+    .await;
+    assert_eq!(
+        response
+            .pointer("/methodResponses/1/1/list/0/title")
+            .and_then(|v| v.as_str()),
+        Some("Budget review"),
+        "{response}"
+    );
+    assert_eq!(
+        response
+            .pointer(&format!(
+                "/methodResponses/1/1/list/0/calendarIds/{default_id}"
+            ))
+            .and_then(|v| v.as_bool()),
+        Some(true),
+        "{response}"
+    );
+    let invitation_id = pointer_str(&response, "/methodResponses/1/1/list/0/id");
+
+    // Only the organizer of the stored event can update or cancel it, even if
+    // the message claims a different organizer
+    for method in ["REQUEST", "CANCEL"] {
+        deliver_imip(
+            &server,
+            "mallory@remote.org",
+            true,
+            &imip_message("mallory@remote.org", method, 5, "Budget review (hijacked)"),
+        )
+        .await;
+    }
+    assert_eq!(
+        event_field(&account_id, &invitation_id, "status").await,
+        None
+    );
+    assert_eq!(
+        event_field(&account_id, &invitation_id, "title").await,
+        Some("Budget review".to_string())
+    );
+    deliver_imip(
+        &server,
+        "boss@remote.org",
+        true,
+        &imip_message("boss@remote.org", "REQUEST", 1, "Budget review (moved)"),
+    )
+    .await;
+    assert_eq!(
+        event_field(&account_id, &invitation_id, "title").await,
+        Some("Budget review (moved)".to_string())
+    );
+    deliver_imip(
+        &server,
+        "boss@remote.org",
+        true,
+        &imip_message("boss@remote.org", "CANCEL", 2, "Budget review (moved)"),
+    )
+    .await;
+    assert_eq!(
+        event_field(&account_id, &invitation_id, "status").await,
+        Some("cancelled".to_string())
+    );
+
+    // Calendars with events can only be removed on request
+    let response = request(
+        r#"[["Calendar/set", {"accountId": "$$", "destroy": ["%%"]}, "0"]]"#
+            .replace("%%", &work_id),
+        &account_id,
+    )
+    .await;
+    assert_eq!(
+        response
+            .pointer(&format!("/methodResponses/0/1/notDestroyed/{work_id}/type"))
+            .and_then(|v| v.as_str()),
+        Some("calendarHasEvent"),
+        "{response}"
+    );
+    let response = request(
+        r#"[["Calendar/set", {"accountId": "$$", "destroy": ["%%work%%", "%%default%%"],
+            "onDestroyRemoveEvents": true}, "0"]]"#
+            .replace("%%work%%", &work_id)
+            .replace("%%default%%", &default_id),
+        &account_id,
+    )
+    .await;
+    assert_eq!(
+        response
+            .pointer("/methodResponses/0/1/destroyed")
+            .and_then(|v| v.as_array())
+            .map(|ids| ids.len()),
+        Some(2),
+        "{response}"
+    );
+    assert_eq!(query(&account_id, "null").await, Vec::<&str>::new());
+
+    // Remove the delivered messages
+    params.client.set_default_account_id(&account_id);
+    destroy_all_mailboxes(params).await;
+
+    assert_is_empty(server).await;
+}
+
+async fn deliver_imip(server: &JMAP, from: &str, authenticated: bool, message: &str) {
+    server
+        .deliver_message(IngestMessage {
+            sender_address: from.to_string(),
+            sender_authenticated: authenticated,
+            recipients: vec![USER.to_string()],
+            message_data: MessageData::from_bytes(message.as_bytes().to_vec()),
+        })
+        .await;
+}
+
+fn imip_message(from: &str, method: &str, sequence: u32, summary: &str) -> String {
+    format!(
+        concat!(
+            "From: {from}\r\n",
+            "To: {to}\r\n",
+            "Subject: {summary}\r\n",
+            "MIME-Version: 1.0\r\n",
+            "Content-Type: text/calendar; method={method}; charset=utf-8\r\n",
+            "\r\n",
+            "BEGIN:VCALENDAR\r\n",
+            "VERSION:2.0\r\n",
+            "PRODID:-//Test//EN\r\n",
+            "METHOD:{method}\r\n",
+            "BEGIN:VEVENT\r\n",
+            "UID:imip-test-1\r\n",
+            "SEQUENCE:{sequence}\r\n",
+            "DTSTAMP:20240101T000000Z\r\n",
+            "DTSTART:20240510T150000Z\r\n",
+            "DTEND:20240510T160000Z\r\n",
+            "SUMMARY:{summary}\r\n",
+            "ORGANIZER:mailto:{from}\r\n",
+            "ATTENDEE;PARTSTAT=NEEDS-ACTION;RSVP=TRUE:mailto:{to}\r\n",
+            "END:VEVENT\r\n",
+            "END:VCALENDAR\r\n"
+        ),
+        from = from,
+        to = USER,
+        method = method,
+        sequence = sequence,
+        summary = summary
+    )
+}
+
+async fn event_field(account_id: &str, id: &str, field: &str) -> Option<String> {
+    let response = request(
+        r#"[["CalendarEvent/get", {"accountId": "$$", "ids": ["%%"]}, "0"]]"#.replace("%%", id),
+        account_id,
+    )
+    .await;
+    response
+        .pointer(&format!("/methodResponses/0/1/list/0/{field}"))
+        .and_then(|v| v.as_str())
+        .map(|v| v.to_string())
+}
+
+async fn request(body: impl AsRef<str>, account_id: &str) -> Value {
+    jmap_json_request(body.as_ref().replace("$$", account_id), USER, SECRET).await
+}
+
+async fn query(account_id: &str, filter: &str) -> Vec<String> {
+    let response = request(
+        r#"[["CalendarEvent/query", {"accountId": "$$", "filter": %%}, "0"]]"#
+            .replace("%%", filter),
+        account_id,
+    )
+    .await;
+    response
+        .pointer("/methodResponses/0/1/ids")
+        .and_then(|v| v.as_array())
+        .unwrap_or_else(|| panic!("{response}"))
+        .iter()
+        .map(|id| id.as_str().unwrap().to_string())
+        .collect()
+}
+
+fn pointer_str(response: &Value, pointer: &str) -> String {
+    response
+        .pointer(pointer)
+        .and_then(|v| v.as_str())
+        .unwrap_or_else(|| panic!("Missing {pointer} in {response}"))
+        .to_string()
+}
+
+async fn dav(
+    method: &str,
+    path: &str,
+    headers: &[(&str, &str)],
+    body: &str,
+) -> (StatusCode, header::HeaderMap, String) {
+    let mut request = reqwest::Client::builder()
+        .danger_accept_invalid_certs(true)
+        .timeout(Duration::from_millis(1000))
+        .build()
+        .unwrap()
+        .request(
+            Method::from_bytes(method.as_bytes()).unwrap(),
+            format!("https://127.0.0.1:8899{path}"),
+        )
+        .header(
+            header::AUTHORIZATION,
+            format!(
+                "Basic {}",
+                general_purpose::STANDARD.encode(format!("{USER}:{SECRET}"))
+            ),
+        );
+    for (name, value) in headers {
+        request = request.header(*name, *value);
+    }
+    let response = request.body(body.to_string()).send().await.unwrap();
+    let status = response.status();
+    let headers = response.headers().clone();
+    (status, headers, response.text().await.unwrap())
+}
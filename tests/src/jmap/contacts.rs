@@ -0,0 +1,474 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::time::Duration;
+
+use base64::{engine::general_purpose, Engine};
+use directory::backend::internal::manage::ManageDirectory;
+use jmap_proto::types::id::Id;
+use reqwest::{header, Method, StatusCode};
+use serde_json::Value;
+
+use crate::jmap::{assert_is_empty, jmap_json_request};
+
+use super::JMAPTest;
+
+const USER: &str = "sarah@example.com";
+const SECRET: &str = "secret";
+
+pub async fn test(params: &mut JMAPTest) {
+    println!("Running Contacts tests...");
+    let server = params.server.clone();
+    params
+        .directory
+        .create_test_user_with_email(USER, SECRET, "Sarah Connor")
+        .await;
+    let account_id =
+        Id::from(server.store.get_or_create_account_id(USER).await.unwrap()).to_string();
+
+    // The default address book is created on first access
+    let response = request(
+        r#"[["AddressBook/get", {"accountId": "$$"}, "0"]]"#,
+        &account_id,
+    )
+    .await;
+    assert_eq!(
+        response
+            .pointer("/methodResponses/0/1/list/0/name")
+            .and_then(|v| v.as_str()),
+        Some("Personal"),
+        "{response}"
+    );
+    assert_eq!(
+        response
+            .pointer("/methodResponses/0/1/list/0/isDefault")
+            .and_then(|v| v.as_bool()),
+        Some(true),
+        "{response}"
+    );
+    let default_id = pointer_str(&response, "/methodResponses/0/1/list/0/id");
+
+    // Create a second address book
+    let response = request(
+        r#"[["AddressBook/set", {"accountId": "$$", "create": {
+            "b1": {"name": "Work", "description": "Colleagues"}
+        }}, "0"]]"#,
+        &account_id,
+    )
+    .await;
+    let work_id = pointer_str(&response, "/methodResponses/0/1/created/b1/id");
+
+    // Create contact cards
+    let response = request(
+        r#"[["ContactCard/set", {"accountId": "$$", "create": {
+            "c1": {
+                "@type": "Card",
+                "addressBookIds": {"%%work%%": true},
+                "name": {"full": "John Doe", "components": [
+                    {"kind": "given", "value": "John"},
+                    {"kind": "surname", "value": "Doe"}
+                ]},
+                "emails": {"e1": {"address": "john@example.com"}},
+                "phones": {"p1": {"number": "+1-555-0100"}}
+            },
+            "c2": {
+                "addressBookIds": {"%%default%%": true},
+                "name": {"full": "Jane Smith"},
+                "emails": {"e1": {"address": "jane@example.org"}},
+                "organizations": {"o1": {"name": "Acme"}}
+            },
+            "c3": {
+                "addressBookIds": {"%%default%%": true},
+                "kind": "group",
+                "name": {"full": "Friends"}
+            },
+            "c4": {
+                "name": {"full": "Nobody"}
+            }
+        }}, "0"]]"#
+            .replace("%%work%%", &work_id)
+            .replace("%%default%%", &default_id),
+        &account_id,
+    )
+    .await;
+    let john_id = pointer_str(&response, "/methodResponses/0/1/created/c1/id");
+    let jane_id = pointer_str(&response, "/methodResponses/0/1/created/c2/id");
+    let group_id = pointer_str(&response, "/methodResponses/0/1/created/c3/id");
+    assert!(
+        pointer_str(&response, "/methodResponses/0/1/created/c1/uid").starts_with("urn:uuid:"),
+        "{response}"
+    );
+    assert_eq!(
+        response
+            .pointer("/methodResponses/0/1/notCreated/c4/type")
+            .and_then(|v| v.as_str()),
+        Some("invalidProperties"),
+        "{response}"
+    );
+
+    // Query contact cards
+    for (filter, expected) in [
+        (
+            format!("{{\"inAddressBook\": \"{default_id}\"}}"),
+            vec![group_id.as_str(), jane_id.as_str()],
+        ),
+        ("{\"text\": \"smith\"}".to_string(), vec![jane_id.as_str()]),
+        (
+            "{\"email\": \"john@example.com\"}".to_string(),
+            vec![john_id.as_str()],
+        ),
+        ("{\"kind\": \"group\"}".to_string(), vec![group_id.as_str()]),
+        (
+            "null".to_string(),
+            vec![group_id.as_str(), jane_id.as_str(), john_id.as_str()],
+        ),
+    ] {
+        assert_eq!(query(&account_id, &filter).await, expected, "{filter}");
+    }
+
+    // Fetch a contact card
+    let response = request(
+        r#"[["ContactCard/get", {"accountId": "$$", "ids": ["%%"]}, "0"]]"#.replace("%%", &john_id),
+        &account_id,
+    )
+    .await;
+    assert_eq!(
+        response
+            .pointer("/methodResponses/0/1/list/0/name/full")
+            .and_then(|v| v.as_str()),
+        Some("John Doe"),
+        "{response}"
+    );
+    assert_eq!(
+        response
+            .pointer(&format!(
+                "/methodResponses/0/1/list/0/addressBookIds/{work_id}"
+            ))
+            .and_then(|v| v.as_bool()),
+        Some(true),
+        "{response}"
+    );
+    let state = pointer_str(&response, "/methodResponses/0/1/state");
+
+    // Patch a contact card
+    let response = request(
+        r#"[["ContactCard/set", {"accountId": "$$", "update": {"%%john%%": {
+            "name/full": "Johnny Doe",
+            "addressBookIds/%%default%%": true
+        }}}, "0"]]"#
+            .replace("%%john%%", &john_id)
+            .replace("%%default%%", &default_id),
+        &account_id,
+    )
+    .await;
+    assert!(
+        response
+            .pointer(&format!("/methodResponses/0/1/updated/{john_id}"))
+            .is_some(),
+        "{response}"
+    );
+    assert_eq!(
+        query(&account_id, "{\"text\": \"johnny\"}").await,
+        vec![john_id.as_str()]
+    );
+    assert_eq!(
+        query(
+            &account_id,
+            &format!("{{\"inAddressBook\": \"{default_id}\"}}")
+        )
+        .await,
+        vec![group_id.as_str(), jane_id.as_str(), john_id.as_str()]
+    );
+    let response = request(
+        r#"[["ContactCard/changes", {"accountId": "$$", "sinceState": "%%"}, "0"]]"#
+            .replace("%%", &state),
+        &account_id,
+    )
+    .await;
+    assert_eq!(
+        response
+            .pointer("/methodResponses/0/1/updated/0")
+            .and_then(|v| v.as_str()),
+        Some(john_id.as_str()),
+        "{response}"
+    );
+
+    // CardDAV: list address books
+    let (status, _, body) = dav(
+        "PROPFIND",
+        &format!("/dav/card/{USER}/"),
+        &[("Depth", "1")],
+        concat!(
+            "<?xml version=\"1.0\" encoding=\"utf-8\"?>",
+            "<D:propfind xmlns:D=\"DAV:\"><D:prop>",
+            "<D:displayname/><D:resourcetype/>",
+            "</D:prop></D:propfind>"
+        ),
+    )
+    .await;
+    assert_eq!(status, StatusCode::MULTI_STATUS, "{body}");
+    assert!(
+        body.contains("<D:displayname>Personal</D:displayname>"),
+        "{body}"
+    );
+    assert!(
+        body.contains("<D:displayname>Work</D:displayname>"),
+        "{body}"
+    );
+    assert!(body.contains("<C:addressbook/>"), "{body}");
+
+    // CardDAV: fetch a card created over JMAP
+    let (status, headers, body) = dav(
+        "GET",
+        &format!("/dav/card/{USER}/default/{jane_id}.vcf"),
+        &[],
+        "",
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK, "{body}");
+    assert!(headers.contains_key(header::ETAG));
+    assert!(body.contains("FN:Jane Smith\r\n"), "{body}");
+    assert!(body.contains("EMAIL"), "{body}");
+
+    // CardDAV: create a card
+    let vcard = concat!(
+        "BEGIN:VCARD\r\n",
+        "VERSION:3.0\r\n",
+        "UID:dav-test-1\r\n",
+        "FN:Kyle Reese\r\n",
+        "N:Reese;Kyle;;;\r\n",
+        "EMAIL;TYPE=WORK:kyle@example.net\r\n",
+        "END:VCARD\r\n"
+    );
+    let href = format!("/dav/card/{USER}/default/kyle.vcf");
+    let (status, headers, body) = dav("PUT", &href, &[("If-None-Match", "*")], vcard).await;
+    assert_eq!(status, StatusCode::CREATED, "{body}");
+    let etag = headers
+        .get(header::ETAG)
+        .unwrap()
+        .to_str()
+        .unwrap()
+        .to_string();
+    let (status, _, _) = dav("PUT", &href, &[("If-None-Match", "*")], vcard).await;
+    assert_eq!(status, StatusCode::PRECONDITION_FAILED);
+    let (status, _, _) = dav("PUT", &href, &[("If-Match", "\"1234\"")], vcard).await;
+    assert_eq!(status, StatusCode::PRECONDITION_FAILED);
+
+    // The new card is visible over JMAP
+    let response = request(
+        r##"[["ContactCard/query", {"accountId": "$$", "filter": {"uid": "dav-test-1"}}, "0"],
+            ["ContactCard/get", {"accountId": "$$", "#ids": {
+                "resultOf": "0", "name": "ContactCard/query", "path": "/ids"
+            }}, "1"]]"##,
+        &account_id,
+    )
+    .await;
+    assert_eq!(
+        response
+            .pointer("/methodResponses/1/1/list/0/name/full")
+            .and_then(|v| v.as_str()),
+        Some("Kyle Reese"),
+        "{response}"
+    );
+    let kyle_id = pointer_str(&response, "/methodResponses/1/1/list/0/id");
+
+    // CardDAV: multiget and query reports
+    let (status, _, body) = dav(
+        "REPORT",
+        &format!("/dav/card/{USER}/default/"),
+        &[],
+        &format!(
+            concat!(
+                "<?xml version=\"1.0\" encoding=\"utf-8\"?>",
+                "<C:addressbook-multiget xmlns:D=\"DAV:\" ",
+                "xmlns:C=\"urn:ietf:params:xml:ns:carddav\">",
+                "<D:prop><D:getetag/><C:address-data/></D:prop>",
+                "<D:href>{}</D:href><D:href>{}</D:href>",
+                "</C:addressbook-multiget>"
+            ),
+            href,
+            format_args!("/dav/card/{USER}/default/missing.vcf")
+        ),
+    )
+    .await;
+    assert_eq!(status, StatusCode::MULTI_STATUS, "{body}");
+    assert!(body.contains(&etag.replace('"', "&quot;")), "{body}");
+    assert!(body.contains("FN:Kyle Reese"), "{body}");
+    assert!(body.contains("404 Not Found"), "{body}");
+
+    let (status, _, body) = dav(
+        "REPORT",
+        &format!("/dav/card/{USER}/default/"),
+        &[("Depth", "1")],
+        concat!(
+            "<?xml version=\"1.0\" encoding=\"utf-8\"?>",
+            "<C:addressbook-query xmlns:D=\"DAV:\" ",
+            "xmlns:C=\"urn:ietf:params:xml:ns:carddav\">",
+            "<D:prop><D:getetag/></D:prop>",
+            "<C:filter><C:prop-filter name=\"FN\">",
+            "<C:text-match collation=\"i;unicode-casemap\" ",
+            "match-type=\"contains\">smith</C:text-match>",
+            "</C:prop-filter></C:filter>",
+            "</C:addressbook-query>"
+        ),
+    )
+    .await;
+    assert_eq!(status, StatusCode::MULTI_STATUS, "{body}");
+    assert!(body.contains(&format!("{jane_id}.vcf")), "{body}");
+    assert!(!body.contains("kyle.vcf"), "{body}");
+
+    // CardDAV: delete a card
+    let (status, _, _) = dav("DELETE", &href, &[("If-Match", &etag)], "").await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+    let (status, _, _) = dav("GET", &href, &[], "").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert_eq!(
+        query(&account_id, "{\"uid\": \"dav-test-1\"}").await,
+        Vec::<&str>::new()
+    );
+    assert!(!kyle_id.is_empty());
+
+    // CardDAV: create and remove an address book
+    let (status, _, _) = dav("MKCOL", &format!("/dav/card/{USER}/family/"), &[], "").await;
+    assert_eq!(status, StatusCode::CREATED);
+    let (status, _, _) = dav("MKCOL", &format!("/dav/card/{USER}/family/"), &[], "").await;
+    assert_eq!(status, StatusCode::METHOD_NOT_ALLOWED);
+    let (status, _, _) = dav("DELETE", &format!("/dav/card/{USER}/family/"), &[], "").await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+
+    // Other accounts cannot be accessed
+    let (status, _, _) = dav("PROPFIND", "/dav/card/jdoe@example.com/", &[], "").await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    // Address books with contents can only be removed on request
+    let response = request(
+        r#"[["AddressBook/set", {"accountId": "$$", "destroy": ["%%"]}, "0"]]"#
+            .replace("%%", &work_id),
+        &account_id,
+    )
+    .await;
+    assert_eq!(
+        response
+            .pointer(&format!("/methodResponses/0/1/notDestroyed/{work_id}/type"))
+            .and_then(|v| v.as_str()),
+        Some("addressBookHasContents"),
+        "{response}"
+    );
+    let response = request(
+        r#"[["AddressBook/set", {"accountId": "$$", "destroy": ["%%"],
+            "onDestroyRemoveContents": true}, "0"]]"#
+            .replace("%%", &work_id),
+        &account_id,
+    )
+    .await;
+    assert_eq!(
+        response
+            .pointer("/methodResponses/0/1/destroyed/0")
+            .and_then(|v| v.as_str()),
+        Some(work_id.as_str()),
+        "{response}"
+    );
+
+    // John Doe was also in the default address book
+    assert_eq!(
+        query(&account_id, "null").await,
+        vec![group_id.as_str(), jane_id.as_str(), john_id.as_str()]
+    );
+
+    // Remove the default address book and its contents
+    let response = request(
+        r#"[["AddressBook/set", {"accountId": "$$", "destroy": ["%%"],
+            "onDestroyRemoveContents": true}, "0"]]"#
+            .replace("%%", &default_id),
+        &account_id,
+    )
+    .await;
+    assert_eq!(
+        response
+            .pointer("/methodResponses/0/1/destroyed/0")
+            .and_then(|v| v.as_str()),
+        Some(default_id.as_str()),
+        "{response}"
+    );
+    assert_eq!(query(&account_id, "null").await, Vec::<&str>::new());
+
+    assert_is_empty(server).await;
+}
+
+async fn request(body: impl AsRef<str>, account_id: &str) -> Value {
+    jmap_json_request(body.as_ref().replace("$$", account_id), USER, SECRET).await
+}
+
+async fn query(account_id: &str, filter: &str) -> Vec<String> {
+    let response = request(
+        r#"[["ContactCard/query", {"accountId": "$$", "filter": %%}, "0"]]"#.replace("%%", filter),
+        account_id,
+    )
+    .await;
+    response
+        .pointer("/methodResponses/0/1/ids")
+        .and_then(|v| v.as_array())
+        .unwrap_or_else(|| panic!("{response}"))
+        .iter()
+        .map(|id| id.as_str().unwrap().to_string())
+        .collect()
+}
+
+fn pointer_str(response: &Value, pointer: &str) -> String {
+    response
+        .pointer(pointer)
+        .and_then(|v| v.as_str())
+        .unwrap_or_else(|| panic!("Missing {pointer} in {response}"))
+        .to_string()
+}
+
+async fn dav(
+    method: &str,
+    path: &str,
+    headers: &[(&str, &str)],
+    body: &str,
+) -> (StatusCode, header::HeaderMap, String) {
+    let mut request = reqwest::Client::builder()
+        .danger_accept_invalid_certs(true)
+        .timeout(Duration::from_millis(1000))
+        .build()
+        .unwrap()
+        .request(
+            Method::from_bytes(method.as_bytes()).unwrap(),
+            format!("https://127.0.0.1:8899{path}"),
+        )
+        .header(
+            header::AUTHORIZATION,
+            format!(
+                "Basic {}",
+                general_purpose::STANDARD.encode(format!("{USER}:{SECRET}"))
+            ),
+        );
+    for (name, value) in headers {
+        request = request.header(*name, *value);
+    }
+    let response = request.body(body.to_string()).send().await.unwrap();
+    let status = response.status();
+    let headers = response.headers().clone();
+    (status, headers, response.text().await.unwrap())
+}
@@ -0,0 +1,250 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::time::{Duration, Instant};
+
+use base64::{engine::general_purpose, Engine};
+use mail_auth::{
+    common::{headers::HeaderWriter, parse::TxtRecordParser, verify::DomainKey},
+    AuthenticatedMessage, DkimResult,
+};
+use reqwest::{header, Method, StatusCode};
+use serde_json::Value;
+use smtp::core::dkim::{KeyState, ManagedKey};
+use store::write::now;
+
+use super::JMAPTest;
+
+const DAY: u64 = 86400;
+
+pub async fn test(params: &mut JMAPTest) {
+    println!("Running DKIM key management tests...");
+    let smtp = params.server.smtp.load_full();
+
+    // The first key of each algorithm is used for signing right away
+    let (status, response) = admin(
+        Method::POST,
+        "/admin/domain/example.org/dkim",
+        r#"{"algorithm": "ed25519-sha256"}"#,
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK, "{response}");
+    let ed_key = serde_json::from_value::<ManagedKey>(response["data"].clone()).unwrap();
+    assert_eq!(ed_key.state, KeyState::Active);
+    assert_eq!(ed_key.algorithm, "ed25519-sha256");
+    assert_eq!(
+        ed_key.dns_name,
+        format!("{}._domainkey.example.org", ed_key.selector)
+    );
+    assert!(
+        ed_key.dns_record.starts_with("v=DKIM1; k=ed25519; p="),
+        "{}",
+        ed_key.dns_record
+    );
+    let (status, response) = admin(Method::POST, "/admin/domain/example.org/dkim", "").await;
+    assert_eq!(status, StatusCode::OK, "{response}");
+    let rsa_key = serde_json::from_value::<ManagedKey>(response["data"].clone()).unwrap();
+    assert_eq!(rsa_key.state, KeyState::Active);
+    assert_eq!(rsa_key.algorithm, "rsa-sha256");
+    assert!(
+        rsa_key.dns_record.starts_with("v=DKIM1; k=rsa; p="),
+        "{}",
+        rsa_key.dns_record
+    );
+    let (status, _) = admin(
+        Method::POST,
+        "/admin/domain/example.org/dkim",
+        r#"{"algorithm": "rsa-sha1"}"#,
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    // Messages from the domain are signed with both keys
+    assert_signed(&smtp, &[&ed_key, &rsa_key]).await;
+    assert!(smtp.dkim.signers("example.com").is_empty());
+
+    // Generating another key publishes it without switching signing
+    let (status, response) = admin(
+        Method::POST,
+        "/admin/domain/example.org/dkim",
+        r#"{"algorithm": "ed25519-sha256"}"#,
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK, "{response}");
+    let new_ed_key = serde_json::from_value::<ManagedKey>(response["data"].clone()).unwrap();
+    assert_eq!(new_ed_key.state, KeyState::Published);
+    assert_ne!(new_ed_key.selector, ed_key.selector);
+    assert_eq!(
+        dkim_keys(KeyState::Published).await,
+        vec![new_ed_key.id.clone()]
+    );
+    assert_signed(&smtp, &[&ed_key, &rsa_key]).await;
+
+    // Signing switches once the grace period is over
+    let start = now();
+    smtp.rotate_dkim_keys(start + DAY).await.unwrap();
+    assert_eq!(
+        dkim_keys(KeyState::Published).await,
+        vec![new_ed_key.id.clone()]
+    );
+    smtp.rotate_dkim_keys(start + 2 * DAY).await.unwrap();
+    assert_eq!(
+        dkim_keys(KeyState::Published).await,
+        vec![new_ed_key.id.clone()]
+    );
+    assert_signed(&smtp, &[&ed_key, &rsa_key]).await;
+
+    // Keys are only promoted once their DNS record resolves
+    publish_key(&smtp, &new_ed_key);
+    smtp.rotate_dkim_keys(start + 2 * DAY).await.unwrap();
+    assert_eq!(dkim_keys(KeyState::Published).await, Vec::<String>::new());
+    assert_eq!(dkim_keys(KeyState::Retired).await, vec![ed_key.id.clone()]);
+    let mut active = dkim_keys(KeyState::Active).await;
+    active.sort_unstable();
+    let mut expected = vec![new_ed_key.id.clone(), rsa_key.id.clone()];
+    expected.sort_unstable();
+    assert_eq!(active, expected);
+    assert_signed(&smtp, &[&new_ed_key, &rsa_key]).await;
+
+    // Retired keys are removed after the retire period
+    smtp.rotate_dkim_keys(start + 8 * DAY).await.unwrap();
+    assert_eq!(dkim_keys(KeyState::Retired).await, vec![ed_key.id.clone()]);
+    smtp.rotate_dkim_keys(start + 9 * DAY).await.unwrap();
+    assert_eq!(dkim_keys(KeyState::Retired).await, Vec::<String>::new());
+
+    // Expired keys are rotated automatically
+    smtp.rotate_dkim_keys(start + 31 * DAY).await.unwrap();
+    let published = smtp
+        .dkim_keys("example.org".into())
+        .await
+        .unwrap()
+        .into_iter()
+        .filter(|key| key.state == KeyState::Published)
+        .collect::<Vec<_>>();
+    assert_eq!(published.len(), 1, "{published:?}");
+    assert_eq!(published[0].algorithm, "rsa-sha256");
+    publish_key(&smtp, &published[0]);
+    smtp.rotate_dkim_keys(start + 34 * DAY).await.unwrap();
+    assert_eq!(dkim_keys(KeyState::Retired).await, vec![rsa_key.id.clone()]);
+    assert_signed(&smtp, &[&new_ed_key, &published[0]]).await;
+    let published = smtp
+        .dkim_keys("example.org".into())
+        .await
+        .unwrap()
+        .into_iter()
+        .filter(|key| key.state == KeyState::Published)
+        .collect::<Vec<_>>();
+    assert_eq!(published.len(), 1, "{published:?}");
+    assert_eq!(published[0].algorithm, "ed25519-sha256");
+
+    // List and delete keys
+    let (status, response) = admin(Method::GET, "/admin/domain/example.org/dkim", "").await;
+    assert_eq!(status, StatusCode::OK);
+    let keys = serde_json::from_value::<Vec<ManagedKey>>(response["data"].clone()).unwrap();
+    assert_eq!(keys.len(), 4, "{keys:?}");
+    for key in keys {
+        let path = format!("/admin/domain/example.org/dkim/{}", key.selector);
+        let (status, _) = admin(Method::DELETE, &path, "").await;
+        assert_eq!(status, StatusCode::OK);
+        let (status, _) = admin(Method::DELETE, &path, "").await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+    assert!(smtp.dkim_keys(None).await.unwrap().is_empty());
+    assert!(smtp.dkim.signers("example.org").is_empty());
+
+    async fn dkim_keys(state: KeyState) -> Vec<String> {
+        let (_, response) = admin(Method::GET, "/admin/domain/example.org/dkim", "").await;
+        serde_json::from_value::<Vec<ManagedKey>>(response["data"].clone())
+            .unwrap()
+            .into_iter()
+            .filter(|key| key.state == state)
+            .map(|key| key.id)
+            .collect()
+    }
+}
+
+async fn assert_signed(smtp: &smtp::core::SMTP, keys: &[&ManagedKey]) {
+    let message = concat!(
+        "From: John Doe <john@example.org>\r\n",
+        "To: jane@example.com\r\n",
+        "Subject: Rotation test\r\n",
+        "\r\n",
+        "Hello world\r\n"
+    );
+
+    for key in keys {
+        publish_key(smtp, key);
+    }
+
+    let signers = smtp.dkim.signers("example.org");
+    assert_eq!(signers.len(), keys.len());
+    let mut signed = String::new();
+    for signer in signers {
+        signed.push_str(&signer.sign(message.as_bytes()).unwrap().to_header());
+    }
+    for key in keys {
+        assert!(
+            signed.contains(&format!("s={};", key.selector)),
+            "{} not in {signed}",
+            key.selector
+        );
+    }
+    signed.push_str(message);
+
+    let message = AuthenticatedMessage::parse(signed.as_bytes()).unwrap();
+    let output = smtp.resolvers.dns.verify_dkim(&message).await;
+    assert_eq!(output.len(), keys.len());
+    for output in output {
+        assert_eq!(output.result(), &DkimResult::Pass, "{signed}");
+    }
+}
+
+fn publish_key(smtp: &smtp::core::SMTP, key: &ManagedKey) {
+    smtp.resolvers.dns.txt_add(
+        key.dns_name.as_str(),
+        DomainKey::parse(key.dns_record.as_bytes()).unwrap(),
+        Instant::now() + Duration::from_secs(5),
+    );
+}
+
+async fn admin(method: Method, path: &str, body: &str) -> (StatusCode, Value) {
+    let response = reqwest::Client::builder()
+        .danger_accept_invalid_certs(true)
+        .timeout(Duration::from_secs(60))
+        .build()
+        .unwrap()
+        .request(method, format!("https://127.0.0.1:8899{path}"))
+        .header(
+            header::AUTHORIZATION,
+            format!("Basic {}", general_purpose::STANDARD.encode("admin:secret")),
+        )
+        .body(body.to_string())
+        .send()
+        .await
+        .unwrap();
+    let status = response.status();
+    (
+        status,
+        serde_json::from_str(&response.text().await.unwrap()).unwrap_or_default(),
+    )
+}
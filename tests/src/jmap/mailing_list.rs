@@ -0,0 +1,414 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::time::{Duration, Instant};
+
+use directory::{
+    backend::internal::manage::ManageDirectory,
+    core::mailing_list::{FromRewrite, HeldMessage, MailingList, MailingListStore, PostingPolicy},
+    Principal, QueryBy, Type,
+};
+use jmap::JMAP;
+use jmap_proto::types::collection::Collection;
+use store::BlobHash;
+use utils::ipc::{DeliveryResult, IngestMessage, MessageData};
+
+use crate::jmap::{
+    assert_is_empty,
+    email_submission::{expect_message_delivery, expect_nothing, spawn_mock_smtp_server},
+};
+
+use super::JMAPTest;
+
+pub async fn test(params: &mut JMAPTest) {
+    println!("Running mailing list tests...");
+    let server = params.server.clone();
+    let (mut smtp_rx, smtp_settings) = spawn_mock_smtp_server();
+    server.smtp.load().resolvers.dns.ipv4_add(
+        "localhost",
+        vec!["127.0.0.1".parse().unwrap()],
+        Instant::now() + Duration::from_secs(10),
+    );
+
+    // Create a moderated list
+    server.store.create_domain("example.com").await.unwrap();
+    let list_id = server
+        .store
+        .create_account(Principal {
+            typ: Type::List,
+            name: "announce".to_string(),
+            emails: vec!["announce@example.com".to_string()],
+            description: Some("Announcements".to_string()),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+    server
+        .store
+        .set_mailing_list(
+            list_id,
+            MailingList {
+                policy: PostingPolicy::Moderated,
+                moderators: vec!["mod@remote.org".to_string()],
+                rewrite_from: FromRewrite::Always,
+                subject_prefix: Some("[announce]".to_string()),
+                archive: true,
+            }
+            .into(),
+        )
+        .await
+        .unwrap();
+
+    // Subscribing requires confirmation
+    assert!(matches!(
+        deliver(
+            &server,
+            "jane@remote.org",
+            "announce+subscribe@example.com",
+            ""
+        )
+        .await,
+        DeliveryResult::Success
+    ));
+    let message = expect_message_delivery(&mut smtp_rx).await;
+    assert_eq!(message.mail_from, "<announce+bounces@example.com>");
+    assert_eq!(message.rcpt_to, vec!["<jane@remote.org>".to_string()]);
+    let confirm_address = message
+        .message
+        .split_once("<announce+confirm-")
+        .and_then(|(_, token)| token.split_once('>'))
+        .map(|(token, _)| format!("announce+confirm-{token}"))
+        .unwrap();
+    assert!(server
+        .store
+        .list_subscribers(list_id)
+        .await
+        .unwrap()
+        .is_empty());
+    assert!(matches!(
+        deliver(&server, "john@remote.org", &confirm_address, "").await,
+        DeliveryResult::PermanentFailure { .. }
+    ));
+    let (expires, mac) = confirm_address
+        .strip_prefix("announce+confirm-")
+        .and_then(|token| token.split_once('-'))
+        .unwrap();
+    assert!(matches!(
+        deliver(
+            &server,
+            "jane@remote.org",
+            &format!(
+                "announce+confirm-{}-{mac}",
+                expires.parse::<u64>().unwrap() + 86400
+            ),
+            ""
+        )
+        .await,
+        DeliveryResult::PermanentFailure { .. }
+    ));
+    assert!(matches!(
+        deliver(&server, "jane@remote.org", &confirm_address, "").await,
+        DeliveryResult::Success
+    ));
+    assert_eq!(
+        server.store.list_subscribers(list_id).await.unwrap(),
+        vec!["jane@remote.org".to_string()]
+    );
+
+    // Posts from non-moderators are held for approval
+    assert!(matches!(
+        deliver(&server, "john@remote.org", "announce@example.com", POST).await,
+        DeliveryResult::Success
+    ));
+    let message = expect_message_delivery(&mut smtp_rx).await;
+    assert_eq!(message.rcpt_to, vec!["<mod@remote.org>".to_string()]);
+    assert!(message.message.contains("awaiting moderation"));
+    let held = server.store.list_held_messages(list_id).await.unwrap();
+    assert_eq!(held.len(), 1);
+    assert_eq!(held[0].sender, "john@remote.org");
+    assert_eq!(held[0].subject, "Release notes");
+
+    // Only authenticated moderators can approve messages using the token they received
+    let approve_address = message
+        .message
+        .split_once("<announce+approve-")
+        .and_then(|(_, token)| token.split_once('>'))
+        .map(|(token, _)| format!("announce+approve-{token}"))
+        .unwrap();
+    for (from, authenticated, rcpt) in [
+        ("john@remote.org", true, approve_address.as_str()),
+        ("mod@remote.org", false, approve_address.as_str()),
+        (
+            "mod@remote.org",
+            true,
+            &format!("announce+approve-{}@example.com", held[0].id),
+        ),
+    ] {
+        assert!(
+            matches!(
+                deliver_as(&server, from, authenticated, rcpt, "").await,
+                DeliveryResult::PermanentFailure { .. }
+            ),
+            "{from} {rcpt}"
+        );
+    }
+    assert!(matches!(
+        deliver(&server, "mod@remote.org", &approve_address, "").await,
+        DeliveryResult::Success
+    ));
+    assert!(server
+        .store
+        .list_held_messages(list_id)
+        .await
+        .unwrap()
+        .is_empty());
+    assert_eq!(
+        server
+            .store
+            .take_held_message(list_id, held[0].id)
+            .await
+            .unwrap(),
+        None
+    );
+
+    // Expired held messages are purged
+    server
+        .store
+        .hold_message(
+            list_id,
+            HeldMessage {
+                id: held[0].id + 1,
+                sender: "john@remote.org".to_string(),
+                subject: "Expired".to_string(),
+                blob_hash: BlobHash::default(),
+                size: 0,
+                received: 0,
+                expires: 1,
+            },
+        )
+        .await
+        .unwrap();
+    assert_eq!(server.store.purge_held_messages().await.unwrap(), 1);
+    assert_eq!(server.store.purge_held_messages().await.unwrap(), 0);
+
+    // Approved messages are distributed with list headers
+    let message = expect_message_delivery(&mut smtp_rx).await;
+    assert_eq!(message.mail_from, "<announce+bounces@example.com>");
+    assert_eq!(message.rcpt_to, vec!["<jane@remote.org>".to_string()]);
+    for needle in [
+        "List-Id: Announcements <announce.example.com>",
+        "List-Post: <mailto:announce@example.com>",
+        "List-Unsubscribe: <mailto:announce+unsubscribe@example.com>",
+        "List-Unsubscribe-Post: List-Unsubscribe=One-Click",
+        "Precedence: list",
+        "From: \"John Doe via Announcements\" <announce@example.com>",
+        "Reply-To: \"John Doe\" <john@remote.org>",
+        "Subject: [announce] Release notes",
+        "Version 1.0 is out.",
+    ] {
+        assert!(
+            message.message.contains(needle),
+            "{needle:?} not found in {}",
+            message.message
+        );
+    }
+    assert!(!message.message.contains("List-Id: <other.example.org>"));
+    assert_eq!(
+        server
+            .get_document_ids(list_id, Collection::Email)
+            .await
+            .unwrap()
+            .map_or(0, |ids| ids.len()),
+        1
+    );
+
+    // Messages that went through the list are not distributed again
+    assert!(matches!(
+        deliver(
+            &server,
+            "mod@remote.org",
+            "announce@example.com",
+            &message.message
+        )
+        .await,
+        DeliveryResult::Success
+    ));
+    expect_nothing(&mut smtp_rx).await;
+
+    // Announce-only lists reject posts from non-moderators
+    let mut list = server
+        .store
+        .get_mailing_list(list_id)
+        .await
+        .unwrap()
+        .unwrap();
+    list.policy = PostingPolicy::AnnounceOnly;
+    server
+        .store
+        .set_mailing_list(list_id, Some(list))
+        .await
+        .unwrap();
+    for (from, authenticated) in [("john@remote.org", true), ("mod@remote.org", false)] {
+        assert!(matches!(
+            deliver_as(&server, from, authenticated, "announce@example.com", POST).await,
+            DeliveryResult::PermanentFailure {
+                code: [5, 7, 1],
+                ..
+            }
+        ));
+    }
+
+    // One-click unsubscribe
+    let unsubscribe_url = message
+        .message
+        .split_once("<https://")
+        .and_then(|(_, url)| url.split_once('>'))
+        .map(|(url, _)| format!("https://{url}"))
+        .unwrap();
+    let client = reqwest::Client::builder()
+        .timeout(Duration::from_millis(500))
+        .danger_accept_invalid_certs(true)
+        .build()
+        .unwrap_or_default();
+    let response = client.get(&unsubscribe_url).send().await.unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(response.text().await.unwrap().contains("jane@remote.org"));
+    assert_eq!(
+        server.store.list_subscribers(list_id).await.unwrap().len(),
+        1
+    );
+    let response = client
+        .post(format!("{unsubscribe_url}x"))
+        .header("Content-Type", "application/x-www-form-urlencoded")
+        .body("List-Unsubscribe=One-Click")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 400);
+    let response = client
+        .post(&unsubscribe_url)
+        .header("Content-Type", "application/x-www-form-urlencoded")
+        .body("List-Unsubscribe=One-Click")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(server
+        .store
+        .list_subscribers(list_id)
+        .await
+        .unwrap()
+        .is_empty());
+
+    // Unsubscribing by e-mail command requires confirmation
+    server
+        .store
+        .add_subscriber(list_id, "Bill@remote.org")
+        .await
+        .unwrap();
+    assert!(matches!(
+        deliver(
+            &server,
+            "bill@remote.org",
+            "announce+unsubscribe@example.com",
+            ""
+        )
+        .await,
+        DeliveryResult::Success
+    ));
+    let message = expect_message_delivery(&mut smtp_rx).await;
+    assert_eq!(message.rcpt_to, vec!["<bill@remote.org>".to_string()]);
+    let confirm_address = message
+        .message
+        .split_once("<announce+unsubscribe-")
+        .and_then(|(_, token)| token.split_once('>'))
+        .map(|(token, _)| format!("announce+unsubscribe-{token}"))
+        .unwrap();
+    assert_eq!(
+        server.store.list_subscribers(list_id).await.unwrap(),
+        vec!["bill@remote.org".to_string()]
+    );
+    assert!(matches!(
+        deliver(&server, "john@remote.org", &confirm_address, "").await,
+        DeliveryResult::PermanentFailure { .. }
+    ));
+    smtp_settings.lock().do_stop = true;
+    assert!(matches!(
+        deliver(&server, "bill@remote.org", &confirm_address, "").await,
+        DeliveryResult::Success
+    ));
+    let message = expect_message_delivery(&mut smtp_rx).await;
+    assert_eq!(message.rcpt_to, vec!["<bill@remote.org>".to_string()]);
+    assert!(message.message.contains("has been removed"));
+    assert!(server
+        .store
+        .list_subscribers(list_id)
+        .await
+        .unwrap()
+        .is_empty());
+
+    // Deleting the list removes its archive and settings
+    server
+        .store
+        .delete_account(QueryBy::Id(list_id))
+        .await
+        .unwrap();
+    server.store.delete_domain("example.com").await.unwrap();
+    assert_is_empty(server).await;
+}
+
+async fn deliver(server: &JMAP, from: &str, rcpt: &str, message: &str) -> DeliveryResult {
+    deliver_as(server, from, true, rcpt, message).await
+}
+
+async fn deliver_as(
+    server: &JMAP,
+    from: &str,
+    authenticated: bool,
+    rcpt: &str,
+    message: &str,
+) -> DeliveryResult {
+    server
+        .deliver_message(IngestMessage {
+            sender_address: from.to_string(),
+            sender_authenticated: authenticated,
+            recipients: vec![rcpt.to_string()],
+            message_data: MessageData::from_bytes(if message.is_empty() {
+                format!("From: {from}\r\nTo: {rcpt}\r\nSubject: command\r\n\r\n").into_bytes()
+            } else {
+                message.as_bytes().to_vec()
+            }),
+        })
+        .await
+        .pop()
+        .unwrap()
+}
+
+const POST: &str = concat!(
+    "From: \"John Doe\" <john@remote.org>\r\n",
+    "To: announce@example.com\r\n",
+    "List-Id: <other.example.org>\r\n",
+    "Subject: Release notes\r\n",
+    "\r\n",
+    "Version 1.0 is out.\r\n"
+);
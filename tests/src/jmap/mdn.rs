@@ -0,0 +1,386 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::time::{Duration, Instant};
+
+use directory::backend::internal::manage::ManageDirectory;
+use jmap_client::{email, mailbox::Role};
+use jmap_proto::types::id::Id;
+use serde_json::Value;
+
+use crate::jmap::{
+    assert_is_empty,
+    email_submission::{expect_message_delivery, spawn_mock_smtp_server},
+    jmap_json_request,
+    mailbox::destroy_all_mailboxes,
+};
+
+use super::JMAPTest;
+
+pub async fn test(params: &mut JMAPTest) {
+    println!("Running MDN tests...");
+    let server = params.server.clone();
+    let (mut smtp_rx, smtp_settings) = spawn_mock_smtp_server();
+    server.smtp.load().resolvers.dns.ipv4_add(
+        "localhost",
+        vec!["127.0.0.1".parse().unwrap()],
+        Instant::now() + Duration::from_secs(10),
+    );
+
+    // Create test account, identity and mailbox
+    params
+        .directory
+        .create_test_user_with_email("jdoe@example.com", "12345", "John Doe")
+        .await;
+    let account_id = Id::from(
+        server
+            .store
+            .get_or_create_account_id("jdoe@example.com")
+            .await
+            .unwrap(),
+    )
+    .to_string();
+    let client = &mut params.client;
+    client.set_default_account_id(&account_id);
+    let identity_id = client
+        .identity_create("John Doe", "jdoe@example.com")
+        .await
+        .unwrap()
+        .take_id();
+    let mailbox_id = client
+        .mailbox_create("JMAP MDN", None::<String>, Role::None)
+        .await
+        .unwrap()
+        .take_id();
+
+    // Import a message requesting a disposition notification and one that does not
+    let email_id = client
+        .email_import(
+            concat!(
+                "From: Jane Smith <jane@remote.org>\r\n",
+                "To: jdoe@example.com\r\n",
+                "Subject: Meeting notes\r\n",
+                "Message-ID: <mdn-test@remote.org>\r\n",
+                "Disposition-Notification-To: Jane Smith <jane@remote.org>\r\n",
+                "\r\n",
+                "Please confirm you have read this.\r\n"
+            )
+            .as_bytes()
+            .to_vec(),
+            [&mailbox_id],
+            None::<Vec<&str>>,
+            None,
+        )
+        .await
+        .unwrap()
+        .take_id();
+    let email_id_no_mdn = client
+        .email_import(
+            concat!(
+                "From: jane@remote.org\r\n",
+                "To: jdoe@example.com\r\n",
+                "Subject: No receipt\r\n",
+                "\r\n",
+                "No receipt requested.\r\n"
+            )
+            .as_bytes()
+            .to_vec(),
+            [&mailbox_id],
+            None::<Vec<&str>>,
+            None,
+        )
+        .await
+        .unwrap()
+        .take_id();
+
+    // Send MDNs
+    smtp_settings.lock().do_stop = true;
+    let response = jmap_json_request(
+        r##"[[
+            "MDN/send",
+            {
+             "accountId": "$$",
+             "identityId": "%%",
+             "send": {
+              "k1": {
+               "forEmailId": "&1",
+               "includeOriginalMessage": true,
+               "disposition": {
+                "actionMode": "manual-action",
+                "sendingMode": "mdn-sent-manually",
+                "type": "displayed"
+               },
+               "extensionFields": {
+                "X-Test-Field": "test value"
+               }
+              },
+              "k2": {
+               "forEmailId": "&2",
+               "disposition": {
+                "actionMode": "manual-action",
+                "sendingMode": "mdn-sent-manually",
+                "type": "displayed"
+               }
+              },
+              "k3": {
+               "forEmailId": "&1",
+               "disposition": {
+                "actionMode": "manual-action",
+                "sendingMode": "mdn-sent-manually",
+                "type": "opened"
+               }
+              },
+              "k4": {
+               "forEmailId": "&1",
+               "mdnGateway": "dns; gateway.example.com",
+               "disposition": {
+                "actionMode": "manual-action",
+                "sendingMode": "mdn-sent-manually",
+                "type": "displayed"
+               }
+              },
+              "k5": {
+               "forEmailId": "&1",
+               "disposition": {
+                "actionMode": "manual-action",
+                "sendingMode": "mdn-sent-manually",
+                "type": "processed"
+               }
+              },
+              "k6": {
+               "forEmailId": "&1",
+               "reportingUA": "ua.example.com\r\nX-Injected: true",
+               "disposition": {
+                "actionMode": "manual-action",
+                "sendingMode": "mdn-sent-manually",
+                "type": "displayed"
+               },
+               "extensionFields": {
+                "X-Valid": "value",
+                "X-Invalid": "value\nX-Injected: true"
+               }
+              }
+             },
+             "onSuccessUpdateEmail": {
+              "#k1": {
+               "keywords/$seen": true
+              }
+             }
+            },
+            "R1"
+           ]]"##
+            .replace("$$", &account_id)
+            .replace("%%", &identity_id)
+            .replace("&1", &email_id)
+            .replace("&2", &email_id_no_mdn),
+        "jdoe@example.com",
+        "12345",
+    )
+    .await;
+    for (pointer, expected) in [
+        ("/methodResponses/0/0", "MDN/send"),
+        (
+            "/methodResponses/0/1/sent/k1/finalRecipient",
+            "rfc822; jdoe@example.com",
+        ),
+        (
+            "/methodResponses/0/1/sent/k1/originalMessageId",
+            "<mdn-test@remote.org>",
+        ),
+        ("/methodResponses/0/1/notSent/k2/type", "invalidProperties"),
+        ("/methodResponses/0/1/notSent/k3/type", "invalidProperties"),
+        ("/methodResponses/0/1/notSent/k4/type", "invalidProperties"),
+        ("/methodResponses/0/1/notSent/k5/type", "mdnAlreadySent"),
+        ("/methodResponses/0/1/notSent/k6/type", "invalidProperties"),
+        (
+            "/methodResponses/0/1/notSent/k6/properties/0",
+            "reportingUA",
+        ),
+        (
+            "/methodResponses/0/1/notSent/k6/properties/1",
+            "extensionFields",
+        ),
+        ("/methodResponses/1/0", "Email/set"),
+        ("/methodResponses/1/2", "R1"),
+    ] {
+        assert_eq!(
+            response
+                .pointer(pointer)
+                .and_then(|v| v.as_str())
+                .unwrap_or_default(),
+            expected,
+            "Pointer {pointer:?} Response: {response:#?}",
+        );
+    }
+    assert!(
+        response
+            .pointer(&format!("/methodResponses/1/1/updated/{email_id}"))
+            .is_some(),
+        "Response: {response:#?}",
+    );
+
+    // Confirm that the MDN has been delivered
+    let message = expect_message_delivery(&mut smtp_rx).await;
+    assert_eq!(message.mail_from, "<>");
+    assert_eq!(message.rcpt_to, vec!["<jane@remote.org>".to_string()]);
+    for needle in [
+        "report-type=\"disposition-notification\"",
+        "Subject: Read: Meeting notes",
+        "In-Reply-To: <mdn-test@remote.org>",
+        "Final-Recipient: rfc822; jdoe@example.com",
+        "Original-Message-ID: <mdn-test@remote.org>",
+        "Disposition: manual-action/mdn-sent-manually; displayed",
+        "X-Test-Field: test value",
+        "Content-Type: message/rfc822",
+        "Content-Transfer-Encoding: 7bit",
+        "Please confirm you have read this.",
+    ] {
+        assert!(
+            message.message.contains(needle),
+            "Needle {needle:?} not found in [{}]",
+            message.message
+        );
+    }
+
+    // The $mdnsent and $seen keywords should have been set
+    let mut keywords = client
+        .email_get(&email_id, [email::Property::Keywords].into())
+        .await
+        .unwrap()
+        .unwrap()
+        .keywords()
+        .into_iter()
+        .map(|k| k.to_string())
+        .collect::<Vec<_>>();
+    keywords.sort_unstable();
+    assert_eq!(keywords, vec!["$mdnsent", "$seen"]);
+
+    // Sending a second MDN for the same message should fail
+    let response = jmap_json_request(
+        r#"[[
+            "MDN/send",
+            {
+             "accountId": "$$",
+             "identityId": "%%",
+             "send": {
+              "k1": {
+               "forEmailId": "&1",
+               "disposition": {
+                "actionMode": "manual-action",
+                "sendingMode": "mdn-sent-manually",
+                "type": "deleted"
+               }
+              }
+             }
+            },
+            "R1"
+           ]]"#
+        .replace("$$", &account_id)
+        .replace("%%", &identity_id)
+        .replace("&1", &email_id),
+        "jdoe@example.com",
+        "12345",
+    )
+    .await;
+    assert_eq!(
+        response
+            .pointer("/methodResponses/0/1/notSent/k1/type")
+            .and_then(|v| v.as_str())
+            .unwrap_or_default(),
+        "mdnAlreadySent",
+        "Response: {response:#?}",
+    );
+    assert_eq!(
+        response
+            .pointer("/methodResponses")
+            .and_then(|v| v.as_array())
+            .map(|v| v.len())
+            .unwrap_or_default(),
+        1,
+        "Response: {response:#?}",
+    );
+
+    // Parse the delivered MDN
+    let blob_id = client
+        .email_import(
+            message.message.into_bytes(),
+            [&mailbox_id],
+            None::<Vec<&str>>,
+            None,
+        )
+        .await
+        .unwrap()
+        .blob_id()
+        .unwrap()
+        .to_string();
+    let response = jmap_json_request(
+        r#"[[
+            "MDN/parse",
+            {
+             "accountId": "$$",
+             "blobIds": [ "%%" ]
+            },
+            "R1"
+           ]]"#
+        .replace("$$", &account_id)
+        .replace("%%", &blob_id),
+        "jdoe@example.com",
+        "12345",
+    )
+    .await;
+    let mdn = response
+        .pointer(&format!("/methodResponses/0/1/parsed/{blob_id}"))
+        .unwrap_or_else(|| panic!("Response: {response:#?}"));
+    for (pointer, expected) in [
+        ("/forEmailId", email_id.as_str()),
+        ("/subject", "Read: Meeting notes"),
+        ("/finalRecipient", "rfc822; jdoe@example.com"),
+        ("/originalMessageId", "<mdn-test@remote.org>"),
+        ("/disposition/actionMode", "manual-action"),
+        ("/disposition/sendingMode", "mdn-sent-manually"),
+        ("/disposition/type", "displayed"),
+        ("/extensionFields/X-Test-Field", "test value"),
+    ] {
+        assert_eq!(
+            mdn.pointer(pointer)
+                .and_then(|v| v.as_str())
+                .unwrap_or_default(),
+            expected,
+            "Pointer {pointer:?} MDN: {mdn:#?}",
+        );
+    }
+    assert_eq!(
+        mdn.pointer("/includeOriginalMessage"),
+        Some(&Value::Bool(true))
+    );
+    assert!(mdn
+        .pointer("/reportingUA")
+        .and_then(|v| v.as_str())
+        .unwrap_or_default()
+        .ends_with("; Stalwart JMAP"));
+
+    // Remove test data
+    params.client.identity_destroy(&identity_id).await.unwrap();
+    destroy_all_mailboxes(params).await;
+    assert_is_empty(server).await;
+}
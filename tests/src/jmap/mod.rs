@@ -44,9 +44,13 @@ use crate::{add_test_certs, directory::DirectoryStore, store::TempDir};
 pub mod auth_acl;
 pub mod auth_limits;
 pub mod auth_oauth;
+pub mod bayes;
 pub mod blob;
+pub mod calendars;
+pub mod contacts;
 pub mod crypto;
 pub mod delivery;
+pub mod dkim;
 pub mod email_changes;
 pub mod email_copy;
 pub mod email_get;
@@ -58,8 +62,11 @@ pub mod email_set;
 pub mod email_submission;
 pub mod event_source;
 pub mod mailbox;
+pub mod mailing_list;
+pub mod mdn;
 pub mod push_subscription;
 pub mod quota;
+pub mod settings;
 pub mod sieve_script;
 pub mod stress_test;
 pub mod thread_get;
@@ -113,6 +120,14 @@ directory = "auth"
 total = 5
 wait = "1ms"
 
+[auth.dkim]
+managed = true
+
+[auth.dkim.rotate]
+interval = "30d"
+grace = "2d"
+retire = "7d"
+
 [queue]
 path = "{TMP}"
 hash = 64
@@ -146,6 +161,10 @@ future-release = [ { if = "authenticated-as", ne = "", then = "99999999d"},
 type = "sqlite"
 path = "{TMP}/sqlite.db"
 
+[store."lookup"]
+type = "sqlite"
+path = "{TMP}/lookup.db"
+
 [store."rocksdb"]
 type = "rocksdb"
 path = "{TMP}/rocks.db"
@@ -185,6 +204,7 @@ private-key = "file://{PK}"
 data = "{STORE}"
 fts = "{STORE}"
 blob = "{STORE}"
+lookup = "lookup"
 directory = "auth"
 
 [storage.spam]
@@ -213,6 +233,9 @@ account = "1000/1m"
 authentication = "100/2s"
 anonymous = "100/1m"
 
+[sieve.untrusted.notify]
+rate = "1/1h"
+
 [jmap.event-source]
 throttle = "500ms"
 
@@ -223,6 +246,9 @@ throttle = "500ms"
 throttle = "500ms"
 attempts.interval = "500ms"
 
+[jmap.mailing-list]
+url = "https://127.0.0.1:8899"
+
 [store."auth"]
 type = "sqlite"
 path = "{TMP}/auth.db"
@@ -269,6 +295,12 @@ user-code = "1s"
 token = "1s"
 refresh-token = "3s"
 refresh-token-renew = "2s"
+
+[oauth.oidc]
+signature-key = "file://{OIDC_KEY}"
+
+[oauth.oidc.registration]
+token = "registration-secret"
 "#;
 
 #[tokio::test(flavor = "multi_thread")]
@@ -316,10 +348,17 @@ pub async fn jmap_tests() {
     sieve_script::test(&mut params).await;
     vacation_response::test(&mut params).await;
     email_submission::test(&mut params).await;
+    mailing_list::test(&mut params).await;
     websocket::test(&mut params).await;
     quota::test(&mut params).await;
     crypto::test(&mut params).await;
     blob::test(&mut params).await;
+    mdn::test(&mut params).await;
+    contacts::test(&mut params).await;
+    calendars::test(&mut params).await;
+    settings::test(&mut params).await;
+    dkim::test(&mut params).await;
+    bayes::test(&mut params).await;
 
     if delete {
         params.temp_dir.delete();
@@ -396,7 +435,14 @@ async fn init_jmap_tests(store_id: &str, delete_if_exists: bool) -> JMAPTest {
     let config = utils::config::Config::new(
         &add_test_certs(SERVER)
             .replace("{STORE}", store_id)
-            .replace("{TMP}", &temp_dir.path.display().to_string()),
+            .replace("{TMP}", &temp_dir.path.display().to_string())
+            .replace(
+                "{OIDC_KEY}",
+                concat!(
+                    env!("CARGO_MANIFEST_DIR"),
+                    "/resources/jmap/oidc_privatekey.pem"
+                ),
+            ),
     )
     .unwrap();
     let mut servers = config.parse_servers().unwrap();
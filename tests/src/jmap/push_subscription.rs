@@ -300,6 +300,7 @@ impl utils::listener::SessionManager for SessionManager {
                         session
                             .instance
                             .acceptor
+                            .load_full()
                             .accept(session.stream)
                             .await
                             .unwrap_tls()
@@ -38,7 +38,9 @@ use std::{
 use crate::jmap::{
     assert_is_empty,
     delivery::SmtpConnection,
-    email_submission::{assert_message_delivery, spawn_mock_smtp_server, MockMessage},
+    email_submission::{
+        assert_message_delivery, expect_nothing, spawn_mock_smtp_server, MockMessage,
+    },
     mailbox::destroy_all_mailboxes,
 };
 
@@ -289,7 +291,7 @@ pub async fn test(params: &mut JMAPTest) {
 
     // Start mock SMTP server
     let (mut smtp_rx, smtp_settings) = spawn_mock_smtp_server();
-    server.smtp.resolvers.dns.ipv4_add(
+    server.smtp.load().resolvers.dns.ipv4_add(
         "localhost",
         vec!["127.0.0.1".parse().unwrap()],
         Instant::now() + Duration::from_secs(10),
@@ -363,14 +365,15 @@ pub async fn test(params: &mut JMAPTest) {
     .await;
 
     // Run enclose + redirect tests
-    client
+    let redirect_script_id = client
         .sieve_script_create(
             "test_redirect_enclose",
             get_script("test_redirect_enclose"),
             true,
         )
         .await
-        .unwrap();
+        .unwrap()
+        .take_id();
     lmtp.ingest(
         "bill@remote.org",
         &["jdoe@example.com"],
@@ -487,6 +490,92 @@ pub async fn test(params: &mut JMAPTest) {
         panic!("Email {:?} not found in: {:#?}", subject, emails);
     }
 
+    // Notifications over the rate limit should not be sent
+    let (mut smtp_rx, smtp_settings) = spawn_mock_smtp_server();
+    lmtp.ingest(
+        "bill@remote.org",
+        &["jdoe@example.com"],
+        concat!(
+            "From: bill@remote.org\r\n",
+            "To: jdoe@example.com\r\n",
+            "Subject: Did you get the memo about the TPS reports?\r\n",
+            "\r\n",
+            "We're putting new coversheets on all the TPS reports."
+        ),
+    )
+    .await;
+    expect_nothing(&mut smtp_rx).await;
+    assert_eq!(
+        client
+            .email_query(None::<email::query::Filter>, None::<Vec<_>>)
+            .await
+            .unwrap()
+            .ids()
+            .len(),
+        5,
+        "Message was not delivered."
+    );
+
+    // Notifications using unsupported methods are discarded without affecting delivery
+    client
+        .sieve_script_create(
+            "test_notify_unsupported",
+            get_script("test_notify_unsupported"),
+            true,
+        )
+        .await
+        .unwrap();
+    lmtp.ingest(
+        "bill@remote.org",
+        &["jdoe@example.com"],
+        concat!(
+            "From: bill@remote.org\r\n",
+            "To: jdoe@example.com\r\n",
+            "Subject: Coversheets\r\n",
+            "\r\n",
+            "Did you see the memo about this?"
+        ),
+    )
+    .await;
+    expect_nothing(&mut smtp_rx).await;
+    assert_eq!(
+        client
+            .email_query(None::<email::query::Filter>, None::<Vec<_>>)
+            .await
+            .unwrap()
+            .ids()
+            .len(),
+        6,
+        "Message was not delivered."
+    );
+
+    client
+        .sieve_script_activate(&redirect_script_id)
+        .await
+        .unwrap();
+    smtp_settings.lock().do_stop = true;
+    lmtp.ingest(
+        "bill@remote.org",
+        &["jdoe@example.com"],
+        concat!(
+            "From: bill@remote.org\r\n",
+            "To: jdoe@example.com\r\n",
+            "Subject: TPS Report\r\n",
+            "\r\n",
+            "Yeah, I'm going to need you to go ahead and come in on Saturday."
+        ),
+    )
+    .await;
+    assert_message_delivery(
+        &mut smtp_rx,
+        MockMessage::new(
+            "<jdoe@example.com>",
+            ["<jane@remote.org>"],
+            "@Attached you'll find",
+        ),
+    )
+    .await;
+
     // Remove test data
     client.sieve_script_deactivate().await.unwrap();
     let mut request = client.build();
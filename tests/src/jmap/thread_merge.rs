@@ -260,6 +260,8 @@ async fn test_multi_thread(params: &mut JMAPTest) {
                         received_at: None,
                         skip_duplicates: true,
                         encrypt: false,
+                        authenticated_sender: None,
+                        is_delivery: false,
                     })
                     .await
                 {
@@ -37,11 +37,8 @@ use store::{
 };
 use tokio::net::TcpSocket;
 
-use utils::{
-    config::{
-        ipmask::IpAddrMask, Config, DynValue, KeyLookup, Listener, Rate, Server, ServerProtocol,
-    },
-    listener::TcpAcceptor,
+use utils::config::{
+    ipmask::IpAddrMask, Config, DynValue, KeyLookup, Listener, Rate, Server, ServerProtocol,
 };
 
 use ahash::AHashMap;
@@ -454,7 +451,7 @@ fn parse_servers() {
                 linger: None,
                 nodelay: true,
             }],
-            acceptor: TcpAcceptor::Plain,
+            acceptor: Default::default(),
             tls_implicit: false,
             max_connections: 8192,
             proxy_networks: vec![],
@@ -484,7 +481,7 @@ fn parse_servers() {
                     nodelay: true,
                 },
             ],
-            acceptor: TcpAcceptor::Plain,
+            acceptor: Default::default(),
             tls_implicit: true,
             max_connections: 1024,
             proxy_networks: vec![],
@@ -504,7 +501,7 @@ fn parse_servers() {
                 linger: None,
                 nodelay: true,
             }],
-            acceptor: TcpAcceptor::Plain,
+            acceptor: Default::default(),
             tls_implicit: true,
             max_connections: 8192,
             proxy_networks: vec![],
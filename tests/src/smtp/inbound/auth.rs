@@ -21,14 +21,21 @@
  * for more details.
 */
 
-use directory::core::config::ConfigDirectory;
-use smtp_proto::{AUTH_LOGIN, AUTH_PLAIN};
+use base64::{engine::general_purpose, Engine};
+use directory::core::{
+    config::ConfigDirectory,
+    scram::{ScramAlgorithm, ScramSecret},
+};
+use smtp_proto::{AUTH_LOGIN, AUTH_PLAIN, AUTH_SCRAM_SHA_256, AUTH_SCRAM_SHA_256_PLUS};
 use store::{Store, Stores};
 use utils::config::{Config, DynValue, Servers};
 
-use crate::smtp::{
-    session::{TestSession, VerifyResponse},
-    ParseTestConfig, TestConfig,
+use crate::{
+    directory::ScramClient,
+    smtp::{
+        session::{TestSession, VerifyResponse},
+        ParseTestConfig, TestConfig,
+    },
 };
 use smtp::{
     config::{ConfigContext, EnvelopeKey, IfBlock},
@@ -54,17 +61,26 @@ secret = "p4ssw0rd"
 email = "jane@example.org"
 email-list = ["info@example.org"]
 member-of = ["sales", "support"]
+
+[[directory."local".principals]]
+name = "scram"
+description = "SCRAM User"
+secret = "{SCRAM}"
+email = "scram@example.org"
 "#;
 
 #[tokio::test]
 async fn auth() {
     let mut core = SMTP::test();
     let mut ctx = ConfigContext::new(&[]);
-    ctx.directory = Config::new(DIRECTORY)
-        .unwrap()
-        .parse_directory(&Stores::default(), &Servers::default(), Store::default())
-        .await
-        .unwrap();
+    ctx.directory = Config::new(&DIRECTORY.replace(
+        "{SCRAM}",
+        &ScramSecret::new(ScramAlgorithm::Sha256, "secret").to_string(),
+    ))
+    .unwrap()
+    .parse_directory(&Stores::default(), &Servers::default(), Store::default())
+    .await
+    .unwrap();
 
     let config = &mut core.session.config.auth;
 
@@ -83,7 +99,7 @@ async fn auth() {
     config.mechanisms = format!(
         "[{{if = 'remote-ip', eq = '10.0.0.1', then = {}}},
     {{else = 0}}]",
-        AUTH_PLAIN | AUTH_LOGIN
+        AUTH_PLAIN | AUTH_LOGIN | AUTH_SCRAM_SHA_256 | AUTH_SCRAM_SHA_256_PLUS
     )
     .as_str()
     .parse_if(&ctx);
@@ -102,7 +118,9 @@ async fn auth() {
         .ehlo("mx.foobar.org")
         .await
         .assert_not_contains(" PLAIN")
-        .assert_not_contains(" LOGIN");
+        .assert_not_contains(" LOGIN")
+        .assert_contains(" SCRAM-SHA-256")
+        .assert_not_contains("SCRAM-SHA-256-PLUS");
 
     // EHLO should advertise AUTH for 10.0.0.1
     session.stream.tls = true;
@@ -161,6 +179,32 @@ async fn auth() {
     session.cmd("amFuZQ==", "334").await;
     session.cmd("cDRzc3cwcmQ=", "235 2.7.0").await;
 
+    // Successful SCRAM-SHA-256 authentication
+    session.data.authenticated_as.clear();
+    let mut client = ScramClient::new("scram", None);
+    let server_first = scram_challenge(
+        session
+            .cmd(
+                &format!(
+                    "AUTH SCRAM-SHA-256 {}",
+                    general_purpose::STANDARD.encode(client.client_first())
+                ),
+                "334",
+            )
+            .await,
+    );
+    let server_final = scram_challenge(
+        session
+            .cmd(
+                &general_purpose::STANDARD.encode(client.client_final("secret", &server_first)),
+                "334",
+            )
+            .await,
+    );
+    assert_eq!(server_final, client.server_final());
+    session.cmd("", "235 2.7.0").await;
+    assert_eq!(session.data.authenticated_as, "scram");
+
     // Login should not be advertised to 10.0.0.2
     session.data.remote_ip = "10.0.0.2".parse().unwrap();
     session.eval_session_params().await;
@@ -175,3 +219,12 @@ async fn auth() {
         .cmd("AUTH PLAIN AGpvaG4Ac2VjcmV0", "503 5.5.1")
         .await;
 }
+
+fn scram_challenge(response: Vec<String>) -> String {
+    String::from_utf8(
+        general_purpose::STANDARD
+            .decode(response.last().unwrap().strip_prefix("334 ").unwrap())
+            .unwrap(),
+    )
+    .unwrap()
+}
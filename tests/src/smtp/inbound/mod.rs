@@ -137,7 +137,7 @@ impl TestQueueEvent for queue::Event {
 
     fn unwrap_done(self) {
         match self {
-            queue::Event::Done(WorkerResult::Done) => (),
+            queue::Event::Done(WorkerResult::Done(_)) => (),
             queue::Event::Queue(message) => {
                 panic!("Unexpected message: {}", message.inner.read_message());
             }
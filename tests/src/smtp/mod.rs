@@ -41,11 +41,11 @@ use smtp::{
     config::{
         if_block::ConfigIf, queue::ConfigQueue, scripts::SieveContext, session::ConfigSession,
         throttle::ConfigThrottle, AggregateReport, ArcAuthConfig, Auth, ConfigContext, Connect,
-        Data, DkimAuthConfig, DmarcAuthConfig, Dsn, Ehlo, EnvelopeKey, Extensions, IfBlock,
-        IpRevAuthConfig, Mail, MailAuthConfig, Milter, QueueConfig, QueueOutboundSourceIp,
-        QueueOutboundTimeout, QueueOutboundTls, QueueQuotas, QueueThrottle, Rcpt, Report,
-        ReportAnalysis, ReportConfig, SessionConfig, SessionThrottle, SpfAuthConfig, Throttle,
-        VerifyStrategy,
+        Data, DkimAuthConfig, DkimRotation, DmarcAuthConfig, Dsn, Ehlo, EnvelopeKey, Extensions,
+        IfBlock, IpRevAuthConfig, Mail, MailAuthConfig, Milter, QueueBackend, QueueConfig,
+        QueueOutboundSourceIp, QueueOutboundTimeout, QueueOutboundTls, QueueQuotas, QueueThrottle,
+        Rcpt, Report, ReportAnalysis, ReportConfig, SessionConfig, SessionThrottle, SpfAuthConfig,
+        Throttle, VerifyStrategy,
     },
     core::{
         throttle::ThrottleKeyHasherBuilder, QueueCore, ReportCore, Resolvers, SessionCore,
@@ -53,7 +53,7 @@ use smtp::{
     },
     outbound::dane::DnssecResolver,
 };
-use utils::config::{utils::ParseValues, Config};
+use utils::config::{cron::SimpleCron, utils::ParseValues, Config};
 
 pub mod config;
 pub mod inbound;
@@ -162,10 +162,12 @@ pub trait TestConfig {
 impl TestConfig for SMTP {
     fn test() -> Self {
         SMTP {
-            worker_pool: rayon::ThreadPoolBuilder::new()
-                .num_threads(num_cpus::get())
-                .build()
-                .unwrap(),
+            worker_pool: Arc::new(
+                rayon::ThreadPoolBuilder::new()
+                    .num_threads(num_cpus::get())
+                    .build()
+                    .unwrap(),
+            ),
             session: SessionCore::test(),
             queue: QueueCore::test(),
             resolvers: Resolvers {
@@ -181,6 +183,7 @@ impl TestConfig for SMTP {
                 },
             },
             mail_auth: MailAuthConfig::test(),
+            dkim: Default::default(),
             report: ReportCore::test(),
             sieve: SieveCore::test(),
             delivery_tx: mpsc::channel(1).0,
@@ -192,11 +195,11 @@ impl TestConfig for SessionCore {
     fn test() -> Self {
         SessionCore {
             config: SessionConfig::test(),
-            throttle: DashMap::with_capacity_and_hasher_and_shard_amount(
+            throttle: Arc::new(DashMap::with_capacity_and_hasher_and_shard_amount(
                 10,
                 ThrottleKeyHasherBuilder::default(),
                 16,
-            ),
+            )),
         }
     }
 }
@@ -276,18 +279,19 @@ impl TestConfig for QueueCore {
     fn test() -> Self {
         Self {
             config: QueueConfig::test(),
-            throttle: DashMap::with_capacity_and_hasher_and_shard_amount(
+            throttle: Arc::new(DashMap::with_capacity_and_hasher_and_shard_amount(
                 10,
                 ThrottleKeyHasherBuilder::default(),
                 16,
-            ),
-            quota: DashMap::with_capacity_and_hasher_and_shard_amount(
+            )),
+            quota: Arc::new(DashMap::with_capacity_and_hasher_and_shard_amount(
                 10,
                 ThrottleKeyHasherBuilder::default(),
                 16,
-            ),
+            )),
             tx: mpsc::channel(1024).0,
-            id_seq: 0.into(),
+            id_seq: Arc::new(0.into()),
+            node_id: 0,
             connectors: TlsConnectors {
                 pki_verify: build_tls_connector(false),
                 dummy_verify: build_tls_connector(true),
@@ -300,8 +304,11 @@ impl TestConfig for QueueConfig {
     fn test() -> Self {
         let store = Store::default();
         Self {
+            backend: QueueBackend::FileSystem,
             path: Default::default(),
             hash: IfBlock::new(10),
+            lock_expiry: Duration::from_secs(60),
+            poll_interval: Duration::from_secs(1),
             retry: IfBlock::new(vec![Duration::from_secs(10)]),
             notify: IfBlock::new(vec![Duration::from_secs(20)]),
             expire: IfBlock::new(Duration::from_secs(10)),
@@ -350,9 +357,12 @@ impl TestConfig for QueueConfig {
                 catch_all: AddressMapping::Disable,
                 subaddressing: AddressMapping::Disable,
                 cache: None,
+                oidc: None,
                 blocked_ips: Arc::new(Default::default()),
+                scram_dummy_key: b"secret".to_vec(),
             }),
             lookup_store: LookupStore::Store(store.clone()),
+            blob_store: store.clone().into(),
             data_store: store,
         }
     }
@@ -364,6 +374,13 @@ impl TestConfig for MailAuthConfig {
             dkim: DkimAuthConfig {
                 verify: IfBlock::new(VerifyStrategy::Relaxed),
                 sign: IfBlock::default(),
+                managed: IfBlock::default(),
+                rotate: DkimRotation {
+                    interval: None,
+                    grace: Duration::from_secs(2 * 86400),
+                    retire: Duration::from_secs(7 * 86400),
+                    frequency: SimpleCron::Day { hour: 3, minute: 0 },
+                },
             },
             arc: ArcAuthConfig {
                 verify: IfBlock::new(VerifyStrategy::Relaxed),
@@ -402,6 +419,7 @@ impl TestConfig for ReportConfig {
                 addresses: vec![],
                 forward: true,
                 store: None,
+                retention: Duration::from_secs(30 * 86400),
                 report_id: 0.into(),
             },
             dkim: Report::test(),
@@ -131,7 +131,7 @@ async fn lmtp_delivery() {
                 dsn.push(message.inner);
             }
             Some(Event::Done(wr)) => match wr {
-                WorkerResult::Done => {
+                WorkerResult::Done(_) => {
                     break;
                 }
                 WorkerResult::Retry(retry) => {
@@ -140,7 +140,7 @@ async fn lmtp_delivery() {
                 WorkerResult::OnHold(_) => unreachable!(),
             },
             None | Some(Event::Stop) => break,
-            Some(Event::Manage(_)) => unreachable!(),
+            Some(Event::Manage(_) | Event::Reload(_)) => unreachable!(),
         }
 
         if !queue.scheduled.is_empty() {
@@ -23,6 +23,7 @@
 
 use std::sync::Arc;
 
+use arc_swap::ArcSwap;
 use tokio::sync::watch;
 
 use ::smtp::core::{SmtpAdminSessionManager, SmtpSessionManager, SMTP};
@@ -83,6 +84,7 @@ pub fn start_test_server(core: Arc<SMTP>, protocols: &[ServerProtocol]) -> watch
 
     // Start servers
     servers.bind(&config);
+    let core = Arc::new(ArcSwap::new(core));
     let smtp_manager = SmtpSessionManager::new(core.clone());
     let smtp_admin_manager = SmtpAdminSessionManager::new(core);
     servers
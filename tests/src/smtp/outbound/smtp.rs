@@ -154,7 +154,7 @@ async fn smtp_delivery() {
                 dsn.push(message.inner);
             }
             Some(Event::Done(wr)) => match wr {
-                WorkerResult::Done => {
+                WorkerResult::Done(_) => {
                     break;
                 }
                 WorkerResult::Retry(retry) => {
@@ -166,7 +166,7 @@ async fn smtp_delivery() {
                 WorkerResult::OnHold(_) => unreachable!(),
             },
             None | Some(Event::Stop) => break,
-            Some(Event::Manage(_)) => unreachable!(),
+            Some(Event::Manage(_) | Event::Reload(_)) => unreachable!(),
         }
 
         if !queue.scheduled.is_empty() {
@@ -268,7 +268,7 @@ async fn smtp_delivery() {
         let event = local_qr.read_event().await;
 
         assert!(
-            matches!(event, Event::Done(WorkerResult::Done)),
+            matches!(event, Event::Done(WorkerResult::Done(_))),
             "event: {:?}",
             event
         );
@@ -58,6 +58,8 @@ async fn generate_dsn() {
         size,
         id: 0,
         path,
+        blob_hash: Default::default(),
+        event_due: 0,
         created: SystemTime::now()
             .duration_since(SystemTime::UNIX_EPOCH)
             .map_or(0, |d| d.as_secs()),
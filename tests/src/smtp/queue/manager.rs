@@ -132,6 +132,8 @@ pub fn new_message(id: u64) -> Box<Message> {
         size: 0,
         id,
         path: Default::default(),
+        blob_hash: Default::default(),
+        event_due: 0,
         created: 0,
         return_path: "sender@foobar.org".to_string(),
         return_path_lcase: "".to_string(),
@@ -117,7 +117,7 @@ async fn queue_retry() {
                 dsn.push(message.inner);
             }
             Some(Event::Done(wr)) => match wr {
-                WorkerResult::Done => break,
+                WorkerResult::Done(_) => break,
                 WorkerResult::Retry(retry) => {
                     queue.schedule(retry);
                     num_retries += 1;
@@ -125,7 +125,7 @@ async fn queue_retry() {
                 WorkerResult::OnHold(_) => unreachable!(),
             },
             None | Some(Event::Stop) => break,
-            Some(Event::Manage(_)) => unreachable!(),
+            Some(Event::Manage(_) | Event::Reload(_)) => unreachable!(),
         }
 
         if !queue.scheduled.is_empty() {
@@ -29,10 +29,11 @@ use std::{
 use smtp_proto::{Response, MAIL_REQUIRETLS, MAIL_SMTPUTF8, RCPT_CONNEG, RCPT_NOTIFY_FAILURE};
 
 use smtp::{
+    config::QueueBackend,
     core::SMTP,
     queue::{
-        Domain, Error, ErrorDetails, HostResponse, Message, Recipient, Schedule, Status,
-        RCPT_STATUS_CHANGED,
+        manager::Queue, Domain, Error, ErrorDetails, HostResponse, Message, Recipient, Schedule,
+        Status, RCPT_STATUS_CHANGED,
     },
 };
 
@@ -45,11 +46,167 @@ async fn queue_serialize() {
     // Create temp dir for queue
     let mut qr = core.init_test_queue("smtp_queue_serialize_test");
 
-    // Create test message
-    let message = Message {
+    // Queue message
+    assert!(
+        core.queue
+            .queue_message(
+                Box::new(test_message()),
+                (&b"From: test@foobar.org\r\n"[..]).into(),
+                b"Subject: test\r\n\n\ntest",
+                &tracing::info_span!("hi")
+            )
+            .await
+    );
+    let mut message = qr.read_event().await.unwrap_message();
+
+    // Deserialize
+    assert_msg_eq(
+        &message,
+        &Message::from_path(message.path.clone()).await.unwrap(),
+    );
+
+    // Write update
+    update_message(&mut message);
+
+    // Save changes
+    message.save_changes(&core.queue.config).await;
+    assert!(message.serialize_changes().is_empty());
+    assert_msg_eq(
+        &message,
+        &Message::from_path(message.path.clone()).await.unwrap(),
+    );
+
+    // Remove
+    message.remove(&core.queue.config).await;
+    assert!(!message.path.exists());
+}
+
+#[tokio::test]
+async fn queue_serialize_store() {
+    let mut core = SMTP::test();
+
+    // Create temp dir for queue
+    let mut qr = core.init_test_queue("smtp_queue_serialize_store_test");
+
+    // Queue a message on the filesystem spool
+    assert!(
+        core.queue
+            .queue_message(
+                Box::new(test_message()),
+                (&b"From: test@foobar.org\r\n"[..]).into(),
+                b"Subject: test\r\n\n\ntest",
+                &tracing::info_span!("hi")
+            )
+            .await
+    );
+    let mut fs_message = qr.read_event().await.unwrap_message();
+    assert!(fs_message.path.exists());
+
+    // Switching to the data store should migrate the spool
+    core.queue.config.backend = QueueBackend::Store;
+    let queue = core.queue.read_queue().await;
+    assert!(!fs_message.path.exists());
+    assert_eq!(queue.scheduled.len(), 1);
+    let mut message = queue.messages.into_values().next().unwrap();
+    fs_message.path = PathBuf::new();
+    assert_msg_eq(&fs_message, &message);
+    assert_eq!(
+        message
+            .read_raw_message(
+                &core.queue.config,
+                0..usize::MAX,
+                &tracing::info_span!("hi")
+            )
+            .await
+            .unwrap(),
+        b"From: test@foobar.org\r\nSubject: test\r\n\n\ntest"
+    );
+
+    // Write update
+    update_message(&mut message);
+    message.save_changes(&core.queue.config).await;
+    assert!(message.serialize_changes().is_empty());
+    let queue = core.queue.read_queue().await;
+    assert_msg_eq(&message, queue.messages.get(&message.id).unwrap());
+
+    // Queue a new message directly in the data store
+    assert!(
+        core.queue
+            .queue_message(
+                Box::new(test_message()),
+                None,
+                b"Subject: hello\r\n\n\nworld",
+                &tracing::info_span!("hi")
+            )
+            .await
+    );
+    let new_message = qr.read_event().await.unwrap_message();
+    assert_eq!(core.queue.read_queue().await.messages.len(), 2);
+    assert_eq!(
+        new_message
+            .read_raw_message(&core.queue.config, 0..9, &tracing::info_span!("hi"))
+            .await
+            .unwrap(),
+        b"Subject: "
+    );
+
+    // Messages queued by other nodes are picked up when polling the store
+    let mut queue = Queue::default();
+    core.queue.poll_queue(&mut queue).await;
+    assert!(queue.messages.contains_key(&new_message.id));
+
+    // Only one node at a time can hold the delivery lease of a message
+    let mut locked = queue
+        .messages
+        .remove(&new_message.id)
+        .unwrap()
+        .lock(&core.queue.config)
+        .await
+        .unwrap();
+    core.queue.poll_queue(&mut queue).await;
+    assert!(queue
+        .messages
+        .remove(&new_message.id)
+        .unwrap()
+        .lock(&core.queue.config)
+        .await
+        .is_none());
+
+    // Saving the message releases the lease
+    locked.save_changes(&core.queue.config).await;
+    core.queue.poll_queue(&mut queue).await;
+    let mut locked = queue
+        .messages
+        .remove(&new_message.id)
+        .unwrap()
+        .lock(&core.queue.config)
+        .await
+        .unwrap();
+    locked.save_changes(&core.queue.config).await;
+
+    // Messages being delivered by this node are not picked up again
+    queue.in_flight.insert(new_message.id);
+    core.queue.poll_queue(&mut queue).await;
+    assert!(!queue.messages.contains_key(&new_message.id));
+    queue.schedule(Schedule {
+        due: Instant::now(),
+        inner: locked,
+    });
+    assert!(queue.in_flight.is_empty());
+
+    // Remove
+    message.remove(&core.queue.config).await;
+    new_message.remove(&core.queue.config).await;
+    assert!(core.queue.read_queue().await.messages.is_empty());
+}
+
+fn test_message() -> Message {
+    Message {
         size: 0,
         id: 0,
         path: PathBuf::new(),
+        blob_hash: Default::default(),
+        event_due: 0,
         created: 123456,
         return_path: "sender@FooBar.org".to_string(),
         return_path_lcase: "sender@foobar.org".to_string(),
@@ -97,28 +254,10 @@ async fn queue_serialize() {
         priority: -1,
 
         queue_refs: vec![],
-    };
-
-    // Queue message
-    assert!(
-        core.queue
-            .queue_message(
-                Box::new(message),
-                (&b"From: test@foobar.org\r\n"[..]).into(),
-                b"Subject: test\r\n\n\ntest",
-                &tracing::info_span!("hi")
-            )
-            .await
-    );
-    let mut message = qr.read_event().await.unwrap_message();
-
-    // Deserialize
-    assert_msg_eq(
-        &message,
-        &Message::from_path(message.path.clone()).await.unwrap(),
-    );
+    }
+}
 
-    // Write update
+fn update_message(message: &mut Message) {
     message.recipients[0].status = Status::PermanentFailure(HostResponse {
         hostname: ErrorDetails {
             entity: "mx.example.org".to_string(),
@@ -164,18 +303,6 @@ async fn queue_serialize() {
     message.domains[1].notify.inner = 321;
     message.domains[1].retry = Schedule::later(Duration::from_secs(62));
     message.domains[1].retry.inner = 678;
-
-    // Save changes
-    message.save_changes().await;
-    assert!(message.serialize_changes().is_empty());
-    assert_msg_eq(
-        &message,
-        &Message::from_path(message.path.clone()).await.unwrap(),
-    );
-
-    // Remove
-    message.remove().await;
-    assert!(!message.path.exists());
 }
 
 fn assert_msg_eq(msg: &Message, other: &Message) {
@@ -28,8 +28,10 @@ use crate::smtp::{
 };
 use smtp::{
     config::{AddressMatch, IfBlock},
-    core::{Session, SMTP},
+    core::{management::IncomingReportFilter, Session, SMTP},
+    reporting::analysis::{Format, IncomingReportId},
 };
+use store::write::now;
 
 #[tokio::test]
 async fn report_analyze() {
@@ -91,6 +93,94 @@ async fn report_analyze() {
     }
     assert_eq!(total_reports, total_reports_received);
 
+    // Received reports should be available in the data store
+    let ids = core
+        .list_incoming_reports(&IncomingReportFilter::default())
+        .await
+        .unwrap();
+    assert_eq!(ids.len(), total_reports_received);
+    for (filter, expected_count) in [
+        (
+            IncomingReportFilter {
+                format: Format::Tls.into(),
+                ..Default::default()
+            },
+            2,
+        ),
+        (
+            IncomingReportFilter {
+                domain: "example.net".to_string().into(),
+                ..Default::default()
+            },
+            2,
+        ),
+        (
+            IncomingReportFilter {
+                reporter: "google inc".to_string().into(),
+                ..Default::default()
+            },
+            1,
+        ),
+        (
+            IncomingReportFilter {
+                min_pass: 1,
+                ..Default::default()
+            },
+            3,
+        ),
+        (
+            IncomingReportFilter {
+                min_fail: 100,
+                ..Default::default()
+            },
+            1,
+        ),
+        (
+            IncomingReportFilter {
+                after: (now() + 86400).into(),
+                ..Default::default()
+            },
+            0,
+        ),
+    ] {
+        assert_eq!(
+            core.list_incoming_reports(&filter).await.unwrap().len(),
+            expected_count,
+            "{filter:?}"
+        );
+    }
+
+    // Fetch report contents
+    let id = core
+        .list_incoming_reports(&IncomingReportFilter {
+            reporter: "google inc".to_string().into(),
+            ..Default::default()
+        })
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let report = core
+        .incoming_report(&IncomingReportId::parse(&id).unwrap())
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(report.type_, "tls");
+    assert_eq!(report.domains, vec!["example.com".to_string()]);
+    assert_eq!(report.reporter, "Google Inc.");
+    assert_eq!((report.pass, report.fail), (23, 1));
+    assert!(report.report.is_some());
+
+    // Reports that have not expired should not be purged
+    core.purge_incoming_reports().await.unwrap();
+    assert_eq!(
+        core.list_incoming_reports(&IncomingReportFilter::default())
+            .await
+            .unwrap()
+            .len(),
+        total_reports_received
+    );
+
     // Test delivery to non-report addresses
     session
         .send_message("john@test.org", &["bill@foobar.org"], "test:no_dkim", "250")
@@ -23,6 +23,7 @@
 
 use std::{borrow::Cow, path::PathBuf, sync::Arc};
 
+use arc_swap::ArcSwap;
 use rustls::{server::ResolvesServerCert, ServerConfig};
 use tokio::{
     io::{AsyncRead, AsyncWrite},
@@ -33,7 +34,10 @@ use smtp::core::{Session, SessionAddress, SessionData, SessionParameters, State,
 use tokio_rustls::TlsAcceptor;
 use utils::{
     config::ServerProtocol,
-    listener::{limiter::ConcurrencyLimiter, ServerInstance, SessionStream, TcpAcceptor},
+    listener::{
+        limiter::ConcurrencyLimiter, tls::ServerCertificates, ServerInstance, SessionStream,
+        TcpAcceptor,
+    },
 };
 
 use super::TestConfig;
@@ -93,6 +97,10 @@ impl SessionStream for DummyIo {
     fn tls_version_and_cipher(&self) -> (Cow<'static, str>, Cow<'static, str>) {
         ("".into(), "".into())
     }
+
+    fn tls_server_end_point(&self, _: &TcpAcceptor) -> Option<Vec<u8>> {
+        None
+    }
 }
 
 impl Unpin for DummyIo {}
@@ -364,11 +372,14 @@ impl TestServerInstance for ServerInstance {
             hostname: "mx.example.org".to_string(),
             protocol: ServerProtocol::Smtp,
             data: "220 mx.example.org at your service.\r\n".to_string(),
-            acceptor: TcpAcceptor::Tls(TlsAcceptor::from(Arc::new(
-                ServerConfig::builder()
-                    .with_no_client_auth()
-                    .with_cert_resolver(Arc::new(DummyCertResolver)),
-            ))),
+            acceptor: Arc::new(ArcSwap::from_pointee(TcpAcceptor::Tls {
+                acceptor: TlsAcceptor::from(Arc::new(
+                    ServerConfig::builder()
+                        .with_no_client_auth()
+                        .with_cert_resolver(Arc::new(DummyCertResolver)),
+                )),
+                certificates: Arc::new(DummyCertResolver),
+            })),
             limiter: ConcurrencyLimiter::new(100),
             shutdown_rx,
             proxy_networks: vec![],
@@ -386,6 +397,12 @@ impl ResolvesServerCert for DummyCertResolver {
     }
 }
 
+impl ServerCertificates for DummyCertResolver {
+    fn certificate(&self, _: Option<&str>) -> Option<Arc<rustls::sign::CertifiedKey>> {
+        None
+    }
+}
+
 impl TestConfig for ServerInstance {
     fn test() -> Self {
         Self::test_with_shutdown(watch::channel(false).1)
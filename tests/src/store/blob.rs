@@ -21,16 +21,47 @@
  * for more details.
 */
 
+use std::sync::atomic::{AtomicUsize, Ordering};
+
 use ahash::AHashMap;
+use base64::{engine::general_purpose, Engine};
+use bytes::Bytes;
+use http_body_util::{BodyExt, Full};
+use hyper::{body, server::conn::http1, service::service_fn};
+use hyper_util::rt::TokioIo;
+use serde_json::json;
 use store::{
     config::ConfigStore,
     write::{blob::BlobQuota, now, BatchBuilder, BlobOp},
-    BlobClass, BlobHash, BlobStore, Serialize,
+    BlobClass, BlobHash, BlobStore, CompressionAlgo, Serialize, Stores,
 };
+use tokio::net::TcpListener;
 use utils::config::Config;
 
 use crate::store::{TempDir, CONFIG};
 
+static KMS_DECRYPT_CALLS: AtomicUsize = AtomicUsize::new(0);
+
+const ENCRYPTION_CONFIG: &str = r#"
+[store."sqlite"]
+type = "sqlite"
+path = "{TMP}/sqlite.db"
+
+[store."sqlite".encryption]
+type = "kms"
+url = "http://127.0.0.1:{PORT}/"
+key-id = "{KMS_KEY}"
+
+[store."fs"]
+type = "fs"
+path = "{TMP}/blobs"
+compression = "lz4"
+
+[store."fs".encryption]
+type = "keyfile"
+path = "{TMP}/blob.keys"
+"#;
+
 #[tokio::test]
 pub async fn blob_tests() {
     let temp_dir = TempDir::new("blob_tests", true);
@@ -39,8 +70,17 @@ pub async fn blob_tests() {
     let stores = config.parse_stores().await.unwrap();
 
     for (store_id, blob_store) in &stores.blob_stores {
-        println!("Testing blob store {}...", store_id);
-        test_store(blob_store.clone()).await;
+        for compression in [
+            CompressionAlgo::None,
+            CompressionAlgo::Lz4,
+            CompressionAlgo::Zstd,
+        ] {
+            println!(
+                "Testing blob store {} with compression {:?}...",
+                store_id, compression
+            );
+            test_store(blob_store.clone().with_compression(compression)).await;
+        }
     }
 
     for (store_id, store) in stores.stores {
@@ -417,10 +457,380 @@ pub async fn blob_tests() {
                     ^ ct
             );
         }
+
+        // Uncompressed blobs can be read from a compressed store and recompressed
+        let data = b"Lorem ipsum dolor sit amet. ".repeat(100);
+        let hash = BlobHash::from(data.as_slice());
+        store
+            .write(
+                BatchBuilder::new()
+                    .with_account_id(0)
+                    .with_collection(0)
+                    .update_document(3)
+                    .set(BlobOp::Link { hash: hash.clone() }, vec![])
+                    .set(BlobOp::Commit { hash: hash.clone() }, vec![])
+                    .build_batch(),
+            )
+            .await
+            .unwrap();
+        blob_store.put_blob(hash.as_ref(), &data).await.unwrap();
+        for compression in [
+            CompressionAlgo::Lz4,
+            CompressionAlgo::Zstd,
+            CompressionAlgo::None,
+        ] {
+            let compressed_store = blob_store.clone().with_compression(compression);
+            assert_eq!(
+                store
+                    .rewrite_blobs(compressed_store.clone())
+                    .await
+                    .unwrap()
+                    .rewritten,
+                1
+            );
+            assert_eq!(
+                store
+                    .rewrite_blobs(compressed_store.clone())
+                    .await
+                    .unwrap()
+                    .rewritten,
+                0
+            );
+            for blob_store in [&compressed_store, &blob_store] {
+                assert_eq!(
+                    blob_store
+                        .get_blob(hash.as_ref(), 0..u32::MAX)
+                        .await
+                        .unwrap()
+                        .unwrap(),
+                    data
+                );
+                assert_eq!(
+                    blob_store
+                        .get_blob(hash.as_ref(), 1000..1500)
+                        .await
+                        .unwrap()
+                        .unwrap(),
+                    &data[1000..1500]
+                );
+            }
+        }
+    }
+    temp_dir.delete();
+}
+
+#[tokio::test]
+pub async fn blob_encryption_tests() {
+    // Spawn mock KMS, data keys are "wrapped" by prefixing them with the key id
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    tokio::spawn(async move {
+        loop {
+            let (stream, _) = listener.accept().await.unwrap();
+            tokio::spawn(async move {
+                let _ = http1::Builder::new()
+                    .keep_alive(false)
+                    .serve_connection(
+                        TokioIo::new(stream),
+                        service_fn(|req: hyper::Request<body::Incoming>| async move {
+                            let target = req.headers()["X-Amz-Target"].to_str().unwrap().to_string();
+                            let request: serde_json::Value =
+                                serde_json::from_slice(&req.collect().await.unwrap().to_bytes())
+                                    .unwrap();
+                            let key_id = request["KeyId"].as_str().unwrap();
+                            let (status, response) = match target.as_str() {
+                                "TrentService.Encrypt" => {
+                                    let mut blob = key_id.as_bytes().to_vec();
+                                    blob.extend(
+                                        general_purpose::STANDARD
+                                            .decode(request["Plaintext"].as_str().unwrap())
+                                            .unwrap(),
+                                    );
+                                    (
+                                        200,
+                                        json!({"CiphertextBlob": general_purpose::STANDARD.encode(blob)}),
+                                    )
+                                }
+                                "TrentService.Decrypt" => {
+                                    KMS_DECRYPT_CALLS.fetch_add(1, Ordering::Relaxed);
+                                    let blob = general_purpose::STANDARD
+                                        .decode(request["CiphertextBlob"].as_str().unwrap())
+                                        .unwrap();
+                                    match blob.strip_prefix(key_id.as_bytes()) {
+                                        Some(plaintext) => (
+                                            200,
+                                            json!({"Plaintext": general_purpose::STANDARD.encode(plaintext)}),
+                                        ),
+                                        None => (400, json!({"__type": "IncorrectKeyException"})),
+                                    }
+                                }
+                                _ => (400, json!({"__type": "UnsupportedOperationException"})),
+                            };
+                            Ok::<_, hyper::Error>(
+                                hyper::Response::builder()
+                                    .status(status)
+                                    .body(Full::new(Bytes::from(response.to_string())))
+                                    .unwrap(),
+                            )
+                        }),
+                    )
+                    .await;
+            });
+        }
+    });
+
+    let temp_dir = TempDir::new("blob_encryption_tests", true);
+    let parse_stores = |keys: &'static str, kms_key: &'static str| {
+        let temp_dir = temp_dir.path.to_str().unwrap().to_string();
+        async move {
+            std::fs::write(format!("{temp_dir}/blob.keys"), keys).unwrap();
+            Config::new(
+                &ENCRYPTION_CONFIG
+                    .replace("{TMP}", &temp_dir)
+                    .replace("{PORT}", &port.to_string())
+                    .replace("{KMS_KEY}", kms_key),
+            )
+            .unwrap()
+            .parse_stores()
+            .await
+            .unwrap()
+        }
+    };
+    const KEY_1: &str = "# Blob master keys\nkey1: 9E3bFuNnNxK1E2A+w3IqMnfp8jM+y3Zqlh0oRRL0xGE=\n";
+    const KEY_2_1: &str = "key2:Wz1fXjZc5sDOmPRvZq7i5m3Gy9cbDEaSmJ8V9wFDh8s=\nkey1:9E3bFuNnNxK1E2A+w3IqMnfp8jM+y3Zqlh0oRRL0xGE=";
+    const KEY_2: &str = "key2:Wz1fXjZc5sDOmPRvZq7i5m3Gy9cbDEaSmJ8V9wFDh8s=";
+    let stores = parse_stores(KEY_1, "kms-key-1").await;
+    let store = stores.stores.get("sqlite").unwrap().clone();
+    store.destroy().await;
+
+    // Encrypted blobs can be read back and are not readable without the master key
+    for (store_id, blob_store) in &stores.blob_stores {
+        println!("Testing encrypted blob store {}...", store_id);
+        test_store(blob_store.clone()).await;
+
+        let data = b"Encrypted data".as_slice();
+        let hash = BlobHash::from(data);
+        blob_store.put_blob(hash.as_ref(), data).await.unwrap();
+        let plain_store = BlobStore::from(blob_store.backend.clone());
+        assert!(plain_store
+            .get_blob(hash.as_ref(), 0..u32::MAX)
+            .await
+            .is_err());
+        assert_eq!(
+            blob_store
+                .get_blob(hash.as_ref(), 0..9)
+                .await
+                .unwrap()
+                .unwrap(),
+            b"Encrypted"
+        );
+        assert!(blob_store.delete_blob(hash.as_ref()).await.unwrap());
+    }
+
+    // Unwrapped data keys are cached
+    let blob_store = stores.blob_stores["sqlite"].clone();
+    let data = b"Encrypted data".as_slice();
+    let hash = BlobHash::from(data);
+    blob_store.put_blob(hash.as_ref(), data).await.unwrap();
+    let decrypt_calls = KMS_DECRYPT_CALLS.load(Ordering::Relaxed);
+    for _ in 0..3 {
+        assert_eq!(
+            blob_store
+                .get_blob(hash.as_ref(), 0..u32::MAX)
+                .await
+                .unwrap()
+                .unwrap(),
+            data
+        );
+    }
+    assert_eq!(KMS_DECRYPT_CALLS.load(Ordering::Relaxed), decrypt_calls + 1);
+
+    // Encrypted blobs copied under a different key fail to decrypt
+    let raw = store
+        .get_blob(hash.as_ref(), 0..u32::MAX)
+        .await
+        .unwrap()
+        .unwrap();
+    let copy_hash = BlobHash::from(b"Copied data".as_slice());
+    store.put_blob(copy_hash.as_ref(), &raw).await.unwrap();
+    assert!(blob_store
+        .get_blob(copy_hash.as_ref(), 0..u32::MAX)
+        .await
+        .is_err());
+
+    // Plain blobs that look like an encrypted one are read back as is
+    let plain_hash = BlobHash::from(raw.as_slice());
+    let plain_store = BlobStore::from(store.clone());
+    plain_store
+        .put_blob(plain_hash.as_ref(), &raw)
+        .await
+        .unwrap();
+    for blob_store in [&plain_store, &blob_store] {
+        assert_eq!(
+            blob_store
+                .get_blob(plain_hash.as_ref(), 0..u32::MAX)
+                .await
+                .unwrap()
+                .unwrap(),
+            raw
+        );
+    }
+    for hash in [&hash, &copy_hash, &plain_hash] {
+        assert!(blob_store.delete_blob(hash.as_ref()).await.unwrap());
+    }
+
+    // Write blobs and link them so they are rewritten on key rotation
+    let data = b"Lorem ipsum dolor sit amet. ".repeat(100);
+    let hash = BlobHash::from(data.as_slice());
+    store
+        .write(
+            BatchBuilder::new()
+                .with_account_id(0)
+                .with_collection(0)
+                .update_document(0)
+                .set(BlobOp::Link { hash: hash.clone() }, vec![])
+                .set(BlobOp::Commit { hash: hash.clone() }, vec![])
+                .build_batch(),
+        )
+        .await
+        .unwrap();
+    for blob_store in stores.blob_stores.values() {
+        blob_store.put_blob(hash.as_ref(), &data).await.unwrap();
+        assert_eq!(
+            store
+                .rewrite_blobs(blob_store.clone())
+                .await
+                .unwrap()
+                .rewritten,
+            0
+        );
+    }
+
+    // Rotate master keys, old keys are still used for reading
+    let rotated_stores = parse_stores(KEY_2_1, "kms-key-2").await;
+    assert_blobs(&rotated_stores, &hash, &data).await;
+    for (store_id, blob_store) in &rotated_stores.blob_stores {
+        println!("Rewrapping data keys on blob store {}...", store_id);
+        assert_eq!(
+            store
+                .rewrite_blobs(blob_store.clone())
+                .await
+                .unwrap()
+                .rewritten,
+            1
+        );
+        assert_eq!(
+            store
+                .rewrite_blobs(blob_store.clone())
+                .await
+                .unwrap()
+                .rewritten,
+            0
+        );
+    }
+
+    // Old keys are no longer needed after rewrapping
+    let new_stores = parse_stores(KEY_2, "kms-key-2").await;
+    assert_blobs(&new_stores, &hash, &data).await;
+    assert!(stores.blob_stores["fs"]
+        .get_blob(hash.as_ref(), 0..u32::MAX)
+        .await
+        .is_err());
+
+    // Changing the compression algorithm encrypts the blob again
+    for blob_store in new_stores.blob_stores.values() {
+        let blob_store = blob_store.clone().with_compression(CompressionAlgo::Zstd);
+        assert_eq!(
+            store
+                .rewrite_blobs(blob_store.clone())
+                .await
+                .unwrap()
+                .rewritten,
+            1
+        );
+        assert_eq!(
+            store
+                .rewrite_blobs(blob_store.clone())
+                .await
+                .unwrap()
+                .rewritten,
+            0
+        );
+        assert!(BlobStore::from(blob_store.backend.clone())
+            .get_blob(hash.as_ref(), 0..u32::MAX)
+            .await
+            .is_err());
     }
+    assert_blobs(&new_stores, &hash, &data).await;
+
+    // Unencrypted blobs are rejected when encryption is required
+    let plain_data = b"Unencrypted data".as_slice();
+    let plain_hash = BlobHash::from(plain_data);
+    let required_stores = Config::new(&format!(
+        "{}require = true\n",
+        ENCRYPTION_CONFIG
+            .replace("{TMP}", temp_dir.path.to_str().unwrap())
+            .replace("{PORT}", &port.to_string())
+            .replace("{KMS_KEY}", "kms-key-2")
+    ))
+    .unwrap()
+    .parse_stores()
+    .await
+    .unwrap();
+    let required_store = required_stores.blob_stores["fs"].clone();
+    BlobStore::from(required_store.backend.clone())
+        .put_blob(plain_hash.as_ref(), plain_data)
+        .await
+        .unwrap();
+    assert_eq!(
+        new_stores.blob_stores["fs"]
+            .get_blob(plain_hash.as_ref(), 0..u32::MAX)
+            .await
+            .unwrap()
+            .unwrap(),
+        plain_data
+    );
+    assert!(required_store
+        .get_blob(plain_hash.as_ref(), 0..u32::MAX)
+        .await
+        .is_err());
+    assert_eq!(
+        required_store
+            .get_blob(hash.as_ref(), 0..u32::MAX)
+            .await
+            .unwrap()
+            .unwrap(),
+        data
+    );
+    assert!(required_store
+        .delete_blob(plain_hash.as_ref())
+        .await
+        .unwrap());
+
     temp_dir.delete();
 }
 
+async fn assert_blobs(stores: &Stores, hash: &BlobHash, data: &[u8]) {
+    for blob_store in stores.blob_stores.values() {
+        assert_eq!(
+            blob_store
+                .get_blob(hash.as_ref(), 0..u32::MAX)
+                .await
+                .unwrap()
+                .unwrap(),
+            data
+        );
+        assert_eq!(
+            blob_store
+                .get_blob(hash.as_ref(), 1000..1500)
+                .await
+                .unwrap()
+                .unwrap(),
+            &data[1000..1500]
+        );
+    }
+}
+
 async fn test_store(store: BlobStore) {
     // Test small blob
     const DATA: &[u8] = b"Lorem ipsum dolor sit amet, consectetur adipiscing elit. Fusce erat nisl, dignissim a porttitor id, varius nec arcu. Sed mauris.";